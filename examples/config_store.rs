@@ -0,0 +1,53 @@
+//! Store configuration variables in an emulated EEPROM.
+//!
+//! Uses the `storage_stack::config()` preset, which keeps CRC-protected variables in two
+//! sectors and survives power loss during updates. Runs against `MockFlash`; to use real
+//! hardware, pass your own `FlashAccess` implementation to `run()`.
+//!
+//! ```text
+//! cargo run --example config_store
+//! ```
+
+use spi_flash::{mock::MockFlash, storage_stack, Error, Flash, FlashAccess};
+
+/// Variable ID of the boot count.
+const BOOT_COUNT: u16 = 1;
+/// Variable ID of the device name.
+const NAME: u16 = 2;
+
+/// Increment the boot count stored in the first two sectors, and return it.
+fn run<A: FlashAccess>(access: &mut A) -> Result<u32, Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+    if flash.erase_size().is_none() {
+        flash.set_erase_size(4096);
+    }
+    let sector = flash.erase_size().unwrap() as u32;
+    let mut config = storage_stack::config(&mut flash, 0..2 * sector)?;
+
+    let mut buf = [0u8; 4];
+    let count = match config.read_var(BOOT_COUNT, &mut buf)? {
+        Some(4) => u32::from_le_bytes(buf) + 1,
+        _ => 1,
+    };
+    config.write_var(BOOT_COUNT, &count.to_le_bytes())?;
+    config.write_var(NAME, b"sensor-01")?;
+    Ok(count)
+}
+
+fn main() {
+    let mut mock = MockFlash::new(64 * 1024);
+    for boot in 1..=3 {
+        let count = run(&mut mock).unwrap();
+        println!("Boot count: {}", count);
+        assert_eq!(count, boot);
+    }
+}
+
+#[test]
+fn example() {
+    main();
+}
@@ -0,0 +1,50 @@
+//! Keep named configuration parameters in a key-value store.
+//!
+//! Uses `KvStore` in the first two sectors of the flash, which compacts itself into the
+//! other sector when the active one is full. Runs against `MockFlash`; to use real
+//! hardware, pass your own `FlashAccess` implementation to `run()`, or see the
+//! `kvstore_spidev` example.
+//!
+//! ```text
+//! cargo run --example kvstore --features kvstore
+//! ```
+
+use spi_flash::{kvstore::KvStore, mock::MockFlash, Error, Flash, FlashAccess};
+
+/// Increment the boot count stored under `boots`, set the device name, and return the
+/// boot count.
+fn run<A: FlashAccess>(access: &mut A) -> Result<u32, Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+    if flash.erase_size().is_none() {
+        flash.set_erase_size(4096);
+    }
+    let sector = flash.erase_size().unwrap() as u32;
+    let mut store = KvStore::mount(&mut flash, 0, sector)?;
+
+    let count = match store.get(b"boots")? {
+        Some(value) if value.len() == 4 => u32::from_le_bytes(value.try_into().unwrap()) + 1,
+        _ => 1,
+    };
+    store.set(b"boots", &count.to_le_bytes())?;
+    store.set(b"name", b"sensor-01")?;
+    assert_eq!(store.get(b"name")?.as_deref(), Some(&b"sensor-01"[..]));
+    Ok(count)
+}
+
+fn main() {
+    let mut mock = MockFlash::new(64 * 1024);
+    for boot in 1..=3 {
+        let count = run(&mut mock).unwrap();
+        println!("Boot count: {}", count);
+        assert_eq!(count, boot);
+    }
+}
+
+#[test]
+fn example() {
+    main();
+}
@@ -0,0 +1,32 @@
+//! Keep named configuration parameters in a key-value store on a flash attached to a
+//! Linux `spidev` device.
+//!
+//! The hardware variant of the `kvstore` example, using `HostAccess` over `Spidev`. The
+//! first two sectors of the flash are erased if they do not hold a store.
+//!
+//! ```text
+//! cargo run --example kvstore_spidev --features kvstore,spidev -- /dev/spidev0.0
+//! ```
+
+use spi_flash::{
+    host::{HostAccess, Spidev},
+    kvstore::KvStore,
+    Flash,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args().nth(1).unwrap_or("/dev/spidev0.0".into());
+    let mut access = HostAccess::new(Spidev::open(path, 10_000_000)?);
+    let mut flash = Flash::new(&mut access);
+    flash.read_params()?;
+    let sector = flash.erase_size().unwrap_or(4096) as u32;
+    let mut store = KvStore::mount(&mut flash, 0, sector)?;
+
+    let count = match store.get(b"boots")? {
+        Some(value) if value.len() == 4 => u32::from_le_bytes(value.try_into().unwrap()) + 1,
+        _ => 1,
+    };
+    store.set(b"boots", &count.to_le_bytes())?;
+    println!("Boot count: {}", count);
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Stage, validate, and swap in a firmware update using A/B slots.
+//!
+//! Uses `DualBank` over two image slots and a metadata journal. Runs against `MockFlash`;
+//! to use real hardware, pass your own `FlashAccess` implementation to `run()`.
+//!
+//! ```text
+//! cargo run --example ota_update
+//! ```
+
+use spi_flash::{mock::MockFlash, DualBank, Error, Flash, FlashAccess, ReadStorage};
+
+/// Write `image` to the inactive slot and make it active, returning the new active slot.
+fn run<A: FlashAccess>(access: &mut A, image: &[u8]) -> Result<usize, Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+    if flash.erase_size().is_none() {
+        flash.set_erase_size(4096);
+    }
+    let slots = [0x0_0000..0x4_0000, 0x4_0000..0x8_0000];
+    let mut bank = DualBank::open(&mut flash, slots, 0x8_0000..0x8_2000)?;
+
+    // The updater stages the image, then a bootloader validates and swaps it in.
+    bank.stage_update(image)?;
+    let staged = bank.state().inactive();
+    if !bank.validate(staged)? {
+        return Err(Error::ChecksumMismatch { address: 0 });
+    }
+    bank.swap()?;
+
+    let active = bank.active_slot();
+    let mut slot = bank.slot(active)?;
+    assert_eq!(slot.read(0, image.len())?, image);
+    Ok(active)
+}
+
+fn main() {
+    let mut mock = MockFlash::new(1024 * 1024);
+    let image: Vec<u8> = (0..10_000).map(|x| (x * 7) as u8).collect();
+    let first = run(&mut mock, &image).unwrap();
+    println!("First update now active in slot {}", first);
+    let second = run(&mut mock, &image[..5000]).unwrap();
+    println!("Second update now active in slot {}", second);
+    assert_ne!(first, second);
+}
+
+#[test]
+fn example() {
+    main();
+}
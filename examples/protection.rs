@@ -0,0 +1,43 @@
+//! Protect a bootloader region from program and erase operations.
+//!
+//! Uses the default BP bit protection scheme, which protects regions at the top of
+//! memory; set another scheme with `Flash::set_protection_scheme()` for devices using
+//! individual block locks or Spansion DYB/PPB bits. Runs against `MockFlash`; to use
+//! real hardware, pass your own `FlashAccess` implementation to `run()`.
+//!
+//! ```text
+//! cargo run --example protection
+//! ```
+
+use spi_flash::{mock::MockFlash, Error, Flash, FlashAccess};
+
+/// Protect the top 64kB of memory, returning whether the first and last
+/// addresses of memory are then protected.
+fn run<A: FlashAccess>(access: &mut A) -> Result<(bool, bool), Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+    let capacity = flash.capacity().unwrap_or(2 * 1024 * 1024);
+    flash.set_capacity(capacity);
+    let top = capacity as u32;
+    flash.unprotect_all()?;
+    flash.set_protection(top - 0x1_0000..top, true)?;
+    Ok((
+        flash.is_address_protected(0)?,
+        flash.is_address_protected(top - 1)?,
+    ))
+}
+
+fn main() {
+    let mut mock = MockFlash::new(2 * 1024 * 1024);
+    let (bottom, top) = run(&mut mock).unwrap();
+    println!("Bottom protected: {}, top protected: {}", bottom, top);
+    assert!(!bottom && top);
+}
+
+#[test]
+fn example() {
+    main();
+}
@@ -0,0 +1,42 @@
+//! Append CRC-protected records to a log and read them back.
+//!
+//! Uses the `storage_stack::logging()` preset. Runs against `MockFlash`; to use real
+//! hardware, pass your own `FlashAccess` implementation to `run()`.
+//!
+//! ```text
+//! cargo run --example ring_log
+//! ```
+
+use spi_flash::{mock::MockFlash, storage_stack, Error, Flash, FlashAccess};
+
+/// Append `message` to the log in 0x10000..0x12000, which discards its oldest records
+/// when full, and return all records.
+fn run<A: FlashAccess>(access: &mut A, message: &[u8]) -> Result<Vec<Vec<u8>>, Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+    if flash.erase_size().is_none() {
+        flash.set_erase_size(4096);
+    }
+    let mut log = storage_stack::logging(&mut flash, 0x1_0000..0x1_2000)?;
+    log.append(message)?;
+    log.records()
+}
+
+fn main() {
+    let mut mock = MockFlash::new(128 * 1024);
+    for n in 0..1000 {
+        let message = format!("event {}", n);
+        let records = run(&mut mock, message.as_bytes()).unwrap();
+        assert_eq!(records.last().unwrap(), message.as_bytes());
+    }
+    let records = run(&mut mock, b"done").unwrap();
+    println!("{} records in log", records.len());
+}
+
+#[test]
+fn example() {
+    main();
+}
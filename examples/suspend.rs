@@ -0,0 +1,63 @@
+//! Suspend a program to read other data, then resume it.
+//!
+//! A device cannot be read while it is programming, so a reader which cannot wait for
+//! the program to complete, such as one fetching code to execute, suspends it with
+//! Program Suspend (0x75) and resumes it with Program Resume (0x7A) afterwards. The
+//! suspend state is checked in the flag status register, as on Micron devices; others
+//! report it elsewhere, such as the SUS bit of Winbond status register 2.
+//!
+//! Runs against `MockFlash`, kept busy for a number of polls after each program; to use
+//! real hardware, pass your own `FlashAccess` implementation to `run()`, or see the
+//! `suspend_spidev` example.
+//!
+//! ```text
+//! cargo run --example suspend
+//! ```
+
+use spi_flash::{mock::MockFlash, CommandOpCode, Direction, Error, Flash, FlashAccess};
+
+/// Start programming `record` at 0x1000, read the 16 bytes at 0 while the program is
+/// suspended, then resume it and return the data read.
+fn run<A: FlashAccess>(access: &mut A, record: &[u8]) -> Result<Vec<u8>, Error>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut flash = Flash::new(access);
+    flash.read_params()?;
+
+    // Start the program without waiting for it to complete.
+    let mut data = record.to_vec();
+    flash.write_enable()?;
+    let opcode = CommandOpCode::PageProgram.into();
+    flash.exec_raw(opcode, Some(0x1000), 0, Direction::Write, &mut data, false)?;
+
+    // The device is ready once the program is suspended, so reads wait for that first.
+    let opcode = CommandOpCode::ProgramSuspend.into();
+    flash.exec_raw(opcode, None, 0, Direction::None, &mut [], false)?;
+    let header = flash.read(0, 16)?;
+    assert!(flash.read_flag_status()?.get_program_suspended());
+
+    // Nothing tracks the resumed program, so wait for it before anything else.
+    let opcode = CommandOpCode::ProgramResume.into();
+    flash.exec_raw(opcode, None, 0, Direction::None, &mut [], false)?;
+    flash.wait_while_busy()?;
+    assert_eq!(flash.read(0x1000, record.len())?, record);
+    Ok(header)
+}
+
+fn main() {
+    let mut mock = MockFlash::new(64 * 1024).with_busy_polls(100);
+    mock.memory_mut()[..16].copy_from_slice(b"boot config v1.0");
+    let header = run(&mut mock, b"log record").unwrap();
+    println!(
+        "Read {:?} during the program",
+        String::from_utf8_lossy(&header)
+    );
+    assert_eq!(header, b"boot config v1.0");
+    assert_eq!(mock.busy_violations(), 0);
+}
+
+#[test]
+fn example() {
+    main();
+}
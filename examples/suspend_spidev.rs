@@ -0,0 +1,43 @@
+//! Suspend a program to read other data, then resume it, on a flash attached to a Linux
+//! `spidev` device.
+//!
+//! The hardware variant of the `suspend` example, using `HostAccess` over `Spidev`. The
+//! suspend state is checked in the flag status register, so this needs a device which
+//! has one, such as a Micron MT25Q. The sector at 0x1000 is erased and programmed.
+//!
+//! ```text
+//! cargo run --example suspend_spidev --features spidev -- /dev/spidev0.0
+//! ```
+
+use spi_flash::{
+    host::{HostAccess, Spidev},
+    CommandOpCode, Direction, Flash, Storage,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args().nth(1).unwrap_or("/dev/spidev0.0".into());
+    let mut access = HostAccess::new(Spidev::open(path, 10_000_000)?);
+    let mut flash = Flash::new(&mut access);
+    flash.read_params()?;
+    let sector = flash.erase_size().unwrap_or(4096);
+    flash.erase_data(0x1000, sector)?;
+
+    let mut data = *b"log record";
+    flash.write_enable()?;
+    let opcode = CommandOpCode::PageProgram.into();
+    flash.exec_raw(opcode, Some(0x1000), 0, Direction::Write, &mut data, false)?;
+    let opcode = CommandOpCode::ProgramSuspend.into();
+    flash.exec_raw(opcode, None, 0, Direction::None, &mut [], false)?;
+    let header = flash.read(0, 16)?;
+    let suspended = flash.read_flag_status()?.get_program_suspended();
+    let opcode = CommandOpCode::ProgramResume.into();
+    flash.exec_raw(opcode, None, 0, Direction::None, &mut [], false)?;
+    flash.wait_while_busy()?;
+
+    println!(
+        "Read {:02X?} during the program, suspended: {}",
+        header, suspended
+    );
+    assert_eq!(flash.read(0x1000, data.len())?, data);
+    Ok(())
+}
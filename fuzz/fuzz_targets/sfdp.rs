@@ -0,0 +1,48 @@
+//! Fuzz SFDP discovery through `Flash::read_params()`.
+//!
+//! The fuzzer input is served as the contents of the SFDP address space,
+//! exercising header, parameter header, and parameter table parsing.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spi_flash::{Command, Flash, FlashAccess};
+
+#[derive(Debug)]
+struct NoData;
+
+impl From<NoData> for spi_flash::Error {
+    fn from(_: NoData) -> spi_flash::Error {
+        spi_flash::Error::Access(anyhow::anyhow!("SFDP read out of range"))
+    }
+}
+
+/// Serves the fuzzer input as the SFDP address space, and all-1s for other reads.
+struct SfdpData<'a>(&'a [u8]);
+
+impl<'a> FlashAccess for SfdpData<'a> {
+    type Error = NoData;
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>, NoData> {
+        let mut rx = vec![0; command.len() + data.len()];
+        match command {
+            Command::ReadSFDPRegister(addr) => {
+                // The first byte returned is the dummy byte.
+                let start = *addr as usize;
+                let end = start.checked_add(nbytes.saturating_sub(1)).ok_or(NoData)?;
+                rx.push(0);
+                rx.extend(self.0.get(start..end).ok_or(NoData)?);
+            }
+            _ => rx.extend(core::iter::repeat(0xFF).take(nbytes)),
+        }
+        Ok(rx)
+    }
+
+    fn delay(&mut self, _duration: core::time::Duration) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut access = SfdpData(data);
+    let mut flash = Flash::new(&mut access);
+    let _ = flash.read_params();
+});
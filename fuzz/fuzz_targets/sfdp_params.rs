@@ -0,0 +1,17 @@
+//! Fuzz parsing of the JEDEC Basic Flash Parameter table.
+//!
+//! The first two bytes of input are used as the parameter major and minor version.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spi_flash::FlashParams;
+
+fuzz_target!(|data: &[u8]| {
+    if let [major, minor, table @ ..] = data {
+        if let Ok(params) = FlashParams::from_bytes(*major, *minor, table) {
+            let _ = params.to_string();
+            let _ = params.sector_erase();
+        }
+    }
+});
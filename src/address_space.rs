@@ -0,0 +1,82 @@
+//! Address spaces for flash addresses.
+//!
+//! When flash memory is mapped into the processor's address space for execute-in-place,
+//! images are linked at the mapped address, while flash commands use storage offsets
+//! from the start of the device. Bootloaders handle both, and mixing them up is an easy
+//! mistake; `AddressSpace` makes the space of each address explicit.
+
+/// The address space an address is given in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    /// Offset from the start of the flash device, as used in flash commands.
+    Storage,
+    /// Address in the memory-mapped window, which starts at the mapped base
+    /// set with `Flash::set_mapped_base()`.
+    Mapped,
+}
+
+#[test]
+fn test_address_space() {
+    use crate::Error;
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(flash.to_storage(AddressSpace::Mapped, 0x9000_0000).is_err());
+    assert_eq!(
+        flash.to_storage(AddressSpace::Storage, 0x1234).unwrap(),
+        0x1234
+    );
+    assert!(flash.to_storage(AddressSpace::Storage, 0x1_0000).is_err());
+
+    flash.set_mapped_base(Some(0x9000_0000));
+    assert_eq!(
+        flash.to_storage(AddressSpace::Mapped, 0x9000_1234).unwrap(),
+        0x1234
+    );
+    assert_eq!(
+        flash.from_storage(AddressSpace::Mapped, 0x1234).unwrap(),
+        0x9000_1234
+    );
+    assert_eq!(
+        flash.from_storage(AddressSpace::Storage, 0x1234).unwrap(),
+        0x1234
+    );
+    // Addresses outside the mapped window are rejected.
+    assert!(matches!(
+        flash.to_storage(AddressSpace::Mapped, 0x8FFF_FFFF),
+        Err(Error::InvalidAddress {
+            address: 0x8FFF_FFFF
+        })
+    ));
+    assert!(flash.to_storage(AddressSpace::Mapped, 0x9001_0000).is_err());
+    flash.set_mapped_base(Some(0xFFFF_F000));
+    assert!(flash.from_storage(AddressSpace::Mapped, 0x2000).is_err());
+    flash.set_mapped_base(Some(0x9000_0000));
+
+    // Data programmed at a mapped address is found at the storage offset.
+    flash
+        .program_data_at(AddressSpace::Mapped, 0x9000_2000, &[1, 2, 3, 4])
+        .unwrap();
+    assert_eq!(
+        flash.read_at(AddressSpace::Storage, 0x2000, 4).unwrap(),
+        [1, 2, 3, 4]
+    );
+    assert_eq!(
+        flash.read_at(AddressSpace::Mapped, 0x9000_2001, 2).unwrap(),
+        [2, 3]
+    );
+    flash
+        .erase_range_at(AddressSpace::Mapped, 0x9000_2000, 4096, false)
+        .unwrap();
+    assert!(flash.is_erased(0x2000, 4).unwrap());
+
+    // Accesses starting inside the window but running past the end of the device fail.
+    assert!(flash
+        .read_at(AddressSpace::Mapped, 0x9000_FFF0, 32)
+        .is_err());
+    assert!(flash
+        .program_data_at(AddressSpace::Mapped, 0x9000_FFFE, &[0; 4])
+        .is_err());
+    drop(flash);
+    assert_eq!(mock.memory()[0xFFFE..], [0xFF, 0xFF]);
+}
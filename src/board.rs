@@ -0,0 +1,50 @@
+//! Description of how a flash device is wired on the board.
+//!
+//! Some device features are only safe to use if the board connects the relevant pins.
+//! For example, enabling quad I/O on a board which only connects the single- or dual-SPI
+//! lines repurposes the WP# and HOLD# pins, and on a board with WP# tied low, setting
+//! the status register protect bit makes the status registers read-only.
+//! `BoardConfig` lets `Flash` avoid such configurations.
+
+/// Board wiring capabilities.
+///
+/// The default describes a board with only the standard SPI lines connected,
+/// and WP# not tied low, which is the most conservative configuration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BoardConfig {
+    /// IO2 and IO3 are connected to the controller, which can perform quad I/O transfers.
+    pub quad_io: bool,
+    /// IO0 to IO7 are connected to the controller, which can perform octal I/O transfers.
+    pub octal_io: bool,
+    /// The WP# pin is tied low, so status registers cannot be written while
+    /// the status register protect bit is set.
+    pub wp_tied_low: bool,
+}
+
+/// A control line driven by the controller, such as WP# or RESET#.
+///
+/// With the `embedded-hal` feature, this is implemented for all `OutputPin`s.
+pub trait ControlPin {
+    /// Drive the line high.
+    fn set_high(&mut self) -> crate::Result<()>;
+
+    /// Drive the line low.
+    fn set_low(&mut self) -> crate::Result<()>;
+}
+
+#[cfg(feature = "embedded-hal")]
+impl<P: embedded_hal::digital::OutputPin> ControlPin for P {
+    fn set_high(&mut self) -> crate::Result<()> {
+        embedded_hal::digital::OutputPin::set_high(self).map_err(pin_error)
+    }
+
+    fn set_low(&mut self) -> crate::Result<()> {
+        embedded_hal::digital::OutputPin::set_low(self).map_err(pin_error)
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+pub(crate) fn pin_error<E: embedded_hal::digital::Error>(err: E) -> crate::Error {
+    log::error!("Control pin error: {:?}", err.kind());
+    crate::Error::Pin
+}
@@ -0,0 +1,210 @@
+//! Write coalescing for small programs.
+//!
+//! Appending small records to flash programs each record with its own PageProgram,
+//! and so its own write enable, instruction, and busy wait, even when many records
+//! share a page. `BufferedFlash` instead holds writes to one page in RAM, merging them
+//! as the flash would, and programs them together when a write targets another page,
+//! the page is read or erased, or `flush()` is called.
+//!
+//! If flushing fails, the pending writes are kept so that `flush()` can be retried.
+//! Pending writes are flushed when the `BufferedFlash` is dropped, but any error is then
+//! only logged, so call `flush()` before dropping it where errors must be handled.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{Error, ReadStorage, Result, Storage};
+
+/// Write coalescing wrapper buffering one page of `storage`.
+pub struct BufferedFlash<'a, F: Storage> {
+    storage: &'a mut F,
+    page_size: usize,
+    /// Address of the page holding pending writes, if any.
+    page: Option<u32>,
+    /// Contents to program to the page, with 0xFF for bytes not written.
+    buf: Vec<u8>,
+    /// Offsets within the page of the pending writes.
+    dirty: Range<usize>,
+}
+
+impl<'a, F: Storage> BufferedFlash<'a, F> {
+    /// Create a wrapper over `storage` buffering writes within pages of `page_size` bytes.
+    pub fn new(storage: &'a mut F, page_size: usize) -> Result<Self> {
+        if page_size == 0 {
+            log::error!("Write buffer page size must not be zero");
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(BufferedFlash {
+            storage,
+            page_size,
+            page: None,
+            buf: vec![0xFF; page_size],
+            dirty: 0..0,
+        })
+    }
+
+    /// Get the page size in bytes.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Check if any writes are pending.
+    pub fn is_dirty(&self) -> bool {
+        self.page.is_some()
+    }
+
+    /// Program any pending writes.
+    ///
+    /// If programming fails, the writes remain pending, so the flush may be retried.
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(page) = self.page else {
+            return Ok(());
+        };
+        let dirty = self.dirty.clone();
+        log::trace!(
+            "Flushing {} buffered bytes at 0x{:08X}",
+            dirty.len(),
+            page as usize + dirty.start
+        );
+        self.storage
+            .program_data(page + dirty.start as u32, &self.buf[dirty])?;
+        self.page = None;
+        self.dirty = 0..0;
+        self.buf.fill(0xFF);
+        Ok(())
+    }
+
+    /// Flush pending writes if they overlap the `length` bytes starting at `address`.
+    fn flush_overlapping(&mut self, address: u32, length: usize) -> Result<()> {
+        if let Some(page) = self.page {
+            let (start, end) = (address as usize, address as usize + length);
+            if start < page as usize + self.page_size && (page as usize) < end {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, F: Storage> ReadStorage for BufferedFlash<'a, F> {
+    fn capacity(&self) -> Option<usize> {
+        self.storage.capacity()
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.flush_overlapping(address, length)?;
+        self.storage.read(address, length)
+    }
+}
+
+impl<'a, F: Storage> Storage for BufferedFlash<'a, F> {
+    fn erase_size(&self) -> Option<usize> {
+        self.storage.erase_size()
+    }
+
+    /// Buffer `data` to be programmed at `address`.
+    ///
+    /// The range is checked against the capacity immediately, rather than when the
+    /// writes are flushed.
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        if let Some(capacity) = self.storage.capacity() {
+            if address as usize + data.len() > capacity {
+                log::error!("Buffered write at 0x{:08X} exceeds the capacity", address);
+                return Err(Error::InvalidAddress { address });
+            }
+        }
+        let mut offset = 0;
+        while offset < data.len() {
+            let address = address as usize + offset;
+            let page = (address - address % self.page_size) as u32;
+            let start = address % self.page_size;
+            let len = usize::min(self.page_size - start, data.len() - offset);
+            if self.page != Some(page) {
+                self.flush()?;
+                self.page = Some(page);
+                self.dirty = start..start + len;
+            } else {
+                self.dirty.start = usize::min(self.dirty.start, start);
+                self.dirty.end = usize::max(self.dirty.end, start + len);
+            }
+            // Programming only clears bits, so merge writes to the same bytes likewise.
+            for (byte, new) in self.buf[start..start + len]
+                .iter_mut()
+                .zip(&data[offset..offset + len])
+            {
+                *byte &= new;
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.flush_overlapping(address, length)?;
+        self.storage.erase_data(address, length)
+    }
+}
+
+impl<'a, F: Storage> Drop for BufferedFlash<'a, F> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::error!("Error flushing buffered writes: {:?}", err);
+        }
+    }
+}
+
+#[test]
+fn test_buffered_flash() {
+    let mut mock = crate::mock::MockFlash::new(4096);
+    assert!(BufferedFlash::new(&mut mock, 0).is_err());
+    let mut buffered = BufferedFlash::new(&mut mock, 256).unwrap();
+    for n in 0..20u8 {
+        buffered
+            .program_data(0x100 + 10 * n as u32, &[n; 10])
+            .unwrap();
+    }
+    buffered.program_data(0x100, &[0xF0]).unwrap();
+    assert_eq!(buffered.storage.page_programs(), 0);
+    assert!(buffered.is_dirty());
+
+    // Reading the page flushes it.
+    assert_eq!(buffered.read(0x100, 2).unwrap(), [0x00, 0x00]);
+    assert_eq!(buffered.storage.page_programs(), 1);
+    assert_eq!(
+        buffered.storage.memory()[0x100 + 190..0x100 + 201],
+        [19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 0xFF]
+    );
+
+    // Writes spanning pages flush the earlier page when moving on.
+    buffered.program_data(0x2F0, &[0x55; 32]).unwrap();
+    buffered.program_data(0x310, &[0xAA; 4]).unwrap();
+    assert_eq!(buffered.storage.page_programs(), 2);
+    buffered.flush().unwrap();
+    assert_eq!(buffered.storage.page_programs(), 3);
+    assert_eq!(
+        buffered.read(0x30F, 6).unwrap(),
+        [0x55, 0xAA, 0xAA, 0xAA, 0xAA, 0xFF]
+    );
+
+    // Writes past the end are rejected before they are buffered.
+    assert!(buffered.program_data(0xFFC, &[0; 8]).is_err());
+    assert!(!buffered.is_dirty());
+
+    // A failed flush keeps the pending writes, so it can be retried.
+    buffered.program_data(0x500, &[0x12; 4]).unwrap();
+    buffered.storage.set_power_loss_after(Some(0));
+    assert!(buffered.flush().is_err());
+    assert!(buffered.is_dirty());
+    buffered.storage.restore_power();
+    buffered.flush().unwrap();
+    assert_eq!(
+        buffered.storage.memory()[0x500..0x505],
+        [0x12, 0x12, 0x12, 0x12, 0xFF]
+    );
+
+    // Dropping flushes pending writes.
+    buffered.program_data(0x400, &[1]).unwrap();
+    drop(buffered);
+    assert_eq!((mock.page_programs(), mock.memory()[0x400]), (6, 1));
+}
@@ -0,0 +1,256 @@
+//! Manual configuration of devices without SFDP.
+//!
+//! Older devices and some clones do not support SFDP, or report incorrect parameters, so
+//! `Flash::read_params()` cannot configure them. A `FlashBuilder` instead takes the
+//! capacity, page size, erase geometry, address mode, and read instruction from the
+//! datasheet, checks they are consistent, and creates a configured `Flash`:
+//!
+//! ```
+//! # use spi_flash::{FlashBuilder, mock::MockFlash};
+//! let mut access = MockFlash::new(1024 * 1024);
+//! let flash = FlashBuilder::new()
+//!     .capacity(1024 * 1024)
+//!     .page_size(256)
+//!     .erase(4096, 0x20)
+//!     .build(&mut access)
+//!     .unwrap();
+//! assert_eq!(flash.erase_size(), Some(4096));
+//! ```
+//!
+//! The same builder creates a `SerialEeprom` for 25xx EEPROMs using `build_eeprom()`.
+
+use crate::{
+    BoardConfig, Error, Flash, FlashAccess, Layout, OpcodeMap, Quirks, ReadParameters,
+    RegisterFile, Result, SerialEeprom,
+};
+
+/// Builder for a `Flash` with manually specified device parameters.
+///
+/// Parameters which are not specified keep the defaults of `Flash::new()`.
+#[derive(Clone, Debug, Default)]
+pub struct FlashBuilder {
+    capacity: Option<usize>,
+    page_size: Option<usize>,
+    erase: Option<(usize, u8)>,
+    layout: Option<Layout>,
+    address_bytes: Option<u8>,
+    read_parameters: Option<ReadParameters>,
+    quirks: Option<Quirks>,
+    board: Option<BoardConfig>,
+    register_file: Option<RegisterFile>,
+    opcode_map: Option<OpcodeMap>,
+}
+
+impl FlashBuilder {
+    /// Create a new builder with no parameters specified.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the total memory capacity in bytes.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Set the page program size in bytes, which must be a power of two.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Set the sector erase size in bytes, which must be a power of two,
+    /// and the opcode of the instruction which erases one sector.
+    pub fn erase(mut self, size: usize, opcode: u8) -> Self {
+        self.erase = Some((size, opcode));
+        self
+    }
+
+    /// Set the erase layout, for devices with non-uniform sectors.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Set the number of address bytes, from 1 to 4.
+    pub fn address_bytes(mut self, address_bytes: u8) -> Self {
+        self.address_bytes = Some(address_bytes);
+        self
+    }
+
+    /// Set the read instruction and its dummy cycles and mode bits.
+    pub fn read_parameters(mut self, read_parameters: ReadParameters) -> Self {
+        self.read_parameters = Some(read_parameters);
+        self
+    }
+
+    /// Set the quirks of the device.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Set the board wiring capabilities.
+    pub fn board(mut self, board: BoardConfig) -> Self {
+        self.board = Some(board);
+        self
+    }
+
+    /// Set the register addresses accessible using RDAR/WRAR.
+    pub fn register_file(mut self, register_file: RegisterFile) -> Self {
+        self.register_file = Some(register_file);
+        self
+    }
+
+    /// Set replacement opcodes for devices implementing standard instructions under
+    /// nonstandard opcodes.
+    pub fn opcode_map(mut self, opcode_map: OpcodeMap) -> Self {
+        self.opcode_map = Some(opcode_map);
+        self
+    }
+
+    /// Check the parameters are consistent and create a configured `Flash` using `access`.
+    ///
+    /// No instructions are sent to the device. Returns `InvalidConfiguration` if a size
+    /// is not a power of two, the number of address bytes is out of range or cannot
+    /// address the whole capacity, or the page or erase size exceeds the capacity.
+    pub fn build<'a, A: FlashAccess>(self, access: &'a mut A) -> Result<Flash<'a, A>>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        self.validate(0)?;
+        let mut flash = Flash::new(access);
+        if let Some(address_bytes) = self.address_bytes {
+            flash.set_address_bytes(address_bytes);
+        }
+        if let Some(capacity) = self.capacity {
+            flash.set_capacity(capacity);
+        }
+        if let Some(page_size) = self.page_size {
+            flash.set_page_size(page_size);
+        }
+        if let Some((size, opcode)) = self.erase {
+            flash.set_erase_size(size);
+            flash.set_erase_opcode(opcode);
+        }
+        if let Some(read_parameters) = self.read_parameters {
+            flash.set_read_parameters(read_parameters);
+        }
+        if let Some(quirks) = self.quirks {
+            flash.set_quirks(quirks);
+        }
+        if let Some(board) = self.board {
+            flash.set_board(board);
+        }
+        flash.set_layout(self.layout);
+        flash.set_register_file(self.register_file);
+        flash.set_opcode_map(self.opcode_map);
+        Ok(flash)
+    }
+
+    /// Check the parameters are consistent and create a `SerialEeprom` using `access`,
+    /// for 25xx EEPROMs rather than flash.
+    ///
+    /// The capacity and page size must be specified; the number of address bytes defaults
+    /// to that used by devices of the given capacity. Other parameters are ignored.
+    /// Returns `InvalidConfiguration` if the parameters are missing or inconsistent.
+    pub fn build_eeprom<'a, A: FlashAccess>(self, access: &'a mut A) -> Result<SerialEeprom<'a, A>>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let (Some(capacity), Some(page_size)) = (self.capacity, self.page_size) else {
+            log::error!("Invalid EEPROM configuration: capacity and page size are required");
+            return Err(Error::InvalidConfiguration);
+        };
+        let address_bytes = self
+            .address_bytes
+            .unwrap_or(SerialEeprom::<A>::default_address_bytes(capacity));
+        let builder = FlashBuilder {
+            address_bytes: Some(address_bytes),
+            ..self
+        };
+        // 4kbit devices send a ninth address bit in the opcode.
+        let extra_bits = if address_bytes == 1 { 1 } else { 0 };
+        builder.validate(extra_bits)?;
+        if address_bytes == 4 {
+            log::error!("Invalid EEPROM configuration: 4-byte addresses are not supported");
+            return Err(Error::InvalidConfiguration);
+        }
+        let mut eeprom = SerialEeprom::new(access, capacity, page_size);
+        eeprom.set_address_bytes(address_bytes);
+        Ok(eeprom)
+    }
+
+    /// Check the parameters, where `extra_bits` address bits are sent outside the address bytes.
+    fn validate(&self, extra_bits: u64) -> Result<()> {
+        let invalid = |reason: &str| {
+            log::error!("Invalid flash configuration: {}", reason);
+            Err(Error::InvalidConfiguration)
+        };
+        let address_bytes = self.address_bytes.unwrap_or(3);
+        if !(1..=4).contains(&address_bytes) {
+            return invalid("address bytes must be from 1 to 4");
+        }
+        if let Some(capacity) = self.capacity {
+            if capacity as u64 > 1 << (8 * address_bytes as u64 + extra_bits) {
+                return invalid("capacity exceeds the addressable range");
+            }
+        }
+        let sizes = [
+            ("page size", self.page_size),
+            ("erase size", self.erase.map(|(size, _)| size)),
+        ];
+        for (name, size) in sizes {
+            let Some(size) = size else {
+                continue;
+            };
+            if !size.is_power_of_two() || self.capacity.is_some_and(|c| size > c) {
+                log::error!(
+                    "Invalid flash configuration: {} {} is not a power of two within the capacity",
+                    name,
+                    size
+                );
+                return Err(Error::InvalidConfiguration);
+            }
+        }
+        if let (Some(layout), Some(capacity)) = (&self.layout, self.capacity) {
+            let end = layout
+                .regions()
+                .last()
+                .map_or(0, |r| r.start as usize + r.size);
+            if end != capacity {
+                return invalid("layout does not cover the capacity");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_flash_builder() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    assert!(FlashBuilder::new()
+        .capacity(64 * 1024)
+        .erase(3000, 0x20)
+        .build(&mut mock)
+        .is_err());
+    assert!(FlashBuilder::new()
+        .capacity(64 * 1024 * 1024)
+        .build(&mut mock)
+        .is_err());
+    assert!(FlashBuilder::new()
+        .capacity(64 * 1024)
+        .page_size(128 * 1024)
+        .build(&mut mock)
+        .is_err());
+    let mut flash = FlashBuilder::new()
+        .capacity(64 * 1024)
+        .page_size(128)
+        .erase(4096, 0x20)
+        .read_parameters(ReadParameters::typical(crate::ReadMode::Fast))
+        .build(&mut mock)
+        .unwrap();
+    assert_eq!(flash.page_size(), Some(128));
+    flash.program(0x1F80, &[0xA5; 512], true).unwrap();
+    assert!(flash.read(0x10000, 1).is_err());
+}
@@ -0,0 +1,155 @@
+//! Read cache for frequently read data.
+//!
+//! Applications reading configuration or lookup tables often read the same few pages
+//! repeatedly, each time paying for a full SPI transaction. `CachedFlash` keeps the `N`
+//! most recently read pages of any `Storage` in RAM, and invalidates cached pages
+//! overlapping any program or erase made through it. Changes made to the underlying
+//! storage other than through the cache are not seen until `invalidate()` is called.
+//!
+//! Reads larger than the whole cache bypass it, so bulk reads such as firmware
+//! verification do not evict the pages worth keeping.
+
+use alloc::vec::Vec;
+
+use crate::{ReadStorage, Result, Storage};
+
+/// Size of each cached page in bytes.
+pub const CACHE_PAGE_SIZE: usize = 256;
+
+/// Read cache of `N` pages over `storage`.
+pub struct CachedFlash<'a, F, const N: usize> {
+    storage: &'a mut F,
+    /// Cached pages as their address and contents, most recently used first.
+    pages: Vec<(u32, Vec<u8>)>,
+}
+
+impl<'a, F: ReadStorage, const N: usize> CachedFlash<'a, F, N> {
+    /// Create an empty cache over `storage`.
+    pub fn new(storage: &'a mut F) -> Self {
+        CachedFlash {
+            storage,
+            pages: Vec::with_capacity(N),
+        }
+    }
+
+    /// Release the underlying storage.
+    pub fn release(self) -> &'a mut F {
+        self.storage
+    }
+
+    /// Discard all cached pages.
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Discard cached pages overlapping the `length` bytes starting at `address`.
+    pub fn invalidate_range(&mut self, address: u32, length: usize) {
+        let start = address as usize;
+        let end = start + length;
+        self.pages.retain(|(page, data)| {
+            let page = *page as usize;
+            end <= page || page + data.len() <= start
+        });
+    }
+
+    /// Get the page starting at `address`, reading it into the cache if required.
+    fn page(&mut self, address: u32) -> Result<&[u8]> {
+        match self.pages.iter().position(|(page, _)| *page == address) {
+            Some(idx) => {
+                let page = self.pages.remove(idx);
+                self.pages.insert(0, page);
+            }
+            None => {
+                let length = match self.storage.capacity() {
+                    Some(capacity) => {
+                        usize::min(CACHE_PAGE_SIZE, capacity.saturating_sub(address as usize))
+                    }
+                    None => CACHE_PAGE_SIZE,
+                };
+                let data = self.storage.read(address, length)?;
+                self.pages.truncate(N.saturating_sub(1));
+                self.pages.insert(0, (address, data));
+            }
+        }
+        Ok(&self.pages[0].1)
+    }
+}
+
+impl<'a, F: ReadStorage, const N: usize> ReadStorage for CachedFlash<'a, F, N> {
+    fn capacity(&self) -> Option<usize> {
+        self.storage.capacity()
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        let start = address as usize;
+        let end = start + length;
+        // Reads beyond the capacity are left to the storage to reject.
+        let beyond = self.capacity().is_some_and(|capacity| end > capacity);
+        if beyond || length > N * CACHE_PAGE_SIZE {
+            return self.storage.read(address, length);
+        }
+        let mut data = Vec::with_capacity(length);
+        let mut page = start - start % CACHE_PAGE_SIZE;
+        while page < end {
+            let cached = self.page(page as u32)?;
+            let from = start.saturating_sub(page);
+            let to = usize::min(end - page, cached.len());
+            data.extend_from_slice(&cached[from..to]);
+            page += CACHE_PAGE_SIZE;
+        }
+        Ok(data)
+    }
+}
+
+impl<'a, F: Storage, const N: usize> Storage for CachedFlash<'a, F, N> {
+    fn erase_size(&self) -> Option<usize> {
+        self.storage.erase_size()
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.invalidate_range(address, data.len());
+        self.storage.program_data(address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.invalidate_range(address, length);
+        self.storage.erase_data(address, length)
+    }
+}
+
+#[test]
+fn test_cached_flash() {
+    // A device whose capacity ends part way through a cache page.
+    let mut mock = crate::mock::MockFlash::new(8000);
+    let pattern: Vec<u8> = (0..8000).map(|x| x as u8).collect();
+    mock.memory_mut().copy_from_slice(&pattern);
+    let mut cache = CachedFlash::<_, 2>::new(&mut mock);
+    assert_eq!(cache.read(0x0FE, 4).unwrap(), [0xFE, 0xFF, 0x00, 0x01]);
+    assert_eq!(cache.read(0x100, 16).unwrap()[..2], [0x00, 0x01]);
+    assert_eq!(cache.read(0x080, 1).unwrap(), [0x80]);
+    assert_eq!(cache.storage.memory_reads(), 2);
+
+    // The least recently used page is evicted.
+    cache.read(0x200, 1).unwrap();
+    cache.read(0x000, 1).unwrap();
+    cache.read(0x100, 1).unwrap();
+    assert_eq!(cache.storage.memory_reads(), 4);
+
+    // The final partial page is cached up to the capacity.
+    assert_eq!(cache.read(0x1F3E, 2).unwrap(), [0x3E, 0x3F]);
+    assert!(cache.read(0x1F3F, 2).is_err());
+    assert!(cache.read(0x1F40, 1).is_err());
+
+    // Programs and erases invalidate overlapping pages.
+    cache.read(0x300, 1).unwrap();
+    cache.program_data(0x3FF, &[0x00]).unwrap();
+    assert_eq!(cache.read(0x300, 1).unwrap(), [0x00]);
+    assert_eq!(cache.read(0x3FF, 1).unwrap(), [0x00]);
+    cache.erase_data(0x000, 4096).unwrap();
+    assert_eq!(cache.read(0x3FF, 1).unwrap(), [0xFF]);
+
+    // Large reads bypass the cache.
+    let reads = cache.storage.memory_reads();
+    assert_eq!(cache.read(0x1000, 1024).unwrap().len(), 1024);
+    assert_eq!(cache.storage.memory_reads(), reads + 1);
+}
@@ -0,0 +1,46 @@
+/// AT45 DataFlash command opcodes.
+///
+/// These are taken from the Adesto AT45DB021E and AT45DB321E datasheets. DataFlash devices
+/// reuse several NOR flash opcodes with different meanings, such as 0x50 for a block erase
+/// and 0x53 for a page to buffer transfer, so they are sent using `Command::DataFlash`, with
+/// the 3-byte page and byte address sent as part of the data.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum DataFlashOpCode {
+    /// Read the manufacturer and device ID.
+    ReadID = 0x9F,
+    /// Read the two status register bytes.
+    StatusRead = 0xD7,
+    /// Read continuously across pages, followed by the address and one dummy byte.
+    ContinuousRead = 0x0B,
+    /// Read a single page, wrapping at its end, followed by the address and four dummy bytes.
+    MainMemoryPageRead = 0xD2,
+    /// Write data into buffer 1, addressed by the byte within the buffer.
+    Buffer1Write = 0x84,
+    /// Erase a page and program it from buffer 1, addressed by the page.
+    Buffer1ToMainWithErase = 0x83,
+    /// Program an erased page from buffer 1, addressed by the page.
+    Buffer1ToMainWithoutErase = 0x88,
+    /// Copy a page into buffer 1, addressed by the page.
+    PageToBuffer1 = 0x53,
+    /// Write data into buffer 1, then erase the addressed page and program it from the buffer.
+    PageProgramThroughBuffer1 = 0x82,
+    /// Erase a page, addressed by the page.
+    PageErase = 0x81,
+    /// Erase a block of 8 pages, addressed by any page in it.
+    BlockErase = 0x50,
+    /// Erase a sector, addressed by any page in it.
+    SectorErase = 0x7C,
+    /// Erase the whole device, followed by the bytes 0x94, 0x80, and 0x9A.
+    ChipErase = 0xC7,
+    /// Configure the page size, followed by the bytes 0x2A, 0x80, and 0xA6 for binary
+    /// page sizes or 0xA7 for DataFlash page sizes.
+    ConfigurePageSize = 0x3D,
+    /// Enter deep power-down.
+    DeepPowerDown = 0xB9,
+    /// Resume from deep power-down.
+    ResumeFromDeepPowerDown = 0xAB,
+}
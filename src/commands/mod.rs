@@ -2,19 +2,75 @@ use core::ops::Deref;
 
 /// 24 bits address.
 ///
-/// Any value if the MSB byte of the `u32` will be ignored.
+/// Created using `new()` or `TryFrom<u32>`, which reject addresses above 0xFFFFFF.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Address24Bits(pub u32);
+pub struct Address24Bits(pub(crate) u32);
 impl Address24Bits {
-    pub fn to_le_bytes(self) -> [u8; 3] {
+    /// Create a 24 bits address, or `None` if `address` does not fit in 3 bytes.
+    ///
+    /// Unlike `TryFrom<u32>`, this can be used in constant expressions.
+    pub const fn new(address: u32) -> Option<Self> {
+        if address > 0xFF_FFFF {
+            None
+        } else {
+            Some(Address24Bits(address))
+        }
+    }
+
+    /// Address bytes in the order they are sent on the bus, most significant first.
+    pub const fn to_be_bytes(self) -> [u8; 3] {
+        let [_, msb, csb, lsb] = self.0.to_be_bytes();
+        [msb, csb, lsb]
+    }
+
+    /// Address bytes least significant first, for transports that expect that order.
+    pub const fn to_le_bytes(self) -> [u8; 3] {
         let [lsb, csb, msb, _] = self.0.to_le_bytes();
         [lsb, csb, msb]
     }
 }
 
+impl Deref for Address24Bits {
+    type Target = u32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Addresses above 0xFFFFFF cannot be sent as 3 bytes, so are rejected with
+/// `Error::InvalidAddress` rather than truncated.
+impl TryFrom<u32> for Address24Bits {
+    type Error = crate::Error;
+
+    fn try_from(address: u32) -> crate::Result<Self> {
+        if address > 0xFF_FFFF {
+            log::error!("Address 0x{:08X} does not fit in 3 bytes", address);
+            return Err(crate::Error::InvalidAddress { address });
+        }
+        Ok(Address24Bits(address))
+    }
+}
+
 /// 32 bits address.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Address32Bits(pub u32);
+impl Address32Bits {
+    /// Create a 32 bits address.
+    ///
+    /// Every `u32` fits in 4 bytes, so this always returns `Some`; it is provided for
+    /// symmetry with `Address24Bits::new()` in constant expressions.
+    pub const fn new(address: u32) -> Option<Self> {
+        Some(Address32Bits(address))
+    }
+
+    /// Address bytes in the order they are sent on the bus, most significant first.
+    pub const fn to_be_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
 impl Deref for Address32Bits {
     type Target = u32;
 
@@ -23,4 +79,10 @@ impl Deref for Address32Bits {
     }
 }
 
+pub mod dataflash;
+pub mod nand;
+pub mod octal;
+pub mod serial_memory;
 pub mod spansion;
+pub mod spec;
+pub mod with_data;
@@ -0,0 +1,40 @@
+/// SPI NAND command opcodes.
+///
+/// These are taken from the Winbond W25N01GV and Micron MT29F1G01ABAFD datasheets. SPI NAND
+/// devices share some opcodes with NOR flash, but with different meanings and address
+/// formats, so they are sent using `Command::Nand`, with any row (page) or column address
+/// sent as part of the data.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum NandOpCode {
+    /// Reset the device, abandoning any operation in progress.
+    Reset = 0xFF,
+    /// Read the JEDEC ID, after one dummy byte.
+    ReadJEDECID = 0x9F,
+    /// Read a feature register, addressed by one byte.
+    GetFeature = 0x0F,
+    /// Write a feature register, addressed by one byte.
+    SetFeature = 0x1F,
+    WriteEnable = 0x06,
+    WriteDisable = 0x04,
+    /// Read a page into the cache, addressed by a 3-byte row address.
+    PageRead = 0x13,
+    /// Read from the cache, addressed by a 2-byte column address and followed by one dummy byte.
+    ReadFromCache = 0x03,
+    /// Fast read from the cache, addressed by a 2-byte column address and followed by
+    /// one dummy byte.
+    FastReadFromCache = 0x0B,
+    /// Load data into the cache, setting the rest of the cache to 0xFF,
+    /// addressed by a 2-byte column address.
+    ProgramLoad = 0x02,
+    /// Load data into the cache without changing the rest of the cache,
+    /// addressed by a 2-byte column address.
+    RandomProgramLoad = 0x84,
+    /// Program the cache into a page, addressed by a 3-byte row address.
+    ProgramExecute = 0x10,
+    /// Erase a block, addressed by the 3-byte row address of any page in it.
+    BlockErase = 0xD8,
+}
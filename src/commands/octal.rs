@@ -0,0 +1,139 @@
+use arrayvec::ArrayVec;
+
+use super::spansion::Command;
+
+/// Octal SPI (xSPI) command opcodes.
+///
+/// These are taken from the Macronix MX25LM51245G (OctaFlash) and Micron MT35XU512ABA
+/// (Xccela) datasheets. In octal mode every instruction uses eight lines and is sent as
+/// a two-byte opcode: the instruction followed by its command extension, see
+/// `CommandExtension`. Both families only accept 4-byte addresses in octal mode, so the
+/// 4-byte address variants of the program, erase, and read instructions are used,
+/// see `four_byte_opcode()`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum OctalOpCode {
+    /// Macronix octal read (8READ) in STR mode.
+    MacronixRead = 0xEC,
+    /// Macronix octal read (8DTRD) in DTR mode.
+    MacronixReadDtr = 0xEE,
+    /// Macronix Read Configuration Register 2, addressed by a 4-byte address.
+    ReadConfigRegister2 = 0x71,
+    /// Macronix Write Configuration Register 2, addressed by a 4-byte address.
+    WriteConfigRegister2 = 0x72,
+
+    /// Micron Octal I/O Fast Read, 1-8-8 in extended SPI mode and 8-8-8 in octal mode.
+    MicronReadOctalIO = 0xCC,
+    /// Micron Octal DTR Read, 8D-8D-8D.
+    MicronReadDtr = 0xFD,
+    /// Micron Write Volatile Configuration Register, addressed by a 3-byte address.
+    WriteVolatileConfig = 0x81,
+    /// Micron Read Volatile Configuration Register, addressed by a 3-byte address.
+    ReadVolatileConfig = 0x85,
+}
+
+/// Encoding of the second opcode byte of instructions sent in octal mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandExtension {
+    /// The opcode is repeated, as used by Micron Xccela devices.
+    Repeat,
+    /// The inverted opcode is sent, as used by Macronix OctaFlash devices.
+    Invert,
+}
+
+impl CommandExtension {
+    /// Get the second opcode byte sent for `opcode`.
+    pub fn encode(self, opcode: u8) -> u8 {
+        match self {
+            CommandExtension::Repeat => opcode,
+            CommandExtension::Invert => !opcode,
+        }
+    }
+}
+
+/// Octal device families, which differ in how octal mode is entered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OctalFamily {
+    /// Macronix OctaFlash devices, such as MX25LM and MX25UM.
+    Macronix,
+    /// Micron Xccela devices, such as MT35X.
+    Micron,
+}
+
+impl OctalFamily {
+    /// Command extension used by this family in octal mode.
+    pub fn extension(self) -> CommandExtension {
+        match self {
+            OctalFamily::Macronix => CommandExtension::Invert,
+            OctalFamily::Micron => CommandExtension::Repeat,
+        }
+    }
+
+    /// Get the register write which enters octal mode, as a command and its data,
+    /// which begins with the register address.
+    ///
+    /// On Macronix devices, this sets the SOPI or DOPI bit in configuration register 2.
+    /// On Micron devices, this writes the octal STR or DTR mode with data strobe to
+    /// volatile configuration register 0.
+    pub fn enter_command(self, dtr: bool) -> (Command, ArrayVec<u8, 5>) {
+        let (opcode, data): (_, &[u8]) = match (self, dtr) {
+            (OctalFamily::Macronix, false) => {
+                (OctalOpCode::WriteConfigRegister2, &[0, 0, 0, 0, 0b01])
+            }
+            (OctalFamily::Macronix, true) => {
+                (OctalOpCode::WriteConfigRegister2, &[0, 0, 0, 0, 0b10])
+            }
+            (OctalFamily::Micron, false) => (OctalOpCode::WriteVolatileConfig, &[0, 0, 0, 0xB7]),
+            (OctalFamily::Micron, true) => (OctalOpCode::WriteVolatileConfig, &[0, 0, 0, 0xE7]),
+        };
+        (Command::Octal(opcode), data.iter().copied().collect())
+    }
+
+    /// Opcode of the read instruction used in octal mode.
+    pub fn read_opcode(self, dtr: bool) -> u8 {
+        let opcode = match (self, dtr) {
+            (OctalFamily::Macronix, false) => OctalOpCode::MacronixRead,
+            (OctalFamily::Macronix, true) => OctalOpCode::MacronixReadDtr,
+            (OctalFamily::Micron, false) => OctalOpCode::MicronReadOctalIO,
+            (OctalFamily::Micron, true) => OctalOpCode::MicronReadDtr,
+        };
+        opcode.into()
+    }
+
+    /// Number of dummy cycles used by reads in octal mode after power-up.
+    pub fn read_dummy_cycles(self) -> u8 {
+        match self {
+            OctalFamily::Macronix => 20,
+            OctalFamily::Micron => 16,
+        }
+    }
+
+    /// Number of dummy cycles used by register reads, such as Read Status Register,
+    /// in octal mode after power-up.
+    pub fn register_dummy_cycles(self) -> u8 {
+        match self {
+            OctalFamily::Macronix => 4,
+            OctalFamily::Micron => 8,
+        }
+    }
+
+    /// Check if register reads in octal mode send a 4-byte address.
+    ///
+    /// Macronix devices expect an address after every register read opcode in octal
+    /// mode, which is zero for registers which are not addressed.
+    pub fn register_read_address(self) -> bool {
+        self == OctalFamily::Macronix
+    }
+}
+
+/// Octal mode configuration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OctalMode {
+    /// Device family, which determines the command extension and read instruction.
+    pub family: OctalFamily,
+    /// If set, octal DTR mode is used, otherwise octal STR mode.
+    pub dtr: bool,
+}
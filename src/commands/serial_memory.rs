@@ -0,0 +1,30 @@
+/// Command opcodes of byte-writable serial memories such as FRAM, MRAM, and EEPROM.
+///
+/// These are taken from the Infineon FM25V10, Fujitsu MB85RS64V, and Microchip 25LC256
+/// datasheets. These devices use a subset of the SPI flash instruction set, but take
+/// 1- or 2-byte addresses when their capacity is 64kB or less, so they are sent using
+/// `Command::SerialMemory`, with the address sent as part of the data.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum SerialMemoryOpCode {
+    WriteEnable = 0x06,
+    WriteDisable = 0x04,
+    ReadStatusRegister = 0x05,
+    WriteStatusRegister = 0x01,
+    /// Read memory, followed by the address.
+    Read = 0x03,
+    /// Write memory, followed by the address and data.
+    Write = 0x02,
+    /// Read memory on 4kbit EEPROMs with 1-byte addresses, whose ninth address bit is
+    /// sent as bit 3 of the opcode, from the upper 256 bytes.
+    ReadUpper = 0x0B,
+    /// Write memory on 4kbit EEPROMs, to the upper 256 bytes.
+    WriteUpper = 0x0A,
+    /// Read the device ID, which is not supported by all devices.
+    ReadID = 0x9F,
+    /// Enter sleep mode, which is left on the next chip select.
+    Sleep = 0xB9,
+}
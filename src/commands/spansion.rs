@@ -1,5 +1,9 @@
 use either::Either;
 
+use super::dataflash::DataFlashOpCode;
+use super::nand::NandOpCode;
+use super::octal::OctalOpCode;
+use super::serial_memory::SerialMemoryOpCode;
 use super::{Address24Bits, Address32Bits};
 
 /// Standard SPI flash command opcodes.
@@ -9,7 +13,8 @@ use super::{Address24Bits, Address32Bits};
 /// the relevant erase opcodes and sizes.
 ///
 /// .
-#[derive(Copy, Clone, Debug, num_enum::IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum CommandOpCode {
     // Core instruction set.
@@ -30,23 +35,44 @@ pub enum CommandOpCode {
     ReleasePowerdown = 0xAB,
     ReadDeviceID = 0x90,
     ChipErase = 0xC7,
+    /// Chip Erase under its alternate opcode, which some devices implement instead of 0xC7.
+    ChipEraseAlt = 0x60,
 
     // Extended instruction set.
     // These commands may be available.
     ReadUniqueID = 0x4B,
+    /// Read Manufacturer/Device ID Dual I/O, with the address and data on two lines.
+    ReadDeviceIDDual = 0x92,
+    /// Read Manufacturer/Device ID Quad I/O, with the address and data on four lines.
+    ReadDeviceIDQuad = 0x94,
     ReadSFDPRegister = 0x5A,
     ReadStatusRegister2 = 0x35,
     ReadStatusRegister3 = 0x15,
     ReadFlagStatusRegister = 0x70,
     WriteStatusRegister2 = 0x31,
     WriteStatusRegister3 = 0x11,
+    /// Alternative status register 2 read, used by devices with QE in bit 7 of SR2.
+    ReadStatusRegister2Alt = 0x3F,
+    /// Alternative status register 2 write, used by devices with QE in bit 7 of SR2.
+    WriteStatusRegister2Alt = 0x3E,
     WriteEnableVolatile = 0x50,
+    ReadAnyRegister = 0x65,
+    WriteAnyRegister = 0x71,
     EnableReset = 0x66,
     Reset = 0x99,
     SoftwareReset = 0xF0,
     ProgramSuspend = 0x75,
     ProgramResume = 0x7A,
 
+    // 4-byte address instructions.
+    // These take a 4-byte address regardless of the device address mode.
+    ReadData4B = 0x13,
+    FastRead4B = 0x0C,
+    PageProgram4B = 0x12,
+    SectorErase4B = 0x21,
+    BlockErase32K4B = 0x5C,
+    BlockErase64K4B = 0xDC,
+
     // Erase instructions.
     // The size affected by each erase operation can vary.
     // Typical sizes are 4kB for sector erase, 32kB for block erase 1,
@@ -64,6 +90,58 @@ pub enum CommandOpCode {
     ReadBlockLock = 0x3D,
     GlobalBlockLock = 0x7E,
     GlobalBlockUnlock = 0x98,
+    /// SST26 Read Block-Protection Register.
+    ReadBlockProtection = 0x72,
+    /// Spansion Dynamic Protection Bit Read, with a 4-byte address.
+    ReadDyb = 0xE0,
+    /// Spansion Dynamic Protection Bit Write, with a 4-byte address.
+    WriteDyb = 0xE1,
+    /// Spansion Persistent Protection Bit Read, with a 4-byte address.
+    ReadPpb = 0xE2,
+    /// Spansion Persistent Protection Bit Program, with a 4-byte address.
+    ProgramPpb = 0xE3,
+    /// Spansion Persistent Protection Bits Erase, which clears all PPBs.
+    ErasePpb = 0xE4,
+    /// Spansion PPB Lock Bit Read.
+    ReadPpbLock = 0xA7,
+    /// Spansion PPB Lock Bit Write, which clears the PPB lock bit, freezing the PPBs
+    /// until the next power cycle or reset.
+    WritePpbLock = 0xA6,
+    /// Spansion ASP Register Read.
+    ReadAsp = 0x2B,
+    /// Spansion ASP Register Program.
+    ProgramAsp = 0x2F,
+    /// Spansion Password Read, which reads the 64-bit password until Password
+    /// Protection mode is selected.
+    ReadPassword = 0xE7,
+    /// Spansion Password Program.
+    ProgramPassword = 0xE8,
+    /// Spansion Password Unlock, which sets the PPB lock bit if the password matches.
+    UnlockPassword = 0xE9,
+
+    /// Spansion Bank Register Read.
+    ReadBankRegister = 0x16,
+    /// Spansion Bank Register Write, which sets the upper address bits used by
+    /// 3-byte address instructions, and the EXTADD bit.
+    WriteBankRegister = 0x17,
+
+    /// Winbond Read Extended Address Register.
+    ReadExtendedAddressRegister = 0xC8,
+    /// Winbond Write Extended Address Register, which sets the upper address bits used by
+    /// 3-byte address instructions.
+    WriteExtendedAddressRegister = 0xC5,
+
+    // Stacked-die instructions.
+    /// Software Die Select, followed by the die index, on stacked-die devices such as
+    /// the Winbond W25M.
+    DieSelect = 0xC2,
+
+    // Replay Protected Monotonic Counter instructions.
+    /// RPMC OP1, which writes a root key, updates an HMAC key, or increments or
+    /// requests a counter, selected by its first data byte.
+    RpmcOp1 = 0x9B,
+    /// RPMC OP2, which reads the RPMC status and the result of the last OP1.
+    RpmcOp2 = 0x96,
 
     // DSPI
     ReadDualOut = 0x3B,
@@ -72,12 +150,28 @@ pub enum CommandOpCode {
     // QSPI
     ReadQuadOut = 0x6B,
     ReadQuadIO = 0xEB,
+
+    // DTR
+    FastReadDtr = 0x0D,
+    ReadDualIODtr = 0xBD,
+    ReadQuadIODtr = 0xED,
+
+    /// Clocking 0xFF exits continuous read mode, as its mode bits are not valid.
+    /// In QPI mode, this is also the Exit QPI instruction.
+    ModeBitReset = 0xFF,
+
+    // QPI
+    EnterQpi = 0x38,
+    SetReadParameters = 0xC0,
 }
 
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy)]
 pub enum Command {
     // Read device identification
     ReadDeviceID,
+    ReadDeviceIDDual,
+    ReadDeviceIDQuad,
     ReadJEDECID,
     ReleasePowerdown,
 
@@ -92,6 +186,7 @@ pub enum Command {
     FastRead(Address24Bits),
     Powerdown,
     ChipErase,
+    ChipEraseAlt,
 
     ReadUniqueID,
     ReadSFDPRegister(Address24Bits),
@@ -100,73 +195,446 @@ pub enum Command {
     ReadFlagStatusRegister,
     WriteStatusRegister2,
     WriteStatusRegister3,
+    ReadStatusRegister2Alt,
+    WriteStatusRegister2Alt,
     WriteEnableVolatile,
+    ReadAnyRegister(Address24Bits),
+    WriteAnyRegister(Address24Bits),
     EnableReset,
     Reset,
+    SoftwareReset,
+
+    SectorErase(Address24Bits),
+    BlockErase1(Address24Bits),
+    BlockErase2(Address24Bits),
+
+    ReadData4B(Address32Bits),
+    FastRead4B(Address32Bits),
+    PageProgram4B(Address32Bits),
+    SectorErase4B(Address32Bits),
+    BlockErase32K4B(Address32Bits),
+    BlockErase64K4B(Address32Bits),
+
+    IndividualBlockLock(Address24Bits),
+    IndividualBlockUnlock(Address24Bits),
+    ReadBlockLock(Address24Bits),
+    GlobalBlockLock,
+    GlobalBlockUnlock,
+    ReadBlockProtection,
+    /// SST26 Write Block-Protection Register, which shares its opcode with
+    /// Program Security Registers on other devices.
+    WriteBlockProtection,
+    /// Winbond and GigaDevice security register instructions, addressed by the register
+    /// number in bits 12 to 15 and the byte within the register in bits 0 to 7.
+    EraseSecurityRegister(Address24Bits),
+    ProgramSecurityRegister(Address24Bits),
+    ReadSecurityRegister(Address24Bits),
+    /// Spansion Read Configuration Register 1, which shares its opcode with
+    /// Read Status Register 2 on other devices.
+    ReadConfigRegister1,
+    ReadDyb(Address32Bits),
+    WriteDyb(Address32Bits),
+    ReadPpb(Address32Bits),
+    ProgramPpb(Address32Bits),
+    ErasePpb,
+    ReadPpbLock,
+    WritePpbLock,
+    ReadAsp,
+    ProgramAsp,
+    ReadPassword,
+    ProgramPassword,
+    UnlockPassword,
+    ReadBankRegister,
+    WriteBankRegister,
+    ReadExtendedAddressRegister,
+    WriteExtendedAddressRegister,
+    DieSelect,
+
+    RpmcOp1,
+    RpmcOp2,
 
     ReadDualOut(Address24Bits),
     ReadQuadOut(Address24Bits),
     ReadDualIO(Address24Bits),
     ReadQuadIO(Address24Bits),
+    FastReadDtr(Address24Bits),
+    ReadDualIODtr(Address24Bits),
+    ReadQuadIODtr(Address24Bits),
+
+    ModeBitReset,
+    EnterQpi,
+    ExitQpi,
+    /// Micron Clear Flag Status Register, sharing its opcode with WriteEnableVolatile.
+    ClearFlagStatusRegister,
+    SetReadParameters,
+
+    /// An octal mode instruction, whose address, if any, is sent as part of the data.
+    Octal(OctalOpCode),
+    /// An SPI NAND instruction, whose address, if any, is sent as part of the data.
+    Nand(NandOpCode),
+    /// An FRAM, MRAM, or EEPROM instruction, whose address, if any, is sent as part of the data.
+    SerialMemory(SerialMemoryOpCode),
+    /// An AT45 DataFlash instruction, whose address, if any, is sent as part of the data.
+    DataFlash(DataFlashOpCode),
+    /// Any other instruction, given by its opcode and an optional 3-byte address,
+    /// as sent by `Flash::exec_raw()`.
+    Raw(u8, Option<Address24Bits>),
+    /// Any other instruction, given by its opcode and a 4-byte address.
+    Raw4B(u8, Address32Bits),
 }
+/// Encoded bytes of a command, as returned by `Command::to_array()`.
+///
+/// This dereferences to a slice of the bytes to send on the bus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CommandBytes {
+    bytes: [u8; 5],
+    len: usize,
+}
+
+impl CommandBytes {
+    /// Get the encoded bytes.
+    pub const fn as_slice(&self) -> &[u8] {
+        self.bytes.split_at(self.len).0
+    }
+
+    /// Get the fixed-size buffer holding the encoded bytes, and the number of bytes used.
+    pub const fn into_parts(self) -> ([u8; 5], usize) {
+        (self.bytes, self.len)
+    }
+}
+
+impl core::ops::Deref for CommandBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
 impl Command {
-    pub(crate) fn to_array(&self) -> arrayvec::ArrayVec<u8, 4> {
+    /// Get the opcode and optional address sent for this command.
+    pub(crate) const fn parts(self) -> (u8, Option<Either<Address24Bits, Address32Bits>>) {
         use either::Either::*;
-        match *self {
-            Command::ReadStatusRegister1 => Left(CommandOpCode::ReadStatusRegister1),
-            Command::WriteStatusRegister1 => Left(CommandOpCode::WriteStatusRegister1),
-            Command::ReadUniqueID => Left(CommandOpCode::ReadUniqueID),
-            Command::ReadJEDECID => Left(CommandOpCode::ReadJEDECID),
-            Command::ReadSFDPRegister(addr) => Right((CommandOpCode::ReadSFDPRegister, addr)),
-            Command::ReadStatusRegister2 => Left(CommandOpCode::ReadStatusRegister2),
-            Command::ReadStatusRegister3 => Left(CommandOpCode::ReadStatusRegister3),
-            Command::WriteStatusRegister2 => Left(CommandOpCode::WriteStatusRegister2),
-            Command::WriteStatusRegister3 => Left(CommandOpCode::WriteStatusRegister3),
-            Command::WriteEnableVolatile => Left(CommandOpCode::WriteEnableVolatile),
-            Command::WriteEnable => Left(CommandOpCode::WriteEnable),
-            Command::WriteDisable => Left(CommandOpCode::WriteDisable),
-            Command::FastRead(addr) => Right((CommandOpCode::FastRead, addr)),
-            Command::PageProgram(addr) => Right((CommandOpCode::PageProgram, addr)),
-            Command::ReadData(addr) => Right((CommandOpCode::ReadData, addr)),
-            Command::ReadDualOut(addr) => Right((CommandOpCode::ReadDualOut, addr)),
-            Command::ReadQuadOut(addr) => Right((CommandOpCode::ReadQuadOut, addr)),
-            Command::ReadDualIO(addr) => Right((CommandOpCode::ReadDualIO, addr)),
-            Command::ReadQuadIO(addr) => Right((CommandOpCode::ReadQuadIO, addr)),
-            _ => {
-                todo!()
+        match self {
+            Command::ReadDeviceID => (CommandOpCode::ReadDeviceID as u8, None),
+            Command::ReadDeviceIDDual => (CommandOpCode::ReadDeviceIDDual as u8, None),
+            Command::ReadDeviceIDQuad => (CommandOpCode::ReadDeviceIDQuad as u8, None),
+            Command::ReadJEDECID => (CommandOpCode::ReadJEDECID as u8, None),
+            Command::ReleasePowerdown => (CommandOpCode::ReleasePowerdown as u8, None),
+            Command::ReadStatusRegister1 => (CommandOpCode::ReadStatusRegister1 as u8, None),
+            Command::WriteEnable => (CommandOpCode::WriteEnable as u8, None),
+            Command::WriteDisable => (CommandOpCode::WriteDisable as u8, None),
+            Command::ReadData(addr) => (CommandOpCode::ReadData as u8, Some(Left(addr))),
+            Command::PageProgram(addr) => (CommandOpCode::PageProgram as u8, Some(Left(addr))),
+            Command::WriteStatusRegister1 => (CommandOpCode::WriteStatusRegister1 as u8, None),
+            Command::FastRead(addr) => (CommandOpCode::FastRead as u8, Some(Left(addr))),
+            Command::Powerdown => (CommandOpCode::Powerdown as u8, None),
+            Command::ChipErase => (CommandOpCode::ChipErase as u8, None),
+            Command::ChipEraseAlt => (CommandOpCode::ChipEraseAlt as u8, None),
+            Command::ReadUniqueID => (CommandOpCode::ReadUniqueID as u8, None),
+            Command::ReadSFDPRegister(addr) => {
+                (CommandOpCode::ReadSFDPRegister as u8, Some(Left(addr)))
+            }
+            Command::ReadStatusRegister2 => (CommandOpCode::ReadStatusRegister2 as u8, None),
+            Command::ReadStatusRegister3 => (CommandOpCode::ReadStatusRegister3 as u8, None),
+            Command::ReadFlagStatusRegister => (CommandOpCode::ReadFlagStatusRegister as u8, None),
+            Command::WriteStatusRegister2 => (CommandOpCode::WriteStatusRegister2 as u8, None),
+            Command::WriteStatusRegister3 => (CommandOpCode::WriteStatusRegister3 as u8, None),
+            Command::ReadStatusRegister2Alt => (CommandOpCode::ReadStatusRegister2Alt as u8, None),
+            Command::WriteStatusRegister2Alt => {
+                (CommandOpCode::WriteStatusRegister2Alt as u8, None)
             }
+            Command::WriteEnableVolatile => (CommandOpCode::WriteEnableVolatile as u8, None),
+            Command::ReadAnyRegister(addr) => {
+                (CommandOpCode::ReadAnyRegister as u8, Some(Left(addr)))
+            }
+            Command::WriteAnyRegister(addr) => {
+                (CommandOpCode::WriteAnyRegister as u8, Some(Left(addr)))
+            }
+            Command::EnableReset => (CommandOpCode::EnableReset as u8, None),
+            Command::Reset => (CommandOpCode::Reset as u8, None),
+            Command::SoftwareReset => (CommandOpCode::SoftwareReset as u8, None),
+            Command::SectorErase(addr) => (CommandOpCode::SectorErase as u8, Some(Left(addr))),
+            Command::BlockErase1(addr) => (CommandOpCode::BlockErase1 as u8, Some(Left(addr))),
+            Command::BlockErase2(addr) => (CommandOpCode::BlockErase2 as u8, Some(Left(addr))),
+            Command::ReadData4B(addr) => (CommandOpCode::ReadData4B as u8, Some(Right(addr))),
+            Command::FastRead4B(addr) => (CommandOpCode::FastRead4B as u8, Some(Right(addr))),
+            Command::PageProgram4B(addr) => (CommandOpCode::PageProgram4B as u8, Some(Right(addr))),
+            Command::SectorErase4B(addr) => (CommandOpCode::SectorErase4B as u8, Some(Right(addr))),
+            Command::BlockErase32K4B(addr) => {
+                (CommandOpCode::BlockErase32K4B as u8, Some(Right(addr)))
+            }
+            Command::BlockErase64K4B(addr) => {
+                (CommandOpCode::BlockErase64K4B as u8, Some(Right(addr)))
+            }
+            Command::IndividualBlockLock(addr) => {
+                (CommandOpCode::IndividualBlockLock as u8, Some(Left(addr)))
+            }
+            Command::IndividualBlockUnlock(addr) => {
+                (CommandOpCode::IndividualBlockUnlock as u8, Some(Left(addr)))
+            }
+            Command::ReadBlockLock(addr) => (CommandOpCode::ReadBlockLock as u8, Some(Left(addr))),
+            Command::GlobalBlockLock => (CommandOpCode::GlobalBlockLock as u8, None),
+            Command::GlobalBlockUnlock => (CommandOpCode::GlobalBlockUnlock as u8, None),
+            Command::ReadBlockProtection => (CommandOpCode::ReadBlockProtection as u8, None),
+            Command::WriteBlockProtection => (CommandOpCode::ProgramSecurityRegisters as u8, None),
+            Command::EraseSecurityRegister(addr) => (
+                CommandOpCode::EraseSecurityRegisters as u8,
+                Some(Left(addr)),
+            ),
+            Command::ProgramSecurityRegister(addr) => (
+                CommandOpCode::ProgramSecurityRegisters as u8,
+                Some(Left(addr)),
+            ),
+            Command::ReadSecurityRegister(addr) => {
+                (CommandOpCode::ReadSecurityRegisters as u8, Some(Left(addr)))
+            }
+            Command::ReadConfigRegister1 => (CommandOpCode::ReadStatusRegister2 as u8, None),
+            Command::ReadDyb(addr) => (CommandOpCode::ReadDyb as u8, Some(Right(addr))),
+            Command::WriteDyb(addr) => (CommandOpCode::WriteDyb as u8, Some(Right(addr))),
+            Command::ReadPpb(addr) => (CommandOpCode::ReadPpb as u8, Some(Right(addr))),
+            Command::ProgramPpb(addr) => (CommandOpCode::ProgramPpb as u8, Some(Right(addr))),
+            Command::ErasePpb => (CommandOpCode::ErasePpb as u8, None),
+            Command::ReadPpbLock => (CommandOpCode::ReadPpbLock as u8, None),
+            Command::WritePpbLock => (CommandOpCode::WritePpbLock as u8, None),
+            Command::ReadAsp => (CommandOpCode::ReadAsp as u8, None),
+            Command::ProgramAsp => (CommandOpCode::ProgramAsp as u8, None),
+            Command::ReadPassword => (CommandOpCode::ReadPassword as u8, None),
+            Command::ProgramPassword => (CommandOpCode::ProgramPassword as u8, None),
+            Command::UnlockPassword => (CommandOpCode::UnlockPassword as u8, None),
+            Command::ReadBankRegister => (CommandOpCode::ReadBankRegister as u8, None),
+            Command::WriteBankRegister => (CommandOpCode::WriteBankRegister as u8, None),
+            Command::ReadExtendedAddressRegister => {
+                (CommandOpCode::ReadExtendedAddressRegister as u8, None)
+            }
+            Command::WriteExtendedAddressRegister => {
+                (CommandOpCode::WriteExtendedAddressRegister as u8, None)
+            }
+            Command::DieSelect => (CommandOpCode::DieSelect as u8, None),
+            Command::RpmcOp1 => (CommandOpCode::RpmcOp1 as u8, None),
+            Command::RpmcOp2 => (CommandOpCode::RpmcOp2 as u8, None),
+            Command::ReadDualOut(addr) => (CommandOpCode::ReadDualOut as u8, Some(Left(addr))),
+            Command::ReadQuadOut(addr) => (CommandOpCode::ReadQuadOut as u8, Some(Left(addr))),
+            Command::ReadDualIO(addr) => (CommandOpCode::ReadDualIO as u8, Some(Left(addr))),
+            Command::ReadQuadIO(addr) => (CommandOpCode::ReadQuadIO as u8, Some(Left(addr))),
+            Command::FastReadDtr(addr) => (CommandOpCode::FastReadDtr as u8, Some(Left(addr))),
+            Command::ReadDualIODtr(addr) => (CommandOpCode::ReadDualIODtr as u8, Some(Left(addr))),
+            Command::ReadQuadIODtr(addr) => (CommandOpCode::ReadQuadIODtr as u8, Some(Left(addr))),
+            Command::ModeBitReset => (CommandOpCode::ModeBitReset as u8, None),
+            Command::EnterQpi => (CommandOpCode::EnterQpi as u8, None),
+            Command::ExitQpi => (CommandOpCode::ModeBitReset as u8, None),
+            Command::ClearFlagStatusRegister => (CommandOpCode::WriteEnableVolatile as u8, None),
+            Command::SetReadParameters => (CommandOpCode::SetReadParameters as u8, None),
+            Command::Octal(opcode) => (opcode as u8, None),
+            Command::Nand(opcode) => (opcode as u8, None),
+            Command::SerialMemory(opcode) => (opcode as u8, None),
+            Command::DataFlash(opcode) => (opcode as u8, None),
+            Command::Raw(opcode, Some(addr)) => (opcode, Some(Left(addr))),
+            Command::Raw(opcode, None) => (opcode, None),
+            Command::Raw4B(opcode, addr) => (opcode, Some(Right(addr))),
         }
-        .map_left(|c| [u8::from(c)].into_iter())
-        .map_right(|(c, addr)| {
-            Iterator::chain([u8::from(c)].into_iter(), addr.to_le_bytes().into_iter())
-        })
-        .into_iter()
-        .collect()
     }
 
-    pub(crate) fn len(&self) -> usize {
-        match self {
-            Command::ReadUniqueID => 1,
-            Command::ReadJEDECID => 1,
-            Command::ReadStatusRegister1 => 1,
-            Command::ReadStatusRegister2 => 1,
-            Command::ReadStatusRegister3 => 1,
-            Command::WriteEnable => 1,
-            Command::WriteDisable => 1,
-            Command::PageProgram(_) => 4,
-            Command::ReadData(_) => 4,
-            Command::ReadDualOut(_) => 4,
-            Command::ReadQuadOut(_) => 4,
-            Command::ReadDualIO(_) => 4,
-            Command::ReadQuadIO(_) => 4,
-            _ => todo!(),
+    /// Get the opcode sent for this command.
+    pub const fn opcode(self) -> u8 {
+        self.parts().0
+    }
+
+    /// Encode this command as the bytes to send on the bus: the opcode,
+    /// followed by the address (most significant byte first) if present.
+    ///
+    /// This is a `const fn`, so command byte sequences may be computed at compile time.
+    pub const fn to_array(self) -> CommandBytes {
+        let (opcode, addr) = self.parts();
+        let mut bytes = [opcode, 0, 0, 0, 0];
+        let len = match addr {
+            Some(Either::Left(addr)) => {
+                let [msb, csb, lsb] = addr.to_be_bytes();
+                (bytes[1], bytes[2], bytes[3]) = (msb, csb, lsb);
+                4
+            }
+            Some(Either::Right(addr)) => {
+                let [b3, b2, b1, b0] = addr.to_be_bytes();
+                (bytes[1], bytes[2], bytes[3], bytes[4]) = (b3, b2, b1, b0);
+                5
+            }
+            None => 1,
+        };
+        CommandBytes { bytes, len }
+    }
+
+    /// Number of bytes sent on the bus for this command.
+    #[allow(clippy::len_without_is_empty)]
+    pub const fn len(self) -> usize {
+        match self.parts().1 {
+            None => 1,
+            Some(Either::Left(_)) => 4,
+            Some(Either::Right(_)) => 5,
         }
     }
 
+    /// Find the command corresponding to `op_code`.
+    ///
+    /// `addr` must be provided for commands which take an address, and is ignored otherwise.
+    /// Commands taking a 24-bit address accept a 32-bit address if its most significant
+    /// byte is zero, and 4-byte address commands accept either.
     pub fn try_from_byte(
         op_code: u8,
         addr: Option<Either<Address24Bits, Address32Bits>>,
     ) -> crate::Result<Self> {
-        todo!()
+        let op_code = CommandOpCode::try_from(op_code).map_err(|err| {
+            log::error!("Unknown opcode 0x{:02X}", err.number);
+            crate::Error::UnknownOpcode { opcode: err.number }
+        })?;
+        let missing_addr = || {
+            log::error!("Opcode {:?} requires an address", op_code);
+            crate::Error::UnknownOpcode {
+                opcode: op_code.into(),
+            }
+        };
+        let needs_addr = || match addr {
+            Some(Either::Left(addr)) => Ok(addr),
+            Some(Either::Right(addr)) if *addr <= 0xFF_FFFF => Ok(Address24Bits(*addr)),
+            Some(Either::Right(addr)) => Err(crate::Error::InvalidAddress { address: *addr }),
+            None => Err(missing_addr()),
+        };
+        let needs_addr32 = || match addr {
+            Some(Either::Left(addr)) => Ok(Address32Bits(addr.0 & 0xFF_FFFF)),
+            Some(Either::Right(addr)) => Ok(addr),
+            None => Err(missing_addr()),
+        };
+        Ok(match op_code {
+            CommandOpCode::WriteEnable => Command::WriteEnable,
+            CommandOpCode::WriteDisable => Command::WriteDisable,
+            CommandOpCode::ReadData => Command::ReadData(needs_addr()?),
+            CommandOpCode::PageProgram => Command::PageProgram(needs_addr()?),
+            CommandOpCode::ReadStatusRegister1 => Command::ReadStatusRegister1,
+            CommandOpCode::WriteStatusRegister1 => Command::WriteStatusRegister1,
+            CommandOpCode::ReadJEDECID => Command::ReadJEDECID,
+            CommandOpCode::FastRead => Command::FastRead(needs_addr()?),
+            CommandOpCode::Powerdown => Command::Powerdown,
+            CommandOpCode::ReleasePowerdown => Command::ReleasePowerdown,
+            CommandOpCode::ReadDeviceID => Command::ReadDeviceID,
+            CommandOpCode::ReadDeviceIDDual => Command::ReadDeviceIDDual,
+            CommandOpCode::ReadDeviceIDQuad => Command::ReadDeviceIDQuad,
+            CommandOpCode::ChipErase => Command::ChipErase,
+            CommandOpCode::ChipEraseAlt => Command::ChipEraseAlt,
+            CommandOpCode::ReadUniqueID => Command::ReadUniqueID,
+            CommandOpCode::ReadSFDPRegister => Command::ReadSFDPRegister(needs_addr()?),
+            CommandOpCode::ReadStatusRegister2 => Command::ReadStatusRegister2,
+            CommandOpCode::ReadStatusRegister3 => Command::ReadStatusRegister3,
+            CommandOpCode::ReadFlagStatusRegister => Command::ReadFlagStatusRegister,
+            CommandOpCode::WriteStatusRegister2 => Command::WriteStatusRegister2,
+            CommandOpCode::WriteStatusRegister3 => Command::WriteStatusRegister3,
+            CommandOpCode::ReadStatusRegister2Alt => Command::ReadStatusRegister2Alt,
+            CommandOpCode::WriteStatusRegister2Alt => Command::WriteStatusRegister2Alt,
+            CommandOpCode::WriteEnableVolatile => Command::WriteEnableVolatile,
+            CommandOpCode::ReadAnyRegister => Command::ReadAnyRegister(needs_addr()?),
+            CommandOpCode::WriteAnyRegister => Command::WriteAnyRegister(needs_addr()?),
+            CommandOpCode::EnableReset => Command::EnableReset,
+            CommandOpCode::Reset => Command::Reset,
+            CommandOpCode::SoftwareReset => Command::SoftwareReset,
+            CommandOpCode::SectorErase => Command::SectorErase(needs_addr()?),
+            CommandOpCode::BlockErase1 => Command::BlockErase1(needs_addr()?),
+            CommandOpCode::BlockErase2 => Command::BlockErase2(needs_addr()?),
+            CommandOpCode::ReadData4B => Command::ReadData4B(needs_addr32()?),
+            CommandOpCode::FastRead4B => Command::FastRead4B(needs_addr32()?),
+            CommandOpCode::PageProgram4B => Command::PageProgram4B(needs_addr32()?),
+            CommandOpCode::SectorErase4B => Command::SectorErase4B(needs_addr32()?),
+            CommandOpCode::BlockErase32K4B => Command::BlockErase32K4B(needs_addr32()?),
+            CommandOpCode::BlockErase64K4B => Command::BlockErase64K4B(needs_addr32()?),
+            CommandOpCode::IndividualBlockLock => Command::IndividualBlockLock(needs_addr()?),
+            CommandOpCode::IndividualBlockUnlock => Command::IndividualBlockUnlock(needs_addr()?),
+            CommandOpCode::ReadBlockLock => Command::ReadBlockLock(needs_addr()?),
+            CommandOpCode::GlobalBlockLock => Command::GlobalBlockLock,
+            CommandOpCode::GlobalBlockUnlock => Command::GlobalBlockUnlock,
+            CommandOpCode::ReadBlockProtection => Command::ReadBlockProtection,
+            CommandOpCode::EraseSecurityRegisters => Command::EraseSecurityRegister(needs_addr()?),
+            CommandOpCode::ProgramSecurityRegisters => match addr {
+                Some(_) => Command::ProgramSecurityRegister(needs_addr()?),
+                None => Command::WriteBlockProtection,
+            },
+            CommandOpCode::ReadSecurityRegisters => Command::ReadSecurityRegister(needs_addr()?),
+            CommandOpCode::ReadDyb => Command::ReadDyb(needs_addr32()?),
+            CommandOpCode::WriteDyb => Command::WriteDyb(needs_addr32()?),
+            CommandOpCode::ReadPpb => Command::ReadPpb(needs_addr32()?),
+            CommandOpCode::ProgramPpb => Command::ProgramPpb(needs_addr32()?),
+            CommandOpCode::ErasePpb => Command::ErasePpb,
+            CommandOpCode::ReadPpbLock => Command::ReadPpbLock,
+            CommandOpCode::WritePpbLock => Command::WritePpbLock,
+            CommandOpCode::ReadAsp => Command::ReadAsp,
+            CommandOpCode::ProgramAsp => Command::ProgramAsp,
+            CommandOpCode::ReadPassword => Command::ReadPassword,
+            CommandOpCode::ProgramPassword => Command::ProgramPassword,
+            CommandOpCode::UnlockPassword => Command::UnlockPassword,
+            CommandOpCode::ReadBankRegister => Command::ReadBankRegister,
+            CommandOpCode::WriteBankRegister => Command::WriteBankRegister,
+            CommandOpCode::ReadExtendedAddressRegister => Command::ReadExtendedAddressRegister,
+            CommandOpCode::WriteExtendedAddressRegister => Command::WriteExtendedAddressRegister,
+            CommandOpCode::DieSelect => Command::DieSelect,
+            CommandOpCode::RpmcOp1 => Command::RpmcOp1,
+            CommandOpCode::RpmcOp2 => Command::RpmcOp2,
+            CommandOpCode::ReadDualOut => Command::ReadDualOut(needs_addr()?),
+            CommandOpCode::ReadQuadOut => Command::ReadQuadOut(needs_addr()?),
+            CommandOpCode::ReadDualIO => Command::ReadDualIO(needs_addr()?),
+            CommandOpCode::ReadQuadIO => Command::ReadQuadIO(needs_addr()?),
+            CommandOpCode::FastReadDtr => Command::FastReadDtr(needs_addr()?),
+            CommandOpCode::ReadDualIODtr => Command::ReadDualIODtr(needs_addr()?),
+            CommandOpCode::ReadQuadIODtr => Command::ReadQuadIODtr(needs_addr()?),
+            CommandOpCode::ModeBitReset => Command::ModeBitReset,
+            CommandOpCode::EnterQpi => Command::EnterQpi,
+            CommandOpCode::SetReadParameters => Command::SetReadParameters,
+            _ => {
+                log::error!("Opcode {:?} is not supported as a command", op_code);
+                return Err(crate::Error::UnknownOpcode {
+                    opcode: op_code.into(),
+                });
+            }
+        })
+    }
+}
+
+/// Get the 4-byte address instruction equivalent to the 3-byte address `opcode`,
+/// or `opcode` if it has no standard 4-byte address equivalent.
+///
+/// This covers the instructions listed in the SFDP 4-Byte Address Instruction Table.
+pub fn four_byte_opcode(opcode: u8) -> u8 {
+    match opcode {
+        0x03 => 0x13,
+        0x0B => 0x0C,
+        0x3B => 0x3C,
+        0xBB => 0xBC,
+        0x6B => 0x6C,
+        0xEB => 0xEC,
+        0x0D => 0x0E,
+        0xBD => 0xBE,
+        0xED => 0xEE,
+        0x02 => 0x12,
+        0x32 => 0x34,
+        0x38 => 0x3E,
+        0x20 => 0x21,
+        0x52 => 0x5C,
+        0xD8 => 0xDC,
+        opcode => opcode,
+    }
+}
+
+#[test]
+fn test_address_byte_order() {
+    let addr = Address24Bits(0x12_3456);
+    for (command, opcode) in [
+        (Command::ReadSFDPRegister(addr), 0x5A),
+        (Command::FastRead(addr), 0x0B),
+        (Command::PageProgram(addr), 0x02),
+        (Command::ReadData(addr), 0x03),
+        (Command::ReadDualOut(addr), 0x3B),
+        (Command::ReadQuadOut(addr), 0x6B),
+        (Command::ReadDualIO(addr), 0xBB),
+        (Command::ReadQuadIO(addr), 0xEB),
+        (Command::SectorErase(addr), 0x20),
+        (Command::BlockErase1(addr), 0x52),
+        (Command::BlockErase2(addr), 0xD8),
+    ] {
+        assert_eq!(&command.to_array()[..], [opcode, 0x12, 0x34, 0x56]);
     }
 }
@@ -0,0 +1,211 @@
+use either::Either;
+
+use super::spansion::Command;
+use super::{Address24Bits, Address32Bits};
+use crate::transaction::Direction;
+
+/// Description of an instruction which `Flash::execute()` can issue.
+///
+/// `Command` implements this for the instructions the driver knows, and downstream crates
+/// can implement it for vendor-specific instructions without extending `Command`.
+/// Everything but the opcode defaults to an instruction with no address, dummy cycles,
+/// or data phase.
+pub trait CommandSpec {
+    /// Opcode of the instruction.
+    fn opcode(&self) -> u8;
+
+    /// Address sent after the opcode, if any, as a 3-byte or 4-byte address.
+    fn address(&self) -> Option<Either<Address24Bits, Address32Bits>> {
+        None
+    }
+
+    /// Number of dummy cycles after the address, sent as whole dummy bytes.
+    fn dummy_cycles(&self) -> u8 {
+        0
+    }
+
+    /// Direction of the data phase.
+    fn direction(&self) -> Direction {
+        Direction::None
+    }
+
+    /// Check if Write Enable must be sent before the instruction.
+    fn needs_write_enable(&self) -> bool {
+        false
+    }
+
+    /// Check if the device is busy after the instruction, so must be polled until ready.
+    fn busy_after(&self) -> bool {
+        self.needs_write_enable()
+    }
+
+    /// Get the `Command` passed to the `FlashAccess` to issue the instruction.
+    ///
+    /// This defaults to `Command::Raw` or `Command::Raw4B`.
+    fn command(&self) -> Command {
+        match self.address() {
+            None => Command::Raw(self.opcode(), None),
+            Some(Either::Left(address)) => Command::Raw(self.opcode(), Some(address)),
+            Some(Either::Right(address)) => Command::Raw4B(self.opcode(), address),
+        }
+    }
+}
+
+/// The dummy cycles of multi-line reads depend on the read parameters of the device,
+/// so are given as 0 and must be sent with the data.
+impl CommandSpec for Command {
+    fn opcode(&self) -> u8 {
+        Command::opcode(*self)
+    }
+
+    fn address(&self) -> Option<Either<Address24Bits, Address32Bits>> {
+        self.parts().1
+    }
+
+    fn dummy_cycles(&self) -> u8 {
+        match self {
+            Command::FastRead(_)
+            | Command::FastRead4B(_)
+            | Command::ReadSFDPRegister(_)
+            | Command::ReadDualOut(_)
+            | Command::ReadQuadOut(_) => 8,
+            Command::ReadUniqueID => 32,
+            _ => 0,
+        }
+    }
+
+    fn direction(&self) -> Direction {
+        match self {
+            Command::ReadDeviceID
+            | Command::ReadDeviceIDDual
+            | Command::ReadDeviceIDQuad
+            | Command::ReadJEDECID
+            | Command::ReadStatusRegister1
+            | Command::ReadData(_)
+            | Command::FastRead(_)
+            | Command::ReadUniqueID
+            | Command::ReadSFDPRegister(_)
+            | Command::ReadStatusRegister2
+            | Command::ReadStatusRegister3
+            | Command::ReadFlagStatusRegister
+            | Command::ReadStatusRegister2Alt
+            | Command::ReadAnyRegister(_)
+            | Command::ReadData4B(_)
+            | Command::FastRead4B(_)
+            | Command::ReadBlockLock(_)
+            | Command::ReadBlockProtection
+            | Command::ReadSecurityRegister(_)
+            | Command::ReadConfigRegister1
+            | Command::ReadDyb(_)
+            | Command::ReadPpb(_)
+            | Command::ReadPpbLock
+            | Command::ReadAsp
+            | Command::ReadPassword
+            | Command::ReadBankRegister
+            | Command::ReadExtendedAddressRegister
+            | Command::RpmcOp2
+            | Command::ReadDualOut(_)
+            | Command::ReadQuadOut(_)
+            | Command::ReadDualIO(_)
+            | Command::ReadQuadIO(_)
+            | Command::FastReadDtr(_)
+            | Command::ReadDualIODtr(_)
+            | Command::ReadQuadIODtr(_) => Direction::Read,
+            Command::PageProgram(_)
+            | Command::WriteStatusRegister1
+            | Command::WriteStatusRegister2
+            | Command::WriteStatusRegister3
+            | Command::WriteStatusRegister2Alt
+            | Command::WriteAnyRegister(_)
+            | Command::PageProgram4B(_)
+            | Command::WriteBlockProtection
+            | Command::ProgramSecurityRegister(_)
+            | Command::WriteDyb(_)
+            | Command::ProgramAsp
+            | Command::ProgramPassword
+            | Command::UnlockPassword
+            | Command::WriteBankRegister
+            | Command::WriteExtendedAddressRegister
+            | Command::DieSelect
+            | Command::RpmcOp1
+            | Command::SetReadParameters => Direction::Write,
+            _ => Direction::None,
+        }
+    }
+
+    fn needs_write_enable(&self) -> bool {
+        matches!(
+            self,
+            Command::PageProgram(_)
+                | Command::WriteStatusRegister1
+                | Command::ChipErase
+                | Command::ChipEraseAlt
+                | Command::WriteStatusRegister2
+                | Command::WriteStatusRegister3
+                | Command::WriteStatusRegister2Alt
+                | Command::WriteAnyRegister(_)
+                | Command::SectorErase(_)
+                | Command::BlockErase1(_)
+                | Command::BlockErase2(_)
+                | Command::PageProgram4B(_)
+                | Command::SectorErase4B(_)
+                | Command::BlockErase32K4B(_)
+                | Command::BlockErase64K4B(_)
+                | Command::IndividualBlockLock(_)
+                | Command::IndividualBlockUnlock(_)
+                | Command::GlobalBlockLock
+                | Command::GlobalBlockUnlock
+                | Command::WriteBlockProtection
+                | Command::EraseSecurityRegister(_)
+                | Command::ProgramSecurityRegister(_)
+                | Command::WriteDyb(_)
+                | Command::ProgramPpb(_)
+                | Command::ErasePpb
+                | Command::WritePpbLock
+                | Command::ProgramAsp
+                | Command::ProgramPassword
+                | Command::WriteExtendedAddressRegister
+        )
+    }
+
+    fn command(&self) -> Command {
+        *self
+    }
+}
+
+/// An instruction given by its parts, as issued by `Flash::exec_raw()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RawCommand {
+    /// Opcode of the instruction.
+    pub opcode: u8,
+    /// Address sent after the opcode, if any.
+    pub address: Option<Either<Address24Bits, Address32Bits>>,
+    /// Number of dummy cycles after the address.
+    pub dummy_cycles: u8,
+    /// Direction of the data phase.
+    pub direction: Direction,
+    /// Whether Write Enable is sent first and the device is polled until ready afterwards.
+    pub write_enable: bool,
+}
+
+impl CommandSpec for RawCommand {
+    fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    fn address(&self) -> Option<Either<Address24Bits, Address32Bits>> {
+        self.address
+    }
+
+    fn dummy_cycles(&self) -> u8 {
+        self.dummy_cycles
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn needs_write_enable(&self) -> bool {
+        self.write_enable
+    }
+}
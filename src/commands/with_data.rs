@@ -0,0 +1,104 @@
+use alloc::vec::Vec;
+
+use super::spansion::Command;
+use super::spec::CommandSpec;
+use super::{Address24Bits, Address32Bits};
+
+/// An instruction together with the data sent in its data phase.
+///
+/// `Command` describes only the opcode and address of an instruction, leaving its data
+/// to be passed separately. `CommandWithData` instead holds a complete write
+/// transaction, borrowing any data buffer, so it can be built, inspected, or encoded
+/// as a whole, and sent with `Flash::send()`.
+#[derive(Copy, Clone, Debug)]
+pub enum CommandWithData<'a> {
+    /// Program `data` to the page containing the 3-byte address.
+    PageProgram(Address24Bits, &'a [u8]),
+    /// Program `data` to the page containing the 4-byte address.
+    PageProgram4B(Address32Bits, &'a [u8]),
+    /// Write status register 1.
+    WriteStatusRegister1(u8),
+    /// Write status registers 1 and 2 together, using 0x01.
+    WriteStatusRegisters12([u8; 2]),
+    /// Write status register 2 using 0x31.
+    WriteStatusRegister2(u8),
+    /// Write status register 3.
+    WriteStatusRegister3(u8),
+    /// Write status register 2 using 0x3E.
+    WriteStatusRegister2Alt(u8),
+    /// Write the register at the given address.
+    WriteAnyRegister(Address24Bits, u8),
+    /// Set the QPI read parameters.
+    SetReadParameters(u8),
+    /// Write the Spansion bank register.
+    WriteBankRegister(u8),
+    /// Write the Winbond Extended Address Register.
+    WriteExtendedAddressRegister(u8),
+    /// Any other command, followed by the given data.
+    Other(Command, &'a [u8]),
+}
+
+impl<'a> CommandWithData<'a> {
+    /// Get the command sent before the data.
+    pub fn command(&self) -> Command {
+        match *self {
+            CommandWithData::PageProgram(address, _) => Command::PageProgram(address),
+            CommandWithData::PageProgram4B(address, _) => Command::PageProgram4B(address),
+            CommandWithData::WriteStatusRegister1(_)
+            | CommandWithData::WriteStatusRegisters12(_) => Command::WriteStatusRegister1,
+            CommandWithData::WriteStatusRegister2(_) => Command::WriteStatusRegister2,
+            CommandWithData::WriteStatusRegister3(_) => Command::WriteStatusRegister3,
+            CommandWithData::WriteStatusRegister2Alt(_) => Command::WriteStatusRegister2Alt,
+            CommandWithData::WriteAnyRegister(address, _) => Command::WriteAnyRegister(address),
+            CommandWithData::SetReadParameters(_) => Command::SetReadParameters,
+            CommandWithData::WriteBankRegister(_) => Command::WriteBankRegister,
+            CommandWithData::WriteExtendedAddressRegister(_) => {
+                Command::WriteExtendedAddressRegister
+            }
+            CommandWithData::Other(command, _) => command,
+        }
+    }
+
+    /// Get the data sent after the command.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            CommandWithData::PageProgram(_, data)
+            | CommandWithData::PageProgram4B(_, data)
+            | CommandWithData::Other(_, data) => data,
+            CommandWithData::WriteStatusRegisters12(values) => values,
+            CommandWithData::WriteStatusRegister1(value)
+            | CommandWithData::WriteStatusRegister2(value)
+            | CommandWithData::WriteStatusRegister3(value)
+            | CommandWithData::WriteStatusRegister2Alt(value)
+            | CommandWithData::WriteAnyRegister(_, value)
+            | CommandWithData::SetReadParameters(value)
+            | CommandWithData::WriteBankRegister(value)
+            | CommandWithData::WriteExtendedAddressRegister(value) => core::slice::from_ref(value),
+        }
+    }
+
+    /// Check if Write Enable must be sent before the instruction.
+    pub fn needs_write_enable(&self) -> bool {
+        self.command().needs_write_enable()
+    }
+
+    /// Number of bytes sent on the bus for the command and its data.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.command().len() + self.data().len()
+    }
+
+    /// Encode the command and its data as the bytes to send on the bus.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = self.command().to_array().to_vec();
+        bytes.extend_from_slice(self.data());
+        bytes
+    }
+}
+
+impl From<Command> for CommandWithData<'static> {
+    /// A command with no data.
+    fn from(command: Command) -> Self {
+        CommandWithData::Other(command, &[])
+    }
+}
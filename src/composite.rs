@@ -0,0 +1,326 @@
+//! Two physical devices presented as one logical device.
+//!
+//! Boards often fit two flashes to provide more storage than a single part offers.
+//! `Concat` places the second device's memory after the first, while `Striped`
+//! interleaves the devices in fixed-size stripes, so sequential accesses alternate
+//! between them. Either implements `Storage` over the combined capacity, mapping
+//! each access to the devices and splitting those which cross between them.
+//!
+//! Logical erase units cover whole erase units of the devices: for `Concat` the larger
+//! of the two erase sizes, and for `Striped` one erase unit of each device, so twice
+//! their common erase size.
+
+use alloc::vec::Vec;
+
+use crate::{Error, ReadStorage, Result, Storage};
+
+/// Get the capacity and erase size of `storage`, which must both be known.
+fn sizes<F: Storage>(storage: &F) -> Result<(usize, usize)> {
+    match (storage.capacity(), storage.erase_size()) {
+        (Some(capacity), Some(erase_size)) => Ok((capacity, erase_size)),
+        _ => {
+            log::error!("Composite devices require known capacities and erase sizes");
+            Err(Error::InvalidConfiguration)
+        }
+    }
+}
+
+/// Check the `length` bytes starting at `address` lie within `capacity`.
+fn check_range(address: u32, length: usize, capacity: usize) -> Result<()> {
+    let end = address as usize + length;
+    if end > capacity {
+        log::error!("Operation would exceed composite device capacity");
+        return Err(Error::InvalidAddress {
+            address: end as u32,
+        });
+    }
+    Ok(())
+}
+
+/// Check the `length` bytes starting at `address` are aligned to `erase_size`.
+fn check_erase_alignment(address: u32, length: usize, erase_size: usize) -> Result<()> {
+    if !(address as usize).is_multiple_of(erase_size) || !length.is_multiple_of(erase_size) {
+        log::error!(
+            "Erase must be aligned to the {} byte erase size",
+            erase_size
+        );
+        return Err(Error::InvalidAddress { address });
+    }
+    Ok(())
+}
+
+/// The memory of `second` following that of `first`.
+pub struct Concat<F1, F2> {
+    first: F1,
+    second: F2,
+    /// Capacity of `first`, where `second` starts.
+    split: usize,
+    capacity: usize,
+    erase_size: usize,
+}
+
+impl<F1: Storage, F2: Storage> Concat<F1, F2> {
+    /// Create a device of `first` followed by `second`.
+    ///
+    /// Both capacities and erase sizes must be known, and the capacity of `first`
+    /// must be a multiple of the larger erase size, so erase units do not span devices.
+    pub fn new(first: F1, second: F2) -> Result<Self> {
+        let (split, erase1) = sizes(&first)?;
+        let (capacity2, erase2) = sizes(&second)?;
+        let erase_size = usize::max(erase1, erase2);
+        if !split.is_multiple_of(erase_size) {
+            log::error!("First device capacity is not a multiple of the erase size");
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(Concat {
+            first,
+            second,
+            split,
+            capacity: split + capacity2,
+            erase_size,
+        })
+    }
+
+    /// Release the two devices.
+    pub fn release(self) -> (F1, F2) {
+        (self.first, self.second)
+    }
+
+    /// Split the `length` bytes starting at `address` into the lengths in each device.
+    fn split(&self, address: u32, length: usize) -> (usize, usize) {
+        let first = usize::min(length, self.split.saturating_sub(address as usize));
+        (first, length - first)
+    }
+
+    /// Get the address in `second` of the part of an access starting at `address`
+    /// after `first` bytes in the first device.
+    fn second_address(&self, address: u32, first: usize) -> u32 {
+        (address as usize + first - self.split) as u32
+    }
+}
+
+impl<F1: Storage, F2: Storage> ReadStorage for Concat<F1, F2> {
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        check_range(address, length, self.capacity)?;
+        let (first, second) = self.split(address, length);
+        let mut data = Vec::with_capacity(length);
+        if first > 0 {
+            data.extend(self.first.read(address, first)?);
+        }
+        if second > 0 {
+            let address = self.second_address(address, first);
+            data.extend(self.second.read(address, second)?);
+        }
+        Ok(data)
+    }
+}
+
+impl<F1: Storage, F2: Storage> Storage for Concat<F1, F2> {
+    fn erase_size(&self) -> Option<usize> {
+        Some(self.erase_size)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        check_range(address, data.len(), self.capacity)?;
+        let (first, second) = self.split(address, data.len());
+        if first > 0 {
+            self.first.program_data(address, &data[..first])?;
+        }
+        if second > 0 {
+            let address = self.second_address(address, first);
+            self.second.program_data(address, &data[first..])?;
+        }
+        Ok(())
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        check_range(address, length, self.capacity)?;
+        check_erase_alignment(address, length, self.erase_size)?;
+        let (first, second) = self.split(address, length);
+        if first > 0 {
+            self.first.erase_data(address, first)?;
+        }
+        if second > 0 {
+            let address = self.second_address(address, first);
+            self.second.erase_data(address, second)?;
+        }
+        Ok(())
+    }
+}
+
+/// The memory of `first` and `second` interleaved in stripes, starting with `first`.
+///
+/// Logical stripe `n` is stored in `first` if `n` is even, or `second` if odd,
+/// at stripe `n / 2` of that device.
+pub struct Striped<F1, F2> {
+    first: F1,
+    second: F2,
+    stripe_size: usize,
+    /// Capacity of each device.
+    device_capacity: usize,
+    /// Erase size of each device.
+    device_erase_size: usize,
+}
+
+impl<F1: Storage, F2: Storage> Striped<F1, F2> {
+    /// Create a device interleaving `first` and `second` in stripes of `stripe_size`
+    /// bytes, such as the page size, so each program of a page goes to one device.
+    ///
+    /// The devices must have equal known capacities and erase sizes, and `stripe_size`
+    /// must be a power of two no larger than the erase size.
+    pub fn new(first: F1, second: F2, stripe_size: usize) -> Result<Self> {
+        let (capacity, erase_size) = sizes(&first)?;
+        if sizes(&second)? != (capacity, erase_size) {
+            log::error!("Striped devices must have equal capacities and erase sizes");
+            return Err(Error::InvalidConfiguration);
+        }
+        if !stripe_size.is_power_of_two() || !erase_size.is_multiple_of(stripe_size) {
+            log::error!("Stripe size {} does not divide the erase size", stripe_size);
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(Striped {
+            first,
+            second,
+            stripe_size,
+            device_capacity: capacity,
+            device_erase_size: erase_size,
+        })
+    }
+
+    /// Get the stripe size in bytes.
+    pub fn stripe_size(&self) -> usize {
+        self.stripe_size
+    }
+
+    /// Release the two devices.
+    pub fn release(self) -> (F1, F2) {
+        (self.first, self.second)
+    }
+
+    /// Iterate over the parts of the `length` bytes starting at `address` in each
+    /// stripe, as the offset into the access, the device index, the device address,
+    /// and the length.
+    fn stripes(
+        &self,
+        address: u32,
+        length: usize,
+    ) -> impl Iterator<Item = (usize, usize, u32, usize)> {
+        let stripe_size = self.stripe_size;
+        let start = address as usize;
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            if offset >= length {
+                return None;
+            }
+            let address = start + offset;
+            let stripe = address / stripe_size;
+            let within = address % stripe_size;
+            let len = usize::min(stripe_size - within, length - offset);
+            let device_address = (stripe / 2 * stripe_size + within) as u32;
+            let part = (offset, stripe % 2, device_address, len);
+            offset += len;
+            Some(part)
+        })
+    }
+}
+
+impl<F1: Storage, F2: Storage> ReadStorage for Striped<F1, F2> {
+    fn capacity(&self) -> Option<usize> {
+        Some(2 * self.device_capacity)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        check_range(address, length, 2 * self.device_capacity)?;
+        let mut data = Vec::with_capacity(length);
+        for (_, device, address, len) in self.stripes(address, length) {
+            match device {
+                0 => data.extend(self.first.read(address, len)?),
+                _ => data.extend(self.second.read(address, len)?),
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl<F1: Storage, F2: Storage> Storage for Striped<F1, F2> {
+    fn erase_size(&self) -> Option<usize> {
+        Some(2 * self.device_erase_size)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        check_range(address, data.len(), 2 * self.device_capacity)?;
+        for (offset, device, address, len) in self.stripes(address, data.len()) {
+            let data = &data[offset..offset + len];
+            match device {
+                0 => self.first.program_data(address, data)?,
+                _ => self.second.program_data(address, data)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        check_range(address, length, 2 * self.device_capacity)?;
+        check_erase_alignment(address, length, 2 * self.device_erase_size)?;
+        // Each logical erase unit holds one erase unit of each device at the same address.
+        self.first.erase_data(address / 2, length / 2)?;
+        self.second.erase_data(address / 2, length / 2)
+    }
+}
+
+#[test]
+fn test_concat() {
+    use crate::mock::MockFlash;
+
+    let mut concat = Concat::new(MockFlash::new(16 * 1024), MockFlash::new(32 * 1024)).unwrap();
+    assert_eq!(concat.capacity(), Some(48 * 1024));
+    let data: Vec<u8> = (0..32).collect();
+    concat.program_data(0x3FF0, &data).unwrap();
+    assert_eq!(concat.read(0x3FF0, 32).unwrap(), data);
+    assert!(concat.read(0xBFFF, 2).is_err());
+    assert!(concat.erase_data(0x3800, 0x1000).is_err());
+
+    let (mut first, mut second) = concat.release();
+    assert_eq!(first.read(0x3FF0, 16).unwrap(), data[..16]);
+    assert_eq!(second.read(0, 16).unwrap(), data[16..]);
+
+    // Erases spanning the devices erase the matching sectors of each.
+    let mut concat = Concat::new(first, second).unwrap();
+    concat.erase_data(0x3000, 0x2000).unwrap();
+    assert!(concat.read(0x3FF0, 32).unwrap().iter().all(|b| *b == 0xFF));
+    assert!(Concat::new(MockFlash::new(1000), MockFlash::new(4096)).is_err());
+}
+
+#[test]
+fn test_striped() {
+    use crate::mock::MockFlash;
+
+    let mock = || MockFlash::new(32 * 1024);
+    assert!(Striped::new(mock(), mock(), 3).is_err());
+    assert!(Striped::new(mock(), MockFlash::new(4096), 256).is_err());
+    let mut striped = Striped::new(mock(), mock(), 256).unwrap();
+    assert_eq!(
+        (striped.capacity(), striped.erase_size()),
+        (Some(64 * 1024), Some(8192))
+    );
+
+    let data: Vec<u8> = (0..=255).cycle().take(700).collect();
+    striped.program_data(0x80, &data).unwrap();
+    assert_eq!(striped.read(0x80, 700).unwrap(), data);
+    assert!(striped.erase_data(0x1000, 0x2000).is_err());
+
+    // Stripes alternate between the devices.
+    let (mut first, mut second) = striped.release();
+    assert_eq!(first.read(0x80, 0x80).unwrap(), data[..0x80]);
+    assert_eq!(second.read(0x00, 0x100).unwrap(), data[0x80..0x180]);
+    assert_eq!(first.read(0x100, 0x100).unwrap(), data[0x180..0x280]);
+    assert_eq!(second.read(0x100, 0x3C).unwrap(), data[0x280..]);
+
+    let mut striped = Striped::new(first, second, 256).unwrap();
+    striped.erase_data(0, 0x2000).unwrap();
+    assert!(striped.read(0x80, 700).unwrap().iter().all(|b| *b == 0xFF));
+}
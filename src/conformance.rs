@@ -0,0 +1,311 @@
+//! Conformance test suite for vendor device support.
+//!
+//! Each supported vendor or device family describes itself with a `VendorProfile`,
+//! which configures both a `MockFlash` emulating the device and a `Flash` driving it.
+//! `vendor_conformance!` then generates a set of tests checking the profile behaves
+//! consistently, so that new vendor support is held to the same standard as existing
+//! support.
+//!
+//! ```
+//! use spi_flash::conformance::{Generic, VendorProfile};
+//!
+//! spi_flash::vendor_conformance!(generic, Generic);
+//! ```
+
+use crate::mock::MockFlash;
+use crate::{
+    BoardConfig, Error, Flash, Quirks, Register, RegisterFile, SFDPBusyPolling, SFDPQuadEnable,
+    StatusWriteMode,
+};
+
+/// Description of a vendor or device family for conformance testing.
+pub trait VendorProfile {
+    /// Create a mock device emulating this vendor's behaviour.
+    fn mock() -> MockFlash;
+
+    /// Configure `flash` to drive this vendor's devices.
+    fn configure(flash: &mut Flash<MockFlash>);
+}
+
+/// Devices using the legacy status register instructions only.
+pub struct Generic;
+
+impl VendorProfile for Generic {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024)
+    }
+
+    fn configure(_flash: &mut Flash<MockFlash>) {}
+}
+
+/// Devices which return status registers 1 and 2 from one 0x05 instruction.
+pub struct CombinedStatus;
+
+impl VendorProfile for CombinedStatus {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024).with_quirks(Self::quirks())
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_quirks(Self::quirks());
+    }
+}
+
+impl CombinedStatus {
+    fn quirks() -> Quirks {
+        Quirks {
+            combined_status_read: true,
+            ..Quirks::default()
+        }
+    }
+}
+
+/// Older Winbond and GigaDevice devices, which must write status registers 1 and 2
+/// together using 0x01, with QE in bit 1 of status register 2.
+pub struct CombinedStatusWrite;
+
+impl VendorProfile for CombinedStatusWrite {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024)
+            .with_jedec_id(0xC8, 0x4018)
+            .with_quirks(Self::quirks())
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_quirks(Self::quirks());
+    }
+}
+
+impl CombinedStatusWrite {
+    fn quirks() -> Quirks {
+        Quirks {
+            combined_status_write: true,
+            quad_enable: Some(SFDPQuadEnable::Sr2Bit1ClearedBySr1Write),
+            ..Quirks::default()
+        }
+    }
+}
+
+/// Infineon/Spansion devices accessing volatile registers using RDAR/WRAR.
+pub struct SpansionAnyRegister;
+
+impl VendorProfile for SpansionAnyRegister {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024)
+            .with_jedec_id(0x01, 0x0219)
+            .with_register_file(RegisterFile::spansion_volatile())
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_register_file(Some(RegisterFile::spansion_volatile()));
+    }
+}
+
+/// Winbond devices, with QE in bit 1 of status register 2 written using 0x31.
+pub struct Winbond;
+
+impl VendorProfile for Winbond {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024).with_jedec_id(0xEF, 0x4018)
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_quirks(Quirks {
+            quad_enable: Some(SFDPQuadEnable::Sr2Bit1Write31),
+            ..Quirks::default()
+        });
+    }
+}
+
+/// Macronix devices, with QE in bit 6 of status register 1.
+pub struct Macronix;
+
+impl VendorProfile for Macronix {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024).with_jedec_id(0xC2, 0x2018)
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_quirks(Quirks {
+            quad_enable: Some(SFDPQuadEnable::Sr1Bit6),
+            ..Quirks::default()
+        });
+    }
+}
+
+/// Micron devices, polled for busy status using the flag status register.
+pub struct Micron;
+
+impl VendorProfile for Micron {
+    fn mock() -> MockFlash {
+        MockFlash::new(64 * 1024).with_jedec_id(0x20, 0xBA18)
+    }
+
+    fn configure(flash: &mut Flash<MockFlash>) {
+        flash.set_quirks(Quirks {
+            busy_polling: Some(SFDPBusyPolling::FlagStatusRegister),
+            ..Quirks::default()
+        });
+    }
+}
+
+/// Create a mock device and `Flash` for profile `P`, and run `f` with them.
+fn with_flash<P: VendorProfile, F: FnOnce(&mut Flash<MockFlash>)>(f: F) -> MockFlash {
+    let mut mock = P::mock();
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    P::configure(&mut flash);
+    f(&mut flash);
+    mock
+}
+
+/// Check the JEDEC ID reported by the mock device is read back.
+pub fn check_identify<P: VendorProfile>() {
+    let expected = P::mock().jedec_id();
+    with_flash::<P, _>(|flash| {
+        let id = flash.read_id().unwrap();
+        assert_eq!(id.manufacturer_id, expected[0]);
+        assert_eq!(
+            id.device_id_long,
+            u16::from_be_bytes([expected[1], expected[2]])
+        );
+    });
+}
+
+/// Check status register writes are encoded so they read back unchanged.
+pub fn check_status_registers<P: VendorProfile>() {
+    let mock = with_flash::<P, _>(|flash| {
+        flash
+            .write_register(Register::Status2, 0x42, StatusWriteMode::NonVolatile)
+            .unwrap();
+        assert_eq!(flash.read_register(Register::Status2).unwrap(), 0x42);
+        let (_, status2) = flash.read_status12().unwrap();
+        assert_eq!(status2.0, 0x42);
+    });
+    assert_eq!(mock.register(Register::Status2), 0x42);
+}
+
+/// Check block protection bits are encoded in status register 1 and can be cleared.
+pub fn check_protection<P: VendorProfile>() {
+    let mock = with_flash::<P, _>(|flash| {
+        assert!(!flash.is_protected().unwrap());
+        flash.protect(true, false, true).unwrap();
+        assert!(flash.is_protected().unwrap());
+        assert_eq!(
+            flash.read_status1().unwrap().get_block_protect(),
+            (true, false, true)
+        );
+        flash.unprotect().unwrap();
+        assert!(!flash.is_protected().unwrap());
+    });
+    assert_eq!(mock.register(Register::Status1) & 0b0001_1100, 0);
+}
+
+/// Check data can be erased, programmed, and read back.
+pub fn check_program_erase<P: VendorProfile>() {
+    let data: alloc::vec::Vec<u8> = (0..600).map(|x| x as u8).collect();
+    let mock = with_flash::<P, _>(|flash| {
+        flash.program_data(0x1080, &data).unwrap();
+        assert_eq!(flash.read(0x1080, data.len()).unwrap(), data);
+        assert!(!flash.is_erased(0x1000, 4096).unwrap());
+        flash.erase_sector(0x1000).unwrap();
+        assert!(flash.is_erased(0x1000, 4096).unwrap());
+    });
+    assert!(mock.memory().iter().all(|x| *x == 0xFF));
+}
+
+/// Check data can be read, programmed, and erased using 4-byte address instructions.
+pub fn check_four_byte_addressing<P: VendorProfile>() {
+    let data: alloc::vec::Vec<u8> = (0..300).map(|x| x as u8).collect();
+    let mock = with_flash::<P, _>(|flash| {
+        flash.set_address_bytes(4);
+        flash.program_data(0x2010, &data).unwrap();
+        assert_eq!(flash.read(0x2010, data.len()).unwrap(), data);
+        assert_eq!(flash.legacy_read(0x2010, data.len()).unwrap(), data);
+        let transaction = flash.program_transaction(0x2000, 256).unwrap();
+        assert_eq!((transaction.opcode, transaction.address_bytes), (0x12, 4));
+        flash.erase_sector(0x2000).unwrap();
+        assert!(flash.is_erased(0x2000, 4096).unwrap());
+    });
+    assert!(mock.memory().iter().all(|x| *x == 0xFF));
+}
+
+/// Check the QE bit is only set when the board has quad I/O wired,
+/// and is set and cleared without disturbing block protection.
+pub fn check_quad_enable<P: VendorProfile>() {
+    with_flash::<P, _>(|flash| {
+        assert!(matches!(
+            flash.set_quad_enable(true),
+            Err(Error::BoardUnsupported)
+        ));
+        if flash.quirks().quad_enable.is_none() {
+            return;
+        }
+        flash.set_board(BoardConfig {
+            quad_io: true,
+            ..flash.board()
+        });
+        flash.protect(true, false, false).unwrap();
+        flash.set_quad_enable(true).unwrap();
+        assert!(flash.is_quad_enabled().unwrap());
+        assert_eq!(
+            flash.read_status1().unwrap().get_block_protect(),
+            (true, false, false)
+        );
+        flash.protect(false, true, false).unwrap();
+        assert!(flash.is_quad_enabled().unwrap());
+        flash.set_quad_enable(false).unwrap();
+        assert!(!flash.is_quad_enabled().unwrap());
+    });
+}
+
+/// Generate the conformance tests for a `VendorProfile`.
+///
+/// `vendor_conformance!(name, Profile)` creates a module `name` containing a test
+/// for each conformance check, run against `Profile`.
+#[macro_export]
+macro_rules! vendor_conformance {
+    ($name:ident, $profile:ty) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[test]
+            fn identify() {
+                $crate::conformance::check_identify::<$profile>();
+            }
+
+            #[test]
+            fn status_registers() {
+                $crate::conformance::check_status_registers::<$profile>();
+            }
+
+            #[test]
+            fn protection() {
+                $crate::conformance::check_protection::<$profile>();
+            }
+
+            #[test]
+            fn program_erase() {
+                $crate::conformance::check_program_erase::<$profile>();
+            }
+
+            #[test]
+            fn four_byte_addressing() {
+                $crate::conformance::check_four_byte_addressing::<$profile>();
+            }
+
+            #[test]
+            fn quad_enable() {
+                $crate::conformance::check_quad_enable::<$profile>();
+            }
+        }
+    };
+}
+
+vendor_conformance!(generic, Generic);
+vendor_conformance!(combined_status, CombinedStatus);
+vendor_conformance!(combined_status_write, CombinedStatusWrite);
+vendor_conformance!(spansion_any_register, SpansionAnyRegister);
+vendor_conformance!(winbond, Winbond);
+vendor_conformance!(macronix, Macronix);
+vendor_conformance!(micron, Micron);
@@ -0,0 +1,142 @@
+//! CRC-32 computation.
+//!
+//! This is the common CRC-32 (IEEE 802.3, as used by zlib and Ethernet), used to
+//! validate records and images stored in flash memory.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::{ReadStorage, Result};
+
+/// Lookup table for the reflected polynomial 0xEDB88320, generated at compile time.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Incremental CRC-32 computation.
+///
+/// Call `update()` with each chunk of data in turn, then `finish()` to obtain the CRC.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Start a new CRC computation.
+    pub const fn new() -> Self {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    /// Update the CRC with `data`.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 = TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    /// Get the CRC of all data processed so far.
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compute the CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Size of chunks read by `storage_crc32()`.
+const STORAGE_CHUNK: usize = 4096;
+
+/// Compute the CRC-32 of `length` bytes of `storage` starting at `address`.
+pub(crate) fn storage_crc32<S: ReadStorage + ?Sized>(
+    storage: &mut S,
+    address: u32,
+    length: usize,
+) -> Result<u32> {
+    let mut crc = Crc32::new();
+    for offset in (0..length).step_by(STORAGE_CHUNK) {
+        let size = usize::min(STORAGE_CHUNK, length - offset);
+        crc.update(&storage.read(address + offset as u32, size)?);
+    }
+    Ok(crc.finish())
+}
+
+/// Future which is pending once, so the executor can run other tasks.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Yield to the executor once.
+pub(crate) fn yield_now() -> impl Future<Output = ()> {
+    YieldNow(false)
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0x0000_0000);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xCBF4_3926);
+}
+
+#[test]
+fn test_flash_crc32() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    mock.memory_mut()[0x1FFC..0x2005].copy_from_slice(b"123456789");
+    assert_eq!(storage_crc32(&mut mock, 0x1FFC, 9).unwrap(), 0xCBF4_3926);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert_eq!(flash.crc32(0x1FFC, 9).unwrap(), 0xCBF4_3926);
+    let image = flash.read(0, 64 * 1024).unwrap();
+    assert_eq!(flash.crc32(0, 64 * 1024).unwrap(), crc32(&image));
+    assert!(flash.crc32(0xFFFF, 2).is_err());
+
+    // The async version yields between chunks, and gives the same result.
+    let mut future = core::pin::pin!(flash.crc32_async(0, 64 * 1024));
+    let mut cx = Context::from_waker(core::task::Waker::noop());
+    let mut yields = 0;
+    let crc = loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(crc) => break crc.unwrap(),
+            Poll::Pending => yields += 1,
+        }
+    };
+    assert_eq!(crc, crc32(&image));
+    assert!(yields > 1);
+}
@@ -0,0 +1,416 @@
+//! AT45 DataFlash devices.
+//!
+//! Adesto (now Renesas) AT45DB DataFlash devices are not programmed directly: data is
+//! written into an SRAM buffer the size of a page, which is then programmed into a page of
+//! main memory, erasing the page first. Pages are 264, 528, or 1056 bytes in the default
+//! DataFlash configuration, with the page number and the byte within the page sent as
+//! separate fields of the address, or 256, 512, or 1024 bytes once configured for binary
+//! page sizes, with linear addresses as for other flash.
+//!
+//! `DataFlash` hides both layouts behind `ReadStorage` and `Storage`, addressing memory
+//! as a contiguous range of pages of the configured size. Writes need no erase, as each
+//! page written is read into the buffer, updated, and programmed back with a built-in erase.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::commands::dataflash::DataFlashOpCode;
+use crate::wait::poll_until;
+use crate::{Command, Error, FlashAccess, ReadStorage, Result, Storage};
+
+/// JEDEC manufacturer ID of Atmel, now Adesto and Renesas.
+pub(crate) const MANUFACTURER_ID: u8 = 0x1F;
+/// Family code of DataFlash devices, in bits 7 to 5 of the first device ID byte.
+const FAMILY_DATAFLASH: u8 = 0b001;
+
+/// First status byte bit set when the device is ready.
+pub(crate) const STATUS_READY: u8 = 0b1000_0000;
+/// First status byte bit set when the device is configured for binary page sizes.
+pub(crate) const STATUS_BINARY_PAGE_SIZE: u8 = 0b0000_0001;
+/// Second status byte bit set when the last program or erase failed.
+pub(crate) const STATUS_EPE: u8 = 0b0010_0000;
+
+/// Organisation of an AT45 DataFlash device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataFlashGeometry {
+    /// Number of pages.
+    pub pages: u32,
+    /// Size of each page in bytes when configured for binary page sizes.
+    pub binary_page_size: usize,
+}
+
+impl DataFlashGeometry {
+    /// Get the geometry of the device with the density code given by bits 4 to 0
+    /// of its first device ID byte, from 0x02 for 1Mbit to 0x08 for 64Mbit.
+    pub fn from_density(density: u8) -> Option<Self> {
+        let (pages, binary_page_size) = match density {
+            0x02..=0x05 => (512 << (density - 0x02), 256),
+            0x06 => (4096, 512),
+            0x07 => (8192, 512),
+            0x08 => (8192, 1024),
+            _ => return None,
+        };
+        Some(DataFlashGeometry {
+            pages,
+            binary_page_size,
+        })
+    }
+
+    /// Size of each page in bytes in the default DataFlash configuration.
+    pub fn dataflash_page_size(&self) -> usize {
+        self.binary_page_size + self.binary_page_size / 32
+    }
+
+    /// Size of each page in bytes, for binary or DataFlash page sizes.
+    pub fn page_size(&self, binary: bool) -> usize {
+        if binary {
+            self.binary_page_size
+        } else {
+            self.dataflash_page_size()
+        }
+    }
+
+    /// Capacity in bytes, for binary or DataFlash page sizes.
+    pub fn capacity(&self, binary: bool) -> usize {
+        self.page_size(binary) * self.pages as usize
+    }
+}
+
+/// Driver for an AT45 DataFlash device.
+pub struct DataFlash<'a, A: FlashAccess> {
+    access: &'a mut A,
+    geometry: DataFlashGeometry,
+    binary: bool,
+    timeout: Option<Duration>,
+}
+
+impl<'a, A: FlashAccess> DataFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a driver for a device with the given `geometry`, configured for binary
+    /// page sizes if `binary` is set.
+    pub fn new(access: &'a mut A, geometry: DataFlashGeometry, binary: bool) -> Self {
+        DataFlash {
+            access,
+            geometry,
+            binary,
+            timeout: None,
+        }
+    }
+
+    /// Set the time after which an operation still in progress fails with
+    /// `Error::Timeout`, or `None`, the default, to wait indefinitely.
+    ///
+    /// As for `Flash::set_timeouts()`, elapsed time is counted from the delays requested
+    /// from `FlashAccess::delay()`, so a timeout must only be set if the transport
+    /// implements delays. A chip erase may take several minutes on larger devices.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Create a driver for the connected device, finding its geometry from its ID and its
+    /// page size configuration from its status register.
+    pub fn detect(access: &'a mut A) -> Result<Self> {
+        // The geometry is only used for addressing, so is not needed until found from the ID.
+        let geometry = DataFlashGeometry {
+            pages: 0,
+            binary_page_size: 256,
+        };
+        let mut flash = Self::new(access, geometry, false);
+        let (manufacturer_id, device_id) = flash.read_id()?;
+        if manufacturer_id != MANUFACTURER_ID {
+            log::error!("Manufacturer 0x{:02X} is not Adesto", manufacturer_id);
+            return Err(Error::InvalidManufacturer);
+        }
+        let [family, _] = device_id.to_be_bytes();
+        let geometry = match family >> 5 {
+            FAMILY_DATAFLASH => DataFlashGeometry::from_density(family & 0x1F),
+            _ => None,
+        };
+        let Some(geometry) = geometry else {
+            log::error!("Device 0x{:04X} is not a known DataFlash", device_id);
+            return Err(Error::UnknownDevice {
+                manufacturer_id,
+                device_id,
+            });
+        };
+        flash.geometry = geometry;
+        flash.binary = flash.read_status()?[0] & STATUS_BINARY_PAGE_SIZE != 0;
+        log::debug!(
+            "Found DataFlash of {} pages of {} bytes",
+            geometry.pages,
+            flash.page_size()
+        );
+        Ok(flash)
+    }
+
+    /// Get the device geometry.
+    pub fn geometry(&self) -> DataFlashGeometry {
+        self.geometry
+    }
+
+    /// Check if the device is configured for binary page sizes.
+    pub fn is_binary_page_size(&self) -> bool {
+        self.binary
+    }
+
+    /// Get the page size in bytes of the current configuration.
+    pub fn page_size(&self) -> usize {
+        self.geometry.page_size(self.binary)
+    }
+
+    /// Get the capacity in bytes of the current configuration.
+    pub fn capacity(&self) -> usize {
+        self.geometry.capacity(self.binary)
+    }
+
+    /// Read the manufacturer ID and the two device ID bytes.
+    pub fn read_id(&mut self) -> Result<(u8, u16)> {
+        let id = self.exchange(DataFlashOpCode::ReadID, &[], 3)?;
+        Ok((id[0], u16::from_be_bytes([id[1], id[2]])))
+    }
+
+    /// Read the two status register bytes.
+    pub fn read_status(&mut self) -> Result<[u8; 2]> {
+        let status = self.exchange(DataFlashOpCode::StatusRead, &[], 2)?;
+        Ok([status[0], status[1]])
+    }
+
+    /// Poll the status register until the device is ready, returning its final value.
+    ///
+    /// Returns `Error::Timeout` if the device is still busy after the timeout, if set.
+    pub fn wait_ready(&mut self) -> Result<[u8; 2]> {
+        poll_until(
+            self,
+            self.timeout,
+            |flash, interval| flash.access.delay(interval),
+            |flash| {
+                let status = flash.read_status()?;
+                Ok((status[0] & STATUS_READY != 0).then_some(status))
+            },
+        )
+    }
+
+    /// Configure the device for binary page sizes if `binary` is set, or for DataFlash
+    /// page sizes otherwise.
+    ///
+    /// The configuration is stored in non-volatile memory, and on D-series devices can only
+    /// be set to binary page sizes once, after which it cannot be changed back. Some
+    /// devices only use the new page size after a power cycle, so the configuration in use
+    /// is read back from the status register.
+    pub fn set_binary_page_size(&mut self, binary: bool) -> Result<()> {
+        let mode = if binary { 0xA6 } else { 0xA7 };
+        log::debug!("Configuring DataFlash for binary page size: {}", binary);
+        self.send(DataFlashOpCode::ConfigurePageSize, &[0x2A, 0x80, mode])?;
+        self.binary = self.wait_ready()?[0] & STATUS_BINARY_PAGE_SIZE != 0;
+        Ok(())
+    }
+
+    /// Read `length` bytes starting at `address`.
+    pub fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.check_range(address, length)?;
+        let page_size = self.page_size() as u32;
+        let mut tx = self.page_address(address / page_size, address % page_size);
+        tx.push(0);
+        self.exchange(DataFlashOpCode::ContinuousRead, &tx, length)
+    }
+
+    /// Write `data` starting at `address`, replacing the existing contents.
+    ///
+    /// Each page written is read into buffer 1 unless it is written in full,
+    /// updated in the buffer, and then programmed from the buffer with a built-in erase.
+    /// Returns `ProgramFailed` if the device reports a failure.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len())?;
+        let page_size = self.page_size();
+        let mut offset = 0;
+        while offset < data.len() {
+            let start = address as usize + offset;
+            let (page, column) = ((start / page_size) as u32, start % page_size);
+            let chunk = usize::min(page_size - column, data.len() - offset);
+            log::trace!("Writing {} bytes to DataFlash page {}", chunk, page);
+            let page_address = self.page_address(page, 0);
+            if chunk < page_size {
+                self.send(DataFlashOpCode::PageToBuffer1, &page_address)?;
+                self.wait_ready()?;
+            }
+            let mut tx = self.page_address(0, column as u32);
+            tx.extend(&data[offset..offset + chunk]);
+            self.send(DataFlashOpCode::Buffer1Write, &tx)?;
+            self.send(DataFlashOpCode::Buffer1ToMainWithErase, &page_address)?;
+            if self.wait_ready()?[1] & STATUS_EPE != 0 {
+                log::error!("Programming DataFlash page {} failed", page);
+                return Err(Error::ProgramFailed {
+                    address: start as u32,
+                });
+            }
+            offset += chunk;
+        }
+        Ok(())
+    }
+
+    /// Erase `page`.
+    ///
+    /// Returns `EraseFailed` if the device reports a failure.
+    pub fn erase_page(&mut self, page: u32) -> Result<()> {
+        if page >= self.geometry.pages {
+            log::error!("DataFlash page {} out of range", page);
+            return Err(Error::InvalidAddress { address: page });
+        }
+        log::trace!("Erasing DataFlash page {}", page);
+        let page_address = self.page_address(page, 0);
+        self.send(DataFlashOpCode::PageErase, &page_address)?;
+        self.check_erase(page * self.page_size() as u32)
+    }
+
+    /// Erase the whole device.
+    pub fn erase_chip(&mut self) -> Result<()> {
+        log::debug!("Erasing DataFlash");
+        self.send(DataFlashOpCode::ChipErase, &[0x94, 0x80, 0x9A])?;
+        self.check_erase(0)
+    }
+
+    fn check_erase(&mut self, address: u32) -> Result<()> {
+        if self.wait_ready()?[1] & STATUS_EPE != 0 {
+            log::error!("Erasing DataFlash at 0x{:06X} failed", address);
+            return Err(Error::EraseFailed { address });
+        }
+        Ok(())
+    }
+
+    /// Get the address bytes of the byte at `column` of `page`.
+    ///
+    /// With DataFlash page sizes, the page number is sent above enough bits to hold any
+    /// column, so 264-byte pages use 9 bits for the column.
+    fn page_address(&self, page: u32, column: u32) -> Vec<u8> {
+        let page_size = self.page_size();
+        let address = if self.binary {
+            page * page_size as u32 + column
+        } else {
+            let column_bits = usize::BITS - (page_size - 1).leading_zeros();
+            (page << column_bits) | column
+        };
+        address.to_be_bytes()[1..].to_vec()
+    }
+
+    fn check_range(&self, address: u32, length: usize) -> Result<()> {
+        if address as usize + length > self.capacity() {
+            log::error!("Operation would exceed DataFlash capacity");
+            return Err(Error::InvalidAddress {
+                address: (address as usize + length) as u32,
+            });
+        }
+        Ok(())
+    }
+
+    fn exchange(&mut self, opcode: DataFlashOpCode, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let command = Command::DataFlash(opcode);
+        let rx = self.access.exchange(command, data, nbytes)?;
+        Ok(rx[command.len() + data.len()..].to_vec())
+    }
+
+    fn send(&mut self, opcode: DataFlashOpCode, data: &[u8]) -> Result<()> {
+        self.access.write(Command::DataFlash(opcode), data)?;
+        Ok(())
+    }
+}
+
+impl<'a, A: FlashAccess> ReadStorage for DataFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn capacity(&self) -> Option<usize> {
+        Some(DataFlash::capacity(self))
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        DataFlash::read(self, address, length)
+    }
+}
+
+/// Pages are erased as part of each write, so programming overwrites any data, and
+/// erasing is only needed to return pages to 0xFF.
+impl<'a, A: FlashAccess> Storage for DataFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn erase_size(&self) -> Option<usize> {
+        Some(self.page_size())
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.write(address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        let page_size = self.page_size();
+        if !(address as usize).is_multiple_of(page_size) || !length.is_multiple_of(page_size) {
+            log::error!(
+                "DataFlash erase must be aligned to {} byte pages",
+                page_size
+            );
+            return Err(Error::InvalidAddress { address });
+        }
+        self.check_range(address, length)?;
+        let first = address / page_size as u32;
+        for page in first..first + (length / page_size) as u32 {
+            self.erase_page(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dataflash() {
+    // A 1Mbit AT45DB011.
+    let mut device = crate::mock::MockDataFlash::new(0x02)
+        .with_busy_polls(2)
+        .with_failing_page(500);
+    device.page_mut(1)[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let mut flash = DataFlash::detect(&mut device).unwrap();
+    assert_eq!(
+        flash.geometry(),
+        DataFlashGeometry {
+            pages: 512,
+            binary_page_size: 256
+        }
+    );
+    assert!(!flash.is_binary_page_size());
+    assert_eq!((flash.page_size(), flash.capacity()), (264, 135168));
+
+    // Writes spanning pages keep the rest of each page.
+    flash.write(260, &[0xA5; 8]).unwrap();
+    assert_eq!(
+        flash.read(258, 12).unwrap(),
+        [0xFF, 0xFF, 0xA5, 0xA5, 0xA5, 0xA5, 0xA5, 0xA5, 0xA5, 0xA5, 5, 6]
+    );
+    assert!(flash.read(135168, 1).is_err());
+    assert!(matches!(
+        flash.write(500 * 264 + 10, &[0]),
+        Err(Error::ProgramFailed { address: 132010 })
+    ));
+
+    // Pages are erased in units of the page size.
+    assert!(flash.erase_data(256, 264).is_err());
+    flash.erase_data(264, 264).unwrap();
+    assert_eq!(flash.read(262, 4).unwrap(), [0xA5, 0xA5, 0xFF, 0xFF]);
+
+    // Binary page sizes use linear addresses.
+    flash.set_binary_page_size(true).unwrap();
+    assert_eq!(flash.erase_size(), Some(256));
+    flash.write(0x1FF, &[0x11, 0x22]).unwrap();
+    assert_eq!(device.page(1)[255..257], [0x11, 0xFF]);
+    assert_eq!(device.page(2)[..2], [0x22, 0xFF]);
+
+    let mut flash = DataFlash::detect(&mut device).unwrap();
+    assert_eq!(flash.capacity(), 131072);
+    flash.erase_chip().unwrap();
+    assert_eq!(flash.read(0x1FF, 2).unwrap(), [0xFF, 0xFF]);
+
+    // A device which stays busy fails once the timeout has passed.
+    assert_eq!(flash.access.busy_violations(), 0);
+    flash.access.set_stuck_busy(true);
+    flash.set_timeout(Some(Duration::from_micros(32)));
+    assert!(matches!(flash.wait_ready(), Err(Error::Timeout)));
+}
@@ -0,0 +1,319 @@
+//! Decoder for captured flash bus traffic.
+//!
+//! A capture of one command, from CS assertion to deassertion, is given as a sequence
+//! of samples of the data lines, where bit n of each sample is the level of IOn (so MOSI
+//! is bit 0 and MISO is bit 1 in standard SPI). There is one sample per clock, except in
+//! the address, mode, dummy, and data phases of DTR instructions, which are sampled on
+//! both clock edges.
+//!
+//! Phases transferred on several lines are reassembled from their 2- or 4-bit symbols,
+//! with the highest-numbered line carrying the most significant bit, so captures of
+//! dual and quad I/O reads can be decoded as well as single-line SPI.
+
+use alloc::vec::Vec;
+
+use crate::commands::spansion::CommandOpCode;
+use crate::transaction::{Direction, Lines, ReadMode, ReadParameters};
+use crate::{Error, Result};
+
+/// All read instructions with a `ReadMode`.
+const READ_MODES: [ReadMode; 8] = [
+    ReadMode::Fast,
+    ReadMode::DualOutput,
+    ReadMode::DualIO,
+    ReadMode::QuadOutput,
+    ReadMode::QuadIO,
+    ReadMode::FastDtr,
+    ReadMode::DualIODtr,
+    ReadMode::QuadIODtr,
+];
+
+/// Reassemble bytes from symbols sampled on `lines` data lines.
+///
+/// Each symbol holds one bit per line in bits 0 to `lines - 1`, and symbols are most
+/// significant first, so a byte is made of `8 / lines` symbols. Any trailing partial
+/// byte is discarded.
+///
+/// Returns `InvalidLines` unless `lines` is 1, 2, 4, or 8.
+pub fn pack_symbols(symbols: &[u8], lines: u8) -> Result<Vec<u8>> {
+    pack_lane(symbols, lines, 0)
+}
+
+/// Reassemble bytes from `lines` lines starting at IO`first`.
+fn pack_lane(symbols: &[u8], lines: u8, first: u8) -> Result<Vec<u8>> {
+    match lines {
+        // Each octal symbol is already a whole byte.
+        8 => Ok(symbols.to_vec()),
+        1 | 2 | 4 => {
+            let mask = (1u8 << lines) - 1;
+            Ok(symbols
+                .chunks_exact(8 / lines as usize)
+                .map(|chunk| {
+                    chunk
+                        .iter()
+                        .fold(0, |byte, s| (byte << lines) | ((s >> first) & mask))
+                })
+                .collect())
+        }
+        _ => {
+            log::error!("Cannot pack symbols on {} lines", lines);
+            Err(Error::InvalidLines { lines })
+        }
+    }
+}
+
+/// A decoded flash command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedCommand {
+    /// Instruction opcode.
+    pub opcode: u8,
+    /// Address, if the instruction takes one.
+    pub address: Option<u32>,
+    /// Mode bits sent after the address, if any.
+    pub mode_bits: Option<u8>,
+    /// Direction of the data phase.
+    pub direction: Direction,
+    /// Data transferred after the address, mode bits, and dummy cycles.
+    pub data: Vec<u8>,
+}
+
+/// Decoder for captured flash commands.
+///
+/// The number of address bytes and the dummy cycles of each read instruction cannot be
+/// inferred from the capture, so must match the configuration used by the driver;
+/// by default 3-byte addresses and the typical read parameters are assumed.
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    address_bytes: u8,
+    read_parameters: Vec<ReadParameters>,
+}
+
+/// Layout of the phases of one instruction.
+struct Layout {
+    lines: Lines,
+    dtr: bool,
+    address: bool,
+    mode_bits: bool,
+    dummy_cycles: u8,
+    direction: Direction,
+}
+
+/// Position in a capture being decoded.
+struct Cursor<'s> {
+    samples: &'s [u8],
+    position: usize,
+}
+
+impl Cursor<'_> {
+    /// Take `nbytes` bytes transferred on `lines` lines starting at IO`first`.
+    fn bytes(&mut self, nbytes: usize, lines: u8, first: u8) -> Result<Vec<u8>> {
+        // An invalid number of lines is rejected by `pack_lane()`.
+        let n = (nbytes * 8).checked_div(lines as usize).unwrap_or(0);
+        let end = self.position + n;
+        if end > self.samples.len() {
+            return Err(Error::BufferTooSmall {
+                length: self.samples.len(),
+                required: end,
+            });
+        }
+        let bytes = pack_lane(&self.samples[self.position..end], lines, first)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Take all remaining whole bytes transferred on `lines` lines starting at IO`first`.
+    fn rest(&mut self, lines: u8, first: u8) -> Result<Vec<u8>> {
+        let start = self.position.min(self.samples.len());
+        self.position = self.samples.len();
+        pack_lane(&self.samples[start..], lines, first)
+    }
+}
+
+impl Decoder {
+    /// Create a decoder for 3-byte addresses and typical read parameters.
+    pub fn new() -> Self {
+        Decoder {
+            address_bytes: 3,
+            read_parameters: Vec::new(),
+        }
+    }
+
+    /// Set the number of address bytes sent by instructions which take an address.
+    pub fn with_address_bytes(mut self, address_bytes: u8) -> Self {
+        self.address_bytes = address_bytes;
+        self
+    }
+
+    /// Use `params` to decode reads with its opcode, instead of the typical parameters.
+    pub fn with_read_parameters(mut self, params: ReadParameters) -> Self {
+        self.read_parameters.retain(|p| p.opcode != params.opcode);
+        self.read_parameters.push(params);
+        self
+    }
+
+    /// Decode one command from its captured `samples`.
+    pub fn decode(&self, samples: &[u8]) -> Result<DecodedCommand> {
+        let mut cursor = Cursor {
+            samples,
+            position: 0,
+        };
+        let opcode = cursor.bytes(1, 1, 0)?[0];
+        let layout = self.layout(opcode);
+        let address = if layout.address {
+            let bytes = cursor.bytes(self.address_bytes as usize, layout.lines.address, 0)?;
+            Some(bytes.iter().fold(0, |a, b| (a << 8) | *b as u32))
+        } else {
+            None
+        };
+        let mode_bits = if layout.mode_bits {
+            Some(cursor.bytes(1, layout.lines.address, 0)?[0])
+        } else {
+            None
+        };
+        let edges = if layout.dtr { 2 } else { 1 };
+        cursor.position += layout.dummy_cycles as usize * edges;
+        // On a single line, data from the flash is driven on MISO (IO1).
+        let data_lane = (layout.lines.data == 1 && layout.direction == Direction::Read) as u8;
+        let data = cursor.rest(layout.lines.data, data_lane)?;
+        Ok(DecodedCommand {
+            opcode,
+            address,
+            mode_bits,
+            direction: layout.direction,
+            data,
+        })
+    }
+
+    /// Find the layout of the instruction `opcode`.
+    fn layout(&self, opcode: u8) -> Layout {
+        let params = self
+            .read_parameters
+            .iter()
+            .copied()
+            .find(|p| p.opcode == opcode)
+            .or_else(|| {
+                READ_MODES
+                    .into_iter()
+                    .find(|m| m.opcode() == opcode)
+                    .map(ReadParameters::typical)
+            });
+        if let Some(params) = params {
+            return Layout {
+                lines: params.mode.lines(),
+                dtr: params.mode.is_dtr(),
+                address: true,
+                mode_bits: params.mode_bits.is_some(),
+                dummy_cycles: params.dummy_cycles,
+                direction: Direction::Read,
+            };
+        }
+        // The I/O ID reads send their address and mode bits, and receive their IDs,
+        // on two or four lines.
+        let id_lines = match CommandOpCode::try_from(opcode) {
+            Ok(CommandOpCode::ReadDeviceIDDual) => Some((Lines::new(1, 2, 2), 0)),
+            Ok(CommandOpCode::ReadDeviceIDQuad) => Some((Lines::new(1, 4, 4), 4)),
+            _ => None,
+        };
+        if let Some((lines, dummy_cycles)) = id_lines {
+            return Layout {
+                lines,
+                dtr: false,
+                address: true,
+                mode_bits: true,
+                dummy_cycles,
+                direction: Direction::Read,
+            };
+        }
+        let (address, dummy_cycles, direction) = match CommandOpCode::try_from(opcode) {
+            Ok(CommandOpCode::ReadData) => (true, 0, Direction::Read),
+            Ok(CommandOpCode::ReadSFDPRegister | CommandOpCode::ReadAnyRegister) => {
+                (true, 8, Direction::Read)
+            }
+            Ok(CommandOpCode::ReadUniqueID) => (false, 32, Direction::Read),
+            Ok(CommandOpCode::ReleasePowerdown) => (false, 24, Direction::Read),
+            Ok(CommandOpCode::ReadDeviceID | CommandOpCode::ReadBlockLock) => {
+                (true, 0, Direction::Read)
+            }
+            Ok(
+                CommandOpCode::ReadStatusRegister1
+                | CommandOpCode::ReadStatusRegister2
+                | CommandOpCode::ReadStatusRegister3
+                | CommandOpCode::ReadStatusRegister2Alt
+                | CommandOpCode::ReadFlagStatusRegister
+                | CommandOpCode::ReadJEDECID,
+            ) => (false, 0, Direction::Read),
+            Ok(
+                CommandOpCode::PageProgram
+                | CommandOpCode::SectorErase
+                | CommandOpCode::BlockErase1
+                | CommandOpCode::BlockErase2
+                | CommandOpCode::WriteAnyRegister
+                | CommandOpCode::IndividualBlockLock
+                | CommandOpCode::IndividualBlockUnlock,
+            ) => (true, 0, Direction::Write),
+            _ => (false, 0, Direction::Write),
+        };
+        Layout {
+            lines: Lines::SINGLE,
+            dtr: false,
+            address,
+            mode_bits: false,
+            dummy_cycles,
+            direction,
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_decode_quad_io() {
+    // Serialise `bytes` as symbols on `lines` lines starting at IO`first`.
+    fn symbols(bytes: &[u8], lines: u8, first: u8) -> Vec<u8> {
+        let per_byte = 8 / lines;
+        let mask = (1u8 << lines) - 1;
+        bytes
+            .iter()
+            .flat_map(|b| {
+                (0..per_byte)
+                    .rev()
+                    .map(move |i| ((b >> (i * lines)) & mask) << first)
+            })
+            .collect()
+    }
+    let decoder = Decoder::new();
+
+    // Quad I/O Fast Read: opcode on IO0, address and mode on four lines,
+    // four dummy cycles, then data on four lines.
+    let mut samples = symbols(&[0xEB], 1, 0);
+    samples.extend(symbols(&[0x12, 0x34, 0x56, 0xFF], 4, 0));
+    samples.extend([0; 4]);
+    samples.extend(symbols(&[0xDE, 0xAD, 0xBE, 0xEF], 4, 0));
+    let decoded = decoder.decode(&samples).unwrap();
+    assert_eq!(decoded.address, Some(0x12_3456));
+    assert_eq!(decoded.mode_bits, Some(0xFF));
+    assert_eq!(decoded.data, [0xDE, 0xAD, 0xBE, 0xEF]);
+
+    // Standard read: data from the flash is on MISO.
+    let mut samples = symbols(&[0x03, 0x00, 0x10, 0x00], 1, 0);
+    samples.extend(symbols(&[0xA5, 0x5A], 1, 1));
+    let decoded = decoder.decode(&samples).unwrap();
+    assert_eq!((decoded.opcode, decoded.address), (0x03, Some(0x1000)));
+    assert_eq!(decoded.data, [0xA5, 0x5A]);
+
+    assert_eq!(
+        pack_symbols(&[0b01, 0b10, 0b11, 0b00], 2).unwrap(),
+        [0b0110_1100]
+    );
+    assert_eq!(pack_symbols(&[0xA5, 0x5A], 8).unwrap(), [0xA5, 0x5A]);
+    for lines in [0, 3, 5, 9] {
+        assert!(matches!(
+            pack_symbols(&[0; 8], lines),
+            Err(Error::InvalidLines { lines: l }) if l == lines
+        ));
+    }
+}
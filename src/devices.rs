@@ -0,0 +1,111 @@
+//! Database of known devices which do not support SFDP.
+//!
+//! SFDP was standardised in 2011, and many earlier devices, and some current clones, do not
+//! implement it. `Flash::detect()` looks up such devices by their JEDEC ID to find their
+//! capacity and erase geometry. Devices which support SFDP do not need an entry.
+//!
+//! Entries also give the power-up timing used by `Flash::detect_with_power_up()`, which SFDP
+//! does not describe, so devices without an entry use the conservative defaults.
+
+use core::time::Duration;
+
+/// Time from VCC reaching its minimum until instructions are accepted, tVSL, used for
+/// devices not in the database. Most devices need 20us to 800us.
+pub const DEFAULT_POWER_UP_DELAY: Duration = Duration::from_millis(1);
+
+/// Time from VCC reaching its minimum until writes are accepted, tPUW, used for
+/// devices not in the database. Most devices specify at most 10ms.
+pub const DEFAULT_POWER_UP_WRITE_DELAY: Duration = Duration::from_millis(10);
+
+/// Parameters of a known device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Part name.
+    pub name: &'static str,
+    /// JEDEC manufacturer ID, without continuation codes.
+    pub manufacturer_id: u8,
+    /// JEDEC (long) device ID.
+    pub device_id: u16,
+    /// Memory capacity in bytes.
+    pub capacity: usize,
+    /// Page program size in bytes.
+    pub page_size: usize,
+    /// Size of the smallest erase in bytes.
+    pub erase_size: usize,
+    /// Opcode of the smallest erase instruction.
+    pub erase_opcode: u8,
+    /// Opcode of the chip erase instruction, 0xC7 or its alternate 0x60.
+    pub chip_erase_opcode: u8,
+    /// Time after power-up until instructions are accepted, tVSL.
+    pub power_up_delay: Duration,
+    /// Time after power-up until writes are accepted, tPUW.
+    pub power_up_write_delay: Duration,
+}
+
+const fn device(
+    name: &'static str,
+    manufacturer_id: u8,
+    device_id: u16,
+    capacity: usize,
+    erase_size: usize,
+    erase_opcode: u8,
+) -> DeviceInfo {
+    DeviceInfo {
+        name,
+        manufacturer_id,
+        device_id,
+        capacity,
+        page_size: 256,
+        erase_size,
+        erase_opcode,
+        chip_erase_opcode: 0xC7,
+        power_up_delay: DEFAULT_POWER_UP_DELAY,
+        power_up_write_delay: DEFAULT_POWER_UP_WRITE_DELAY,
+    }
+}
+
+const KB: usize = 1024;
+const MB: usize = 1024 * 1024;
+
+/// Known devices, by JEDEC ID.
+pub const DEVICES: &[DeviceInfo] = &[
+    // Winbond.
+    device("W25X10", 0xEF, 0x3011, 128 * KB, 4 * KB, 0x20),
+    device("W25X20", 0xEF, 0x3012, 256 * KB, 4 * KB, 0x20),
+    device("W25X40", 0xEF, 0x3013, 512 * KB, 4 * KB, 0x20),
+    device("W25X80", 0xEF, 0x3014, MB, 4 * KB, 0x20),
+    device("W25Q80", 0xEF, 0x4014, MB, 4 * KB, 0x20),
+    device("W25Q16", 0xEF, 0x4015, 2 * MB, 4 * KB, 0x20),
+    device("W25Q32", 0xEF, 0x4016, 4 * MB, 4 * KB, 0x20),
+    device("W25Q64", 0xEF, 0x4017, 8 * MB, 4 * KB, 0x20),
+    device("W25Q128", 0xEF, 0x4018, 16 * MB, 4 * KB, 0x20),
+    // Macronix.
+    device("MX25L8005", 0xC2, 0x2014, MB, 4 * KB, 0x20),
+    device("MX25L1605", 0xC2, 0x2015, 2 * MB, 4 * KB, 0x20),
+    device("MX25L3205", 0xC2, 0x2016, 4 * MB, 4 * KB, 0x20),
+    device("MX25L6405", 0xC2, 0x2017, 8 * MB, 4 * KB, 0x20),
+    // Spansion, with uniform 64kB sectors.
+    device("S25FL016A", 0x01, 0x0214, 2 * MB, 64 * KB, 0xD8),
+    device("S25FL032A", 0x01, 0x0215, 4 * MB, 64 * KB, 0xD8),
+    device("S25FL064A", 0x01, 0x0216, 8 * MB, 64 * KB, 0xD8),
+    // Numonyx/Micron, with uniform 64kB or 256kB sectors.
+    device("M25P16", 0x20, 0x2015, 2 * MB, 64 * KB, 0xD8),
+    device("M25P32", 0x20, 0x2016, 4 * MB, 64 * KB, 0xD8),
+    device("M25P64", 0x20, 0x2017, 8 * MB, 64 * KB, 0xD8),
+    device("M25P128", 0x20, 0x2018, 16 * MB, 256 * KB, 0xD8),
+    // GigaDevice.
+    device("GD25Q16", 0xC8, 0x4015, 2 * MB, 4 * KB, 0x20),
+    device("GD25Q32", 0xC8, 0x4016, 4 * MB, 4 * KB, 0x20),
+    device("GD25Q64", 0xC8, 0x4017, 8 * MB, 4 * KB, 0x20),
+    // Atmel/Adesto.
+    device("AT25DF041A", 0x1F, 0x4401, 512 * KB, 4 * KB, 0x20),
+    device("AT25DF081A", 0x1F, 0x4501, MB, 4 * KB, 0x20),
+    device("AT25DF161", 0x1F, 0x4602, 2 * MB, 4 * KB, 0x20),
+];
+
+/// Look up a known device by its JEDEC manufacturer and device IDs.
+pub fn lookup(manufacturer_id: u8, device_id: u16) -> Option<&'static DeviceInfo> {
+    DEVICES
+        .iter()
+        .find(|d| d.manufacturer_id == manufacturer_id && d.device_id == device_id)
+}
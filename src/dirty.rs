@@ -0,0 +1,177 @@
+//! Tracking which sectors have been programmed since they were last erased.
+//!
+//! Layers above `Flash`, such as file systems and key-value stores, must know whether a
+//! region can be programmed directly or must be erased first. Reading it back to check
+//! for 0xFF is slow, so a `DirtyMap` set using `Flash::set_dirty_map()` instead records a
+//! bit for each sector: it is set when a page program reaches the sector, and cleared
+//! when the whole sector is erased. A clean sector is known to be erased.
+//!
+//! A sector is marked dirty before it is programmed, and clean only after its erase
+//! completes, so a failed or interrupted operation leaves it dirty. The contents of the
+//! device are not known when the map is created, so every sector starts dirty unless
+//! the map is created using `DirtyMap::erased()`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Error, Result};
+
+/// One bit per sector, set if the sector may have been programmed since it was last erased.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirtyMap {
+    sector_size: usize,
+    sectors: usize,
+    bits: Vec<u32>,
+}
+
+impl DirtyMap {
+    /// Create a map of `capacity` bytes in sectors of `sector_size` bytes, with every
+    /// sector dirty.
+    ///
+    /// Returns `InvalidConfiguration` if the sector size is not a power of two, or the
+    /// capacity is not a multiple of it.
+    pub fn new(capacity: usize, sector_size: usize) -> Result<Self> {
+        if !sector_size.is_power_of_two() || !capacity.is_multiple_of(sector_size) {
+            log::error!(
+                "Invalid dirty map of {} bytes in {} byte sectors",
+                capacity,
+                sector_size
+            );
+            return Err(Error::InvalidConfiguration);
+        }
+        let sectors = capacity / sector_size;
+        let mut map = DirtyMap {
+            sector_size,
+            sectors,
+            bits: vec![0; sectors.div_ceil(32)],
+        };
+        map.set_all_dirty();
+        Ok(map)
+    }
+
+    /// Create a map as `new()` does, with every sector known to be erased.
+    pub fn erased(capacity: usize, sector_size: usize) -> Result<Self> {
+        let mut map = DirtyMap::new(capacity, sector_size)?;
+        map.set_all_erased();
+        Ok(map)
+    }
+
+    /// Get the size of each sector in bytes.
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Get the number of sectors.
+    pub fn sectors(&self) -> usize {
+        self.sectors
+    }
+
+    /// Get the number of dirty sectors.
+    pub fn dirty_count(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Check if the sector containing `address` may have been programmed since it was
+    /// last erased.
+    pub fn is_dirty(&self, address: u32) -> bool {
+        let sector = address as usize / self.sector_size;
+        sector < self.sectors && self.bits[sector / 32] & (1 << (sector % 32)) != 0
+    }
+
+    /// Check if any sector overlapping `length` bytes from `address` must be erased
+    /// before they are programmed.
+    pub fn needs_erase(&self, address: u32, length: usize) -> bool {
+        self.overlapping(address, length)
+            .any(|sector| self.bits[sector / 32] & (1 << (sector % 32)) != 0)
+    }
+
+    /// Get the addresses of the dirty sectors, in order.
+    pub fn dirty_sectors(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.sectors)
+            .filter(|sector| self.bits[sector / 32] & (1 << (sector % 32)) != 0)
+            .map(|sector| (sector * self.sector_size) as u32)
+    }
+
+    /// Mark every sector overlapping `length` bytes from `address` as programmed.
+    pub fn mark_programmed(&mut self, address: u32, length: usize) {
+        for sector in self.overlapping(address, length) {
+            self.bits[sector / 32] |= 1 << (sector % 32);
+        }
+    }
+
+    /// Mark the sectors wholly within `length` bytes from `address` as erased.
+    ///
+    /// Sectors only partly within the range are left unchanged.
+    pub fn mark_erased(&mut self, address: u32, length: usize) {
+        let start = (address as usize).div_ceil(self.sector_size);
+        let end = usize::min((address as usize + length) / self.sector_size, self.sectors);
+        for sector in start..end {
+            self.bits[sector / 32] &= !(1 << (sector % 32));
+        }
+    }
+
+    /// Mark every sector as erased, for example after a chip erase.
+    pub fn set_all_erased(&mut self) {
+        self.bits.fill(0);
+    }
+
+    /// Mark every sector as dirty, for example after the device was written by other means.
+    pub fn set_all_dirty(&mut self) {
+        self.bits.fill(u32::MAX);
+        if !self.sectors.is_multiple_of(32) {
+            if let Some(last) = self.bits.last_mut() {
+                *last = (1 << (self.sectors % 32)) - 1;
+            }
+        }
+    }
+
+    /// Get the indices of the sectors overlapping `length` bytes from `address`.
+    fn overlapping(&self, address: u32, length: usize) -> core::ops::Range<usize> {
+        if length == 0 {
+            return 0..0;
+        }
+        let start = address as usize / self.sector_size;
+        let end = (address as usize + length).div_ceil(self.sector_size);
+        usize::min(start, self.sectors)..usize::min(end, self.sectors)
+    }
+}
+
+#[test]
+fn test_dirty_map() {
+    use crate::mock::MockFlash;
+
+    assert!(DirtyMap::new(64 * 1024, 3000).is_err());
+    assert!(DirtyMap::new(10_000, 4096).is_err());
+    let mut map = DirtyMap::new(40 * 4096, 4096).unwrap();
+    assert_eq!(map.sectors(), 40);
+    assert_eq!(map.dirty_count(), 40);
+    assert!(!map.is_dirty(40 * 4096));
+    map.mark_erased(0x0800, 3 * 4096);
+    assert_eq!(map.dirty_count(), 38);
+    assert!(map.is_dirty(0x0000) && !map.is_dirty(0x1000) && !map.is_dirty(0x2FFF));
+    assert!(map.is_dirty(0x3000));
+    map.set_all_erased();
+    map.mark_programmed(0x1FFF, 2);
+    assert!(!map.needs_erase(0x0000, 0x1000));
+    assert!(map.needs_erase(0x0000, 0x2000));
+    assert!(!map.needs_erase(0x2000, 0));
+    assert_eq!(map.dirty_sectors().collect::<Vec<_>>(), [0x1000, 0x2000]);
+
+    // The map is fed by programs and erases through `Flash`.
+    let mut mock = MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_dirty_map(Some(DirtyMap::new(64 * 1024, 4096).unwrap()));
+    flash.erase().unwrap();
+    assert_eq!(flash.dirty_map().unwrap().dirty_count(), 0);
+    flash.page_program(0x1100, &[0; 16]).unwrap();
+    flash.program(0x8000, &[0; 8192], false).unwrap();
+    let dirty: Vec<u32> = flash.dirty_map().unwrap().dirty_sectors().collect();
+    assert_eq!(dirty, [0x1000, 0x8000, 0x9000]);
+    flash.erase_sector(0x1234).unwrap();
+    flash.erase_range(0x8000, 0x1000, false).unwrap();
+    let dirty: Vec<u32> = flash.dirty_map().unwrap().dirty_sectors().collect();
+    assert_eq!(dirty, [0x9000]);
+}
@@ -0,0 +1,291 @@
+//! A/B firmware image slots with a metadata journal.
+//!
+//! `DualBank` manages two image slots and a metadata region recording which slot is
+//! active, whether an update is pending, and the length and CRC-32 of each slot's image.
+//! An updater writes the new image to the inactive slot using `stage_update()`, and a
+//! bootloader then checks it with `validate()` and makes it active with `swap()`;
+//! swapping back to the previous image provides rollback.
+//!
+//! Metadata is appended as fixed-size CRC-protected records to a `Journal` in the metadata
+//! region, and the newest valid record is used, so an update interrupted by power loss
+//! leaves the previous state intact. When a sector of the journal is full the next one is
+//! erased, so the latest record always survives.
+
+use core::ops::Range;
+
+use crate::crc::storage_crc32;
+use crate::{Error, Journal, Partition, Result, Storage};
+
+/// Magic number at the start of each metadata record.
+const MAGIC: u32 = 0x4B4E_4244;
+/// Size of a metadata record.
+const RECORD_LEN: usize = 28;
+/// Length field of a slot without a valid image.
+const NO_IMAGE: u32 = 0xFFFF_FFFF;
+
+/// Length and CRC-32 of an image in a slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    /// Length of the image in bytes.
+    pub length: usize,
+    /// CRC-32 of the image.
+    pub crc: u32,
+}
+
+/// State recorded in the metadata region.
+///
+/// The default state has slot 0 active and no images recorded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BankState {
+    /// Index of the active slot, 0 or 1.
+    pub active: usize,
+    /// If set, an update has been staged in the inactive slot but not yet swapped in.
+    pub pending: bool,
+    /// Image stored in each slot, if any.
+    pub images: [Option<Image>; 2],
+}
+
+impl BankState {
+    /// Get the index of the inactive slot.
+    pub fn inactive(&self) -> usize {
+        1 - self.active
+    }
+
+    fn to_record(self) -> [u8; RECORD_LEN] {
+        let mut record = [0u8; RECORD_LEN];
+        record[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        record[4] = self.active as u8;
+        record[5] = self.pending as u8;
+        for (i, image) in self.images.iter().enumerate() {
+            let (length, crc) = match image {
+                Some(image) => (image.length as u32, image.crc),
+                None => (NO_IMAGE, NO_IMAGE),
+            };
+            let base = 8 + i * 8;
+            record[base..base + 4].copy_from_slice(&length.to_le_bytes());
+            record[base + 4..base + 8].copy_from_slice(&crc.to_le_bytes());
+        }
+        let crc = crate::crc::crc32(&record[..RECORD_LEN - 4]);
+        record[RECORD_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    fn from_record(record: &[u8]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes(record[i..i + 4].try_into().unwrap());
+        if word(0) != MAGIC
+            || word(RECORD_LEN - 4) != crate::crc::crc32(&record[..RECORD_LEN - 4])
+            || record[4] > 1
+        {
+            return None;
+        }
+        let image = |i: usize| {
+            let length = word(8 + i * 8);
+            (length != NO_IMAGE).then(|| Image {
+                length: length as usize,
+                crc: word(12 + i * 8),
+            })
+        };
+        Some(BankState {
+            active: record[4] as usize,
+            pending: record[5] != 0,
+            images: [image(0), image(1)],
+        })
+    }
+}
+
+/// Two firmware image slots and a metadata region in `storage`.
+pub struct DualBank<'a, F: Storage> {
+    storage: &'a mut F,
+    slots: [Range<u32>; 2],
+    metadata: Range<u32>,
+    state: BankState,
+}
+
+impl<'a, F: Storage> DualBank<'a, F> {
+    /// Open the image slots `slots` and the metadata region `metadata` in `storage`.
+    ///
+    /// Each region must be aligned to the erase size and must not overlap the others, and
+    /// the metadata region must contain at least two sectors. If the metadata region
+    /// contains no valid record, slot 0 is active and no images are recorded.
+    pub fn open(storage: &'a mut F, slots: [Range<u32>; 2], metadata: Range<u32>) -> Result<Self> {
+        let regions = [slots[0].clone(), slots[1].clone(), metadata.clone()];
+        for (i, region) in regions.iter().enumerate() {
+            Partition::new_erasable(&mut *storage, region.start, region.len())?;
+            for other in regions[i + 1..].iter() {
+                if region.start < other.end && other.start < region.end {
+                    log::error!("Dual bank regions overlap at 0x{:08X}", other.start);
+                    return Err(Error::InvalidAddress {
+                        address: other.start,
+                    });
+                }
+            }
+        }
+        let mut bank = DualBank {
+            storage,
+            slots,
+            metadata,
+            state: BankState::default(),
+        };
+        bank.load()?;
+        log::debug!("Opened dual bank, state {:?}", bank.state);
+        Ok(bank)
+    }
+
+    /// Get the current state.
+    pub fn state(&self) -> BankState {
+        self.state
+    }
+
+    /// Get the index of the active slot.
+    pub fn active_slot(&self) -> usize {
+        self.state.active
+    }
+
+    /// Get a partition covering `slot`, for example to read or boot its image.
+    pub fn slot(&mut self, slot: usize) -> Result<Partition<'_, F>> {
+        let region = self.slots[slot].clone();
+        Partition::new(&mut *self.storage, region.start, region.len())
+    }
+
+    /// Write `image` to the inactive slot, verify it, and record it as a pending update.
+    pub fn stage_update(&mut self, image: &[u8]) -> Result<()> {
+        let target = self.state.inactive();
+        if image.len() > self.slots[target].len() {
+            log::error!("Image does not fit in slot {}", target);
+            return Err(Error::NoSpace);
+        }
+        // Forget the old image before erasing it, so it is never mistaken for valid.
+        let mut state = self.state;
+        state.images[target] = None;
+        state.pending = false;
+        self.record(state)?;
+
+        let mut slot = self.slot(target)?;
+        slot.erase_all()?;
+        slot.program_data(0, image)?;
+        let crc = crate::crc::crc32(image);
+        if storage_crc32(&mut slot, 0, image.len())? != crc {
+            log::error!("Staged image in slot {} failed verification", target);
+            return Err(Error::ChecksumMismatch {
+                address: self.slots[target].start,
+            });
+        }
+        state.images[target] = Some(Image {
+            length: image.len(),
+            crc,
+        });
+        state.pending = true;
+        self.record(state)
+    }
+
+    /// Check the image recorded in `slot` is present and matches its CRC.
+    pub fn validate(&mut self, slot: usize) -> Result<bool> {
+        let image = match self.state.images[slot] {
+            Some(image) => image,
+            None => return Ok(false),
+        };
+        let mut partition = self.slot(slot)?;
+        if image.length > partition.len() {
+            return Ok(false);
+        }
+        Ok(storage_crc32(&mut partition, 0, image.length)? == image.crc)
+    }
+
+    /// Make the inactive slot active, clearing any pending update.
+    ///
+    /// The inactive slot's image is validated first, and if it is not valid
+    /// `ChecksumMismatch` is returned and the state is not changed. Swapping again
+    /// rolls back to the previous image.
+    pub fn swap(&mut self) -> Result<()> {
+        let target = self.state.inactive();
+        if !self.validate(target)? {
+            log::error!("Cannot swap to slot {}: no valid image", target);
+            return Err(Error::ChecksumMismatch {
+                address: self.slots[target].start,
+            });
+        }
+        let mut state = self.state;
+        state.active = target;
+        state.pending = false;
+        self.record(state)
+    }
+
+    /// Mount the metadata journal.
+    fn journal(&mut self) -> Result<Journal<'_, F>> {
+        let region = self.metadata.clone();
+        Journal::mount(&mut *self.storage, region)
+    }
+
+    /// Find the newest valid metadata record.
+    fn load(&mut self) -> Result<()> {
+        let records = self.journal()?.records()?;
+        for (seq, record) in records.iter().rev() {
+            if record.len() == RECORD_LEN {
+                if let Some(state) = BankState::from_record(record) {
+                    self.state = state;
+                    break;
+                }
+            }
+            log::warn!("Skipping invalid metadata record {}", seq);
+        }
+        Ok(())
+    }
+
+    /// Append a metadata record for `state` to the metadata journal.
+    fn record(&mut self, state: BankState) -> Result<()> {
+        self.journal()?.append(&state.to_record())?;
+        self.state = state;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dual_bank() {
+    use crate::ReadStorage;
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let slots = [0x0000..0x8000, 0x8000..0xE000];
+    assert!(DualBank::open(&mut flash, slots.clone(), 0xD000..0xF000).is_err());
+    assert!(DualBank::open(&mut flash, slots.clone(), 0xF000..0x10000).is_err());
+    let image: alloc::vec::Vec<u8> = (0..3000).map(|x| x as u8).collect();
+    {
+        let mut bank = DualBank::open(&mut flash, slots.clone(), 0xE000..0x10000).unwrap();
+        assert_eq!(bank.state(), BankState::default());
+        assert!(bank.swap().is_err());
+        bank.stage_update(&image).unwrap();
+        assert!(bank.state().pending);
+        assert!(bank.validate(1).unwrap());
+    }
+
+    // The state persists, and the staged image can be swapped in and rolled back.
+    let mut bank = DualBank::open(&mut flash, slots.clone(), 0xE000..0x10000).unwrap();
+    assert!(bank.state().pending);
+    bank.swap().unwrap();
+    assert_eq!((bank.active_slot(), bank.state().pending), (1, false));
+    assert_eq!(bank.slot(1).unwrap().read(0, 3000).unwrap(), image);
+    bank.stage_update(&image[..100]).unwrap();
+    bank.swap().unwrap();
+    bank.swap().unwrap();
+    assert_eq!(bank.active_slot(), 1);
+
+    // Fill the metadata journal, which wraps around its sectors.
+    for _ in 0..300 {
+        bank.swap().unwrap();
+    }
+    let state = bank.state();
+    let bank = DualBank::open(&mut flash, slots.clone(), 0xE000..0x10000).unwrap();
+    assert_eq!(bank.state(), state);
+    drop(flash);
+
+    // Power loss while recording a swap leaves the previous state.
+    mock.set_power_loss_after(Some(10));
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut bank = DualBank::open(&mut flash, slots.clone(), 0xE000..0x10000).unwrap();
+    assert!(bank.swap().is_err());
+    drop(flash);
+    mock.restore_power();
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let bank = DualBank::open(&mut flash, slots, 0xE000..0x10000).unwrap();
+    assert_eq!(bank.state(), state);
+}
@@ -0,0 +1,273 @@
+//! EEPROM emulation using two flash sectors.
+//!
+//! Variables are identified by a 16-bit ID and stored as records appended to the
+//! active sector. Each record is stored as:
+//!
+//! | Bytes   | Content                               |
+//! |---------|---------------------------------------|
+//! | 2       | Variable ID, little-endian            |
+//! | 2       | Data length `n`, little-endian        |
+//! | `n`     | Data                                  |
+//! | 4       | CRC-32 of ID, length, and data        |
+//!
+//! When the active sector is full, the latest record for each variable is copied to
+//! the other sector, which then becomes active, and the old sector is erased. A header
+//! at the start of each sector tracks this swap, so that an interrupted compaction is
+//! completed or discarded when mounting, without losing the variables.
+
+use alloc::vec::Vec;
+
+use crate::crc::Crc32;
+use crate::sector_pair::{SectorPair, HEADER_LEN};
+use crate::{Error, Flash, FlashAccess, Result};
+
+/// Size of a record's ID and length fields.
+const RECORD_HEADER_LEN: usize = 4;
+/// Size of a record's CRC.
+const RECORD_CRC_LEN: usize = 4;
+/// ID value of erased memory, which marks the end of the records in a sector.
+const ID_ERASED: u16 = 0xFFFF;
+
+/// Emulated EEPROM stored in two flash sectors.
+pub struct Eeprom<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    sectors: SectorPair,
+    sector_size: usize,
+    /// Offset within the active sector where the next record will be written.
+    next: usize,
+}
+
+impl<'f, 'a, A: FlashAccess> Eeprom<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Mount an emulated EEPROM using the two sectors at `sector0` and `sector1`.
+    ///
+    /// The sector erase size must be known, and both sectors must be aligned to it.
+    /// If neither sector holds a valid EEPROM, both are erased and a new EEPROM created.
+    /// An interrupted compaction is completed or discarded as appropriate.
+    pub fn mount(flash: &'f mut Flash<'a, A>, sector0: u32, sector1: u32) -> Result<Self> {
+        let mut sectors = SectorPair::new(&*flash, sector0, sector1)?;
+        if !sectors.mount(flash)? {
+            log::info!("No valid EEPROM found, formatted");
+        }
+        let mut eeprom = Eeprom {
+            flash,
+            sector_size: sectors.sector_size(),
+            sectors,
+            next: HEADER_LEN,
+        };
+        eeprom.next = eeprom.find_end()?;
+        Ok(eeprom)
+    }
+
+    /// Erase all variables.
+    pub fn format(&mut self) -> Result<()> {
+        self.next = HEADER_LEN;
+        self.sectors.format(self.flash)
+    }
+
+    /// Read variable `id` into `buf`.
+    ///
+    /// Returns the length of the variable, or `None` if it has never been written.
+    pub fn read_var(&mut self, id: u16, buf: &mut [u8]) -> Result<Option<usize>> {
+        match self.find_var(id)? {
+            Some((offset, len)) => {
+                if buf.len() < len {
+                    return Err(Error::BufferTooSmall {
+                        length: buf.len(),
+                        required: len,
+                    });
+                }
+                let address = self.sectors.active_address() + (offset + RECORD_HEADER_LEN) as u32;
+                self.flash.read_into(address, &mut buf[..len])?;
+                Ok(Some(len))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Write `data` to variable `id`.
+    ///
+    /// `id` must not be 0xFFFF, and `data` must be at most 65535 bytes, otherwise
+    /// `InvalidArgument` is returned. If the variable already holds `data`, nothing
+    /// is written.
+    pub fn write_var(&mut self, id: u16, data: &[u8]) -> Result<()> {
+        if id == ID_ERASED || data.len() > u16::MAX as usize {
+            log::error!("Invalid EEPROM variable ID or length");
+            return Err(Error::InvalidArgument);
+        }
+        let record_len = RECORD_HEADER_LEN + data.len() + RECORD_CRC_LEN;
+        if HEADER_LEN + record_len > self.sector_size {
+            return Err(Error::NoSpace);
+        }
+
+        if let Some((offset, len)) = self.find_var(id)? {
+            let address = self.sectors.active_address() + (offset + RECORD_HEADER_LEN) as u32;
+            if len == data.len() && self.flash.read(address, len)? == data {
+                log::trace!("EEPROM variable {} unchanged", id);
+                return Ok(());
+            }
+        }
+
+        if self.next + record_len > self.sector_size {
+            self.compact()?;
+            if self.next + record_len > self.sector_size {
+                log::error!("EEPROM is full");
+                return Err(Error::NoSpace);
+            }
+        }
+
+        let address = self.sectors.active_address() + self.next as u32;
+        let record = Self::make_record(id, data);
+        self.flash.program_data(address, &record)?;
+        self.next += record_len;
+        Ok(())
+    }
+
+    /// Copy the latest record for each variable into the other sector, and make it active.
+    pub fn compact(&mut self) -> Result<()> {
+        log::debug!("Compacting EEPROM");
+
+        // Find the latest record for each variable.
+        let mut latest: Vec<(u16, usize, usize)> = Vec::new();
+        for (id, offset, len) in self.records()? {
+            match latest.iter_mut().find(|(i, _, _)| *i == id) {
+                Some(entry) => *entry = (id, offset, len),
+                None => latest.push((id, offset, len)),
+            }
+        }
+
+        self.next = self.sectors.swap(self.flash, |flash, from, to| {
+            let mut next = HEADER_LEN;
+            for (_, offset, len) in latest {
+                let record_len = RECORD_HEADER_LEN + len + RECORD_CRC_LEN;
+                let record = flash.read(from + offset as u32, record_len)?;
+                flash.program_data(to + next as u32, &record)?;
+                next += record_len;
+            }
+            Ok(next)
+        })?;
+        Ok(())
+    }
+
+    /// Find the latest valid record for `id`, returning its offset and data length.
+    fn find_var(&mut self, id: u16) -> Result<Option<(usize, usize)>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .rev()
+            .find(|(i, _, _)| *i == id)
+            .map(|(_, offset, len)| (offset, len)))
+    }
+
+    /// Find the offset after the last record in the active sector.
+    ///
+    /// If the sector ends with a corrupted record, the sector is treated as full, so that
+    /// the next write will compact the valid records into the other sector.
+    fn find_end(&mut self) -> Result<usize> {
+        let mut offset = HEADER_LEN;
+        while offset + RECORD_HEADER_LEN <= self.sector_size {
+            match self.read_record(offset)? {
+                Some((id, len, valid)) => {
+                    if id == ID_ERASED && len == 0xFFFF {
+                        return Ok(offset);
+                    } else if !valid {
+                        log::warn!("Corrupt EEPROM record at offset {}", offset);
+                        return Ok(self.sector_size);
+                    }
+                    offset += RECORD_HEADER_LEN + len + RECORD_CRC_LEN;
+                }
+                None => return Ok(self.sector_size),
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Get the (ID, offset, length) of each valid record in the active sector.
+    fn records(&mut self) -> Result<Vec<(u16, usize, usize)>> {
+        let mut records = Vec::new();
+        let mut offset = HEADER_LEN;
+        while offset < self.next {
+            match self.read_record(offset)? {
+                Some((id, len, true)) => {
+                    records.push((id, offset, len));
+                    offset += RECORD_HEADER_LEN + len + RECORD_CRC_LEN;
+                }
+                _ => break,
+            }
+        }
+        Ok(records)
+    }
+
+    /// Read the record at `offset` in the active sector.
+    ///
+    /// Returns its ID, data length, and whether its CRC is valid, or `None`
+    /// if its length would exceed the sector.
+    fn read_record(&mut self, offset: usize) -> Result<Option<(u16, usize, bool)>> {
+        let address = self.sectors.active_address() + offset as u32;
+        let header = self.flash.read(address, RECORD_HEADER_LEN)?;
+        let id = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        if id == ID_ERASED && len == 0xFFFF {
+            return Ok(Some((id, len, true)));
+        }
+        if offset + RECORD_HEADER_LEN + len + RECORD_CRC_LEN > self.sector_size {
+            return Ok(None);
+        }
+        let rest = self
+            .flash
+            .read(address + RECORD_HEADER_LEN as u32, len + RECORD_CRC_LEN)?;
+        let mut crc = Crc32::new();
+        crc.update(&header);
+        crc.update(&rest[..len]);
+        let stored = u32::from_le_bytes(rest[len..].try_into().unwrap());
+        Ok(Some((id, len, crc.finish() == stored)))
+    }
+
+    /// Build the bytes of a record storing `data` for `id`.
+    fn make_record(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + data.len() + RECORD_CRC_LEN);
+        record.extend(id.to_le_bytes());
+        record.extend((data.len() as u16).to_le_bytes());
+        record.extend(data);
+        let mut crc = Crc32::new();
+        crc.update(&record);
+        record.extend(crc.finish().to_le_bytes());
+        record
+    }
+}
+
+#[test]
+fn test_eeprom() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(Eeprom::mount(&mut flash, 0x1000, 0x1000).is_err());
+    let mut eeprom = Eeprom::mount(&mut flash, 0x1000, 0x2000).unwrap();
+    eeprom.write_var(1, b"config").unwrap();
+    for n in 0..40u8 {
+        eeprom.write_var(2, &[n; 200]).unwrap();
+    }
+    let mut buf = [0; 200];
+    assert_eq!(eeprom.read_var(1, &mut buf).unwrap(), Some(6));
+    assert_eq!(&buf[..6], b"config");
+    assert_eq!(eeprom.read_var(3, &mut buf).unwrap(), None);
+    assert!(matches!(
+        eeprom.write_var(0xFFFF, b"x"),
+        Err(Error::InvalidArgument)
+    ));
+    drop(flash);
+
+    // Lose power while erasing the old sector after a compaction.
+    mock.set_power_loss_during_erase(Some(1));
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut eeprom = Eeprom::mount(&mut flash, 0x1000, 0x2000).unwrap();
+    assert!(eeprom.compact().is_err());
+    drop(flash);
+    mock.restore_power();
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut eeprom = Eeprom::mount(&mut flash, 0x2000, 0x1000).unwrap();
+    assert_eq!(eeprom.read_var(1, &mut buf).unwrap(), Some(6));
+    assert_eq!(eeprom.read_var(2, &mut buf).unwrap(), Some(200));
+    assert_eq!(buf, [39; 200]);
+}
@@ -0,0 +1,277 @@
+//! Byte-granular rewrite emulation for NOR flash.
+//!
+//! NOR flash can only clear bits when programming, and can only set bits again by erasing
+//! a whole sector. `EmulatedByteWrite` hides this by performing a read-modify-write of each
+//! affected sector, so code written for EEPROM or FRAM can be ported onto NOR flash.
+//!
+//! With `Staging::SpareSector`, a sector being rewritten is first copied with its new data
+//! to a spare sector, and a marker naming the sector is then written to a second spare
+//! sector before the original is erased and programmed back from the copy. The marker is
+//! erased once the sector is complete, so if power is lost while the sector is rewritten,
+//! `EmulatedByteWrite::new()` finds the marker and copies the sector back again.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Error, Flash, FlashAccess, Result};
+
+/// Magic number starting a spare sector marker.
+const MARKER_MAGIC: u32 = 0x5350_5231;
+/// Size of a marker: the magic number, the target sector address, and its complement.
+const MARKER_LEN: usize = 12;
+
+/// Where sector contents are staged while a sector is erased and reprogrammed.
+#[derive(Copy, Clone, Debug)]
+pub enum Staging {
+    /// Stage the sector in a RAM buffer of one sector, allocated on creation.
+    Ram,
+    /// Stage the sector in the two spare flash sectors starting at the given address.
+    ///
+    /// Only a page-sized RAM buffer is required, and the merged sector contents
+    /// remain on the first spare sector, with a marker on the second, while the target
+    /// sector is erased, so an interrupted rewrite is completed on the next creation.
+    /// This costs two extra erases and programs per sector updated.
+    SpareSector(u32),
+}
+
+/// Byte-granular rewrite wrapper over a `Flash`.
+///
+/// Writes may be made to any address with any length, and will overwrite existing data
+/// without requiring an erase first; other data in affected sectors is preserved.
+pub struct EmulatedByteWrite<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    staging: Staging,
+    sector_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<'f, 'a, A: FlashAccess> EmulatedByteWrite<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a new wrapper over `flash` using the `staging` strategy.
+    ///
+    /// The flash sector erase size must be known, either set manually or discovered using SFDP.
+    /// A spare sector address must be aligned to the sector erase size. If the spare sectors
+    /// hold a sector whose rewrite was interrupted, it is restored first.
+    pub fn new(flash: &'f mut Flash<'a, A>, staging: Staging) -> Result<Self> {
+        let sector_size = match flash.erase_size() {
+            Some(erase_size) => erase_size,
+            None => {
+                log::warn!("Sector erase size not known.");
+                return Err(Error::NoEraseInstruction);
+            }
+        };
+        let buf = match staging {
+            Staging::Ram => vec![0; sector_size],
+            Staging::SpareSector(address) => {
+                if !(address as usize).is_multiple_of(sector_size) {
+                    log::error!("Spare sector 0x{:08X} is not sector-aligned", address);
+                    return Err(Error::InvalidAddress { address });
+                }
+                let len = usize::min(flash.page_size().unwrap_or(256), sector_size);
+                vec![0; usize::max(len, MARKER_LEN)]
+            }
+        };
+        let mut emulated = EmulatedByteWrite {
+            flash,
+            staging,
+            sector_size,
+            buf,
+        };
+        if let Staging::SpareSector(spare) = staging {
+            emulated.recover(spare)?;
+        }
+        Ok(emulated)
+    }
+
+    /// Get the staging strategy in use.
+    pub fn staging(&self) -> Staging {
+        self.staging
+    }
+
+    /// Get a reference to the wrapped `Flash`.
+    pub fn flash(&mut self) -> &mut Flash<'a, A> {
+        self.flash
+    }
+
+    /// Read `buf.len()` bytes starting at `address` into `buf`.
+    pub fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<()> {
+        self.flash.read_into(address, buf)
+    }
+
+    /// Write `data` to `address`, preserving all other data.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        match self.staging {
+            Staging::Ram => self.flash.update(address, data, &mut self.buf),
+            Staging::SpareSector(spare) => self.write_via_spare(spare, address, data),
+        }
+    }
+
+    /// Write `data` to `address`, staging each modified sector in the spare sector.
+    fn write_via_spare(&mut self, spare: u32, address: u32, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let start = address as usize;
+        let end = start + data.len();
+        let spare_end = spare as usize + 2 * self.sector_size;
+        if start < spare_end && (spare as usize) < end {
+            log::error!("Write to 0x{:08X} overlaps spare sector", address);
+            return Err(Error::InvalidAddress { address: spare });
+        }
+
+        let mut sector = start - (start % self.sector_size);
+        while sector < end {
+            let (lo, hi) = (
+                usize::max(start, sector),
+                usize::min(end, sector + self.sector_size),
+            );
+            let new = &data[lo - start..hi - start];
+            let old = self.flash.read(lo as u32, new.len())?;
+            if old.iter().zip(new).all(|(o, n)| n & !o == 0) {
+                if old != new {
+                    log::trace!("Writing 0x{:08X} without erase", lo);
+                    self.flash.program_data(lo as u32, new)?;
+                }
+            } else {
+                log::trace!(
+                    "Rewriting sector 0x{:08X} via spare 0x{:08X}",
+                    sector,
+                    spare
+                );
+                self.flash.erase_sector(spare)?;
+                self.copy_sector(sector, spare as usize, Some((lo, new)))?;
+                self.write_marker(spare, sector as u32)?;
+                self.restore(spare, sector as u32)?;
+            }
+            sector += self.sector_size;
+        }
+        Ok(())
+    }
+
+    /// Get the address of the marker sector of the spare sectors at `spare`.
+    fn marker_address(&self, spare: u32) -> u32 {
+        spare + self.sector_size as u32
+    }
+
+    /// Record that the spare sector at `spare` holds the new contents of `target`.
+    fn write_marker(&mut self, spare: u32, target: u32) -> Result<()> {
+        let mut marker = [0; MARKER_LEN];
+        marker[0..4].copy_from_slice(&MARKER_MAGIC.to_le_bytes());
+        marker[4..8].copy_from_slice(&target.to_le_bytes());
+        marker[8..12].copy_from_slice(&(!target).to_le_bytes());
+        self.flash.program_data(self.marker_address(spare), &marker)
+    }
+
+    /// Erase `target` and copy the spare sector at `spare` back to it, then clear the marker.
+    fn restore(&mut self, spare: u32, target: u32) -> Result<()> {
+        self.flash.erase_sector(target)?;
+        self.copy_sector(spare as usize, target as usize, None)?;
+        self.flash.erase_sector(self.marker_address(spare))
+    }
+
+    /// Complete any rewrite through the spare sectors at `spare` interrupted by power loss.
+    fn recover(&mut self, spare: u32) -> Result<()> {
+        let address = self.marker_address(spare);
+        let marker = &mut self.buf[..MARKER_LEN];
+        self.flash.read_into(address, marker)?;
+        let word = |i: usize| u32::from_le_bytes(marker[i..i + 4].try_into().unwrap());
+        let (magic, target, check) = (word(0), word(4), word(8));
+        if marker.iter().all(|x| *x == 0xFF) {
+            return Ok(());
+        }
+        if magic != MARKER_MAGIC || target != !check {
+            // The marker was being written or erased, so the target was not yet erased
+            // or is already complete; only the marker needs clearing.
+            log::warn!(
+                "Clearing incomplete spare sector marker at 0x{:08X}",
+                address
+            );
+            return self.flash.erase_sector(address);
+        }
+        log::warn!(
+            "Restoring sector 0x{:08X} from spare 0x{:08X} after interrupted rewrite",
+            target,
+            spare
+        );
+        self.restore(spare, target)
+    }
+
+    /// Copy the sector at `from` to the erased sector at `to` one buffer at a time,
+    /// replacing any bytes covered by `merge` (given as an address in `from` and new data).
+    fn copy_sector(&mut self, from: usize, to: usize, merge: Option<(usize, &[u8])>) -> Result<()> {
+        for offset in (0..self.sector_size).step_by(self.buf.len()) {
+            let len = usize::min(self.buf.len(), self.sector_size - offset);
+            let buf = &mut self.buf[..len];
+            self.flash.read_into((from + offset) as u32, buf)?;
+            if let Some((addr, data)) = merge {
+                let (lo, hi) = (
+                    usize::max(addr, from + offset),
+                    usize::min(addr + data.len(), from + offset + len),
+                );
+                if lo < hi {
+                    buf[lo - from - offset..hi - from - offset]
+                        .copy_from_slice(&data[lo - addr..hi - addr]);
+                }
+            }
+            // Erased flash is all 0xFF so there is no need to program those chunks.
+            if buf.iter().any(|x| *x != 0xFF) {
+                self.flash.program_data((to + offset) as u32, buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_emulated_byte_write() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let pattern: Vec<u8> = (0..64 * 1024).map(|x| (x / 7) as u8 & 0x7F).collect();
+    mock.memory_mut().copy_from_slice(&pattern);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+
+    // A read-modify-write across a sector boundary keeps the rest of both sectors.
+    let mut emulated = EmulatedByteWrite::new(&mut flash, Staging::Ram).unwrap();
+    emulated.write(0x1FF8, &[0xAA; 16]).unwrap();
+    let mut buf = [0; 18];
+    emulated.read(0x1FF7, &mut buf).unwrap();
+    assert_eq!(buf[0], pattern[0x1FF7]);
+    assert_eq!(buf[1..17], [0xAA; 16]);
+    assert_eq!(buf[17], pattern[0x2008]);
+
+    // The spare sectors must be aligned, and must not be written through the wrapper.
+    assert!(EmulatedByteWrite::new(&mut flash, Staging::SpareSector(0xE100)).is_err());
+    let mut emulated = EmulatedByteWrite::new(&mut flash, Staging::SpareSector(0xE000)).unwrap();
+    assert!(emulated.write(0xDFFF, &[0; 2]).is_err());
+    assert!(emulated.write(0xF000, &[0; 2]).is_err());
+    emulated
+        .write(0x2FFC, &[0x80, 0x81, 0x82, 0x83, 0x84, 0x85])
+        .unwrap();
+    // Clearing bits only is programmed in place.
+    emulated.write(0x3001, &[0x00]).unwrap();
+    drop(flash);
+
+    let mut expected = pattern.clone();
+    expected[0x1FF8..0x2008].fill(0xAA);
+    expected[0x2FFC..0x3002].copy_from_slice(&[0x80, 0x81, 0x82, 0x83, 0x84, 0x00]);
+    assert_eq!(mock.memory()[..0xE000], expected[..0xE000]);
+    assert!(mock.memory()[0xF000..].iter().all(|x| *x == 0xFF));
+
+    // Lose power while erasing the target sector, after it was staged on the spare.
+    mock.set_power_loss_during_erase(Some(1));
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut emulated = EmulatedByteWrite::new(&mut flash, Staging::SpareSector(0xE000)).unwrap();
+    assert!(emulated.write(0x4010, &[0xFF, 0xFE]).is_err());
+    drop(flash);
+    assert_ne!(mock.memory()[0x4000..0x5000], expected[0x4000..0x5000]);
+    mock.restore_power();
+
+    // The rewrite is completed when the wrapper is next created.
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    EmulatedByteWrite::new(&mut flash, Staging::SpareSector(0xE000)).unwrap();
+    drop(flash);
+    expected[0x4010..0x4012].copy_from_slice(&[0xFF, 0xFE]);
+    assert_eq!(mock.memory()[..0xE000], expected[..0xE000]);
+    assert!(mock.memory()[0xF000..].iter().all(|x| *x == 0xFF));
+}
@@ -1,12 +1,33 @@
 use alloc::vec::Vec;
 use core::time::Duration;
 
+/// A single erase operation in an `ErasePlan`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EraseOp {
+    /// Erase instruction opcode.
+    pub opcode: u8,
+    /// Number of bytes erased.
+    pub size: usize,
+    /// Base address of the erased sector or block.
+    pub address: u32,
+    /// Typical duration of the erase, if known.
+    pub duration: Option<Duration>,
+}
+
 /// Erase plan of (opcode, size, base address, typical duration) to erase a range of memory.
-#[derive(Clone, Debug)]
-pub(crate) struct ErasePlan(pub Vec<(u8, usize, u32, Option<Duration>)>);
+///
+/// Plans are created by `Flash::plan_erase()` and may be inspected before being
+/// run using `Flash::run_erase()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErasePlan(pub(crate) Vec<(u8, usize, u32, Option<Duration>)>);
 
 impl ErasePlan {
-    pub fn new(insts: &[(usize, u8, Option<Duration>)], start: usize, length: usize) -> Self {
+    pub(crate) fn new(
+        insts: &[(usize, u8, Option<Duration>)],
+        start: usize,
+        length: usize,
+    ) -> Self {
         log::trace!("Creating erase plan, start={} length={}", start, length);
         let mut plan = Vec::new();
 
@@ -53,10 +74,37 @@ impl ErasePlan {
         ErasePlan(plan)
     }
 
-    #[cfg(feature = "std")]
+    /// Get the operations in this plan, in the order they will be run.
+    pub fn operations(&self) -> impl Iterator<Item = EraseOp> + '_ {
+        self.0
+            .iter()
+            .map(|&(opcode, size, address, duration)| EraseOp {
+                opcode,
+                size,
+                address,
+                duration,
+            })
+    }
+
+    /// Get the number of operations in this plan.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if this plan has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the total number of bytes erased by this plan.
     pub fn total_size(&self) -> usize {
         self.0.iter().map(|x| x.1).sum()
     }
+
+    /// Get the total typical duration of this plan, if known for every operation.
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.0.iter().map(|x| x.3).sum()
+    }
 }
 
 #[test]
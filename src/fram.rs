@@ -0,0 +1,188 @@
+//! FRAM and MRAM devices.
+//!
+//! Ferroelectric and magnetoresistive RAM devices, such as the Infineon (Cypress) FM25,
+//! Fujitsu MB85RS, and Everspin MR25 families, use a subset of the SPI flash instruction
+//! set but have no erase or page structure: any number of bytes may be written at any
+//! address, and writes complete within the instruction, so there is no busy status to poll.
+//! `FramFlash` drives these devices and implements `Storage` with byte-granular writes,
+//! so they may be used with the same partitions and storage layers as flash.
+
+use alloc::vec::Vec;
+
+use crate::commands::serial_memory::SerialMemoryOpCode;
+use crate::{Command, Error, FlashAccess, ReadStorage, Result, Storage};
+
+/// Driver for an FRAM or MRAM device.
+pub struct FramFlash<'a, A: FlashAccess> {
+    access: &'a mut A,
+    capacity: usize,
+    address_bytes: u8,
+}
+
+impl<'a, A: FlashAccess> FramFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a driver for a device of `capacity` bytes.
+    ///
+    /// Devices of 64kB or less use 2-byte addresses, and larger devices 3-byte addresses.
+    pub fn new(access: &'a mut A, capacity: usize) -> Self {
+        let address_bytes = if capacity <= 64 * 1024 { 2 } else { 3 };
+        FramFlash {
+            access,
+            capacity,
+            address_bytes,
+        }
+    }
+
+    /// Get the capacity in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the number of address bytes sent.
+    pub fn address_bytes(&self) -> u8 {
+        self.address_bytes
+    }
+
+    /// Set the number of address bytes sent, for devices which do not follow the usual
+    /// convention, such as 2Mbit parts which take 3-byte addresses.
+    ///
+    /// Panics if `n` is not 2 or 3.
+    pub fn set_address_bytes(&mut self, n: u8) {
+        assert!(n == 2 || n == 3, "set_address_bytes: n must be 2 or 3");
+        self.address_bytes = n;
+    }
+
+    /// Read `len` bytes of device ID, which is not supported by all devices.
+    ///
+    /// FM25V devices return six 0x7F continuation codes followed by the manufacturer ID
+    /// and two product ID bytes, while MB85RS devices return the manufacturer ID,
+    /// a continuation code, and two product ID bytes.
+    pub fn read_id(&mut self, len: usize) -> Result<Vec<u8>> {
+        self.exchange(SerialMemoryOpCode::ReadID, &[], len)
+    }
+
+    /// Read the status register, which holds the block protect and WPEN bits.
+    pub fn read_status(&mut self) -> Result<u8> {
+        Ok(self.exchange(SerialMemoryOpCode::ReadStatusRegister, &[], 1)?[0])
+    }
+
+    /// Write the status register.
+    pub fn write_status(&mut self, status: u8) -> Result<()> {
+        self.write_enable()?;
+        self.send(SerialMemoryOpCode::WriteStatusRegister, &[status])
+    }
+
+    /// Read `length` bytes starting at `address`.
+    pub fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.check_range(address, length)?;
+        let data = self.address(address);
+        self.exchange(SerialMemoryOpCode::Read, &data, length)
+    }
+
+    /// Write `data` starting at `address`, in a single transfer.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len())?;
+        log::trace!("Writing {} bytes at 0x{:06X}", data.len(), address);
+        self.write_enable()?;
+        let mut tx = self.address(address);
+        tx.extend(data);
+        self.send(SerialMemoryOpCode::Write, &tx)
+    }
+
+    /// Enter sleep mode, which is left on the next instruction, after a wake-up time
+    /// given in the datasheet.
+    pub fn sleep(&mut self) -> Result<()> {
+        self.send(SerialMemoryOpCode::Sleep, &[])
+    }
+
+    /// Set the write enable latch, which is cleared after each write.
+    fn write_enable(&mut self) -> Result<()> {
+        self.send(SerialMemoryOpCode::WriteEnable, &[])
+    }
+
+    fn address(&self, address: u32) -> Vec<u8> {
+        let bytes = address.to_be_bytes();
+        bytes[4 - self.address_bytes as usize..].to_vec()
+    }
+
+    fn check_range(&self, address: u32, length: usize) -> Result<()> {
+        if address as usize + length > self.capacity {
+            log::error!("Operation would exceed FRAM capacity");
+            return Err(Error::InvalidAddress {
+                address: (address as usize + length) as u32,
+            });
+        }
+        Ok(())
+    }
+
+    fn exchange(
+        &mut self,
+        opcode: SerialMemoryOpCode,
+        data: &[u8],
+        nbytes: usize,
+    ) -> Result<Vec<u8>> {
+        let command = Command::SerialMemory(opcode);
+        let rx = self.access.exchange(command, data, nbytes)?;
+        Ok(rx[command.len() + data.len()..].to_vec())
+    }
+
+    fn send(&mut self, opcode: SerialMemoryOpCode, data: &[u8]) -> Result<()> {
+        self.access.write(Command::SerialMemory(opcode), data)?;
+        Ok(())
+    }
+}
+
+impl<'a, A: FlashAccess> ReadStorage for FramFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        FramFlash::read(self, address, length)
+    }
+}
+
+/// FRAM has no erase, so erasing writes 0xFF bytes, and programming overwrites any data.
+impl<'a, A: FlashAccess> Storage for FramFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn erase_size(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.write(address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.write(address, &alloc::vec![0xFF; length])
+    }
+}
+
+#[test]
+fn test_fram_flash() {
+    let mut device = crate::mock::MockSerialMemory::new(8192, 2);
+    let mut fram = FramFlash::new(&mut device, 8192);
+    assert_eq!(fram.address_bytes(), 2);
+    fram.write(0x1234, &[1, 2, 3]).unwrap();
+    fram.write(0x1235, &[9]).unwrap();
+    assert_eq!(fram.read(0x1233, 5).unwrap(), [0, 1, 9, 3, 0]);
+    assert!(fram.write(0x1FFF, &[0; 2]).is_err());
+
+    // Byte-granular storage, usable through partitions.
+    let mut partition = crate::Partition::new(&mut fram, 0x1200, 0x100).unwrap();
+    partition.erase_data(0x34, 2).unwrap();
+    partition.program_data(0x35, &[0x42]).unwrap();
+    assert_eq!(fram.read(0x1233, 4).unwrap(), [0, 0xFF, 0x42, 3]);
+
+    let mut device = crate::mock::MockSerialMemory::new(256 * 1024, 3);
+    let mut fram = FramFlash::new(&mut device, 256 * 1024);
+    fram.write(0x3FFFE, &[0xAA, 0xBB]).unwrap();
+    assert_eq!(fram.read(0x3FFFD, 3).unwrap(), [0, 0xAA, 0xBB]);
+}
@@ -0,0 +1,195 @@
+//! Flash memory geometry and address arithmetic.
+//!
+//! NOR flash is programmed in pages, typically 256 bytes, erased in sectors, typically
+//! 4kB, and often also in larger blocks, typically 64kB. `Geometry` describes these
+//! sizes for a device, and converts between addresses and the `Page`, `Sector`, and
+//! `Block` containing them, so layers built on `Flash` need not repeat the alignment
+//! arithmetic themselves. `Flash::geometry()` provides the geometry of a configured device.
+
+use core::ops::Range;
+
+/// Index of a program page, counting from 0 at address 0.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Page(pub u32);
+
+/// Index of an erase sector, counting from 0 at address 0.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sector(pub u32);
+
+/// Index of an erase block, counting from 0 at address 0.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Block(pub u32);
+
+/// Unit of memory to align addresses to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// A program page.
+    Page,
+    /// The smallest erase unit.
+    Sector,
+    /// The largest erase unit.
+    Block,
+}
+
+/// Sizes of the units of memory of a device.
+///
+/// Each size must be a power of two, with pages no larger than sectors,
+/// and sectors no larger than blocks.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    /// Program page size in bytes.
+    pub page_size: usize,
+    /// Smallest erase size in bytes.
+    pub sector_size: usize,
+    /// Largest erase size in bytes, other than a chip erase.
+    pub block_size: usize,
+    /// Capacity in bytes.
+    pub capacity: usize,
+}
+
+impl Geometry {
+    /// Get the size of `unit` in bytes.
+    pub fn size(&self, unit: Unit) -> usize {
+        match unit {
+            Unit::Page => self.page_size,
+            Unit::Sector => self.sector_size,
+            Unit::Block => self.block_size,
+        }
+    }
+
+    /// Round `address` down to a multiple of the size of `unit`.
+    pub fn align_down(&self, address: u32, unit: Unit) -> u32 {
+        address & !(self.size(unit) as u32 - 1)
+    }
+
+    /// Round `address` up to a multiple of the size of `unit`.
+    pub fn align_up(&self, address: u32, unit: Unit) -> u32 {
+        self.align_down(address + (self.size(unit) as u32 - 1), unit)
+    }
+
+    /// Check if `address` is a multiple of the size of `unit`.
+    pub fn is_aligned(&self, address: u32, unit: Unit) -> bool {
+        (address as usize).is_multiple_of(self.size(unit))
+    }
+
+    /// Get the number of pages in the device.
+    pub fn pages(&self) -> u32 {
+        (self.capacity / self.page_size) as u32
+    }
+
+    /// Get the number of sectors in the device.
+    pub fn sectors(&self) -> u32 {
+        (self.capacity / self.sector_size) as u32
+    }
+
+    /// Get the number of blocks in the device.
+    pub fn blocks(&self) -> u32 {
+        (self.capacity / self.block_size) as u32
+    }
+
+    /// Get the page containing `address`.
+    pub fn page_containing(&self, address: u32) -> Page {
+        Page(address / self.page_size as u32)
+    }
+
+    /// Get the sector containing `address`.
+    pub fn sector_containing(&self, address: u32) -> Sector {
+        Sector(address / self.sector_size as u32)
+    }
+
+    /// Get the block containing `address`.
+    pub fn block_containing(&self, address: u32) -> Block {
+        Block(address / self.block_size as u32)
+    }
+
+    /// Get the addresses of `page`.
+    pub fn page_range(&self, page: Page) -> Range<u32> {
+        Self::range(page.0, self.page_size)
+    }
+
+    /// Get the addresses of `sector`.
+    pub fn sector_range(&self, sector: Sector) -> Range<u32> {
+        Self::range(sector.0, self.sector_size)
+    }
+
+    /// Get the addresses of `block`.
+    pub fn block_range(&self, block: Block) -> Range<u32> {
+        Self::range(block.0, self.block_size)
+    }
+
+    /// Iterate over the pages overlapping `range`.
+    pub fn pages_in(&self, range: Range<u32>) -> impl Iterator<Item = Page> {
+        Self::indices(range, self.page_size).map(Page)
+    }
+
+    /// Iterate over the sectors overlapping `range`.
+    pub fn sectors_in(&self, range: Range<u32>) -> impl Iterator<Item = Sector> {
+        Self::indices(range, self.sector_size).map(Sector)
+    }
+
+    /// Iterate over the blocks overlapping `range`.
+    pub fn blocks_in(&self, range: Range<u32>) -> impl Iterator<Item = Block> {
+        Self::indices(range, self.block_size).map(Block)
+    }
+
+    /// Check if `range` lies within the device.
+    pub fn contains(&self, range: &Range<u32>) -> bool {
+        range.start <= range.end && range.end as usize <= self.capacity
+    }
+
+    fn range(index: u32, size: usize) -> Range<u32> {
+        let start = index * size as u32;
+        start..start + size as u32
+    }
+
+    fn indices(range: Range<u32>, size: usize) -> Range<u32> {
+        if range.is_empty() {
+            return 0..0;
+        }
+        let size = size as u32;
+        range.start / size..(range.end - 1) / size + 1
+    }
+}
+
+#[test]
+fn test_geometry() {
+    let geometry = Geometry {
+        page_size: 256,
+        sector_size: 4096,
+        block_size: 65536,
+        capacity: 1 << 20,
+    };
+    assert_eq!(geometry.align_down(0x1234, Unit::Sector), 0x1000);
+    assert_eq!(geometry.align_up(0x1234, Unit::Sector), 0x2000);
+    assert_eq!(geometry.align_up(0x2000, Unit::Sector), 0x2000);
+    assert_eq!(geometry.align_up(0x1201, Unit::Page), 0x1300);
+    assert!(geometry.is_aligned(0x20000, Unit::Block));
+    assert!(!geometry.is_aligned(0x21000, Unit::Block));
+    assert_eq!(
+        (geometry.pages(), geometry.sectors(), geometry.blocks()),
+        (4096, 256, 16)
+    );
+
+    assert_eq!(geometry.sector_containing(0x1FFF), Sector(1));
+    assert_eq!(geometry.block_containing(0x1FFFF), Block(1));
+    assert_eq!(geometry.page_containing(0x100), Page(1));
+    assert_eq!(geometry.sector_range(Sector(2)), 0x2000..0x3000);
+    assert_eq!(geometry.block_range(Block(1)), 0x10000..0x20000);
+    assert_eq!(geometry.page_range(Page(3)), 0x300..0x400);
+
+    let sectors: alloc::vec::Vec<_> = geometry.sectors_in(0x0FFF..0x3001).collect();
+    assert_eq!(sectors, [Sector(0), Sector(1), Sector(2), Sector(3)]);
+    assert_eq!(
+        geometry
+            .pages_in(0x100..0x200)
+            .collect::<alloc::vec::Vec<_>>(),
+        [Page(1)]
+    );
+    assert_eq!(geometry.blocks_in(0x100..0x100).count(), 0);
+    assert!(geometry.contains(&(0xF_F000..0x10_0000)));
+    assert!(!geometry.contains(&(0xF_F000..0x10_0001)));
+}
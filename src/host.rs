@@ -0,0 +1,230 @@
+//! Host-side transports for flashing and inspection tools.
+//!
+//! Tools running on a PC or single-board computer reach the flash through a host SPI
+//! adapter: a Linux `spidev` device, or a USB adapter such as the FTDI FT232H in MPSSE
+//! mode. `HostSpi` describes such an adapter as full-duplex transfers made with CS
+//! asserted throughout, and `HostAccess` implements `FlashAccess` over any `HostSpi`, so
+//! the whole `Flash` API is available to tools and hardware-in-the-loop tests.
+//!
+//! With the `spidev` feature, `Spidev` implements `HostSpi` for Linux `spidev` devices.
+//! Other adapters, such as FTDI devices driven by a vendor library, implement `HostSpi`
+//! themselves.
+
+use std::io;
+
+use crate::{Command, Error, FlashAccess, Result};
+
+/// A host SPI adapter.
+pub trait HostSpi {
+    /// Clock out `buf` with CS asserted throughout, replacing its contents with the bytes
+    /// clocked in.
+    fn transfer(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Maximum number of bytes in a single transfer, if limited.
+    fn max_transfer_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// `FlashAccess` over a `HostSpi` adapter.
+pub struct HostAccess<S> {
+    spi: S,
+}
+
+impl<S: HostSpi> HostAccess<S> {
+    /// Create a transport using `spi`.
+    pub fn new(spi: S) -> Self {
+        HostAccess { spi }
+    }
+
+    /// Release the adapter.
+    pub fn release(self) -> S {
+        self.spi
+    }
+
+    /// Transfer `command`, `data`, and `nbytes` of zeros, returning the bytes clocked in.
+    fn transfer(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(command.len() + data.len() + nbytes);
+        buf.extend_from_slice(&command.to_array());
+        buf.extend_from_slice(data);
+        buf.resize(buf.len() + nbytes, 0);
+        if let Some(max) = self.spi.max_transfer_size().filter(|max| buf.len() > *max) {
+            log::error!(
+                "Transfer of {} bytes exceeds the adapter limit of {} bytes",
+                buf.len(),
+                max
+            );
+            return Err(Error::BufferTooSmall {
+                length: max,
+                required: buf.len(),
+            });
+        }
+        self.spi.transfer(&mut buf).map_err(|err| {
+            log::error!("SPI transfer failed: {}", err);
+            anyhow::Error::new(err).context("SPI transfer failed")
+        })?;
+        Ok(buf)
+    }
+}
+
+impl<S: HostSpi> FlashAccess for HostAccess<S> {
+    type Error = Error;
+
+    fn write(&mut self, command: Command, data: &[u8]) -> Result<()> {
+        self.transfer(command, data, 0)?;
+        Ok(())
+    }
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        self.transfer(command, data, nbytes)
+    }
+
+    /// Leaves room for the instruction, address, and dummy bytes within the adapter limit.
+    fn max_transfer_size(&self) -> Option<usize> {
+        self.spi
+            .max_transfer_size()
+            .map(|max| max.saturating_sub(16))
+    }
+}
+
+#[cfg(all(feature = "spidev", target_os = "linux"))]
+pub use self::spidev::Spidev;
+
+#[cfg(all(feature = "spidev", target_os = "linux"))]
+mod spidev {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::path::Path;
+
+    use super::HostSpi;
+
+    /// `struct spi_ioc_transfer` from `linux/spi/spidev.h`.
+    #[repr(C)]
+    #[derive(Default)]
+    struct SpiIocTransfer {
+        tx_buf: u64,
+        rx_buf: u64,
+        len: u32,
+        speed_hz: u32,
+        delay_usecs: u16,
+        bits_per_word: u8,
+        cs_change: u8,
+        tx_nbits: u8,
+        rx_nbits: u8,
+        word_delay_usecs: u8,
+        pad: u8,
+    }
+
+    /// Encode a write ioctl request of type 'k' for `size` bytes, as the `_IOW` macro.
+    const fn iow(nr: u32, size: usize) -> u32 {
+        (1 << 30) | ((size as u32) << 16) | ((b'k' as u32) << 8) | nr
+    }
+
+    const SPI_IOC_MESSAGE_1: u32 = iow(0, core::mem::size_of::<SpiIocTransfer>());
+    const SPI_IOC_WR_MODE: u32 = iow(1, 1);
+    const SPI_IOC_WR_BITS_PER_WORD: u32 = iow(3, 1);
+    const SPI_IOC_WR_MAX_SPEED_HZ: u32 = iow(4, 4);
+
+    /// Default transfer size limit of the spidev driver, used if its `bufsiz` module
+    /// parameter cannot be read.
+    const DEFAULT_BUFSIZ: usize = 4096;
+
+    /// A Linux `spidev` device, such as `/dev/spidev0.0`.
+    pub struct Spidev {
+        file: File,
+        speed_hz: u32,
+        bufsiz: usize,
+    }
+
+    impl Spidev {
+        /// Open the spidev device at `path`, using SPI mode 0 at `speed_hz`.
+        pub fn open(path: impl AsRef<Path>, speed_hz: u32) -> io::Result<Self> {
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            let bufsiz = std::fs::read_to_string("/sys/module/spidev/parameters/bufsiz")
+                .ok()
+                .and_then(|bufsiz| bufsiz.trim().parse().ok())
+                .unwrap_or(DEFAULT_BUFSIZ);
+            let spidev = Spidev {
+                file,
+                speed_hz,
+                bufsiz,
+            };
+            spidev.ioctl(SPI_IOC_WR_MODE, &0u8)?;
+            spidev.ioctl(SPI_IOC_WR_BITS_PER_WORD, &8u8)?;
+            spidev.ioctl(SPI_IOC_WR_MAX_SPEED_HZ, &speed_hz)?;
+            log::debug!("Opened spidev at {}Hz, {} byte transfers", speed_hz, bufsiz);
+            Ok(spidev)
+        }
+
+        fn ioctl<T>(&self, request: u32, arg: &T) -> io::Result<()> {
+            // SAFETY: each request is a write ioctl reading a `T` from `arg`.
+            let result =
+                unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg as *const T) };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl HostSpi for Spidev {
+        fn transfer(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            let transfer = SpiIocTransfer {
+                tx_buf: buf.as_ptr() as u64,
+                rx_buf: buf.as_mut_ptr() as u64,
+                len: buf.len() as u32,
+                speed_hz: self.speed_hz,
+                bits_per_word: 8,
+                ..Default::default()
+            };
+            // The kernel copies the transmit buffer before writing the receive buffer,
+            // so both may point to `buf`.
+            self.ioctl(SPI_IOC_MESSAGE_1, &transfer)
+        }
+
+        fn max_transfer_size(&self) -> Option<usize> {
+            Some(self.bufsiz)
+        }
+    }
+}
+
+#[test]
+fn test_host_access() {
+    /// Adapter recording transfers and responding to Read JEDEC ID.
+    struct TestSpi(Vec<Vec<u8>>);
+    impl HostSpi for TestSpi {
+        fn transfer(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            self.0.push(buf.to_vec());
+            if buf[0] == 0x9F {
+                buf[1..4].copy_from_slice(&[0xEF, 0x40, 0x18]);
+            } else if buf.len() > 64 {
+                return Err(io::Error::other("too long"));
+            }
+            Ok(())
+        }
+
+        fn max_transfer_size(&self) -> Option<usize> {
+            Some(128)
+        }
+    }
+
+    let mut access = HostAccess::new(TestSpi(Vec::new()));
+    assert_eq!(access.max_transfer_size(), Some(112));
+    let mut flash = crate::Flash::new(&mut access);
+    assert_eq!(flash.read_jedec_id().unwrap(), (0, 0xEF, 0x4018));
+    flash.write_enable().unwrap();
+    assert!(matches!(
+        flash.read(0, 100),
+        Err(Error::Access(err)) if err.to_string() == "SPI transfer failed"
+    ));
+    assert!(matches!(
+        access.exchange(Command::ReadData(crate::Address24Bits(0)), &[], 200),
+        Err(Error::BufferTooSmall {
+            length: 128,
+            required: 204
+        })
+    ));
+    let spi = access.release();
+    assert_eq!(spi.0[..2], [vec![0x9F, 0, 0, 0], vec![0x06]]);
+}
@@ -1,3 +1,5 @@
+use crate::{Quirks, SFDPQuadEnable};
+
 /// Store the ID read off an SPI flash memory.
 ///
 /// The manufacturer ID and (long, 16-bit) device ID are read using the 0x9F command,
@@ -5,6 +7,8 @@
 /// is stored as `manufacturer_bank`.
 ///
 /// The 64-bit unique ID is read using the 0x4B command.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub struct FlashID {
     pub manufacturer_bank: u8,
@@ -15,15 +19,20 @@ pub struct FlashID {
 }
 
 impl FlashID {
+    /// Get the manufacturer from the JEDEC ID.
+    pub fn manufacturer(&self) -> Manufacturer {
+        Manufacturer::from_jedec(self.manufacturer_bank, self.manufacturer_id)
+    }
+
     /// Look up a manufacturer name from the JEDEC ID.
     #[cfg(feature = "std")]
     pub fn manufacturer_name(&self) -> Option<&'static str> {
         let (bank, id) = (self.manufacturer_bank, self.manufacturer_id & 0x7F);
         match jep106::JEP106Code::new(bank, id).get() {
             // Winbond acquired NEXCOM and so the ID 0xEF is commonly used for Winbond memory.
-            Some(mfn) if mfn == "NEXCOM" => Some("Winbond/NEXCOM"),
+            Some("NEXCOM") => Some("Winbond/NEXCOM"),
             // GigaDevice flash doesn't use a continuation code, so 0xC8 appears as Apple Computer.
-            Some(mfn) if mfn == "Apple Computer" => Some("Apple Computer/GigaDevice Semiconductor"),
+            Some("Apple Computer") => Some("Apple Computer/GigaDevice Semiconductor"),
             Some(mfn) => Some(mfn),
             None => None,
         }
@@ -48,3 +57,119 @@ impl core::fmt::Display for FlashID {
         )
     }
 }
+
+/// Manufacturer and short (8-bit) device ID, read using `Flash::read_manufacturer_device_id()`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceID {
+    pub manufacturer: Manufacturer,
+    pub device_id: u8,
+}
+
+/// Instruction used to read a `DeviceID`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceIDMode {
+    /// Read Manufacturer/Device ID (0x90), with a 3-byte address of 0.
+    Single,
+    /// Read Manufacturer/Device ID Dual I/O (0x92), with a 3-byte address of 0 and
+    /// mode bits on two lines, and the IDs read on two lines.
+    DualIO,
+    /// Read Manufacturer/Device ID Quad I/O (0x94), with a 3-byte address of 0,
+    /// mode bits, and four dummy cycles on four lines, and the IDs read on four lines.
+    QuadIO,
+}
+
+/// Manufacturer of an SPI flash memory, identified by its JEDEC ID.
+///
+/// The JEDEC JEP106 manufacturer codes are organised in banks of 126 codes, and the bank
+/// number is given by the number of 0x7F continuation codes sent before the manufacturer
+/// ID. Some manufacturers' flash devices omit the continuation codes, and are matched by
+/// the IDs they actually send.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Manufacturer {
+    /// Winbond, which uses the NEXCOM ID 0xEF.
+    Winbond,
+    /// Macronix, 0xC2.
+    Macronix,
+    /// Micron, including Numonyx and ST devices, 0x20.
+    Micron,
+    /// Infineon, including Spansion and Cypress devices, 0x01, and Ramtron and Cypress
+    /// FRAM devices, 0xC2 in bank 6.
+    Infineon,
+    /// GigaDevice, which sends 0xC8 without its continuation codes.
+    GigaDevice,
+    /// ISSI, which sends 0x9D for flash devices.
+    Issi,
+    /// Adesto, including Atmel devices, 0x1F.
+    Adesto,
+    /// Microchip, including SST devices, 0xBF.
+    Microchip,
+    /// Fujitsu, 0x04.
+    Fujitsu,
+    /// Eon, which sends 0x1C without its continuation codes.
+    Eon,
+    /// Any other manufacturer, as the bank number and manufacturer ID.
+    Unknown(u8, u8),
+}
+
+impl Manufacturer {
+    /// Identify the manufacturer from its bank number and manufacturer ID.
+    pub fn from_jedec(bank: u8, id: u8) -> Self {
+        match (bank, id) {
+            (0, 0xEF) => Manufacturer::Winbond,
+            (0, 0xC2) => Manufacturer::Macronix,
+            (0, 0x20) => Manufacturer::Micron,
+            (0, 0x01) | (6, 0xC2) => Manufacturer::Infineon,
+            (0, 0xC8) => Manufacturer::GigaDevice,
+            (0, 0x9D) => Manufacturer::Issi,
+            (0, 0x1F) => Manufacturer::Adesto,
+            (0, 0xBF) => Manufacturer::Microchip,
+            (0, 0x04) => Manufacturer::Fujitsu,
+            (0, 0x1C) => Manufacturer::Eon,
+            (bank, id) => Manufacturer::Unknown(bank, id),
+        }
+    }
+
+    /// Get the quirks usually required by this manufacturer's devices which do not
+    /// support SFDP, which are applied by `Flash::detect()` for such devices.
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            // Early Winbond and GigaDevice parts take status register 2 as the second
+            // byte of Write Status Register 1, which holds the QE bit.
+            Manufacturer::Winbond | Manufacturer::GigaDevice => Quirks {
+                combined_status_write: true,
+                quad_enable: Some(SFDPQuadEnable::Sr2Bit1ClearedBySr1Write),
+                ..Quirks::default()
+            },
+            Manufacturer::Macronix | Manufacturer::Issi => Quirks {
+                quad_enable: Some(SFDPQuadEnable::Sr1Bit6),
+                ..Quirks::default()
+            },
+            _ => Quirks::default(),
+        }
+    }
+}
+
+#[test]
+fn test_manufacturer() {
+    assert_eq!(Manufacturer::from_jedec(0, 0xEF), Manufacturer::Winbond);
+    assert_eq!(Manufacturer::from_jedec(0, 0xC2), Manufacturer::Macronix);
+    assert_eq!(Manufacturer::from_jedec(6, 0xC2), Manufacturer::Infineon);
+    assert_eq!(
+        Manufacturer::from_jedec(2, 0xC2),
+        Manufacturer::Unknown(2, 0xC2)
+    );
+    let id = FlashID {
+        manufacturer_bank: 0,
+        manufacturer_id: 0xC8,
+        device_id_long: 0x4015,
+        device_id_short: 0x14,
+        unique_id: 0,
+    };
+    assert_eq!(id.manufacturer(), Manufacturer::GigaDevice);
+    assert!(id.manufacturer().quirks().combined_status_write);
+}
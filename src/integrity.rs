@@ -0,0 +1,125 @@
+//! Detection of sectors left inconsistent by power loss.
+//!
+//! An erase interrupted by power loss leaves a sector neither fully erased nor holding
+//! its previous contents, and reads from it may even change between reads. Such sectors
+//! cannot be detected from their contents alone, so `check_integrity()` classifies each
+//! sector of a range as erased, matching a CRC-32 expected by the application, or
+//! suspect, and returns an `IntegrityReport` listing the sectors to re-erase or reprogram.
+//! This suits layers which record CRCs of their contents, such as firmware image slots,
+//! while record-based layers like `Journal` may check just the sectors they expect to
+//! be erased.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{crc, Partition, Result, Storage};
+
+/// State of a sector found by `check_integrity()`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SectorState {
+    /// All bytes are 0xFF.
+    Erased,
+    /// The sector's CRC-32 matches the expected CRC.
+    Valid,
+    /// The sector is not erased, and either its CRC-32 `crc` differs from the expected
+    /// CRC or no CRC was expected.
+    Suspect { crc: u32 },
+}
+
+/// Result of checking one sector.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectorCheck {
+    /// Address of the sector.
+    pub address: u32,
+    /// Size of the sector in bytes.
+    pub size: usize,
+    /// State of the sector.
+    pub state: SectorState,
+}
+
+/// Report of the state of each sector in a range, created by `check_integrity()`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Checks of each sector in address order.
+    pub sectors: Vec<SectorCheck>,
+}
+
+impl IntegrityReport {
+    /// Check if no sectors are suspect.
+    pub fn is_clean(&self) -> bool {
+        self.suspect().next().is_none()
+    }
+
+    /// Iterate over the suspect sectors.
+    pub fn suspect(&self) -> impl Iterator<Item = &SectorCheck> {
+        self.sectors
+            .iter()
+            .filter(|s| matches!(s.state, SectorState::Suspect { .. }))
+    }
+}
+
+/// Check each sector of `range`, which must be aligned to the erase size of `storage`.
+///
+/// `expected` is called with the address of each sector which is not erased, and returns
+/// the CRC-32 the sector should have, or `None` if the sector is expected to be erased.
+pub fn check_integrity<F: Storage>(
+    storage: &mut F,
+    range: Range<u32>,
+    mut expected: impl FnMut(u32) -> Option<u32>,
+) -> Result<IntegrityReport> {
+    Partition::new_erasable(&mut *storage, range.start, range.len())?;
+    let size = storage.erase_size().unwrap();
+    let mut sectors = Vec::new();
+    for address in range.step_by(size) {
+        let data = storage.read(address, size)?;
+        let state = if data.iter().all(|x| *x == 0xFF) {
+            SectorState::Erased
+        } else {
+            let crc = crc::crc32(&data);
+            if expected(address) == Some(crc) {
+                SectorState::Valid
+            } else {
+                log::warn!("Sector at 0x{:08X} is suspect, CRC 0x{:08X}", address, crc);
+                SectorState::Suspect { crc }
+            }
+        };
+        sectors.push(SectorCheck {
+            address,
+            size,
+            state,
+        });
+    }
+    Ok(IntegrityReport { sectors })
+}
+
+#[test]
+fn test_check_integrity() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(flash.check_integrity(0x1000..0x1800, |_| None).is_err());
+
+    flash.program_data(0x1000, &[0x55; 4096]).unwrap();
+    // An interrupted erase of the sector at 0x2000, which left a few bits programmed.
+    flash.program_data(0x2100, &[0xEF]).unwrap();
+    flash.program_data(0x3000, &[1, 2, 3]).unwrap();
+    let good = crc::crc32(&[0x55; 4096]);
+    let report = flash
+        .check_integrity(0x0000..0x4000, |address| {
+            (address == 0x1000).then_some(good)
+        })
+        .unwrap();
+    let states: Vec<_> = report.sectors.iter().map(|s| s.state).collect();
+    assert_eq!(states[..2], [SectorState::Erased, SectorState::Valid]);
+    assert!(!report.is_clean());
+    let suspect: Vec<_> = report.suspect().map(|s| s.address).collect();
+    assert_eq!(suspect, [0x2000, 0x3000]);
+
+    flash.erase_range(0x2000, 0x2000, false).unwrap();
+    assert!(flash
+        .check_integrity(0x0000..0x4000, |_| Some(good))
+        .unwrap()
+        .is_clean());
+}
@@ -0,0 +1,270 @@
+//! Stream adapters implementing the `embedded-io` traits.
+//!
+//! `FlashReader` provides `Read` and `Seek` over a region of flash, so that
+//! parsers can consume flash contents as a stream, and `FlashWriter` provides
+//! `Write` with an internal page buffer, coalescing small writes into page programs.
+
+use alloc::vec::Vec;
+use embedded_io::{ErrorKind, ErrorType, Read, Seek, SeekFrom, Write};
+
+use crate::{Error, Flash, FlashAccess};
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::InvalidAddress { .. } | Error::BufferTooSmall { .. } => ErrorKind::InvalidInput,
+            Error::NoSpace => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Streaming reader over a region of flash.
+///
+/// Reads past the end of the region return 0 bytes, indicating end-of-file.
+pub struct FlashReader<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    start: u32,
+    length: usize,
+    position: usize,
+}
+
+impl<'f, 'a, A: FlashAccess> FlashReader<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a new reader over the `length` bytes of flash starting at `start`.
+    pub fn new(flash: &'f mut Flash<'a, A>, start: u32, length: usize) -> Self {
+        FlashReader {
+            flash,
+            start,
+            length,
+            position: 0,
+        }
+    }
+
+    /// Get the current position relative to the start of the region.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Get the length of the region.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Check if the region is empty.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+impl<'f, 'a, A: FlashAccess> ErrorType for FlashReader<'f, 'a, A> {
+    type Error = Error;
+}
+
+impl<'f, 'a, A: FlashAccess> Read for FlashReader<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let n = usize::min(buf.len(), self.length.saturating_sub(self.position));
+        if n == 0 {
+            return Ok(0);
+        }
+        let address = self.start + self.position as u32;
+        self.flash.read_into(address, &mut buf[..n])?;
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl<'f, 'a, A: FlashAccess> Seek for FlashReader<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.length as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.position as u64).checked_add_signed(offset),
+        };
+        match position {
+            Some(position) if position <= u32::MAX as u64 => {
+                self.position = position as usize;
+                Ok(position)
+            }
+            _ => {
+                log::error!("Invalid seek to {:?}", pos);
+                Err(Error::InvalidAddress {
+                    address: self.start,
+                })
+            }
+        }
+    }
+}
+
+/// Buffered sequential writer over a region of flash.
+///
+/// Written data is buffered until a page boundary is reached, so that many small writes
+/// result in a single page program operation. Any partial page is programmed on `flush()`,
+/// or when the writer is dropped.
+///
+/// The region must already be erased, for example using `Flash::erase_range()`;
+/// writes past the end of the region fail with `Error::NoSpace`.
+pub struct FlashWriter<'f, 'a, A: FlashAccess>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    flash: &'f mut Flash<'a, A>,
+    start: u32,
+    length: usize,
+    position: usize,
+    page_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<'f, 'a, A: FlashAccess> FlashWriter<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a new writer over the `length` bytes of erased flash starting at `start`.
+    ///
+    /// The page size is taken from `flash`, or assumed to be 256 bytes if not known.
+    pub fn new(flash: &'f mut Flash<'a, A>, start: u32, length: usize) -> Self {
+        let page_size = flash.page_size().unwrap_or(256);
+        FlashWriter {
+            flash,
+            start,
+            length,
+            position: 0,
+            page_size,
+            buf: Vec::with_capacity(page_size),
+        }
+    }
+
+    /// Get the number of bytes written so far, including any still buffered.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Program any buffered data.
+    fn flush_buffer(&mut self) -> Result<(), Error> {
+        if !self.buf.is_empty() {
+            let address = self.start + (self.position - self.buf.len()) as u32;
+            log::trace!("Flushing {} bytes to 0x{:08X}", self.buf.len(), address);
+            self.flash.program_data(address, &self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<'f, 'a, A: FlashAccess> ErrorType for FlashWriter<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    type Error = Error;
+}
+
+impl<'f, 'a, A: FlashAccess> Write for FlashWriter<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        } else if self.position >= self.length {
+            log::error!("FlashWriter region is full");
+            return Err(Error::NoSpace);
+        }
+        let address = self.start as usize + self.position;
+        let page_remaining = self.page_size - (address % self.page_size);
+        let n = buf
+            .len()
+            .min(page_remaining)
+            .min(self.length - self.position);
+        self.buf.extend_from_slice(&buf[..n]);
+        self.position += n;
+        if n == page_remaining {
+            self.flush_buffer()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.flush_buffer()
+    }
+}
+
+impl<'f, 'a, A: FlashAccess> Drop for FlashWriter<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_buffer() {
+            log::error!("Error flushing FlashWriter on drop: {:?}", err);
+        }
+    }
+}
+
+#[test]
+fn test_flash_reader() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let pattern: Vec<u8> = (0..64 * 1024).map(|x| (x % 251) as u8).collect();
+    mock.memory_mut().copy_from_slice(&pattern);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut reader = FlashReader::new(&mut flash, 0x1000, 100);
+    assert_eq!((reader.len(), reader.is_empty()), (100, false));
+
+    let mut buf = [0; 10];
+    assert_eq!(reader.read(&mut buf).unwrap(), 10);
+    assert_eq!(buf, pattern[0x1000..0x100A]);
+    assert_eq!(reader.seek(SeekFrom::Current(-5)).unwrap(), 5);
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, pattern[0x1005..0x100F]);
+
+    // Reads are truncated at the end of the region, then return end-of-file.
+    assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), 96);
+    assert_eq!(reader.read(&mut buf).unwrap(), 4);
+    assert_eq!(buf[..4], pattern[0x1060..0x1064]);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.seek(SeekFrom::Start(200)).unwrap(), 200);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.position(), 200);
+
+    // Seeking before the start fails, leaving the position unchanged.
+    assert!(reader.seek(SeekFrom::Current(-201)).is_err());
+    assert_eq!(reader.position(), 200);
+}
+
+#[test]
+fn test_flash_writer() {
+    let data: Vec<u8> = (0..0x200).map(|x| (x % 251) as u8).collect();
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+
+    // The region spans two pages and the boundary between the first two sectors.
+    let mut writer = FlashWriter::new(&mut flash, 0x0F80, 0x200);
+    for chunk in data.chunks(37) {
+        writer.write_all(chunk).unwrap();
+    }
+    assert_eq!(writer.position(), 0x200);
+    assert!(matches!(writer.write(&[0]), Err(Error::NoSpace)));
+    writer.flush().unwrap();
+    drop(writer);
+    drop(flash);
+    // Each page is programmed once, the last partial page when flushed.
+    assert_eq!(mock.page_programs(), 3);
+    assert_eq!(mock.memory()[0x0F80..0x1180], data[..]);
+    assert_eq!(mock.memory()[0x0F7F], 0xFF);
+    assert_eq!(mock.memory()[0x1180], 0xFF);
+
+    // A partial page is programmed when the writer is dropped.
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut writer = FlashWriter::new(&mut flash, 0x2000, 16);
+    writer.write_all(&[0x5A; 3]).unwrap();
+    drop(writer);
+    drop(flash);
+    assert_eq!(mock.memory()[0x2000..0x2004], [0x5A, 0x5A, 0x5A, 0xFF]);
+}
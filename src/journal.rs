@@ -0,0 +1,322 @@
+//! Power-loss-safe append-only record journal.
+//!
+//! `Journal` appends variable-length records, such as boot flags, event logs, or counter
+//! updates, to a log spread across several sectors. Records are written one after another
+//! within a sector, and when a sector is full the journal moves to the next one, erasing
+//! it and discarding the oldest records it held. Each record holds:
+//!
+//! | Bytes   | Content                                       |
+//! |---------|-----------------------------------------------|
+//! | 4       | Sequence number, little-endian                |
+//! | 2       | Data length `n`, little-endian                |
+//! | `n`     | Data                                          |
+//! | 4       | CRC-32 of sequence number, length, and data   |
+//!
+//! Mounting scans each sector up to its first invalid record, so a record torn by power
+//! loss while it was written is discarded along with anything after it, and appending
+//! resumes in a fresh sector if the rest of the current one is not erased. A sector whose
+//! erase was interrupted fails its CRC checks in the same way.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::crc::Crc32;
+use crate::{Error, Partition, Result, Storage};
+
+/// Sequence number of erased memory, which marks the end of the records in a sector.
+const SEQ_ERASED: u32 = 0xFFFF_FFFF;
+/// Size of a record's sequence number and length.
+const RECORD_HEADER_LEN: usize = 6;
+/// Size of a record's CRC.
+const RECORD_CRC_LEN: usize = 4;
+
+/// Valid records found in one sector.
+struct SectorScan {
+    /// Sequence number, offset, and length of each record's data.
+    records: Vec<(u32, usize, usize)>,
+    /// Offset after the last valid record.
+    end: usize,
+}
+
+/// Append-only record journal.
+pub struct Journal<'a, F: Storage> {
+    storage: &'a mut F,
+    region: Range<u32>,
+    sector_size: usize,
+    /// Sector the next record will be written to.
+    head: usize,
+    /// Offset within the head sector where the next record will be written.
+    next: usize,
+    /// Sequence number of the next record.
+    seq: u32,
+}
+
+impl<'a, F: Storage> Journal<'a, F> {
+    /// Mount a journal in `region`, which must be aligned to the erase size and contain
+    /// at least two sectors.
+    pub fn mount(storage: &'a mut F, region: Range<u32>) -> Result<Self> {
+        Partition::new_erasable(&mut *storage, region.start, region.len())?;
+        let sector_size = storage.erase_size().unwrap();
+        if region.len() < 2 * sector_size {
+            log::error!("Journal region must be at least two sectors");
+            return Err(Error::NoSpace);
+        }
+        let mut journal = Journal {
+            storage,
+            region,
+            sector_size,
+            head: 0,
+            next: 0,
+            seq: 0,
+        };
+        journal.recover()?;
+        log::debug!(
+            "Mounted journal, next sequence {}, sector {} offset {}",
+            journal.seq,
+            journal.head,
+            journal.next
+        );
+        Ok(journal)
+    }
+
+    /// Get the largest record length that can be appended.
+    pub fn max_record_len(&self) -> usize {
+        usize::min(
+            self.sector_size - RECORD_HEADER_LEN - RECORD_CRC_LEN,
+            u16::MAX as usize,
+        )
+    }
+
+    /// Get the sequence number the next appended record will have.
+    pub fn next_sequence(&self) -> u32 {
+        self.seq
+    }
+
+    /// Append a record, returning its sequence number.
+    ///
+    /// If the current sector is full, the next sector is erased first, discarding the
+    /// oldest records.
+    pub fn append(&mut self, data: &[u8]) -> Result<u32> {
+        if data.len() > self.max_record_len() {
+            log::error!("Journal record of {} bytes is too long", data.len());
+            return Err(Error::NoSpace);
+        }
+        let len = RECORD_HEADER_LEN + data.len() + RECORD_CRC_LEN;
+        if self.next + len > self.sector_size {
+            self.start_sector((self.head + 1) % self.sectors())?;
+        }
+        let seq = self.seq;
+        let mut record = Vec::with_capacity(len);
+        record.extend(seq.to_le_bytes());
+        record.extend((data.len() as u16).to_le_bytes());
+        record.extend(data);
+        let mut crc = Crc32::new();
+        crc.update(&record);
+        record.extend(crc.finish().to_le_bytes());
+        let address = self.sector_address(self.head) + self.next as u32;
+        self.storage.program_data(address, &record)?;
+        self.next += len;
+        self.seq = seq.wrapping_add(1) % SEQ_ERASED;
+        Ok(seq)
+    }
+
+    /// Read all records in the journal, oldest first, with their sequence numbers.
+    pub fn records(&mut self) -> Result<Vec<(u32, Vec<u8>)>> {
+        let mut sectors = Vec::new();
+        for sector in 0..self.sectors() {
+            let scan = self.scan_sector(sector)?;
+            if !scan.records.is_empty() {
+                sectors.push((sector, scan));
+            }
+        }
+        // Order sectors by age: the oldest sector is the one after the head.
+        let sectors_count = self.sectors();
+        let head = self.head;
+        sectors.sort_by_key(|(sector, _)| (sector + sectors_count - head - 1) % sectors_count);
+        let mut records = Vec::new();
+        for (sector, scan) in sectors {
+            let base = self.sector_address(sector);
+            for (seq, offset, len) in scan.records {
+                records.push((seq, self.storage.read(base + offset as u32, len)?));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Read the most recent record, if any.
+    pub fn latest(&mut self) -> Result<Option<(u32, Vec<u8>)>> {
+        let scan = self.scan_sector(self.head)?;
+        let base = self.sector_address(self.head);
+        match scan.records.last() {
+            Some(&(seq, offset, len)) => {
+                Ok(Some((seq, self.storage.read(base + offset as u32, len)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Erase all records, restarting the journal in its first sector.
+    ///
+    /// Sequence numbers continue from their current value.
+    pub fn clear(&mut self) -> Result<()> {
+        self.storage
+            .erase_data(self.region.start, self.region.len())?;
+        self.head = 0;
+        self.next = 0;
+        Ok(())
+    }
+
+    fn sectors(&self) -> usize {
+        self.region.len() / self.sector_size
+    }
+
+    fn sector_address(&self, sector: usize) -> u32 {
+        self.region.start + (sector * self.sector_size) as u32
+    }
+
+    /// Erase `sector` and make it the head.
+    fn start_sector(&mut self, sector: usize) -> Result<()> {
+        log::trace!("Starting journal sector {}", sector);
+        let address = self.sector_address(sector);
+        self.storage.erase_data(address, self.sector_size)?;
+        self.head = sector;
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Find the valid records of `sector`, stopping at the first invalid record.
+    fn scan_sector(&mut self, sector: usize) -> Result<SectorScan> {
+        let data = self
+            .storage
+            .read(self.sector_address(sector), self.sector_size)?;
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset + RECORD_HEADER_LEN + RECORD_CRC_LEN <= data.len() {
+            let seq = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+            if seq == SEQ_ERASED {
+                break;
+            }
+            let len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
+            let end = offset + RECORD_HEADER_LEN + len;
+            if end + RECORD_CRC_LEN > data.len() {
+                log::warn!("Journal sector {} has a truncated record", sector);
+                break;
+            }
+            let mut crc = Crc32::new();
+            crc.update(&data[offset..end]);
+            let stored = u32::from_le_bytes(data[end..end + RECORD_CRC_LEN].try_into().unwrap());
+            if crc.finish() != stored {
+                log::warn!("Discarding torn journal record in sector {}", sector);
+                break;
+            }
+            records.push((seq, offset + RECORD_HEADER_LEN, len));
+            offset = end + RECORD_CRC_LEN;
+        }
+        Ok(SectorScan {
+            records,
+            end: offset,
+        })
+    }
+
+    /// Find the sector holding the latest record and the position after it.
+    ///
+    /// Sequence numbers wrap around, so the latest record is the one which no other
+    /// record follows within half the sequence space.
+    fn recover(&mut self) -> Result<()> {
+        let mut latest: Option<(u32, usize, usize)> = None;
+        for sector in 0..self.sectors() {
+            let scan = self.scan_sector(sector)?;
+            if let Some(&(seq, _, _)) = scan.records.last() {
+                if latest.is_none_or(|(latest, _, _)| seq.wrapping_sub(latest) as i32 > 0) {
+                    latest = Some((seq, sector, scan.end));
+                }
+            }
+        }
+        let Some((seq, sector, end)) = latest else {
+            // Stale data which is not a valid record cannot be programmed over.
+            let first = self.storage.read(self.region.start, self.sector_size)?;
+            if first.iter().any(|x| *x != 0xFF) {
+                log::warn!("Journal holds no valid records, erasing its first sector");
+                self.start_sector(0)?;
+            }
+            return Ok(());
+        };
+        self.seq = seq.wrapping_add(1) % SEQ_ERASED;
+        self.head = sector;
+        self.next = end;
+        // A torn record leaves programmed bytes after the last valid record, which
+        // cannot be programmed again, so continue in the next sector.
+        let rest = self.storage.read(
+            self.sector_address(sector) + end as u32,
+            self.sector_size - end,
+        )?;
+        if rest.iter().any(|x| *x != 0xFF) {
+            log::warn!(
+                "Journal sector {} is not erased after its last record",
+                sector
+            );
+            self.next = self.sector_size;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_journal() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(Journal::mount(&mut flash, 0x1000..0x2000).is_err());
+    {
+        let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+        assert_eq!(journal.latest().unwrap(), None);
+        // Four records of about 1000 bytes fit in each sector, so this wraps around the region.
+        for n in 0..13u32 {
+            assert_eq!(
+                journal
+                    .append(&alloc::vec![n as u8; 990 - n as usize])
+                    .unwrap(),
+                n
+            );
+        }
+        assert!(journal.append(&[0; 5000]).is_err());
+        let seqs: Vec<_> = journal.records().unwrap().iter().map(|r| r.0).collect();
+        assert_eq!(seqs, (4..13).collect::<Vec<_>>());
+    }
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    assert_eq!(journal.next_sequence(), 13);
+    let (seq, data) = journal.latest().unwrap().unwrap();
+    assert_eq!((seq, data.len(), data[0]), (12, 978, 12));
+
+    // Tear the next record, as if power was lost while it was programmed.
+    let torn = 0x1000 + 988;
+    flash
+        .program_data(torn, &[13, 0, 0, 0, 0xFF, 0x00, 1, 2])
+        .unwrap();
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    let seqs: Vec<_> = journal.records().unwrap().iter().map(|r| r.0).collect();
+    assert_eq!(seqs, (4..13).collect::<Vec<_>>());
+    assert_eq!(journal.append(b"after").unwrap(), 13);
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    assert_eq!(journal.latest().unwrap(), Some((13, b"after".to_vec())));
+    assert_eq!(journal.records().unwrap()[0].0, 8);
+    journal.clear().unwrap();
+    assert_eq!(journal.records().unwrap(), []);
+
+    // A region holding stale data but no valid records is erased before appending.
+    flash.program_data(0x1000, &[0x12; 64]).unwrap();
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    journal.append(b"fresh").unwrap();
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    assert_eq!(journal.records().unwrap(), [(0, b"fresh".to_vec())]);
+
+    // The latest record is found across sequence number wraparound.
+    journal.clear().unwrap();
+    journal.seq = SEQ_ERASED - 2;
+    for _ in 0..6 {
+        journal.append(&[0; 1500]).unwrap();
+    }
+    let mut journal = Journal::mount(&mut flash, 0x1000..0x4000).unwrap();
+    assert_eq!(journal.next_sequence(), 4);
+    let seqs: Vec<_> = journal.records().unwrap().iter().map(|r| r.0).collect();
+    assert_eq!(seqs, [SEQ_ERASED - 2, SEQ_ERASED - 1, 0, 1, 2, 3]);
+}
@@ -0,0 +1,304 @@
+//! Small key-value store using two sectors.
+//!
+//! `KvStore` maps byte-string keys, such as configuration parameter names, to small
+//! values, without the overhead of a filesystem. Each update is appended to the active
+//! sector as a record:
+//!
+//! | Bytes   | Content                                               |
+//! |---------|-------------------------------------------------------|
+//! | 1       | Key length `k`, from 1 to 254                         |
+//! | 2       | Value length `n`, little-endian, or 0xFFFF if removed |
+//! | `k`     | Key                                                   |
+//! | `n`     | Value                                                 |
+//! | 4       | CRC-32 of the lengths, key, and value                 |
+//!
+//! When the active sector is full, the latest value of each key is copied to the other
+//! sector, which then becomes active, and the old sector is erased. A header at the start
+//! of each sector tracks this swap, so an interrupted compaction is completed or
+//! discarded when mounting, without losing the stored keys.
+//!
+//! The store works with any `Storage`, such as a `Flash`, a `Partition`, or a `MockFlash`.
+
+use alloc::vec::Vec;
+
+use crate::crc::Crc32;
+use crate::sector_pair::{SectorPair, HEADER_LEN};
+use crate::{Error, Result, Storage};
+
+/// Size of a record's key and value length fields.
+const RECORD_HEADER_LEN: usize = 3;
+/// Size of a record's CRC.
+const RECORD_CRC_LEN: usize = 4;
+/// Key length of erased memory, which marks the end of the records in a sector.
+const KEY_ERASED: u8 = 0xFF;
+/// Value length of a record marking its key as removed.
+const VALUE_REMOVED: u16 = 0xFFFF;
+/// Longest key length.
+pub const MAX_KEY_LEN: usize = 254;
+
+/// A valid record found in the active sector.
+struct Record {
+    key: Vec<u8>,
+    /// Offset of the record in the sector.
+    offset: usize,
+    /// Value length, or `None` if the key was removed.
+    value_len: Option<usize>,
+}
+
+impl Record {
+    fn len(&self) -> usize {
+        RECORD_HEADER_LEN + self.key.len() + self.value_len.unwrap_or(0) + RECORD_CRC_LEN
+    }
+}
+
+/// Key-value store in two sectors.
+pub struct KvStore<'a, F: Storage> {
+    storage: &'a mut F,
+    sectors: SectorPair,
+    sector_size: usize,
+    /// Offset within the active sector where the next record will be written.
+    next: usize,
+}
+
+impl<'a, F: Storage> KvStore<'a, F> {
+    /// Mount a key-value store using the two sectors at `sector0` and `sector1`.
+    ///
+    /// The erase size must be known, and both sectors must be aligned to it.
+    /// If neither sector holds a valid store, both are erased and a new store created.
+    pub fn mount(storage: &'a mut F, sector0: u32, sector1: u32) -> Result<Self> {
+        let mut sectors = SectorPair::new(&*storage, sector0, sector1)?;
+        if !sectors.mount(storage)? {
+            log::info!("No valid key-value store found, formatted");
+        }
+        let mut store = KvStore {
+            storage,
+            sector_size: sectors.sector_size(),
+            sectors,
+            next: HEADER_LEN,
+        };
+        store.next = store.scan()?.1;
+        Ok(store)
+    }
+
+    /// Remove all keys.
+    pub fn format(&mut self) -> Result<()> {
+        self.next = HEADER_LEN;
+        self.sectors.format(self.storage)
+    }
+
+    /// Get the value of `key`, or `None` if it is not set.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.find(key)? {
+            Some(Record {
+                offset,
+                value_len: Some(len),
+                ..
+            }) => {
+                let address = self.value_address(offset, key.len());
+                Ok(Some(self.storage.read(address, len)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Set `key` to `value`.
+    ///
+    /// Keys must be from 1 to `MAX_KEY_LEN` bytes long, and values shorter than 65535
+    /// bytes, otherwise `InvalidArgument` is returned. If the key already holds `value`,
+    /// nothing is written.
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if value.len() >= VALUE_REMOVED as usize {
+            log::error!("Value of {} bytes is too long", value.len());
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(Record {
+            offset,
+            value_len: Some(len),
+            ..
+        }) = self.find(key)?
+        {
+            let address = self.value_address(offset, key.len());
+            if len == value.len() && self.storage.read(address, len)? == value {
+                log::trace!("Key {:02X?} unchanged", key);
+                return Ok(());
+            }
+        }
+        self.append(key, Some(value))
+    }
+
+    /// Remove `key`, returning whether it was set.
+    pub fn remove(&mut self, key: &[u8]) -> Result<bool> {
+        match self.find(key)? {
+            Some(Record {
+                value_len: Some(_), ..
+            }) => {
+                self.append(key, None)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Get all keys which are set.
+    pub fn keys(&mut self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .latest()?
+            .into_iter()
+            .filter(|r| r.value_len.is_some())
+            .map(|r| r.key)
+            .collect())
+    }
+
+    /// Copy the latest value of each key into the other sector, and make it active.
+    pub fn compact(&mut self) -> Result<()> {
+        log::debug!("Compacting key-value store");
+        let latest = self.latest()?;
+        self.next = self.sectors.swap(self.storage, |storage, from, to| {
+            let mut next = HEADER_LEN;
+            for record in latest.iter().filter(|r| r.value_len.is_some()) {
+                let data = storage.read(from + record.offset as u32, record.len())?;
+                storage.program_data(to + next as u32, &data)?;
+                next += record.len();
+            }
+            Ok(next)
+        })?;
+        Ok(())
+    }
+
+    /// Append a record setting `key` to `value`, or removing it if `value` is `None`,
+    /// compacting first if the active sector is full.
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            log::error!("Invalid key length {}", key.len());
+            return Err(Error::InvalidArgument);
+        }
+        let value_len = value.map_or(0, |v| v.len());
+        let record_len = RECORD_HEADER_LEN + key.len() + value_len + RECORD_CRC_LEN;
+        if HEADER_LEN + record_len > self.sector_size {
+            return Err(Error::NoSpace);
+        }
+        if self.next + record_len > self.sector_size {
+            self.compact()?;
+            if self.next + record_len > self.sector_size {
+                log::error!("Key-value store is full");
+                return Err(Error::NoSpace);
+            }
+        }
+        let mut record = Vec::with_capacity(record_len);
+        record.push(key.len() as u8);
+        record.extend(
+            value
+                .map_or(VALUE_REMOVED, |v| v.len() as u16)
+                .to_le_bytes(),
+        );
+        record.extend(key);
+        record.extend(value.unwrap_or(&[]));
+        let mut crc = Crc32::new();
+        crc.update(&record);
+        record.extend(crc.finish().to_le_bytes());
+        let address = self.sectors.active_address() + self.next as u32;
+        self.storage.program_data(address, &record)?;
+        self.next += record_len;
+        Ok(())
+    }
+
+    /// Find the latest record for `key`.
+    fn find(&mut self, key: &[u8]) -> Result<Option<Record>> {
+        Ok(self.scan()?.0.into_iter().rev().find(|r| r.key == key))
+    }
+
+    /// Get the latest record for each key, in the order the keys were first written.
+    fn latest(&mut self) -> Result<Vec<Record>> {
+        let mut latest: Vec<Record> = Vec::new();
+        for record in self.scan()?.0 {
+            match latest.iter_mut().find(|r| r.key == record.key) {
+                Some(entry) => *entry = record,
+                None => latest.push(record),
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Read the valid records of the active sector, and the offset after them.
+    ///
+    /// If the sector ends with a corrupted record, such as one torn by power loss, the
+    /// offset is the end of the sector, so the next write compacts the valid records
+    /// into the other sector.
+    fn scan(&mut self) -> Result<(Vec<Record>, usize)> {
+        let data = self
+            .storage
+            .read(self.sectors.active_address(), self.sector_size)?;
+        let mut records = Vec::new();
+        let mut offset = HEADER_LEN;
+        while offset + RECORD_HEADER_LEN <= self.sector_size {
+            let key_len = data[offset] as usize;
+            let value_len = u16::from_le_bytes([data[offset + 1], data[offset + 2]]);
+            if key_len == KEY_ERASED as usize && value_len == 0xFFFF {
+                return Ok((records, offset));
+            }
+            let value_len = (value_len != VALUE_REMOVED).then_some(value_len as usize);
+            let end = offset + RECORD_HEADER_LEN + key_len + value_len.unwrap_or(0);
+            if key_len == 0 || end + RECORD_CRC_LEN > self.sector_size {
+                break;
+            }
+            let mut crc = Crc32::new();
+            crc.update(&data[offset..end]);
+            if crc.finish() != u32::from_le_bytes(data[end..end + 4].try_into().unwrap()) {
+                break;
+            }
+            let key_start = offset + RECORD_HEADER_LEN;
+            records.push(Record {
+                key: data[key_start..key_start + key_len].to_vec(),
+                offset,
+                value_len,
+            });
+            offset = end + RECORD_CRC_LEN;
+        }
+        if offset + RECORD_HEADER_LEN <= self.sector_size {
+            log::warn!("Corrupt key-value store record at offset {}", offset);
+        }
+        Ok((records, self.sector_size))
+    }
+
+    fn value_address(&self, offset: usize, key_len: usize) -> u32 {
+        self.sectors.active_address() + (offset + RECORD_HEADER_LEN + key_len) as u32
+    }
+}
+
+#[test]
+fn test_kvstore() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    {
+        let mut store = KvStore::mount(&mut mock, 0x1000, 0x2000).unwrap();
+        assert_eq!(store.get(b"name").unwrap(), None);
+        store.set(b"name", b"sensor-1").unwrap();
+        store.set(b"rate", &[10]).unwrap();
+        store.set(b"name", b"sensor-2").unwrap();
+        assert!(store.remove(b"rate").unwrap());
+        assert!(!store.remove(b"rate").unwrap());
+        assert!(matches!(store.set(b"", b"x"), Err(Error::InvalidArgument)));
+    }
+    // Mount the same memory through a flash driver.
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut store = KvStore::mount(&mut flash, 0x1000, 0x2000).unwrap();
+    assert_eq!(store.get(b"name").unwrap(), Some(b"sensor-2".to_vec()));
+    assert_eq!(store.get(b"rate").unwrap(), None);
+    // Fill the sector to force compactions, which keep only the latest values.
+    for n in 0..100u32 {
+        store.set(b"counter", &[n as u8; 100]).unwrap();
+    }
+    store.set(b"rate", &[20]).unwrap();
+    let mut store = KvStore::mount(&mut flash, 0x1000, 0x2000).unwrap();
+    assert_eq!(store.keys().unwrap(), [&b"name"[..], b"counter", b"rate"]);
+    assert_eq!(store.get(b"counter").unwrap(), Some(alloc::vec![99; 100]));
+    assert_eq!(store.get(b"rate").unwrap(), Some(alloc::vec![20]));
+
+    // Lose power while erasing the old sector after a compaction.
+    mock.set_power_loss_during_erase(Some(1));
+    let mut store = KvStore::mount(&mut mock, 0x1000, 0x2000).unwrap();
+    assert!(store.compact().is_err());
+    mock.restore_power();
+    let mut store = KvStore::mount(&mut mock, 0x1000, 0x2000).unwrap();
+    assert_eq!(store.keys().unwrap(), [&b"name"[..], b"counter", b"rate"]);
+    assert_eq!(store.get(b"counter").unwrap(), Some(alloc::vec![99; 100]));
+}
@@ -0,0 +1,97 @@
+//! Selection of read dummy cycles for the SPI clock frequency.
+//!
+//! Fast read instructions need enough dummy cycles for the device to fetch data, so
+//! the cycles required grow with the clock frequency. SFDP gives the dummy cycles for
+//! the maximum frequency, which wastes cycles on slower clocks, and some devices
+//! instead use a configurable latency, selected by a latency code in a register.
+//! A `LatencyTable` from the datasheet, set using `Flash::set_latency_table()`, lets
+//! `Flash::set_frequency()` pick the fewest dummy cycles valid at the clock frequency,
+//! and write the matching latency code to the device.
+
+use crate::StatusWriteMode;
+
+/// Dummy cycles valid up to a clock frequency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LatencyEntry {
+    /// Highest clock frequency in Hz at which these dummy cycles may be used.
+    pub max_hz: u32,
+    /// Number of dummy cycles.
+    pub dummy_cycles: u8,
+    /// Latency code written to the device to select these dummy cycles,
+    /// ignored if the device uses fixed dummy cycles.
+    pub code: u8,
+}
+
+/// How the device is configured for the selected dummy cycles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LatencyMethod {
+    /// The device reads with any number of dummy cycles of at least those required,
+    /// so only the driver's read parameters change.
+    Fixed,
+    /// The code is written as P5-P4 using Set Read Parameters (0xC0), as used for
+    /// QPI reads by Winbond and ISSI devices.
+    SetReadParameters,
+    /// The code is written to the LC bits of Spansion configuration register 1.
+    ConfigRegister1(StatusWriteMode),
+}
+
+/// Dummy cycles of the configured read instruction at each clock frequency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LatencyTable {
+    /// Entries in order of increasing `max_hz`.
+    pub entries: &'static [LatencyEntry],
+    /// How the device is configured for the selected entry.
+    pub method: LatencyMethod,
+}
+
+impl LatencyTable {
+    /// Get the entry with the lowest `max_hz` supporting `hz`.
+    pub fn select(&self, hz: u32) -> Option<&LatencyEntry> {
+        self.entries.iter().find(|entry| entry.max_hz >= hz)
+    }
+}
+
+#[test]
+fn test_latency_table() {
+    use crate::Error;
+
+    const ENTRIES: &[LatencyEntry] = &[
+        LatencyEntry {
+            max_hz: 50_000_000,
+            dummy_cycles: 4,
+            code: 0,
+        },
+        LatencyEntry {
+            max_hz: 80_000_000,
+            dummy_cycles: 6,
+            code: 1,
+        },
+        LatencyEntry {
+            max_hz: 104_000_000,
+            dummy_cycles: 8,
+            code: 2,
+        },
+    ];
+    let table = LatencyTable {
+        entries: ENTRIES,
+        method: LatencyMethod::ConfigRegister1(StatusWriteMode::NonVolatile),
+    };
+    assert_eq!(table.select(80_000_000).unwrap().code, 1);
+    assert!(table.select(133_000_000).is_none());
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024).with_jedec_id(0x01, 0x0216);
+    let mut flash = crate::Flash::new(&mut mock);
+    flash.set_frequency(133_000_000).unwrap();
+    assert_eq!(flash.frequency(), Some(133_000_000));
+    assert_eq!(flash.read_parameters().dummy_cycles, 8);
+
+    flash.set_latency_table(Some(table));
+    flash.set_frequency(60_000_000).unwrap();
+    assert_eq!(flash.read_parameters().dummy_cycles, 6);
+    assert_eq!(flash.read_config1().unwrap().get_latency_code(), 1);
+    assert!(matches!(
+        flash.set_frequency(133_000_000),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert_eq!(flash.frequency(), Some(60_000_000));
+}
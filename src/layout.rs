@@ -0,0 +1,151 @@
+//! Erase layout of memory with non-uniform sectors.
+//!
+//! Some devices, such as Spansion hybrid-sector parts, have small parameter sectors at
+//! the bottom or top of memory and larger sectors elsewhere, and only allow each erase
+//! instruction in some regions. A `Layout` lists each region with the erase instructions
+//! it supports, so that `Flash::erase_range()` only uses valid instructions in each region.
+//!
+//! The layout is read from the SFDP Sector Map Parameter Table by `Flash::read_params()`
+//! if present, or may be set using `Flash::set_layout()`.
+
+use alloc::vec::Vec;
+
+use crate::{Error, Result, SFDPEraseInst, SFDPSectorRegion};
+
+/// A region of memory with uniform erase granularity.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EraseRegion {
+    /// Start address of the region.
+    pub start: u32,
+    /// Size of the region in bytes.
+    pub size: usize,
+    /// Erase instructions which may be used in this region.
+    pub erase_insts: Vec<SFDPEraseInst>,
+}
+
+impl EraseRegion {
+    /// Get the smallest erase size in this region, if any erase instruction is supported.
+    pub fn erase_size(&self) -> Option<usize> {
+        self.erase_insts.iter().map(|inst| inst.size as usize).min()
+    }
+
+    fn end(&self) -> usize {
+        self.start as usize + self.size
+    }
+}
+
+/// Erase layout of the whole memory, as a list of consecutive regions.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    regions: Vec<EraseRegion>,
+}
+
+impl Layout {
+    /// Create a layout from `regions`, which must be consecutive starting from address 0.
+    pub fn new(regions: Vec<EraseRegion>) -> Result<Self> {
+        let mut end = 0;
+        for region in regions.iter() {
+            if region.start as usize != end {
+                log::error!("Layout region at 0x{:08X} is not contiguous", region.start);
+                return Err(Error::InvalidAddress {
+                    address: region.start,
+                });
+            }
+            end = region.end();
+        }
+        Ok(Layout { regions })
+    }
+
+    /// Create a layout from SFDP sector map `regions`, using the erase instructions
+    /// `erase_insts` indexed by each region's erase types.
+    pub fn from_sfdp(
+        regions: &[SFDPSectorRegion],
+        erase_insts: &[Option<SFDPEraseInst>; 4],
+    ) -> Result<Self> {
+        let mut start = 0;
+        let regions = regions
+            .iter()
+            .map(|region| {
+                let erase_insts = erase_insts
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| region.erase_types & (1 << i) != 0)
+                    .filter_map(|(_, inst)| *inst)
+                    .collect();
+                let erase_region = EraseRegion {
+                    start,
+                    size: region.size as usize,
+                    erase_insts,
+                };
+                start += region.size;
+                erase_region
+            })
+            .collect();
+        Self::new(regions)
+    }
+
+    /// Get all regions, in order of increasing address.
+    pub fn regions(&self) -> &[EraseRegion] {
+        &self.regions
+    }
+
+    /// Get the region containing `address`, if any.
+    pub fn region_at(&self, address: u32) -> Option<&EraseRegion> {
+        self.regions
+            .iter()
+            .find(|r| r.start <= address && (address as usize) < r.end())
+    }
+
+    /// Get the regions overlapping `length` bytes from `address`, each with the start
+    /// and length of the overlapping part.
+    pub(crate) fn overlapping(
+        &self,
+        address: u32,
+        length: usize,
+    ) -> impl Iterator<Item = (&EraseRegion, usize, usize)> {
+        let start = address as usize;
+        let end = start + length;
+        self.regions.iter().filter_map(move |region| {
+            let seg_start = usize::max(start, region.start as usize);
+            let seg_end = usize::min(end, region.end());
+            (seg_start < seg_end).then(|| (region, seg_start, seg_end - seg_start))
+        })
+    }
+}
+
+#[test]
+fn test_layout() {
+    let inst = |opcode, size| SFDPEraseInst {
+        opcode,
+        size,
+        time_typ: None,
+        time_max: None,
+    };
+    let erase_insts = [Some(inst(0x20, 4096)), None, Some(inst(0xD8, 65536)), None];
+    let regions = [
+        SFDPSectorRegion {
+            size: 32 * 1024,
+            erase_types: 0b0001,
+        },
+        SFDPSectorRegion {
+            size: 32 * 1024,
+            erase_types: 0b0101,
+        },
+        SFDPSectorRegion {
+            size: 192 * 1024,
+            erase_types: 0b0100,
+        },
+    ];
+    let layout = Layout::from_sfdp(&regions, &erase_insts).unwrap();
+    assert_eq!(layout.regions().len(), 3);
+    assert_eq!(layout.region_at(0x1000).unwrap().erase_size(), Some(4096));
+    assert_eq!(layout.region_at(0x10000).unwrap().erase_size(), Some(65536));
+    assert!(layout.region_at(0x40000).is_none());
+    let overlapping: Vec<_> = layout
+        .overlapping(0x7000, 0x2000)
+        .map(|(r, start, len)| (r.start, start, len))
+        .collect();
+    assert_eq!(overlapping, [(0, 0x7000, 0x1000), (0x8000, 0x8000, 0x1000)]);
+}
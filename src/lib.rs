@@ -18,22 +18,138 @@ use indicatif::{ProgressBar, ProgressStyle};
 #[cfg(feature = "std")]
 use std::time::Instant;
 
+pub mod address_space;
+pub mod board;
+pub mod buffered;
+pub mod builder;
+pub mod cache;
+pub mod composite;
+pub mod conformance;
+pub mod crc;
+pub mod dataflash;
+pub mod decoder;
+pub mod devices;
+pub mod dirty;
+pub mod dual_bank;
+pub mod eeprom;
+pub mod emulated;
 pub mod erase_plan;
+pub mod fram;
+pub mod geometry;
+#[cfg(feature = "std")]
+pub mod host;
 pub mod id;
+pub mod integrity;
+#[cfg(feature = "embedded-io")]
+pub mod io;
+pub mod journal;
+#[cfg(feature = "kvstore")]
+pub mod kvstore;
+pub mod latency;
+pub mod layout;
+#[cfg(feature = "littlefs2")]
+pub mod littlefs;
+pub mod mock;
+pub mod nand;
+pub mod opcode_map;
+pub mod progress;
+pub mod protection;
+pub mod provisioning;
+pub mod quirks;
+pub mod read_only;
+pub mod registers;
+pub mod retry;
+#[cfg(feature = "rpmc")]
+pub mod rpmc;
+pub mod security;
+pub mod self_test;
+pub mod serial_eeprom;
+pub mod session;
 pub mod sfdp;
+pub mod shared;
+#[cfg(feature = "embedded-hal")]
+pub mod spi_bus;
 pub mod sreg;
+pub mod stats;
+pub mod storage;
+pub mod storage_stack;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod transaction;
+pub mod wait;
+#[cfg(feature = "wear")]
+pub mod wear;
+pub mod xip;
 
 mod commands;
-pub use commands::{spansion::Command, Address24Bits};
+mod sector_pair;
+pub use commands::dataflash::DataFlashOpCode;
+pub use commands::nand::NandOpCode;
+pub use commands::octal::{CommandExtension, OctalFamily, OctalMode, OctalOpCode};
+pub use commands::serial_memory::SerialMemoryOpCode;
+pub use commands::{
+    spansion::{Command, CommandBytes, CommandOpCode},
+    spec::{CommandSpec, RawCommand},
+    with_data::CommandWithData,
+    Address24Bits, Address32Bits,
+};
 
-pub use id::FlashID;
-pub use sfdp::{FlashParams, SFDPAddressBytes, SFDPEraseInst, SFDPStatus1Volatility, SFDPTiming};
-pub use sreg::{StatusRegister1, StatusRegister2, StatusRegister3};
+pub use address_space::AddressSpace;
+pub use board::{BoardConfig, ControlPin};
+pub use buffered::BufferedFlash;
+pub use builder::FlashBuilder;
+pub use cache::CachedFlash;
+pub use composite::{Concat, Striped};
+pub use dataflash::{DataFlash, DataFlashGeometry};
+pub use dirty::DirtyMap;
+pub use dual_bank::DualBank;
+pub use eeprom::Eeprom;
+pub use emulated::EmulatedByteWrite;
+pub use erase_plan::{EraseOp, ErasePlan};
+pub use fram::FramFlash;
+pub use geometry::{Block, Geometry, Page, Sector};
+pub use id::{DeviceID, DeviceIDMode, FlashID, Manufacturer};
+pub use integrity::{IntegrityReport, SectorCheck, SectorState};
+pub use journal::Journal;
+pub use latency::{LatencyEntry, LatencyMethod, LatencyTable};
+pub use layout::{EraseRegion, Layout};
+pub use nand::{EccReporting, EccStatus, NandFlash, NandGeometry};
+pub use opcode_map::OpcodeMap;
+pub use progress::{Progress, ProgressSink};
+pub use protection::ProtectionScheme;
+pub use provisioning::{Provisioning, ProvisioningRecord};
+pub use quirks::Quirks;
+pub use read_only::ReadOnlyFlash;
+pub use registers::{Register, RegisterFile};
+pub use retry::RetryPolicy;
+pub use security::Security;
+pub use self_test::{self_test, PatternFailure, SelfTestReport, TestOutcome};
+pub use serial_eeprom::SerialEeprom;
+pub use session::{ProgrammingSession, SessionState};
+pub use sfdp::{
+    FlashParams, SFDPAddressBytes, SFDPBusyPolling, SFDPConfigDetect, SFDPEraseInst, SFDPFastRead,
+    SFDPFourByteInstructions, SFDPParameterHeader, SFDPQuadEnable, SFDPSectorMap, SFDPSectorRegion,
+    SFDPStatus1Volatility, SFDPTiming,
+};
+#[cfg(feature = "embassy-sync")]
+pub use shared::AsyncSharedStorage;
+pub use shared::{Lock, SharedStorage};
+pub use sreg::{
+    AddressExtension, BankRegister, ConfigRegister1, FlagStatusRegister, StatusRegister1,
+    StatusRegister2, StatusRegister3, StatusWriteMode,
+};
+pub use stats::{SectorStats, StatsFlash};
+pub use storage::{Partition, ReadStorage, Storage};
+pub use transaction::{Direction, Protocol, ReadMode, ReadParameters, Transaction};
+pub use wait::{Timings, WaitReady};
+pub use xip::XipConfig;
 
-use erase_plan::ErasePlan;
+use commands::spansion::four_byte_opcode;
+use protection::IndividualBlockLocks;
 use sfdp::SFDPHeader;
 
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug)]
 pub enum Error {
     #[cfg_attr(feature = "std", error("Mismatch during flash readback verification."))]
@@ -49,14 +165,124 @@ pub enum Error {
         error("Address out of range for memory: 0x{address:08X}.")
     )]
     InvalidAddress { address: u32 },
+    #[cfg_attr(
+        feature = "std",
+        error("Address 0x{address:08X} is not aligned to {required} bytes.")
+    )]
+    Misaligned { address: u32, required: u32 },
     #[cfg_attr(feature = "std", error("No supported reset instruction is available."))]
     NoResetInstruction,
     #[cfg_attr(feature = "std", error("No erase instruction has been specified."))]
     NoEraseInstruction,
+    #[cfg_attr(
+        feature = "std",
+        error("No command is known for opcode 0x{opcode:02X}.")
+    )]
+    UnknownOpcode { opcode: u8 },
+    #[cfg_attr(
+        feature = "std",
+        error("Buffer too small: got {length} bytes, need {required} bytes.")
+    )]
+    BufferTooSmall { length: usize, required: usize },
+    #[cfg_attr(feature = "std", error("Invalid argument."))]
+    InvalidArgument,
+    #[cfg_attr(
+        feature = "std",
+        error("No address is known for register {register:?}.")
+    )]
+    NoRegisterAddress { register: Register },
+    #[cfg_attr(feature = "std", error("Not enough free space in storage."))]
+    NoSpace,
+    #[cfg_attr(
+        feature = "std",
+        error("The configured read instruction does not support continuous read mode.")
+    )]
+    ContinuousReadUnsupported,
+    #[cfg_attr(
+        feature = "std",
+        error("The operation is not supported by the board configuration.")
+    )]
+    BoardUnsupported,
+    #[cfg_attr(feature = "std", error("The quad enable bit location is not known."))]
+    NoQuadEnable,
+    #[cfg_attr(
+        feature = "std",
+        error("Unsupported number of dummy cycles: {cycles}.")
+    )]
+    InvalidDummyCycles { cycles: u8 },
+    #[cfg_attr(feature = "std", error("Unsupported number of data lines: {lines}."))]
+    InvalidLines { lines: u8 },
+    #[cfg_attr(
+        feature = "std",
+        error("Instruction 0x{opcode:02X} is not confirmed supported by the device.")
+    )]
+    Unsupported { opcode: u8 },
+    #[cfg_attr(
+        feature = "std",
+        error("Checksum mismatch in data at 0x{address:08X}.")
+    )]
+    ChecksumMismatch { address: u32 },
+    #[cfg_attr(
+        feature = "std",
+        error("Inconsistent device parameters in configuration.")
+    )]
+    InvalidConfiguration,
+    #[cfg_attr(
+        feature = "std",
+        error("Device detection failed while {stage}: {source}")
+    )]
+    Detect {
+        stage: DetectStage,
+        #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+        source: alloc::boxed::Box<Error>,
+    },
+    #[cfg_attr(
+        feature = "std",
+        error("Device 0x{manufacturer_id:02X}/0x{device_id:04X} has no SFDP and is not known.")
+    )]
+    UnknownDevice { manufacturer_id: u8, device_id: u16 },
+    #[cfg_attr(
+        feature = "std",
+        error("RPMC command failed with extended status 0x{status:02X}.")
+    )]
+    Rpmc { status: u8 },
+    #[cfg_attr(
+        feature = "std",
+        error("The device reported a program failure at 0x{address:08X}.")
+    )]
+    ProgramFailed { address: u32 },
+    #[cfg_attr(
+        feature = "std",
+        error("The device reported an erase failure at 0x{address:08X}.")
+    )]
+    EraseFailed { address: u32 },
+    #[cfg_attr(
+        feature = "std",
+        error("The device rejected an operation on protected memory at 0x{address:08X}.")
+    )]
+    RegionProtected { address: u32 },
+    #[cfg_attr(feature = "std", error("Timed out waiting for the device."))]
+    Timeout,
+    #[cfg_attr(feature = "std", error("Error driving a control pin."))]
+    Pin,
+    #[cfg_attr(
+        feature = "std",
+        error("Protection bits are locked until the next power cycle or reset.")
+    )]
+    ProtectionLocked,
+    #[cfg_attr(
+        feature = "std",
+        error("The device rejected the password, and must be reset before retrying.")
+    )]
+    PasswordRejected,
 
     #[cfg(feature = "std")]
     #[error(transparent)]
-    Access(#[from] anyhow::Error),
+    Access(
+        #[from]
+        #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+        anyhow::Error,
+    ),
 
     #[cfg(not(feature = "std"))]
     Access,
@@ -64,11 +290,47 @@ pub enum Error {
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Stages of `Flash::detect()`, reported in `Error::Detect`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DetectStage {
+    /// Waking the device from power-down and leaving QPI and continuous read modes.
+    Wake,
+    /// Waiting for the device to respond after power-up.
+    PowerUp,
+    /// Reading the device ID.
+    ReadId,
+    /// Reading the SFDP parameters.
+    ReadParams,
+}
+
+impl core::fmt::Display for DetectStage {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(match self {
+            DetectStage::Wake => "waking the device",
+            DetectStage::PowerUp => "waiting for the device to power up",
+            DetectStage::ReadId => "reading the device ID",
+            DetectStage::ReadParams => "reading SFDP parameters",
+        })
+    }
+}
+
+/// Work done by `Flash::update_image()`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageUpdate {
+    /// Number of sectors erased.
+    pub sectors_erased: usize,
+    /// Number of pages, or parts of pages, programmed.
+    pub pages_programmed: usize,
+}
+
 /// Trait for objects which provide access to SPI flash.
 ///
-/// Providers only need to implement `exchange()`, which asserts CS, writes all the bytes
-/// in `data`, then returns all the received bytes. If it provides a performance optimisation,
-/// providers may also implement `write()`, which does not require the received data.
+/// Providers only need to implement `exchange()`, which asserts CS, writes the bytes of
+/// `command` (see `Command::to_array()`) followed by all the bytes in `data`, then returns
+/// all the received bytes, including those received while the command and data were sent.
+/// If it provides a performance optimisation, providers may also implement `write()`,
+/// which does not require the received data.
 ///
 /// `From<FlashAccess::Error>` must be implemented for `spi_flash::Error`; for example in your
 /// implementation code, add:
@@ -101,6 +363,41 @@ pub trait FlashAccess {
         nbytes: usize,
     ) -> core::result::Result<Vec<u8>, Self::Error>;
 
+    /// Required alignment of data transfers, in bytes.
+    ///
+    /// Read and program helpers will only request read lengths, program lengths, and
+    /// flash addresses which are multiples of this value, reading extra data or padding
+    /// programmed data with 0xFF bytes as required. This is useful for transports using
+    /// DMA engines which require word-aligned transfers.
+    ///
+    /// Must be a power of two no larger than the flash page size. The default is 1.
+    fn transfer_alignment(&self) -> usize {
+        1
+    }
+
+    /// Maximum number of data bytes to read or program in a single transfer, if limited.
+    ///
+    /// Read and program helpers will split larger operations into multiple transfers,
+    /// with boundaries aligned to `transfer_alignment()`. The default is no limit.
+    fn max_transfer_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Check if the transport supports `protocol`.
+    ///
+    /// The default implementation only supports standard SPI.
+    fn supports_protocol(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Spi
+    }
+
+    /// Switch the transport to `protocol` for all subsequent transfers.
+    ///
+    /// This is only called with protocols for which `supports_protocol()` returns true.
+    /// The default implementation does nothing.
+    fn set_protocol(&mut self, protocol: Protocol) {
+        let _ = protocol;
+    }
+
     /// Wait for at least `duration`.
     ///
     /// This delay is advisory and reduces polling traffic based on known
@@ -111,6 +408,8 @@ pub trait FlashAccess {
     fn delay(&mut self, duration: Duration) {
         #[cfg(feature = "std")]
         std::thread::sleep(duration);
+        #[cfg(not(feature = "std"))]
+        let _ = duration;
     }
 }
 
@@ -143,6 +442,102 @@ pub struct Flash<'a, A: FlashAccess> {
     /// EraseSector instruction opcode.
     /// This is set to 0x20 by default but may be overridden.
     erase_opcode: u8,
+
+    /// Register addresses for devices accessing registers using RDAR/WRAR.
+    register_file: Option<RegisterFile>,
+
+    /// Device capability flags.
+    quirks: Quirks,
+
+    /// Read instruction, dummy cycles, and mode bits used for data reads.
+    read_parameters: ReadParameters,
+
+    /// Set if the device may be in continuous read mode, and must be sent
+    /// the mode bit reset sequence before the next command.
+    continuous_read: bool,
+
+    /// Board wiring capabilities.
+    board: BoardConfig,
+
+    /// Bus protocol currently in use by the device.
+    protocol: Protocol,
+
+    /// Base address of the memory-mapped window, if the flash is mapped.
+    mapped_base: Option<u32>,
+
+    /// Erase layout for devices with non-uniform sectors.
+    layout: Option<Layout>,
+
+    /// If set, instructions not confirmed supported by the device are rejected.
+    strict: bool,
+
+    /// Write protection scheme, or `None` to use the BP bits of status register 1.
+    protection: Option<alloc::boxed::Box<dyn ProtectionScheme<A>>>,
+
+    /// Receiver of progress reports from program and erase operations, if any.
+    progress: Option<alloc::boxed::Box<dyn ProgressSink>>,
+
+    /// Retry behaviour for transfers failing with transport errors.
+    retry: RetryPolicy,
+
+    /// Flag status register error bits seen while polling, until checked.
+    flag_errors: u8,
+
+    /// If set, programs and erases are checked against the protection scheme first,
+    /// with the protection of each sector cached as its address and state.
+    protection_guard: Option<Vec<(u32, bool)>>,
+
+    /// SPI clock frequency in Hz, if set.
+    frequency: Option<u32>,
+
+    /// Dummy cycles of the read instruction for each clock frequency, if known.
+    latency_table: Option<LatencyTable>,
+
+    /// Hook waiting for the device to become ready, replacing status polling, if any.
+    wait_ready: Option<alloc::boxed::Box<dyn WaitReady>>,
+
+    /// Operation times overriding those from SFDP, if set.
+    timings: Option<Timings>,
+
+    /// If set, operations still busy after their maximum time fail with `Error::Timeout`.
+    timeouts: bool,
+
+    /// If set, `program_data()` reads back each page after programming it.
+    verify_programs: bool,
+
+    /// Replacement opcodes for instructions passed to the transport, if any.
+    opcode_map: Option<OpcodeMap>,
+
+    /// Sectors programmed since they were last erased, if tracked.
+    dirty: Option<DirtyMap>,
+
+    /// Receiver of a trace event for each instruction issued, if any.
+    #[cfg(feature = "trace")]
+    trace: Option<alloc::boxed::Box<dyn trace::TraceHook>>,
+
+    /// Set after a write enable, until the next instruction is sent.
+    write_latched: bool,
+
+    /// Set after an instruction which may leave the device busy, until it is seen to be ready.
+    busy_pending: bool,
+
+    /// WP# line, if driven by the controller.
+    wp_pin: Option<alloc::boxed::Box<dyn ControlPin>>,
+
+    /// RESET# (or shared HOLD#/RESET#) line, if driven by the controller.
+    reset_pin: Option<alloc::boxed::Box<dyn ControlPin>>,
+
+    /// Number of stacked dies selected with Die Select, 1 for single-die devices.
+    dies: u8,
+
+    /// Die last selected with Die Select.
+    die: u8,
+
+    /// How 3-byte address instructions reach memory above 16MB.
+    address_extension: AddressExtension,
+
+    /// Address bits above A23 last written to the device, if known.
+    upper_address: Option<u8>,
 }
 
 impl<'a, A: FlashAccess> Flash<'a, A>
@@ -158,6 +553,13 @@ where
     #[cfg(feature = "std")]
     const DATA_PROGRESS_CHARS: &'static str = "━╸━";
 
+    /// Number of bytes read at a time when streaming through memory,
+    /// such as for blank checks or CRC computation.
+    const STREAM_CHUNK: usize = 4096;
+
+    /// Flag status register erase, program, and protection error bits.
+    const FSR_ERRORS: u8 = 0b0011_0010;
+
     /// Create a new Flash instance using the given FlashAccess provider.
     pub fn new(access: &'a mut A) -> Self {
         Flash {
@@ -169,7 +571,177 @@ where
             page_size: None,
             erase_size: None,
             erase_opcode: 0x20,
+            register_file: None,
+            quirks: Quirks::default(),
+            read_parameters: ReadParameters::default(),
+            continuous_read: false,
+            board: BoardConfig::default(),
+            protocol: Protocol::Spi,
+            mapped_base: None,
+            layout: None,
+            strict: false,
+            protection: None,
+            progress: None,
+            retry: RetryPolicy::NONE,
+            flag_errors: 0,
+            protection_guard: None,
+            frequency: None,
+            latency_table: None,
+            wait_ready: None,
+            timings: None,
+            timeouts: false,
+            verify_programs: false,
+            opcode_map: None,
+            dirty: None,
+            #[cfg(feature = "trace")]
+            trace: None,
+            write_latched: false,
+            busy_pending: false,
+            wp_pin: None,
+            reset_pin: None,
+            dies: 1,
+            die: 0,
+            address_extension: AddressExtension::None,
+            upper_address: None,
+        }
+    }
+
+    /// Detect the device attached to `access`, and create a `Flash` configured to suit it.
+    ///
+    /// The device is woken from power-down and taken out of QPI (if the transport supports
+    /// it) and continuous read modes, its ID is read, and its SFDP parameters are read if
+    /// supported. Devices without SFDP are looked up by JEDEC ID in `devices::DEVICES`.
+    ///
+    /// A failure at any stage is returned as `Error::Detect` with the stage which failed,
+    /// or if the device has no SFDP and is not known, `Error::UnknownDevice`.
+    pub fn detect(access: &'a mut A) -> Result<Self> {
+        let detect = |stage| {
+            move |err| Error::Detect {
+                stage,
+                source: alloc::boxed::Box::new(err),
+            }
+        };
+        let mut flash = Flash::new(access);
+        flash.wake().map_err(detect(DetectStage::Wake))?;
+        let id = flash.read_id().map_err(detect(DetectStage::ReadId))?;
+        if flash
+            .read_params()
+            .map_err(detect(DetectStage::ReadParams))?
+            .is_some()
+        {
+            return Ok(flash);
+        }
+        let (manufacturer_id, device_id) = (id.manufacturer_id, id.device_id_long);
+        match devices::lookup(manufacturer_id, device_id) {
+            Some(device) => {
+                log::info!("No SFDP, using known parameters for {}", device.name);
+                let address_bytes = if device.capacity > 16 * 1024 * 1024 {
+                    4
+                } else {
+                    3
+                };
+                flash.set_address_bytes(address_bytes);
+                flash.set_capacity(device.capacity);
+                flash.set_page_size(device.page_size);
+                flash.set_erase_size(device.erase_size);
+                flash.set_erase_opcode(device.erase_opcode);
+                let mut quirks = id.manufacturer().quirks();
+                quirks.chip_erase_60 =
+                    device.chip_erase_opcode == u8::from(CommandOpCode::ChipEraseAlt);
+                flash.set_quirks(quirks);
+                Ok(flash)
+            }
+            None => {
+                log::error!("Device has no SFDP and is not in the device database");
+                Err(Error::UnknownDevice {
+                    manufacturer_id,
+                    device_id,
+                })
+            }
+        }
+    }
+
+    /// Detect the device attached to `access` as `detect()` does, directly after power is
+    /// applied, using `delay` to meet the power-up timing of the device.
+    ///
+    /// Devices accept no instructions until tVSL after VCC reaches its minimum, and ignore
+    /// writes until tPUW, so writes made too early in boot may silently fail. tVSL is
+    /// waited before detection, or if `poll_ready` is set, status register 1 is instead
+    /// polled until it reads as ready, which is often sooner on fast-ramping rails.
+    /// A device still not ready at tPUW gives an `Error::Detect` at `DetectStage::PowerUp`.
+    /// Once detected, the remainder of tPUW is waited before returning.
+    ///
+    /// The times are taken from `devices::DEVICES` if the device is listed there,
+    /// otherwise the conservative defaults `devices::DEFAULT_POWER_UP_DELAY` and
+    /// `devices::DEFAULT_POWER_UP_WRITE_DELAY` are used, as SFDP does not give them.
+    /// Polling requires MISO to read as 1s while the device is unpowered, such as
+    /// with a pull-up, or the device may be reported ready too soon.
+    #[cfg(feature = "embedded-hal")]
+    pub fn detect_with_power_up<D: embedded_hal::delay::DelayNs>(
+        access: &'a mut A,
+        delay: &mut D,
+        poll_ready: bool,
+    ) -> Result<Self> {
+        // Until identified, wait for the slowest device.
+        let limit = devices::DEFAULT_POWER_UP_WRITE_DELAY.as_micros() as u32;
+        let mut elapsed = 0;
+        let mut flash = Flash::new(access);
+        if poll_ready {
+            loop {
+                // An unpowered device reads as all 1s, so as busy.
+                match flash.exchange(Command::ReadStatusRegister1, &[], 1) {
+                    Ok(status) if !StatusRegister1(status[0]).get_busy() => break,
+                    Ok(_) if elapsed < limit => (),
+                    result => {
+                        let err = result.err().unwrap_or(Error::Timeout);
+                        log::error!("Device not ready {}us after power-up", elapsed);
+                        return Err(Error::Detect {
+                            stage: DetectStage::PowerUp,
+                            source: alloc::boxed::Box::new(err),
+                        });
+                    }
+                }
+                delay.delay_us(100);
+                elapsed += 100;
+            }
+            log::debug!("Device ready {}us after power-up", elapsed);
+        } else {
+            let vsl = devices::DEFAULT_POWER_UP_DELAY.as_micros() as u32;
+            delay.delay_us(vsl);
+            elapsed = vsl;
+        }
+        let flash = Flash::detect(flash.access)?;
+        let write_delay = flash
+            .id
+            .as_ref()
+            .and_then(|id| devices::lookup(id.manufacturer_id, id.device_id_long))
+            .map_or(devices::DEFAULT_POWER_UP_WRITE_DELAY, |device| {
+                device.power_up_write_delay
+            });
+        let remaining = (write_delay.as_micros() as u32).saturating_sub(elapsed);
+        if remaining > 0 {
+            log::debug!(
+                "Waiting {}us for writes to be accepted after power-up",
+                remaining
+            );
+            delay.delay_us(remaining);
+        }
+        Ok(flash)
+    }
+
+    /// Wake the device from power-down, and leave QPI and continuous read modes.
+    fn wake(&mut self) -> Result<()> {
+        if self.access.supports_protocol(Protocol::Qpi) {
+            log::debug!("Sending Exit QPI in case device was left in QPI mode");
+            self.access.set_protocol(Protocol::Qpi);
+            self.protocol = Protocol::Qpi;
+            self.exit_qpi()?;
         }
+        self.exit_continuous_read()?;
+        self.release_power_down()?;
+        // Allow the release from power-down time, tRES1, which is at most 30us on most devices.
+        self.access.delay(Duration::from_micros(30));
+        Ok(())
     }
 
     /// Get the number of address bytes which will be used in read and write commands.
@@ -200,6 +772,83 @@ where
         self.capacity = Some(n);
     }
 
+    /// Get how 3-byte address instructions reach memory above 16MB.
+    pub fn address_extension(&self) -> AddressExtension {
+        self.address_extension
+    }
+
+    /// Set how 3-byte address instructions reach memory above 16MB.
+    ///
+    /// With an extension set and 3 address bytes, the driver writes the upper address
+    /// bits to the device before accessing each 16MB bank, and splits reads crossing
+    /// banks, so the whole capacity is addressed as one range while the device stays in
+    /// 3-byte address mode, as boot ROMs expecting 3-byte addresses require.
+    /// The upper address bits are written before the next access, and are left selecting
+    /// the last bank accessed until `reset_bank()` is called.
+    pub fn set_address_extension(&mut self, extension: AddressExtension) {
+        self.address_extension = extension;
+        self.upper_address = None;
+    }
+
+    /// Select the first 16MB bank, if the address extension may have selected another.
+    ///
+    /// Accesses above 16MB leave the bank register or Extended Address Register
+    /// selecting their bank, so 3-byte addresses sent by other code, such as a boot ROM
+    /// after a reset which does not reset the device, would reach the wrong memory.
+    /// Call this before handing the device over to such code.
+    pub fn reset_bank(&mut self) -> Result<()> {
+        if self.address_extended() && self.upper_address != Some(0) {
+            self.write_upper_address(0)?;
+        }
+        Ok(())
+    }
+
+    /// Get the number of stacked dies.
+    pub fn dies(&self) -> u8 {
+        self.dies
+    }
+
+    /// Set the number of stacked dies of a device such as the Winbond W25M, which are
+    /// selected using Die Select (0xC2) and share the capacity equally.
+    ///
+    /// The capacity covers all dies, with addresses continuing from one die to the next,
+    /// and the driver selects the die holding each address before accessing it, splitting
+    /// reads which cross dies. As the SFDP parameters of such devices describe a single
+    /// die, a capacity already set by `read_params()` is scaled to `dies` dies, as is
+    /// the capacity found by any later `read_params()`.
+    ///
+    /// The device is assumed to have die 0 selected, as it does at power-up.
+    pub fn set_dies(&mut self, dies: u8) {
+        let dies = u8::max(dies, 1);
+        self.capacity = self
+            .capacity
+            .map(|c| c / self.dies as usize * dies as usize);
+        self.dies = dies;
+        self.die = 0;
+    }
+
+    /// Get the die last selected with Die Select.
+    pub fn active_die(&self) -> u8 {
+        self.die
+    }
+
+    /// Select `die` using Die Select (0xC2).
+    ///
+    /// Any program or erase left in progress on the current die, for example by
+    /// `exec_raw()` or a program suspended and resumed, is waited for first, since status
+    /// reads only report the busy state of the selected die.
+    pub fn select_die(&mut self, die: u8) -> Result<()> {
+        if die >= self.dies {
+            log::error!("Die {} does not exist, device has {} dies", die, self.dies);
+            return Err(Error::InvalidConfiguration);
+        }
+        self.wait_if_pending(Command::DieSelect)?;
+        log::trace!("Selecting die {}", die);
+        self.write(Command::DieSelect, &[die])?;
+        self.die = die;
+        Ok(())
+    }
+
     /// Get the page program size in bytes.
     pub fn page_size(&self) -> Option<usize> {
         self.page_size
@@ -236,104 +885,555 @@ where
         self.erase_opcode = opcode;
     }
 
-    /// Get the flash ID, if it has already been read.
+    /// Get the register file used for RDAR/WRAR register access, if set.
+    pub fn register_file(&self) -> Option<&RegisterFile> {
+        self.register_file.as_ref()
+    }
+
+    /// Set the register file used for RDAR/WRAR register access.
     ///
-    /// Call `read_id()` to read the ID from the flash.
-    pub fn get_id(&self) -> Option<FlashID> {
-        self.id
+    /// When set, registers with an address in the register file are accessed using the
+    /// Read Any Register (0x65) and Write Any Register (0x71) commands, including by
+    /// `read_status1()` and the other status and protection methods. Registers without
+    /// an address continue to use their legacy instructions.
+    pub fn set_register_file(&mut self, register_file: Option<RegisterFile>) {
+        self.register_file = register_file;
     }
 
-    /// Get the flash parameters, if they have already been read.
+    /// Get the device capability flags in use.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the device capability flags.
     ///
-    /// Call `read_params()` to read the params from the flash.
-    pub fn get_params(&self) -> Option<FlashParams> {
-        self.params
+    /// These describe device behaviour which cannot be discovered using SFDP,
+    /// and all default to off.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    /// Read the device's manufacturer ID and device IDs.
+    /// Get the read instruction configuration used for data reads.
+    pub fn read_parameters(&self) -> ReadParameters {
+        self.read_parameters
+    }
+
+    /// Set the read instruction configuration used for data reads.
     ///
-    /// This method additionally brings the flash out of powerdown and resets it.
+    /// By default FastRead (0x0B) is used with 8 dummy cycles. Parameters for other read
+    /// instructions may be obtained from SFDP using `ReadParameters::from_sfdp()`, but note
+    /// the `FlashAccess` implementation must then transfer the mode bits, dummy bytes,
+    /// and data on the lines required by the instruction.
+    pub fn set_read_parameters(&mut self, read_parameters: ReadParameters) {
+        self.read_parameters = read_parameters;
+    }
+
+    /// Get the board wiring capabilities in use.
+    pub fn board(&self) -> BoardConfig {
+        self.board
+    }
+
+    /// Set the board wiring capabilities.
     ///
-    /// `self.id` is updated with the new ID; use `get_id()` to
-    /// retrieve it without re-reading the ID from the flash.
-    pub fn read_id(&mut self) -> Result<FlashID> {
-        log::debug!("Reading SPI Flash ID");
+    /// These are consulted by `init()` and `set_quad_enable()` to avoid configuring the
+    /// device in ways which would break communication on the board.
+    pub fn set_board(&mut self, board: BoardConfig) {
+        self.board = board;
+    }
 
-        let legacy_id = self.release_power_down()?;
-        self.reset()?;
+    /// Get the base address of the memory-mapped window, if set.
+    pub fn mapped_base(&self) -> Option<u32> {
+        self.mapped_base
+    }
 
-        let (bank_long, mfn_id_long, device_id_long) = self.read_jedec_id()?;
-        let (bank_short, mfn_id_short, mut device_id_short) = self.read_device_id()?;
-        let unique_id = self.read_unique_id()?;
+    /// Set the base address of the memory-mapped window, such as an XIP base address,
+    /// allowing addresses in `AddressSpace::Mapped` to be used.
+    pub fn set_mapped_base(&mut self, base: Option<u32>) {
+        self.mapped_base = base;
+    }
 
-        // The device may implement any or none of the three identification
-        // instructions; attempt to obtain a valid manufacturer ID and device ID.
-        // If there is no device present or a communication error, we'll probably
-        // receive all-0s or all-1s for all the data.
-        let manufacturer_bank;
-        let manufacturer_id;
-        if mfn_id_long != 0x00 && mfn_id_long != 0xFF {
-            manufacturer_bank = bank_long;
-            manufacturer_id = mfn_id_long;
-        } else if mfn_id_short != 0x00 && mfn_id_short != 0xFF {
-            manufacturer_bank = bank_short;
-            manufacturer_id = mfn_id_short;
-        } else {
-            log::warn!("No valid manufacturer ID found");
-            if legacy_id == 0x00 || legacy_id == 0xFF {
-                log::error!("No device or manufacturer ID found");
-                return Err(Error::InvalidManufacturer);
-            } else {
-                device_id_short = legacy_id;
-                manufacturer_bank = 0;
-                manufacturer_id = 0;
+    /// Convert `address` in `space` to a storage offset.
+    ///
+    /// Returns an error if `address` is mapped but no mapped base is set, the address is
+    /// below the mapped base, or the resulting offset is beyond the capacity, if known.
+    pub fn to_storage(&self, space: AddressSpace, address: u32) -> Result<u32> {
+        let offset = match space {
+            AddressSpace::Storage => address,
+            AddressSpace::Mapped => match self.mapped_base {
+                Some(base) if address >= base => address - base,
+                Some(base) => {
+                    log::error!(
+                        "Address 0x{:08X} is below mapped base 0x{:08X}",
+                        address,
+                        base
+                    );
+                    return Err(Error::InvalidAddress { address });
+                }
+                None => {
+                    log::error!("No mapped base set for mapped address 0x{:08X}", address);
+                    return Err(Error::InvalidAddress { address });
+                }
+            },
+        };
+        match self.capacity {
+            Some(capacity) if offset as usize >= capacity => {
+                log::error!("Address 0x{:08X} is beyond the flash capacity", address);
+                Err(Error::InvalidAddress { address })
             }
+            _ => Ok(offset),
         }
+    }
 
-        let id = FlashID {
-            manufacturer_bank,
-            manufacturer_id,
-            device_id_long,
-            device_id_short,
-            unique_id,
-        };
-
-        log::debug!("Read ID: {:?}", id);
-        self.id = Some(id);
-        Ok(id)
+    /// Convert the storage offset `offset` to an address in `space`.
+    pub fn from_storage(&self, space: AddressSpace, offset: u32) -> Result<u32> {
+        match space {
+            AddressSpace::Storage => Ok(offset),
+            AddressSpace::Mapped => self
+                .mapped_base
+                .and_then(|base| base.checked_add(offset))
+                .ok_or(Error::InvalidAddress { address: offset }),
+        }
     }
 
-    /// Read SFDP JEDEC Basic Flash Parameter table from flash.
+    /// Get the geometry of the device, if its capacity and erase size are known.
     ///
-    /// Access errors are returned as usual, but if SFDP is not supported
-    /// (no SFDP signature is detected in the first SFDP DWORD) then
-    /// `Ok(None)` is returned instead.
+    /// The page size defaults to 256 bytes if not known. The block size is the largest
+    /// erase size in the SFDP parameters, or 64kB if they have not been read.
+    pub fn geometry(&self) -> Option<Geometry> {
+        let capacity = self.capacity?;
+        let sector_size = self.erase_size?;
+        let block_size = self
+            .params
+            .and_then(|params| params.erase_insts.iter().flatten().map(|i| i.size).max())
+            .map_or(64 * 1024, |size| size as usize);
+        Some(Geometry {
+            page_size: self.page_size.unwrap_or(256),
+            sector_size,
+            block_size: usize::max(block_size, sector_size),
+            capacity,
+        })
+    }
+
+    /// Get the erase layout, if the device has non-uniform sectors.
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    /// Set the erase layout used by `erase_range()` and the other multi-sector erase
+    /// methods, or None for a uniform layout using all available erase instructions.
     ///
-    /// Depending on the version of SFDP supported, some fields may
-    /// not be available.
+    /// Note that `erase_sector()` always uses the configured sector erase instruction.
     ///
-    /// Once read, the parameters are available using `get_params()`,
-    /// and the parameter values are automatically used for the
-    /// configuration of address bytes, capacity, page size, sector size,
-    /// and erase sector opcode. Additionally, larger erase commands
-    /// described by the SFDP parameters will be used when appropriate.
-    pub fn read_params(&mut self) -> Result<Option<FlashParams>> {
-        log::debug!("Reading SFDP data");
+    /// This is set automatically by `read_params()` for devices with an SFDP Sector Map.
+    pub fn set_layout(&mut self, layout: Option<Layout>) {
+        self.layout = layout;
+    }
 
-        // Read just SFDP header to get NPH first.
-        let data = self.read_sfdp(0, 8)?;
-        let nph = data[6] as usize + 1;
+    /// Check if strict mode is enabled.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
 
-        // Re-read overall SFDP header including parameter headers.
-        // Handle errors parsing the header by returning Ok(None),
-        // since not all flash devices support SFDP.
-        // After this parse is successful, however, subsequent errors
-        // are returned as errors.
-        let data = self.read_sfdp(0, 8 + nph * 8)?;
-        let header = match SFDPHeader::from_bytes(&data) {
-            Ok(header) => header,
-            Err(_) => return Ok(None),
+    /// Enable or disable strict mode.
+    ///
+    /// In strict mode, any instruction not confirmed supported by the device, as checked by
+    /// `is_supported()`, is rejected with `Error::Unsupported` instead of being sent, where
+    /// it would likely be silently ignored. This helps find code relying on vendor-specific
+    /// instructions when porting firmware between flash devices.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Check if the instruction `opcode` is confirmed supported by the device.
+    ///
+    /// The core instructions and the identification instructions used by `read_id()` and
+    /// `read_params()` are always supported. Other instructions are confirmed by the SFDP
+    /// parameters, including their read, erase, reset, and 4-byte address instructions,
+    /// or by the configured quirks and register file. Register bits whose location is not
+    /// known, such as QE, are always rejected regardless of strict mode.
+    pub fn is_supported(&self, opcode: u8) -> bool {
+        const CORE: [CommandOpCode; 16] = [
+            CommandOpCode::WriteEnable,
+            CommandOpCode::WriteDisable,
+            CommandOpCode::ReadData,
+            CommandOpCode::FastRead,
+            CommandOpCode::PageProgram,
+            CommandOpCode::ReadStatusRegister1,
+            CommandOpCode::WriteStatusRegister1,
+            CommandOpCode::ChipErase,
+            CommandOpCode::Powerdown,
+            CommandOpCode::ReleasePowerdown,
+            CommandOpCode::ReadJEDECID,
+            CommandOpCode::ReadDeviceID,
+            CommandOpCode::ReadUniqueID,
+            CommandOpCode::ReadSFDPRegister,
+            CommandOpCode::ModeBitReset,
+            CommandOpCode::EnableReset,
+        ];
+        // Devices implementing only the alternate encoding of an instruction do not
+        // support its usual opcode.
+        let alias = match CommandOpCode::try_from(opcode) {
+            Ok(CommandOpCode::ChipErase) => Some(!self.quirks.chip_erase_60),
+            Ok(CommandOpCode::ChipEraseAlt) => Some(self.quirks.chip_erase_60),
+            Ok(CommandOpCode::EnableReset | CommandOpCode::Reset) if self.quirks.reset_f0 => {
+                Some(false)
+            }
+            Ok(CommandOpCode::SoftwareReset) if self.quirks.reset_f0 => Some(true),
+            _ => None,
+        };
+        if let Some(supported) = alias {
+            return supported;
+        }
+        if CORE.iter().any(|op| u8::from(*op) == opcode) {
+            return true;
+        }
+        let any_register = [
+            CommandOpCode::ReadAnyRegister.into(),
+            CommandOpCode::WriteAnyRegister.into(),
+        ];
+        if self.register_file.is_some() && any_register.contains(&opcode) {
+            return true;
+        }
+        if self.quirks.combined_status_write
+            && opcode == u8::from(CommandOpCode::ReadStatusRegister2)
+        {
+            return true;
+        }
+        if opcode == u8::from(CommandOpCode::ReadFlagStatusRegister)
+            && self.busy_polling() == SFDPBusyPolling::FlagStatusRegister
+        {
+            return true;
+        }
+        let quad_enable: &[u8] = match self.quad_enable_requirement() {
+            Some(SFDPQuadEnable::Sr2Bit7) => &[0x3F, 0x3E],
+            Some(SFDPQuadEnable::Sr2Bit1Read35) => &[0x35],
+            Some(SFDPQuadEnable::Sr2Bit1Write31) => &[0x35, 0x31],
+            _ => &[],
+        };
+        if quad_enable.contains(&opcode) {
+            return true;
+        }
+        let params = match self.params {
+            Some(params) => params,
+            // Before SFDP is read, the device may only be identified; the default
+            // reset sequence is allowed as it is used by `read_id()`.
+            None => return opcode == u8::from(CommandOpCode::Reset),
+        };
+        let erase = params
+            .erase_insts
+            .iter()
+            .flatten()
+            .any(|i| i.opcode == opcode)
+            || (params.legacy_4kb_erase_supported && params.legacy_4kb_erase_inst == opcode);
+        let fast_read = [
+            params.fast_read_112,
+            params.fast_read_122,
+            params.fast_read_114,
+            params.fast_read_144,
+        ]
+        .iter()
+        .flatten()
+        .any(|inst| inst.opcode == opcode);
+        let reset = match CommandOpCode::try_from(opcode) {
+            Ok(CommandOpCode::Reset) => params.reset_inst_66_99 != Some(false),
+            Ok(CommandOpCode::SoftwareReset) => params.reset_inst_f0 == Some(true),
+            _ => false,
+        };
+        let volatile_write_enable = opcode == u8::from(CommandOpCode::WriteEnableVolatile)
+            && match params.status_1_vol {
+                Some(vol) => matches!(
+                    vol,
+                    SFDPStatus1Volatility::Volatile50
+                        | SFDPStatus1Volatility::NonVolatile06Volatile50
+                ),
+                None => params.legacy_volatile_write_en_inst == opcode,
+            };
+        let four_byte = params
+            .four_byte_instructions
+            .is_some_and(|four_byte| four_byte.supports(opcode));
+        erase || fast_read || reset || volatile_write_enable || four_byte
+    }
+
+    /// In strict mode, check `command` is confirmed supported by the device.
+    fn check_supported(&self, command: Command) -> Result<()> {
+        let opcode = command.opcode();
+        let raw = matches!(command, Command::Raw(..) | Command::Raw4B(..));
+        if self.strict && !raw && !self.is_supported(opcode) {
+            log::error!(
+                "Strict mode: rejecting unconfirmed instruction {:?}",
+                command
+            );
+            return Err(Error::Unsupported { opcode });
+        }
+        Ok(())
+    }
+
+    /// Check if an instruction which may leave the device busy, such as a program or erase,
+    /// has been sent without the device since being seen to be ready.
+    ///
+    /// This is normally only the case if an operation failed or was abandoned before it
+    /// finished waiting, for example due to a transport error or timeout. Sending any
+    /// other instruction first waits until the device is ready, except for status reads,
+    /// suspend, and reset instructions, so a new instruction is never sent to a busy device.
+    pub fn is_busy_pending(&self) -> bool {
+        self.busy_pending
+    }
+
+    /// Get the retry policy for transfers failing with transport errors.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Set the retry policy for transfers failing with transport errors.
+    ///
+    /// By default, failed transfers are not retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Run `op`, retrying it on transport errors if `enabled` and the retry policy allow.
+    fn retrying<T>(
+        &mut self,
+        enabled: bool,
+        mut op: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match op(self) {
+                Err(err) if enabled => match self.retry.retry_delay(attempt, &err) {
+                    Some(delay) => {
+                        log::warn!("Retrying after transfer error: {:?}", err);
+                        self.access.delay(delay);
+                        attempt += 1;
+                    }
+                    None => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Set a hook to wait for the device to become ready in place of polling the status
+    /// register, or `None` to poll.
+    pub fn set_wait_ready(&mut self, hook: Option<alloc::boxed::Box<dyn WaitReady>>) {
+        self.wait_ready = hook;
+    }
+
+    /// Set a hook called with each instruction issued to the device, or `None` to stop
+    /// tracing.
+    #[cfg(feature = "trace")]
+    pub fn set_trace_hook(&mut self, hook: Option<alloc::boxed::Box<dyn trace::TraceHook>>) {
+        self.trace = hook;
+    }
+
+    /// Get the typical and maximum times of program and erase operations.
+    ///
+    /// These are the times set by `set_timings()` if any, otherwise they are taken from
+    /// the SFDP parameters, using the erase instruction set by `erase_opcode` for the
+    /// sector erase times. Returns `None` if any of the times are not known.
+    pub fn timings(&self) -> Option<Timings> {
+        let (page_program_typ, page_program_max) = self.page_program_times()?;
+        let (Some(sector_erase_typ), Some(sector_erase_max)) = self.erase_times(self.erase_opcode)
+        else {
+            return None;
+        };
+        let (chip_erase_typ, chip_erase_max) = self.chip_erase_times()?;
+        Some(Timings {
+            page_program_typ,
+            page_program_max,
+            sector_erase_typ,
+            sector_erase_max,
+            chip_erase_typ,
+            chip_erase_max,
+        })
+    }
+
+    /// Set the operation times, overriding those from SFDP, or `None` to use SFDP.
+    ///
+    /// This is useful for devices without SFDP, using the times from their datasheet.
+    pub fn set_timings(&mut self, timings: Option<Timings>) {
+        self.timings = timings;
+    }
+
+    /// Set whether programs and erases still busy after their maximum time from
+    /// `timings()` fail with `Error::Timeout`.
+    ///
+    /// Elapsed time is counted from the delays requested from `FlashAccess::delay()`, so
+    /// this must only be enabled if the transport implements delays. While a `WaitReady`
+    /// hook is set, the time spent in the hook is not counted, but a delay is added each
+    /// time it returns with the device still busy. Disabled by default.
+    pub fn set_timeouts(&mut self, enabled: bool) {
+        self.timeouts = enabled;
+    }
+
+    /// Check whether `program_data()` verifies each page it programs.
+    pub fn verify_programs(&self) -> bool {
+        self.verify_programs
+    }
+
+    /// Set whether `program_data()`, and so the `Storage` implementation and the layers
+    /// built on it, read back each page after programming it, as `program_data_verified()`.
+    ///
+    /// This catches silent corruption from worn-out sectors or marginal power at the cost
+    /// of a read per page. Disabled by default.
+    pub fn set_verify_programs(&mut self, enabled: bool) {
+        self.verify_programs = enabled;
+    }
+
+    /// Get the opcode remapping applied to instructions, if any.
+    pub fn opcode_map(&self) -> Option<&OpcodeMap> {
+        self.opcode_map.as_ref()
+    }
+
+    /// Set replacement opcodes for instructions passed to the transport, for devices
+    /// implementing standard instructions under nonstandard opcodes, or `None` to send
+    /// the standard opcodes.
+    pub fn set_opcode_map(&mut self, map: Option<OpcodeMap>) {
+        self.opcode_map = map.filter(|map| !map.is_empty());
+    }
+
+    /// Get the map of sectors programmed since they were last erased, if tracked.
+    pub fn dirty_map(&self) -> Option<&DirtyMap> {
+        self.dirty.as_ref()
+    }
+
+    /// Set a map to be updated with the sectors programmed and erased through this
+    /// `Flash`, or `None` to stop tracking, returning any previous map.
+    pub fn set_dirty_map(&mut self, map: Option<DirtyMap>) -> Option<DirtyMap> {
+        core::mem::replace(&mut self.dirty, map)
+    }
+
+    /// Set the receiver of progress reports from program and erase operations,
+    /// or `None` to stop reporting progress.
+    pub fn set_progress_sink(&mut self, sink: Option<alloc::boxed::Box<dyn ProgressSink>>) {
+        self.progress = sink;
+    }
+
+    /// Set the WP# line, if driven by the controller, or `None` if it is not.
+    ///
+    /// WP# is driven low, so while the status register protect bit is set the status
+    /// registers are hardware protected, and is only driven high while status register
+    /// writes are sent.
+    pub fn set_wp_pin(&mut self, pin: Option<alloc::boxed::Box<dyn ControlPin>>) -> Result<()> {
+        self.wp_pin = pin;
+        if let Some(pin) = &mut self.wp_pin {
+            pin.set_low()?;
+        }
+        Ok(())
+    }
+
+    /// Set the RESET# line, or HOLD#/RESET# line on devices which share the pin,
+    /// if driven by the controller, or `None` if it is not.
+    ///
+    /// The line is driven high, and `reset()` pulses it low instead of sending
+    /// a software reset.
+    pub fn set_reset_pin(&mut self, pin: Option<alloc::boxed::Box<dyn ControlPin>>) -> Result<()> {
+        self.reset_pin = pin;
+        if let Some(pin) = &mut self.reset_pin {
+            pin.set_high()?;
+        }
+        Ok(())
+    }
+
+    /// Get the flash ID, if it has already been read.
+    ///
+    /// Call `read_id()` to read the ID from the flash.
+    pub fn get_id(&self) -> Option<FlashID> {
+        self.id
+    }
+
+    /// Get the flash parameters, if they have already been read.
+    ///
+    /// Call `read_params()` to read the params from the flash.
+    pub fn get_params(&self) -> Option<FlashParams> {
+        self.params
+    }
+
+    /// Read the device's manufacturer ID and device IDs.
+    ///
+    /// This method additionally brings the flash out of powerdown and resets it.
+    ///
+    /// `self.id` is updated with the new ID; use `get_id()` to
+    /// retrieve it without re-reading the ID from the flash.
+    pub fn read_id(&mut self) -> Result<FlashID> {
+        log::debug!("Reading SPI Flash ID");
+
+        self.exit_continuous_read()?;
+        let legacy_id = self.release_power_down()?;
+        self.reset()?;
+
+        let (bank_long, mfn_id_long, device_id_long) = self.read_jedec_id()?;
+        let (bank_short, mfn_id_short, mut device_id_short) = self.read_device_id()?;
+        let unique_id = self.read_unique_id()?;
+
+        // The device may implement any or none of the three identification
+        // instructions; attempt to obtain a valid manufacturer ID and device ID.
+        // If there is no device present or a communication error, we'll probably
+        // receive all-0s or all-1s for all the data.
+        let manufacturer_bank;
+        let manufacturer_id;
+        if mfn_id_long != 0x00 && mfn_id_long != 0xFF {
+            manufacturer_bank = bank_long;
+            manufacturer_id = mfn_id_long;
+        } else if mfn_id_short != 0x00 && mfn_id_short != 0xFF {
+            manufacturer_bank = bank_short;
+            manufacturer_id = mfn_id_short;
+        } else {
+            log::warn!("No valid manufacturer ID found");
+            if legacy_id == 0x00 || legacy_id == 0xFF {
+                log::error!("No device or manufacturer ID found");
+                return Err(Error::InvalidManufacturer);
+            } else {
+                device_id_short = legacy_id;
+                manufacturer_bank = 0;
+                manufacturer_id = 0;
+            }
+        }
+
+        let id = FlashID {
+            manufacturer_bank,
+            manufacturer_id,
+            device_id_long,
+            device_id_short,
+            unique_id,
+        };
+
+        log::debug!("Read ID: {:?}", id);
+        self.id = Some(id);
+        Ok(id)
+    }
+
+    /// Read SFDP JEDEC Basic Flash Parameter table from flash.
+    ///
+    /// Access errors are returned as usual, but if SFDP is not supported
+    /// (no SFDP signature is detected in the first SFDP DWORD) then
+    /// `Ok(None)` is returned instead.
+    ///
+    /// Depending on the version of SFDP supported, some fields may
+    /// not be available.
+    ///
+    /// Once read, the parameters are available using `get_params()`,
+    /// and the parameter values are automatically used for the
+    /// configuration of address bytes, capacity, page size, sector size,
+    /// and erase sector opcode. Additionally, larger erase commands
+    /// described by the SFDP parameters will be used when appropriate.
+    pub fn read_params(&mut self) -> Result<Option<FlashParams>> {
+        log::debug!("Reading SFDP data");
+
+        // Handle errors parsing the header by returning Ok(None),
+        // since not all flash devices support SFDP.
+        // After this parse is successful, however, subsequent errors
+        // are returned as errors.
+        let header = match self.read_sfdp_header()? {
+            Some(header) => header,
+            None => return Ok(None),
         };
+        log::debug!(
+            "Found SFDP v{}.{} with {} parameter headers",
+            header.major,
+            header.minor,
+            header.nph
+        );
 
         // Check the first parameter header is the JEDEC basic flash parameters,
         // as required by JESD216.
@@ -345,17 +1445,58 @@ where
 
         // Read SFDP table data and parse into a FlashParams struct.
         let data = self.read_sfdp(params.ptp, params.plen * 4)?;
-        let params = FlashParams::from_bytes(params.major, params.minor, &data)?;
+        let mut params = FlashParams::from_bytes(params.major, params.minor, &data)?;
+
+        // Read the 4-Byte Address Instruction Table if present.
+        let four_byte = header
+            .params
+            .iter()
+            .find(|p| p.parameter_id == SFDPFourByteInstructions::PARAMETER_ID);
+        if let Some(four_byte) = four_byte {
+            let data = self.read_sfdp(four_byte.ptp, four_byte.plen * 4)?;
+            params.four_byte_instructions = Some(SFDPFourByteInstructions::from_bytes(&data)?);
+        }
         self.params = Some(params);
 
+        // Read the Sector Map if present, to find the erase layout of non-uniform devices.
+        let sector_map = header
+            .params
+            .iter()
+            .find(|p| p.parameter_id == SFDPSectorMap::PARAMETER_ID);
+        if let Some(sector_map) = sector_map {
+            let data = self.read_sfdp(sector_map.ptp, sector_map.plen * 4)?;
+            let sector_map = SFDPSectorMap::from_bytes(&data)?;
+            self.layout = match self.detect_sector_config(&sector_map) {
+                Ok(id) => match sector_map.regions(id) {
+                    Some(regions) => Some(Layout::from_sfdp(regions, &params.erase_insts)?),
+                    None => {
+                        log::warn!("No SFDP sector map for configuration {}", id);
+                        None
+                    }
+                },
+                Err(err) => {
+                    log::warn!("Could not detect sector map configuration: {:?}", err);
+                    None
+                }
+            };
+        }
+
         // Use params to update settings where posssible.
         self.address_bytes = match params.address_bytes {
             SFDPAddressBytes::Three => 3,
+            // Devices over 16MiB which list native 4-byte address instructions
+            // are used with those, without switching the device's address mode.
+            SFDPAddressBytes::ThreeOrFour
+                if params.capacity_bytes() > 1 << 24 && params.four_byte_instructions.is_some() =>
+            {
+                4
+            }
             SFDPAddressBytes::ThreeOrFour => 3,
             SFDPAddressBytes::Four => 4,
             _ => 3,
         };
-        self.capacity = Some(params.capacity_bytes());
+        // SFDP describes a single die of stacked-die devices.
+        self.capacity = Some(params.capacity_bytes() * self.dies as usize);
         if let Some(page_size) = params.page_size {
             self.page_size = Some(page_size as usize);
         }
@@ -378,17 +1519,371 @@ where
             self.erase_size,
             self.erase_opcode
         );
+        log::debug!("Busy polling: {:?}", self.busy_polling());
 
         Ok(Some(params))
     }
 
     /// Read `length` bytes of data from the attached flash, starting at `address`.
     ///
-    /// This method uses the FastRead instruction; if it is not supported
-    /// try using `legacy_read()` instead.
+    /// This method uses the FastRead instruction, or the read instruction configured
+    /// using `set_read_parameters()`; if it is not supported try using `legacy_read()` instead.
     pub fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
         self.check_address_length(address, length)?;
-        self.exchange(Command::FastRead(Address24Bits(address)), &[], length)
+        self.read_chunks(address, length, true)
+    }
+
+    /// Read `length` bytes of data starting at `address` in `space`.
+    ///
+    /// See `read()`.
+    pub fn read_at(&mut self, space: AddressSpace, address: u32, length: usize) -> Result<Vec<u8>> {
+        let address = self.to_storage(space, address)?;
+        self.read(address, length)
+    }
+
+    /// Read `buf.len()` bytes of data from the attached flash into `buf`, starting at `address`.
+    ///
+    /// This method uses the FastRead instruction, like `read()`.
+    pub fn read_into(&mut self, address: u32, buf: &mut [u8]) -> Result<()> {
+        let data = self.read(address, buf.len())?;
+        buf.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Read each of `requests`, filling its buffer with the data starting at its address.
+    ///
+    /// Every request is checked against the capacity before anything is read, then
+    /// requests for contiguous ranges are merged, so large transfers, such as an image
+    /// scattered across several buffers, reach the transport as the fewest and largest
+    /// reads its maximum transfer size allows, suiting DMA transports. The data of each
+    /// transfer is copied straight into the buffers it covers, without staging the run.
+    ///
+    /// `FlashAccess::exchange()` returns the data received in a buffer it owns, so one
+    /// copy per transfer into the caller's buffers remains.
+    pub fn read_vectored(&mut self, requests: &mut [(u32, &mut [u8])]) -> Result<()> {
+        for (address, buf) in requests.iter() {
+            self.check_address_length(*address, buf.len())?;
+        }
+        let lengths: Vec<(u32, usize)> = requests.iter().map(|(a, buf)| (*a, buf.len())).collect();
+        for (run, address, length) in Self::contiguous_runs(&lengths) {
+            self.read_scatter(address, length, &mut requests[run])?;
+        }
+        Ok(())
+    }
+
+    /// Read each of `requests` as `read_vectored()`, yielding to the executor after
+    /// each run of contiguous requests.
+    ///
+    /// Each read still blocks, as `FlashAccess` is blocking, but other tasks can run
+    /// between runs while a large scattered read completes.
+    pub async fn read_vectored_async(&mut self, requests: &mut [(u32, &mut [u8])]) -> Result<()> {
+        for (address, buf) in requests.iter() {
+            self.check_address_length(*address, buf.len())?;
+        }
+        let lengths: Vec<(u32, usize)> = requests.iter().map(|(a, buf)| (*a, buf.len())).collect();
+        for (run, address, length) in Self::contiguous_runs(&lengths) {
+            self.read_scatter(address, length, &mut requests[run])?;
+            crc::yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// Read the `length` bytes at `address` into the buffers of the contiguous `requests`.
+    fn read_scatter(
+        &mut self,
+        address: u32,
+        length: usize,
+        requests: &mut [(u32, &mut [u8])],
+    ) -> Result<()> {
+        let (mut idx, mut offset) = (0, 0);
+        self.read_chunks_with(address, length, true, |mut data| {
+            while !data.is_empty() {
+                let buf = &mut requests[idx].1[offset..];
+                let n = usize::min(buf.len(), data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                data = &data[n..];
+                offset += n;
+                if offset == requests[idx].1.len() {
+                    idx += 1;
+                    offset = 0;
+                }
+            }
+        })
+    }
+
+    /// Group `requests` of address and length into runs of contiguous ranges, giving
+    /// the indices of the requests in each run, and its address and total length.
+    fn contiguous_runs(requests: &[(u32, usize)]) -> Vec<(core::ops::Range<usize>, u32, usize)> {
+        let mut runs: Vec<(core::ops::Range<usize>, u32, usize)> = Vec::new();
+        for (idx, (address, length)) in requests.iter().enumerate() {
+            match runs.last_mut() {
+                Some((run, start, total)) if *start as usize + *total == *address as usize => {
+                    run.end = idx + 1;
+                    *total += length;
+                }
+                _ => runs.push((idx..idx + 1, *address, *length)),
+            }
+        }
+        runs
+    }
+
+    /// Build the transaction `read()` would use to read `length` bytes from `address`,
+    /// without executing it.
+    ///
+    /// This allows controllers which execute transactions themselves, such as QSPI
+    /// peripherals using DMA, to be driven using this crate's configuration.
+    pub fn read_transaction(&self, address: u32, length: usize) -> Result<Transaction> {
+        self.check_address_length(address, length)?;
+        let mut transaction =
+            Transaction::read(&self.read_parameters, address, self.address_bytes, length);
+        transaction.opcode = self.address_opcode(transaction.opcode);
+        Ok(transaction.with_protocol(self.protocol))
+    }
+
+    /// Build a read transaction which also places the device in continuous read mode.
+    ///
+    /// In continuous read mode, subsequent reads omit the opcode and begin directly with
+    /// the address, which is useful for execute-in-place controllers. The configured read
+    /// parameters must use mode bits, as the I/O read instructions do.
+    ///
+    /// After this call, the device is assumed to be in continuous read mode, and the mode
+    /// bit reset sequence is sent before the next command issued through this `Flash`.
+    pub fn continuous_read_transaction(
+        &mut self,
+        address: u32,
+        length: usize,
+    ) -> Result<Transaction> {
+        if self.read_parameters.mode_bits.is_none() {
+            log::error!("Read instruction has no mode bits, cannot use continuous read");
+            return Err(Error::ContinuousReadUnsupported);
+        }
+        let mut transaction = self.read_transaction(address, length)?;
+        transaction.mode_bits = Some(ReadParameters::CONTINUOUS_MODE_BITS);
+        self.continuous_read = true;
+        Ok(transaction)
+    }
+
+    /// Prepare the device for a memory-mapped controller, returning the read command
+    /// to program into the controller, which uses continuous read if `continuous` is set.
+    ///
+    /// Any program or erase in progress is waited for, and the write enable latch is
+    /// cleared, so the controller's reads are not rejected or disturbed. If the read
+    /// instruction uses four data lines, the board must have quad I/O wired, and the
+    /// QE bit is set if its location is known. After this call the device is assumed to
+    /// be in continuous read mode if `continuous` is set; call `exit_xip()` before
+    /// issuing further commands once the controller leaves memory-mapped mode.
+    pub fn enter_xip(&mut self, continuous: bool) -> Result<XipConfig> {
+        let transaction = self.read_transaction(0, 1)?;
+        if continuous && self.read_parameters.mode_bits.is_none() {
+            log::error!("Read instruction has no mode bits, cannot use continuous read");
+            return Err(Error::ContinuousReadUnsupported);
+        }
+        let lines = self.read_parameters.mode.lines();
+        if self.protocol == Protocol::Spi && lines.data == 4 {
+            if !self.board.quad_io {
+                log::error!("Quad reads require IO2 and IO3 to be connected");
+                return Err(Error::BoardUnsupported);
+            }
+            if self.quad_enable_requirement().is_some() {
+                self.set_quad_enable(true)?;
+            }
+        }
+        if self.continuous_read {
+            self.exit_continuous_read()?;
+        }
+        self.wait_while_busy()?;
+        self.busy_pending = false;
+        self.command(Command::WriteDisable)?;
+        let mut config = XipConfig::from_transaction(&transaction, continuous);
+        if continuous {
+            config.mode_bits = Some(ReadParameters::CONTINUOUS_MODE_BITS);
+            self.continuous_read = true;
+        }
+        log::debug!("Handing over to memory-mapped controller: {:?}", config);
+        Ok(config)
+    }
+
+    /// Return the device to normal commands after a memory-mapped controller stops
+    /// using it, exiting continuous read mode.
+    ///
+    /// The mode bit reset sequence is always sent, as the controller may have left
+    /// the device in continuous read mode whatever it was configured with.
+    pub fn exit_xip(&mut self) -> Result<()> {
+        self.exit_continuous_read()?;
+        self.wait_while_busy()
+    }
+
+    /// Check if the device may be in continuous read mode.
+    pub fn is_continuous_read(&self) -> bool {
+        self.continuous_read
+    }
+
+    /// Exit continuous read mode, if the device is in it.
+    ///
+    /// 16 clocks of 0xFF are sent, which is not a valid continuous read address and mode,
+    /// and which covers the reset sequences for both 3- and 4-byte addressing.
+    /// This is always safe to send, and is sent by `read_id()` in case a previous boot
+    /// left the device in continuous read mode.
+    pub fn exit_continuous_read(&mut self) -> Result<()> {
+        log::debug!("Sending mode bit reset to exit continuous read mode");
+        self.continuous_read = false;
+        self.access_write(Command::ModeBitReset, &[0xFF])
+    }
+
+    /// Get the bus protocol currently in use.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Enter QPI mode using Enter QPI (0x38), where all instructions use four lines.
+    ///
+    /// The board must have quad I/O wired and the transport must support QPI. The QE bit
+    /// is set first if its location is known, as most devices require it for QPI.
+    ///
+    /// Many devices use fewer dummy cycles for reads in QPI mode, so the read parameters
+    /// should be updated to match, for example using `set_qpi_read_parameters()`.
+    pub fn enter_qpi(&mut self) -> Result<()> {
+        if !self.board.quad_io || !self.access.supports_protocol(Protocol::Qpi) {
+            log::error!("QPI is not supported by the board or transport");
+            return Err(Error::BoardUnsupported);
+        }
+        if self.quad_enable_requirement().is_some() {
+            self.set_quad_enable(true)?;
+        }
+        self.command(Command::EnterQpi)?;
+        self.access.set_protocol(Protocol::Qpi);
+        self.protocol = Protocol::Qpi;
+        Ok(())
+    }
+
+    /// Exit QPI mode using Exit QPI (0xFF), returning to standard SPI.
+    pub fn exit_qpi(&mut self) -> Result<()> {
+        if self.protocol == Protocol::Qpi {
+            self.command(Command::ExitQpi)?;
+        }
+        self.access.set_protocol(Protocol::Spi);
+        self.protocol = Protocol::Spi;
+        Ok(())
+    }
+
+    /// Enter octal mode for a device of `family`, in DTR mode if `dtr` is set.
+    ///
+    /// The board must have octal I/O wired and the transport must support the octal
+    /// protocol. Once entered, the transport encodes every instruction for eight lines,
+    /// as described by `Transaction::with_protocol()`.
+    pub fn enter_octal(&mut self, family: OctalFamily, dtr: bool) -> Result<()> {
+        let protocol = Protocol::Octal(OctalMode { family, dtr });
+        if !self.board.octal_io || !self.access.supports_protocol(protocol) {
+            log::error!("Octal mode is not supported by the board or transport");
+            return Err(Error::BoardUnsupported);
+        }
+        let (command, data) = family.enter_command(dtr);
+        self.write_enable()?;
+        self.write(command, &data)?;
+        self.access.set_protocol(protocol);
+        self.protocol = protocol;
+        Ok(())
+    }
+
+    /// Exit octal mode, returning to standard SPI.
+    ///
+    /// The device is reset, which restores its power-up configuration and so resets
+    /// any volatile configuration in addition to the octal mode.
+    pub fn exit_octal(&mut self) -> Result<()> {
+        if let Protocol::Octal(_) = self.protocol {
+            self.command(Command::EnableReset)?;
+            self.command(Command::Reset)?;
+        }
+        self.access.set_protocol(Protocol::Spi);
+        self.protocol = Protocol::Spi;
+        Ok(())
+    }
+
+    /// Configure the number of dummy cycles used by reads in QPI mode, using the
+    /// Set Read Parameters (0xC0) instruction of Winbond and ISSI devices,
+    /// and update the read parameters to match.
+    ///
+    /// `dummy_cycles` must be 2, 4, 6, or 8. The wrap length is set to 8 bytes, which only
+    /// affects wrapped burst reads.
+    pub fn set_qpi_read_parameters(&mut self, dummy_cycles: u8) -> Result<()> {
+        let p5_p4 = match dummy_cycles {
+            2 => 0b00,
+            4 => 0b01,
+            6 => 0b10,
+            8 => 0b11,
+            cycles => return Err(Error::InvalidDummyCycles { cycles }),
+        };
+        self.write(Command::SetReadParameters, &[p5_p4 << 4])?;
+        self.read_parameters.dummy_cycles = dummy_cycles;
+        Ok(())
+    }
+
+    /// Get the SPI clock frequency in Hz, if set.
+    pub fn frequency(&self) -> Option<u32> {
+        self.frequency
+    }
+
+    /// Set the table of read dummy cycles for each clock frequency, used by
+    /// `set_frequency()`, or `None` to keep the configured dummy cycles.
+    pub fn set_latency_table(&mut self, table: Option<LatencyTable>) {
+        self.latency_table = table;
+    }
+
+    /// Set the SPI clock frequency in Hz, reconfiguring the read dummy cycles for it.
+    ///
+    /// With a latency table set, the fewest dummy cycles valid at `hz` are selected,
+    /// the device latency is configured to match as the table describes, and the read
+    /// parameters are updated. Returns `InvalidConfiguration`, changing nothing, if no
+    /// entry supports `hz`. Without a table, the configured dummy cycles, such as
+    /// those from SFDP which are valid up to the maximum frequency, are kept.
+    ///
+    /// Call this before changing the transport's clock, so the device latency is
+    /// never too short for the clock in use.
+    pub fn set_frequency(&mut self, hz: u32) -> Result<()> {
+        if let Some(table) = self.latency_table {
+            let Some(entry) = table.select(hz).copied() else {
+                log::error!("No dummy cycle setting supports {}Hz", hz);
+                return Err(Error::InvalidConfiguration);
+            };
+            log::debug!("Using {} dummy cycles at {}Hz", entry.dummy_cycles, hz);
+            match table.method {
+                LatencyMethod::Fixed => (),
+                LatencyMethod::SetReadParameters => {
+                    self.write(Command::SetReadParameters, &[(entry.code & 0b11) << 4])?
+                }
+                LatencyMethod::ConfigRegister1(mode) => {
+                    let mut config1 = self.read_config1()?;
+                    config1.set_latency_code(entry.code);
+                    self.write_config1(config1, mode)?;
+                }
+            }
+            self.read_parameters.dummy_cycles = entry.dummy_cycles;
+        }
+        self.frequency = Some(hz);
+        Ok(())
+    }
+
+    /// Build the transaction `page_program()` would use to program `length` bytes
+    /// to `address`, without executing it.
+    ///
+    /// The transaction must be preceded by WriteEnable, and followed by polling
+    /// until the device is no longer busy.
+    pub fn program_transaction(&self, address: u32, length: usize) -> Result<Transaction> {
+        self.check_address_length(address, length)?;
+        let mut transaction = Transaction::page_program(address, self.address_bytes, length);
+        transaction.opcode = self.address_opcode(transaction.opcode);
+        Ok(transaction.with_protocol(self.protocol))
+    }
+
+    /// Build the transaction `erase_sector()` would use to erase the sector at `address`,
+    /// without executing it.
+    ///
+    /// The transaction must be preceded by WriteEnable, and followed by polling
+    /// until the device is no longer busy.
+    pub fn erase_transaction(&self, address: u32) -> Result<Transaction> {
+        self.check_address_length(address, 1)?;
+        let opcode = self.address_opcode(self.erase_opcode);
+        Ok(Transaction::erase(opcode, address, self.address_bytes).with_protocol(self.protocol))
     }
 
     /// Read `length` bytes of data from the attached flash, starting at `address`.
@@ -398,7 +1893,237 @@ where
     /// and may be faster for very short reads as it does not require a dummy byte.
     pub fn legacy_read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
         self.check_address_length(address, length)?;
-        self.exchange(Command::ReadData(Address24Bits(address)), &[], length)
+        self.read_chunks(address, length, false)
+    }
+
+    /// Read `length` bytes starting at `address`, using the configured read instruction
+    /// if `fast` is set, otherwise ReadData.
+    ///
+    /// The read is expanded to whole `transfer_alignment()` units and split into
+    /// transfers of up to `max_transfer_size()` bytes, and the requested data returned.
+    fn read_chunks(&mut self, address: u32, length: usize, fast: bool) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(length);
+        self.read_chunks_with(address, length, fast, |chunk| data.extend_from_slice(chunk))?;
+        Ok(data)
+    }
+
+    /// Read `length` bytes starting at `address` as `read_chunks()`, passing the data of
+    /// each transfer, less any alignment padding, to `sink` in order.
+    fn read_chunks_with(
+        &mut self,
+        address: u32,
+        length: usize,
+        fast: bool,
+        mut sink: impl FnMut(&[u8]),
+    ) -> Result<()> {
+        let (start, end) = self.align_transfer(address as usize, address as usize + length);
+        let wanted = address as usize..address as usize + length;
+        let chunk = self.transfer_chunk_size();
+        // Encode the mode bits and dummy bytes which follow the command and address.
+        let tx = Transaction::read(&self.read_parameters, 0, self.address_bytes, 0)
+            .with_protocol(self.protocol);
+        let mut header = alloc::vec![0; tx.header_len()];
+        tx.write_header(&mut header)?;
+        let mut addr = start;
+        while addr < end {
+            let size = usize::min(usize::min(chunk, end - addr), self.segment_end(addr) - addr);
+            let rx = self.retrying(self.retry.reads, |flash| {
+                if fast {
+                    let opcode = flash.read_parameters.opcode;
+                    let command = flash.address_command(opcode, addr as u32)?;
+                    flash.exchange(command, &header[command.len()..], size)
+                } else {
+                    let opcode = CommandOpCode::ReadData.into();
+                    let command = flash.address_command(opcode, addr as u32)?;
+                    flash.exchange(command, &[], size)
+                }
+            })?;
+            let lo = usize::max(addr, wanted.start);
+            let hi = usize::min(addr + size, wanted.end);
+            if lo < hi {
+                sink(&rx[lo - addr..hi - addr]);
+            }
+            addr += size;
+        }
+        Ok(())
+    }
+
+    /// Run the SFDP sector map configuration detection commands, returning the
+    /// current configuration ID.
+    fn detect_sector_config(&mut self, sector_map: &SFDPSectorMap) -> Result<u8> {
+        let mut id = 0;
+        for detect in sector_map.detect.iter() {
+            let address_bytes = detect.address_bytes.unwrap_or(self.address_bytes);
+            let address = detect
+                .address
+                .map(|address| self.encode_address(address, address_bytes))
+                .transpose()?;
+            let command = Command::try_from_byte(detect.opcode, address)?;
+            let dummy = alloc::vec![0; (detect.dummy_cycles as usize).div_ceil(8)];
+            let data = self.exchange(command, &dummy, 1)?;
+            id = (id << 1) | (data[0] & detect.mask != 0) as u8;
+        }
+        log::debug!("Detected sector map configuration {}", id);
+        Ok(id)
+    }
+
+    /// Get the opcode to send for the address-taking instruction `opcode`.
+    ///
+    /// With 4-byte addresses, the equivalent native 4-byte address instruction is used,
+    /// so the device never needs to be switched into 4-byte address mode. If the SFDP
+    /// 4-Byte Address Instruction Table was read, it provides the erase opcodes and is
+    /// checked for support of other instructions.
+    fn address_opcode(&self, opcode: u8) -> u8 {
+        if self.address_bytes != 4 {
+            return opcode;
+        }
+        let opcode4b = four_byte_opcode(opcode);
+        let params = match self.params {
+            Some(params) => params,
+            None => return opcode4b,
+        };
+        let four_byte = match params.four_byte_instructions {
+            Some(four_byte) => four_byte,
+            None => return opcode4b,
+        };
+        let erase_type = params
+            .erase_insts
+            .iter()
+            .position(|inst| inst.map(|inst| inst.opcode) == Some(opcode));
+        if let Some(erase_opcode) = erase_type.and_then(|i| four_byte.erase_opcodes[i]) {
+            return erase_opcode;
+        }
+        if !four_byte.supports(opcode4b) {
+            log::warn!(
+                "4-byte address instruction 0x{:02X} not listed in SFDP 4BAIT",
+                opcode4b
+            );
+        }
+        opcode4b
+    }
+
+    /// Build the command for the address-taking instruction `opcode` at `address`,
+    /// using the 4-byte address instruction if configured for 4-byte addresses.
+    ///
+    /// On stacked-die devices, the die holding `address` is selected first.
+    fn address_command(&mut self, opcode: u8, address: u32) -> Result<Command> {
+        let address = self.device_address(address)?;
+        let address = self.encode_address(address, self.address_bytes)?;
+        Command::try_from_byte(self.address_opcode(opcode), Some(address))
+    }
+
+    /// Encode `address` to send with `address_bytes` bytes, rejecting addresses which
+    /// do not fit in 3 bytes rather than truncating them.
+    fn encode_address(
+        &self,
+        address: u32,
+        address_bytes: u8,
+    ) -> Result<Either<Address24Bits, Address32Bits>> {
+        match address_bytes {
+            4 => Ok(Either::Right(Address32Bits(address))),
+            _ => Address24Bits::try_from(address).map(Either::Left),
+        }
+    }
+
+    /// Build the command for `opcode` at `address`, for instructions such as the Winbond
+    /// block lock instructions which have no 4-byte address variant, and instead take a
+    /// 4-byte address when configured for 4-byte addresses.
+    ///
+    /// As with `address_command()`, the die and bank holding `address` are selected first.
+    pub(crate) fn mode_address_command(&mut self, opcode: u8, address: u32) -> Result<Command> {
+        let address = self.device_address(address)?;
+        match self.encode_address(address, self.address_bytes)? {
+            Either::Left(address) => Command::try_from_byte(opcode, Some(Either::Left(address))),
+            Either::Right(address) => Ok(Command::Raw4B(opcode, address)),
+        }
+    }
+
+    /// Select the die and bank holding `address` if required, returning the address to
+    /// send to the device.
+    fn device_address(&mut self, address: u32) -> Result<u32> {
+        let mut address = address;
+        if self.dies > 1 {
+            let die_size = self.die_size()?;
+            let die = (address as usize / die_size) as u8;
+            if die != self.die {
+                self.select_die(die)?;
+            }
+            address = (address as usize % die_size) as u32;
+        }
+        if self.address_extended() {
+            let upper = (address >> 24) as u8;
+            if self.upper_address != Some(upper) {
+                self.write_upper_address(upper)?;
+            }
+            address &= 0xFF_FFFF;
+        }
+        Ok(address)
+    }
+
+    /// Check if 3-byte addresses are extended by a register holding the upper address bits.
+    fn address_extended(&self) -> bool {
+        self.address_bytes == 3 && self.address_extension != AddressExtension::None
+    }
+
+    /// Write `upper` as the address bits above A23 using the address extension.
+    fn write_upper_address(&mut self, upper: u8) -> Result<()> {
+        log::trace!("Selecting 16MB bank {}", upper);
+        match self.address_extension {
+            AddressExtension::None => return Ok(()),
+            AddressExtension::BankRegister => {
+                let mut bank = BankRegister(0);
+                bank.set_bank(upper);
+                self.write(Command::WriteBankRegister, &[bank.0])?;
+            }
+            AddressExtension::ExtendedAddressRegister => {
+                self.write_enable()?;
+                self.write(Command::WriteExtendedAddressRegister, &[upper])?;
+            }
+        }
+        self.upper_address = Some(upper);
+        Ok(())
+    }
+
+    /// Get the size of each die of a stacked-die device.
+    fn die_size(&self) -> Result<usize> {
+        match self.capacity {
+            Some(capacity) => Ok(capacity / self.dies as usize),
+            None => {
+                log::error!("Capacity must be known to address stacked dies");
+                Err(Error::InvalidConfiguration)
+            }
+        }
+    }
+
+    /// Get the end of the contiguous region of device addresses holding `address`,
+    /// which a single transfer must not cross.
+    fn segment_end(&self, address: usize) -> usize {
+        let die_end = match self.die_size() {
+            Ok(die_size) if self.dies > 1 => (address / die_size + 1) * die_size,
+            _ => usize::MAX,
+        };
+        if self.address_extended() {
+            // Banks are aligned within each die, as the die size is a multiple of 16MB.
+            usize::min(die_end, (address / 0x100_0000 + 1) * 0x100_0000)
+        } else {
+            die_end
+        }
+    }
+
+    /// Expand the range `start..end` to whole units of the transport's transfer alignment.
+    fn align_transfer(&self, start: usize, end: usize) -> (usize, usize) {
+        let align = self.access.transfer_alignment();
+        (start - (start % align), end.next_multiple_of(align))
+    }
+
+    /// Get the largest number of bytes to read or program in one transfer:
+    /// the transport's maximum transfer size rounded down to its alignment.
+    fn transfer_chunk_size(&self) -> usize {
+        let align = self.access.transfer_alignment();
+        match self.access.max_transfer_size() {
+            Some(max) => usize::max(max - (max % align), align),
+            None => usize::MAX,
+        }
     }
 
     /// Read `length` bytes of data from the attached flash, starting at `address`.
@@ -417,8 +2142,9 @@ where
         cb(0);
         for addr in (start..end).step_by(chunk_size) {
             let size = usize::min(chunk_size, end - addr);
+            let command = self.address_command(CommandOpCode::FastRead.into(), addr as u32)?;
             data.append(&mut self.exchange(
-                Command::FastRead(Address24Bits(addr as u32)),
+                command,
                 &[0], // 1 dummy bytes
                 size,
             )?);
@@ -453,18 +2179,350 @@ where
         result
     }
 
-    /// Erase entire flash chip.
-    ///
-    /// This method uses the ChipErase instruction, so no progress information
-    /// is available, but the typical and maximum times taken may be available
-    /// from the SFDP parameters.
-    ///
-    /// Returns only after erase operation is complete.
-    pub fn erase(&mut self) -> Result<()> {
+    /// Check whether `length` bytes starting at `address` are all erased (0xFF).
+    ///
+    /// The memory is read in chunks, so no buffer for the entire range is required.
+    pub fn is_erased(&mut self, address: u32, length: usize) -> Result<bool> {
+        if length == 0 {
+            return Ok(true);
+        }
+        self.check_address_length(address, length)?;
+        let start = address as usize;
+        let end = start + length;
+        for addr in (start..end).step_by(Self::STREAM_CHUNK) {
+            let size = usize::min(Self::STREAM_CHUNK, end - addr);
+            if self.read(addr as u32, size)?.iter().any(|x| *x != 0xFF) {
+                log::trace!("Found non-erased data in chunk at 0x{:08X}", addr);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Compute the CRC-32 of `length` bytes starting at `address`.
+    ///
+    /// The memory is read in chunks using FastRead, so no buffer for the entire
+    /// range is required. See the `crc` module for details of the CRC used.
+    pub fn crc32(&mut self, address: u32, length: usize) -> Result<u32> {
+        let mut crc = crc::Crc32::new();
+        if length > 0 {
+            self.check_address_length(address, length)?;
+        }
+        let start = address as usize;
+        let end = start + length;
+        for addr in (start..end).step_by(Self::STREAM_CHUNK) {
+            let size = usize::min(Self::STREAM_CHUNK, end - addr);
+            crc.update(&self.read(addr as u32, size)?);
+        }
+        Ok(crc.finish())
+    }
+
+    /// Compute the CRC-32 of `length` bytes starting at `address` as `crc32()`, yielding
+    /// to the executor after each chunk.
+    ///
+    /// Each read still blocks, as `FlashAccess` is blocking, but other tasks can run
+    /// between chunks while a large image is validated.
+    pub async fn crc32_async(&mut self, address: u32, length: usize) -> Result<u32> {
+        let mut crc = crc::Crc32::new();
+        if length > 0 {
+            self.check_address_length(address, length)?;
+        }
+        let start = address as usize;
+        let end = start + length;
+        for addr in (start..end).step_by(Self::STREAM_CHUNK) {
+            let size = usize::min(Self::STREAM_CHUNK, end - addr);
+            crc.update(&self.read(addr as u32, size)?);
+            crc::yield_now().await;
+        }
+        Ok(crc.finish())
+    }
+
+    /// Check each sector of `range` is either erased or has the CRC-32 returned by
+    /// `expected` for its address, reporting any suspect sectors such as those left
+    /// by an interrupted erase.
+    ///
+    /// See the `integrity` module for details.
+    pub fn check_integrity(
+        &mut self,
+        range: core::ops::Range<u32>,
+        expected: impl FnMut(u32) -> Option<u32>,
+    ) -> Result<IntegrityReport> {
+        integrity::check_integrity(self, range, expected)
+    }
+
+    /// Erase `length` bytes starting at `address`.
+    ///
+    /// The range must be aligned to the available erase instructions, so that
+    /// no data outside the range is erased; otherwise `Misaligned` is returned.
+    /// When available, SFDP parameters are used to generate an efficient
+    /// sequence of erase instructions, as for `program()`.
+    ///
+    /// If `skip_erased` is true, each sector or block is first read back, and
+    /// is not erased if it is already all 0xFF. This is usually much faster
+    /// than erasing when most of the range is already blank.
+    pub fn erase_range(&mut self, address: u32, length: usize, skip_erased: bool) -> Result<()> {
+        let plan = self.plan_erase(address, length)?;
+        let plan = if skip_erased {
+            let mut ops = Vec::new();
+            for op in plan.0.into_iter() {
+                if self.is_erased(op.2, op.1)? {
+                    log::trace!("Skipping erase at 0x{:08X}: already erased", op.2);
+                } else {
+                    ops.push(op);
+                }
+            }
+            ErasePlan(ops)
+        } else {
+            plan
+        };
+        self.run_erase_plan(&plan, |_| {})
+    }
+
+    /// Work out the erase operations needed to erase exactly `length` bytes starting
+    /// at `address`, without running them.
+    ///
+    /// The fewest operations are chosen from the 4kB, 32kB, and 64kB (or other sized)
+    /// erase instructions available from the layout, SFDP parameters, or `erase_size`,
+    /// using a single chip erase when the range covers the whole memory. The range must
+    /// be aligned to the available erase instructions, otherwise `Misaligned` is
+    /// returned. The plan may be inspected, for example to check the expected erase
+    /// time, and then run using `run_erase()`.
+    pub fn plan_erase(&self, address: u32, length: usize) -> Result<ErasePlan> {
+        if length == 0 {
+            return Ok(ErasePlan(Vec::new()));
+        }
+        self.check_address_length(address, length)?;
+        if address == 0 && self.capacity == Some(length) {
+            let duration = self.chip_erase_times().map(|(typ, _)| typ);
+            log::debug!("Erase plan: chip erase");
+            return Ok(ErasePlan(alloc::vec![(
+                self.chip_erase_command().opcode(),
+                length,
+                0,
+                duration
+            )]));
+        }
+        let plan = self.make_erase_plan(address, length)?;
+        let (_, size, base, _) = plan.0.last().unwrap();
+        if plan.0[0].2 != address {
+            log::error!(
+                "Erase start 0x{:08X} is not aligned to an erase size",
+                address
+            );
+            return Err(Error::Misaligned {
+                address,
+                required: plan.0[0].1 as u32,
+            });
+        } else if (*base as usize + *size) != address as usize + length {
+            let end = address + length as u32;
+            log::error!("Erase end 0x{:08X} is not aligned to an erase size", end);
+            return Err(Error::Misaligned {
+                address: end,
+                required: *size as u32,
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Run the erase operations in `plan`, as created by `plan_erase()`.
+    ///
+    /// Returns only after all erase operations are complete.
+    pub fn run_erase(&mut self, plan: &ErasePlan) -> Result<()> {
+        self.run_erase_plan(plan, |_| {})
+    }
+
+    /// Erase `length` bytes starting at `address` in `space`.
+    ///
+    /// See `erase_range()`.
+    pub fn erase_range_at(
+        &mut self,
+        space: AddressSpace,
+        address: u32,
+        length: usize,
+        skip_erased: bool,
+    ) -> Result<()> {
+        let address = self.to_storage(space, address)?;
+        self.erase_range(address, length, skip_erased)
+    }
+
+    /// Erase entire flash chip.
+    ///
+    /// This method uses the ChipErase instruction, so no progress information
+    /// is available, but the typical and maximum times taken may be available
+    /// from the SFDP parameters. Any progress sink is only notified when the
+    /// erase starts, with the typical time as the estimate, and when it completes.
+    ///
+    /// Returns only after erase operation is complete.
+    pub fn erase(&mut self) -> Result<()> {
+        let duration = self.chip_erase_times().map(|(typ, _)| typ);
+        self.report_erased(0, 1, duration);
+        self.chip_erase(None)?;
+        self.report_erased(1, 1, Some(Duration::ZERO));
+        Ok(())
+    }
+
+    /// Get the Chip Erase instruction implemented by the device, 0xC7 unless the
+    /// `chip_erase_60` quirk is set.
+    fn chip_erase_command(&self) -> Command {
+        if self.quirks.chip_erase_60 {
+            Command::ChipEraseAlt
+        } else {
+            Command::ChipErase
+        }
+    }
+
+    /// Check if `opcode` is either encoding of Chip Erase.
+    fn is_chip_erase(opcode: u8) -> bool {
+        matches!(
+            CommandOpCode::try_from(opcode),
+            Ok(CommandOpCode::ChipErase | CommandOpCode::ChipEraseAlt)
+        )
+    }
+
+    /// Send Chip Erase and wait for it to complete, waiting for half of `duration` first
+    /// if given. Each die of a stacked-die device is erased in turn.
+    fn chip_erase(&mut self, duration: Option<Duration>) -> Result<()> {
+        if let Some(capacity) = self.capacity {
+            self.check_unprotected(0, capacity)?;
+        }
+        let timeout = self.chip_erase_times().map(|(_, max)| max);
+        for die in 0..self.dies {
+            if self.dies > 1 {
+                self.select_die(die)?;
+            }
+            self.operation_enable()?;
+            self.command(self.chip_erase_command())?;
+            self.wait_for_operation(0, duration, timeout)?;
+        }
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.set_all_erased();
+        }
+        Ok(())
+    }
+
+    /// Erase entire flash chip, even if parts of it are protected.
+    ///
+    /// Chip Erase is ignored or rejected by devices with any memory protected, so this
+    /// records the BP bits of status register 1, and the CMP bit of status register 2
+    /// if the `combined_status_read` quirk is set, then clears them for the erase, and
+    /// afterwards restores them. If individual block protection is used, give its
+    /// layout as `block_locks`, and the lock bit of each unit is likewise recorded,
+    /// cleared using Global Block Unlock, and restored.
+    ///
+    /// The status registers are only written using their volatile copies, so if power is
+    /// lost part way through, the original protection is in force at the next power up.
+    /// Protection is restored even if clearing it or the erase fails: each restore step
+    /// is attempted, and the first error encountered is returned. Chip erase may take
+    /// minutes, so any `WaitReady` hook is given the maximum chip erase time from SFDP,
+    /// rather than the typical time, as the expected duration.
+    pub fn chip_erase_force(&mut self, block_locks: Option<&IndividualBlockLocks>) -> Result<()> {
+        let status1 = self.read_status1()?;
+        let status2 = if self.quirks.combined_status_read {
+            Some(self.read_status2()?)
+        } else {
+            None
+        };
+        let locked = match block_locks {
+            Some(layout) => self.read_block_locks(layout)?,
+            None => Vec::new(),
+        };
+        let (bp0, bp1, bp2) = status1.get_block_protect();
+        let bp = bp0 || bp1 || bp2;
+        let cmp = status2.is_some_and(|status2| status2.get_cmp());
+        log::debug!(
+            "Clearing protection for chip erase: BP {}, CMP {}, {} locked units",
+            bp,
+            cmp,
+            locked.len()
+        );
+        let result = self.clear_protection_and_erase(status1, status2, bp, cmp, &locked);
+
+        log::debug!("Restoring protection after chip erase");
+        let mut restored = Ok(());
+        for address in locked {
+            restored = restored.and(self.lock_block(address));
+        }
+        if bp {
+            restored = restored.and(
+                self.write_status1(status1, StatusWriteMode::Volatile)
+                    .and_then(|()| self.wait_while_busy()),
+            );
+        }
+        if cmp {
+            restored = restored.and(
+                self.write_status2(status2.unwrap(), StatusWriteMode::Volatile)
+                    .and_then(|()| self.wait_while_busy()),
+            );
+        }
+        if let Err(err) = &restored {
+            log::error!("Failed to restore protection after chip erase: {:?}", err);
+        }
+        result.and(restored)
+    }
+
+    /// Clear the protection recorded by `chip_erase_force()`, then erase the chip.
+    fn clear_protection_and_erase(
+        &mut self,
+        status1: StatusRegister1,
+        status2: Option<StatusRegister2>,
+        bp: bool,
+        cmp: bool,
+        locked: &[u32],
+    ) -> Result<()> {
+        if cmp {
+            let mut cleared = status2.unwrap();
+            cleared.set_cmp(false);
+            self.write_status2(cleared, StatusWriteMode::Volatile)?;
+            self.wait_while_busy()?;
+        }
+        if bp {
+            let mut cleared = status1;
+            cleared.set_block_protect(false, false, false);
+            self.write_status1(cleared, StatusWriteMode::Volatile)?;
+            self.wait_while_busy()?;
+        }
+        if !locked.is_empty() {
+            self.write_enable()?;
+            self.command(Command::GlobalBlockUnlock)?;
+        }
+
+        let times = self.chip_erase_times();
+        let typ = times.map(|(typ, _)| typ);
+        let max = times.map(|(_, max)| max);
+        let duration = typ.filter(|_| self.wait_ready.is_none()).or(max);
+        self.report_erased(0, 1, typ);
+        self.chip_erase(duration)?;
+        self.report_erased(1, 1, Some(Duration::ZERO));
+        Ok(())
+    }
+
+    /// Set the individual block lock bit of the unit at `address`.
+    fn lock_block(&mut self, address: u32) -> Result<()> {
+        let command =
+            self.mode_address_command(CommandOpCode::IndividualBlockLock as u8, address)?;
         self.write_enable()?;
-        self.command(Command::ChipErase)?;
-        self.wait_while_busy()?;
-        Ok(())
+        self.command(command)
+    }
+
+    /// Get the base address of each individual block lock unit of `layout` which is
+    /// locked, or none if individual block protection is not selected.
+    fn read_block_locks(&mut self, layout: &IndividualBlockLocks) -> Result<Vec<u32>> {
+        if !self.read_status3()?.get_wps() {
+            return Ok(Vec::new());
+        }
+        let Some(capacity) = self.capacity else {
+            log::error!("Capacity must be known to read block locks");
+            return Err(Error::InvalidAddress { address: 0 });
+        };
+        let mut locked = Vec::new();
+        for address in layout.lock_units(&(0..capacity as u32), capacity) {
+            let command = self.mode_address_command(CommandOpCode::ReadBlockLock as u8, address)?;
+            let data = self.exchange(command, &[], 1)?;
+            if data[0] & 1 != 0 {
+                locked.push(address);
+            }
+        }
+        Ok(locked)
     }
 
     /// Erase entire flash chip.
@@ -476,8 +2534,8 @@ where
     /// otherwise the progress bar is drawn as a spinner.
     #[cfg(feature = "std")]
     pub fn erase_progress(&mut self) -> Result<()> {
-        let time = self.params.map(|p| p.timing.map(|t| t.chip_erase_time_typ));
-        let pb = if let Some(Some(time)) = time {
+        let time = self.chip_erase_times().map(|(typ, _)| typ);
+        let pb = if let Some(time) = time {
             ProgressBar::new(time.as_millis() as u64).with_style(
                 ProgressStyle::with_template(" {msg} [{bar:40.cyan/black}] {elapsed} < {eta}")
                     .unwrap()
@@ -488,11 +2546,19 @@ where
         };
         pb.set_message("Erasing");
         let t0 = Instant::now();
-        self.write_enable()?;
-        self.command(Command::ChipErase)?;
-        while self.is_busy()? {
-            let t = t0.elapsed().as_millis() as u64;
-            pb.set_position(t);
+        for die in 0..self.dies {
+            if self.dies > 1 {
+                self.select_die(die)?;
+            }
+            self.write_enable()?;
+            self.command(self.chip_erase_command())?;
+            while self.is_busy()? {
+                let t = t0.elapsed().as_millis() as u64;
+                pb.set_position(t);
+            }
+        }
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.set_all_erased();
         }
         pb.finish();
         Ok(())
@@ -569,12 +2635,360 @@ where
         Ok(())
     }
 
+    /// Update `data` at `address`, preserving all other data in the affected sectors.
+    ///
+    /// Each sector touched by `data` is read into `scratch`, which must be at least
+    /// `erase_size` bytes long, and merged with the new data. If the new data only
+    /// clears bits, it is programmed directly; otherwise the sector is erased and
+    /// the merged contents reprogrammed. Sectors whose contents would not change
+    /// are left untouched.
+    ///
+    /// This allows byte-granular updates without the caller managing erase granularity,
+    /// at the cost of a sector erase for most updates. The sector erase size and opcode
+    /// must be known, either set manually or discovered using SFDP.
+    pub fn update(&mut self, address: u32, data: &[u8], scratch: &mut [u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.check_address_length(address, data.len())?;
+        let scratch = self.sector_scratch(scratch)?;
+        let sector_size = scratch.len();
+
+        let start = address as usize;
+        let end = start + data.len();
+        let mut sector = start - (start % sector_size);
+        while sector < end {
+            let (lo, hi) = (
+                usize::max(start, sector),
+                usize::min(end, sector + sector_size),
+            );
+            let new = &data[lo - start..hi - start];
+            self.read_into(sector as u32, scratch)?;
+            let old = &mut scratch[lo - sector..hi - sector];
+            if old != new {
+                if old.iter().zip(new).all(|(o, n)| n & !o == 0) {
+                    log::trace!("Updating 0x{:08X} without erase", lo);
+                    self.program_data(lo as u32, new)?;
+                } else {
+                    log::trace!(
+                        "Updating 0x{:08X} with erase of sector 0x{:08X}",
+                        lo,
+                        sector
+                    );
+                    old.copy_from_slice(new);
+                    self.erase_sector(sector as u32)?;
+                    self.program_data(sector as u32, scratch)?;
+                }
+            }
+            sector += sector_size;
+        }
+
+        Ok(())
+    }
+
+    /// Update the image at `address` to `image`, erasing and programming only what changed.
+    ///
+    /// Each sector touched by `image` is read into `scratch`, which must be at least
+    /// `erase_size` bytes long, and compared with the new image page by page. Sectors
+    /// which are unchanged are left untouched. If the changed pages of a sector only
+    /// clear bits, just those pages are programmed; otherwise the sector is erased,
+    /// and only its pages which are not left blank are reprogrammed, including any
+    /// existing data in the sector outside the image. When an update changes a small
+    /// part of an image, this avoids most of the erase and program time, and wear,
+    /// of programming the whole image with `program()`.
+    ///
+    /// Returns the number of sectors erased and pages programmed.
+    pub fn update_image(
+        &mut self,
+        address: u32,
+        image: &[u8],
+        scratch: &mut [u8],
+    ) -> Result<ImageUpdate> {
+        let mut summary = ImageUpdate::default();
+        if image.is_empty() {
+            return Ok(summary);
+        }
+        self.check_address_length(address, image.len())?;
+        let scratch = self.sector_scratch(scratch)?;
+        let sector_size = scratch.len();
+        let page_size = usize::min(self.page_size.unwrap_or(256), sector_size);
+
+        let start = address as usize;
+        let end = start + image.len();
+        let mut sector = start - (start % sector_size);
+        while sector < end {
+            let (lo, hi) = (
+                usize::max(start, sector),
+                usize::min(end, sector + sector_size),
+            );
+            let new = &image[lo - start..hi - start];
+            self.read_into(sector as u32, scratch)?;
+            let old = &mut scratch[lo - sector..hi - sector];
+            if old.iter().zip(new).any(|(o, n)| n & !o != 0) {
+                log::trace!("Updating sector 0x{:08X} with erase", sector);
+                old.copy_from_slice(new);
+                self.erase_sector(sector as u32)?;
+                summary.sectors_erased += 1;
+                for (idx, page) in scratch.chunks(page_size).enumerate() {
+                    if page.iter().any(|byte| *byte != 0xFF) {
+                        self.program_data((sector + idx * page_size) as u32, page)?;
+                        summary.pages_programmed += 1;
+                    }
+                }
+            } else {
+                let mut page = lo - (lo % page_size);
+                while page < hi {
+                    let (from, to) = (usize::max(lo, page), usize::min(hi, page + page_size));
+                    let new = &image[from - start..to - start];
+                    if scratch[from - sector..to - sector] != *new {
+                        log::trace!("Updating page 0x{:08X} without erase", page);
+                        self.program_data(from as u32, new)?;
+                        summary.pages_programmed += 1;
+                    }
+                    page += page_size;
+                }
+            }
+            sector += sector_size;
+        }
+
+        Ok(summary)
+    }
+
+    /// Get the first `erase_size` bytes of `scratch`, for a copy of one sector.
+    fn sector_scratch<'s>(&self, scratch: &'s mut [u8]) -> Result<&'s mut [u8]> {
+        let sector_size = match self.erase_size {
+            Some(erase_size) => erase_size,
+            None => {
+                log::warn!("Sector erase size not known.");
+                log::warn!("Try setting one manually using `Flash::set_erase_size()`.");
+                return Err(Error::NoEraseInstruction);
+            }
+        };
+        if scratch.len() < sector_size {
+            log::error!("Scratch buffer smaller than an erase sector");
+            return Err(Error::BufferTooSmall {
+                length: scratch.len(),
+                required: sector_size,
+            });
+        }
+        Ok(&mut scratch[..sector_size])
+    }
+
+    /// Erase the sector containing `address`.
+    ///
+    /// The erase opcode is set by `erase_opcode`, which is automatically set
+    /// when SFDP parameters are read.
+    ///
+    /// Returns only after erase operation is complete.
+    pub fn erase_sector(&mut self, address: u32) -> Result<()> {
+        self.check_address_length(address, 1)?;
+        self.check_unprotected(address, 1)?;
+        let opcode = self.erase_opcode;
+        let (duration, timeout) = self.erase_times(opcode);
+        let command = self.address_command(opcode, address)?;
+        self.operation_enable()?;
+        self.command(command)?;
+        self.wait_for_operation(address, duration, timeout)?;
+        self.mark_sector_erased(address);
+        Ok(())
+    }
+
+    /// Erase the sectors containing each of `addresses`, in order.
+    ///
+    /// This is equivalent to calling `erase_sector()` for each address, but all addresses
+    /// are checked before anything is erased, and progress is reported across the whole
+    /// batch. If `verify` is true, each sector is read back after it is erased, and a
+    /// `ReadbackError` returned if it is not erased; this requires the erase size.
+    pub fn erase_sectors(
+        &mut self,
+        addresses: impl IntoIterator<Item = u32>,
+        verify: bool,
+    ) -> Result<()> {
+        let addresses: Vec<u32> = addresses.into_iter().collect();
+        for address in &addresses {
+            self.check_address_length(*address, 1)?;
+            self.check_unprotected(*address, 1)?;
+        }
+        let erase_size = match (verify, self.erase_size) {
+            (false, _) => 0,
+            (true, Some(erase_size)) => erase_size,
+            (true, None) => {
+                log::error!("Erase size must be known to verify erased sectors");
+                return Err(Error::InvalidConfiguration);
+            }
+        };
+        let opcode = self.erase_opcode;
+        let (duration, timeout) = self.erase_times(opcode);
+        let ops = addresses.len();
+        self.report_erased(0, ops, duration.map(|d| d * ops as u32));
+        for (idx, address) in addresses.into_iter().enumerate() {
+            log::trace!("Erasing sector at 0x{:08X}", address);
+            let command = self.address_command(opcode, address)?;
+            self.operation_enable()?;
+            self.command(command)?;
+            self.wait_for_operation(address, duration, timeout)?;
+            self.mark_sector_erased(address);
+            if verify {
+                let base = address - address % erase_size as u32;
+                let erased = self.read(base, erase_size)?;
+                self.verify_readback(base, &alloc::vec![0xFF; erase_size], &erased)?;
+            }
+            let remaining = duration.map(|d| d * (ops - idx - 1) as u32);
+            self.report_erased(idx + 1, ops, remaining);
+        }
+        Ok(())
+    }
+
+    /// Mark the sector erased by `erase_opcode` at `address` as erased in any dirty map,
+    /// if the erase size is known.
+    fn mark_sector_erased(&mut self, address: u32) {
+        if let (Some(dirty), Some(erase_size)) = (self.dirty.as_mut(), self.erase_size) {
+            let base = address - address % erase_size as u32;
+            dirty.mark_erased(base, erase_size);
+        }
+    }
+
+    /// Get the typical and maximum times taken by the erase instruction `opcode`, if known.
+    fn erase_times(&self, opcode: u8) -> (Option<Duration>, Option<Duration>) {
+        if let Some(timings) = self.timings.filter(|_| opcode == self.erase_opcode) {
+            return (
+                Some(timings.sector_erase_typ),
+                Some(timings.sector_erase_max),
+            );
+        }
+        self.params
+            .and_then(|params| {
+                params
+                    .erase_insts
+                    .iter()
+                    .flatten()
+                    .find(|inst| inst.opcode == opcode)
+                    .map(|inst| (inst.time_typ, inst.time_max))
+            })
+            .unwrap_or((None, None))
+    }
+
+    /// Get the typical and maximum times taken to program a page, if known.
+    fn page_program_times(&self) -> Option<(Duration, Duration)> {
+        match self.timings {
+            Some(timings) => Some((timings.page_program_typ, timings.page_program_max)),
+            None => self
+                .params
+                .and_then(|params| params.timing)
+                .map(|timing| (timing.page_prog_time_typ, timing.page_prog_time_max)),
+        }
+    }
+
+    /// Get the typical and maximum times taken to erase the chip, if known.
+    fn chip_erase_times(&self) -> Option<(Duration, Duration)> {
+        match self.timings {
+            Some(timings) => Some((timings.chip_erase_typ, timings.chip_erase_max)),
+            None => self
+                .params
+                .and_then(|params| params.timing)
+                .map(|timing| (timing.chip_erase_time_typ, timing.chip_erase_time_max)),
+        }
+    }
+
+    /// Wait for a program or erase taking `duration` typically and `timeout` at most
+    /// to complete, sleeping for half the typical time if known before polling every
+    /// eighth of it, or passing it to the `WaitReady` hook if set. Without a typical
+    /// time, the device is polled continuously, or every sixteenth of `timeout` if
+    /// timeouts are enabled.
+    ///
+    /// If timeouts are enabled, returns `Error::Timeout` if the device is still busy once
+    /// the delays requested add up to `timeout`.
+    fn wait_for_completion(
+        &mut self,
+        duration: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let timeout = timeout.filter(|_| self.timeouts);
+        if self.wait_ready.is_some() {
+            return self.wait_with_hook(duration, timeout);
+        }
+        let interval = match (duration, timeout) {
+            (Some(duration), _) => duration / 8,
+            (None, Some(timeout)) => timeout / 16,
+            (None, None) => return self.wait_while_busy(),
+        };
+        let interval = interval.max(Duration::from_micros(1));
+        let mut waited = duration.map_or(Duration::ZERO, |duration| duration / 2);
+        if !waited.is_zero() {
+            self.access.delay(waited);
+        }
+        while self.is_busy()? {
+            if timeout.is_some_and(|timeout| waited >= timeout) {
+                log::error!("Device still busy after {:?}", waited);
+                return Err(Error::Timeout);
+            }
+            self.access.delay(interval);
+            waited += interval;
+        }
+        Ok(())
+    }
+
+    /// Wait for a program or erase at `address` to complete, as `wait_for_completion()`,
+    /// then check for errors reported in the flag status register.
+    fn wait_for_operation(
+        &mut self,
+        address: u32,
+        duration: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        self.wait_for_completion(duration, timeout)?;
+        self.check_flag_status(address)
+    }
+
+    /// Call the `WaitReady` hook until the device is ready.
+    ///
+    /// The time spent in the hook is not known, so with a `timeout`, each time the hook
+    /// returns with the device still busy a sixteenth of `timeout` is waited before polling
+    /// again, and `Error::Timeout` returned once those delays add up to `timeout`.
+    fn wait_with_hook(
+        &mut self,
+        duration: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        wait::poll_until(
+            self,
+            timeout,
+            |flash, interval| flash.access.delay(interval),
+            |flash| {
+                if !flash.is_busy()? {
+                    return Ok(Some(()));
+                }
+                if let Some(hook) = &mut flash.wait_ready {
+                    hook.wait_ready(duration)?;
+                }
+                Ok(None)
+            },
+        )
+    }
+
     /// Reset the attached flash.
     ///
     /// The instruction sequence EnableReset 0x66 followed by Reset 0x99
     /// is sent by default, but if the SFDP parameters indicate that only
-    /// the 0xF0 instruction is supported for reset, that is sent instead.
+    /// the 0xF0 instruction is supported for reset, or the `reset_f0` quirk
+    /// is set, that is sent instead.
     pub fn reset(&mut self) -> Result<()> {
+        if let Some(pin) = &mut self.reset_pin {
+            log::debug!("Pulsing RESET#");
+            pin.set_low()?;
+            // Hold RESET# low for tRESET, then allow the reset recovery time, tRST,
+            // which are at most 1us and 30us on most devices.
+            self.access.delay(Duration::from_micros(1));
+            pin.set_high()?;
+            self.access.delay(Duration::from_micros(30));
+            self.write_latched = false;
+            self.busy_pending = false;
+            self.upper_address = None;
+            self.invalidate_protection(Command::Reset);
+            return Ok(());
+        }
+        // Reset clears the upper address bits, so write them again before the next access.
+        self.upper_address = None;
         let mut do_f0 = false;
         let mut do_66_99 = true;
 
@@ -586,12 +3000,16 @@ where
                 do_f0 = op_f0;
             }
         }
+        if self.quirks.reset_f0 {
+            do_66_99 = false;
+            do_f0 = true;
+        }
 
         if do_66_99 {
             self.command(Command::EnableReset)?;
             self.command(Command::Reset)
         } else if do_f0 {
-            self.command(Command::try_from_byte(0xF0, None)?)
+            self.command(Command::SoftwareReset)
         } else {
             log::error!("No reset instruction available.");
             Err(Error::NoResetInstruction)
@@ -599,8 +3017,19 @@ where
     }
 
     /// Check if any block protect bits are set in status register 1.
+    ///
+    /// If the `combined_status_read` quirk is set, status register 2 is read in the
+    /// same transaction, and a set CMP bit, which inverts the protected region,
+    /// is also taken into account.
     pub fn is_protected(&mut self) -> Result<bool> {
         log::debug!("Checking if BP bits are set");
+        if self.quirks.combined_status_read {
+            let (status1, status2) = self.read_status12()?;
+            let (bp0, bp1, bp2) = status1.get_block_protect();
+            let cmp = status2.get_cmp();
+            log::debug!("BP0: {}, BP1: {}, BP2: {}, CMP: {}", bp0, bp1, bp2, cmp);
+            return Ok((bp0 || bp1 || bp2) != cmp);
+        }
         let status1 = self.read_status1()?;
         let (bp0, bp1, bp2) = status1.get_block_protect();
         log::debug!("BP0: {}, BP1: {}, BP2: {}", bp0, bp1, bp2);
@@ -622,7 +3051,7 @@ where
         );
         let mut status1 = self.read_status1()?;
         status1.set_block_protect(bp0, bp1, bp2);
-        self.write_status1(status1)?;
+        self.write_status1(status1, StatusWriteMode::NonVolatile)?;
         self.wait_while_busy()?;
         Ok(())
     }
@@ -632,19 +3061,259 @@ where
     /// This checks and clears the block protect bits in status register 1,
     /// using the non-volatile commands if supported. If available, the SFDP
     /// parameters are used to determine the correct non-volatile instruction.
+    ///
+    /// If the `combined_status_read` quirk is set, status register 2 is read in the
+    /// same transaction, and its CMP bit is also cleared if set.
     pub fn unprotect(&mut self) -> Result<()> {
         log::debug!("Checking if BP bits are set before clearing them");
-        let mut status1 = self.read_status1()?;
+        let mut status1 = if self.quirks.combined_status_read {
+            let (status1, mut status2) = self.read_status12()?;
+            if status2.get_cmp() {
+                log::debug!("CMP bit set, clearing.");
+                status2.set_cmp(false);
+                self.write_status2(status2, StatusWriteMode::NonVolatile)?;
+                self.wait_while_busy()?;
+            }
+            status1
+        } else {
+            self.read_status1()?
+        };
         let (bp0, bp1, bp2) = status1.get_block_protect();
         if bp0 || bp1 || bp2 {
             log::debug!("Block protect bits are currently set, clearing.");
             status1.set_block_protect(false, false, false);
-            self.write_status1(status1)?;
+            self.write_status1(status1, StatusWriteMode::NonVolatile)?;
+            self.wait_while_busy()?;
+        }
+        Ok(())
+    }
+
+    /// Set the write protection scheme used by `set_protection()`, `is_address_protected()`,
+    /// and `unprotect_all()`.
+    ///
+    /// By default, `protection::BlockProtectBits` is used.
+    pub fn set_protection_scheme(&mut self, scheme: alloc::boxed::Box<dyn ProtectionScheme<A>>) {
+        self.protection = Some(scheme);
+    }
+
+    /// Protect or unprotect the memory in `range` from program and erase operations,
+    /// using the device's protection scheme.
+    ///
+    /// Schemes which cannot protect exactly `range` protect the smallest region covering it,
+    /// or when unprotecting, leave protected only memory outside it; see `ProtectionScheme`.
+    pub fn set_protection(&mut self, range: core::ops::Range<u32>, protect: bool) -> Result<()> {
+        self.with_protection(|scheme, flash| scheme.set_protection(flash, range, protect))
+    }
+
+    /// Check if `address` is protected, using the device's protection scheme.
+    pub fn is_address_protected(&mut self, address: u32) -> Result<bool> {
+        self.with_protection(|scheme, flash| scheme.is_protected(flash, address))
+    }
+
+    /// Unprotect all memory, using the device's protection scheme.
+    ///
+    /// Only the scheme set using `set_protection_scheme()` is cleared; see `unlock_all()`
+    /// to clear every protection mechanism the device may use.
+    pub fn unprotect_all(&mut self) -> Result<()> {
+        self.with_protection(|scheme, flash| scheme.unprotect_all(flash))
+    }
+
+    /// Clear every write protection mechanism the device may use, in the sequence its
+    /// manufacturer requires, so programs and erases are no longer silently ignored.
+    ///
+    /// The ID is read if it has not been already, to find the manufacturer, then:
+    ///
+    /// * On Infineon/Spansion devices, the PPBs are erased, returning `ProtectionLocked`
+    ///   if they are locked, and Global Block Unlock (0x98) clears the DYBs.
+    /// * The BP bits of status register 1 are cleared, as `unprotect()` does.
+    /// * On Winbond and GigaDevice devices, the CMP bit of status register 2 is cleared.
+    /// * Where individual block locking is active, selected by the WPS bit of status
+    ///   register 3 on Winbond and GigaDevice devices and always on Microchip SST26
+    ///   devices, Global Block Unlock clears the lock bit of every block.
+    /// * Any scheme set using `set_protection_scheme()` is cleared, as `unprotect_all()`
+    ///   does.
+    pub fn unlock_all(&mut self) -> Result<()> {
+        let id = match self.id {
+            Some(id) => id,
+            None => self.read_id()?,
+        };
+        let manufacturer = id.manufacturer();
+        // Infineon FRAM devices, in bank 6, have no sector protection.
+        if manufacturer == Manufacturer::Infineon && id.manufacturer_bank == 0 {
+            if protection::PersistentProtectionBits::is_locked(self)? {
+                log::error!("PPBs are locked, cannot unlock all memory");
+                return Err(Error::ProtectionLocked);
+            }
+            log::debug!("Erasing PPBs and clearing DYBs");
+            self.write_enable()?;
+            self.command(Command::ErasePpb)?;
+            self.wait_while_busy()?;
+            self.write_enable()?;
+            self.command(Command::GlobalBlockUnlock)?;
+            self.wait_while_busy()?;
+        }
+        self.unprotect()?;
+        let winbond = matches!(
+            manufacturer,
+            Manufacturer::Winbond | Manufacturer::GigaDevice
+        );
+        if winbond && !self.quirks.combined_status_read {
+            let mut status2 = self.read_status2()?;
+            if status2.get_cmp() {
+                log::debug!("CMP bit set, clearing.");
+                status2.set_cmp(false);
+                self.write_status2(status2, StatusWriteMode::NonVolatile)?;
+                self.wait_while_busy()?;
+            }
+        }
+        let individual_locks = match manufacturer {
+            _ if winbond => self.read_status3()?.get_wps(),
+            Manufacturer::Microchip => id.device_id_long >> 8 == 0x26,
+            _ => false,
+        };
+        if individual_locks {
+            log::debug!("Individual block locking active, unlocking all blocks.");
+            self.write_enable()?;
+            self.command(Command::GlobalBlockUnlock)?;
             self.wait_while_busy()?;
         }
+        if self.protection.is_some() {
+            self.unprotect_all()?;
+        }
+        Ok(())
+    }
+
+    /// Check programs and erases against the protection scheme before sending them,
+    /// returning `RegionProtected` for any which target protected memory, rather than
+    /// leaving the device to ignore them.
+    ///
+    /// The protection of each sector is read using `is_address_protected()` when first
+    /// needed and then cached, until an instruction which may change protection is sent.
+    /// Protection schemes may require the capacity to be known.
+    pub fn set_protection_guard(&mut self, enabled: bool) {
+        self.protection_guard = enabled.then(Vec::new);
+    }
+
+    /// Check the `length` bytes starting at `address` are not protected, if the
+    /// protection guard is enabled.
+    fn check_unprotected(&mut self, address: u32, length: usize) -> Result<()> {
+        if self.protection_guard.is_none() {
+            return Ok(());
+        }
+        let sector = self.erase_size.unwrap_or(1) as u32;
+        let end = address as u64 + length.max(1) as u64;
+        let mut base = address - address % sector;
+        while (base as u64) < end {
+            let cached = self
+                .protection_guard
+                .iter()
+                .flatten()
+                .find(|(cached, _)| *cached == base)
+                .map(|(_, protected)| *protected);
+            let protected = match cached {
+                Some(protected) => protected,
+                None => {
+                    let protected = self.is_address_protected(base)?;
+                    if let Some(cache) = &mut self.protection_guard {
+                        cache.push((base, protected));
+                    }
+                    protected
+                }
+            };
+            if protected {
+                let address = u32::max(base, address);
+                log::error!("Refusing to modify protected memory at 0x{:08X}", address);
+                return Err(Error::RegionProtected { address });
+            }
+            base = match base.checked_add(sector) {
+                Some(next) => next,
+                None => break,
+            };
+        }
         Ok(())
     }
 
+    /// Discard the protection cached by the protection guard, if `command` may change it.
+    fn invalidate_protection(&mut self, command: Command) {
+        let changes = matches!(
+            command,
+            Command::WriteStatusRegister1
+                | Command::WriteStatusRegister2
+                | Command::WriteStatusRegister3
+                | Command::WriteStatusRegister2Alt
+                | Command::WriteAnyRegister(_)
+                | Command::IndividualBlockLock(_)
+                | Command::IndividualBlockUnlock(_)
+                | Command::GlobalBlockLock
+                | Command::GlobalBlockUnlock
+                | Command::WriteBlockProtection
+                | Command::WriteDyb(_)
+                | Command::ProgramPpb(_)
+                | Command::ErasePpb
+                | Command::WritePpbLock
+                | Command::UnlockPassword
+                | Command::Reset
+                | Command::SoftwareReset
+                | Command::Raw(..)
+                | Command::Raw4B(..)
+        );
+        if let Some(cache) = self.protection_guard.as_mut().filter(|_| changes) {
+            cache.clear();
+        }
+    }
+
+    /// Read the flag status register.
+    pub fn read_flag_status(&mut self) -> Result<FlagStatusRegister> {
+        let data = self.exchange(Command::ReadFlagStatusRegister, &[], 1)?;
+        Ok(FlagStatusRegister(data[0]))
+    }
+
+    /// Clear the error bits of the flag status register.
+    pub fn clear_flag_status(&mut self) -> Result<()> {
+        self.flag_errors = 0;
+        self.command(Command::ClearFlagStatusRegister)
+    }
+
+    /// Check the flag status register error bits seen while polling for an operation at
+    /// `address` to complete, clearing them if set.
+    ///
+    /// A protection error is returned as `RegionProtected`, and otherwise a program or
+    /// erase error as `ProgramFailed` or `EraseFailed`. Nothing is checked unless the
+    /// flag status register is used for busy polling.
+    fn check_flag_status(&mut self, address: u32) -> Result<()> {
+        let errors = FlagStatusRegister(self.flag_errors);
+        if errors.0 == 0 {
+            return Ok(());
+        }
+        log::error!(
+            "Flag status register errors 0x{:02X} at 0x{:08X}",
+            errors.0,
+            address
+        );
+        self.clear_flag_status()?;
+        Err(if errors.get_protection_error() {
+            Error::RegionProtected { address }
+        } else if errors.get_erase_error() {
+            Error::EraseFailed { address }
+        } else {
+            Error::ProgramFailed { address }
+        })
+    }
+
+    /// Call `f` with the protection scheme, which is taken out of `self` for the call.
+    fn with_protection<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&dyn ProtectionScheme<A>, &mut Self) -> Result<T>,
+    {
+        let scheme = self.protection.take();
+        let result = match &scheme {
+            Some(scheme) => f(scheme.as_ref(), self),
+            None => f(&protection::BlockProtectBits, self),
+        };
+        self.protection = scheme;
+        result
+    }
+
     /// Clear the write-protect-selection bit in status register 3, if set.
     ///
     /// This status bit configures the fine-granularity write protection
@@ -658,7 +3327,7 @@ where
         if status3.get_wps() {
             log::debug!("WPS bit set, clearing.");
             status3.set_wps(false);
-            self.write_status3(status3)?;
+            self.write_status3(status3, StatusWriteMode::NonVolatile)?;
             self.wait_while_busy()?;
         }
         Ok(())
@@ -682,11 +3351,119 @@ where
     /// Program `data` to `address`, automatically split into multiple page program operations.
     ///
     /// Note that this does *not* erase the flash beforehand; use `program()` for a higher-level
-    /// erase-program-verify interface.
+    /// erase-program-verify interface. Each page is verified if enabled using
+    /// `set_verify_programs()`.
     pub fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
         self.program_data_cb(address, data, |_| {})
     }
 
+    /// Program each of `requests`, writing its data starting at its address, as
+    /// `program_data()` does.
+    ///
+    /// Every request is checked against the capacity and any protection before anything
+    /// is programmed, then requests for contiguous ranges are merged, so each page is
+    /// programmed in a single operation even if its data is split across requests.
+    /// Data is programmed directly from the caller's buffers, except for pages split
+    /// across requests, which are gathered into a page-sized buffer first.
+    ///
+    /// Note that this does *not* erase the flash beforehand.
+    pub fn write_vectored(&mut self, requests: &[(u32, &[u8])]) -> Result<()> {
+        for (address, data) in self.write_segments(requests)? {
+            self.program_data(address, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Program each of `requests` as `write_vectored()`, yielding to the executor after
+    /// each program.
+    ///
+    /// Each program still blocks, as `FlashAccess` is blocking, but other tasks can run
+    /// in between while a large scattered image is programmed.
+    pub async fn write_vectored_async(&mut self, requests: &[(u32, &[u8])]) -> Result<()> {
+        for (address, data) in self.write_segments(requests)? {
+            self.program_data(address, &data)?;
+            crc::yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// Check `requests` and split them into the programs `write_vectored()` makes: the
+    /// data of each request, borrowed, apart from any page shared with a contiguous
+    /// neighbour, which is gathered from the requests it spans.
+    fn write_segments<'r>(
+        &mut self,
+        requests: &'r [(u32, &'r [u8])],
+    ) -> Result<Vec<(u32, alloc::borrow::Cow<'r, [u8]>)>> {
+        use alloc::borrow::Cow;
+
+        for (address, data) in requests {
+            self.check_address_length(*address, data.len())?;
+            self.check_unprotected(*address, data.len())?;
+        }
+        let page_size = self.page_size.unwrap_or(256);
+        let lengths: Vec<(u32, usize)> = requests.iter().map(|(a, d)| (*a, d.len())).collect();
+        let mut segments = Vec::new();
+        for (run, address, length) in Self::contiguous_runs(&lengths) {
+            let parts: Vec<&[u8]> = requests[run]
+                .iter()
+                .map(|(_, data)| *data)
+                .filter(|data| !data.is_empty())
+                .collect();
+            let (mut idx, mut offset) = (0, 0);
+            let mut addr = address as usize;
+            let end = addr + length;
+            while addr < end {
+                let rest = &parts[idx][offset..];
+                let part_end = addr + rest.len();
+                // Borrow up to the end of this request, or if the next request shares
+                // its last page, up to the start of that page.
+                let direct_end = if part_end == end || part_end.is_multiple_of(page_size) {
+                    part_end
+                } else {
+                    part_end - part_end % page_size
+                };
+                if direct_end > addr {
+                    segments.push((addr as u32, Cow::Borrowed(&rest[..direct_end - addr])));
+                    offset += direct_end - addr;
+                    addr = direct_end;
+                } else {
+                    let page_end = usize::min(addr + page_size - addr % page_size, end);
+                    let mut page = Vec::with_capacity(page_end - addr);
+                    while addr + page.len() < page_end {
+                        let rest = &parts[idx][offset..];
+                        let n = usize::min(rest.len(), page_end - addr - page.len());
+                        page.extend_from_slice(&rest[..n]);
+                        offset += n;
+                        if offset == parts[idx].len() {
+                            idx += 1;
+                            offset = 0;
+                        }
+                    }
+                    segments.push((addr as u32, Cow::Owned(page)));
+                    addr = page_end;
+                }
+                if idx < parts.len() && offset == parts[idx].len() {
+                    idx += 1;
+                    offset = 0;
+                }
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Program `data` starting at `address` in `space`.
+    ///
+    /// See `program_data()`.
+    pub fn program_data_at(
+        &mut self,
+        space: AddressSpace,
+        address: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        let address = self.to_storage(space, address)?;
+        self.program_data(address, data)
+    }
+
     /// Program `data` to `address`, automatically split into multiple page program operations,
     /// and draws a progress bar to the terminal.
     ///
@@ -700,7 +3477,7 @@ where
                 .progress_chars(Self::DATA_PROGRESS_CHARS),
         );
         pb.set_message("Writing");
-        self.program_data_cb(address, &data, |n| pb.set_position(n as u64))?;
+        self.program_data_cb(address, data, |n| pb.set_position(n as u64))?;
         pb.set_style(
             ProgressStyle::with_template(Self::DATA_FINISHED_TPL)
                 .unwrap()
@@ -718,9 +3495,81 @@ where
     /// Calls `cb` with the number of bytes programmed so far after each
     /// page programming operation.
     pub fn program_data_cb<F: Fn(usize)>(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        cb: F,
+    ) -> Result<()> {
+        self.program_paged(address, data, self.verify_programs, cb)
+    }
+
+    /// Program `data` to `address`, automatically split into multiple page program operations,
+    /// reading back each page after it is programmed to verify it.
+    ///
+    /// Note that this does *not* erase the flash beforehand; use `program()` for a higher-level
+    /// erase-program-verify interface.
+    ///
+    /// Returns a `ReadbackError` with the address of the first mismatching byte, and the
+    /// values written and read there, as soon as any page fails verification, without
+    /// programming further pages. To verify every program, see `set_verify_programs()`.
+    pub fn program_data_verified(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.program_paged(address, data, true, |_| {})
+    }
+
+    /// Program each of `pages`, given as an address and up to one page of data, in order.
+    ///
+    /// This is equivalent to calling `page_program()` for each page, but all pages are
+    /// checked to lie within a single page of the flash before anything is programmed,
+    /// and progress is reported across the whole batch. If `verify` is true, each page is
+    /// read back after it is programmed, and a `ReadbackError` returned if it differs.
+    ///
+    /// Note that this does *not* erase the flash beforehand.
+    pub fn program_pages<'d>(
+        &mut self,
+        pages: impl IntoIterator<Item = (u32, &'d [u8])>,
+        verify: bool,
+    ) -> Result<()> {
+        let pages: Vec<_> = pages
+            .into_iter()
+            .filter(|(_, data)| !data.is_empty())
+            .collect();
+        let page_size = self.page_size.unwrap_or(256);
+        for (address, data) in &pages {
+            self.check_address_length(*address, data.len())?;
+            if *address as usize % page_size + data.len() > page_size {
+                log::error!(
+                    "Program of {} bytes at 0x{:08X} crosses a page boundary",
+                    data.len(),
+                    address
+                );
+                return Err(Error::InvalidAddress {
+                    address: address + data.len() as u32 - 1,
+                });
+            }
+        }
+        let total = pages.iter().map(|(_, data)| data.len()).sum();
+        let mut done = 0;
+        self.report_written(done, total, page_size);
+        for (address, data) in pages {
+            self.page_program(address, data)?;
+            if verify {
+                self.verify_page(address, data)?;
+            }
+            done += data.len();
+            self.report_written(done, total, page_size);
+        }
+        Ok(())
+    }
+
+    /// Program `data` to `address` in page-sized chunks, optionally verifying each page.
+    ///
+    /// Calls `cb` with the number of bytes programmed so far after each
+    /// page programming operation.
+    fn program_paged<F: Fn(usize)>(
         &mut self,
         address: u32,
         mut data: &[u8],
+        verify: bool,
         cb: F,
     ) -> Result<()> {
         let page_size = match self.page_size {
@@ -737,64 +3586,258 @@ where
             address,
             page_size
         );
+        self.check_address_length(address, data.len())?;
 
+        let length = data.len();
         let mut total_bytes = 0;
         cb(total_bytes);
+        self.report_written(total_bytes, length, page_size);
 
         // If the address is not page-aligned, we need to do a
         // smaller-than-page-size initial program.
         let first_write = page_size - ((address as usize) % page_size);
         if first_write != page_size {
+            let first_write = usize::min(first_write, data.len());
             log::trace!("Programming partial first page of {} bytes", first_write);
             self.page_program(address, &data[..first_write])?;
+            if verify {
+                self.verify_page(address, &data[..first_write])?;
+            }
             total_bytes += first_write;
             data = &data[first_write..];
             cb(total_bytes);
+            self.report_written(total_bytes, length, page_size);
         }
 
         for page_data in data.chunks(page_size) {
-            self.page_program(address + total_bytes as u32, page_data)?;
+            let page_address = address + total_bytes as u32;
+            self.page_program(page_address, page_data)?;
+            if verify {
+                self.verify_page(page_address, page_data)?;
+            }
             total_bytes += page_data.len();
             cb(total_bytes);
+            self.report_written(total_bytes, length, page_size);
         }
 
         Ok(())
     }
 
+    /// Read back a just-programmed page and check it matches `data`.
+    fn verify_page(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        let programmed = self.read(address, data.len())?;
+        self.verify_readback(address, data, &programmed)
+    }
+
     /// Send the WriteEnable command, setting the WEL in the status register.
     pub fn write_enable(&mut self) -> Result<()> {
         self.command(Command::WriteEnable)
     }
 
+    /// Send the WriteEnable command before a program or erase whose errors are checked
+    /// by `wait_for_operation()`.
+    ///
+    /// Flag status register errors left by an earlier operation, which were seen while
+    /// polling but not checked, are cleared first, so they are not reported for this one.
+    fn operation_enable(&mut self) -> Result<()> {
+        if self.flag_errors != 0 {
+            log::warn!(
+                "Clearing stale flag status register errors 0x{:02X}",
+                self.flag_errors
+            );
+            self.clear_flag_status()?;
+        }
+        self.write_enable()
+    }
+
     /// Program up to one page of data.
     ///
+    /// Returns `Misaligned` if `data` would extend past the end of its page, where
+    /// the device would wrap around to the start of the page, or `InvalidAddress` if
+    /// it would extend beyond the capacity.
+    ///
     /// This method sets the write-enable latch and then waits for programming to complete,
     /// including sleeping half the typical page program time if known before polling.
     ///
     /// Note that this does *not* erase the flash beforehand;
     /// use `program()` for a higher-level erase-program-verify interface.
+    ///
+    /// If the transport requires aligned transfers, `data` is padded with 0xFF bytes,
+    /// which leave the flash contents unchanged, and if the transport limits the transfer
+    /// size, the page is programmed using several smaller program operations.
     pub fn page_program(&mut self, address: u32, data: &[u8]) -> Result<()> {
-        self.write_enable()?;
-        self.exchange(Command::PageProgram(Address24Bits(address)), &data, 0)?;
-        if let Some(params) = self.params {
-            if let Some(timing) = params.timing {
-                // Only bother sleeping if the expected programming time is greater than 1ms,
-                // otherwise we'll likely have waited long enough just due to round-trip delays.
-                // We always poll the status register at least once to check write completion.
-                if timing.page_prog_time_typ > Duration::from_millis(1) {
-                    self.access.delay(timing.page_prog_time_typ / 2);
-                }
-            }
+        self.check_address_length(address, data.len())?;
+        let page_size = self.page_size.unwrap_or(256);
+        if address as usize % page_size + data.len() > page_size {
+            log::error!(
+                "Program of {} bytes at 0x{:08X} crosses a page boundary",
+                data.len(),
+                address
+            );
+            return Err(Error::Misaligned {
+                address,
+                required: page_size as u32,
+            });
+        }
+        let (start, end) = self.align_transfer(address as usize, address as usize + data.len());
+        if (start, end) != (address as usize, address as usize + data.len()) {
+            log::trace!("Padding page program to 0x{:08X}..0x{:08X}", start, end);
+            let mut padded = alloc::vec![0xFF; end - start];
+            let offset = address as usize - start;
+            padded[offset..offset + data.len()].copy_from_slice(data);
+            return self.page_program_chunks(start as u32, &padded);
+        }
+        self.page_program_chunks(address, data)
+    }
+
+    /// Program aligned `data` to `address`, in chunks of at most the maximum transfer size.
+    fn page_program_chunks(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        let chunk = self.transfer_chunk_size();
+        for (idx, chunk_data) in data.chunks(chunk).enumerate() {
+            self.page_program_single(address + (idx * chunk) as u32, chunk_data)?;
         }
-        self.wait_while_busy()?;
         Ok(())
     }
 
+    /// Program `data` to `address` in a single PageProgram operation.
+    fn page_program_single(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_unprotected(address, data.len())?;
+        let command = self.address_command(CommandOpCode::PageProgram.into(), address)?;
+        // Mark the sector before programming, so a failed program leaves it dirty.
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.mark_programmed(address, data.len());
+        }
+        self.operation_enable()?;
+        self.exchange(command, data, 0)?;
+        // Only bother sleeping if the expected programming time is greater than 1ms,
+        // otherwise we'll likely have waited long enough just due to round-trip delays.
+        // We always poll the status register at least once to check write completion.
+        let times = self.page_program_times();
+        let duration = times
+            .map(|(typ, _)| typ)
+            .filter(|time| *time > Duration::from_millis(1));
+        self.wait_for_operation(address, duration, times.map(|(_, max)| max))
+    }
+
+    /// Initialise the attached flash and configure this `Flash` to suit it.
+    ///
+    /// This exits QPI and continuous read modes, reads the device ID, and reads the SFDP parameters
+    /// if present. If the board configuration indicates quad I/O is available and the device
+    /// supports the Quad I/O Fast Read instruction, the QE bit is set and that instruction
+    /// selected for reads; otherwise the QE bit is left unchanged and FastRead is used.
+    pub fn init(&mut self) -> Result<FlashID> {
+        if self.board.quad_io && self.access.supports_protocol(Protocol::Qpi) {
+            // A device left in QPI mode would not understand standard SPI commands,
+            // while Exit QPI sent using QPI is ignored by devices in standard SPI mode.
+            log::debug!("Sending Exit QPI in case device was left in QPI mode");
+            self.access.set_protocol(Protocol::Qpi);
+            self.protocol = Protocol::Qpi;
+            self.exit_qpi()?;
+        }
+        let id = self.read_id()?;
+        let params = self.read_params()?;
+        self.read_parameters = ReadParameters::default();
+        if !self.board.quad_io {
+            log::debug!("Quad I/O not available on board, using FastRead");
+            return Ok(id);
+        }
+        let quad = params.and_then(|params| ReadParameters::from_sfdp(&params, ReadMode::QuadIO));
+        match (quad, self.quad_enable_requirement()) {
+            (Some(quad), Some(_)) => {
+                self.set_quad_enable(true)?;
+                self.read_parameters = quad;
+                log::debug!("Using quad I/O reads: {:?}", quad);
+            }
+            _ => log::debug!("Quad I/O reads not supported by device, using FastRead"),
+        }
+        Ok(id)
+    }
+
+    /// Get the quad enable requirement, from the quirks if set or otherwise from SFDP.
+    fn quad_enable_requirement(&self) -> Option<SFDPQuadEnable> {
+        self.quirks
+            .quad_enable
+            .or_else(|| self.params.and_then(|params| params.quad_enable))
+    }
+
+    /// Set or clear the QE bit, which enables quad I/O and disables the WP# and HOLD# pins.
+    ///
+    /// The QE bit location is taken from the `quad_enable` quirk if set, otherwise from SFDP.
+    /// Setting QE is refused if the board configuration does not indicate quad I/O is wired.
+    pub fn set_quad_enable(&mut self, enable: bool) -> Result<()> {
+        if enable && !self.board.quad_io {
+            log::error!("Refusing to set QE without IO2 and IO3 connected");
+            return Err(Error::BoardUnsupported);
+        }
+        let qe = (enable as u8) << 1;
+        match self.quad_enable_requirement() {
+            None | Some(SFDPQuadEnable::Reserved) => {
+                log::error!("Quad enable bit location not known");
+                return Err(Error::NoQuadEnable);
+            }
+            Some(SFDPQuadEnable::NoQuadEnable) => return Ok(()),
+            Some(SFDPQuadEnable::Sr1Bit6) => {
+                let mut status1 = self.read_status1()?;
+                status1.0 = (status1.0 & !0b0100_0000) | ((enable as u8) << 6);
+                self.write_status1(status1, StatusWriteMode::NonVolatile)?;
+            }
+            Some(SFDPQuadEnable::Sr2Bit7) => {
+                let status2 = self.exchange(Command::ReadStatusRegister2Alt, &[], 1)?[0];
+                let status2 = (status2 & !0b1000_0000) | ((enable as u8) << 7);
+                self.write_enable()?;
+                self.write(Command::WriteStatusRegister2Alt, &[status2])?;
+            }
+            Some(SFDPQuadEnable::Sr2Bit1ClearedBySr1Write) | Some(SFDPQuadEnable::Sr2Bit1)
+                if !self.quirks.combined_status_write =>
+            {
+                // Status register 2 cannot be read on these devices, so only QE is written.
+                let status1 = self.read_status1()?;
+                self.write_status12(status1, StatusRegister2(qe), StatusWriteMode::NonVolatile)?;
+            }
+            Some(
+                SFDPQuadEnable::Sr2Bit1ClearedBySr1Write
+                | SFDPQuadEnable::Sr2Bit1
+                | SFDPQuadEnable::Sr2Bit1Read35,
+            ) => {
+                let (status1, status2) = self.read_status12()?;
+                self.write_status12(
+                    status1,
+                    StatusRegister2((status2.0 & !0b10) | qe),
+                    StatusWriteMode::NonVolatile,
+                )?;
+            }
+            Some(SFDPQuadEnable::Sr2Bit1Write31) => {
+                let status2 = self.read_status2()?;
+                self.write_status2(
+                    StatusRegister2((status2.0 & !0b10) | qe),
+                    StatusWriteMode::NonVolatile,
+                )?;
+            }
+        }
+        self.wait_while_busy()
+    }
+
+    /// Check if the QE bit is set.
+    ///
+    /// Returns true for devices without a QE bit, which always allow quad I/O.
+    pub fn is_quad_enabled(&mut self) -> Result<bool> {
+        match self.quad_enable_requirement() {
+            None | Some(SFDPQuadEnable::Reserved) => Err(Error::NoQuadEnable),
+            Some(SFDPQuadEnable::NoQuadEnable) => Ok(true),
+            Some(SFDPQuadEnable::Sr1Bit6) => Ok(self.read_status1()?.0 & 0b0100_0000 != 0),
+            Some(SFDPQuadEnable::Sr2Bit7) => {
+                let status2 = self.exchange(Command::ReadStatusRegister2Alt, &[], 1)?[0];
+                Ok(status2 & 0b1000_0000 != 0)
+            }
+            Some(_) => Ok(self.read_status2()?.0 & 0b10 != 0),
+        }
+    }
+
     /// Reads the JEDEC manufacturer and long (16-bit) device IDs.
     ///
-    /// The manufacturer ID may be prefixed with up to 13 of the
-    /// continuation code 0x7F; the number of continuation codes
-    /// is returned as the bank number.
+    /// The manufacturer ID may be prefixed with up to 15 of the
+    /// continuation code 0x7F, covering all 16 JEP106 banks; the number
+    /// of continuation codes is returned as the bank number.
     ///
     /// Returns (bank, manufacturer ID, device ID).
     pub fn read_jedec_id(&mut self) -> Result<(u8, u8, u16)> {
@@ -803,10 +3846,10 @@ where
         if data[0] != 0x7F {
             Ok((0, data[0], u16::from_be_bytes([data[1], data[2]])))
         } else {
-            // If the first byte is continuation, read 16 bytes, to allow
-            // up to 13 continuation bytes, and then parse it to find the IDs.
-            let data = self.exchange(Command::ReadJEDECID, &[], 16)?;
-            for n in 1..=13 {
+            // If the first byte is continuation, read 18 bytes, to allow
+            // up to 15 continuation bytes, and then parse it to find the IDs.
+            let data = self.exchange(Command::ReadJEDECID, &[], 18)?;
+            for n in 1..=15 {
                 if data[n] != 0x7F {
                     return Ok((
                         n as u8,
@@ -815,33 +3858,57 @@ where
                     ));
                 }
             }
-            log::error!("Found more than 11 continuation bytes in manufacturer ID");
+            log::error!("Found more than 15 continuation bytes in manufacturer ID");
             Err(Error::InvalidManufacturer)
         }
     }
 
-    /// Reads the JEDEC manufacturer and short (8-bit) device IDs.
+    /// Reads the JEDEC manufacturer and short (8-bit) device IDs,
+    /// using Read Manufacturer/Device ID (0x90).
     ///
-    /// The manufacturer ID may be prefixed with up to 13 of the
+    /// The manufacturer ID may be prefixed with up to 15 of the
     /// continuation code 0x7F; the number of continuation codes
     /// is returned as the bank number.
     ///
     /// Returns (bank, manufacturer ID, device ID).
     pub fn read_device_id(&mut self) -> Result<(u8, u8, u8)> {
+        self.read_short_id(DeviceIDMode::Single)
+    }
+
+    /// Reads the manufacturer and short (8-bit) device ID using the instruction `mode`.
+    ///
+    /// This identifies devices which do not support Read JEDEC ID (0x9F); the dual and
+    /// quad variants require a transport supporting those instructions.
+    pub fn read_manufacturer_device_id(&mut self, mode: DeviceIDMode) -> Result<DeviceID> {
+        let (bank, manufacturer_id, device_id) = self.read_short_id(mode)?;
+        Ok(DeviceID {
+            manufacturer: Manufacturer::from_jedec(bank, manufacturer_id),
+            device_id,
+        })
+    }
+
+    fn read_short_id(&mut self, mode: DeviceIDMode) -> Result<(u8, u8, u8)> {
+        // The address 0 selects the manufacturer ID first; the I/O variants are followed
+        // by mode bits, and the quad variant by four dummy cycles.
+        let (command, header): (Command, &[u8]) = match mode {
+            DeviceIDMode::Single => (Command::ReadDeviceID, &[0, 0, 0]),
+            DeviceIDMode::DualIO => (Command::ReadDeviceIDDual, &[0, 0, 0, 0xFF]),
+            DeviceIDMode::QuadIO => (Command::ReadDeviceIDQuad, &[0, 0, 0, 0xFF, 0, 0]),
+        };
         // Attempt to read assuming a single-byte manufacturer ID.
-        let data = self.exchange(Command::ReadDeviceID, &[0, 0, 0], 2)?;
+        let data = self.exchange(command, header, 2)?;
         if data[0] != 0x7F {
             Ok((0, data[0], data[1]))
         } else {
-            // If the first byte is continuation, read 15 bytes, to allow
-            // up to 13 continuation bytes, and then parse it to find the IDs.
-            let data = self.exchange(Command::ReadJEDECID, &[0, 0, 0], 15)?;
-            for n in 1..=13 {
+            // If the first byte is continuation, read 17 bytes, to allow
+            // up to 15 continuation bytes, and then parse it to find the IDs.
+            let data = self.exchange(command, header, 17)?;
+            for n in 1..=15 {
                 if data[n] != 0x7F {
                     return Ok((n as u8, data[n], data[n + 1]));
                 }
             }
-            log::error!("Found more than 11 continuation bytes in manufacturer ID");
+            log::error!("Found more than 15 continuation bytes in manufacturer ID");
             Err(Error::InvalidManufacturer)
         }
     }
@@ -852,10 +3919,97 @@ where
             .map(|data| u64::from_be_bytes(data.try_into().unwrap()))
     }
 
+    /// Read `length` bytes of security register `index`, from 1 to 3, starting at `offset`.
+    ///
+    /// Security registers are the three 256-byte one-time programmable registers of Winbond
+    /// and GigaDevice devices, which are programmed and erased like memory until locked by
+    /// their LB bit in status register 2, addressed using 3-byte addresses.
+    pub fn read_security_register(
+        &mut self,
+        index: u8,
+        offset: usize,
+        length: usize,
+    ) -> Result<Vec<u8>> {
+        let address = Self::security_register_address(index, offset, length)?;
+        let chunk = self.transfer_chunk_size();
+        let mut data = Vec::with_capacity(length);
+        for start in (0..length).step_by(chunk) {
+            let size = usize::min(chunk, length - start);
+            let command =
+                Command::ReadSecurityRegister(Address24Bits::try_from(address + start as u32)?);
+            data.extend(self.exchange(command, &[0], size)?);
+        }
+        Ok(data)
+    }
+
+    /// Program `data` to security register `index`, from 1 to 3, starting at `offset`.
+    ///
+    /// Returns `ProtectionLocked` if the register is locked. As for memory, programming
+    /// only clears bits, so the register must be erased to change programmed data.
+    pub fn program_security_register(
+        &mut self,
+        index: u8,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let address = Self::security_register_address(index, offset, data.len())?;
+        self.check_security_register_unlocked(index)?;
+        let chunk = self.transfer_chunk_size();
+        for (idx, chunk_data) in data.chunks(chunk).enumerate() {
+            let address = Address24Bits::try_from(address + (idx * chunk) as u32)?;
+            self.write_enable()?;
+            self.write(Command::ProgramSecurityRegister(address), chunk_data)?;
+            self.wait_while_busy()?;
+        }
+        Ok(())
+    }
+
+    /// Erase security register `index`, from 1 to 3.
+    ///
+    /// Returns `ProtectionLocked` if the register is locked.
+    pub fn erase_security_register(&mut self, index: u8) -> Result<()> {
+        let address = Self::security_register_address(index, 0, 0)?;
+        self.check_security_register_unlocked(index)?;
+        self.write_enable()?;
+        self.command(Command::EraseSecurityRegister(Address24Bits::try_from(
+            address,
+        )?))?;
+        self.wait_while_busy()
+    }
+
+    /// Check if security register `index`, from 1 to 3, is locked by its LB bit.
+    pub fn is_security_register_locked(&mut self, index: u8) -> Result<bool> {
+        Self::security_register_address(index, 0, 0)?;
+        Ok(self.read_status2()?.get_security_lock(index))
+    }
+
+    fn check_security_register_unlocked(&mut self, index: u8) -> Result<()> {
+        if self.is_security_register_locked(index)? {
+            log::error!("Security register {} is locked", index);
+            return Err(Error::ProtectionLocked);
+        }
+        Ok(())
+    }
+
+    /// Get the address of `length` bytes from `offset` in security register `index`.
+    fn security_register_address(index: u8, offset: usize, length: usize) -> Result<u32> {
+        if !(1..=3).contains(&index) || offset + length > 256 {
+            log::error!(
+                "Invalid access of {} bytes at {} in security register {}",
+                length,
+                offset,
+                index
+            );
+            return Err(Error::InvalidAddress {
+                address: ((index as u32) << 12) + offset as u32,
+            });
+        }
+        Ok(((index as u32) << 12) + offset as u32)
+    }
+
     /// Read status register 1.
     pub fn read_status1(&mut self) -> Result<StatusRegister1> {
-        self.exchange(Command::ReadStatusRegister1, &[], 1)
-            .map(|data| StatusRegister1(data[0]))
+        self.read_register(Register::Status1).map(StatusRegister1)
     }
 
     /// Read status register 2.
@@ -863,8 +4017,7 @@ where
     /// This status register is less widely supported and SFDP does
     /// not indicate whether or not it is present.
     pub fn read_status2(&mut self) -> Result<StatusRegister2> {
-        self.exchange(Command::ReadStatusRegister2, &[], 1)
-            .map(|data| StatusRegister2(data[0]))
+        self.read_register(Register::Status2).map(StatusRegister2)
     }
 
     /// Read status register 3.
@@ -872,19 +4025,100 @@ where
     /// This status register is less widely supported and SFDP does
     /// not indicate whether or not it is present.
     pub fn read_status3(&mut self) -> Result<StatusRegister3> {
-        self.exchange(Command::ReadStatusRegister3, &[], 1)
-            .map(|data| StatusRegister3(data[0]))
+        self.read_register(Register::Status3).map(StatusRegister3)
+    }
+
+    /// Read status registers 1 and 2.
+    ///
+    /// If the `combined_status_read` quirk is set and the registers are accessed using
+    /// their legacy instructions, both are read in a single transaction, otherwise
+    /// each register is read separately.
+    pub fn read_status12(&mut self) -> Result<(StatusRegister1, StatusRegister2)> {
+        if self.quirks.combined_status_read
+            && self.any_register_address(Register::Status1).is_none()
+        {
+            let data = self.exchange(Command::ReadStatusRegister1, &[], 2)?;
+            return Ok((StatusRegister1(data[0]), StatusRegister2(data[1])));
+        }
+        Ok((self.read_status1()?, self.read_status2()?))
+    }
+
+    /// Read `register`.
+    ///
+    /// If the register file contains an address for `register`, the RDAR instruction
+    /// is used, otherwise status registers are read using their legacy instructions.
+    pub fn read_register(&mut self, register: Register) -> Result<u8> {
+        if let Some((address, dummy_bytes)) = self.any_register_address(register) {
+            let dummy = alloc::vec![0; dummy_bytes];
+            let command = Command::ReadAnyRegister(Address24Bits::try_from(address)?);
+            return self.exchange(command, &dummy, 1).map(|data| data[0]);
+        }
+        let command = match register {
+            Register::Status1 => Command::ReadStatusRegister1,
+            Register::Status2 => Command::ReadStatusRegister2,
+            Register::Status3 => Command::ReadStatusRegister3,
+            Register::Config1 => Command::ReadConfigRegister1,
+            _ => return Err(Error::NoRegisterAddress { register }),
+        };
+        self.exchange(command, &[], 1).map(|data| data[0])
+    }
+
+    /// Write `value` to `register`, and wait for the write to complete.
+    ///
+    /// If the register file contains an address for `register`, the WRAR instruction
+    /// is used, otherwise status registers are written using their legacy instructions,
+    /// updating the volatile or non-volatile bits according to `mode`.
+    pub fn write_register(
+        &mut self,
+        register: Register,
+        value: u8,
+        mode: StatusWriteMode,
+    ) -> Result<()> {
+        if let Some((address, _)) = self.any_register_address(register) {
+            self.write_enable()?;
+            self.write(
+                Command::WriteAnyRegister(Address24Bits::try_from(address)?),
+                &[value],
+            )?;
+        } else {
+            match register {
+                Register::Status1 => self.write_status1(StatusRegister1(value), mode)?,
+                Register::Status2 => self.write_status2(StatusRegister2(value), mode)?,
+                Register::Status3 => self.write_status3(StatusRegister3(value), mode)?,
+                Register::Config1 => self.write_config1(ConfigRegister1(value), mode)?,
+                _ => return Err(Error::NoRegisterAddress { register }),
+            }
+        }
+        self.wait_while_busy()
+    }
+
+    /// Get the RDAR/WRAR address of `register` and the number of RDAR dummy bytes,
+    /// if a register file is set and contains `register`.
+    fn any_register_address(&self, register: Register) -> Option<(u32, usize)> {
+        self.register_file
+            .as_ref()
+            .and_then(|file| file.address(register).map(|a| (a, file.dummy_bytes())))
     }
 
     /// Write status register 1.
     ///
     /// This method does *not* require you call `write_enable()` first.
     ///
-    /// If the SFDP parameters indicate a specific command should be used
-    /// to enable writing to status register 1, that is used, otherwise the
-    /// default WriteEnable of 0x06 is used.
-    fn write_status1(&mut self, status1: StatusRegister1) -> Result<()> {
-        let we_opcode = if let Some(params) = self.params {
+    /// For non-volatile writes, if the SFDP parameters indicate a specific command
+    /// should be used to enable writing to status register 1, that is used, otherwise
+    /// the default WriteEnable of 0x06 is used.
+    fn write_status1(&mut self, status1: StatusRegister1, mode: StatusWriteMode) -> Result<()> {
+        self.check_status_writable()?;
+        if let Some((address, _)) = self.any_register_address(Register::Status1) {
+            self.write_enable()?;
+            return self.write(
+                Command::WriteAnyRegister(Address24Bits::try_from(address)?),
+                &[status1.0],
+            );
+        }
+        let we_opcode = if mode == StatusWriteMode::Volatile {
+            Command::WriteEnableVolatile
+        } else if let Some(params) = self.params {
             match params.status_1_vol {
                 Some(SFDPStatus1Volatility::NonVolatile06) => Command::WriteEnable,
                 Some(SFDPStatus1Volatility::Volatile06) => Command::WriteEnable,
@@ -902,21 +4136,176 @@ where
         } else {
             Command::WriteEnable
         };
+        if self.quirks.combined_status_write {
+            // Preserve status register 2, which is cleared by a one-byte write.
+            let status2 = self.read_status2()?;
+            self.command(we_opcode)?;
+            return self.write(Command::WriteStatusRegister1, &[status1.0, status2.0]);
+        }
         self.command(we_opcode)?;
         let s1 = self.read_status1()?;
         log::debug!("Set WEL, s1 now: {:02X}", s1.0);
         self.write(Command::WriteStatusRegister1, &[status1.0])
     }
 
-    /// Write status register 2.
-    pub fn write_status2(&mut self, status2: StatusRegister2) -> Result<()> {
+    /// Write status registers 1 and 2 together, using Write Status Register 1 (0x01)
+    /// with two data bytes.
+    ///
+    /// Volatile writes update the bits without consuming their write endurance,
+    /// for example to set QE or the block protect bits on each boot.
+    pub fn write_status12(
+        &mut self,
+        status1: StatusRegister1,
+        status2: StatusRegister2,
+        mode: StatusWriteMode,
+    ) -> Result<()> {
+        self.check_status_writable()?;
+        self.status_write_enable(mode)?;
+        self.write(Command::WriteStatusRegister1, &[status1.0, status2.0])
+    }
+
+    /// Read Spansion configuration register 1.
+    ///
+    /// If the register file contains an address for CR1, RDAR is used,
+    /// otherwise Read Configuration Register 1 (0x35).
+    pub fn read_config1(&mut self) -> Result<ConfigRegister1> {
+        self.read_register(Register::Config1).map(ConfigRegister1)
+    }
+
+    /// Read the Spansion bank register using BRRD (0x16).
+    pub fn read_bank_register(&mut self) -> Result<BankRegister> {
+        let data = self.exchange(Command::ReadBankRegister, &[], 1)?;
+        Ok(BankRegister(data[0]))
+    }
+
+    /// Write the Spansion bank register using BRWR (0x17).
+    ///
+    /// Setting EXTADD switches the device to 4-byte addresses, so set 4 address bytes
+    /// with `set_address_bytes()` to match. When the bank register is the address
+    /// extension, later accesses select their bank themselves, overwriting the bank bits.
+    pub fn write_bank_register(&mut self, bank: BankRegister) -> Result<()> {
+        self.write(Command::WriteBankRegister, &[bank.0])?;
+        self.upper_address = match self.address_extension {
+            AddressExtension::BankRegister if !bank.get_extadd() => Some(bank.get_bank()),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    /// Read the Winbond Extended Address Register using 0xC8.
+    pub fn read_extended_address_register(&mut self) -> Result<u8> {
+        let data = self.exchange(Command::ReadExtendedAddressRegister, &[], 1)?;
+        Ok(data[0])
+    }
+
+    /// Write the Winbond Extended Address Register using 0xC5.
+    ///
+    /// When the Extended Address Register is the address extension, later accesses
+    /// select their bank themselves, overwriting the value.
+    pub fn write_extended_address_register(&mut self, value: u8) -> Result<()> {
         self.write_enable()?;
+        self.write(Command::WriteExtendedAddressRegister, &[value])?;
+        self.upper_address = match self.address_extension {
+            AddressExtension::ExtendedAddressRegister => Some(value),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    /// Set the address mode a Winbond device powers up in, using the ADP bit of status
+    /// register 3: 4-byte addresses if `four_byte` is set, otherwise 3-byte addresses
+    /// with the Extended Address Register providing the upper address bits.
+    ///
+    /// The current address mode is unchanged until the next power-up or reset, and is
+    /// reported by the ADS bit of status register 3.
+    pub fn set_power_up_address_mode(
+        &mut self,
+        four_byte: bool,
+        mode: StatusWriteMode,
+    ) -> Result<()> {
+        let mut status3 = self.read_status3()?;
+        status3.set_adp(four_byte);
+        self.write_status3(status3, mode)
+    }
+
+    /// Write Spansion status register 1 and configuration register 1 together,
+    /// using Write Registers (0x01) with two data bytes.
+    pub fn write_status_config1(
+        &mut self,
+        status1: StatusRegister1,
+        config1: ConfigRegister1,
+        mode: StatusWriteMode,
+    ) -> Result<()> {
+        self.write_status12(status1, StatusRegister2(config1.0), mode)
+    }
+
+    /// Write Spansion configuration register 1.
+    ///
+    /// If the register file contains an address for CR1, WRAR is used, otherwise
+    /// status register 1 is read and written back together with `config1` using
+    /// `write_status_config1()`, as the devices have no separate CR1 write.
+    pub fn write_config1(&mut self, config1: ConfigRegister1, mode: StatusWriteMode) -> Result<()> {
+        if let Some((address, _)) = self.any_register_address(Register::Config1) {
+            self.write_enable()?;
+            return self.write(
+                Command::WriteAnyRegister(Address24Bits::try_from(address)?),
+                &[config1.0],
+            );
+        }
+        let status1 = self.read_status1()?;
+        self.write_status_config1(status1, config1, mode)
+    }
+
+    /// Set the write enable latch before a status register write in `mode`.
+    fn status_write_enable(&mut self, mode: StatusWriteMode) -> Result<()> {
+        match mode {
+            StatusWriteMode::NonVolatile => self.write_enable(),
+            StatusWriteMode::Volatile => self.command(Command::WriteEnableVolatile),
+        }
+    }
+
+    /// Check the status registers can be written, given the board configuration.
+    ///
+    /// If WP# is tied low and the status register protect bit is set, writes are
+    /// ignored by the device, so an error is returned instead.
+    fn check_status_writable(&mut self) -> Result<()> {
+        if self.board.wp_tied_low && self.read_status1()?.get_srp() {
+            log::error!("Status registers are hardware protected, WP# is tied low and SRP set");
+            return Err(Error::BoardUnsupported);
+        }
+        Ok(())
+    }
+
+    /// Write status register 2.
+    ///
+    /// If the `combined_status_write` quirk is set, status register 1 is read and
+    /// written back together with `status2` using `write_status12()`.
+    pub fn write_status2(&mut self, status2: StatusRegister2, mode: StatusWriteMode) -> Result<()> {
+        if let Some((address, _)) = self.any_register_address(Register::Status2) {
+            self.write_enable()?;
+            return self.write(
+                Command::WriteAnyRegister(Address24Bits::try_from(address)?),
+                &[status2.0],
+            );
+        }
+        if self.quirks.combined_status_write {
+            let status1 = self.read_status1()?;
+            return self.write_status12(status1, status2, mode);
+        }
+        self.status_write_enable(mode)?;
         self.write(Command::WriteStatusRegister2, &[status2.0])
     }
 
     /// Write status register 3.
-    pub fn write_status3(&mut self, status3: StatusRegister3) -> Result<()> {
-        self.write_enable()?;
+    pub fn write_status3(&mut self, status3: StatusRegister3, mode: StatusWriteMode) -> Result<()> {
+        if let Some((address, _)) = self.any_register_address(Register::Status3) {
+            self.write_enable()?;
+            return self.write(
+                Command::WriteAnyRegister(Address24Bits::try_from(address)?),
+                &[status3.0],
+            );
+        }
+        self.status_write_enable(mode)?;
         self.write(Command::WriteStatusRegister3, &[status3.0])
     }
 
@@ -926,61 +4315,340 @@ where
     /// instruction (0x70), it is used, otherwise legacy polling of status
     /// register 1 is used.
     pub fn is_busy(&mut self) -> Result<bool> {
-        // If we have read parameters and flag status polling is supported, use that.
-        // Bit 7 of FSR is 0=busy and 1=ready.
-        if let Some(params) = self.params {
-            if let Some(busy_poll_flag) = params.busy_poll_flag {
-                if busy_poll_flag {
-                    let fsr = self.exchange(Command::ReadFlagStatusRegister, &[], 1)?[0];
-                    return Ok(fsr & 0b1000_0000 == 0);
-                }
+        let polling = self.busy_polling();
+        self.retrying(self.retry.status_polls, |flash| match polling {
+            // Bit 7 of FSR is 0=busy and 1=ready.
+            SFDPBusyPolling::FlagStatusRegister => {
+                let fsr = flash.read_flag_status()?;
+                // Keep any error bits for `check_flag_status()`.
+                flash.flag_errors |= fsr.0 & Self::FSR_ERRORS;
+                Ok(!fsr.get_ready())
+            }
+            SFDPBusyPolling::StatusRegister => flash.read_status1().map(|status| status.get_busy()),
+        })
+        .inspect(|busy| self.busy_pending &= *busy)
+    }
+
+    /// Get the busy polling method used by `is_busy()`.
+    ///
+    /// This is taken from the `busy_polling` quirk if set, otherwise from the SFDP
+    /// status register polling field, so devices such as Micron parts which require
+    /// flag status register polling are handled automatically. By default, status
+    /// register 1 is polled.
+    pub fn busy_polling(&self) -> SFDPBusyPolling {
+        self.quirks
+            .busy_polling
+            .or_else(|| self.params.and_then(|params| params.busy_polling()))
+            .unwrap_or(SFDPBusyPolling::StatusRegister)
+    }
+
+    /// Wait until the device stops being busy.
+    ///
+    /// This polls using `is_busy()`, which uses the flag status
+    /// register if available or otherwise uses status register 1.
+    /// If a `WaitReady` hook is set, it is called between polls.
+    pub fn wait_while_busy(&mut self) -> Result<()> {
+        if self.wait_ready.is_some() {
+            return self.wait_with_hook(None, None);
+        }
+        while self.is_busy()? {}
+        Ok(())
+    }
+
+    /// Read SFDP register data.
+    ///
+    /// `addr` is always sent as a 24-bit address, regardless of the address_bytes setting.
+    pub fn read_sfdp(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
+        self.exchange(
+            Command::ReadSFDPRegister(Address24Bits::try_from(addr)?),
+            &[],
+            1 + len,
+        )
+        .map(|data| data[1..].to_vec())
+    }
+
+    /// Read raw SFDP data starting at `offset` into `buf`.
+    pub fn read_sfdp_raw(&mut self, offset: u32, buf: &mut [u8]) -> Result<()> {
+        let data = self.read_sfdp(offset, buf.len())?;
+        buf.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Read the SFDP parameter headers, describing each parameter table of the device,
+    /// including vendor tables the driver does not parse.
+    ///
+    /// Returns `Error::InvalidSFDPHeader` if the device has no valid SFDP header.
+    pub fn sfdp_tables(&mut self) -> Result<impl Iterator<Item = SFDPParameterHeader>> {
+        match self.read_sfdp_header()? {
+            Some(header) => Ok(header.params.into_iter()),
+            None => Err(Error::InvalidSFDPHeader),
+        }
+    }
+
+    /// Read the whole SFDP address space used by the device: the SFDP header,
+    /// parameter headers, and every parameter table, for archiving or offline parsing.
+    ///
+    /// Returns `Error::InvalidSFDPHeader` if the device has no valid SFDP header.
+    pub fn read_sfdp_blob(&mut self) -> Result<Vec<u8>> {
+        let tables: Vec<_> = self.sfdp_tables()?.collect();
+        let headers_end = 8 + tables.len() * 8;
+        let end = tables
+            .iter()
+            .map(|table| table.ptp as usize + table.len_bytes())
+            .fold(headers_end, usize::max);
+        self.read_sfdp(0, end)
+    }
+
+    /// Read the SFDP header and parameter headers, returning `None` if the header
+    /// is not valid, as on devices without SFDP.
+    fn read_sfdp_header(&mut self) -> Result<Option<SFDPHeader>> {
+        // Read just SFDP header to get NPH first.
+        let data = self.read_sfdp(0, 8)?;
+        let nph = data[6] as usize + 1;
+
+        // Re-read overall SFDP header including parameter headers.
+        let data = self.read_sfdp(0, 8 + nph * 8)?;
+        Ok(SFDPHeader::from_bytes(&data).ok())
+    }
+
+    /// Writes `command` and `data` to the flash memory, then returns `nbytes` of response.
+    pub fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        self.check_supported(command)?;
+        if self.continuous_read {
+            self.exit_continuous_read()?;
+        }
+        self.wait_if_pending(command)?;
+        log::trace!(
+            "SPI exchange: write {:?} {:02X?}, read {} bytes",
+            command,
+            &data,
+            nbytes
+        );
+        let rx = self.access_exchange(command, data, nbytes)?;
+        self.track_busy(command);
+        let skip = command.len() + data.len();
+        log::trace!("SPI exchange: read {:02X?}", &rx[skip..]);
+        Ok(rx[skip..].to_vec())
+    }
+
+    /// Writes `command` and `data` to the flash memory, without reading the response.
+    pub fn write(&mut self, command: Command, data: &[u8]) -> Result<()> {
+        self.check_supported(command)?;
+        if self.continuous_read {
+            self.exit_continuous_read()?;
+        }
+        self.wait_if_pending(command)?;
+        log::trace!("SPI write: {:?} {:02X?}", command, data);
+        let drive_wp = matches!(
+            command,
+            Command::WriteStatusRegister1
+                | Command::WriteStatusRegister2
+                | Command::WriteStatusRegister3
+                | Command::WriteStatusRegister2Alt
+                | Command::WriteAnyRegister(_)
+        );
+        // WP# is sampled when the instruction is accepted, so it may be driven
+        // low again immediately, while the write is still in progress.
+        if let Some(pin) = self.wp_pin.as_mut().filter(|_| drive_wp) {
+            pin.set_high()?;
+        }
+        let result = self.access_write(command, data);
+        if let Some(pin) = self.wp_pin.as_mut().filter(|_| drive_wp) {
+            pin.set_low()?;
+        }
+        result?;
+        self.invalidate_protection(command);
+        self.track_busy(command);
+        Ok(())
+    }
+
+    /// Pass `command` and `data` to the transport, after any opcode remapping, reporting
+    /// it to any trace hook.
+    fn access_write(&mut self, command: Command, data: &[u8]) -> Result<()> {
+        let command = self.remap(command);
+        #[cfg(feature = "trace")]
+        let timer = trace::Timer::start();
+        let result = self.access.write(command, data).map_err(Error::from);
+        #[cfg(feature = "trace")]
+        self.trace(command, data.len(), 0, timer, result.as_ref().err());
+        result
+    }
+
+    /// Pass `command` and `data` to the transport reading `nbytes` of response, after
+    /// any opcode remapping, reporting it to any trace hook.
+    fn access_exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let command = self.remap(command);
+        #[cfg(feature = "trace")]
+        let timer = trace::Timer::start();
+        let result = self
+            .access
+            .exchange(command, data, nbytes)
+            .map_err(Error::from);
+        #[cfg(feature = "trace")]
+        self.trace(command, data.len(), nbytes, timer, result.as_ref().err());
+        result
+    }
+
+    /// Get the command to send in place of `command` using any opcode map.
+    fn remap(&self, command: Command) -> Command {
+        self.opcode_map
+            .as_ref()
+            .map_or(command, |map| map.apply(command))
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace(
+        &mut self,
+        command: Command,
+        written: usize,
+        read: usize,
+        timer: trace::Timer,
+        error: Option<&Error>,
+    ) {
+        if let Some(hook) = self.trace.as_mut() {
+            hook.trace(&trace::TraceEvent {
+                command,
+                opcode: command.opcode(),
+                address: command
+                    .parts()
+                    .1
+                    .map(|address| address.either(|a| a.0, |a| a.0)),
+                written,
+                read,
+                duration: timer.elapsed(),
+                result: error.map_or(Ok(()), Err),
+            });
+        }
+    }
+
+    /// Check if `command` may be sent while the device is busy.
+    fn allowed_while_busy(command: Command) -> bool {
+        matches!(
+            CommandOpCode::try_from(command.opcode()),
+            Ok(CommandOpCode::ReadStatusRegister1
+                | CommandOpCode::ReadFlagStatusRegister
+                | CommandOpCode::ProgramSuspend
+                | CommandOpCode::ProgramResume
+                | CommandOpCode::EnableReset
+                | CommandOpCode::Reset
+                | CommandOpCode::SoftwareReset)
+        )
+    }
+
+    /// If an earlier operation may have left the device busy, wait for it before sending
+    /// `command`.
+    fn wait_if_pending(&mut self, command: Command) -> Result<()> {
+        if self.busy_pending && !Self::allowed_while_busy(command) {
+            log::debug!("Waiting for unfinished operation before {:?}", command);
+            // Clear the flag while waiting, as polling may itself use other instructions.
+            self.busy_pending = false;
+            self.wait_while_busy()
+                .inspect_err(|_| self.busy_pending = true)?;
+        }
+        Ok(())
+    }
+
+    /// Record whether `command`, just sent, may have left the device busy.
+    ///
+    /// Any instruction accepted after a write enable, such as a program, erase,
+    /// or status register write, may leave the device busy.
+    fn track_busy(&mut self, command: Command) {
+        match CommandOpCode::try_from(command.opcode()) {
+            // Clear Flag Status Register shares its opcode with Write Enable for Volatile
+            // Status Register, but does not set WEL.
+            _ if matches!(command, Command::ClearFlagStatusRegister) => (),
+            Ok(CommandOpCode::WriteEnable | CommandOpCode::WriteEnableVolatile) => {
+                self.write_latched = true;
+            }
+            Ok(CommandOpCode::WriteDisable) => self.write_latched = false,
+            _ if Self::allowed_while_busy(command) => (),
+            _ => {
+                self.busy_pending |= self.write_latched;
+                self.write_latched = false;
             }
         }
-
-        // Otherwise and by default, poll status register 1 instead.
-        self.read_status1().map(|status| status.get_busy())
     }
 
-    /// Wait until the device stops being busy.
-    ///
-    /// This polls using `is_busy()`, which uses the flag status
-    /// register if available or otherwise uses status register 1.
-    pub fn wait_while_busy(&mut self) -> Result<()> {
-        while self.is_busy()? {}
+    /// Convenience method for issuing a single command and not caring about the returned data
+    pub fn command(&mut self, command: Command) -> Result<()> {
+        self.write(command, &[])?;
         Ok(())
     }
 
-    /// Read SFDP register data.
+    /// Issue an instruction not modelled by `Command`, such as a vendor-specific instruction
+    /// from a datasheet.
     ///
-    /// `addr` is always sent as a 24-bit address, regardless of the address_bytes setting.
-    pub fn read_sfdp(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
-        self.exchange(Command::ReadSFDPRegister(Address24Bits(addr)), &[], 1 + len)
-            .map(|data| data[1..].to_vec())
-    }
-
-    /// Writes `command` and `data` to the flash memory, then returns `nbytes` of response.
-    pub fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
-        log::trace!(
-            "SPI exchange: write {:?} {:02X?}, read {} bytes",
-            command,
-            &data,
-            nbytes
-        );
-        let rx = self.access.exchange(command, data, nbytes)?;
-        log::trace!("SPI exchange: read {:02X?}", &rx[1 + data.len()..]);
-        Ok(rx[1 + data.len()..].to_vec())
+    /// The instruction `opcode` is sent, followed by `address` using the current number of
+    /// address bytes if given, then `dummy_cycles` rounded up to whole dummy bytes. If
+    /// `direction` is `Direction::Read`, `buf` is filled with the data read, and if it is
+    /// `Direction::Write`, `buf` is sent.
+    ///
+    /// If `write_enable` is set, the instruction is treated like a program or register
+    /// write: Write Enable is sent first, and the device is polled until it is no longer
+    /// busy afterwards. Raw instructions are never rejected by strict mode.
+    pub fn exec_raw(
+        &mut self,
+        opcode: u8,
+        address: Option<u32>,
+        dummy_cycles: u8,
+        direction: Direction,
+        buf: &mut [u8],
+        write_enable: bool,
+    ) -> Result<()> {
+        let address = address
+            .map(|address| self.encode_address(address, self.address_bytes))
+            .transpose()?;
+        let spec = RawCommand {
+            opcode,
+            address,
+            dummy_cycles,
+            direction,
+            write_enable,
+        };
+        self.execute(&spec, buf)
     }
 
-    /// Writes `command` and `data` to the flash memory, without reading the response.
-    pub fn write(&mut self, command: Command, data: &[u8]) -> Result<()> {
-        log::trace!("SPI write: {:?} {:02X?}", command, data);
-        self.access.write(command, data)?;
+    /// Send `command` with its data.
+    ///
+    /// Write Enable is sent first if required, and if the device is busy afterwards,
+    /// it is polled until ready.
+    pub fn send(&mut self, command: CommandWithData<'_>) -> Result<()> {
+        if command.needs_write_enable() {
+            self.write_enable()?;
+        }
+        self.write(command.command(), command.data())?;
+        if command.command().busy_after() {
+            self.wait_while_busy()?;
+        }
         Ok(())
     }
 
-    /// Convenience method for issuing a single command and not caring about the returned data
-    pub fn command(&mut self, command: Command) -> Result<()> {
-        self.write(command, &[])?;
+    /// Issue the instruction described by `spec`.
+    ///
+    /// Write Enable is sent first if required, then the instruction with its address and
+    /// dummy bytes. Depending on the direction of its data phase, `buf` is either filled
+    /// with the data read or sent, and is otherwise ignored. If the device is busy after
+    /// the instruction, it is polled until ready.
+    pub fn execute(&mut self, spec: &impl CommandSpec, buf: &mut [u8]) -> Result<()> {
+        let command = spec.command();
+        let mut data = alloc::vec![0; (spec.dummy_cycles() as usize).div_ceil(8)];
+        if spec.needs_write_enable() {
+            self.write_enable()?;
+        }
+        match spec.direction() {
+            Direction::Read => {
+                let rx = self.exchange(command, &data, buf.len())?;
+                buf.copy_from_slice(&rx);
+            }
+            Direction::Write => {
+                data.extend_from_slice(buf);
+                self.write(command, &data)?;
+            }
+            Direction::None => self.write(command, &data)?,
+        }
+        if spec.busy_after() {
+            self.wait_while_busy()?;
+        }
         Ok(())
     }
 
@@ -988,36 +4656,52 @@ where
     /// * `address` must not exceed the current number of address bytes
     /// * Both `address` and `address+length` must be within the flash memory bounds,
     ///   if the capacity is known.
+    ///
     /// Returns either Err(Error::InvalidAddress) or Ok(()).
     fn check_address_length(&self, address: u32, length: usize) -> Result<()> {
         log::trace!("Checking address={:08X} length={}", address, length);
-        let start = address as usize;
-        let end = (address as usize) + length - 1;
-        let max_addr = 1 << (self.address_bytes * 8);
-
-        if (end & (max_addr - 1)) < start {
-            log::error!("Operation would wrap");
-            Err(Error::InvalidAddress {
-                address: end as u32,
-            })
-        } else if end > max_addr {
-            log::error!("Operation would exceed largest address");
-            Err(Error::InvalidAddress {
-                address: end as u32,
-            })
-        } else {
-            match self.capacity {
-                Some(capacity) if (end >= capacity) => {
+        // Checked in u64, so neither the end address nor the limit overflows on 32-bit targets.
+        let start = address as u64;
+        if length == 0 {
+            // An empty operation may start at the end of memory, but not beyond it.
+            return match self.capacity {
+                Some(capacity) if start > capacity as u64 => {
                     log::error!("Operation would exceed flash capacity");
-                    Err(Error::InvalidAddress {
-                        address: end as u32,
-                    })
+                    Err(Error::InvalidAddress { address })
                 }
                 _ => Ok(()),
+            };
+        }
+        let end = start.saturating_add(length as u64 - 1);
+        let end_address = end.min(u32::MAX as u64) as u32;
+        if end >= self.max_address() {
+            log::error!("Operation would exceed largest address");
+            return Err(Error::InvalidAddress {
+                address: end_address,
+            });
+        }
+        match self.capacity {
+            Some(capacity) if end >= capacity as u64 => {
+                log::error!("Operation would exceed flash capacity");
+                Err(Error::InvalidAddress {
+                    address: end_address,
+                })
             }
+            _ => Ok(()),
         }
     }
 
+    /// Get the size of the address space reachable with the current number of
+    /// address bytes and any address extension, across all dies.
+    fn max_address(&self) -> u64 {
+        let bits = if self.address_extended() {
+            32
+        } else {
+            self.address_bytes * 8
+        };
+        (1u64 << bits) * self.dies as u64
+    }
+
     /// Work out what combination of erase operations to run to efficiently
     /// erase the specified memory.
     fn make_erase_plan(&self, address: u32, length: usize) -> Result<ErasePlan> {
@@ -1026,6 +4710,24 @@ where
             address,
             length
         );
+        // With a non-uniform layout, plan each region using only its erase instructions.
+        if let Some(layout) = &self.layout {
+            let mut plan = Vec::new();
+            for (region, start, length) in layout.overlapping(address, length) {
+                let insts: Vec<_> = region
+                    .erase_insts
+                    .iter()
+                    .map(|inst| (inst.size as usize, inst.opcode, inst.time_typ))
+                    .collect();
+                if insts.is_empty() {
+                    log::warn!("No erase instructions for region at 0x{:08X}", region.start);
+                    return Err(Error::NoEraseInstruction);
+                }
+                plan.extend(ErasePlan::new(&insts, start, length).0);
+            }
+            return Ok(ErasePlan(plan));
+        }
+
         // Erase instructions: (size in bytes, opcode).
         let mut insts = Vec::new();
 
@@ -1106,8 +4808,8 @@ where
         data: &[u8],
         erase_plan: &ErasePlan,
     ) -> Result<Vec<u8>> {
-        let preamble = self.read_erase_preamble(address, &erase_plan)?;
-        let postamble = self.read_erase_postamble(address, data.len(), &erase_plan)?;
+        let preamble = self.read_erase_preamble(address, erase_plan)?;
+        let postamble = self.read_erase_postamble(address, data.len(), erase_plan)?;
         let mut full_data = preamble;
         full_data.extend(data);
         full_data.extend(&postamble);
@@ -1118,31 +4820,68 @@ where
     ///
     /// `cb` is called with the number of bytes erased so far.
     fn run_erase_plan<F: Fn(usize)>(&mut self, plan: &ErasePlan, cb: F) -> Result<()> {
+        for (opcode, size, base, _) in plan.0.iter() {
+            if !Self::is_chip_erase(*opcode) {
+                self.check_unprotected(*base, *size)?;
+            }
+        }
         let mut total_erased = 0;
         cb(total_erased);
-        for (opcode, size, base, duration) in plan.0.iter() {
+        let ops = plan.0.len();
+        self.report_erased(0, ops, plan.total_duration());
+        for (idx, (opcode, size, base, duration)) in plan.0.iter().enumerate() {
             log::trace!(
                 "Executing erase plan: Erase 0x{:02X} ({} bytes) from 0x{:08X}",
                 opcode,
                 size,
                 base
             );
-            self.write_enable()?;
-            self.write(
-                Command::try_from_byte(*opcode, Some(Either::Left(Address24Bits(*base))))?,
-                &[],
-            )?;
-            if let Some(duration) = duration {
-                self.access.delay(*duration / 2);
+            if Self::is_chip_erase(*opcode) {
+                self.chip_erase(*duration)?;
+            } else {
+                let command = self.address_command(*opcode, *base)?;
+                self.operation_enable()?;
+                self.write(command, &[])?;
+                let (_, timeout) = self.erase_times(*opcode);
+                self.wait_for_operation(*base, *duration, timeout)?;
+                if let Some(dirty) = self.dirty.as_mut() {
+                    dirty.mark_erased(*base, *size);
+                }
             }
-            self.wait_while_busy()?;
             total_erased += size;
             cb(total_erased);
+            let remaining = plan.0[idx + 1..].iter().map(|op| op.3).sum();
+            self.report_erased(idx + 1, ops, remaining);
         }
         cb(total_erased);
         Ok(())
     }
 
+    /// Report `done` of `total` bytes programmed to the progress sink, if any,
+    /// estimating the time remaining from the typical page program time.
+    fn report_written(&mut self, done: usize, total: usize, page_size: usize) {
+        let pages = (total - done).div_ceil(page_size) as u32;
+        let remaining = self.page_program_times().map(|(typ, _)| typ * pages);
+        if let Some(sink) = self.progress.as_mut() {
+            sink.bytes_written(Progress {
+                done,
+                total,
+                remaining,
+            });
+        }
+    }
+
+    /// Report `done` of `total` erase operations to the progress sink, if any.
+    fn report_erased(&mut self, done: usize, total: usize, remaining: Option<Duration>) {
+        if let Some(sink) = self.progress.as_mut() {
+            sink.sectors_erased(Progress {
+                done,
+                total,
+                remaining,
+            });
+        }
+    }
+
     /// Execute the sequence of erase operations from `plan`, and draw a progress bar
     /// to the terminal.
     #[cfg(feature = "std")]
@@ -1154,7 +4893,7 @@ where
                 .progress_chars(Self::DATA_PROGRESS_CHARS),
         );
         pb.set_message("Erasing");
-        self.run_erase_plan(&plan, |n| pb.set_position(n as u64))?;
+        self.run_erase_plan(plan, |n| pb.set_position(n as u64))?;
         pb.set_style(
             ProgressStyle::with_template(Self::DATA_FINISHED_TPL)
                 .unwrap()
@@ -1195,3 +4934,1219 @@ where
         }
     }
 }
+
+#[test]
+fn test_transfer_alignment() {
+    /// Flash memory which requires word-aligned transfers of at most 8 bytes.
+    struct AlignedAccess(Vec<u8>);
+
+    impl FlashAccess for AlignedAccess {
+        type Error = Error;
+
+        fn exchange(
+            &mut self,
+            command: Command,
+            data: &[u8],
+            nbytes: usize,
+        ) -> core::result::Result<Vec<u8>, Error> {
+            let mut rx = alloc::vec![0; command.len() + data.len() + nbytes];
+            match command {
+                Command::FastRead(Address24Bits(addr)) => {
+                    let addr = addr as usize;
+                    assert!(addr.is_multiple_of(4) && nbytes.is_multiple_of(4) && nbytes <= 8);
+                    rx[5..].copy_from_slice(&self.0[addr..addr + nbytes]);
+                }
+                Command::PageProgram(Address24Bits(addr)) => {
+                    let addr = addr as usize;
+                    assert!(
+                        addr.is_multiple_of(4) && data.len().is_multiple_of(4) && data.len() <= 8
+                    );
+                    for (old, new) in self.0[addr..].iter_mut().zip(data) {
+                        *old &= new;
+                    }
+                }
+                _ => (),
+            }
+            Ok(rx)
+        }
+
+        fn transfer_alignment(&self) -> usize {
+            4
+        }
+
+        fn max_transfer_size(&self) -> Option<usize> {
+            Some(10)
+        }
+    }
+
+    let mut access = AlignedAccess((0..64).collect());
+    let mut flash = Flash::new(&mut access);
+    assert_eq!(flash.read(3, 13).unwrap(), (3..16).collect::<Vec<u8>>());
+    flash.page_program(33, &[0, 0, 0]).unwrap();
+    assert_eq!(flash.read(32, 6).unwrap(), [32, 0, 0, 0, 36, 37]);
+}
+
+#[test]
+fn test_hybrid_layout_erase() {
+    let inst = |opcode, size| SFDPEraseInst {
+        opcode,
+        size,
+        time_typ: None,
+        time_max: None,
+    };
+    let layout = Layout::new(alloc::vec![
+        EraseRegion {
+            start: 0,
+            size: 64 * 1024,
+            erase_insts: alloc::vec![inst(0x20, 4096)],
+        },
+        EraseRegion {
+            start: 64 * 1024,
+            size: 128 * 1024,
+            erase_insts: alloc::vec![inst(0xD8, 64 * 1024)],
+        },
+    ])
+    .unwrap();
+    let mut mock = mock::MockFlash::new(192 * 1024);
+    mock.memory_mut().fill(0);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_layout(Some(layout));
+    let plan = flash.make_erase_plan(0x8000, 0x18000).unwrap();
+    let opcodes: Vec<u8> = plan.0.iter().map(|op| op.0).collect();
+    assert_eq!(opcodes, [[0x20; 8].as_slice(), &[0xD8]].concat());
+    flash.erase_range(0x8000, 0x18000, false).unwrap();
+    // 4kB sectors cannot be used in the upper region.
+    assert!(flash.erase_range(0x10000, 0x1000, false).is_err());
+    let memory = mock.memory();
+    assert!(memory[..0x8000].iter().all(|x| *x == 0));
+    assert!(memory[0x8000..0x20000].iter().all(|x| *x == 0xFF));
+    assert!(memory[0x20000..].iter().all(|x| *x == 0));
+}
+
+#[test]
+fn test_strict_mode() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_strict(true);
+    flash.read_id().unwrap();
+    flash.read(0, 16).unwrap();
+    assert!(matches!(
+        flash.read_status3(),
+        Err(Error::Unsupported { opcode: 0x15 })
+    ));
+    // Without SFDP parameters, no erase instruction is confirmed supported.
+    assert!(matches!(
+        flash.erase_sector(0),
+        Err(Error::Unsupported { opcode: 0x20 })
+    ));
+    flash.set_strict(false);
+    flash.erase_sector(0).unwrap();
+}
+
+#[test]
+fn test_plan_erase() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    mock.memory_mut().fill(0);
+    let mut flash = mock::configured_flash(&mut mock);
+    let plan = flash.plan_erase(0x1000, 0x2000).unwrap();
+    let ops: Vec<(u8, u32)> = plan
+        .operations()
+        .map(|op| (op.opcode, op.address))
+        .collect();
+    assert_eq!(ops, [(0x20, 0x1000), (0x20, 0x2000)]);
+    assert_eq!(plan.total_size(), 0x2000);
+    flash.run_erase(&plan).unwrap();
+    assert!(flash.plan_erase(0x1800, 0x1000).is_err());
+    // The whole memory is erased with a single chip erase.
+    let plan = flash.plan_erase(0, 64 * 1024).unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan.operations().next().unwrap().opcode, 0xC7);
+    flash.run_erase(&plan).unwrap();
+    assert!(mock.memory().iter().all(|x| *x == 0xFF));
+}
+
+#[test]
+fn test_busy_polling() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    // Report busy in status register 1, but ready in the flag status register.
+    mock.set_register(Register::Status1, 0b0000_0001);
+    let mut flash = Flash::new(&mut mock);
+    assert_eq!(flash.busy_polling(), SFDPBusyPolling::StatusRegister);
+    assert!(flash.is_busy().unwrap());
+    flash.set_quirks(Quirks {
+        busy_polling: Some(SFDPBusyPolling::FlagStatusRegister),
+        ..Quirks::default()
+    });
+    assert!(!flash.is_busy().unwrap());
+}
+
+#[test]
+fn test_verify_programs() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    // A bit which reads back wrongly, as from a worn-out cell.
+    mock.inject_bit_flip(0x1105, 0x01);
+    let mut flash = mock::configured_flash(&mut mock);
+    assert!(matches!(
+        flash.program_data_verified(0x1000, &[0; 512]),
+        Err(Error::ReadbackError {
+            address: 0x1105,
+            wrote: 0x00,
+            read: 0x01
+        })
+    ));
+    flash.program_data(0x2000, &[0; 512]).unwrap();
+    flash.erase_sector(0x1000).unwrap();
+    flash.set_verify_programs(true);
+    assert!(flash.verify_programs());
+    assert!(matches!(
+        flash.program_data(0x1000, &[0; 512]),
+        Err(Error::ReadbackError {
+            address: 0x1105,
+            ..
+        })
+    ));
+    flash.program_data(0x3000, &[0; 512]).unwrap();
+}
+
+#[test]
+fn test_busy_pending() {
+    /// Transport which fails the first status read, logging each opcode sent.
+    struct FlakyAccess {
+        mock: mock::MockFlash,
+        fail: bool,
+        opcodes: Vec<u8>,
+    }
+    impl FlashAccess for FlakyAccess {
+        type Error = Error;
+        fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+            self.opcodes.push(command.opcode());
+            if command.opcode() == 0x05 && core::mem::take(&mut self.fail) {
+                return Err(Error::NoSpace);
+            }
+            self.mock.exchange(command, data, nbytes)
+        }
+    }
+
+    let mut access = FlakyAccess {
+        mock: mock::MockFlash::new(64 * 1024),
+        fail: true,
+        opcodes: Vec::new(),
+    };
+    let mut flash = Flash::new(&mut access);
+    flash.set_capacity(64 * 1024);
+    flash.erase_sector(0x1000).unwrap_err();
+    assert!(flash.is_busy_pending());
+    // The next instruction waits for the erase to finish first.
+    flash.read(0, 4).unwrap();
+    assert!(!flash.is_busy_pending());
+    flash.erase_sector(0x2000).unwrap();
+    assert!(!flash.is_busy_pending());
+    assert_eq!(
+        access.opcodes,
+        [0x06, 0x20, 0x05, 0x05, 0x0B, 0x06, 0x20, 0x05]
+    );
+}
+
+#[test]
+fn test_octal_mode() {
+    /// Transport framing each instruction for its protocol, logging the headers sent.
+    struct OctalAccess {
+        mock: mock::MockFlash,
+        protocol: Protocol,
+        headers: Vec<Vec<u8>>,
+    }
+    impl FlashAccess for OctalAccess {
+        type Error = Error;
+        fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+            let tx =
+                Transaction::from_command(command, data.len(), nbytes).with_protocol(self.protocol);
+            let mut header = [0; 64];
+            let len = tx.write_header(&mut header)?;
+            self.headers.push(header[..len].to_vec());
+            self.mock.exchange(command, data, nbytes)
+        }
+        fn supports_protocol(&self, protocol: Protocol) -> bool {
+            self.mock.supports_protocol(protocol)
+        }
+        fn set_protocol(&mut self, protocol: Protocol) {
+            self.protocol = protocol;
+            self.mock.set_protocol(protocol);
+        }
+    }
+
+    let mut access = OctalAccess {
+        mock: mock::MockFlash::new(64 * 1024).with_busy_polls(1),
+        protocol: Protocol::Spi,
+        headers: Vec::new(),
+    };
+    let mut flash = Flash::new(&mut access);
+    flash.set_capacity(64 * 1024);
+    assert!(matches!(
+        flash.enter_octal(OctalFamily::Macronix, false),
+        Err(Error::BoardUnsupported)
+    ));
+    flash.set_board(BoardConfig {
+        octal_io: true,
+        ..BoardConfig::default()
+    });
+    flash.enter_octal(OctalFamily::Macronix, false).unwrap();
+    flash.erase_sector(0x1000).unwrap();
+    flash.exit_octal().unwrap();
+    assert_eq!(flash.read_jedec_id().unwrap(), (0, 0xEF, 0x4018));
+    drop(flash);
+    assert!(!access.mock.is_octal());
+    assert_eq!(access.mock.busy_violations(), 0);
+    assert_eq!(access.mock.memory()[0x1000], 0xFF);
+    // Status polls in octal mode send a zero address and 4 dummy cycles.
+    let poll = [0x05, 0xFA, 0, 0, 0, 0, 0, 0, 0, 0];
+    let expected: [&[u8]; 10] = [
+        &[0x06],
+        &[0x72],
+        &poll,
+        &[0x06, 0xF9],
+        &[0x21, 0xDE, 0x00, 0x00, 0x10, 0x00],
+        &poll,
+        &poll,
+        &[0x66, 0x99],
+        &[0x99, 0x66],
+        &[0x9F],
+    ];
+    assert_eq!(access.headers, expected);
+}
+
+#[test]
+fn test_const_command_encoding() {
+    const READ: CommandBytes = Command::ReadData(Address24Bits(0x123456)).to_array();
+    const ERASE: CommandBytes = Command::SectorErase4B(Address32Bits(0x89ABCDEF)).to_array();
+    const WREN: ([u8; 5], usize) = Command::WriteEnable.to_array().into_parts();
+    assert_eq!(*READ, [0x03, 0x12, 0x34, 0x56]);
+    assert_eq!(*ERASE, [0x21, 0x89, 0xAB, 0xCD, 0xEF]);
+    assert_eq!(WREN, ([0x06, 0, 0, 0, 0], 1));
+}
+
+#[test]
+fn test_detect() {
+    // Without SFDP, the device is found in the database by its JEDEC ID.
+    let mut mock = mock::MockFlash::new(2 * 1024 * 1024).with_jedec_id(0x20, 0x2015);
+    let flash = Flash::detect(&mut mock).unwrap();
+    assert_eq!(flash.capacity(), Some(2 * 1024 * 1024));
+    assert_eq!(flash.erase_size(), Some(64 * 1024));
+    assert_eq!(flash.erase_opcode(), 0xD8);
+    assert_eq!(flash.quirks(), Quirks::default());
+
+    // Vendor quirks are applied for devices without SFDP.
+    let mut mock = mock::MockFlash::new(2 * 1024 * 1024).with_jedec_id(0xC8, 0x4015);
+    let flash = Flash::detect(&mut mock).unwrap();
+    assert_eq!(
+        flash.get_id().unwrap().manufacturer(),
+        Manufacturer::GigaDevice
+    );
+    assert!(flash.quirks().combined_status_write);
+
+    let mut mock = mock::MockFlash::new(64 * 1024).with_jedec_id(0xAA, 0x1234);
+    assert!(matches!(
+        Flash::detect(&mut mock),
+        Err(Error::UnknownDevice {
+            manufacturer_id: 0xAA,
+            device_id: 0x1234
+        })
+    ));
+    let mut mock = mock::MockFlash::new(64 * 1024).with_jedec_id(0, 0);
+    match Flash::detect(&mut mock) {
+        Err(Error::Detect { stage, source }) => {
+            assert_eq!(stage, DetectStage::ReadId);
+            assert!(matches!(*source, Error::InvalidManufacturer));
+        }
+        _ => panic!("expected detection to fail reading the ID"),
+    }
+}
+
+#[test]
+fn test_read_device_id() {
+    let mut mock = mock::MockFlash::new(64 * 1024).with_jedec_id(0xC2, 0x2016);
+    let mut flash = Flash::new(&mut mock);
+    assert_eq!(flash.read_device_id().unwrap(), (0, 0xC2, 0x15));
+    for mode in [DeviceIDMode::DualIO, DeviceIDMode::QuadIO] {
+        let id = flash.read_manufacturer_device_id(mode).unwrap();
+        assert_eq!(id.manufacturer, Manufacturer::Macronix);
+        assert_eq!(id.device_id, 0x15);
+    }
+    assert_eq!(flash.read_id().unwrap().device_id_short, 0x15);
+    assert_eq!(Command::ReadDeviceIDQuad.opcode(), 0x94);
+}
+
+#[test]
+fn test_status_write_mode() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    flash
+        .write_status2(StatusRegister2(0x02), StatusWriteMode::Volatile)
+        .unwrap();
+    flash
+        .write_register(Register::Status1, 0x1C, StatusWriteMode::Volatile)
+        .unwrap();
+    assert_eq!(flash.read_status2().unwrap().0, 0x02);
+    assert_eq!(flash.read_status1().unwrap().0, 0x1C);
+    flash
+        .write_status3(StatusRegister3(0x04), StatusWriteMode::NonVolatile)
+        .unwrap();
+    assert_eq!(flash.read_status3().unwrap().0, 0x04);
+    assert_eq!(mock.status_write_cycles(), 1);
+}
+
+#[test]
+fn test_config_register1() {
+    let mut mock = mock::MockFlash::new(64 * 1024).with_jedec_id(0x01, 0x0216);
+    let mut flash = Flash::new(&mut mock);
+    flash
+        .write_status12(
+            StatusRegister1(0x1C),
+            StatusRegister2(0),
+            StatusWriteMode::NonVolatile,
+        )
+        .unwrap();
+    let mut cr1 = flash.read_config1().unwrap();
+    cr1.set_quad(true);
+    cr1.set_latency_code(2);
+    flash
+        .write_config1(cr1, StatusWriteMode::NonVolatile)
+        .unwrap();
+    let cr1 = flash.read_config1().unwrap();
+    assert!(cr1.get_quad() && !cr1.get_tbparm());
+    assert_eq!((cr1.0, cr1.get_latency_code()), (0x82, 2));
+    assert_eq!(flash.read_status1().unwrap().0, 0x1C);
+
+    // Devices with a register file use WRAR.
+    let mut mock =
+        mock::MockFlash::new(64 * 1024).with_register_file(RegisterFile::spansion_volatile());
+    let mut flash = Flash::new(&mut mock);
+    flash.set_register_file(Some(RegisterFile::spansion_volatile()));
+    flash
+        .write_register(Register::Config1, 0x02, StatusWriteMode::Volatile)
+        .unwrap();
+    assert!(flash.read_config1().unwrap().get_quad());
+}
+
+#[test]
+fn test_exec_raw() {
+    let mut mock = mock::MockFlash::new(64 * 1024).with_busy_polls(2);
+    let mut flash = Flash::new(&mut mock);
+    flash.set_strict(true);
+    let mut id = [0; 3];
+    flash
+        .exec_raw(0x9F, None, 0, Direction::Read, &mut id, false)
+        .unwrap();
+    assert_eq!(id, [0xEF, 0x40, 0x18]);
+
+    // A write-type instruction is write enabled, and waited for.
+    flash.program_data(0x1000, &[0x55; 16]).unwrap();
+    flash
+        .exec_raw(0x20, Some(0x1000), 0, Direction::None, &mut [], true)
+        .unwrap();
+    assert!(!flash.is_busy_pending());
+    let mut data = [0; 4];
+    flash
+        .exec_raw(0x0B, Some(0x1000), 8, Direction::Read, &mut data, false)
+        .unwrap();
+    assert_eq!(data, [0xFF; 4]);
+    flash
+        .exec_raw(0x02, Some(0x1002), 0, Direction::Write, &mut [1, 2], true)
+        .unwrap();
+    assert_eq!(flash.read(0x1001, 3).unwrap(), [0xFF, 1, 2]);
+}
+
+#[test]
+fn test_stacked_dies() {
+    let mut mock = mock::MockFlash::new(64 * 1024)
+        .with_dies(2)
+        .with_busy_polls(2);
+    let mut flash = Flash::new(&mut mock);
+    flash.set_capacity(32 * 1024);
+    flash.set_erase_size(4096);
+    flash.set_dies(2);
+    assert_eq!(flash.capacity(), Some(64 * 1024));
+    assert!(flash.select_die(2).is_err());
+
+    let data: Vec<u8> = (0..32).collect();
+    flash.program_data(0x7FF0, &data).unwrap();
+    assert_eq!(flash.active_die(), 1);
+    assert_eq!(flash.read(0x7FF0, 32).unwrap(), data);
+    flash.erase_sectors([0x8000], true).unwrap();
+    assert_eq!(flash.read(0x7FFF, 2).unwrap(), [15, 0xFF]);
+    flash.program_data(0xF000, &[0x55]).unwrap();
+    flash.erase().unwrap();
+    assert!(flash.is_erased(0, 64 * 1024).unwrap());
+
+    // A program left running on one die completes before the other die is selected.
+    flash.write_enable().unwrap();
+    flash
+        .exec_raw(0x02, Some(0x7000), 0, Direction::Write, &mut [0x00], false)
+        .unwrap();
+    assert!(flash.busy_pending);
+    flash.select_die(0).unwrap();
+    assert!(!flash.busy_pending);
+    drop(flash);
+    assert_eq!(mock.busy_violations(), 0);
+    assert_eq!(mock.active_die(), 0);
+}
+
+#[test]
+fn test_bank_register() {
+    let mut mock = mock::MockFlash::new(32 * 1024 * 1024);
+    let mut flash = mock::configured_flash(&mut mock);
+    assert!(flash.read(0x100_0000, 1).is_err());
+    flash.set_address_extension(AddressExtension::BankRegister);
+
+    let data: Vec<u8> = (0..32).collect();
+    flash.program_data(0xFF_FFF0, &data).unwrap();
+    assert_eq!(flash.read_bank_register().unwrap().get_bank(), 1);
+    assert_eq!(flash.read(0xFF_FFF0, 32).unwrap(), data);
+    flash.erase_sectors([0x100_0000], true).unwrap();
+    assert_eq!(flash.read(0xFF_FFFF, 2).unwrap(), [15, 0xFF]);
+
+    // The first bank is selected again before handing over the device.
+    flash.reset_bank().unwrap();
+    assert_eq!(flash.read_bank_register().unwrap().get_bank(), 0);
+    assert_eq!(flash.read(0x100_0000, 1).unwrap(), [0xFF]);
+    assert_eq!(flash.read_bank_register().unwrap().get_bank(), 1);
+
+    // A reset returns the device to bank 0, so the bank is written again.
+    flash.reset().unwrap();
+    assert_eq!(flash.read(0x100_0000 - 1, 1).unwrap(), [15]);
+    assert_eq!(flash.read_bank_register().unwrap().get_bank(), 0);
+    drop(flash);
+    assert_eq!(mock.read(0xFF_FFF0, 16).unwrap(), data[..16]);
+    assert_eq!(mock.bank_register(), 0);
+}
+
+#[test]
+fn test_extended_address_register() {
+    let mut mock = mock::MockFlash::new(32 * 1024 * 1024).with_busy_polls(1);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_address_extension(AddressExtension::ExtendedAddressRegister);
+
+    let data: Vec<u8> = (0..32).collect();
+    flash.program_data(0xFF_FFF0, &data).unwrap();
+    assert_eq!(flash.read_extended_address_register().unwrap(), 1);
+    assert_eq!(flash.read(0xFF_FFF0, 32).unwrap(), data);
+    flash.write_extended_address_register(0).unwrap();
+    assert_eq!(flash.read(0x100_0000, 1).unwrap(), [16]);
+    flash.reset_bank().unwrap();
+    assert_eq!(flash.read_extended_address_register().unwrap(), 0);
+
+    flash
+        .set_power_up_address_mode(true, StatusWriteMode::Volatile)
+        .unwrap();
+    let status3 = flash.read_status3().unwrap();
+    assert!(status3.get_adp() && !status3.get_ads());
+    drop(flash);
+    assert_eq!(mock.read(0x100_0000, 16).unwrap(), data[16..]);
+    assert_eq!(mock.busy_violations(), 0);
+}
+
+#[cfg(feature = "embedded-hal")]
+#[test]
+fn test_detect_with_power_up() {
+    /// Delay which counts the time waited.
+    struct TestDelay(u64);
+    impl embedded_hal::delay::DelayNs for TestDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0 += ns as u64;
+        }
+    }
+
+    let mut mock = mock::MockFlash::new(1024 * 1024).with_jedec_id(0xEF, 0x4014);
+    let mut delay = TestDelay(0);
+    let flash = Flash::detect_with_power_up(&mut mock, &mut delay, false).unwrap();
+    assert_eq!(flash.capacity(), Some(1024 * 1024));
+    assert_eq!(delay.0, 10_000_000);
+
+    // A device which stays busy is reported once tPUW has passed.
+    mock.set_stuck_busy(true);
+    let mut delay = TestDelay(0);
+    let result = Flash::detect_with_power_up(&mut mock, &mut delay, true);
+    assert!(matches!(
+        result,
+        Err(Error::Detect {
+            stage: DetectStage::PowerUp,
+            ..
+        })
+    ));
+    assert_eq!(delay.0, 10_000_000);
+    mock.set_stuck_busy(false);
+    let mut delay = TestDelay(0);
+    Flash::detect_with_power_up(&mut mock, &mut delay, true).unwrap();
+    assert_eq!(delay.0, 10_000_000);
+}
+
+#[test]
+fn test_sfdp_tables() {
+    let mut sfdp = vec![0xFF; 0x70];
+    sfdp[..8].copy_from_slice(b"SFDP\x06\x01\x01\xFF");
+    // Basic flash parameters v1.0 of 9 DWORDs, and a vendor table of 2 DWORDs.
+    sfdp[8..16].copy_from_slice(&[0x00, 0x00, 0x01, 0x09, 0x30, 0x00, 0x00, 0xFF]);
+    sfdp[16..24].copy_from_slice(&[0xC2, 0x00, 0x01, 0x02, 0x60, 0x00, 0x00, 0xFF]);
+    sfdp[0x60..0x68].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let mut mock = mock::MockFlash::new(64 * 1024).with_sfdp(sfdp.clone());
+    let mut flash = Flash::new(&mut mock);
+
+    let tables: Vec<_> = flash.sfdp_tables().unwrap().collect();
+    assert_eq!(tables.len(), 2);
+    assert_eq!(
+        (tables[0].parameter_id, tables[0].len_bytes()),
+        (0xFF00, 36)
+    );
+    assert_eq!(
+        tables[1],
+        SFDPParameterHeader {
+            plen: 2,
+            major: 1,
+            minor: 0,
+            parameter_id: 0xFFC2,
+            ptp: 0x60,
+        }
+    );
+    let mut vendor = [0; 8];
+    flash.read_sfdp_raw(tables[1].ptp, &mut vendor).unwrap();
+    assert_eq!(vendor, [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(flash.read_sfdp_blob().unwrap(), sfdp[..0x68]);
+
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    assert!(matches!(
+        flash.sfdp_tables().map(|tables| tables.count()),
+        Err(Error::InvalidSFDPHeader)
+    ));
+}
+
+#[test]
+fn test_update() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = mock::configured_flash(&mut mock);
+    let mut data: Vec<u8> = (0..0x2000).map(|x| x as u8).collect();
+    flash.program(0x1000, &data, false).unwrap();
+    let mut scratch = vec![0; 4096];
+
+    // Setting bits erases each sector touched, keeping the rest of its data.
+    flash.update(0x1FFE, &[0xFF; 4], &mut scratch).unwrap();
+    data[0xFFE..0x1002].fill(0xFF);
+    assert_eq!(flash.read(0x1000, 0x2000).unwrap(), data);
+
+    // Clearing bits programs just the new data, and unchanged data is not written.
+    let programs = flash.access.page_programs();
+    flash.update(0x1800, &[0x00, 0x00], &mut scratch).unwrap();
+    data[0x800..0x802].fill(0x00);
+    assert_eq!(flash.access.page_programs(), programs + 1);
+    flash
+        .update(0x1800, &data[0x800..0x900], &mut scratch)
+        .unwrap();
+    flash.update(0x1800, &[], &mut scratch).unwrap();
+    assert_eq!(flash.access.page_programs(), programs + 1);
+    assert_eq!(flash.read(0x1000, 0x2000).unwrap(), data);
+
+    assert!(matches!(
+        flash.update(0x1000, &[0], &mut scratch[..100]),
+        Err(Error::BufferTooSmall {
+            length: 100,
+            required: 4096
+        })
+    ));
+    assert!(flash.update(0xFFFF, &[0; 2], &mut scratch).is_err());
+    let mut flash = Flash::new(&mut mock);
+    assert!(matches!(
+        flash.update(0x1000, &[0], &mut scratch),
+        Err(Error::NoEraseInstruction)
+    ));
+}
+
+#[test]
+fn test_update_image() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = mock::configured_flash(&mut mock);
+    let mut image: Vec<u8> = (0..6000).map(|x| (x % 251) as u8).collect();
+    flash.program(0x1000, &image, false).unwrap();
+    flash.program_data(0x3000, &[0x42]).unwrap();
+    let mut scratch = vec![0; 4096];
+
+    // An unchanged image needs no erases or programs.
+    let summary = flash.update_image(0x1000, &image, &mut scratch).unwrap();
+    assert_eq!(summary, ImageUpdate::default());
+
+    // Changes only clearing bits program just the changed pages.
+    image[0x300] = 0;
+    image[0x1710] = 0;
+    let summary = flash.update_image(0x1000, &image, &mut scratch).unwrap();
+    assert_eq!((summary.sectors_erased, summary.pages_programmed), (0, 2));
+
+    // Setting bits erases the sector, reprogramming only its pages which are not blank.
+    image[0x1200] = 0xFF;
+    let summary = flash.update_image(0x1000, &image, &mut scratch).unwrap();
+    assert_eq!((summary.sectors_erased, summary.pages_programmed), (1, 8));
+    assert_eq!(flash.read(0x1000, image.len()).unwrap(), image);
+    assert_eq!(flash.read(0x2770, 1).unwrap(), [0xFF]);
+    assert_eq!(flash.read(0x3000, 1).unwrap(), [0x42]);
+    assert!(flash
+        .update_image(0x1000, &image, &mut scratch[..100])
+        .is_err());
+}
+
+#[test]
+fn test_send_command_with_data() {
+    use mock::MockFlash;
+
+    let command = CommandWithData::PageProgram(Address24Bits(0x123456), &[0xA5, 0x5A]);
+    assert_eq!(command.to_vec(), [0x02, 0x12, 0x34, 0x56, 0xA5, 0x5A]);
+    assert_eq!(command.len(), 6);
+    assert!(command.needs_write_enable());
+    let command = CommandWithData::WriteStatusRegisters12([0x1C, 0x02]);
+    assert_eq!(command.to_vec(), [0x01, 0x1C, 0x02]);
+    assert!(!CommandWithData::from(Command::ReadJEDECID).needs_write_enable());
+
+    let mut mock = MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    flash
+        .send(CommandWithData::PageProgram(
+            Address24Bits(0x100),
+            &[1, 2, 3],
+        ))
+        .unwrap();
+    flash
+        .send(CommandWithData::WriteStatusRegister1(0b0000_1100))
+        .unwrap();
+    assert_eq!(flash.read(0x100, 4).unwrap(), [1, 2, 3, 0xFF]);
+    assert_eq!(mock.register(Register::Status1), 0b0000_1100);
+}
+
+#[test]
+fn test_chip_erase_force() {
+    use mock::MockFlash;
+
+    let quirks = Quirks {
+        combined_status_read: true,
+        ..Quirks::default()
+    };
+    let mut mock = MockFlash::new(256 * 1024).with_quirks(quirks);
+    mock.set_register(Register::Status1, 0b0000_1000);
+    mock.set_register(Register::Status2, 0b0100_0000);
+    mock.memory_mut()[0x100..0x200].fill(0x00);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_quirks(quirks);
+
+    // A plain chip erase is ignored while memory is protected.
+    flash.erase().unwrap();
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0x00]);
+    flash.chip_erase_force(None).unwrap();
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0xFF]);
+    assert_eq!(mock.register(Register::Status1), 0b0000_1000);
+    assert_eq!(mock.register(Register::Status2), 0b0100_0000);
+    // Only the volatile status register bits were written.
+    assert_eq!(mock.status_write_cycles(), 0);
+
+    // CMP is restored even if clearing BP fails.
+    struct FailingAccess<'a> {
+        mock: &'a mut MockFlash,
+        fail: bool,
+    }
+    impl FlashAccess for FailingAccess<'_> {
+        type Error = Error;
+        fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+            let clears_bp = command.opcode() == 0x01 && data.first() == Some(&0);
+            if clears_bp && core::mem::take(&mut self.fail) {
+                return Err(Error::NoSpace);
+            }
+            self.mock.exchange(command, data, nbytes)
+        }
+    }
+    mock.memory_mut()[0x100] = 0x00;
+    let mut access = FailingAccess {
+        mock: &mut mock,
+        fail: true,
+    };
+    let mut flash = Flash::new(&mut access);
+    flash.set_capacity(256 * 1024);
+    flash.set_quirks(quirks);
+    assert!(matches!(flash.chip_erase_force(None), Err(Error::NoSpace)));
+    drop(flash);
+    assert!(!access.fail);
+    assert_eq!(mock.memory()[0x100], 0x00);
+    assert_eq!(mock.register(Register::Status1), 0b0000_1000);
+    assert_eq!(mock.register(Register::Status2), 0b0100_0000);
+
+    // Individual block locks are restored after the erase.
+    mock.set_register(Register::Status1, 0);
+    mock.set_register(Register::Status2, 0);
+    mock.set_register(Register::Status3, 0b0000_0100);
+    mock.memory_mut()[0x21000] = 0x00;
+    let mut flash = mock::configured_flash(&mut mock);
+    for address in [0x0000_1000, 0x0002_0000] {
+        flash.write_enable().unwrap();
+        flash
+            .command(Command::IndividualBlockLock(Address24Bits(address)))
+            .unwrap();
+    }
+    let layout = protection::IndividualBlockLocks::winbond();
+    flash.chip_erase_force(Some(&layout)).unwrap();
+    flash.set_protection_scheme(alloc::boxed::Box::new(layout));
+    assert_eq!(flash.read(0x21000, 1).unwrap(), [0xFF]);
+    assert!(flash.is_address_protected(0x1000).unwrap());
+    assert!(!flash.is_address_protected(0x2000).unwrap());
+    assert!(flash.is_address_protected(0x20000).unwrap());
+}
+
+#[test]
+fn test_unlock_all() {
+    use mock::MockFlash;
+
+    // Winbond, with BP, CMP, and individual block locks all active.
+    let mut mock = MockFlash::new(64 * 1024).with_jedec_id(0xEF, 0x4010);
+    mock.set_register(Register::Status1, 0b0001_0100);
+    mock.set_register(Register::Status2, 0b0100_0000);
+    mock.set_register(Register::Status3, 0b0000_0100);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.write_enable().unwrap();
+    flash
+        .command(Command::IndividualBlockLock(Address24Bits(0x1000)))
+        .unwrap();
+    flash.unlock_all().unwrap();
+    assert_eq!(
+        flash.get_id().unwrap().manufacturer(),
+        Manufacturer::Winbond
+    );
+    assert!(!flash.read_status1().unwrap().get_block_protect().0);
+    assert!(!flash.read_status2().unwrap().get_cmp());
+    flash.set_protection_scheme(alloc::boxed::Box::new(
+        protection::IndividualBlockLocks::winbond(),
+    ));
+    assert!(!flash.is_address_protected(0x1000).unwrap());
+    flash.erase().unwrap();
+    drop(flash);
+    assert_eq!(mock.memory()[0], 0xFF);
+
+    // Infineon, with PPBs programmed, which can only be erased while unlocked.
+    let mut mock = MockFlash::new(64 * 1024).with_jedec_id(0x01, 0x0219);
+    let mut flash = mock::configured_flash(&mut mock);
+    let ppb = protection::PersistentProtectionBits { sector_size: 4096 };
+    flash.set_protection_scheme(alloc::boxed::Box::new(ppb));
+    flash.set_protection(0..0x2000, true).unwrap();
+    flash.unlock_all().unwrap();
+    assert!(!flash.is_address_protected(0).unwrap());
+    flash.set_protection(0..0x2000, true).unwrap();
+    protection::PersistentProtectionBits::lock(&mut flash).unwrap();
+    assert!(matches!(flash.unlock_all(), Err(Error::ProtectionLocked)));
+    assert!(flash.is_address_protected(0).unwrap());
+}
+
+#[test]
+fn test_vectored() {
+    use mock::MockFlash;
+
+    let mut mock = MockFlash::new(64 * 1024);
+    let mut flash = mock::configured_flash(&mut mock);
+    let (first, second, third) = ([1u8; 200], [2u8; 100], [3u8; 10]);
+    let requests: [(u32, &[u8]); 3] = [(0x1000, &first), (0x10C8, &second), (0x4000, &third)];
+    // The page shared by the first two requests is gathered, and the rest is borrowed.
+    let segments = flash.write_segments(&requests).unwrap();
+    let segments: Vec<(u32, usize, bool)> = segments
+        .iter()
+        .map(|(a, d)| (*a, d.len(), matches!(d, alloc::borrow::Cow::Borrowed(_))))
+        .collect();
+    assert_eq!(
+        segments,
+        [(0x1000, 256, false), (0x1100, 44, true), (0x4000, 10, true)]
+    );
+    flash.write_vectored(&requests).unwrap();
+    assert!(flash
+        .write_vectored(&[(0x100, &[0]), (0x10000, &[0])])
+        .is_err());
+
+    let (mut a, mut b, mut c, mut d) = ([0u8; 8], [0u8; 300], [0u8; 4], [0u8; 1]);
+    let mut requests: [(u32, &mut [u8]); 4] = [
+        (0x0FF8, &mut a),
+        (0x1000, &mut b),
+        (0x3FFE, &mut c),
+        (0x0100, &mut d),
+    ];
+    flash.read_vectored(&mut requests).unwrap();
+    assert_eq!(a, [0xFF; 8]);
+    assert_eq!(b[..200], [1; 200]);
+    assert_eq!(b[200..], [2; 100]);
+    assert_eq!(c, [0xFF, 0xFF, 3, 3]);
+    // The rejected write programmed nothing.
+    assert_eq!(d, [0xFF]);
+    assert!(flash.read_vectored(&mut [(0xFFFF, &mut [0; 2])]).is_err());
+
+    // The async versions yield between transfers, and give the same results.
+    let poll = |future: core::pin::Pin<&mut dyn core::future::Future<Output = Result<()>>>| {
+        let waker = core::task::Waker::noop();
+        let mut cx = core::task::Context::from_waker(waker);
+        let mut future = future;
+        loop {
+            if let core::task::Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+                return result;
+            }
+        }
+    };
+    let requests: [(u32, &[u8]); 2] = [(0x5000, &first), (0x6000, &third)];
+    poll(core::pin::pin!(flash.write_vectored_async(&requests))).unwrap();
+    let (mut e, mut f) = ([0u8; 200], [0u8; 10]);
+    let mut requests: [(u32, &mut [u8]); 2] = [(0x5000, &mut e), (0x6000, &mut f)];
+    poll(core::pin::pin!(flash.read_vectored_async(&mut requests))).unwrap();
+    assert_eq!((e, f), (first, third));
+}
+
+#[test]
+fn test_opcode_aliases() {
+    use mock::MockFlash;
+
+    let quirks = Quirks {
+        chip_erase_60: true,
+        reset_f0: true,
+        ..Quirks::default()
+    };
+    let mut mock = MockFlash::new(64 * 1024).with_quirks(quirks);
+    mock.memory_mut()[0x100] = 0x00;
+    let mut flash = mock::configured_flash(&mut mock);
+
+    // The usual encodings are ignored by the device.
+    flash.erase().unwrap();
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0x00]);
+    flash.write(Command::WriteBankRegister, &[1]).unwrap();
+    flash.reset().unwrap();
+    drop(flash);
+    assert_eq!(mock.bank_register(), 1);
+
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_quirks(quirks);
+    flash.set_strict(true);
+    assert!(flash.is_supported(0x60));
+    assert!(!flash.is_supported(0xC7));
+    assert!(flash.is_supported(0xF0));
+    assert!(!flash.is_supported(0x99));
+    flash.erase_range(0, 64 * 1024, false).unwrap();
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0xFF]);
+    flash.set_strict(false);
+    flash.write(Command::WriteBankRegister, &[1]).unwrap();
+    flash.set_strict(true);
+    flash.reset().unwrap();
+    drop(flash);
+    assert_eq!(mock.bank_register(), 0);
+}
+
+#[test]
+fn test_flag_status_errors() {
+    use mock::MockFlash;
+
+    let quirks = Quirks {
+        busy_polling: Some(SFDPBusyPolling::FlagStatusRegister),
+        ..Quirks::default()
+    };
+    let mut mock = MockFlash::new(64 * 1024).with_busy_polls(2);
+    mock.inject_protected_range(0x8000..0x9000);
+    let mut flash = mock::configured_flash(&mut mock);
+    flash.set_quirks(quirks);
+
+    flash.page_program(0x100, &[0x00]).unwrap();
+    assert!(matches!(
+        flash.page_program(0x8100, &[0x00]),
+        Err(Error::RegionProtected { address: 0x8100 })
+    ));
+    assert!(matches!(
+        flash.erase_sector(0x8000),
+        Err(Error::RegionProtected { address: 0x8000 })
+    ));
+    let fsr = flash.read_flag_status().unwrap();
+    assert!(fsr.get_ready() && !fsr.get_protection_error() && !fsr.get_erase_error());
+    flash.erase_sector(0x0000).unwrap();
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0xFF]);
+
+    // Errors from an unchecked operation are not reported for the next one.
+    flash
+        .exec_raw(0x02, Some(0x8200), 0, Direction::Write, &mut [0x00], true)
+        .unwrap();
+    flash.page_program(0x200, &[0x00]).unwrap();
+    assert_eq!(flash.read(0x200, 1).unwrap(), [0x00]);
+
+    // Clearing the flag status register does not latch a write enable.
+    flash.clear_flag_status().unwrap();
+    assert!(!flash.write_latched);
+
+    let fsr = FlagStatusRegister(0b1011_0011);
+    assert!(fsr.get_erase_error() && fsr.get_program_error() && fsr.get_four_byte());
+    assert!(!fsr.get_erase_suspended() && !fsr.get_program_suspended());
+}
+
+#[test]
+fn test_protection_guard() {
+    use mock::MockFlash;
+
+    let mut mock = MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_protection(0x8000..0x10000, true).unwrap();
+    flash.set_protection_guard(true);
+
+    flash.page_program(0x100, &[0x00]).unwrap();
+    assert!(matches!(
+        flash.page_program(0x80F0, &[0x00; 16]),
+        Err(Error::RegionProtected { address: 0x80F0 })
+    ));
+    assert!(matches!(
+        flash.erase_range(0x7000, 0x2000, false),
+        Err(Error::RegionProtected { address: 0x8000 })
+    ));
+    assert!(matches!(flash.erase(), Err(Error::RegionProtected { .. })));
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0x00]);
+
+    // Changing protection discards the cached state.
+    flash.unprotect_all().unwrap();
+    flash.page_program(0x8100, &[0x00]).unwrap();
+    flash.erase().unwrap();
+}
+
+#[test]
+fn test_address_validation() {
+    use mock::MockFlash;
+
+    assert!(Address24Bits::try_from(0xFF_FFFF).is_ok());
+    const ADDRESS: Option<Address24Bits> = Address24Bits::new(0x12_3456);
+    assert_eq!(ADDRESS.map(|a| a.to_be_bytes()), Some([0x12, 0x34, 0x56]));
+    assert_eq!(Address24Bits::new(0x100_0000), None);
+    assert!(matches!(
+        Address24Bits::try_from(0x100_0000),
+        Err(Error::InvalidAddress {
+            address: 0x100_0000
+        })
+    ));
+
+    let mut mock = MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    let mut buf = [0; 1];
+    assert!(matches!(
+        flash.exec_raw(0x03, Some(0x100_0000), 0, Direction::Read, &mut buf, false),
+        Err(Error::InvalidAddress { .. })
+    ));
+
+    // Without a capacity, the last byte of the 3-byte address space is the limit.
+    assert!(flash.check_address_length(0xFF_FFFF, 1).is_ok());
+    assert!(matches!(
+        flash.check_address_length(0xFF_FFFF, 2),
+        Err(Error::InvalidAddress {
+            address: 0x100_0000
+        })
+    ));
+    flash.set_address_bytes(4);
+    flash.set_dies(2);
+    assert!(flash.check_address_length(0xFFFF_FF00, 0x200).is_ok());
+    assert!(matches!(
+        flash.check_address_length(0xFFFF_FF00, usize::MAX),
+        Err(Error::InvalidAddress { address: u32::MAX })
+    ));
+    flash.set_dies(1);
+    flash.set_address_bytes(3);
+
+    flash.set_capacity(64 * 1024);
+    assert!(matches!(
+        flash.page_program(0x1F0, &[0; 32]),
+        Err(Error::Misaligned {
+            address: 0x1F0,
+            required: 256
+        })
+    ));
+    flash.set_erase_size(4096);
+    assert!(matches!(
+        flash.erase_range(0x800, 0x1000, false),
+        Err(Error::Misaligned {
+            address: 0x800,
+            required: 4096
+        })
+    ));
+    assert!(matches!(
+        flash.erase_range(0x1000, 0x800, false),
+        Err(Error::Misaligned {
+            address: 0x1800,
+            required: 4096
+        })
+    ));
+    assert!(matches!(
+        flash.program_data(0xFFF0, &[0; 32]),
+        Err(Error::InvalidAddress { address: 0x1000F })
+    ));
+    assert!(flash.read(0x10000, 0).unwrap().is_empty());
+    assert!(flash.read(0x10001, 0).is_err());
+    assert!(flash.read(0xFFFF, 2).is_err());
+}
+
+#[test]
+fn test_timings() {
+    use mock::MockFlash;
+
+    let mut mock = MockFlash::new(64 * 1024).with_busy_polls(1000);
+    let mut flash = mock::configured_flash(&mut mock);
+    assert_eq!(flash.timings(), None);
+
+    let timings = Timings {
+        page_program_typ: Duration::from_micros(16),
+        page_program_max: Duration::from_micros(40),
+        sector_erase_typ: Duration::from_micros(80),
+        sector_erase_max: Duration::from_micros(200),
+        chip_erase_typ: Duration::from_millis(1),
+        chip_erase_max: Duration::from_millis(2),
+    };
+    flash.set_timings(Some(timings));
+    assert_eq!(flash.timings(), Some(timings));
+    assert_eq!(
+        timings.program_estimate(600, 256),
+        Duration::from_micros(48)
+    );
+    assert_eq!(timings.erase_estimate(3), Duration::from_micros(240));
+
+    // Without timeouts, operations are waited for however long they take.
+    flash.erase_sector(0).unwrap();
+
+    // With timeouts, the device stays busy for longer than the maximum erase time.
+    flash.set_timeouts(true);
+    assert!(matches!(flash.erase_sector(0x1000), Err(Error::Timeout)));
+    flash.wait_while_busy().unwrap();
+    assert!(matches!(
+        flash.page_program(0x1000, &[0; 16]),
+        Err(Error::Timeout)
+    ));
+    flash.wait_while_busy().unwrap();
+    drop(flash);
+    assert_eq!(mock.busy_violations(), 0);
+}
+
+#[test]
+fn test_flash_geometry() {
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    assert_eq!(flash.geometry(), None);
+    flash.set_capacity(64 * 1024);
+    flash.set_erase_size(4096);
+    let geometry = flash.geometry().unwrap();
+    assert_eq!(
+        (geometry.page_size, geometry.block_size, geometry.sectors()),
+        (256, 64 * 1024, 16)
+    );
+}
+
+#[test]
+fn test_batch_operations() {
+    let mut mock = mock::MockFlash::new(64 * 1024).with_busy_polls(2);
+    let mut flash = mock::configured_flash(&mut mock);
+    let page: Vec<u8> = (0..=255).collect();
+    let pages = [
+        (0x1000, &page[..]),
+        (0x2100, &page[..16]),
+        (0x3080, &page[..0x80]),
+    ];
+    flash.program_pages(pages, true).unwrap();
+    assert!(!flash.is_busy_pending());
+    assert_eq!(flash.read(0x1000, 256).unwrap(), page);
+    assert_eq!(flash.read(0x30FF, 2).unwrap(), [0x7F, 0xFF]);
+
+    // Nothing is programmed if any page is invalid.
+    let invalid = [(0x4000, &page[..4]), (0x40F0, &page[..32])];
+    assert!(flash.program_pages(invalid, false).is_err());
+    assert!(flash.is_erased(0x4000, 4).unwrap());
+
+    assert!(flash.erase_sectors([0x1000, 0x10_0000], false).is_err());
+    assert!(!flash.is_erased(0x1000, 256).unwrap());
+    flash.erase_sectors([0x1000, 0x2100, 0x3FFF], true).unwrap();
+    assert!(flash.is_erased(0x1000, 0x3000).unwrap());
+
+    // Interleaved verification stops at the first sector which failed to erase.
+    flash
+        .program_pages([(0x1000, &page[..4]), (0x2000, &page[..4])], false)
+        .unwrap();
+    drop(flash);
+    mock.inject_protected_range(0x1000..0x2000);
+    let mut flash = mock::configured_flash(&mut mock);
+    assert!(matches!(
+        flash.erase_sectors([0x1000, 0x2000], true),
+        Err(Error::ReadbackError {
+            address: 0x1000,
+            ..
+        })
+    ));
+    assert!(!flash.is_erased(0x2000, 4).unwrap());
+}
+
+#[test]
+fn test_execute_command_spec() {
+    /// A vendor erase instruction, which the mock treats as a sector erase.
+    struct VendorErase(u32);
+    impl CommandSpec for VendorErase {
+        fn opcode(&self) -> u8 {
+            0x20
+        }
+        fn address(&self) -> Option<Either<Address24Bits, Address32Bits>> {
+            Some(Either::Left(Address24Bits(self.0)))
+        }
+        fn needs_write_enable(&self) -> bool {
+            true
+        }
+    }
+
+    let mut mock = mock::MockFlash::new(64 * 1024).with_busy_polls(2);
+    let mut flash = Flash::new(&mut mock);
+    flash.program_data(0x2000, &[0x55; 16]).unwrap();
+    flash.execute(&VendorErase(0x2000), &mut []).unwrap();
+    assert!(!flash.is_busy_pending());
+    assert_eq!(flash.read(0x2000, 4).unwrap(), [0xFF; 4]);
+
+    let command = Command::FastRead(Address24Bits(0x1000));
+    assert_eq!(
+        (command.dummy_cycles(), command.direction()),
+        (8, Direction::Read)
+    );
+    assert!(Command::SectorErase(Address24Bits(0)).needs_write_enable());
+    flash.program_data(0x1001, &[1, 2]).unwrap();
+    let mut data = [0; 3];
+    flash.execute(&command, &mut data).unwrap();
+    assert_eq!(data, [0xFF, 1, 2]);
+}
+
+#[test]
+fn test_control_pins() {
+    use alloc::{boxed::Box, rc::Rc};
+    use core::cell::RefCell;
+
+    struct Pin(&'static str, Rc<RefCell<Vec<(&'static str, bool)>>>);
+    impl ControlPin for Pin {
+        fn set_high(&mut self) -> Result<()> {
+            self.1.borrow_mut().push((self.0, true));
+            Ok(())
+        }
+        fn set_low(&mut self) -> Result<()> {
+            self.1.borrow_mut().push((self.0, false));
+            Ok(())
+        }
+    }
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut mock = mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    flash
+        .set_wp_pin(Some(Box::new(Pin("wp", log.clone()))))
+        .unwrap();
+    flash
+        .set_reset_pin(Some(Box::new(Pin("reset", log.clone()))))
+        .unwrap();
+    flash.read_status1().unwrap();
+    assert_eq!(*log.borrow(), [("wp", false), ("reset", true)]);
+    log.borrow_mut().clear();
+
+    flash
+        .write_status12(
+            StatusRegister1(0x80),
+            StatusRegister2(0),
+            StatusWriteMode::NonVolatile,
+        )
+        .unwrap();
+    assert_eq!(*log.borrow(), [("wp", true), ("wp", false)]);
+    assert!(flash.read_status1().unwrap().get_srp());
+    log.borrow_mut().clear();
+
+    flash.reset().unwrap();
+    assert_eq!(*log.borrow(), [("reset", false), ("reset", true)]);
+}
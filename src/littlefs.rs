@@ -0,0 +1,120 @@
+//! littlefs storage backend.
+//!
+//! `LittlefsStorage` implements `littlefs2::driver::Storage` for any `Storage`, such as a
+//! `Flash` or a `Partition` of one, so a littlefs filesystem can be mounted on it directly.
+//! littlefs takes its geometry as compile-time constants, so the block size and count are
+//! const generic parameters, which are checked against the erase size and capacity of the
+//! storage when the adapter is created. Reads and programs are byte-granular since the
+//! driver splits programs into pages itself.
+//!
+//! A `Flash` with 4kB sectors and 4MB capacity would be mounted with:
+//!
+//! ```text
+//! let mut storage = LittlefsStorage::<_, 4096, 1024>::new(&mut flash)?;
+//! let mut alloc = littlefs2::fs::Filesystem::allocate();
+//! let fs = littlefs2::fs::Filesystem::mount(&mut alloc, &mut storage)?;
+//! ```
+
+use littlefs2::{consts, io};
+
+use crate::{Error, Result, Storage};
+
+/// Adapter implementing `littlefs2::driver::Storage` for `BLOCK_COUNT` blocks
+/// of `BLOCK_SIZE` bytes at the start of `storage`.
+pub struct LittlefsStorage<'a, F: Storage, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize> {
+    storage: &'a mut F,
+}
+
+impl<'a, F: Storage, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize>
+    LittlefsStorage<'a, F, BLOCK_SIZE, BLOCK_COUNT>
+{
+    /// Size of littlefs's read, program, and per-file caches.
+    const CACHE_SIZE: usize = 256;
+
+    /// Create an adapter for `storage`.
+    ///
+    /// Returns `Error::InvalidConfiguration` if `BLOCK_SIZE` is not a multiple of both the
+    /// erase size and the 256-byte cache size, or if the blocks exceed the capacity.
+    pub fn new(storage: &'a mut F) -> Result<Self> {
+        let Some(erase_size) = storage.erase_size() else {
+            log::error!("littlefs requires storage with a known erase size");
+            return Err(Error::InvalidConfiguration);
+        };
+        if !BLOCK_SIZE.is_multiple_of(erase_size) || !BLOCK_SIZE.is_multiple_of(Self::CACHE_SIZE) {
+            log::error!(
+                "littlefs block size {} is not a multiple of erase size {} and cache size {}",
+                BLOCK_SIZE,
+                erase_size,
+                Self::CACHE_SIZE
+            );
+            return Err(Error::InvalidConfiguration);
+        }
+        if let Some(capacity) = storage.capacity() {
+            if BLOCK_SIZE * BLOCK_COUNT > capacity {
+                log::error!(
+                    "littlefs blocks of {} bytes exceed capacity of {} bytes",
+                    BLOCK_SIZE * BLOCK_COUNT,
+                    capacity
+                );
+                return Err(Error::InvalidConfiguration);
+            }
+        }
+        Ok(LittlefsStorage { storage })
+    }
+
+    /// Release the underlying storage.
+    pub fn release(self) -> &'a mut F {
+        self.storage
+    }
+}
+
+/// Log `err` and convert it to littlefs's I/O error.
+fn io_error(err: Error) -> io::Error {
+    log::error!("littlefs storage error: {:?}", err);
+    io::Error::IO
+}
+
+impl<'a, F: Storage, const BLOCK_SIZE: usize, const BLOCK_COUNT: usize> littlefs2::driver::Storage
+    for LittlefsStorage<'a, F, BLOCK_SIZE, BLOCK_COUNT>
+{
+    const READ_SIZE: usize = 1;
+    const WRITE_SIZE: usize = 1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const BLOCK_COUNT: usize = BLOCK_COUNT;
+
+    type CACHE_SIZE = consts::U256;
+    type LOOKAHEAD_SIZE = consts::U16;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.storage.read(off as u32, buf.len()).map_err(io_error)?;
+        buf.copy_from_slice(&data);
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> io::Result<usize> {
+        self.storage
+            .program_data(off as u32, data)
+            .map_err(io_error)?;
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> io::Result<usize> {
+        self.storage.erase_data(off as u32, len).map_err(io_error)?;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_littlefs_storage() {
+    use littlefs2::driver::Storage as _;
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(LittlefsStorage::<_, 4096, 32>::new(&mut flash).is_err());
+    assert!(LittlefsStorage::<_, 2048, 16>::new(&mut flash).is_err());
+    let mut storage = LittlefsStorage::<_, 4096, 16>::new(&mut flash).unwrap();
+    assert_eq!(storage.erase(0x1000, 4096), Ok(4096));
+    assert_eq!(storage.write(0x1FFE, &[1, 2, 3, 4]), Ok(4));
+    let mut buf = [0; 6];
+    assert_eq!(storage.read(0x1FFD, &mut buf), Ok(6));
+    assert_eq!(buf, [0xFF, 1, 2, 3, 4, 0xFF]);
+}
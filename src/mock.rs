@@ -0,0 +1,1569 @@
+//! Simulated flash device for testing.
+//!
+//! `MockFlash` implements `FlashAccess` by emulating a simple NOR flash memory,
+//! so that code using `Flash` can be tested without hardware. It supports the core
+//! read, program, and erase commands with 3- and 4-byte addresses, the status registers, RDAR/WRAR register
+//! access, individual block lock bits, and entering octal mode, and may be configured with a device's
+//! quirks to emulate its behaviour.
+//!
+//! Like a real device, memory is erased to 0xFF and programming only clears bits, page
+//! programs wrap around within the page, and program, erase, and status register writes
+//! are ignored unless the write enable latch is set. Operations complete immediately by
+//! default, but `with_busy_polls()` keeps the device busy for a number of status register
+//! polls after each one, and instructions received while busy are ignored and counted by
+//! `busy_violations()`, to check that code waits for operations to complete. Program
+//! Suspend (0x75) pauses the busy period of the operation in progress until Program
+//! Resume (0x7A), reporting it as suspended in the flag status register.
+//!
+//! `configured_flash()` creates a `Flash` using a `MockFlash` with its capacity and erase
+//! size already set. `MockFlash` also implements `Storage` directly, so application logic
+//! built on the storage traits can be tested without a `Flash`.
+//!
+//! Faults may be injected to deterministically stress-test code layered on this crate:
+//! power loss after a number of bytes are programmed, flipped bits in stored data, a BUSY
+//! flag which never clears, and protected regions where programs and erases are ignored.
+//!
+//! Devices without NOR flash semantics have their own models: `MockSerialMemory` for
+//! serial EEPROM, FRAM, and MRAM, `MockNand` for SPI NAND, and `MockDataFlash` for
+//! DataFlash, to test `SerialEeprom`, `FramFlash`, `NandFlash`, and `DataFlash`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use either::Either;
+
+use crate::{dataflash, nand, serial_eeprom};
+use crate::{
+    Address24Bits, Address32Bits, Command, DataFlashGeometry, DataFlashOpCode, Error, Flash,
+    FlashAccess, NandGeometry, NandOpCode, OctalOpCode, Protocol, Quirks, ReadStorage, Register,
+    RegisterFile, Result, SerialMemoryOpCode, Storage,
+};
+
+/// Status register 1 write enable latch bit.
+const SR1_WEL: u8 = 0b0000_0010;
+/// Status register 2 LB1-LB3 bits, locking the security registers, which are one-time
+/// programmable.
+const SR2_LB: u8 = 0b0011_1000;
+/// Sector size used by the `Storage` implementation.
+const SECTOR_SIZE: usize = 4096;
+
+/// Simulated flash device.
+#[derive(Clone, Debug)]
+pub struct MockFlash {
+    memory: Vec<u8>,
+    page_size: usize,
+    jedec_id: [u8; 3],
+    sfdp: Vec<u8>,
+    quirks: Quirks,
+    register_file: Option<RegisterFile>,
+    /// Register values, indexed by `Register`.
+    registers: [u8; 7],
+    /// Addresses locked using Individual Block Lock (0x36).
+    block_locks: Vec<u32>,
+    /// Sector addresses with their DYB set to protect the sector.
+    dybs: Vec<u32>,
+    /// Sector addresses with their PPB programmed.
+    ppbs: Vec<u32>,
+    /// Set when the PPB lock bit has been cleared, freezing the PPBs until reset.
+    ppb_locked: bool,
+    /// Advanced Sector Protection register.
+    asp: u16,
+    /// ASP password.
+    password: [u8; 8],
+    /// Set when Password Unlock was given the wrong password, leaving the device
+    /// busy with P_ERR set until reset.
+    password_error: bool,
+    /// Number of status polls for which each operation keeps the device busy.
+    busy_polls: u32,
+    /// Number of status polls before the current operation completes.
+    busy_remaining: u32,
+    /// Number of instructions received and ignored while busy.
+    busy_violations: usize,
+    /// Number of page programs performed.
+    page_programs: usize,
+    /// Number of reads of the memory array.
+    memory_reads: usize,
+    /// Set when WEL was last set by Write Enable for Volatile Status Register (0x50).
+    volatile_wel: bool,
+    /// Number of status register writes which consumed non-volatile write endurance.
+    status_write_cycles: usize,
+    /// Number of bytes which may be programmed before power is lost, if limited.
+    power_loss_after: Option<usize>,
+    /// Number of erases which complete before power is lost during the next, if limited.
+    erase_power_loss_after: Option<usize>,
+    /// Cleared when power is lost, after which all instructions fail.
+    powered: bool,
+    /// Addresses and masks of bits flipped when stored data is read.
+    bit_flips: Vec<(u32, u8)>,
+    /// If set, the device reports BUSY indefinitely.
+    stuck_busy: bool,
+    /// Remaining busy period of the operation paused by Program Suspend (0x75), if any.
+    suspended: Option<u32>,
+    /// Regions where programs and erases are ignored.
+    protected: Vec<Range<u32>>,
+    /// Number of programs and erases ignored because they targeted a protected region.
+    protection_violations: usize,
+    /// Number of stacked dies sharing the memory, selected by Die Select (0xC2).
+    dies: usize,
+    /// Currently selected die.
+    active_die: usize,
+    /// Number of upcoming transfers which fail as if glitched on the bus.
+    transient_errors: usize,
+    /// Spansion bank register, whose BA bits extend 3-byte addresses.
+    bank: u8,
+    /// Winbond Extended Address Register, which likewise extends 3-byte addresses.
+    extended_address: u8,
+    /// Error bits of the flag status register, set until cleared with 0x50.
+    flag_errors: u8,
+    /// Security registers 1 to 3, locked by the LB bits of status register 2.
+    security_registers: [[u8; 256]; 3],
+    /// Set when the device is in octal mode, entered by writing its configuration register.
+    octal: bool,
+    /// Protocol the transport has been switched to.
+    protocol: Protocol,
+}
+
+impl MockFlash {
+    /// Create a new erased device of `capacity` bytes, with 256-byte pages.
+    ///
+    /// The device reports JEDEC ID EF 40 18 and has no SFDP data.
+    pub fn new(capacity: usize) -> Self {
+        MockFlash {
+            memory: vec![0xFF; capacity],
+            page_size: 256,
+            jedec_id: [0xEF, 0x40, 0x18],
+            sfdp: Vec::new(),
+            quirks: Quirks::default(),
+            register_file: None,
+            registers: [0; 7],
+            block_locks: Vec::new(),
+            dybs: Vec::new(),
+            ppbs: Vec::new(),
+            ppb_locked: false,
+            asp: 0xFFFF,
+            password: [0xFF; 8],
+            password_error: false,
+            busy_polls: 0,
+            busy_remaining: 0,
+            busy_violations: 0,
+            page_programs: 0,
+            memory_reads: 0,
+            volatile_wel: false,
+            status_write_cycles: 0,
+            power_loss_after: None,
+            erase_power_loss_after: None,
+            powered: true,
+            bit_flips: Vec::new(),
+            stuck_busy: false,
+            suspended: None,
+            protected: Vec::new(),
+            protection_violations: 0,
+            dies: 1,
+            active_die: 0,
+            transient_errors: 0,
+            bank: 0,
+            extended_address: 0,
+            flag_errors: 0,
+            security_registers: [[0xFF; 256]; 3],
+            octal: false,
+            protocol: Protocol::Spi,
+        }
+    }
+
+    /// Set the JEDEC manufacturer and device IDs reported by the device.
+    pub fn with_jedec_id(mut self, manufacturer_id: u8, device_id: u16) -> Self {
+        let [msb, lsb] = device_id.to_be_bytes();
+        self.jedec_id = [manufacturer_id, msb, lsb];
+        self
+    }
+
+    /// Set the contents of the SFDP address space.
+    pub fn with_sfdp(mut self, sfdp: Vec<u8>) -> Self {
+        self.sfdp = sfdp;
+        self
+    }
+
+    /// Set the quirks of the emulated device.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Set the register addresses accessible using RDAR/WRAR.
+    pub fn with_register_file(mut self, register_file: RegisterFile) -> Self {
+        self.register_file = Some(register_file);
+        self
+    }
+
+    /// Split the memory into `dies` stacked dies of equal size, each addressed from 0 and
+    /// selected using Die Select (0xC2), with die 0 selected initially.
+    pub fn with_dies(mut self, dies: usize) -> Self {
+        self.dies = dies;
+        self
+    }
+
+    /// Get the currently selected die.
+    pub fn active_die(&self) -> usize {
+        self.active_die
+    }
+
+    /// Get the bank register value.
+    pub fn bank_register(&self) -> u8 {
+        self.bank
+    }
+
+    /// Get the Extended Address Register value.
+    pub fn extended_address_register(&self) -> u8 {
+        self.extended_address
+    }
+
+    /// Check if the device is in octal mode.
+    pub fn is_octal(&self) -> bool {
+        self.octal
+    }
+
+    /// Keep the device busy for `polls` status register reads after each program, erase,
+    /// or register write.
+    pub fn with_busy_polls(mut self, polls: u32) -> Self {
+        self.busy_polls = polls;
+        self
+    }
+
+    /// Check if an operation is still in progress.
+    pub fn is_busy(&self) -> bool {
+        self.busy_remaining > 0 || self.stuck_busy || self.password_error
+    }
+
+    /// Get the number of instructions which were received and ignored while busy.
+    pub fn busy_violations(&self) -> usize {
+        self.busy_violations
+    }
+
+    /// Get the number of status register writes made after Write Enable (0x06), which
+    /// update the non-volatile bits; writes after Write Enable for Volatile Status
+    /// Register (0x50) are not counted.
+    pub fn status_write_cycles(&self) -> usize {
+        self.status_write_cycles
+    }
+
+    /// Lose power after `bytes` more bytes are programmed, or never if `None`.
+    ///
+    /// A page program which runs out of bytes is left partially programmed, and all
+    /// instructions then fail with an access error until `restore_power()` is called.
+    pub fn set_power_loss_after(&mut self, bytes: Option<usize>) {
+        self.power_loss_after = bytes;
+    }
+
+    /// Lose power during the erase after `erases` more erases complete, or never if `None`.
+    ///
+    /// The interrupted erase leaves every other byte of the sector erased and the rest
+    /// unchanged, as a partially erased sector may read back, and all instructions then
+    /// fail with an access error until `restore_power()` is called.
+    pub fn set_power_loss_during_erase(&mut self, erases: Option<usize>) {
+        self.erase_power_loss_after = erases;
+    }
+
+    /// Get the number of page programs performed, including those interrupted by
+    /// power loss but not those ignored by a protected region.
+    pub fn page_programs(&self) -> usize {
+        self.page_programs
+    }
+
+    /// Get the number of reads of the memory array, by read instructions or through
+    /// `ReadStorage`.
+    pub fn memory_reads(&self) -> usize {
+        self.memory_reads
+    }
+
+    /// Check if power has been lost.
+    pub fn power_lost(&self) -> bool {
+        !self.powered
+    }
+
+    /// Restore power after a power loss, as if the device had been power cycled.
+    ///
+    /// Memory contents are kept, and any further power loss is cancelled.
+    pub fn restore_power(&mut self) {
+        self.powered = true;
+        self.power_loss_after = None;
+        self.erase_power_loss_after = None;
+        self.busy_remaining = 0;
+        self.suspended = None;
+        self.password_error = false;
+        self.clear_wel();
+    }
+
+    /// Flip the bits in `mask` of the byte at `address` whenever it is read,
+    /// as if the stored data had been corrupted.
+    pub fn inject_bit_flip(&mut self, address: u32, mask: u8) {
+        self.bit_flips.push((address, mask));
+    }
+
+    /// Remove all injected bit flips.
+    pub fn clear_bit_flips(&mut self) {
+        self.bit_flips.clear();
+    }
+
+    /// Set whether the device reports BUSY indefinitely, as if an operation never completed.
+    ///
+    /// Note that `Flash::wait_while_busy()` does not time out, so only poll a stuck device
+    /// using `Flash::is_busy()`.
+    pub fn set_stuck_busy(&mut self, stuck: bool) {
+        self.stuck_busy = stuck;
+    }
+
+    /// Make the next `count` transfers fail with a transport error, without reaching
+    /// the device, as transfers corrupted by a noisy bus would.
+    pub fn set_transient_errors(&mut self, count: usize) {
+        self.transient_errors = count;
+    }
+
+    /// Protect `range`, so programs and erases affecting it are ignored.
+    ///
+    /// This models write protection the driver is not aware of, independently of the
+    /// status register protection bits.
+    pub fn inject_protected_range(&mut self, range: Range<u32>) {
+        self.protected.push(range);
+    }
+
+    /// Get the number of programs and erases ignored because they targeted a protected region.
+    pub fn protection_violations(&self) -> usize {
+        self.protection_violations
+    }
+
+    /// Get the JEDEC ID bytes reported by the device.
+    pub fn jedec_id(&self) -> [u8; 3] {
+        self.jedec_id
+    }
+
+    /// Get the memory contents.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Get the memory contents for modification.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Get the current value of `register`.
+    pub fn register(&self, register: Register) -> u8 {
+        self.registers[register as usize]
+    }
+
+    /// Set the current value of `register`.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        self.registers[register as usize] = value;
+    }
+
+    /// Check if Password Protection mode is selected in the ASP register.
+    fn password_mode(&self) -> bool {
+        self.asp & 0b0100 == 0
+    }
+
+    fn write_enabled(&self) -> bool {
+        self.register(Register::Status1) & SR1_WEL != 0
+    }
+
+    fn clear_wel(&mut self) {
+        self.registers[Register::Status1 as usize] &= !SR1_WEL;
+    }
+
+    /// Complete a write-enabled operation, clearing WEL and starting the busy period.
+    fn finish_write(&mut self) {
+        self.clear_wel();
+        self.busy_remaining = self.busy_polls;
+    }
+
+    /// Complete a status register write, counting it if it was non-volatile.
+    fn finish_status_write(&mut self) {
+        if !self.volatile_wel {
+            self.status_write_cycles += 1;
+        }
+        self.finish_write();
+    }
+
+    /// Count down the busy period on a status poll, returning true if still busy.
+    fn poll_busy(&mut self) -> bool {
+        let busy = self.is_busy();
+        self.busy_remaining = self.busy_remaining.saturating_sub(1);
+        busy
+    }
+
+    /// Write a status register, preserving the read-only BUSY and WEL bits of SR1.
+    fn write_status(&mut self, register: Register, value: u8) {
+        if register == Register::Status1 {
+            let old = self.register(Register::Status1);
+            self.set_register(register, (value & 0b1111_1100) | (old & 0b0000_0011));
+        } else if register == Register::Status2 {
+            let old = self.register(Register::Status2);
+            self.set_register(register, value | (old & SR2_LB));
+        } else {
+            self.set_register(register, value);
+        }
+    }
+
+    /// Get the index and byte offset of the security register at `address`, and whether
+    /// it is unlocked.
+    fn security_register(&self, address: u32) -> Option<(usize, usize, bool)> {
+        let index = (address >> 12) as usize;
+        if address & !0x30FF != 0 || !(1..=3).contains(&index) {
+            return None;
+        }
+        let unlocked = self.register(Register::Status2) & (1 << (index + 2)) == 0;
+        Some((index - 1, (address & 0xFF) as usize, unlocked))
+    }
+
+    fn any_register(&self, address: u32) -> Option<Register> {
+        let file = self.register_file.as_ref()?;
+        [
+            Register::Status1,
+            Register::Status2,
+            Register::Status3,
+            Register::Config1,
+            Register::Config2,
+            Register::Config3,
+            Register::Config4,
+        ]
+        .into_iter()
+        .find(|r| file.address(*r) == Some(address))
+    }
+
+    fn read_memory(&self, address: u32, nbytes: usize) -> impl Iterator<Item = u8> + '_ {
+        let len = self.memory.len();
+        (0..nbytes).map(move |i| {
+            let address = (address as usize + i) % len;
+            self.bit_flips
+                .iter()
+                .filter(|(a, _)| *a as usize == address)
+                .fold(self.memory[address], |byte, (_, mask)| byte ^ mask)
+        })
+    }
+
+    /// Check if an operation on `length` bytes from `start` is blocked by a protected region,
+    /// counting the violation if so.
+    fn is_blocked(&mut self, start: usize, length: usize) -> bool {
+        let end = start + length;
+        let blocked = self
+            .protected
+            .iter()
+            .any(|r| (r.start as usize) < end && start < r.end as usize);
+        if blocked {
+            log::warn!("MockFlash ignoring write to protected 0x{:08X}", start);
+            self.protection_violations += 1;
+        }
+        blocked
+    }
+
+    fn program(&mut self, address: u32, data: &[u8]) {
+        let len = self.memory.len();
+        let page = (address as usize % len) - (address as usize % self.page_size);
+        if self.is_blocked(page, self.page_size) {
+            self.flag_errors |= 0b0001_0010;
+            return;
+        }
+        self.page_programs += 1;
+        let mut offset = address as usize % self.page_size;
+        for byte in data {
+            if let Some(remaining) = self.power_loss_after.as_mut() {
+                if *remaining == 0 {
+                    log::warn!("MockFlash losing power during program");
+                    self.powered = false;
+                    return;
+                }
+                *remaining -= 1;
+            }
+            self.memory[page + offset] &= byte;
+            offset = (offset + 1) % self.page_size;
+        }
+    }
+
+    fn erase(&mut self, address: u32, size: usize) {
+        let start = (address as usize % self.memory.len()) & !(size - 1);
+        let end = usize::min(start + size, self.memory.len());
+        if self.is_blocked(start, end - start) {
+            self.flag_errors |= 0b0010_0010;
+        } else {
+            self.erase_range(start..end);
+        }
+    }
+
+    /// Erase `range`, unless power is lost part way through.
+    fn erase_range(&mut self, range: Range<usize>) {
+        match self.erase_power_loss_after.as_mut() {
+            Some(0) => {
+                log::warn!("MockFlash losing power during erase");
+                self.powered = false;
+                self.memory[range]
+                    .iter_mut()
+                    .step_by(2)
+                    .for_each(|b| *b = 0xFF);
+            }
+            Some(remaining) => {
+                *remaining -= 1;
+                self.memory[range].fill(0xFF);
+            }
+            None => self.memory[range].fill(0xFF),
+        }
+    }
+}
+
+impl FlashAccess for MockFlash {
+    type Error = Error;
+
+    fn exchange(
+        &mut self,
+        command: Command,
+        data: &[u8],
+        nbytes: usize,
+    ) -> core::result::Result<Vec<u8>, Error> {
+        let mut rx = vec![0; command.len() + data.len()];
+        // Raw instructions are handled as the command with the same opcode, if any.
+        let command = match command {
+            Command::Raw(opcode, address) => {
+                Command::try_from_byte(opcode, address.map(Either::Left)).unwrap_or(command)
+            }
+            Command::Raw4B(opcode, address) => {
+                Command::try_from_byte(opcode, Some(Either::Right(address))).unwrap_or(command)
+            }
+            command => command,
+        };
+        if !self.powered {
+            return Err(power_lost());
+        }
+        if self.transient_errors > 0 {
+            self.transient_errors -= 1;
+            return Err(transient_error());
+        }
+        // A device only decodes instructions sent using the protocol of its current mode.
+        if matches!(self.protocol, Protocol::Octal(_)) != self.octal {
+            log::warn!(
+                "MockFlash ignoring {:?} sent in {:?}",
+                command,
+                self.protocol
+            );
+            rx.resize(rx.len() + nbytes, 0xFF);
+            return Ok(rx);
+        }
+        let command = self.map_address(command);
+        let mut response: Vec<u8> = Vec::new();
+        let status_poll = matches!(
+            command,
+            Command::ReadStatusRegister1 | Command::ReadFlagStatusRegister
+        );
+        if self.is_busy()
+            && !status_poll
+            && !matches!(
+                command,
+                Command::EnableReset
+                    | Command::Reset
+                    | Command::SoftwareReset
+                    | Command::Raw(0x75 | 0x7A, None)
+            )
+        {
+            log::warn!("MockFlash ignoring {:?} while busy", command);
+            self.busy_violations += 1;
+            rx.resize(rx.len() + nbytes, 0xFF);
+            return Ok(rx);
+        }
+        // Devices implementing only the alternate encoding of an instruction ignore its
+        // usual opcode.
+        let unimplemented = match command {
+            Command::ChipErase => self.quirks.chip_erase_60,
+            Command::EnableReset | Command::Reset => self.quirks.reset_f0,
+            _ => false,
+        };
+        if unimplemented {
+            log::warn!("MockFlash ignoring unimplemented {:?}", command);
+            rx.resize(rx.len() + nbytes, 0xFF);
+            return Ok(rx);
+        }
+        match command {
+            Command::WriteEnable | Command::WriteEnableVolatile => {
+                self.registers[Register::Status1 as usize] |= SR1_WEL;
+                self.volatile_wel = matches!(command, Command::WriteEnableVolatile);
+            }
+            Command::WriteDisable => self.clear_wel(),
+            Command::ReadStatusRegister1 => {
+                let (sr1, sr2) = (
+                    self.register(Register::Status1)
+                        | self.poll_busy() as u8
+                        | (self.password_error as u8) << 6,
+                    self.register(Register::Status2),
+                );
+                let next = if self.quirks.combined_status_read {
+                    sr2
+                } else {
+                    sr1
+                };
+                response.extend([sr1, next].into_iter().cycle().take(nbytes));
+            }
+            Command::ReadStatusRegister2 | Command::ReadConfigRegister1 => {
+                response.resize(nbytes, self.register(Register::Status2))
+            }
+            Command::ReadStatusRegister2Alt => {
+                response.resize(nbytes, self.register(Register::Status2))
+            }
+            Command::WriteStatusRegister2Alt if self.write_enabled() => {
+                if let Some(value) = data.first() {
+                    self.write_status(Register::Status2, *value);
+                }
+                self.finish_status_write();
+            }
+            Command::ReadFlagStatusRegister => {
+                // Bit 7 is set when the device is ready.
+                let ready = (!self.poll_busy() as u8) << 7;
+                let suspended = (self.suspended.is_some() as u8) << 2;
+                response.resize(nbytes, ready | suspended | self.flag_errors)
+            }
+            Command::ClearFlagStatusRegister => self.flag_errors = 0,
+            Command::ReadStatusRegister3 => {
+                response.resize(nbytes, self.register(Register::Status3))
+            }
+            Command::WriteStatusRegister1 if self.write_enabled() => {
+                if let Some(value) = data.first() {
+                    self.write_status(Register::Status1, *value);
+                }
+                match data.get(1) {
+                    Some(value) => self.write_status(Register::Status2, *value),
+                    None if self.quirks.combined_status_write => {
+                        self.write_status(Register::Status2, 0)
+                    }
+                    None => (),
+                }
+                self.finish_status_write();
+            }
+            Command::WriteStatusRegister2
+                if self.write_enabled() && !self.quirks.combined_status_write =>
+            {
+                if let Some(value) = data.first() {
+                    self.write_status(Register::Status2, *value);
+                }
+                self.finish_status_write();
+            }
+            Command::WriteStatusRegister3 if self.write_enabled() => {
+                if let Some(value) = data.first() {
+                    self.write_status(Register::Status3, *value);
+                }
+                self.finish_status_write();
+            }
+            Command::ReadAnyRegister(address) => {
+                let value = self.any_register(address.0).map(|r| self.register(r));
+                response.resize(nbytes, value.unwrap_or(0xFF));
+            }
+            Command::WriteAnyRegister(address) if self.write_enabled() => {
+                if let (Some(register), Some(value)) = (self.any_register(address.0), data.first())
+                {
+                    self.write_status(register, *value);
+                }
+                self.finish_write();
+            }
+            // Block lock instructions take a 4-byte address in 4-byte address mode.
+            Command::IndividualBlockLock(Address24Bits(address))
+            | Command::Raw4B(0x36, Address32Bits(address))
+                if self.write_enabled() =>
+            {
+                if !self.block_locks.contains(&address) {
+                    self.block_locks.push(address);
+                }
+                self.finish_write();
+            }
+            Command::IndividualBlockUnlock(Address24Bits(address))
+            | Command::Raw4B(0x39, Address32Bits(address))
+                if self.write_enabled() =>
+            {
+                self.block_locks.retain(|a| *a != address);
+                self.finish_write();
+            }
+            Command::GlobalBlockUnlock if self.write_enabled() => {
+                // Infineon devices clear their DYBs with the same instruction.
+                self.block_locks.clear();
+                self.dybs.clear();
+                self.finish_write();
+            }
+            Command::ReadSecurityRegister(address) => {
+                if let Some((index, offset, _)) = self.security_register(address.0) {
+                    let register = &self.security_registers[index];
+                    response.extend((0..nbytes).map(|i| register[(offset + i) % 256]));
+                }
+            }
+            Command::ProgramSecurityRegister(address) if self.write_enabled() => {
+                if let Some((index, offset, true)) = self.security_register(address.0) {
+                    let register = &mut self.security_registers[index];
+                    for (i, byte) in data.iter().enumerate() {
+                        register[(offset + i) % 256] &= byte;
+                    }
+                }
+                self.finish_write();
+            }
+            Command::EraseSecurityRegister(address) if self.write_enabled() => {
+                if let Some((index, _, true)) = self.security_register(address.0) {
+                    self.security_registers[index].fill(0xFF);
+                }
+                self.finish_write();
+            }
+            Command::ReadDyb(address) => {
+                response.resize(nbytes, (!self.dybs.contains(&address.0)) as u8 * 0xFF)
+            }
+            Command::WriteDyb(address) if self.write_enabled() => {
+                self.dybs.retain(|a| *a != address.0);
+                if data.first() == Some(&0x00) {
+                    self.dybs.push(address.0);
+                }
+                self.finish_write();
+            }
+            Command::ReadPpb(address) => {
+                response.resize(nbytes, (!self.ppbs.contains(&address.0)) as u8 * 0xFF)
+            }
+            Command::ProgramPpb(address) if self.write_enabled() => {
+                if !self.ppb_locked && !self.ppbs.contains(&address.0) {
+                    self.ppbs.push(address.0);
+                }
+                self.finish_write();
+            }
+            Command::ErasePpb if self.write_enabled() => {
+                if !self.ppb_locked {
+                    self.ppbs.clear();
+                }
+                self.finish_write();
+            }
+            Command::ReadPpbLock => response.resize(nbytes, (!self.ppb_locked) as u8),
+            Command::WritePpbLock if self.write_enabled() => {
+                self.ppb_locked = true;
+                self.finish_write();
+            }
+            Command::ReadAsp => response.extend(self.asp.to_le_bytes().into_iter().take(nbytes)),
+            Command::ProgramAsp if self.write_enabled() => {
+                if let [lo, hi, ..] = *data {
+                    // ASP bits are one-time programmable, so can only be cleared.
+                    self.asp &= u16::from_le_bytes([lo, hi]);
+                }
+                self.finish_write();
+            }
+            Command::ReadPassword => {
+                // The password reads as all ones once Password Protection mode is selected.
+                let password = if self.password_mode() {
+                    [0xFF; 8]
+                } else {
+                    self.password
+                };
+                response.extend(password.into_iter().take(nbytes))
+            }
+            Command::ProgramPassword if self.write_enabled() => {
+                if !self.password_mode() {
+                    for (byte, new) in self.password.iter_mut().zip(data) {
+                        *byte &= new;
+                    }
+                }
+                self.finish_write();
+            }
+            Command::UnlockPassword => {
+                if self.password_mode() && data == self.password {
+                    self.ppb_locked = false;
+                } else {
+                    self.password_error = true;
+                }
+            }
+            Command::ReadBlockLock(Address24Bits(address))
+            | Command::Raw4B(0x3D, Address32Bits(address)) => {
+                response.resize(nbytes, self.block_locks.contains(&address) as u8)
+            }
+            Command::ReadJEDECID => {
+                response.extend(
+                    self.jedec_id
+                        .iter()
+                        .copied()
+                        .chain(core::iter::repeat(0))
+                        .take(nbytes),
+                );
+            }
+            Command::ReadDeviceID | Command::ReadDeviceIDDual | Command::ReadDeviceIDQuad => {
+                // The short device ID is one less than the low byte of the JEDEC device ID,
+                // as on Winbond devices, and the pair repeats for as long as it is read.
+                let id = [self.jedec_id[0], self.jedec_id[2].wrapping_sub(1)];
+                response.extend(id.iter().copied().cycle().take(nbytes));
+            }
+            Command::ReadSFDPRegister(address) => {
+                // The first byte clocked out is the dummy byte.
+                let start = address.0 as usize;
+                response.push(0);
+                response.extend(
+                    (start..start + nbytes.saturating_sub(1))
+                        .map(|i| *self.sfdp.get(i).unwrap_or(&0xFF)),
+                );
+            }
+            Command::ReadData(address) | Command::FastRead(address) => {
+                self.memory_reads += 1;
+                response.extend(self.read_memory(address.0, nbytes));
+            }
+            Command::ReadData4B(address) | Command::FastRead4B(address) => {
+                self.memory_reads += 1;
+                response.extend(self.read_memory(address.0, nbytes));
+            }
+            Command::PageProgram(address) if self.write_enabled() => {
+                self.program(address.0, data);
+                self.finish_write();
+            }
+            Command::PageProgram4B(address) if self.write_enabled() => {
+                self.program(address.0, data);
+                self.finish_write();
+            }
+            Command::SectorErase(address) if self.write_enabled() => {
+                self.erase(address.0, 4 * 1024);
+                self.finish_write();
+            }
+            Command::SectorErase4B(address) if self.write_enabled() => {
+                self.erase(address.0, 4 * 1024);
+                self.finish_write();
+            }
+            Command::BlockErase1(address) if self.write_enabled() => {
+                self.erase(address.0, 32 * 1024);
+                self.finish_write();
+            }
+            Command::BlockErase32K4B(address) if self.write_enabled() => {
+                self.erase(address.0, 32 * 1024);
+                self.finish_write();
+            }
+            Command::BlockErase2(address) if self.write_enabled() => {
+                self.erase(address.0, 64 * 1024);
+                self.finish_write();
+            }
+            Command::BlockErase64K4B(address) if self.write_enabled() => {
+                self.erase(address.0, 64 * 1024);
+                self.finish_write();
+            }
+            Command::ChipErase | Command::ChipEraseAlt if self.write_enabled() => {
+                let die_size = self.memory.len() / self.dies;
+                let die = self.active_die * die_size..(self.active_die + 1) * die_size;
+                // Chip Erase is ignored if any memory is protected by the BP bits or
+                // their complement selected by CMP, or by an individual block lock.
+                let bp = self.register(Register::Status1) & 0b0001_1100 != 0;
+                let cmp = self.register(Register::Status2) & 0b0100_0000 != 0;
+                if bp || cmp || !self.block_locks.is_empty() {
+                    log::warn!("MockFlash ignoring chip erase with memory protected");
+                    self.protection_violations += 1;
+                } else if !self.is_blocked(die.start, die_size) {
+                    self.memory[die].fill(0xFF);
+                }
+                self.finish_write();
+            }
+            Command::DieSelect => {
+                if let Some(die) = data.first().filter(|die| (**die as usize) < self.dies) {
+                    self.active_die = *die as usize;
+                }
+            }
+            Command::ReadBankRegister => response.resize(nbytes, self.bank),
+            Command::WriteBankRegister => {
+                if let Some(bank) = data.first() {
+                    self.bank = *bank;
+                }
+            }
+            Command::ReadExtendedAddressRegister => response.resize(nbytes, self.extended_address),
+            Command::WriteExtendedAddressRegister if self.write_enabled() => {
+                if let Some(value) = data.first() {
+                    self.extended_address = *value;
+                }
+                self.clear_wel();
+            }
+            // Setting SOPI or DOPI in Macronix configuration register 2 enters octal mode.
+            Command::Octal(OctalOpCode::WriteConfigRegister2) if self.write_enabled() => {
+                self.octal |= data.get(4).is_some_and(|value| value & 0b11 != 0);
+                self.clear_wel();
+            }
+            // Any mode other than extended SPI (0xFF) in Micron volatile configuration
+            // register 0 enters octal mode.
+            Command::Octal(OctalOpCode::WriteVolatileConfig) if self.write_enabled() => {
+                self.octal |= data.get(3).is_some_and(|value| *value != 0xFF);
+                self.clear_wel();
+            }
+            Command::Reset | Command::SoftwareReset => {
+                self.octal = false;
+                // Password Protection mode freezes the PPBs at reset until unlocked.
+                self.ppb_locked = self.password_mode();
+                self.password_error = false;
+                self.bank = 0;
+                self.extended_address = 0;
+                self.clear_wel();
+                self.busy_remaining = 0;
+                self.suspended = None;
+            }
+            Command::Raw(0x75, None) if self.busy_remaining > 0 && !self.stuck_busy => {
+                self.suspended = Some(self.busy_remaining);
+                self.busy_remaining = 0;
+            }
+            Command::Raw(0x7A, None) => {
+                if let Some(remaining) = self.suspended.take() {
+                    self.busy_remaining = remaining;
+                }
+            }
+            _ => log::trace!("MockFlash ignoring {:?}", command),
+        }
+        response.resize(nbytes, 0);
+        rx.extend(response);
+        Ok(rx)
+    }
+
+    fn supports_protocol(&self, protocol: Protocol) -> bool {
+        matches!(protocol, Protocol::Spi | Protocol::Octal(_))
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+}
+
+impl MockFlash {
+    /// Translate the address of a memory access to an address in `memory`,
+    /// within the selected die, with 3-byte addresses extended by the bank register or
+    /// Extended Address Register.
+    fn map_address(&self, command: Command) -> Command {
+        let die_size = (self.memory.len() / self.dies) as u32;
+        let base = self.active_die as u32 * die_size;
+        let map = |address: u32| base + address % die_size;
+        let upper = (self.bank & 0x7F | self.extended_address) as u32;
+        let extend = |address: u32| upper << 24 | address & 0xFF_FFFF;
+        let address = match command {
+            Command::ReadData(a)
+            | Command::FastRead(a)
+            | Command::PageProgram(a)
+            | Command::SectorErase(a)
+            | Command::BlockErase1(a)
+            | Command::BlockErase2(a) => Either::Left(Address24Bits(map(extend(a.0)))),
+            Command::ReadData4B(a)
+            | Command::FastRead4B(a)
+            | Command::PageProgram4B(a)
+            | Command::SectorErase4B(a)
+            | Command::BlockErase32K4B(a)
+            | Command::BlockErase64K4B(a) => Either::Right(Address32Bits(map(a.0))),
+            command => return command,
+        };
+        Command::try_from_byte(command.opcode(), Some(address)).unwrap()
+    }
+
+    /// Check `length` bytes from `address` lie within the memory.
+    fn check_range(&self, address: u32, length: usize) -> Result<core::ops::Range<usize>> {
+        let range = address as usize..address as usize + length;
+        if range.end > self.memory.len() {
+            return Err(Error::InvalidAddress {
+                address: range.end as u32,
+            });
+        }
+        Ok(range)
+    }
+}
+
+impl ReadStorage for MockFlash {
+    fn capacity(&self) -> Option<usize> {
+        Some(self.memory.len())
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.check_range(address, length)?;
+        if !self.powered {
+            return Err(power_lost());
+        }
+        self.memory_reads += 1;
+        Ok(self.read_memory(address, length).collect())
+    }
+}
+
+impl Storage for MockFlash {
+    fn erase_size(&self) -> Option<usize> {
+        Some(SECTOR_SIZE)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len())?;
+        if !self.powered {
+            return Err(power_lost());
+        }
+        let mut offset = 0;
+        while offset < data.len() {
+            let page_address = address as usize + offset;
+            let chunk = usize::min(
+                self.page_size - page_address % self.page_size,
+                data.len() - offset,
+            );
+            self.program(page_address as u32, &data[offset..offset + chunk]);
+            if !self.powered {
+                return Err(power_lost());
+            }
+            offset += chunk;
+        }
+        Ok(())
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        let range = self.check_range(address, length)?;
+        for boundary in [range.start, range.end] {
+            if !boundary.is_multiple_of(SECTOR_SIZE) {
+                return Err(Error::InvalidAddress {
+                    address: boundary as u32,
+                });
+            }
+        }
+        if !self.powered {
+            return Err(power_lost());
+        }
+        if !self.is_blocked(range.start, range.len()) {
+            self.erase_range(range);
+            if !self.powered {
+                return Err(power_lost());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create a `Flash` using `mock`, with its capacity and page size set to those of `mock`
+/// and its erase size set to 4kB, as most tests need.
+pub fn configured_flash(mock: &mut MockFlash) -> Flash<'_, MockFlash> {
+    let (capacity, page_size) = (mock.memory.len(), mock.page_size);
+    let mut flash = Flash::new(mock);
+    flash.set_capacity(capacity);
+    flash.set_page_size(page_size);
+    flash.set_erase_size(SECTOR_SIZE);
+    flash
+}
+
+/// Simulated 25xx-style serial EEPROM, FRAM, or MRAM.
+///
+/// Memory is not erased and writes replace bytes, as with the devices `SerialEeprom`
+/// and `FramFlash` drive. Writes are ignored unless the write enable latch is set,
+/// which each write clears. By default a write may cover the whole memory and completes
+/// immediately, as on FRAM and MRAM; `with_page_size()` makes writes wrap around within
+/// a page, and `with_busy_polls()` keeps the device busy for a number of status register
+/// polls after each write, as on an EEPROM.
+#[derive(Clone, Debug)]
+pub struct MockSerialMemory {
+    memory: Vec<u8>,
+    address_bytes: usize,
+    page_size: usize,
+    wel: bool,
+    /// Number of status polls for which each write keeps the device busy.
+    busy_polls: u32,
+    /// Number of status polls before the current write completes.
+    busy_remaining: u32,
+    /// Number of instructions received and ignored while busy.
+    busy_violations: usize,
+}
+
+impl MockSerialMemory {
+    /// Create a new device of `capacity` bytes, all zero, addressed with `address_bytes`
+    /// bytes.
+    ///
+    /// Devices with one address byte and more than 256 bytes take the ninth address bit
+    /// from the instruction, as the 25xx040 does.
+    pub fn new(capacity: usize, address_bytes: usize) -> Self {
+        MockSerialMemory {
+            memory: vec![0; capacity],
+            address_bytes,
+            page_size: capacity,
+            wel: false,
+            busy_polls: 0,
+            busy_remaining: 0,
+            busy_violations: 0,
+        }
+    }
+
+    /// Make writes wrap around within pages of `page_size` bytes.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Keep the device busy for `polls` status register polls after each write.
+    pub fn with_busy_polls(mut self, polls: u32) -> Self {
+        self.busy_polls = polls;
+        self
+    }
+
+    /// Get the number of instructions ignored because they were sent while busy.
+    pub fn busy_violations(&self) -> usize {
+        self.busy_violations
+    }
+
+    /// Get the memory contents.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Get the memory contents for modification.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+}
+
+impl FlashAccess for MockSerialMemory {
+    type Error = Error;
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let Command::SerialMemory(opcode) = command else {
+            log::warn!("MockSerialMemory ignoring {:?}", command);
+            return Ok(vec![0xFF; command.len() + data.len() + nbytes]);
+        };
+        let mut rx = vec![0; 1 + data.len()];
+        if opcode == SerialMemoryOpCode::ReadStatusRegister {
+            let busy = self.busy_remaining > 0;
+            rx.push(
+                if busy { serial_eeprom::STATUS_WIP } else { 0 }
+                    | if self.wel { SR1_WEL } else { 0 },
+            );
+            self.busy_remaining = self.busy_remaining.saturating_sub(1);
+            return Ok(rx);
+        }
+        if self.busy_remaining > 0 {
+            log::warn!("MockSerialMemory ignoring {:?} while busy", opcode);
+            self.busy_violations += 1;
+            rx.resize(1 + data.len() + nbytes, 0xFF);
+            return Ok(rx);
+        }
+        let high = matches!(
+            opcode,
+            SerialMemoryOpCode::ReadUpper | SerialMemoryOpCode::WriteUpper
+        );
+        let address = data
+            .iter()
+            .take(self.address_bytes)
+            .fold(high as usize, |a, b| (a << 8) | *b as usize);
+        match opcode {
+            SerialMemoryOpCode::WriteEnable => self.wel = true,
+            SerialMemoryOpCode::WriteDisable => self.wel = false,
+            SerialMemoryOpCode::Read | SerialMemoryOpCode::ReadUpper => {
+                let len = self.memory.len();
+                rx.extend((0..nbytes).map(|i| self.memory[(address + i) % len]));
+            }
+            SerialMemoryOpCode::Write | SerialMemoryOpCode::WriteUpper => {
+                if !core::mem::take(&mut self.wel) {
+                    log::warn!("MockSerialMemory ignoring write without WEL");
+                } else {
+                    let address = address % self.memory.len();
+                    let page = address - address % self.page_size;
+                    for (i, byte) in data[self.address_bytes..].iter().enumerate() {
+                        let offset = (address + i - page) % self.page_size;
+                        self.memory[page + offset] = *byte;
+                    }
+                    self.busy_remaining = self.busy_polls;
+                }
+            }
+            _ => (),
+        }
+        rx.resize(1 + data.len() + nbytes, 0);
+        Ok(rx)
+    }
+}
+
+/// Simulated SPI NAND device, reporting JEDEC ID EF AA 21.
+///
+/// Pages are read into and programmed from a cache, as on a real device, and
+/// programming only clears bits. All blocks start protected, and programs and erases
+/// fail with the status register's fail bits set while protected, without WEL, or for
+/// blocks configured with `with_failing_block()`.
+#[derive(Clone, Debug)]
+pub struct MockNand {
+    geometry: NandGeometry,
+    /// Data and spare area of each page.
+    pages: Vec<Vec<u8>>,
+    cache: Vec<u8>,
+    status: u8,
+    /// Block protection register.
+    protection: u8,
+    /// Blocks whose program and erase operations fail.
+    failing: Vec<u32>,
+    /// Status register ECC bits reported by reads of each page.
+    ecc: Vec<(u32, u8)>,
+    /// If set, the device reports BUSY indefinitely.
+    stuck_busy: bool,
+}
+
+impl MockNand {
+    /// Create a new erased device with `geometry`.
+    pub fn new(geometry: NandGeometry) -> Self {
+        let page_len = geometry.page_size + geometry.spare_size;
+        MockNand {
+            geometry,
+            pages: vec![vec![0xFF; page_len]; geometry.pages() as usize],
+            cache: vec![0xFF; page_len],
+            status: 0,
+            protection: 0x38,
+            failing: Vec::new(),
+            ecc: Vec::new(),
+            stuck_busy: false,
+        }
+    }
+
+    /// Make programs and erases of `block` fail.
+    pub fn with_failing_block(mut self, block: u32) -> Self {
+        self.failing.push(block);
+        self
+    }
+
+    /// Report the status register ECC bits `bits` after each read of `page`.
+    pub fn set_ecc_bits(&mut self, page: u32, bits: u8) {
+        self.ecc.retain(|(p, _)| *p != page);
+        self.ecc.push((page, bits));
+    }
+
+    /// Get the data and spare area of `page` for modification.
+    pub fn page_mut(&mut self, page: u32) -> &mut [u8] {
+        &mut self.pages[page as usize]
+    }
+
+    /// Make the device report BUSY indefinitely, or clear this.
+    pub fn set_stuck_busy(&mut self, stuck: bool) {
+        self.stuck_busy = stuck;
+    }
+
+    fn row(data: &[u8]) -> usize {
+        u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize
+    }
+
+    fn writable(&mut self, row: usize, fail: u8) -> bool {
+        let block = row as u32 / self.geometry.pages_per_block;
+        let ok = self.status & nand::STATUS_WEL != 0
+            && self.protection == 0
+            && !self.failing.contains(&block);
+        self.status = if ok { 0 } else { fail };
+        ok
+    }
+}
+
+impl FlashAccess for MockNand {
+    type Error = Error;
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let Command::Nand(opcode) = command else {
+            log::warn!("MockNand ignoring {:?}", command);
+            return Ok(vec![0xFF; command.len() + data.len() + nbytes]);
+        };
+        let mut rx = vec![0; 1 + data.len()];
+        match opcode {
+            NandOpCode::ReadJEDECID => rx.extend([0xEF, 0xAA, 0x21]),
+            NandOpCode::GetFeature => rx.push(match data[0] {
+                0xA0 => self.protection,
+                0xC0 if self.stuck_busy => self.status | nand::STATUS_BUSY,
+                0xC0 => self.status,
+                _ => 0,
+            }),
+            NandOpCode::SetFeature if data[0] == 0xA0 => self.protection = data[1],
+            NandOpCode::WriteEnable => self.status |= nand::STATUS_WEL,
+            NandOpCode::PageRead => {
+                let row = Self::row(data);
+                self.cache = self.pages[row].clone();
+                let ecc = self.ecc.iter().find(|(page, _)| *page as usize == row);
+                self.status = ecc.map_or(0, |(_, bits)| bits << 4);
+            }
+            NandOpCode::ReadFromCache => {
+                let column = u16::from_be_bytes([data[0], data[1]]) as usize;
+                rx.extend(&self.cache[column..column + nbytes]);
+            }
+            NandOpCode::ProgramLoad => {
+                let column = u16::from_be_bytes([data[0], data[1]]) as usize;
+                self.cache.fill(0xFF);
+                self.cache[column..column + data.len() - 2].copy_from_slice(&data[2..]);
+            }
+            NandOpCode::ProgramExecute => {
+                let row = Self::row(data);
+                if self.writable(row, nand::STATUS_PROGRAM_FAIL) {
+                    for (byte, new) in self.pages[row].iter_mut().zip(&self.cache) {
+                        *byte &= new;
+                    }
+                }
+            }
+            NandOpCode::BlockErase => {
+                let per_block = self.geometry.pages_per_block as usize;
+                let first = Self::row(data) / per_block * per_block;
+                if self.writable(first, nand::STATUS_ERASE_FAIL) {
+                    for page in &mut self.pages[first..first + per_block] {
+                        page.fill(0xFF);
+                    }
+                }
+            }
+            _ => (),
+        }
+        rx.resize(1 + data.len() + nbytes, 0);
+        Ok(rx)
+    }
+}
+
+/// Simulated Adesto DataFlash device with one SRAM buffer.
+///
+/// The device starts configured for DataFlash page sizes of the binary page size plus
+/// 1/32, switching with Configure Page Size, and pages are programmed through the buffer
+/// with a built-in erase. `with_busy_polls()` keeps the device busy for a number of
+/// status register polls after each page or buffer transfer, and instructions received
+/// while busy are ignored and counted by `busy_violations()`.
+#[derive(Clone, Debug)]
+pub struct MockDataFlash {
+    density: u8,
+    geometry: DataFlashGeometry,
+    /// Content of each page, in the DataFlash page size.
+    pages: Vec<Vec<u8>>,
+    buffer: Vec<u8>,
+    binary: bool,
+    /// Set when the last program or erase failed.
+    epe: bool,
+    /// Pages which cannot be programmed or erased.
+    failing: Vec<usize>,
+    /// Number of status polls for which each operation keeps the device busy.
+    busy_polls: u32,
+    /// Number of status polls before the current operation completes.
+    busy_remaining: u32,
+    /// Number of instructions received and ignored while busy.
+    busy_violations: usize,
+    /// If set, the device reports BUSY indefinitely.
+    stuck_busy: bool,
+}
+
+impl MockDataFlash {
+    /// Create a new erased device with the density code `density` of its device ID,
+    /// such as 0x02 for the 1Mbit AT45DB011.
+    ///
+    /// Panics if `density` is not a known DataFlash density.
+    pub fn new(density: u8) -> Self {
+        let geometry = DataFlashGeometry::from_density(density).expect("unknown density");
+        let page_size = geometry.binary_page_size + geometry.binary_page_size / 32;
+        MockDataFlash {
+            density,
+            geometry,
+            pages: vec![vec![0xFF; page_size]; geometry.pages as usize],
+            buffer: vec![0xFF; page_size],
+            binary: false,
+            epe: false,
+            failing: Vec::new(),
+            busy_polls: 0,
+            busy_remaining: 0,
+            busy_violations: 0,
+            stuck_busy: false,
+        }
+    }
+
+    /// Keep the device busy for `polls` status register polls after each operation.
+    pub fn with_busy_polls(mut self, polls: u32) -> Self {
+        self.busy_polls = polls;
+        self
+    }
+
+    /// Make programs and erases of `page` fail.
+    pub fn with_failing_page(mut self, page: usize) -> Self {
+        self.failing.push(page);
+        self
+    }
+
+    /// Get the number of instructions ignored because they were sent while busy.
+    pub fn busy_violations(&self) -> usize {
+        self.busy_violations
+    }
+
+    /// Get the content of `page`, in the DataFlash page size.
+    pub fn page(&self, page: usize) -> &[u8] {
+        &self.pages[page]
+    }
+
+    /// Get the content of `page` for modification, in the DataFlash page size.
+    pub fn page_mut(&mut self, page: usize) -> &mut [u8] {
+        &mut self.pages[page]
+    }
+
+    /// Make the device report BUSY indefinitely, or clear this.
+    pub fn set_stuck_busy(&mut self, stuck: bool) {
+        self.stuck_busy = stuck;
+    }
+
+    fn page_size(&self) -> usize {
+        if self.binary {
+            self.geometry.binary_page_size
+        } else {
+            self.buffer.len()
+        }
+    }
+
+    /// Decode the page and column of a 3-byte address.
+    fn address(&self, data: &[u8]) -> (usize, usize) {
+        let address = u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize;
+        if self.binary {
+            let size = self.geometry.binary_page_size;
+            (address / size, address % size)
+        } else {
+            let column_bits = self.geometry.binary_page_size.trailing_zeros() + 1;
+            (address >> column_bits, address & ((1 << column_bits) - 1))
+        }
+    }
+
+    fn program(&mut self, page: usize, data: Option<&[u8]>) {
+        self.epe = self.failing.contains(&page);
+        if !self.epe {
+            let size = self.pages[page].len();
+            match data {
+                Some(data) => self.pages[page].copy_from_slice(&data[..size]),
+                None => self.pages[page].fill(0xFF),
+            }
+        }
+        self.busy_remaining = self.busy_polls;
+    }
+}
+
+impl FlashAccess for MockDataFlash {
+    type Error = Error;
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let Command::DataFlash(opcode) = command else {
+            log::warn!("MockDataFlash ignoring {:?}", command);
+            return Ok(vec![0xFF; command.len() + data.len() + nbytes]);
+        };
+        let mut rx = vec![0; 1 + data.len()];
+        let busy = self.busy_remaining > 0 || self.stuck_busy;
+        if opcode == DataFlashOpCode::StatusRead {
+            let ready = if busy { 0 } else { dataflash::STATUS_READY };
+            let epe = if self.epe { dataflash::STATUS_EPE } else { 0 };
+            let binary = if self.binary {
+                dataflash::STATUS_BINARY_PAGE_SIZE
+            } else {
+                0
+            };
+            rx.extend([ready | ((2 * self.density - 1) << 2) | binary, ready | epe]);
+            self.busy_remaining = self.busy_remaining.saturating_sub(1);
+            return Ok(rx);
+        }
+        if busy {
+            log::warn!("MockDataFlash ignoring {:?} while busy", opcode);
+            self.busy_violations += 1;
+            rx.resize(1 + data.len() + nbytes, 0xFF);
+            return Ok(rx);
+        }
+        let page_size = self.page_size();
+        match opcode {
+            DataFlashOpCode::ReadID => {
+                rx.extend([dataflash::MANUFACTURER_ID, 0x20 | self.density, 0x00])
+            }
+            DataFlashOpCode::ContinuousRead => {
+                let (page, column) = self.address(data);
+                let memory: Vec<u8> = self
+                    .pages
+                    .iter()
+                    .flat_map(|p| &p[..page_size])
+                    .copied()
+                    .collect();
+                let start = page * page_size + column;
+                rx.extend((0..nbytes).map(|i| memory[(start + i) % memory.len()]));
+            }
+            DataFlashOpCode::PageToBuffer1 => {
+                let (page, _) = self.address(data);
+                self.buffer[..page_size].copy_from_slice(&self.pages[page][..page_size]);
+                self.busy_remaining = self.busy_polls;
+            }
+            DataFlashOpCode::Buffer1Write => {
+                let (_, column) = self.address(data);
+                for (i, byte) in data[3..].iter().enumerate() {
+                    self.buffer[(column + i) % page_size] = *byte;
+                }
+            }
+            DataFlashOpCode::Buffer1ToMainWithErase => {
+                let (page, _) = self.address(data);
+                let buffer = self.buffer.clone();
+                self.program(page, Some(&buffer));
+            }
+            DataFlashOpCode::PageErase => {
+                let (page, _) = self.address(data);
+                self.program(page, None);
+            }
+            DataFlashOpCode::ChipErase => {
+                if data == [0x94, 0x80, 0x9A] {
+                    for page in 0..self.pages.len() {
+                        self.program(page, None);
+                    }
+                } else {
+                    log::warn!("MockDataFlash ignoring chip erase with bad sequence");
+                }
+            }
+            DataFlashOpCode::ConfigurePageSize if data[..2] == [0x2A, 0x80] => {
+                self.binary = data[2] == 0xA6;
+                self.busy_remaining = self.busy_polls;
+            }
+            _ => (),
+        }
+        rx.resize(1 + data.len() + nbytes, 0);
+        Ok(rx)
+    }
+}
+
+/// Get the error returned by all operations after power is lost.
+fn power_lost() -> Error {
+    #[cfg(feature = "std")]
+    return Error::Access(anyhow::anyhow!("MockFlash has lost power"));
+    #[cfg(not(feature = "std"))]
+    return Error::Access;
+}
+
+fn transient_error() -> Error {
+    #[cfg(feature = "std")]
+    return Error::Access(anyhow::anyhow!("MockFlash transfer glitched"));
+    #[cfg(not(feature = "std"))]
+    return Error::Access;
+}
+
+#[test]
+fn test_mock_flash() {
+    let mut mock = MockFlash::new(64 * 1024).with_busy_polls(3);
+    {
+        let mut flash = configured_flash(&mut mock);
+        // Programming without write enable is ignored.
+        flash
+            .write(Command::PageProgram(crate::Address24Bits(0)), &[0])
+            .unwrap();
+        flash.program_data(0x1F0, &[0x5A; 32]).unwrap();
+        flash.erase_sector(0x1000).unwrap();
+        // Page programs wrap within the page.
+        flash.write_enable().unwrap();
+        flash
+            .write(
+                Command::PageProgram(crate::Address24Bits(0x3FE)),
+                &[1, 2, 3],
+            )
+            .unwrap();
+        assert!(flash.read_status1().unwrap().get_busy());
+    }
+    // Instructions sent without waiting for the program to finish are ignored.
+    let rx = mock.exchange(Command::ReadJEDECID, &[], 3).unwrap();
+    assert_eq!(rx[1..], [0xFF; 3]);
+    assert_eq!(mock.busy_violations(), 1);
+    assert!(mock.is_busy());
+    assert_eq!(mock.memory()[0], 0xFF);
+    assert_eq!(&mock.memory()[0x1F0..0x210], &[0x5A; 32]);
+    assert_eq!(&mock.memory()[0x3FE..0x400], &[1, 2]);
+    assert_eq!(mock.memory()[0x300], 3);
+
+    // The storage traits operate on the memory directly.
+    mock.erase_data(0, 4096).unwrap();
+    assert!(mock.erase_data(0x800, 4096).is_err());
+    mock.program_data(0xF0, &[0; 32]).unwrap();
+    assert_eq!(mock.read(0xEF, 34).unwrap()[..3], [0xFF, 0, 0]);
+    assert!(mock.read(0xFFFF, 2).is_err());
+}
+
+#[test]
+fn test_mock_faults() {
+    let mut mock = MockFlash::new(64 * 1024);
+    mock.set_power_loss_after(Some(300));
+    {
+        let mut flash = configured_flash(&mut mock);
+        assert!(flash.program_data(0, &[0; 512]).is_err());
+    }
+    assert!(mock.power_lost());
+    mock.restore_power();
+    assert!(mock.memory()[..300].iter().all(|x| *x == 0));
+    assert!(mock.memory()[300..].iter().all(|x| *x == 0xFF));
+
+    mock.inject_bit_flip(0x400, 0b0000_0100);
+    assert_eq!(mock.read(0x400, 1).unwrap(), [0xFB]);
+    mock.set_stuck_busy(true);
+    assert!(crate::Flash::new(&mut mock).is_busy().unwrap());
+    mock.set_stuck_busy(false);
+
+    mock.inject_protected_range(0x1000..0x2000);
+    let mut flash = configured_flash(&mut mock);
+    flash.erase_sector(0x1000).unwrap();
+    flash.program_data(0x1800, &[0]).unwrap();
+    assert_eq!(flash.read(0x1800, 1).unwrap(), [0xFF]);
+    assert_eq!(mock.protection_violations(), 2);
+}
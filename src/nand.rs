@@ -0,0 +1,485 @@
+//! SPI NAND flash devices.
+//!
+//! SPI NAND devices, such as the Winbond W25N and Micron MT29F SPI families, are organised
+//! as blocks of pages, each with a spare area used for bad block marks and ECC data. Pages
+//! are not read or programmed directly: a page read (0x13) loads a page into the device's
+//! cache, which is then read out, and a program loads data into the cache before a program
+//! execute (0x10) writes it to a page. Blocks may be marked bad at the factory or wear out
+//! in use, so users must skip bad blocks.
+//!
+//! Since the geometry and semantics differ from NOR flash, these devices are accessed using
+//! `NandFlash` rather than `Flash`, using the same `FlashAccess` transports.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::commands::nand::NandOpCode;
+use crate::wait::poll_until;
+use crate::{Command, Error, FlashAccess, Result};
+
+/// Feature register addresses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Feature {
+    /// Block protection register, whose block protect bits are set after power-up.
+    Protection = 0xA0,
+    /// Configuration register, including the ECC enable bit.
+    Configuration = 0xB0,
+    /// Status register.
+    Status = 0xC0,
+}
+
+/// Status register bit set while an operation is in progress.
+pub const STATUS_BUSY: u8 = 0b0000_0001;
+/// Status register bit set while writes are enabled.
+pub const STATUS_WEL: u8 = 0b0000_0010;
+/// Status register bit set when the last erase failed.
+pub const STATUS_ERASE_FAIL: u8 = 0b0000_0100;
+/// Status register bit set when the last program failed.
+pub const STATUS_PROGRAM_FAIL: u8 = 0b0000_1000;
+
+/// Result of the on-die ECC check of a page read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EccStatus {
+    /// No bit errors were found.
+    Ok,
+    /// Bit errors were found and corrected; this is the largest number of bits which may
+    /// have been corrected in any ECC sector of the page, as reported by the device.
+    Corrected(u8),
+    /// Bit errors were found which could not be corrected, so the data is not valid.
+    Uncorrectable,
+}
+
+/// Encoding of the ECC status bits in the status register, which differs between vendors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EccReporting {
+    /// Two bits at bits 4 and 5, as used by the Winbond W25N and GigaDevice GD5F, where
+    /// corrected errors are reported without a count. `max_corrected` is the number of bits
+    /// the ECC can correct per sector, reported as the count of corrected errors.
+    TwoBit { max_corrected: u8 },
+    /// Three bits at bits 4 to 6, as used by the Micron MT29F, where 0b001, 0b011, and
+    /// 0b101 report 1 to 3, 4 to 6, and 7 to 8 corrected bits, and 0b010 an uncorrectable
+    /// error.
+    Micron,
+}
+
+impl EccReporting {
+    /// Decode the ECC status from the value of the status register.
+    pub fn decode(self, status: u8) -> EccStatus {
+        match self {
+            EccReporting::TwoBit { max_corrected } => match (status >> 4) & 0b11 {
+                0b00 => EccStatus::Ok,
+                0b01 => EccStatus::Corrected(max_corrected),
+                _ => EccStatus::Uncorrectable,
+            },
+            EccReporting::Micron => match (status >> 4) & 0b111 {
+                0b000 => EccStatus::Ok,
+                0b001 => EccStatus::Corrected(3),
+                0b011 => EccStatus::Corrected(6),
+                0b101 => EccStatus::Corrected(8),
+                _ => EccStatus::Uncorrectable,
+            },
+        }
+    }
+}
+
+/// Organisation of an SPI NAND device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NandGeometry {
+    /// Size of the data area of each page in bytes.
+    pub page_size: usize,
+    /// Size of the spare area following the data area of each page in bytes.
+    pub spare_size: usize,
+    /// Number of pages in each erase block.
+    pub pages_per_block: u32,
+    /// Number of erase blocks.
+    pub blocks: u32,
+}
+
+impl NandGeometry {
+    /// Geometry of 1Gbit devices such as the W25N01GV and MT29F1G01.
+    pub const GBIT_1: NandGeometry = NandGeometry {
+        page_size: 2048,
+        spare_size: 64,
+        pages_per_block: 64,
+        blocks: 1024,
+    };
+
+    /// Total number of pages.
+    pub fn pages(&self) -> u32 {
+        self.pages_per_block * self.blocks
+    }
+
+    /// Size of the data area of the device in bytes, excluding spare areas.
+    pub fn capacity(&self) -> usize {
+        self.page_size * self.pages() as usize
+    }
+
+    /// Size of the data area of each block in bytes.
+    pub fn block_size(&self) -> usize {
+        self.page_size * self.pages_per_block as usize
+    }
+}
+
+/// Driver for an SPI NAND flash device.
+pub struct NandFlash<'a, A: FlashAccess> {
+    access: &'a mut A,
+    geometry: NandGeometry,
+    ecc: EccReporting,
+    relocation_threshold: Option<u8>,
+    last_ecc: Option<EccStatus>,
+    timeout: Option<Duration>,
+}
+
+impl<'a, A: FlashAccess> NandFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a driver for a device with the given `geometry`.
+    ///
+    /// ECC status is decoded as `EccReporting::TwoBit` correcting up to 4 bits,
+    /// see `set_ecc_reporting()`.
+    pub fn new(access: &'a mut A, geometry: NandGeometry) -> Self {
+        NandFlash {
+            access,
+            geometry,
+            ecc: EccReporting::TwoBit { max_corrected: 4 },
+            relocation_threshold: None,
+            last_ecc: None,
+            timeout: None,
+        }
+    }
+
+    /// Set the time after which an operation still in progress fails with
+    /// `Error::Timeout`, or `None`, the default, to wait indefinitely.
+    ///
+    /// As for `Flash::set_timeouts()`, elapsed time is counted from the delays requested
+    /// from `FlashAccess::delay()`, so a timeout must only be set if the transport
+    /// implements delays.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Set how the ECC status bits of the status register are decoded.
+    pub fn set_ecc_reporting(&mut self, ecc: EccReporting) {
+        self.ecc = ecc;
+    }
+
+    /// Set the number of corrected bits at or above which `needs_relocation()` reports
+    /// that a page should be moved, before further wear makes its errors uncorrectable.
+    ///
+    /// If `None`, the default, only uncorrectable pages need relocation.
+    pub fn set_relocation_threshold(&mut self, threshold: Option<u8>) {
+        self.relocation_threshold = threshold;
+    }
+
+    /// Check if a page read with ECC status `ecc` should have its data moved to a
+    /// different block, according to the relocation threshold.
+    pub fn needs_relocation(&self, ecc: EccStatus) -> bool {
+        match ecc {
+            EccStatus::Ok => false,
+            EccStatus::Corrected(bits) => self.relocation_threshold.is_some_and(|t| bits >= t),
+            EccStatus::Uncorrectable => true,
+        }
+    }
+
+    /// Get the ECC status of the most recent page read, if any.
+    pub fn last_ecc_status(&self) -> Option<EccStatus> {
+        self.last_ecc
+    }
+
+    /// Get the device geometry.
+    pub fn geometry(&self) -> NandGeometry {
+        self.geometry
+    }
+
+    /// Reset the device and wait for it to become ready.
+    pub fn reset(&mut self) -> Result<()> {
+        self.write(NandOpCode::Reset, &[])?;
+        self.wait_ready()?;
+        Ok(())
+    }
+
+    /// Read the JEDEC manufacturer ID and device ID.
+    pub fn read_id(&mut self) -> Result<(u8, u16)> {
+        let id = self.exchange(NandOpCode::ReadJEDECID, &[0], 3)?;
+        Ok((id[0], u16::from_be_bytes([id[1], id[2]])))
+    }
+
+    /// Read a feature register.
+    pub fn get_feature(&mut self, feature: Feature) -> Result<u8> {
+        Ok(self.exchange(NandOpCode::GetFeature, &[feature as u8], 1)?[0])
+    }
+
+    /// Write a feature register.
+    pub fn set_feature(&mut self, feature: Feature, value: u8) -> Result<()> {
+        self.write(NandOpCode::SetFeature, &[feature as u8, value])
+    }
+
+    /// Clear all block protect bits, which are set after power-up on most devices.
+    pub fn unprotect(&mut self) -> Result<()> {
+        self.set_feature(Feature::Protection, 0)
+    }
+
+    /// Poll the status register until no operation is in progress, returning its final value.
+    ///
+    /// Returns `Error::Timeout` if the device is still busy after the timeout, if set.
+    pub fn wait_ready(&mut self) -> Result<u8> {
+        poll_until(
+            self,
+            self.timeout,
+            |nand, interval| nand.access.delay(interval),
+            |nand| {
+                let status = nand.get_feature(Feature::Status)?;
+                Ok((status & STATUS_BUSY == 0).then_some(status))
+            },
+        )
+    }
+
+    /// Read `len` bytes from `page`, starting at byte `column`.
+    ///
+    /// Columns from `page_size` onwards address the spare area. The data is returned even
+    /// if it has uncorrectable errors; use `read_page_ecc()` to also get the ECC status.
+    pub fn read_page(&mut self, page: u32, column: usize, len: usize) -> Result<Vec<u8>> {
+        Ok(self.read_page_ecc(page, column, len)?.0)
+    }
+
+    /// Read `len` bytes from `page`, starting at byte `column`, and the ECC status of the page.
+    pub fn read_page_ecc(
+        &mut self,
+        page: u32,
+        column: usize,
+        len: usize,
+    ) -> Result<(Vec<u8>, EccStatus)> {
+        self.check_page(page, column, len)?;
+        let ecc = self.ecc.decode(self.load_page(page)?);
+        self.last_ecc = Some(ecc);
+        match ecc {
+            EccStatus::Ok => (),
+            EccStatus::Corrected(bits) => {
+                log::debug!("NAND page {} had up to {} bits corrected", page, bits)
+            }
+            EccStatus::Uncorrectable => log::warn!("NAND page {} has uncorrectable errors", page),
+        }
+        Ok((self.read_cache(column, len)?, ecc))
+    }
+
+    /// Read the data area of `page`.
+    pub fn read_page_data(&mut self, page: u32) -> Result<Vec<u8>> {
+        self.read_page(page, 0, self.geometry.page_size)
+    }
+
+    /// Program `data` into `page`, starting at byte `column`.
+    ///
+    /// The rest of the page is left unprogrammed. Each page should only be programmed once
+    /// between erases. Returns `ProgramFailed` if the device reports a failure, in which
+    /// case the block should be marked bad.
+    pub fn program_page(&mut self, page: u32, column: usize, data: &[u8]) -> Result<()> {
+        self.check_page(page, column, data.len())?;
+        log::trace!("Programming NAND page {} column {}", page, column);
+        self.write(NandOpCode::WriteEnable, &[])?;
+        let mut load = Vec::with_capacity(2 + data.len());
+        load.extend((column as u16).to_be_bytes());
+        load.extend(data);
+        self.write(NandOpCode::ProgramLoad, &load)?;
+        self.write(NandOpCode::ProgramExecute, &Self::row_address(page))?;
+        if self.wait_ready()? & STATUS_PROGRAM_FAIL != 0 {
+            log::error!("Programming NAND page {} failed", page);
+            return Err(Error::ProgramFailed {
+                address: self.page_address(page),
+            });
+        }
+        Ok(())
+    }
+
+    /// Erase `block`.
+    ///
+    /// Returns `EraseFailed` if the device reports a failure, in which case the block
+    /// should be marked bad.
+    pub fn erase_block(&mut self, block: u32) -> Result<()> {
+        self.check_block(block)?;
+        log::trace!("Erasing NAND block {}", block);
+        let page = block * self.geometry.pages_per_block;
+        self.write(NandOpCode::WriteEnable, &[])?;
+        self.write(NandOpCode::BlockErase, &Self::row_address(page))?;
+        if self.wait_ready()? & STATUS_ERASE_FAIL != 0 {
+            log::error!("Erasing NAND block {} failed", block);
+            return Err(Error::EraseFailed {
+                address: self.page_address(page),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check if `block` is marked bad.
+    ///
+    /// Bad blocks are marked by a byte other than 0xFF at the start of the spare area
+    /// of their first page.
+    pub fn is_bad_block(&mut self, block: u32) -> Result<bool> {
+        self.check_block(block)?;
+        let page = block * self.geometry.pages_per_block;
+        let mark = self.read_page(page, self.geometry.page_size, 1)?;
+        Ok(mark[0] != 0xFF)
+    }
+
+    /// Mark `block` as bad, so it is skipped by `is_bad_block()` checks.
+    ///
+    /// The block is erased first where possible, as the mark cannot be programmed into
+    /// a spare area which has already been programmed.
+    pub fn mark_bad_block(&mut self, block: u32) -> Result<()> {
+        log::warn!("Marking NAND block {} bad", block);
+        if let Err(err) = self.erase_block(block) {
+            log::warn!("Erasing bad block {} failed: {:?}", block, err);
+        }
+        let page = block * self.geometry.pages_per_block;
+        self.program_page(page, self.geometry.page_size, &[0x00])
+    }
+
+    /// Get a list of all blocks which are marked bad.
+    pub fn scan_bad_blocks(&mut self) -> Result<Vec<u32>> {
+        let mut bad = Vec::new();
+        for block in 0..self.geometry.blocks {
+            if self.is_bad_block(block)? {
+                bad.push(block);
+            }
+        }
+        log::debug!("Found {} bad NAND blocks", bad.len());
+        Ok(bad)
+    }
+
+    /// Read `page` into the device's cache.
+    fn load_page(&mut self, page: u32) -> Result<u8> {
+        self.write(NandOpCode::PageRead, &Self::row_address(page))?;
+        self.wait_ready()
+    }
+
+    /// Read `len` bytes from the device's cache, starting at `column`.
+    fn read_cache(&mut self, column: usize, len: usize) -> Result<Vec<u8>> {
+        let [hi, lo] = (column as u16).to_be_bytes();
+        self.exchange(NandOpCode::ReadFromCache, &[hi, lo, 0], len)
+    }
+
+    fn check_page(&self, page: u32, column: usize, len: usize) -> Result<()> {
+        let page_len = self.geometry.page_size + self.geometry.spare_size;
+        if page >= self.geometry.pages() || column + len > page_len {
+            log::error!(
+                "NAND page {} column {} length {} out of range",
+                page,
+                column,
+                len
+            );
+            return Err(Error::InvalidAddress { address: page });
+        }
+        Ok(())
+    }
+
+    fn check_block(&self, block: u32) -> Result<()> {
+        if block >= self.geometry.blocks {
+            log::error!("NAND block {} out of range", block);
+            return Err(Error::InvalidAddress { address: block });
+        }
+        Ok(())
+    }
+
+    /// Byte address of the start of the data area of `page`, used in errors.
+    fn page_address(&self, page: u32) -> u32 {
+        page * self.geometry.page_size as u32
+    }
+
+    fn row_address(page: u32) -> [u8; 3] {
+        let [_, b2, b1, b0] = page.to_be_bytes();
+        [b2, b1, b0]
+    }
+
+    fn exchange(&mut self, opcode: NandOpCode, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let command = Command::Nand(opcode);
+        let rx = self.access.exchange(command, data, nbytes)?;
+        Ok(rx[command.len() + data.len()..].to_vec())
+    }
+
+    fn write(&mut self, opcode: NandOpCode, data: &[u8]) -> Result<()> {
+        self.access.write(Command::Nand(opcode), data)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_nand_flash() {
+    let geometry = NandGeometry {
+        page_size: 512,
+        spare_size: 16,
+        pages_per_block: 4,
+        blocks: 8,
+    };
+    let mut device = crate::mock::MockNand::new(geometry).with_failing_block(5);
+    device.page_mut(4 * 3)[512] = 0x00;
+    let mut nand = NandFlash::new(&mut device, geometry);
+    assert_eq!(nand.read_id().unwrap(), (0xEF, 0xAA21));
+    nand.reset().unwrap();
+
+    // Blocks are protected until unprotected.
+    assert!(matches!(
+        nand.program_page(0, 0, &[0x12]),
+        Err(Error::ProgramFailed { address: 0 })
+    ));
+    nand.unprotect().unwrap();
+    nand.program_page(1, 16, &[0xA5; 32]).unwrap();
+    let data = nand.read_page_data(1).unwrap();
+    assert_eq!(&data[..16], &[0xFF; 16]);
+    assert_eq!(&data[16..48], &[0xA5; 32]);
+    assert!(nand.read_page(1, 520, 16).is_err());
+    assert!(nand.read_page(32, 0, 1).is_err());
+    nand.erase_block(0).unwrap();
+    assert_eq!(nand.read_page(1, 16, 1).unwrap(), [0xFF]);
+
+    assert!(matches!(
+        nand.erase_block(5),
+        Err(Error::EraseFailed { address: 10240 })
+    ));
+    assert_eq!(nand.scan_bad_blocks().unwrap(), [3]);
+
+    nand.mark_bad_block(6).unwrap();
+    assert!(nand.is_bad_block(6).unwrap());
+    assert_eq!(nand.scan_bad_blocks().unwrap(), [3, 6]);
+
+    // A device which stays busy fails once the timeout has passed.
+    nand.access.set_stuck_busy(true);
+    nand.set_timeout(Some(Duration::from_micros(32)));
+    assert!(matches!(nand.wait_ready(), Err(Error::Timeout)));
+}
+
+#[test]
+fn test_nand_ecc() {
+    let mut device = crate::mock::MockNand::new(NandGeometry::GBIT_1);
+    for (page, bits) in [(1, 0b001), (2, 0b010), (3, 0b011), (4, 0b101)] {
+        device.set_ecc_bits(page, bits);
+    }
+    let mut nand = NandFlash::new(&mut device, NandGeometry::GBIT_1);
+    assert_eq!(nand.last_ecc_status(), None);
+    assert_eq!(nand.read_page_ecc(0, 0, 4).unwrap().1, EccStatus::Ok);
+    assert_eq!(
+        nand.read_page_ecc(1, 0, 4).unwrap().1,
+        EccStatus::Corrected(4)
+    );
+    assert_eq!(nand.read_page(2, 0, 4).unwrap(), [0xFF; 4]);
+    assert_eq!(nand.last_ecc_status(), Some(EccStatus::Uncorrectable));
+    assert_eq!(
+        nand.read_page_ecc(3, 0, 4).unwrap().1,
+        EccStatus::Uncorrectable
+    );
+
+    nand.set_ecc_reporting(EccReporting::Micron);
+    let ecc: Vec<_> = (0..5)
+        .map(|page| nand.read_page_ecc(page, 0, 1).unwrap().1)
+        .collect();
+    use EccStatus::*;
+    assert_eq!(
+        ecc,
+        [Ok, Corrected(3), Uncorrectable, Corrected(6), Corrected(8)]
+    );
+    assert!(!nand.needs_relocation(Corrected(6)));
+    assert!(nand.needs_relocation(Uncorrectable));
+    nand.set_relocation_threshold(Some(6));
+    assert!(!nand.needs_relocation(Corrected(3)));
+    assert!(nand.needs_relocation(Corrected(6)));
+}
@@ -0,0 +1,153 @@
+//! Remapping instruction opcodes for devices with nonstandard encodings.
+//!
+//! Some clone parts implement the usual instructions under different opcodes, for example
+//! reading status register 2 using 0x37 rather than 0x35. An `OpcodeMap` set using
+//! `Flash::set_opcode_map()` or `FlashBuilder::opcode_map()` replaces the opcode of each
+//! mapped instruction as it is passed to the transport, so such parts can be driven
+//! without changes to the crate. Any address is sent unchanged.
+//!
+//! Instructions are matched by the opcode they would otherwise send, so instructions
+//! sharing an opcode, such as Write Enable for Volatile Status Register and Clear Flag
+//! Status Register, are remapped together. Vendor instructions sent using
+//! `Command::Octal`, `Command::Nand`, `Command::SerialMemory`, or `Command::DataFlash`,
+//! and raw instructions, are never remapped.
+
+use alloc::vec::Vec;
+
+use crate::{Address24Bits, Address32Bits, Command, CommandOpCode};
+
+/// Replacement opcodes for standard instructions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeMap {
+    /// Pairs of standard opcode and the opcode sent instead.
+    entries: Vec<(u8, u8)>,
+}
+
+impl OpcodeMap {
+    /// Create an empty map, which sends every instruction unchanged.
+    pub fn new() -> Self {
+        OpcodeMap::default()
+    }
+
+    /// Send `replacement` in place of `opcode`.
+    pub fn with(mut self, opcode: CommandOpCode, replacement: u8) -> Self {
+        self.insert(opcode, replacement);
+        self
+    }
+
+    /// Send `replacement` in place of `opcode`, replacing any earlier mapping of `opcode`.
+    pub fn insert(&mut self, opcode: CommandOpCode, replacement: u8) {
+        let opcode = opcode as u8;
+        self.entries.retain(|(from, _)| *from != opcode);
+        self.entries.push((opcode, replacement));
+    }
+
+    /// Remove any mapping of `opcode`.
+    pub fn remove(&mut self, opcode: CommandOpCode) {
+        self.entries.retain(|(from, _)| *from != opcode as u8);
+    }
+
+    /// Get the opcode sent in place of `opcode`, if it is mapped.
+    pub fn get(&self, opcode: CommandOpCode) -> Option<u8> {
+        self.lookup(opcode as u8)
+    }
+
+    /// Check if no opcodes are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the command to send in place of `command`: a raw command with the replacement
+    /// opcode and the same address if its opcode is mapped, otherwise `command` itself.
+    pub fn apply(&self, command: Command) -> Command {
+        if matches!(
+            command,
+            Command::Octal(_)
+                | Command::Nand(_)
+                | Command::SerialMemory(_)
+                | Command::DataFlash(_)
+                | Command::Raw(..)
+                | Command::Raw4B(..)
+        ) {
+            return command;
+        }
+        let (opcode, address) = command.parts();
+        let Some(replacement) = self.lookup(opcode) else {
+            return command;
+        };
+        match address {
+            Some(address) => address.either(
+                |Address24Bits(a)| Command::Raw(replacement, Some(Address24Bits(a))),
+                |Address32Bits(a)| Command::Raw4B(replacement, Address32Bits(a)),
+            ),
+            None => Command::Raw(replacement, None),
+        }
+    }
+
+    fn lookup(&self, opcode: u8) -> Option<u8> {
+        self.entries
+            .iter()
+            .find(|(from, _)| *from == opcode)
+            .map(|(_, to)| *to)
+    }
+}
+
+#[test]
+fn test_opcode_map() {
+    use crate::{Error, Flash, FlashAccess};
+
+    /// Transport recording the bytes of each command, reading zeros.
+    #[derive(Default)]
+    struct Recorder(Vec<crate::CommandBytes>);
+    impl FlashAccess for Recorder {
+        type Error = Error;
+
+        fn write(&mut self, command: Command, _data: &[u8]) -> Result<(), Error> {
+            self.0.push(command.to_array());
+            Ok(())
+        }
+
+        fn exchange(
+            &mut self,
+            command: Command,
+            data: &[u8],
+            nbytes: usize,
+        ) -> Result<Vec<u8>, Error> {
+            self.0.push(command.to_array());
+            Ok(alloc::vec![0; command.len() + data.len() + nbytes])
+        }
+    }
+
+    let mut map = OpcodeMap::new()
+        .with(CommandOpCode::ReadStatusRegister2, 0x37)
+        .with(CommandOpCode::ChipErase, 0x61)
+        .with(CommandOpCode::ChipErase, 0x60)
+        .with(CommandOpCode::SectorErase, 0xD7);
+    assert_eq!(map.get(CommandOpCode::ChipErase), Some(0x60));
+    assert_eq!(map.get(CommandOpCode::WriteEnable), None);
+    assert!(matches!(
+        map.apply(Command::SectorErase(Address24Bits(0x1000))),
+        Command::Raw(0xD7, Some(Address24Bits(0x1000)))
+    ));
+    assert!(matches!(
+        map.apply(Command::Raw(0x35, None)),
+        Command::Raw(0x35, None)
+    ));
+    map.remove(CommandOpCode::SectorErase);
+
+    let mut access = Recorder::default();
+    let mut flash = Flash::new(&mut access);
+    flash.set_opcode_map(Some(map));
+    flash.read_status2().unwrap();
+    flash.write_enable().unwrap();
+    flash.erase().unwrap();
+    flash.erase_sector(0x1000).unwrap();
+    drop(flash);
+    assert_eq!(access.0[0][..], [0x37]);
+    assert_eq!(access.0[1][..], [0x06]);
+    assert!(access.0.iter().any(|bytes| bytes[..] == [0x60]));
+    assert!(access
+        .0
+        .iter()
+        .any(|bytes| bytes[..] == [0x20, 0x00, 0x10, 0x00]));
+}
@@ -0,0 +1,80 @@
+//! Progress reporting for long-running operations.
+//!
+//! Chip erases and large writes can take many seconds. A `ProgressSink` set using
+//! `Flash::set_progress_sink()` is notified as program and erase operations proceed,
+//! so firmware can blink an LED or report progress over a debug channel. Unlike the
+//! `_progress()` methods, this does not require the `std` feature.
+
+use core::time::Duration;
+
+/// Progress of an operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// Units completed so far.
+    pub done: usize,
+    /// Total units in the operation.
+    pub total: usize,
+    /// Estimated time remaining from the SFDP typical timings, if known.
+    pub remaining: Option<Duration>,
+}
+
+impl Progress {
+    /// Check if the operation is complete.
+    pub fn is_complete(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+/// Receiver of progress reports from program and erase operations.
+///
+/// Each method is called once when an operation starts, with `done` equal to 0,
+/// and then after each step. The default implementations do nothing.
+pub trait ProgressSink {
+    /// Called as data is programmed, with progress counted in bytes.
+    fn bytes_written(&mut self, progress: Progress) {
+        let _ = progress;
+    }
+
+    /// Called as memory is erased, with progress counted in erase operations,
+    /// each of which erases one sector or block; a chip erase is a single operation.
+    fn sectors_erased(&mut self, progress: Progress) {
+        let _ = progress;
+    }
+}
+
+#[test]
+fn test_progress_sink() {
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    #[derive(Default)]
+    struct Recorder {
+        written: Vec<Progress>,
+        erased: Vec<Progress>,
+    }
+    struct Sink(Rc<RefCell<Recorder>>);
+    impl ProgressSink for Sink {
+        fn bytes_written(&mut self, progress: Progress) {
+            self.0.borrow_mut().written.push(progress);
+        }
+        fn sectors_erased(&mut self, progress: Progress) {
+            self.0.borrow_mut().erased.push(progress);
+        }
+    }
+
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_progress_sink(Some(Box::new(Sink(recorder.clone()))));
+    flash.erase_range(0x1000, 0x3000, false).unwrap();
+    flash.program_data(0x1080, &[0xA5; 600]).unwrap();
+    flash.erase().unwrap();
+
+    let recorder = recorder.borrow();
+    let done: Vec<_> = recorder.written.iter().map(|p| p.done).collect();
+    assert_eq!(done, [0, 128, 384, 600]);
+    assert!(recorder.written.iter().all(|p| p.total == 600));
+    let done: Vec<_> = recorder.erased.iter().map(|p| (p.done, p.total)).collect();
+    assert_eq!(done, [(0, 3), (1, 3), (2, 3), (3, 3), (0, 1), (1, 1)]);
+    assert!(recorder.erased.last().unwrap().is_complete());
+}
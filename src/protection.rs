@@ -0,0 +1,557 @@
+//! Write protection schemes.
+//!
+//! Vendors protect memory from program and erase operations in several incompatible
+//! ways. `ProtectionScheme` abstracts over them, so `Flash::set_protection()` and
+//! `Flash::is_address_protected()` work on any device once its scheme has been set
+//! with `Flash::set_protection_scheme()`:
+//!
+//! * `BlockProtectBits`: the BP bits of status register 1 protect a range at the top
+//!   of memory whose size is a power-of-two fraction of the capacity. This is the default.
+//! * `IndividualBlockLocks`: each block has a volatile lock bit, set and cleared with
+//!   0x36 and 0x39 after selecting individual protection with the WPS bit (Winbond, GigaDevice).
+//! * `DynamicProtectionBits` and `PersistentProtectionBits`: the volatile DYB and
+//!   non-volatile PPB bits of Spansion/Infineon Advanced Sector Protection. The PPBs
+//!   may be frozen until reset with `PersistentProtectionBits::lock()`, and the ASP
+//!   register is accessed with `PersistentProtectionBits::read_asp()`.
+//! * `BlockProtectionRegister`: a bitmap of block write-lock bits read with 0x72 and
+//!   written with 0x42 (SST26).
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{
+    Address32Bits, Command, CommandOpCode, Error, Flash, FlashAccess, Result, StatusWriteMode,
+};
+
+/// A method of protecting memory from program and erase operations.
+pub trait ProtectionScheme<A: FlashAccess>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Protect or unprotect the memory in `range`.
+    ///
+    /// Schemes which cannot protect exactly `range` protect the smallest region covering
+    /// it, or when unprotecting, leave protected only memory outside it.
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()>;
+
+    /// Check if `address` is protected.
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool>;
+
+    /// Unprotect all memory.
+    fn unprotect_all(&self, flash: &mut Flash<'_, A>) -> Result<()> {
+        let capacity = capacity(flash)?;
+        self.set_protection(flash, 0..capacity as u32, false)
+    }
+}
+
+/// Get the capacity of `flash`, which protection schemes require.
+fn capacity<A: FlashAccess>(flash: &Flash<'_, A>) -> Result<usize>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    flash.capacity().ok_or_else(|| {
+        log::error!("Capacity must be known to set protection");
+        Error::InvalidAddress { address: 0 }
+    })
+}
+
+/// Get the base addresses of each `unit`-sized unit overlapping `range`.
+fn units(range: &Range<u32>, unit: usize) -> impl Iterator<Item = u32> {
+    let start = range.start as usize - (range.start as usize % unit);
+    (start..range.end as usize).step_by(unit).map(|a| a as u32)
+}
+
+/// Protection using the BP0-BP2 bits of status register 1.
+///
+/// A BP value of n from 1 to 6 protects the top 1/2^(7-n) of memory, and 7 protects
+/// all of it, as on most Winbond, GigaDevice, and Macronix devices with TB, SEC, and
+/// CMP clear. Only ranges ending at the top of memory can be protected exactly.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockProtectBits;
+
+impl BlockProtectBits {
+    /// Get the size of memory protected by BP value `bp`.
+    fn protected_size(bp: u8, capacity: usize) -> usize {
+        match bp {
+            0 => 0,
+            7.. => capacity,
+            bp => capacity >> (7 - bp),
+        }
+    }
+}
+
+impl<A: FlashAccess> ProtectionScheme<A> for BlockProtectBits
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()> {
+        let capacity = capacity(flash)?;
+        let (bp0, bp1, bp2) = flash.read_status1()?.get_block_protect();
+        let current = bp0 as u8 | (bp1 as u8) << 1 | (bp2 as u8) << 2;
+        let bp = if protect {
+            if range.end as usize != capacity {
+                log::error!("BP bits can only protect the top of memory");
+                return Err(Error::InvalidAddress { address: range.end });
+            }
+            let required = capacity - range.start as usize;
+            let bp = (1..=7)
+                .find(|bp| Self::protected_size(*bp, capacity) >= required)
+                .unwrap_or(7);
+            u8::max(bp, current)
+        } else {
+            let allowed = capacity - range.end as usize;
+            (0..=current)
+                .rev()
+                .find(|bp| Self::protected_size(*bp, capacity) <= allowed)
+                .unwrap_or(0)
+        };
+        if bp == current {
+            return Ok(());
+        }
+        flash.protect(bp & 1 != 0, bp & 2 != 0, bp & 4 != 0)
+    }
+
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool> {
+        let capacity = capacity(flash)?;
+        let (bp0, bp1, bp2) = flash.read_status1()?.get_block_protect();
+        let bp = bp0 as u8 | (bp1 as u8) << 1 | (bp2 as u8) << 2;
+        let size = Self::protected_size(bp, capacity);
+        Ok(size > 0 && address as usize >= capacity - size)
+    }
+}
+
+/// Protection using individual block lock bits.
+///
+/// Setting the WPS bit in status register 3 selects individual protection, after which
+/// each block is locked with 0x36, unlocked with 0x39, and checked with 0x3D. All lock
+/// bits are set at power-up and are volatile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IndividualBlockLocks {
+    /// Size of each lockable block.
+    pub block_size: usize,
+    /// If set, the top and bottom blocks are instead locked in sectors of this size.
+    pub boundary_sector_size: Option<usize>,
+}
+
+impl IndividualBlockLocks {
+    /// Individual block locks as on Winbond devices: 64kB blocks, with the top and
+    /// bottom blocks locked in 4kB sectors.
+    pub fn winbond() -> Self {
+        IndividualBlockLocks {
+            block_size: 64 * 1024,
+            boundary_sector_size: Some(4096),
+        }
+    }
+
+    /// Get the base address of each lock unit overlapping `range`.
+    pub(crate) fn lock_units(&self, range: &Range<u32>, capacity: usize) -> Vec<u32> {
+        let mut addresses = Vec::new();
+        for block in units(range, self.block_size) {
+            let boundary = block == 0 || block as usize + self.block_size >= capacity;
+            match self.boundary_sector_size {
+                Some(sector) if boundary => {
+                    let end = u32::min(block + self.block_size as u32, range.end);
+                    let start = u32::max(block, range.start);
+                    addresses.extend(units(&(start..end), sector));
+                }
+                _ => addresses.push(block),
+            }
+        }
+        addresses
+    }
+
+    /// Select individual block protection by setting WPS, if not already set.
+    fn enable<A: FlashAccess>(flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let mut status3 = flash.read_status3()?;
+        if !status3.get_wps() {
+            log::debug!("Setting WPS to select individual block protection");
+            status3.set_wps(true);
+            flash.write_status3(status3, StatusWriteMode::NonVolatile)?;
+            flash.wait_while_busy()?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: FlashAccess> ProtectionScheme<A> for IndividualBlockLocks
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()> {
+        let capacity = capacity(flash)?;
+        Self::enable(flash)?;
+        for address in self.lock_units(&range, capacity) {
+            let opcode = if protect {
+                CommandOpCode::IndividualBlockLock
+            } else {
+                CommandOpCode::IndividualBlockUnlock
+            };
+            let command = flash.mode_address_command(opcode as u8, address)?;
+            flash.write_enable()?;
+            flash.write(command, &[])?;
+        }
+        Ok(())
+    }
+
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool> {
+        if !flash.read_status3()?.get_wps() {
+            return Ok(false);
+        }
+        let command = flash.mode_address_command(CommandOpCode::ReadBlockLock as u8, address)?;
+        let data = flash.exchange(command, &[], 1)?;
+        Ok(data[0] & 1 != 0)
+    }
+
+    fn unprotect_all(&self, flash: &mut Flash<'_, A>) -> Result<()> {
+        Self::enable(flash)?;
+        flash.write_enable()?;
+        flash.write(Command::GlobalBlockUnlock, &[])
+    }
+}
+
+/// Protection using the volatile Dynamic Protection Bits of Spansion/Infineon
+/// Advanced Sector Protection, one per sector of `sector_size` bytes.
+///
+/// A sector is protected if either its DYB or its PPB is set.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DynamicProtectionBits {
+    /// Size of each protected sector.
+    pub sector_size: usize,
+}
+
+impl<A: FlashAccess> ProtectionScheme<A> for DynamicProtectionBits
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()> {
+        // DYB value 0x00 protects the sector, 0xFF unprotects it.
+        let value = if protect { 0x00 } else { 0xFF };
+        for address in units(&range, self.sector_size) {
+            flash.write_enable()?;
+            flash.write(Command::WriteDyb(Address32Bits(address)), &[value])?;
+        }
+        Ok(())
+    }
+
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool> {
+        let address = address - address % self.sector_size as u32;
+        let data = flash.exchange(Command::ReadDyb(Address32Bits(address)), &[], 1)?;
+        Ok(data[0] == 0x00)
+    }
+}
+
+/// Protection using the non-volatile Persistent Protection Bits of Spansion/Infineon
+/// Advanced Sector Protection, one per sector of `sector_size` bytes.
+///
+/// PPBs can only be cleared all at once, so unprotecting a range erases all PPBs and
+/// then reprograms those of protected sectors outside the range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PersistentProtectionBits {
+    /// Size of each protected sector.
+    pub sector_size: usize,
+}
+
+impl<A: FlashAccess> ProtectionScheme<A> for PersistentProtectionBits
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()> {
+        if Self::is_locked(flash)? {
+            log::error!("PPBs are locked, cannot change protection");
+            return Err(Error::ProtectionLocked);
+        }
+        let program = if protect {
+            units(&range, self.sector_size).collect()
+        } else {
+            // Find protected sectors outside `range` to restore after erasing.
+            let capacity = capacity(flash)?;
+            let mut keep = Vec::new();
+            for address in units(&(0..capacity as u32), self.sector_size) {
+                let end = address + self.sector_size as u32;
+                let outside = end <= range.start || address >= range.end;
+                if outside && self.is_protected(flash, address)? {
+                    keep.push(address);
+                }
+            }
+            flash.write_enable()?;
+            flash.write(Command::ErasePpb, &[])?;
+            flash.wait_while_busy()?;
+            keep
+        };
+        for address in program {
+            flash.write_enable()?;
+            flash.write(Command::ProgramPpb(Address32Bits(address)), &[])?;
+            flash.wait_while_busy()?;
+        }
+        Ok(())
+    }
+
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool> {
+        let address = address - address % self.sector_size as u32;
+        let data = flash.exchange(Command::ReadPpb(Address32Bits(address)), &[], 1)?;
+        Ok(data[0] == 0x00)
+    }
+
+    fn unprotect_all(&self, flash: &mut Flash<'_, A>) -> Result<()> {
+        if Self::is_locked(flash)? {
+            log::error!("PPBs are locked, cannot change protection");
+            return Err(Error::ProtectionLocked);
+        }
+        flash.write_enable()?;
+        flash.write(Command::ErasePpb, &[])?;
+        flash.wait_while_busy()
+    }
+}
+
+impl PersistentProtectionBits {
+    /// Check if the PPB lock bit is cleared, so the PPBs cannot be programmed or erased.
+    pub fn is_locked<A: FlashAccess>(flash: &mut Flash<'_, A>) -> Result<bool>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let data = flash.exchange(Command::ReadPpbLock, &[], 1)?;
+        Ok(data[0] & 1 == 0)
+    }
+
+    /// Clear the PPB lock bit, so the PPBs cannot be changed until the next power cycle
+    /// or reset, as is usually done early in boot once the PPBs are known to be correct.
+    pub fn lock<A: FlashAccess>(flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        flash.write_enable()?;
+        flash.write(Command::WritePpbLock, &[])?;
+        flash.wait_while_busy()
+    }
+
+    /// Read the ASP register.
+    pub fn read_asp<A: FlashAccess>(flash: &mut Flash<'_, A>) -> Result<AspRegister>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let data = flash.exchange(Command::ReadAsp, &[], 2)?;
+        Ok(AspRegister(u16::from_le_bytes([data[0], data[1]])))
+    }
+
+    /// Program the ASP register.
+    ///
+    /// ASP register bits are one-time programmable: clearing a mode lock bit permanently
+    /// selects that protection mode.
+    pub fn program_asp<A: FlashAccess>(flash: &mut Flash<'_, A>, asp: AspRegister) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        flash.write_enable()?;
+        flash.write(Command::ProgramAsp, &asp.0.to_le_bytes())?;
+        flash.wait_while_busy()
+    }
+}
+
+/// Advanced Sector Protection register of Spansion/Infineon devices, which selects
+/// how the PPB lock bit is controlled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AspRegister(pub u16);
+
+impl AspRegister {
+    /// Check if Persistent Protection mode is permanently selected, so the PPB lock bit
+    /// is cleared only by `PersistentProtectionBits::lock()`.
+    pub fn is_persistent_mode_locked(&self) -> bool {
+        self.0 & 0b0010 == 0
+    }
+
+    /// Check if Password Protection mode is permanently selected, so the PPB lock bit is
+    /// cleared at power-up and set only by the password unlock command.
+    pub fn is_password_mode_locked(&self) -> bool {
+        self.0 & 0b0100 == 0
+    }
+
+    /// Clear the Persistent Protection Mode Lock bit, to permanently select Persistent
+    /// Protection mode when programmed.
+    pub fn lock_persistent_mode(&mut self) {
+        self.0 &= !0b0010;
+    }
+
+    /// Clear the Password Protection Mode Lock bit, to permanently select Password
+    /// Protection mode when programmed.
+    pub fn lock_password_mode(&mut self) {
+        self.0 &= !0b0100;
+    }
+}
+
+/// Protection using a Block-Protection Register bitmap, as on SST26 devices.
+///
+/// The register is `length` bytes, sent most significant bit first, and each entry
+/// of `blocks` gives a block's address range and the index of its write-lock bit.
+/// The block layout varies between devices and is listed in each datasheet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockProtectionRegister {
+    /// Length of the register in bytes.
+    pub length: usize,
+    /// Address range and write-lock bit index of each block.
+    pub blocks: Vec<(Range<u32>, usize)>,
+}
+
+impl BlockProtectionRegister {
+    /// Get the byte offset and mask of bit `bit` in the register as transferred.
+    fn bit_position(&self, bit: usize) -> (usize, u8) {
+        (self.length - 1 - bit / 8, 1 << (bit % 8))
+    }
+
+    fn read<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<Vec<u8>>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        flash.exchange(Command::ReadBlockProtection, &[], self.length)
+    }
+}
+
+impl<A: FlashAccess> ProtectionScheme<A> for BlockProtectionRegister
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn set_protection(
+        &self,
+        flash: &mut Flash<'_, A>,
+        range: Range<u32>,
+        protect: bool,
+    ) -> Result<()> {
+        let mut bpr = self.read(flash)?;
+        for (block, bit) in self.blocks.iter() {
+            if block.start < range.end && range.start < block.end {
+                let (byte, mask) = self.bit_position(*bit);
+                if protect {
+                    bpr[byte] |= mask;
+                } else {
+                    bpr[byte] &= !mask;
+                }
+            }
+        }
+        flash.write_enable()?;
+        flash.write(Command::WriteBlockProtection, &bpr)
+    }
+
+    fn is_protected(&self, flash: &mut Flash<'_, A>, address: u32) -> Result<bool> {
+        let bpr = self.read(flash)?;
+        Ok(self.blocks.iter().any(|(block, bit)| {
+            let (byte, mask) = self.bit_position(*bit);
+            block.contains(&address) && bpr[byte] & mask != 0
+        }))
+    }
+
+    fn unprotect_all(&self, flash: &mut Flash<'_, A>) -> Result<()> {
+        flash.write_enable()?;
+        flash.write(Command::GlobalBlockUnlock, &[])
+    }
+}
+
+#[test]
+fn test_block_protect_bits() {
+    use crate::Register;
+    let mut mock = crate::mock::MockFlash::new(2 * 1024 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    // Protecting the top 40kB requires the top 64kB, 1/32, to be protected.
+    flash.set_protection(0x1F_6000..0x20_0000, true).unwrap();
+    assert_eq!(
+        flash.read_status1().unwrap().get_block_protect(),
+        (false, true, false)
+    );
+    assert!(flash.is_address_protected(0x1F_0000).unwrap());
+    assert!(!flash.is_address_protected(0x1E_FFFF).unwrap());
+    assert!(flash.set_protection(0..0x1000, true).is_err());
+    // Unprotecting the top 4kB leaves nothing protected.
+    flash.set_protection(0x1F_F000..0x20_0000, false).unwrap();
+    assert!(!flash.is_protected().unwrap());
+
+    flash.set_protection_scheme(alloc::boxed::Box::new(IndividualBlockLocks::winbond()));
+    assert_eq!(
+        IndividualBlockLocks::winbond().lock_units(&(0xE000..0x12000), 0x20_0000),
+        [0xE000, 0xF000, 0x10000]
+    );
+    flash.set_protection(0x10000..0x20000, true).unwrap();
+    assert!(flash.read_status3().unwrap().get_wps());
+    assert!(flash.is_address_protected(0x10000).unwrap());
+    assert!(!flash.is_address_protected(0x20000).unwrap());
+    flash.unprotect_all().unwrap();
+    assert!(!flash.is_address_protected(0x10000).unwrap());
+    assert_eq!(mock.register(Register::Status1) & 0b0001_1100, 0);
+
+    // Blocks above 16MiB are locked using 4-byte addresses, not truncated ones.
+    let mut mock = crate::mock::MockFlash::new(32 * 1024 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_address_bytes(4);
+    flash.set_protection_scheme(alloc::boxed::Box::new(IndividualBlockLocks::winbond()));
+    flash.set_protection(0x100_0000..0x101_0000, true).unwrap();
+    assert!(flash.is_address_protected(0x100_0000).unwrap());
+    assert!(!flash.is_address_protected(0).unwrap());
+    flash.set_address_bytes(3);
+    assert!(flash.is_address_protected(0x100_0000).is_err());
+}
+
+#[test]
+fn test_advanced_sector_protection() {
+    use alloc::boxed::Box;
+    let mut mock = crate::mock::MockFlash::new(1024 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let ppb = PersistentProtectionBits {
+        sector_size: 64 * 1024,
+    };
+    flash.set_protection_scheme(Box::new(ppb));
+    flash.set_protection(0x10000..0x30000, true).unwrap();
+    flash.set_protection(0x10000..0x20000, false).unwrap();
+    assert!(!flash.is_address_protected(0x1FFFF).unwrap());
+    assert!(flash.is_address_protected(0x20000).unwrap());
+
+    // Once locked, the PPBs cannot be changed until reset.
+    PersistentProtectionBits::lock(&mut flash).unwrap();
+    assert!(PersistentProtectionBits::is_locked(&mut flash).unwrap());
+    assert!(matches!(
+        flash.unprotect_all(),
+        Err(Error::ProtectionLocked)
+    ));
+    flash.reset().unwrap();
+    flash.unprotect_all().unwrap();
+    assert!(!flash.is_address_protected(0x20000).unwrap());
+
+    let mut asp = PersistentProtectionBits::read_asp(&mut flash).unwrap();
+    assert!(!asp.is_persistent_mode_locked() && !asp.is_password_mode_locked());
+    asp.lock_persistent_mode();
+    PersistentProtectionBits::program_asp(&mut flash, asp).unwrap();
+    let asp = PersistentProtectionBits::read_asp(&mut flash).unwrap();
+    assert!(asp.is_persistent_mode_locked() && !asp.is_password_mode_locked());
+
+    flash.set_protection_scheme(Box::new(DynamicProtectionBits {
+        sector_size: 64 * 1024,
+    }));
+    flash.set_protection(0xF0000..0x100000, true).unwrap();
+    assert!(flash.is_address_protected(0xF8000).unwrap());
+    flash.unprotect_all().unwrap();
+    assert!(!flash.is_address_protected(0xF8000).unwrap());
+}
@@ -0,0 +1,238 @@
+//! Device provisioning records stored in OTP security registers.
+//!
+//! Factory provisioning usually records a few facts about each unit, such as its serial
+//! number, hardware revision, and a hash of the keys it was given, which must survive any
+//! later reprogramming of the flash. `Provisioning` stores them as a `ProvisioningRecord`
+//! in one of the security registers of a Winbond or GigaDevice device, following a
+//! write-once then lock workflow:
+//!
+//! 1. `write()` programs the record into the blank register, and reads it back to check
+//!    it was stored correctly.
+//! 2. `lock()` verifies the stored record again, then prepares to lock the register by
+//!    setting its LB bit, as an `Irreversible` operation confirmed by the caller.
+//! 3. `read()` returns the record, checking its CRC-32.
+//!
+//! The record is stored as the magic bytes `PROV`, a format version, the record length,
+//! the fields, and a CRC-32 of all the preceding bytes, with integers little-endian.
+
+use crate::crc::crc32;
+use crate::security::{Irreversible, LockSecurityRegister};
+use crate::{Error, Flash, FlashAccess, Result};
+
+/// Magic bytes at the start of a record.
+const MAGIC: [u8; 4] = *b"PROV";
+
+/// Format version of records written by this module.
+pub const RECORD_VERSION: u8 = 1;
+
+/// Length in bytes of a stored record.
+pub const RECORD_LEN: usize = 60;
+
+/// Facts recorded about a unit when it is provisioned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProvisioningRecord {
+    /// Serial number of the unit, padded as the manufacturer chooses.
+    pub serial_number: [u8; 16],
+    /// Hardware revision of the unit.
+    pub hardware_revision: u16,
+    /// Hash, such as SHA-256, of the keys provisioned into the unit.
+    pub keys_hash: [u8; 32],
+}
+
+impl ProvisioningRecord {
+    /// Encode the record as stored.
+    pub fn to_bytes(&self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4] = RECORD_VERSION;
+        bytes[5] = RECORD_LEN as u8;
+        bytes[6..22].copy_from_slice(&self.serial_number);
+        bytes[22..24].copy_from_slice(&self.hardware_revision.to_le_bytes());
+        bytes[24..56].copy_from_slice(&self.keys_hash);
+        let crc = crc32(&bytes[..56]);
+        bytes[56..60].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a stored record from `bytes`, returning `None` if they are erased.
+    ///
+    /// Returns `InvalidConfiguration` if the bytes are not a record of a supported
+    /// version, and `ChecksumMismatch` if its CRC-32 does not match.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Option<Self>> {
+        if bytes.iter().all(|b| *b == 0xFF) {
+            return Ok(None);
+        }
+        if bytes.len() < RECORD_LEN || bytes[0..4] != MAGIC || bytes[5] as usize != RECORD_LEN {
+            log::error!("No provisioning record found");
+            return Err(Error::InvalidConfiguration);
+        }
+        if bytes[4] != RECORD_VERSION {
+            log::error!("Unsupported provisioning record version {}", bytes[4]);
+            return Err(Error::InvalidConfiguration);
+        }
+        let crc = u32::from_le_bytes(bytes[56..60].try_into().unwrap());
+        if crc32(&bytes[..56]) != crc {
+            log::error!("Provisioning record CRC mismatch");
+            return Err(Error::ChecksumMismatch { address: 0 });
+        }
+        Ok(Some(ProvisioningRecord {
+            serial_number: bytes[6..22].try_into().unwrap(),
+            hardware_revision: u16::from_le_bytes([bytes[22], bytes[23]]),
+            keys_hash: bytes[24..56].try_into().unwrap(),
+        }))
+    }
+}
+
+/// Provisioning record access to one security register of a flash device.
+pub struct Provisioning<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    register: u8,
+}
+
+impl<'f, 'a, A: FlashAccess> Provisioning<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Access the provisioning record in security register `register`, from 1 to 3,
+    /// of `flash`.
+    pub fn new(flash: &'f mut Flash<'a, A>, register: u8) -> Self {
+        Provisioning { flash, register }
+    }
+
+    /// Read the record, returning `None` if the register has not been provisioned.
+    ///
+    /// Returns `ChecksumMismatch` if the record is corrupt.
+    pub fn read(&mut self) -> Result<Option<ProvisioningRecord>> {
+        let bytes = self
+            .flash
+            .read_security_register(self.register, 0, RECORD_LEN)?;
+        ProvisioningRecord::from_bytes(&bytes)
+    }
+
+    /// Check if the register is locked, so the record can no longer be changed.
+    pub fn is_locked(&mut self) -> Result<bool> {
+        self.flash.is_security_register_locked(self.register)
+    }
+
+    /// Program `record` into the register, then read it back to check it.
+    ///
+    /// Records are written once: returns `InvalidConfiguration` if the register is not
+    /// blank, and `ProtectionLocked` if it is locked.
+    pub fn write(&mut self, record: &ProvisioningRecord) -> Result<()> {
+        let existing = self
+            .flash
+            .read_security_register(self.register, 0, RECORD_LEN)?;
+        if existing.iter().any(|b| *b != 0xFF) {
+            log::error!("Security register {} is already provisioned", self.register);
+            return Err(Error::InvalidConfiguration);
+        }
+        let bytes = record.to_bytes();
+        self.flash
+            .program_security_register(self.register, 0, &bytes)?;
+        let read = self
+            .flash
+            .read_security_register(self.register, 0, RECORD_LEN)?;
+        if let Some((offset, (wrote, read))) = bytes
+            .iter()
+            .zip(&read)
+            .enumerate()
+            .find(|(_, (a, b))| a != b)
+        {
+            log::error!("Provisioning record readback mismatch at byte {}", offset);
+            return Err(Error::ReadbackError {
+                address: offset as u32,
+                wrote: *wrote,
+                read: *read,
+            });
+        }
+        log::info!("Provisioned security register {}", self.register);
+        Ok(())
+    }
+
+    /// Verify the stored record, then prepare to permanently lock the register.
+    ///
+    /// Returns `InvalidConfiguration` if the register holds no valid record, so a blank
+    /// or corrupt register is never locked, and `ProtectionLocked` if it is already locked.
+    pub fn lock(&mut self) -> Result<Irreversible<'_, 'a, A, LockSecurityRegister>> {
+        if self.is_locked()? {
+            log::error!("Security register {} is already locked", self.register);
+            return Err(Error::ProtectionLocked);
+        }
+        if self.read().ok().flatten().is_none() {
+            log::error!(
+                "Security register {} holds no valid provisioning record",
+                self.register
+            );
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(Irreversible::new(
+            self.flash,
+            LockSecurityRegister(self.register),
+        ))
+    }
+}
+
+#[test]
+fn test_provisioning() {
+    use crate::{StatusRegister2, StatusWriteMode};
+
+    let record = ProvisioningRecord {
+        serial_number: *b"SN-0000000000042",
+        hardware_revision: 3,
+        keys_hash: [0x5A; 32],
+    };
+    let bytes = record.to_bytes();
+    assert_eq!(
+        ProvisioningRecord::from_bytes(&bytes).unwrap(),
+        Some(record)
+    );
+    assert_eq!(ProvisioningRecord::from_bytes(&[0xFF; 60]).unwrap(), None);
+    let mut corrupt = bytes;
+    corrupt[30] ^= 1;
+    assert!(matches!(
+        ProvisioningRecord::from_bytes(&corrupt),
+        Err(Error::ChecksumMismatch { .. })
+    ));
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    assert!(flash.read_security_register(4, 0, 1).is_err());
+    assert!(flash.read_security_register(1, 200, 57).is_err());
+    let mut provisioning = Provisioning::new(&mut flash, 1);
+    assert_eq!(provisioning.read().unwrap(), None);
+    assert!(matches!(
+        provisioning.lock(),
+        Err(Error::InvalidConfiguration)
+    ));
+    provisioning.write(&record).unwrap();
+    assert_eq!(provisioning.read().unwrap(), Some(record));
+    assert!(matches!(
+        provisioning.write(&record),
+        Err(Error::InvalidConfiguration)
+    ));
+
+    // Dropping a prepared lock has no effect.
+    drop(provisioning.lock().unwrap());
+    assert!(!provisioning.is_locked().unwrap());
+    provisioning.lock().unwrap().confirm_irreversible().unwrap();
+    assert!(provisioning.is_locked().unwrap());
+    assert!(matches!(provisioning.lock(), Err(Error::ProtectionLocked)));
+    assert_eq!(provisioning.read().unwrap(), Some(record));
+
+    // The LB bit cannot be cleared, and locked registers cannot be changed.
+    flash
+        .write_status2(StatusRegister2(0), StatusWriteMode::NonVolatile)
+        .unwrap();
+    assert!(flash.is_security_register_locked(1).unwrap());
+    assert!(matches!(
+        flash.erase_security_register(1),
+        Err(Error::ProtectionLocked)
+    ));
+    // Other registers are independent, and a corrupt record is never locked.
+    flash.program_security_register(2, 0, &corrupt).unwrap();
+    let mut provisioning = Provisioning::new(&mut flash, 2);
+    assert!(provisioning.read().is_err());
+    assert!(provisioning.lock().is_err());
+    flash.erase_security_register(2).unwrap();
+    assert_eq!(Provisioning::new(&mut flash, 2).read().unwrap(), None);
+}
@@ -0,0 +1,31 @@
+//! Device-specific behaviour which cannot be discovered using SFDP.
+//!
+//! `Quirks` collects capability flags which change how `Flash` drives a device.
+//! All flags default to off, which gives the most widely compatible behaviour.
+
+use crate::{SFDPBusyPolling, SFDPQuadEnable};
+
+/// Device capability flags.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Reading status register 1 (0x05) for two bytes returns status register 1
+    /// followed by status register 2, allowing both to be read in a single transaction.
+    pub combined_status_read: bool,
+    /// Status registers 1 and 2 must be written together, as two data bytes of
+    /// Write Status Register 1 (0x01): writing one byte clears status register 2,
+    /// and Write Status Register 2 (0x31) is not supported. Status register 2 can be
+    /// read using 0x35. Found on older Winbond and GigaDevice parts.
+    pub combined_status_write: bool,
+    /// Quad enable bit location, for devices without SFDP or with an incorrect
+    /// quad enable requirement in SFDP. Takes precedence over SFDP if set.
+    pub quad_enable: Option<SFDPQuadEnable>,
+    /// Busy polling method, for devices without SFDP or with an incorrect status
+    /// register polling field in SFDP. Takes precedence over SFDP if set.
+    pub busy_polling: Option<SFDPBusyPolling>,
+    /// Chip Erase is only implemented under its alternate opcode 0x60, not 0xC7.
+    pub chip_erase_60: bool,
+    /// Reset is only implemented as the single instruction 0xF0, not as 0x66 followed
+    /// by 0x99, for devices without SFDP or with incorrect reset fields in SFDP. Takes
+    /// precedence over SFDP if set.
+    pub reset_f0: bool,
+}
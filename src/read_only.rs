@@ -0,0 +1,134 @@
+//! Read-only access to flash memory.
+//!
+//! `ReadOnlyFlash` wraps a `Flash` and only exposes methods which cannot modify the
+//! memory contents or device registers, so diagnostic and data-recovery tools can
+//! guarantee at compile time that they never alter the device.
+//!
+//! Raw command access through `exchange()` and `write()` is deliberately not exposed,
+//! as it could be used to issue program or erase instructions.
+
+use alloc::vec::Vec;
+
+use crate::{Error, Flash, FlashAccess, FlashID, FlashParams, Result};
+use crate::{Register, StatusRegister1, StatusRegister2, StatusRegister3};
+
+/// Read-only wrapper over a `Flash`.
+pub struct ReadOnlyFlash<'a, A: FlashAccess> {
+    flash: Flash<'a, A>,
+}
+
+impl<'a, A: FlashAccess> ReadOnlyFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a new read-only flash using `access`.
+    pub fn new(access: &'a mut A) -> Self {
+        ReadOnlyFlash {
+            flash: Flash::new(access),
+        }
+    }
+
+    /// Get a shared reference to the wrapped `Flash`, for reading its configuration.
+    pub fn flash(&self) -> &Flash<'a, A> {
+        &self.flash
+    }
+
+    /// Get the total memory capacity in bytes, if known.
+    pub fn capacity(&self) -> Option<usize> {
+        self.flash.capacity()
+    }
+
+    /// Set the total memory capacity in bytes.
+    pub fn set_capacity(&mut self, n: usize) {
+        self.flash.set_capacity(n)
+    }
+
+    /// Set the number of address bytes used in read commands.
+    pub fn set_address_bytes(&mut self, n: u8) {
+        self.flash.set_address_bytes(n)
+    }
+
+    /// Get the cached flash ID, if available.
+    pub fn get_id(&self) -> Option<FlashID> {
+        self.flash.get_id()
+    }
+
+    /// Get the cached flash parameters, if available.
+    pub fn get_params(&self) -> Option<FlashParams> {
+        self.flash.get_params()
+    }
+
+    /// Read the device's manufacturer ID, device ID, and unique ID.
+    ///
+    /// See `Flash::read_id()`; this also releases power-down and resets the device,
+    /// neither of which modifies the memory contents or non-volatile registers.
+    pub fn read_id(&mut self) -> Result<FlashID> {
+        self.flash.read_id()
+    }
+
+    /// Read the SFDP parameters, if present, and configure the capacity to match.
+    pub fn read_params(&mut self) -> Result<Option<FlashParams>> {
+        self.flash.read_params()
+    }
+
+    /// Read SFDP register data.
+    pub fn read_sfdp(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
+        self.flash.read_sfdp(addr, len)
+    }
+
+    /// Read `length` bytes of data starting at `address`, using FastRead.
+    pub fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.flash.read(address, length)
+    }
+
+    /// Read `buf.len()` bytes of data starting at `address` into `buf`.
+    pub fn read_into(&mut self, address: u32, buf: &mut [u8]) -> Result<()> {
+        self.flash.read_into(address, buf)
+    }
+
+    /// Read `length` bytes of data starting at `address`, using the legacy ReadData instruction.
+    pub fn legacy_read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.flash.legacy_read(address, length)
+    }
+
+    /// Read `length` bytes of data starting at `address`, calling `cb` with progress.
+    pub fn read_cb<F: Fn(usize)>(&mut self, address: u32, length: usize, cb: F) -> Result<Vec<u8>> {
+        self.flash.read_cb(address, length, cb)
+    }
+
+    /// Read `length` bytes of data starting at `address`, displaying a progress bar.
+    #[cfg(feature = "std")]
+    pub fn read_progress(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.flash.read_progress(address, length)
+    }
+
+    /// Check if `length` bytes starting at `address` are all erased.
+    pub fn is_erased(&mut self, address: u32, length: usize) -> Result<bool> {
+        self.flash.is_erased(address, length)
+    }
+
+    /// Compute the CRC-32 of `length` bytes starting at `address`.
+    pub fn crc32(&mut self, address: u32, length: usize) -> Result<u32> {
+        self.flash.crc32(address, length)
+    }
+
+    /// Read status register 1.
+    pub fn read_status1(&mut self) -> Result<StatusRegister1> {
+        self.flash.read_status1()
+    }
+
+    /// Read status register 2.
+    pub fn read_status2(&mut self) -> Result<StatusRegister2> {
+        self.flash.read_status2()
+    }
+
+    /// Read status register 3.
+    pub fn read_status3(&mut self) -> Result<StatusRegister3> {
+        self.flash.read_status3()
+    }
+
+    /// Read `register`, using the legacy status register instructions.
+    pub fn read_register(&mut self, register: Register) -> Result<u8> {
+        self.flash.read_register(register)
+    }
+}
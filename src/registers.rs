@@ -0,0 +1,93 @@
+//! Register access through Read Any Register / Write Any Register commands.
+//!
+//! Newer devices, such as the Infineon S25FS-S and Semper families and some Micron parts,
+//! provide generic RDAR (0x65) and WRAR (0x71) commands which access any register by its
+//! address. A `RegisterFile` maps symbolic registers to those addresses for a given device,
+//! and when set on a `Flash`, is used transparently by the status and configuration
+//! register methods.
+
+use alloc::vec::Vec;
+
+/// Symbolic device registers.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// Status register 1.
+    Status1,
+    /// Status register 2.
+    Status2,
+    /// Status register 3.
+    Status3,
+    /// Configuration register 1.
+    Config1,
+    /// Configuration register 2.
+    Config2,
+    /// Configuration register 3.
+    Config3,
+    /// Configuration register 4.
+    Config4,
+}
+
+/// Mapping of symbolic registers to RDAR/WRAR register addresses.
+#[derive(Clone, Debug, Default)]
+pub struct RegisterFile {
+    registers: Vec<(Register, u32)>,
+    dummy_bytes: usize,
+}
+
+impl RegisterFile {
+    /// Create a new, empty register file.
+    ///
+    /// `dummy_bytes` is the number of dummy bytes sent after the address in RDAR commands.
+    pub fn new(dummy_bytes: usize) -> Self {
+        RegisterFile {
+            registers: Vec::new(),
+            dummy_bytes,
+        }
+    }
+
+    /// Add or replace the address of `register`.
+    pub fn with(mut self, register: Register, address: u32) -> Self {
+        self.registers.retain(|(r, _)| *r != register);
+        self.registers.push((register, address));
+        self
+    }
+
+    /// Get the address of `register`, if mapped.
+    pub fn address(&self, register: Register) -> Option<u32> {
+        self.registers
+            .iter()
+            .find(|(r, _)| *r == register)
+            .map(|(_, address)| *address)
+    }
+
+    /// Number of dummy bytes to send after the address in RDAR commands.
+    pub fn dummy_bytes(&self) -> usize {
+        self.dummy_bytes
+    }
+
+    /// Volatile registers of Infineon/Spansion S25FS-S and Semper devices.
+    ///
+    /// RDAR uses 8 dummy cycles by default on these devices.
+    pub fn spansion_volatile() -> Self {
+        RegisterFile::new(1)
+            .with(Register::Status1, 0x80_0000)
+            .with(Register::Status2, 0x80_0001)
+            .with(Register::Config1, 0x80_0002)
+            .with(Register::Config2, 0x80_0003)
+            .with(Register::Config3, 0x80_0004)
+            .with(Register::Config4, 0x80_0005)
+    }
+
+    /// Non-volatile registers of Infineon/Spansion S25FS-S and Semper devices.
+    ///
+    /// RDAR uses 8 dummy cycles by default on these devices.
+    pub fn spansion_nonvolatile() -> Self {
+        RegisterFile::new(1)
+            .with(Register::Status1, 0x00_0000)
+            .with(Register::Config1, 0x00_0002)
+            .with(Register::Config2, 0x00_0003)
+            .with(Register::Config3, 0x00_0004)
+            .with(Register::Config4, 0x00_0005)
+    }
+}
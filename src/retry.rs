@@ -0,0 +1,99 @@
+//! Retrying transfers which fail transiently.
+//!
+//! On long or noisy buses, an occasional transfer may fail in the transport, for
+//! example with a DMA or CRC error, and would otherwise abort a read of many megabytes.
+//! A `RetryPolicy` set using `Flash::set_retry_policy()` repeats failed transfers of
+//! idempotent operations: data reads, and the status reads used to poll the busy flag.
+//! Programs, erases, and register writes are never retried, as repeating one which
+//! reached the device may not be safe.
+//!
+//! Only transport errors, `Error::Access`, are retried.
+
+use core::time::Duration;
+
+use crate::Error;
+
+/// Retry behaviour for transfers failing with transport errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts of each transfer, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubling before each later retry.
+    pub backoff: Duration,
+    /// If set, data reads are retried.
+    pub reads: bool,
+    /// If set, status reads polling the busy flag are retried.
+    pub status_polls: bool,
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        backoff: Duration::ZERO,
+        reads: false,
+        status_polls: false,
+    };
+
+    /// Create a policy retrying reads and status polls up to `max_attempts` attempts,
+    /// waiting `backoff` before the first retry.
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            backoff,
+            reads: true,
+            status_polls: true,
+        }
+    }
+
+    /// Get the delay before retrying after `attempt` failed attempts, if another
+    /// attempt is allowed after `err`.
+    pub(crate) fn retry_delay(&self, attempt: u32, err: &Error) -> Option<Duration> {
+        #[cfg(feature = "std")]
+        let transport = matches!(err, Error::Access(_));
+        #[cfg(not(feature = "std"))]
+        let transport = matches!(err, Error::Access);
+        if !transport || attempt >= self.max_attempts {
+            return None;
+        }
+        Some(self.backoff * 2u32.saturating_pow(attempt - 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Never retry.
+    fn default() -> Self {
+        RetryPolicy::NONE
+    }
+}
+
+#[test]
+fn test_retry_policy() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024).with_busy_polls(2);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.program_data(0x100, &[1, 2, 3, 4]).unwrap();
+    flash.access.set_transient_errors(1);
+    assert!(flash.read(0x100, 4).is_err());
+
+    flash.set_retry_policy(RetryPolicy::new(3, Duration::from_micros(1)));
+    flash.access.set_transient_errors(2);
+    assert_eq!(flash.read(0x100, 4).unwrap(), [1, 2, 3, 4]);
+    flash.access.set_transient_errors(3);
+    assert!(flash.read(0x100, 4).is_err());
+
+    // Status polls are retried, but the program itself is not.
+    flash.program_data(0x200, &[5]).unwrap();
+    flash.access.set_transient_errors(1);
+    flash.wait_while_busy().unwrap();
+    flash.access.set_transient_errors(1);
+    assert!(flash.program_data(0x300, &[6]).is_err());
+
+    let policy = RetryPolicy {
+        status_polls: false,
+        ..RetryPolicy::new(3, Duration::ZERO)
+    };
+    flash.set_retry_policy(policy);
+    flash.program_data(0x200, &[5]).unwrap();
+    flash.access.set_transient_errors(1);
+    assert!(flash.wait_while_busy().is_err());
+}
@@ -0,0 +1,263 @@
+//! Replay Protected Monotonic Counters.
+//!
+//! Devices such as the Winbond W74M and W77Q and Macronix ArmorFlash parts provide
+//! monotonic counters which can only be incremented, using commands authenticated with
+//! HMAC-SHA-256, as described in JESD234 and the SFDP RPMC parameter table. A counter is
+//! provisioned once with a root key, which is never sent again; each session then derives
+//! an HMAC key from the root key and some key data, and uses it to sign increments and to
+//! check the signature of counter values read back. These are typically used for
+//! anti-rollback counters in secure boot.
+//!
+//! Commands are sent using OP1 (0x9B), whose first data byte selects the command, and
+//! their result read using OP2 (0x96), which returns an extended status byte, followed by
+//! the tag, counter value, and signature of the last counter request.
+//!
+//! This crate does not implement HMAC-SHA-256; provide an implementation of `Hmac`,
+//! for example using the `hmac` and `sha2` crates or a hardware accelerator.
+
+use crate::{Command, Error, Flash, FlashAccess, Result};
+
+/// OP1 command types.
+const WRITE_ROOT_KEY: u8 = 0x00;
+const UPDATE_HMAC_KEY: u8 = 0x01;
+const INCREMENT_COUNTER: u8 = 0x02;
+const REQUEST_COUNTER: u8 = 0x03;
+
+/// Extended status bit set while an OP1 command is in progress.
+const STATUS_BUSY: u8 = 0b0000_0001;
+/// Extended status bit set when the last OP1 command completed successfully.
+const STATUS_SUCCESS: u8 = 0b1000_0000;
+
+/// HMAC-SHA-256 provider.
+pub trait Hmac {
+    /// Compute the HMAC-SHA-256 of `message` using `key`.
+    fn hmac_sha256(&mut self, key: &[u8; 32], message: &[u8]) -> [u8; 32];
+}
+
+/// Replay Protected Monotonic Counter access to a flash device.
+pub struct Rpmc<'f, 'a, A: FlashAccess, M: Hmac> {
+    flash: &'f mut Flash<'a, A>,
+    mac: M,
+}
+
+impl<'f, 'a, A: FlashAccess, M: Hmac> Rpmc<'f, 'a, A, M>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Access the counters of `flash`, using `mac` to compute signatures.
+    pub fn new(flash: &'f mut Flash<'a, A>, mac: M) -> Self {
+        Rpmc { flash, mac }
+    }
+
+    /// Read the extended status byte, which also reports the result of the last command.
+    pub fn read_status(&mut self) -> Result<u8> {
+        Ok(self.flash.exchange(Command::RpmcOp2, &[0], 1)?[0])
+    }
+
+    /// Provision `counter` with `root_key`.
+    ///
+    /// This may only be done once for each counter; the device rejects later attempts.
+    pub fn write_root_key(&mut self, counter: u8, root_key: &[u8; 32]) -> Result<()> {
+        let header = Self::header(WRITE_ROOT_KEY, counter);
+        let signature = self.mac.hmac_sha256(root_key, &header);
+        // Only the least significant 224 bits of the signature are sent.
+        self.op1(&header, &[root_key, &signature[4..]])
+    }
+
+    /// Derive an HMAC key for `counter` from `root_key` and `key_data`, and load it into
+    /// the device, returning the derived key for use in the other commands.
+    ///
+    /// The HMAC key is lost when the device is reset or powered down, so this must be
+    /// called in each session before the counter is used.
+    pub fn update_hmac_key(
+        &mut self,
+        counter: u8,
+        root_key: &[u8; 32],
+        key_data: [u8; 4],
+    ) -> Result<[u8; 32]> {
+        let hmac_key = self.mac.hmac_sha256(root_key, &key_data);
+        let header = Self::header(UPDATE_HMAC_KEY, counter);
+        let signature = self.sign(root_key, &header, &key_data);
+        self.op1(&header, &[&key_data, &signature])?;
+        Ok(hmac_key)
+    }
+
+    /// Increment `counter`, whose current value must be `value`.
+    pub fn increment_counter(
+        &mut self,
+        counter: u8,
+        hmac_key: &[u8; 32],
+        value: u32,
+    ) -> Result<()> {
+        let header = Self::header(INCREMENT_COUNTER, counter);
+        let data = value.to_be_bytes();
+        let signature = self.sign(hmac_key, &header, &data);
+        self.op1(&header, &[&data, &signature])
+    }
+
+    /// Read the value of `counter`, checking the device's signature over `tag` and the
+    /// value using `hmac_key`.
+    ///
+    /// `tag` should be unique for each request, such as a random nonce or timestamp,
+    /// so an old response cannot be replayed. Returns `ChecksumMismatch` if the signature
+    /// does not match.
+    pub fn read_counter(&mut self, counter: u8, hmac_key: &[u8; 32], tag: [u8; 12]) -> Result<u32> {
+        let header = Self::header(REQUEST_COUNTER, counter);
+        let signature = self.sign(hmac_key, &header, &tag);
+        self.op1(&header, &[&tag, &signature])?;
+        let data = self
+            .flash
+            .exchange(Command::RpmcOp2, &[0], 1 + 12 + 4 + 32)?;
+        let (response, signature) = data[1..].split_at(16);
+        if response[..12] != tag || self.mac.hmac_sha256(hmac_key, response) != signature {
+            log::error!("RPMC counter {} response signature mismatch", counter);
+            return Err(Error::ChecksumMismatch {
+                address: counter as u32,
+            });
+        }
+        Ok(u32::from_be_bytes(response[12..].try_into().unwrap()))
+    }
+
+    /// Get the opcode, command type, counter address, and reserved byte starting an OP1.
+    fn header(command: u8, counter: u8) -> [u8; 4] {
+        [Command::RpmcOp1.opcode(), command, counter, 0]
+    }
+
+    /// Sign `header` followed by `data`.
+    fn sign(&mut self, key: &[u8; 32], header: &[u8; 4], data: &[u8]) -> [u8; 32] {
+        let mut message = alloc::vec::Vec::with_capacity(header.len() + data.len());
+        message.extend(header);
+        message.extend(data);
+        self.mac.hmac_sha256(key, &message)
+    }
+
+    /// Send an OP1 with `header` followed by each of `fields`, and wait for it to complete.
+    fn op1(&mut self, header: &[u8; 4], fields: &[&[u8]]) -> Result<()> {
+        let mut data = header[1..].to_vec();
+        for field in fields {
+            data.extend(*field);
+        }
+        self.flash.write(Command::RpmcOp1, &data)?;
+        let status = loop {
+            let status = self.read_status()?;
+            if status & STATUS_BUSY == 0 {
+                break status;
+            }
+        };
+        if status != STATUS_SUCCESS {
+            log::error!(
+                "RPMC command 0x{:02X} failed, status 0x{:02X}",
+                header[1],
+                status
+            );
+            return Err(Error::Rpmc { status });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rpmc() {
+    use alloc::vec::Vec;
+
+    /// Stand-in for HMAC-SHA-256, which is sufficient to check the protocol.
+    struct XorMac;
+    impl Hmac for XorMac {
+        fn hmac_sha256(&mut self, key: &[u8; 32], message: &[u8]) -> [u8; 32] {
+            let mut mac = *key;
+            for (i, byte) in message.iter().enumerate() {
+                mac[i % 32] ^= byte.rotate_left(i as u32 / 32 + 1);
+            }
+            mac
+        }
+    }
+
+    /// Device with a single counter, which checks the signatures it is sent.
+    #[derive(Default)]
+    struct Device {
+        root_key: Option<[u8; 32]>,
+        hmac_key: Option<[u8; 32]>,
+        counter: u32,
+        status: u8,
+        response: Vec<u8>,
+    }
+    impl Device {
+        fn op1(&mut self, data: &[u8]) -> u8 {
+            let header = [0x9B, data[0], data[1], data[2]];
+            let sign = |key: &[u8; 32], fields: &[u8]| {
+                let message: Vec<u8> = header.iter().chain(fields).copied().collect();
+                XorMac.hmac_sha256(key, &message)
+            };
+            let payload = &data[3..];
+            match (data[0], self.root_key, self.hmac_key) {
+                (0x00, None, _) => {
+                    let key: [u8; 32] = payload[..32].try_into().unwrap();
+                    if sign(&key, &[])[4..] != payload[32..] {
+                        return 0x04;
+                    }
+                    self.root_key = Some(key);
+                }
+                (0x01, Some(root), _) => {
+                    if sign(&root, &payload[..4]) != payload[4..] {
+                        return 0x04;
+                    }
+                    self.hmac_key = Some(XorMac.hmac_sha256(&root, &payload[..4]));
+                }
+                (0x02, _, Some(key)) => {
+                    if sign(&key, &payload[..4]) != payload[4..]
+                        || payload[..4] != self.counter.to_be_bytes()
+                    {
+                        return 0x10;
+                    }
+                    self.counter += 1;
+                }
+                (0x03, _, Some(key)) => {
+                    if sign(&key, &payload[..12]) != payload[12..] {
+                        return 0x04;
+                    }
+                    let mut response = payload[..12].to_vec();
+                    response.extend(self.counter.to_be_bytes());
+                    let signature = XorMac.hmac_sha256(&key, &response);
+                    response.extend(signature);
+                    self.response = response;
+                }
+                (_, _, None) => return 0x08,
+                _ => return 0x02,
+            }
+            0x80
+        }
+    }
+    impl FlashAccess for Device {
+        type Error = Error;
+        fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+            let mut rx = alloc::vec![0; command.len() + data.len()];
+            match command {
+                Command::RpmcOp1 => self.status = self.op1(data),
+                Command::RpmcOp2 => {
+                    rx.push(self.status);
+                    rx.extend(&self.response);
+                }
+                _ => (),
+            }
+            rx.resize(command.len() + data.len() + nbytes, 0);
+            Ok(rx)
+        }
+    }
+
+    let mut device = Device::default();
+    let mut flash = Flash::new(&mut device);
+    let mut rpmc = Rpmc::new(&mut flash, XorMac);
+    let root_key = [0x5A; 32];
+    assert!(matches!(
+        rpmc.increment_counter(0, &[0; 32], 0),
+        Err(Error::Rpmc { status: 0x08 })
+    ));
+    rpmc.write_root_key(0, &root_key).unwrap();
+    assert!(rpmc.write_root_key(0, &root_key).is_err());
+    let hmac_key = rpmc.update_hmac_key(0, &root_key, [1, 2, 3, 4]).unwrap();
+    rpmc.increment_counter(0, &hmac_key, 0).unwrap();
+    rpmc.increment_counter(0, &hmac_key, 1).unwrap();
+    assert!(rpmc.increment_counter(0, &hmac_key, 1).is_err());
+    assert_eq!(rpmc.read_counter(0, &hmac_key, [7; 12]).unwrap(), 2);
+    assert!(rpmc.read_counter(0, &[0; 32], [7; 12]).is_err());
+}
@@ -0,0 +1,259 @@
+//! Power-loss-safe swapping between two sectors.
+//!
+//! `Eeprom` and `KvStore` append records to one active sector of a pair, and when it is
+//! full copy the live records to the other sector, which then becomes active. The first
+//! bytes of each sector hold a header tracking this swap:
+//!
+//! | Bytes   | Content                                         |
+//! |---------|-------------------------------------------------|
+//! | 4       | State, little-endian                            |
+//! | 4       | Sequence number, little-endian                  |
+//! | 4       | Inverted sequence number, little-endian         |
+//!
+//! States only ever move from erased through receiving and active to obsolete, each by
+//! clearing bits. A swap erases the spare sector, marks it receiving with the next
+//! sequence number, copies the records, marks it active, marks the old sector obsolete,
+//! and finally erases the old sector. At every step one sector remains active with its
+//! sequence number intact, so mounting after power loss finds it: if both are active,
+//! the newer by sequence number wins. A partial erase can only set bits, which breaks
+//! the inverted copy of the sequence number, so a half-erased sector is never taken as
+//! active. The other sector is erased when mounting unless it already is.
+
+use crate::{Error, Result, Storage};
+
+/// Sector state: erased and unused.
+const STATE_ERASED: u32 = 0xFFFF_FFFF;
+/// Sector state: records are being copied into this sector.
+const STATE_RECEIVING: u32 = 0xFFFF_FF00;
+/// Sector state: this sector holds the current records.
+const STATE_ACTIVE: u32 = 0xFFFF_0000;
+/// Sector state: the records of this sector have been copied to the other sector.
+const STATE_OBSOLETE: u32 = 0x0000_0000;
+
+/// Size of the sector header, after which records start.
+pub(crate) const HEADER_LEN: usize = 12;
+
+/// Two sectors, one of which holds the current records.
+pub(crate) struct SectorPair {
+    sectors: [u32; 2],
+    sector_size: usize,
+    active: usize,
+    /// Sequence number of the active sector.
+    seq: u32,
+}
+
+impl SectorPair {
+    /// Check `sector0` and `sector1` are distinct sectors of `storage`, whose erase size
+    /// must be known.
+    pub(crate) fn new<F: Storage + ?Sized>(
+        storage: &F,
+        sector0: u32,
+        sector1: u32,
+    ) -> Result<Self> {
+        let Some(sector_size) = storage.erase_size() else {
+            log::warn!("Sector erase size not known.");
+            return Err(Error::NoEraseInstruction);
+        };
+        for address in [sector0, sector1] {
+            if !(address as usize).is_multiple_of(sector_size) {
+                log::error!("Sector 0x{:08X} is not sector-aligned", address);
+                return Err(Error::InvalidAddress { address });
+            }
+        }
+        if sector0.abs_diff(sector1) < sector_size as u32 {
+            log::error!("Sectors 0x{:08X} and 0x{:08X} overlap", sector0, sector1);
+            return Err(Error::InvalidAddress { address: sector1 });
+        }
+        Ok(SectorPair {
+            sectors: [sector0, sector1],
+            sector_size,
+            active: 0,
+            seq: 0,
+        })
+    }
+
+    /// Get the size of each sector in bytes.
+    pub(crate) fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Get the address of the active sector.
+    pub(crate) fn active_address(&self) -> u32 {
+        self.sectors[self.active]
+    }
+
+    /// Find the active sector, completing or discarding any interrupted swap.
+    ///
+    /// If neither sector is active, both are erased and sector 0 made active, returning
+    /// `false`.
+    pub(crate) fn mount<F: Storage + ?Sized>(&mut self, storage: &mut F) -> Result<bool> {
+        let headers = [self.read_header(storage, 0)?, self.read_header(storage, 1)?];
+        log::debug!("Mounting sector pair, headers {:08X?}", headers);
+        let active = |sector: usize| match headers[sector] {
+            (STATE_ACTIVE, Some(seq)) => Some(seq),
+            _ => None,
+        };
+        self.active = match (active(0), active(1)) {
+            // The older sector was about to be marked obsolete.
+            (Some(seq0), Some(seq1)) => usize::from(seq1.wrapping_sub(seq0) as i32 > 0),
+            (Some(_), None) => 0,
+            (None, Some(_)) => 1,
+            (None, None) => {
+                log::info!("No active sector found, formatting");
+                self.format(storage)?;
+                return Ok(false);
+            }
+        };
+        self.seq = active(self.active).unwrap();
+        let spare = 1 - self.active;
+        if headers[spare] != (STATE_ERASED, None) || !self.is_erased(storage, spare)? {
+            log::debug!("Erasing spare sector left by an interrupted swap");
+            self.erase(storage, spare)?;
+        }
+        Ok(true)
+    }
+
+    /// Erase both sectors, and make sector 0 active.
+    pub(crate) fn format<F: Storage + ?Sized>(&mut self, storage: &mut F) -> Result<()> {
+        self.erase(storage, 1)?;
+        self.erase(storage, 0)?;
+        self.active = 0;
+        self.seq = 0;
+        self.write_header(storage, 0, STATE_ACTIVE, Some(0))
+    }
+
+    /// Swap to the spare sector, calling `copy` to copy the records from the active
+    /// sector at the first address to the spare sector at the second address.
+    ///
+    /// `copy` returns the offset after the copied records, which is returned.
+    pub(crate) fn swap<F: Storage + ?Sized>(
+        &mut self,
+        storage: &mut F,
+        copy: impl FnOnce(&mut F, u32, u32) -> Result<usize>,
+    ) -> Result<usize> {
+        let (from, to) = (self.active, 1 - self.active);
+        let seq = self.seq.wrapping_add(1);
+        self.erase(storage, to)?;
+        self.write_header(storage, to, STATE_RECEIVING, Some(seq))?;
+        let next = copy(storage, self.sectors[from], self.sectors[to])?;
+        self.write_state(storage, to, STATE_ACTIVE)?;
+        self.active = to;
+        self.seq = seq;
+        self.write_state(storage, from, STATE_OBSOLETE)?;
+        self.erase(storage, from)?;
+        Ok(next)
+    }
+
+    /// Read the state and sequence number of `sector`, if its sequence number is intact.
+    fn read_header<F: Storage + ?Sized>(
+        &self,
+        storage: &mut F,
+        sector: usize,
+    ) -> Result<(u32, Option<u32>)> {
+        let data = storage.read(self.sectors[sector], HEADER_LEN)?;
+        let word = |i: usize| u32::from_le_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+        let (state, seq, inverted) = (word(0), word(1), word(2));
+        Ok((state, (seq == !inverted).then_some(seq)))
+    }
+
+    fn write_header<F: Storage + ?Sized>(
+        &self,
+        storage: &mut F,
+        sector: usize,
+        state: u32,
+        seq: Option<u32>,
+    ) -> Result<()> {
+        let mut header = [0xFF; HEADER_LEN];
+        header[0..4].copy_from_slice(&state.to_le_bytes());
+        if let Some(seq) = seq {
+            header[4..8].copy_from_slice(&seq.to_le_bytes());
+            header[8..12].copy_from_slice(&(!seq).to_le_bytes());
+        }
+        storage.program_data(self.sectors[sector], &header)
+    }
+
+    fn write_state<F: Storage + ?Sized>(
+        &self,
+        storage: &mut F,
+        sector: usize,
+        state: u32,
+    ) -> Result<()> {
+        storage.program_data(self.sectors[sector], &state.to_le_bytes())
+    }
+
+    fn is_erased<F: Storage + ?Sized>(&self, storage: &mut F, sector: usize) -> Result<bool> {
+        let data = storage.read(self.sectors[sector], self.sector_size)?;
+        Ok(data.iter().all(|b| *b == 0xFF))
+    }
+
+    fn erase<F: Storage + ?Sized>(&self, storage: &mut F, sector: usize) -> Result<()> {
+        storage.erase_data(self.sectors[sector], self.sector_size)
+    }
+}
+
+#[test]
+fn test_sector_pair_power_loss() {
+    use crate::mock::MockFlash;
+
+    /// Swap to a sector holding 64 bytes of `new`.
+    fn swap(mock: &mut MockFlash, pair: &mut SectorPair, new: u8) -> Result<usize> {
+        pair.swap(mock, |mock, _, to| {
+            mock.program_data(to + HEADER_LEN as u32, &[new; 64])?;
+            Ok(HEADER_LEN + 64)
+        })
+    }
+
+    let mut mock = MockFlash::new(64 * 1024);
+    assert!(SectorPair::new(&mock, 0x1000, 0x1800).is_err());
+    assert!(SectorPair::new(&mock, 0x1100, 0x2000).is_err());
+    let mut pair = SectorPair::new(&mock, 0x1000, 0x2000).unwrap();
+    assert!(!pair.mount(&mut mock).unwrap());
+    assert!(pair.mount(&mut mock).unwrap());
+    assert_eq!(pair.active_address(), 0x1000);
+    mock.program_data(0x1000 + HEADER_LEN as u32, &[1; 64])
+        .unwrap();
+
+    // Interrupt each program and erase of a swap in turn. Mounting afterwards must
+    // find either the old or the new records, never neither.
+    let mut value = 1;
+    for (programs, erases) in (0..100)
+        .map(|n| (Some(n), None))
+        .chain((0..2).map(|n| (None, Some(n))))
+    {
+        mock.set_power_loss_after(programs);
+        mock.set_power_loss_during_erase(erases);
+        let result = swap(&mut mock, &mut pair, value + 1);
+        mock.restore_power();
+        let mut pair_after = SectorPair::new(&mock, 0x1000, 0x2000).unwrap();
+        assert!(pair_after.mount(&mut mock).unwrap());
+        let found = mock.memory()[pair_after.active_address() as usize + HEADER_LEN];
+        if result.is_ok() {
+            assert_eq!(found, value + 1);
+        } else {
+            assert!(found == value || found == value + 1, "found {}", found);
+        }
+        value = found;
+        pair = pair_after;
+        let spare = 0x3000 - pair.active_address();
+        assert!(mock.memory()[spare as usize..][..4096]
+            .iter()
+            .all(|b| *b == 0xFF));
+    }
+
+    // The newer of two active sectors wins, even across sequence number wraparound.
+    pair.seq = u32::MAX;
+    swap(&mut mock, &mut pair, 9).unwrap();
+    assert_eq!(pair.seq, 0);
+    let older = 0x3000 - pair.active_address();
+    pair.write_header(
+        &mut mock,
+        (older == 0x2000) as usize,
+        STATE_ACTIVE,
+        Some(u32::MAX),
+    )
+    .unwrap();
+    let active = pair.active_address();
+    let mut pair = SectorPair::new(&mock, 0x1000, 0x2000).unwrap();
+    assert!(pair.mount(&mut mock).unwrap());
+    assert_eq!(pair.active_address(), active);
+}
@@ -0,0 +1,323 @@
+//! Password protection and permanent protection mode selection.
+//!
+//! Spansion/Infineon Advanced Sector Protection can guard the PPB lock bit with a 64-bit
+//! password: once Password Protection mode is selected, the PPB lock bit is cleared at
+//! every power-up and reset, freezing the PPBs, and only Password Unlock (0xE9) with the
+//! matching password sets it again so the PPBs can be changed. The password is
+//! programmed with 0xE8, and can be read back with 0xE7 until the mode is selected.
+//! The PPB lock bit itself is read with `PersistentProtectionBits::is_locked()`.
+//!
+//! Programming the password and selecting a protection mode are one-time operations
+//! which cannot be undone on the device. `Security` therefore only prepares them as an
+//! `Irreversible` operation, which has no effect until `confirm_irreversible()` is called.
+//! Locking a Winbond or GigaDevice security register, prepared by
+//! `Security::lock_security_register()`, is likewise permanent.
+
+use core::time::Duration;
+
+use crate::protection::{AspRegister, PersistentProtectionBits};
+use crate::wait::poll_until;
+use crate::{Command, Error, Flash, FlashAccess, Result, StatusWriteMode};
+
+/// Status register 1 bit set when Password Unlock is given the wrong password.
+const SR1_P_ERR: u8 = 0b0100_0000;
+/// Time after which a Password Unlock still in progress fails with `Error::Timeout`, if
+/// timeouts are enabled, well above the time devices take to check the password.
+const UNLOCK_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// A 64-bit password, in the order its bytes are sent to the device.
+pub type Password = [u8; 8];
+
+/// Password protection and permanent protection mode access to a flash device.
+pub struct Security<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+}
+
+impl<'f, 'a, A: FlashAccess> Security<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Access the password protection of `flash`.
+    pub fn new(flash: &'f mut Flash<'a, A>) -> Self {
+        Security { flash }
+    }
+
+    /// Read the ASP register, which reports the selected protection mode.
+    pub fn read_asp(&mut self) -> Result<AspRegister> {
+        PersistentProtectionBits::read_asp(self.flash)
+    }
+
+    /// Read the password.
+    ///
+    /// Once Password Protection mode is selected, the device no longer returns the
+    /// password, so this returns `ProtectionLocked`.
+    pub fn read_password(&mut self) -> Result<Password> {
+        if self.read_asp()?.is_password_mode_locked() {
+            log::error!("Password cannot be read in Password Protection mode");
+            return Err(Error::ProtectionLocked);
+        }
+        let data = self.flash.exchange(Command::ReadPassword, &[], 8)?;
+        Ok(data.try_into().unwrap())
+    }
+
+    /// Send `password` to set the PPB lock bit in Password Protection mode, so the PPBs
+    /// can be changed until the next power cycle or reset.
+    ///
+    /// Returns `PasswordRejected` if the password does not match, after which the
+    /// device stays busy and must be reset before it accepts another attempt. If timeouts
+    /// are enabled using `Flash::set_timeouts()`, returns `Timeout` if the device is still
+    /// busy without either result after 1 ms.
+    pub fn unlock(&mut self, password: &Password) -> Result<()> {
+        self.flash.write(Command::UnlockPassword, password)?;
+        let timeout = self.flash.timeouts.then_some(UNLOCK_TIMEOUT);
+        poll_until(
+            self.flash,
+            timeout,
+            |flash, interval| flash.access.delay(interval),
+            |flash| {
+                let status = flash.read_status1()?;
+                if status.0 & SR1_P_ERR != 0 {
+                    log::error!("Device rejected the password");
+                    return Err(Error::PasswordRejected);
+                }
+                Ok((!status.get_busy()).then_some(()))
+            },
+        )
+    }
+
+    /// Prepare to program `password`, which is checked by reading it back.
+    ///
+    /// Password bits can only be cleared, so a password can be programmed only once.
+    /// Returns `ProtectionLocked` if Password Protection mode is already selected.
+    pub fn program_password(
+        &mut self,
+        password: Password,
+    ) -> Result<Irreversible<'_, 'a, A, ProgramPassword>> {
+        if self.read_asp()?.is_password_mode_locked() {
+            log::error!("Password cannot be programmed in Password Protection mode");
+            return Err(Error::ProtectionLocked);
+        }
+        Ok(Irreversible::new(self.flash, ProgramPassword(password)))
+    }
+
+    /// Prepare to permanently select Password Protection mode.
+    ///
+    /// The password can never be read or changed afterwards, so program and record it
+    /// first. Returns `InvalidConfiguration` if the password is still erased, or if
+    /// Persistent Protection mode is already selected.
+    pub fn lock_password_mode(&mut self) -> Result<Irreversible<'_, 'a, A, LockPasswordMode>> {
+        if self.read_asp()?.is_persistent_mode_locked() {
+            log::error!("Persistent Protection mode is already selected");
+            return Err(Error::InvalidConfiguration);
+        }
+        if self.read_password()? == [0xFF; 8] {
+            log::error!("Program the password before selecting Password Protection mode");
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(Irreversible::new(self.flash, LockPasswordMode))
+    }
+
+    /// Prepare to permanently select Persistent Protection mode, so the PPB lock bit
+    /// can only be cleared by `PersistentProtectionBits::lock()`, and a password can
+    /// never be used.
+    ///
+    /// Returns `InvalidConfiguration` if Password Protection mode is already selected.
+    pub fn lock_persistent_mode(&mut self) -> Result<Irreversible<'_, 'a, A, LockPersistentMode>> {
+        if self.read_asp()?.is_password_mode_locked() {
+            log::error!("Password Protection mode is already selected");
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(Irreversible::new(self.flash, LockPersistentMode))
+    }
+
+    /// Prepare to permanently lock security register `index`, from 1 to 3, by setting
+    /// its LB bit in status register 2, so it can never be programmed or erased again.
+    ///
+    /// Returns `ProtectionLocked` if the register is already locked.
+    pub fn lock_security_register(
+        &mut self,
+        index: u8,
+    ) -> Result<Irreversible<'_, 'a, A, LockSecurityRegister>> {
+        if self.flash.is_security_register_locked(index)? {
+            log::error!("Security register {} is already locked", index);
+            return Err(Error::ProtectionLocked);
+        }
+        Ok(Irreversible::new(self.flash, LockSecurityRegister(index)))
+    }
+}
+
+mod private {
+    use crate::{Error, Flash, FlashAccess, Result};
+
+    pub trait Execute {
+        fn execute<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<()>
+        where
+            Error: From<<A as FlashAccess>::Error>;
+    }
+}
+
+/// A one-time operation prepared by `Security`.
+pub trait PermanentOperation: private::Execute {}
+
+/// Program the password, prepared by `Security::program_password()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProgramPassword(Password);
+
+/// Select Password Protection mode, prepared by `Security::lock_password_mode()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LockPasswordMode;
+
+/// Select Persistent Protection mode, prepared by `Security::lock_persistent_mode()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LockPersistentMode;
+
+/// Lock a security register, prepared by `Security::lock_security_register()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LockSecurityRegister(pub(crate) u8);
+
+impl PermanentOperation for ProgramPassword {}
+impl PermanentOperation for LockPasswordMode {}
+impl PermanentOperation for LockPersistentMode {}
+impl PermanentOperation for LockSecurityRegister {}
+
+impl private::Execute for ProgramPassword {
+    fn execute<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        flash.write_enable()?;
+        flash.write(Command::ProgramPassword, &self.0)?;
+        flash.wait_while_busy()?;
+        let data = flash.exchange(Command::ReadPassword, &[], 8)?;
+        for (address, (wrote, read)) in self.0.into_iter().zip(data).enumerate() {
+            if wrote != read {
+                log::error!("Password readback mismatch at byte {}", address);
+                return Err(Error::ReadbackError {
+                    address: address as u32,
+                    wrote,
+                    read,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl private::Execute for LockPasswordMode {
+    fn execute<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let mut asp = PersistentProtectionBits::read_asp(flash)?;
+        asp.lock_password_mode();
+        PersistentProtectionBits::program_asp(flash, asp)
+    }
+}
+
+impl private::Execute for LockPersistentMode {
+    fn execute<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let mut asp = PersistentProtectionBits::read_asp(flash)?;
+        asp.lock_persistent_mode();
+        PersistentProtectionBits::program_asp(flash, asp)
+    }
+}
+
+impl private::Execute for LockSecurityRegister {
+    fn execute<A: FlashAccess>(&self, flash: &mut Flash<'_, A>) -> Result<()>
+    where
+        Error: From<<A as FlashAccess>::Error>,
+    {
+        let mut status2 = flash.read_status2()?;
+        status2.set_security_lock(self.0);
+        flash.write_status2(status2, StatusWriteMode::NonVolatile)?;
+        flash.wait_while_busy()
+    }
+}
+
+/// A prepared one-time operation, which permanently changes the device only when
+/// `confirm_irreversible()` is called. Dropping it instead leaves the device unchanged.
+#[must_use = "the operation only takes effect once confirmed"]
+pub struct Irreversible<'s, 'a, A: FlashAccess, Op: PermanentOperation> {
+    flash: &'s mut Flash<'a, A>,
+    operation: Op,
+}
+
+impl<'s, 'a, A: FlashAccess, Op: PermanentOperation> Irreversible<'s, 'a, A, Op>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    pub(crate) fn new(flash: &'s mut Flash<'a, A>, operation: Op) -> Self {
+        Irreversible { flash, operation }
+    }
+
+    /// Get the prepared operation.
+    pub fn operation(&self) -> &Op {
+        &self.operation
+    }
+
+    /// Perform the operation. This cannot be undone.
+    pub fn confirm_irreversible(self) -> Result<()> {
+        self.operation.execute(self.flash)
+    }
+}
+
+#[test]
+fn test_password_protection() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = Flash::new(&mut mock);
+    let password = [1, 2, 3, 4, 5, 6, 7, 8];
+    let mut security = Security::new(&mut flash);
+    assert_eq!(security.read_password().unwrap(), [0xFF; 8]);
+    assert!(matches!(
+        security.lock_password_mode(),
+        Err(Error::InvalidConfiguration)
+    ));
+
+    // Dropping a prepared operation has no effect.
+    drop(security.program_password(password).unwrap());
+    assert_eq!(security.read_password().unwrap(), [0xFF; 8]);
+    let program = security.program_password(password).unwrap();
+    assert_eq!(program.operation(), &ProgramPassword(password));
+    program.confirm_irreversible().unwrap();
+    assert_eq!(security.read_password().unwrap(), password);
+
+    security
+        .lock_password_mode()
+        .unwrap()
+        .confirm_irreversible()
+        .unwrap();
+    assert!(security.read_asp().unwrap().is_password_mode_locked());
+    assert!(matches!(
+        security.read_password(),
+        Err(Error::ProtectionLocked)
+    ));
+    assert!(security.lock_persistent_mode().is_err());
+
+    // The PPBs are frozen after reset until the password is given.
+    flash.reset().unwrap();
+    assert!(PersistentProtectionBits::is_locked(&mut flash).unwrap());
+    let mut security = Security::new(&mut flash);
+    security.unlock(&password).unwrap();
+    assert!(!PersistentProtectionBits::is_locked(&mut flash).unwrap());
+
+    flash.reset().unwrap();
+    let mut security = Security::new(&mut flash);
+    assert!(matches!(
+        security.unlock(&[0; 8]),
+        Err(Error::PasswordRejected)
+    ));
+    flash.reset().unwrap();
+    assert!(PersistentProtectionBits::is_locked(&mut flash).unwrap());
+    Security::new(&mut flash).unlock(&password).unwrap();
+
+    // A device which never completes the unlock times out if timeouts are enabled.
+    flash.set_timeouts(true);
+    flash.access.set_stuck_busy(true);
+    assert!(matches!(
+        Security::new(&mut flash).unlock(&password),
+        Err(Error::Timeout)
+    ));
+}
@@ -0,0 +1,281 @@
+//! Self-test for board bring-up and production test fixtures.
+//!
+//! `self_test()` checks a newly assembled board can reach its flash, and optionally
+//! that the memory array works, returning a `SelfTestReport` of each check rather than
+//! stopping at the first failure:
+//!
+//! * The ID is read twice, and must give a valid manufacturer ID both times, catching
+//!   missing parts and unreliable connections.
+//! * The SFDP parameters, if present, must parse, which also configures the geometry.
+//! * If a scratch sector is given, each bit of it is programmed and read back using
+//!   walking-one and walking-zero patterns, with the sector erased and checked in
+//!   between. Its original contents are then restored.
+//!
+//! The ID and SFDP checks do not modify the device. The pattern test erases the scratch
+//! sector, so its contents are lost if power fails before they are restored.
+
+use alloc::vec::Vec;
+
+use crate::{Error, Flash, FlashAccess, FlashID, FlashParams, Result};
+
+/// Outcome of one check of a self-test.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The check passed.
+    Passed,
+    /// The check was not run, or does not apply to the device.
+    Skipped,
+    /// The check failed.
+    Failed,
+}
+
+/// First byte of the scratch sector which did not read back as expected.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PatternFailure {
+    /// Address of the byte.
+    pub address: u32,
+    /// Value expected, 0xFF if the sector had just been erased.
+    pub wrote: u8,
+    /// Value read back.
+    pub read: u8,
+}
+
+/// Results of a self-test, created by `self_test()`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug)]
+pub struct SelfTestReport {
+    /// ID read from the device, if valid.
+    pub id: Option<FlashID>,
+    /// Whether a valid ID was read consistently.
+    pub id_check: TestOutcome,
+    /// SFDP parameters read from the device, if present.
+    pub params: Option<FlashParams>,
+    /// Whether the SFDP parameters parsed, skipped if the device has none.
+    pub sfdp_check: TestOutcome,
+    /// Whether the pattern test passed, skipped if no scratch sector was given or the
+    /// ID check failed.
+    pub pattern_check: TestOutcome,
+    /// First mismatch found by the pattern test, if it failed.
+    pub pattern_failure: Option<PatternFailure>,
+}
+
+impl SelfTestReport {
+    /// Check if no check failed.
+    pub fn passed(&self) -> bool {
+        ![self.id_check, self.sfdp_check, self.pattern_check].contains(&TestOutcome::Failed)
+    }
+}
+
+/// Run a self-test of `flash`, including a destructive pattern test of the sector at
+/// `scratch_sector` if given.
+///
+/// Failed checks are recorded in the report. The scratch sector must be aligned to the
+/// erase size, which must be known, or found from the SFDP parameters; errors in its
+/// configuration, and transport errors during the pattern test, are returned as errors
+/// after attempting to restore the sector.
+pub fn self_test<A: FlashAccess>(
+    flash: &mut Flash<'_, A>,
+    scratch_sector: Option<u32>,
+) -> Result<SelfTestReport>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    let mut report = SelfTestReport {
+        id: None,
+        id_check: TestOutcome::Failed,
+        params: None,
+        sfdp_check: TestOutcome::Skipped,
+        pattern_check: TestOutcome::Skipped,
+        pattern_failure: None,
+    };
+
+    match (flash.read_id(), flash.read_id()) {
+        (Ok(first), Ok(second)) if id_fields(&first) == id_fields(&second) => {
+            report.id = Some(first);
+            report.id_check = TestOutcome::Passed;
+        }
+        (Ok(first), Ok(second)) => {
+            log::error!(
+                "Self-test read inconsistent IDs {:?} and {:?}",
+                first,
+                second
+            );
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            log::error!("Self-test failed to read ID: {:?}", err);
+        }
+    }
+
+    match flash.read_params() {
+        Ok(Some(params)) => {
+            report.params = Some(params);
+            report.sfdp_check = TestOutcome::Passed;
+        }
+        Ok(None) => log::debug!("Self-test found no SFDP parameters"),
+        Err(err) => {
+            log::error!("Self-test failed to read SFDP parameters: {:?}", err);
+            report.sfdp_check = TestOutcome::Failed;
+        }
+    }
+
+    let Some(address) = scratch_sector else {
+        return Ok(report);
+    };
+    let Some(sector_size) = flash.erase_size() else {
+        log::error!("Erase size must be known to test a scratch sector");
+        return Err(Error::NoEraseInstruction);
+    };
+    if !(address as usize).is_multiple_of(sector_size) {
+        log::error!("Scratch sector 0x{:08X} is not sector aligned", address);
+        return Err(Error::InvalidAddress { address });
+    }
+    if report.id_check != TestOutcome::Passed {
+        log::warn!("Skipping pattern test as no device was identified");
+        return Ok(report);
+    }
+
+    let original = flash.read(address, sector_size)?;
+    let result = test_patterns(flash, address, sector_size);
+    flash.erase_sector(address)?;
+    if original.iter().any(|b| *b != 0xFF) {
+        flash.program_data(address, &original)?;
+    }
+    report.pattern_failure = result?;
+    report.pattern_check = match report.pattern_failure {
+        Some(failure) => {
+            log::error!("Self-test pattern test failed: {:?}", failure);
+            TestOutcome::Failed
+        }
+        None => TestOutcome::Passed,
+    };
+    Ok(report)
+}
+
+/// Get the fields of `id` compared between reads.
+fn id_fields(id: &FlashID) -> (u8, u8, u16, u8, u64) {
+    (
+        id.manufacturer_bank,
+        id.manufacturer_id,
+        id.device_id_long,
+        id.device_id_short,
+        id.unique_id,
+    )
+}
+
+/// Erase the sector at `address` and program each pattern into it in turn, returning the
+/// first byte which did not read back as expected.
+fn test_patterns<A: FlashAccess>(
+    flash: &mut Flash<'_, A>,
+    address: u32,
+    sector_size: usize,
+) -> Result<Option<PatternFailure>>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    for bit in 0..8 {
+        for walking_zero in [false, true] {
+            let pattern: Vec<u8> = (0..sector_size)
+                .map(|i| {
+                    let byte = 1u8.rotate_left((i + bit) as u32);
+                    if walking_zero {
+                        !byte
+                    } else {
+                        byte
+                    }
+                })
+                .collect();
+            flash.erase_sector(address)?;
+            let erased = flash.read(address, sector_size)?;
+            if let Some(failure) = compare(address, |_| 0xFF, &erased) {
+                return Ok(Some(failure));
+            }
+            flash.program_data(address, &pattern)?;
+            let read = flash.read(address, sector_size)?;
+            if let Some(failure) = compare(address, |i| pattern[i], &read) {
+                return Ok(Some(failure));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Find the first byte of `read`, from `address`, differing from `expected`.
+fn compare(address: u32, expected: impl Fn(usize) -> u8, read: &[u8]) -> Option<PatternFailure> {
+    read.iter()
+        .enumerate()
+        .find(|(i, byte)| **byte != expected(*i))
+        .map(|(i, byte)| PatternFailure {
+            address: address + i as u32,
+            wrote: expected(i),
+            read: *byte,
+        })
+}
+
+#[test]
+fn test_self_test() {
+    use crate::mock::MockFlash;
+
+    let mut mock = MockFlash::new(64 * 1024);
+    mock.memory_mut()[0x2000..0x3000].fill(0x5A);
+    mock.memory_mut()[0x3000] = 0x00;
+    assert!(matches!(
+        self_test(&mut Flash::new(&mut mock), Some(0x2000)),
+        Err(Error::NoEraseInstruction)
+    ));
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(self_test(&mut flash, Some(0x2100)).is_err());
+
+    let report = self_test(&mut flash, None).unwrap();
+    assert!(report.passed());
+    assert_eq!(report.id.unwrap().manufacturer_id, 0xEF);
+    assert_eq!(report.sfdp_check, TestOutcome::Skipped);
+    assert_eq!(report.pattern_check, TestOutcome::Skipped);
+
+    let report = self_test(&mut flash, Some(0x2000)).unwrap();
+    assert!(report.passed());
+    assert_eq!(report.pattern_check, TestOutcome::Passed);
+    drop(flash);
+    // The scratch sector is restored, and its neighbours are untouched.
+    assert!(mock.memory()[0x2000..0x3000].iter().all(|b| *b == 0x5A));
+    assert_eq!(mock.memory()[0x3000], 0x00);
+
+    // A stuck bit fails the pattern test.
+    mock.inject_bit_flip(0x2042, 0x04);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let report = self_test(&mut flash, Some(0x2000)).unwrap();
+    assert!(!report.passed());
+    assert_eq!(report.pattern_check, TestOutcome::Failed);
+    assert_eq!(
+        report.pattern_failure,
+        Some(PatternFailure {
+            address: 0x2042,
+            wrote: 0xFF,
+            read: 0xFB
+        })
+    );
+    drop(flash);
+    mock.clear_bit_flips();
+    // The flipped bit was also in the contents read before the test.
+    assert_eq!(mock.memory()[0x2042], 0x5E);
+    assert!(mock.memory()[0x2043..0x3000].iter().all(|b| *b == 0x5A));
+
+    // An SFDP header without basic flash parameters fails the SFDP check.
+    let mut sfdp = alloc::vec![0xFF; 16];
+    sfdp[..8].copy_from_slice(b"SFDP\x06\x01\x00\xFF");
+    sfdp[8..16].copy_from_slice(&[0x84, 0x00, 0x01, 0x02, 0x30, 0x00, 0x00, 0xFF]);
+    let mut mock = MockFlash::new(64 * 1024).with_sfdp(sfdp);
+    let report = self_test(&mut Flash::new(&mut mock), None).unwrap();
+    assert_eq!(report.id_check, TestOutcome::Passed);
+    assert_eq!(report.sfdp_check, TestOutcome::Failed);
+    assert!(!report.passed());
+
+    // No device answers with a valid ID, so the pattern test is skipped.
+    let mut mock = MockFlash::new(64 * 1024).with_jedec_id(0xFF, 0xFFFF);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let report = self_test(&mut flash, Some(0)).unwrap();
+    assert!(report.id.is_none());
+    assert_eq!(report.id_check, TestOutcome::Failed);
+    assert_eq!(report.pattern_check, TestOutcome::Skipped);
+}
@@ -0,0 +1,234 @@
+//! Small SPI EEPROM devices.
+//!
+//! Serial EEPROMs such as the Microchip 25LC and 25AA and ST M95 families share the basic
+//! SPI flash instruction set, but need no erase: each write replaces the bytes written,
+//! within a page of 16 to 256 bytes, after which the device is busy for a few milliseconds,
+//! as reported by the WIP bit of the status register. Devices of 256 bytes or less use
+//! 1-byte addresses, 4kbit devices also send the ninth address bit in the opcode,
+//! devices of up to 64kB use 2-byte addresses, and larger devices 3-byte addresses.
+//!
+//! `SerialEeprom` is usually created by `FlashBuilder::build_eeprom()`, so the same
+//! configuration code can describe both flash and EEPROM parts.
+
+use alloc::vec::Vec;
+
+use crate::commands::serial_memory::SerialMemoryOpCode;
+use crate::{Command, Error, FlashAccess, ReadStorage, Result, Storage};
+
+/// Status register bit set while a write is in progress.
+pub(crate) const STATUS_WIP: u8 = 0b0000_0001;
+
+/// Driver for a small SPI EEPROM.
+pub struct SerialEeprom<'a, A: FlashAccess> {
+    access: &'a mut A,
+    capacity: usize,
+    page_size: usize,
+    address_bytes: u8,
+}
+
+impl<'a, A: FlashAccess> SerialEeprom<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Create a driver for a device of `capacity` bytes with writes of up to `page_size` bytes.
+    ///
+    /// The number of address bytes is chosen from the capacity; use `FlashBuilder` to set
+    /// it explicitly and to check the parameters.
+    pub fn new(access: &'a mut A, capacity: usize, page_size: usize) -> Self {
+        let address_bytes = Self::default_address_bytes(capacity);
+        SerialEeprom {
+            access,
+            capacity,
+            page_size,
+            address_bytes,
+        }
+    }
+
+    /// Get the number of address bytes used by devices of `capacity` bytes.
+    pub fn default_address_bytes(capacity: usize) -> u8 {
+        match capacity {
+            0..=512 => 1,
+            513..=0x10000 => 2,
+            _ => 3,
+        }
+    }
+
+    pub(crate) fn set_address_bytes(&mut self, n: u8) {
+        self.address_bytes = n;
+    }
+
+    /// Get the capacity in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the write page size in bytes.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Get the number of address bytes sent.
+    pub fn address_bytes(&self) -> u8 {
+        self.address_bytes
+    }
+
+    /// Read the status register.
+    pub fn read_status(&mut self) -> Result<u8> {
+        Ok(self.exchange(SerialMemoryOpCode::ReadStatusRegister, &[], 1)?[0])
+    }
+
+    /// Write the status register, which holds the block protect bits, and wait for the
+    /// write to complete.
+    pub fn write_status(&mut self, status: u8) -> Result<()> {
+        self.send(SerialMemoryOpCode::WriteEnable, &[])?;
+        self.send(SerialMemoryOpCode::WriteStatusRegister, &[status])?;
+        self.wait_while_busy()
+    }
+
+    /// Check if a write is in progress.
+    pub fn is_busy(&mut self) -> Result<bool> {
+        Ok(self.read_status()? & STATUS_WIP != 0)
+    }
+
+    /// Poll the status register until no write is in progress.
+    pub fn wait_while_busy(&mut self) -> Result<()> {
+        while self.is_busy()? {}
+        Ok(())
+    }
+
+    /// Read `length` bytes starting at `address`.
+    pub fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.check_range(address, length)?;
+        let (opcode, data) = self.address(SerialMemoryOpCode::Read, address);
+        self.exchange(opcode, &data, length)
+    }
+
+    /// Write `data` starting at `address`, split into writes within each page,
+    /// waiting for each to complete.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len())?;
+        let mut offset = 0;
+        while offset < data.len() {
+            let page_address = address as usize + offset;
+            let chunk = usize::min(
+                self.page_size - page_address % self.page_size,
+                data.len() - offset,
+            );
+            log::trace!("Writing {} bytes at 0x{:06X}", chunk, page_address);
+            let (opcode, mut tx) = self.address(SerialMemoryOpCode::Write, page_address as u32);
+            tx.extend(&data[offset..offset + chunk]);
+            self.send(SerialMemoryOpCode::WriteEnable, &[])?;
+            self.send(opcode, &tx)?;
+            self.wait_while_busy()?;
+            offset += chunk;
+        }
+        Ok(())
+    }
+
+    /// Get the opcode and address bytes to send for `opcode` at `address`.
+    fn address(&self, opcode: SerialMemoryOpCode, address: u32) -> (SerialMemoryOpCode, Vec<u8>) {
+        let opcode = match opcode {
+            SerialMemoryOpCode::Read if self.address_bytes == 1 && address >= 0x100 => {
+                SerialMemoryOpCode::ReadUpper
+            }
+            SerialMemoryOpCode::Write if self.address_bytes == 1 && address >= 0x100 => {
+                SerialMemoryOpCode::WriteUpper
+            }
+            opcode => opcode,
+        };
+        let bytes = address.to_be_bytes();
+        (opcode, bytes[4 - self.address_bytes as usize..].to_vec())
+    }
+
+    fn check_range(&self, address: u32, length: usize) -> Result<()> {
+        if address as usize + length > self.capacity {
+            log::error!("Operation would exceed EEPROM capacity");
+            return Err(Error::InvalidAddress {
+                address: (address as usize + length) as u32,
+            });
+        }
+        Ok(())
+    }
+
+    fn exchange(
+        &mut self,
+        opcode: SerialMemoryOpCode,
+        data: &[u8],
+        nbytes: usize,
+    ) -> Result<Vec<u8>> {
+        let command = Command::SerialMemory(opcode);
+        let rx = self.access.exchange(command, data, nbytes)?;
+        Ok(rx[command.len() + data.len()..].to_vec())
+    }
+
+    fn send(&mut self, opcode: SerialMemoryOpCode, data: &[u8]) -> Result<()> {
+        self.access.write(Command::SerialMemory(opcode), data)?;
+        Ok(())
+    }
+}
+
+impl<'a, A: FlashAccess> ReadStorage for SerialEeprom<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        SerialEeprom::read(self, address, length)
+    }
+}
+
+/// EEPROM has no erase, so erasing writes 0xFF bytes, and programming overwrites any data.
+impl<'a, A: FlashAccess> Storage for SerialEeprom<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn erase_size(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.write(address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.write(address, &alloc::vec![0xFF; length])
+    }
+}
+
+#[test]
+fn test_serial_eeprom() {
+    let mut device = crate::mock::MockSerialMemory::new(32 * 1024, 2)
+        .with_page_size(64)
+        .with_busy_polls(2);
+    let mut eeprom = SerialEeprom::new(&mut device, 32 * 1024, 64);
+    assert_eq!(eeprom.address_bytes(), 2);
+    let data: Vec<u8> = (0..200).collect();
+    eeprom.write(0x1030, &data).unwrap();
+    assert_eq!(eeprom.read(0x1030, 200).unwrap(), data);
+    assert!(eeprom.read(0x7FFF, 2).is_err());
+    eeprom.erase_data(0x1040, 2).unwrap();
+    assert_eq!(eeprom.read(0x103F, 4).unwrap(), [15, 0xFF, 0xFF, 18]);
+    assert_eq!(device.busy_violations(), 0);
+}
+
+#[test]
+fn test_serial_eeprom_a8() {
+    let mut device = crate::mock::MockSerialMemory::new(512, 1)
+        .with_page_size(16)
+        .with_busy_polls(2);
+    let mut eeprom = crate::FlashBuilder::new()
+        .capacity(512)
+        .page_size(16)
+        .build_eeprom(&mut device)
+        .unwrap();
+    assert_eq!(eeprom.address_bytes(), 1);
+    eeprom.write(0xF8, &[0xA5; 16]).unwrap();
+    assert_eq!(eeprom.read(0xF7, 18).unwrap()[1..17], [0xA5; 16]);
+    assert_eq!(eeprom.read(0x100, 8).unwrap(), [0xA5; 8]);
+    assert_eq!(eeprom.read(0, 1).unwrap(), [0]);
+    assert_eq!(device.memory()[0xF8..0x108], [0xA5; 16]);
+    assert_eq!(device.busy_violations(), 0);
+}
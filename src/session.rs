@@ -0,0 +1,310 @@
+//! Streaming programming sessions for flash algorithms.
+//!
+//! Debug probes program external flash through a flash algorithm, such as a CMSIS-Pack
+//! algorithm or a probe-rs target, which is sent the image a buffer at a time, so must
+//! erase sectors as the data reaches them and program it page by page without holding
+//! the whole image. `ProgrammingSession` implements this sequence for a region of a
+//! `Flash`: `begin()` checks the region, `write()` streams data in, erasing each sector
+//! before the first page programmed into it, and `finish()` programs any final partial
+//! page and verifies the CRC-32 of the region as read back from the device.
+//!
+//! Any failure leaves the session in `SessionState::Failed`, after which it accepts no
+//! more data. `resume_offset()` then gives the offset of the first sector which may not
+//! be fully programmed, and a new session started from it using `resume()`, for example
+//! after a probe reconnects, erases that sector again and continues from there.
+//!
+//! The region need not end on a sector boundary: data after it in its last sector is
+//! read before that sector is erased and programmed back afterwards, so is lost only if
+//! power fails in between.
+
+use alloc::vec::Vec;
+
+use crate::crc::Crc32;
+use crate::geometry::Unit;
+use crate::{Error, Flash, FlashAccess, Geometry, Result};
+
+/// State of a `ProgrammingSession`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    /// The session is accepting data.
+    Programming,
+    /// All data was programmed and verified, giving a CRC-32 of the region.
+    Finished { crc: u32 },
+    /// An erase, program, or verification failed, so the session accepts no more data.
+    Failed,
+}
+
+/// A programming session for a region of a `Flash`.
+pub struct ProgrammingSession<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    geometry: Geometry,
+    address: u32,
+    length: usize,
+    /// Number of bytes programmed, always a multiple of the page size until finished.
+    programmed: usize,
+    /// Offset of the end of the sectors erased by this session.
+    erased: usize,
+    /// Data received but not yet programmed, less than a page.
+    pending: Vec<u8>,
+    crc: Crc32,
+    state: SessionState,
+}
+
+impl<'f, 'a, A: FlashAccess> ProgrammingSession<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Begin programming `length` bytes starting at `address`, which must be aligned to
+    /// the erase size.
+    ///
+    /// Nothing is erased until data is written. The geometry of `flash` must be known.
+    pub fn begin(flash: &'f mut Flash<'a, A>, address: u32, length: usize) -> Result<Self> {
+        Self::resume(flash, address, length, 0)
+    }
+
+    /// Resume programming `length` bytes starting at `address` from `offset`, as returned
+    /// by `resume_offset()` of an earlier session for the same region.
+    ///
+    /// Data before `offset` is kept, and read back to continue the CRC-32, so the next
+    /// data written must start at `offset`.
+    pub fn resume(
+        flash: &'f mut Flash<'a, A>,
+        address: u32,
+        length: usize,
+        offset: usize,
+    ) -> Result<Self> {
+        let Some(geometry) = flash.geometry() else {
+            log::error!("Geometry must be known to start a programming session");
+            return Err(Error::InvalidConfiguration);
+        };
+        let end = address as usize + length;
+        if !geometry.contains(&(address..end as u32)) {
+            log::error!("Programming session would exceed capacity");
+            return Err(Error::InvalidAddress {
+                address: end as u32,
+            });
+        }
+        let start = address + offset as u32;
+        if offset > length
+            || !geometry.is_aligned(address, Unit::Sector)
+            || !geometry.is_aligned(start, Unit::Sector)
+        {
+            log::error!(
+                "Programming session at 0x{:08X} is not sector aligned",
+                start
+            );
+            return Err(Error::InvalidAddress { address: start });
+        }
+        let mut crc = Crc32::new();
+        for chunk in (address..start).step_by(geometry.sector_size) {
+            let size = usize::min(geometry.sector_size, (start - chunk) as usize);
+            crc.update(&flash.read(chunk, size)?);
+        }
+        log::debug!(
+            "Programming session of {} bytes at 0x{:08X} from offset {}",
+            length,
+            address,
+            offset
+        );
+        Ok(ProgrammingSession {
+            flash,
+            geometry,
+            address,
+            length,
+            programmed: offset,
+            erased: offset,
+            pending: Vec::with_capacity(geometry.page_size),
+            crc,
+            state: SessionState::Programming,
+        })
+    }
+
+    /// Get the state of the session.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Get the number of bytes received so far, including those before any resume offset.
+    pub fn written(&self) -> usize {
+        self.programmed + self.pending.len()
+    }
+
+    /// Get the offset within the region from which a new session may be resumed: the
+    /// start of the first sector which may not be fully programmed.
+    pub fn resume_offset(&self) -> usize {
+        self.programmed - self.programmed % self.geometry.sector_size
+    }
+
+    /// Program the next `data` of the region, erasing sectors as required.
+    ///
+    /// Full pages are programmed as they become available, and the rest held until more
+    /// data is written or the session is finished. Returns `InvalidAddress` if the data
+    /// would extend past the end of the region.
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.check_programming()?;
+        if self.written() + data.len() > self.length {
+            log::error!("Programming session data exceeds its length");
+            return Err(Error::InvalidAddress {
+                address: (self.address as usize + self.written() + data.len()) as u32,
+            });
+        }
+        self.crc.update(data);
+        let page_size = self.geometry.page_size;
+        let mut data = data;
+        while !data.is_empty() {
+            let take = usize::min(page_size - self.pending.len(), data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() == page_size {
+                self.program_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Program any final partial page, then verify the CRC-32 of the region read back from
+    /// the device against that of the data written, and against `expected` if given.
+    ///
+    /// Returns `ChecksumMismatch` for an incomplete image, as `DualBank` and `Ota` do,
+    /// if fewer than `length` bytes were written, in which case more data may still be
+    /// written, or if verification fails, which also fails the session.
+    pub fn finish(&mut self, expected: Option<u32>) -> Result<u32> {
+        self.check_programming()?;
+        if self.written() != self.length {
+            log::error!(
+                "Programming session finished after {} of {} bytes",
+                self.written(),
+                self.length
+            );
+            return Err(Error::ChecksumMismatch {
+                address: self.address,
+            });
+        }
+        if !self.pending.is_empty() {
+            self.program_pending()?;
+        }
+        let crc = self.crc.finish();
+        let result = self.flash.crc32(self.address, self.length);
+        let read_back = self.fail_on_error(result)?;
+        if read_back != crc || expected.is_some_and(|expected| expected != crc) {
+            log::error!(
+                "Programmed CRC 0x{:08X} read back as 0x{:08X}, expected {:08X?}",
+                crc,
+                read_back,
+                expected
+            );
+            self.state = SessionState::Failed;
+            return Err(Error::ChecksumMismatch {
+                address: self.address,
+            });
+        }
+        log::debug!("Programming session finished with CRC 0x{:08X}", crc);
+        self.state = SessionState::Finished { crc };
+        Ok(crc)
+    }
+
+    fn check_programming(&self) -> Result<()> {
+        if self.state != SessionState::Programming {
+            log::error!("Programming session is {:?}", self.state);
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(())
+    }
+
+    /// Erase the sectors the pending data reaches, then program it.
+    fn program_pending(&mut self) -> Result<()> {
+        let start = self.address + self.programmed as u32;
+        let end = start + self.pending.len() as u32;
+        while self.address as usize + self.erased < end as usize {
+            let sector = self.address + self.erased as u32;
+            let result = self.erase_keeping_tail(sector);
+            self.fail_on_error(result)?;
+            self.erased += self.geometry.sector_size;
+        }
+        let result = self.flash.page_program(start, &self.pending);
+        self.fail_on_error(result)?;
+        self.programmed += self.pending.len();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Erase `sector`, restoring any data after the end of the region within it.
+    fn erase_keeping_tail(&mut self, sector: u32) -> Result<()> {
+        let end = self.address as usize + self.length;
+        let sector_end = sector as usize + self.geometry.sector_size;
+        let tail = if end < sector_end {
+            Some(self.flash.read(end as u32, sector_end - end)?)
+        } else {
+            None
+        };
+        self.flash.erase_sector(sector)?;
+        if let Some(tail) = tail {
+            self.flash.program_data(end as u32, &tail)?;
+        }
+        Ok(())
+    }
+
+    /// Fail the session if `result` is an error.
+    fn fail_on_error<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(err) = &result {
+            log::error!("Programming session failed: {:?}", err);
+            self.state = SessionState::Failed;
+        }
+        result
+    }
+}
+
+#[test]
+fn test_programming_session() {
+    use crate::mock::MockFlash;
+
+    let image: Vec<u8> = (0..10_000u32).map(|x| (x * 7) as u8).collect();
+    let expected = crate::crc::crc32(&image);
+    let mut mock = MockFlash::new(64 * 1024);
+    mock.memory_mut().fill(0x00);
+    // Lose power part way through the second sector.
+    mock.set_power_loss_after(Some(5000));
+    let mut flash = crate::mock::configured_flash(&mut mock);
+
+    assert!(ProgrammingSession::begin(&mut flash, 0x1100, 16).is_err());
+    assert!(ProgrammingSession::begin(&mut flash, 0xF000, 0x2000).is_err());
+
+    let mut session = ProgrammingSession::begin(&mut flash, 0x1000, image.len()).unwrap();
+    let mut result = Ok(());
+    for chunk in image.chunks(1000) {
+        result = session.write(chunk);
+        if result.is_err() {
+            break;
+        }
+    }
+    assert!(result.is_err());
+    assert_eq!(session.state(), SessionState::Failed);
+    assert!(session.write(&[0]).is_err());
+    let offset = session.resume_offset();
+    assert_eq!(offset, 4096);
+    drop(session);
+    drop(flash);
+
+    // Resume after power is restored, from the start of the interrupted sector.
+    mock.restore_power();
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    let mut session = ProgrammingSession::resume(&mut flash, 0x1000, image.len(), offset).unwrap();
+    assert!(matches!(
+        session.finish(None),
+        Err(Error::ChecksumMismatch { address: 0x1000 })
+    ));
+    for chunk in image[offset..].chunks(300) {
+        session.write(chunk).unwrap();
+    }
+    assert!(session.write(&[0]).is_err());
+    assert_eq!(session.finish(Some(expected)).unwrap(), expected);
+    assert_eq!(session.state(), SessionState::Finished { crc: expected });
+    drop(flash);
+    assert_eq!(mock.memory()[0x1000..0x1000 + image.len()], image[..]);
+    // Data outside the region is kept, including the rest of its final sector.
+    assert!(mock.memory()[0x1000 + image.len()..0x4000]
+        .iter()
+        .all(|b| *b == 0x00));
+    assert_eq!(mock.memory()[0x0FFF], 0x00);
+    assert_eq!(mock.memory()[0x4000], 0x00);
+}
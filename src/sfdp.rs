@@ -13,7 +13,13 @@ pub(crate) struct SFDPHeader {
 impl SFDPHeader {
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
         log::debug!("Parsing SFDP header from data: {:X?}", data);
-        if &data[0..4] != b"SFDP" {
+        if data.len() < 8 {
+            log::error!(
+                "Did not read enough SFDP bytes for header: got {}",
+                data.len()
+            );
+            Err(Error::InvalidSFDPHeader)
+        } else if &data[0..4] != b"SFDP" {
             log::error!("Did not read expected SFDP signature");
             Err(Error::InvalidSFDPHeader)
         } else if data[7] != 0xFF {
@@ -38,7 +44,8 @@ impl SFDPHeader {
                 Err(Error::InvalidSFDPHeader)
             } else {
                 let params = data[8..]
-                    .chunks(8)
+                    .chunks_exact(8)
+                    .take(nph)
                     .map(SFDPParameterHeader::from_bytes)
                     .collect();
                 Ok(SFDPHeader {
@@ -52,16 +59,32 @@ impl SFDPHeader {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub(crate) struct SFDPParameterHeader {
+/// SFDP parameter header, describing one parameter table.
+///
+/// Returned by `Flash::sfdp_tables()`, so tables the parser does not understand
+/// can still be found and read using `Flash::read_sfdp_raw()`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SFDPParameterHeader {
+    /// Length of the table in DWORDs.
     pub plen: usize,
+    /// Table major version.
     pub major: u8,
+    /// Table minor version.
     pub minor: u8,
+    /// Parameter ID, with the MSB from the ID MSB field, such as 0xFF00 for the
+    /// basic flash parameter table, or a manufacturer ID in the LSB for vendor tables.
     pub parameter_id: u16,
+    /// Parameter table pointer, the SFDP address of the table.
     pub ptp: u32,
 }
 
 impl SFDPParameterHeader {
+    /// Get the length of the table in bytes.
+    pub fn len_bytes(&self) -> usize {
+        self.plen * 4
+    }
+
     fn from_bytes(data: &[u8]) -> Self {
         log::debug!("Reading SFDP parameter header from: {:X?}", data);
         let parameter_id = u16::from_be_bytes([data[7], data[0]]);
@@ -95,6 +118,7 @@ impl SFDPParameterHeader {
 /// operation are parsed.
 ///
 /// Fields are taken from JESD216D-01, supporting parameter versions up to 1.7.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub struct FlashParams {
     /// Parameter header major version field.
@@ -125,6 +149,15 @@ pub struct FlashParams {
     /// Newer memories use `page_size`.
     pub legacy_byte_write_granularity: bool,
 
+    /// Dual Output Fast Read (1-1-2) instruction, if supported.
+    pub fast_read_112: Option<SFDPFastRead>,
+    /// Dual I/O Fast Read (1-2-2) instruction, if supported.
+    pub fast_read_122: Option<SFDPFastRead>,
+    /// Quad Output Fast Read (1-1-4) instruction, if supported.
+    pub fast_read_114: Option<SFDPFastRead>,
+    /// Quad I/O Fast Read (1-4-4) instruction, if supported.
+    pub fast_read_144: Option<SFDPFastRead>,
+
     /// Erase instructions.
     ///
     /// Up to four erase instructions may be available,
@@ -154,11 +187,19 @@ pub struct FlashParams {
     /// If true, the device may be reset using instruction 0x66 followed by 0x99.
     pub reset_inst_66_99: Option<bool>,
 
+    /// Quad enable bit location and the instructions to set it.
+    pub quad_enable: Option<SFDPQuadEnable>,
+
     /// Status register 1 volatility and write-enable instruction.
     pub status_1_vol: Option<SFDPStatus1Volatility>,
+
+    /// Supported native 4-byte address instructions, from the separate
+    /// 4-Byte Address Instruction Table if present.
+    pub four_byte_instructions: Option<SFDPFourByteInstructions>,
 }
 
 /// SFDP Address Bytes field.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub enum SFDPAddressBytes {
     /// Three-byte only addressing.
@@ -183,8 +224,51 @@ impl SFDPAddressBytes {
     }
 }
 
-/// SFDP Erase Instruction.
+/// Bitfield extraction helper macro.
+///
+/// `bits!(word, length, offset)` extracts `length` number of bits at offset `offset`.
+macro_rules! bits {
+    ($d:expr, $n:expr, $o:expr) => {
+        ($d & (((1 << $n) - 1) << $o)) >> $o
+    };
+}
+
+/// SFDP Fast Read Instruction.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
+pub struct SFDPFastRead {
+    /// Opcode for read instruction.
+    pub opcode: u8,
+    /// Number of dummy clock cycles (wait states) after the address and mode bits.
+    pub dummy_cycles: u8,
+    /// Number of clock cycles used to send mode bits.
+    pub mode_clocks: u8,
+}
+
+impl SFDPFastRead {
+    /// Parse the 16-bit fast read field used in DWORDs 3 and 4.
+    fn from_bits(bits: u32) -> Self {
+        SFDPFastRead {
+            opcode: bits!(bits, 8, 8) as u8,
+            dummy_cycles: bits!(bits, 5, 0) as u8,
+            mode_clocks: bits!(bits, 3, 5) as u8,
+        }
+    }
+}
+
+impl core::fmt::Display for SFDPFastRead {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "Opcode 0x{:02X}: {} dummy cycles, {} mode clocks",
+            self.opcode, self.dummy_cycles, self.mode_clocks
+        )
+    }
+}
+
+/// SFDP Erase Instruction.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct SFDPEraseInst {
     /// Opcode for erase instruction.
     pub opcode: u8,
@@ -209,6 +293,57 @@ impl core::fmt::Display for SFDPEraseInst {
     }
 }
 
+/// SFDP Quad Enable Requirements field.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SFDPQuadEnable {
+    /// The device has no QE bit, and quad I/O is always available.
+    NoQuadEnable,
+    /// QE is bit 1 of status register 2, written as the second byte of
+    /// Write Status Register 1 (0x01); writing only one byte clears status register 2.
+    Sr2Bit1ClearedBySr1Write,
+    /// QE is bit 6 of status register 1, written with Write Status Register 1 (0x01).
+    Sr1Bit6,
+    /// QE is bit 7 of status register 2, read with 0x3F and written with 0x3E.
+    Sr2Bit7,
+    /// QE is bit 1 of status register 2, written as the second byte of
+    /// Write Status Register 1 (0x01).
+    Sr2Bit1,
+    /// QE is bit 1 of status register 2, read with 0x35 and written as the second
+    /// byte of Write Status Register 1 (0x01).
+    Sr2Bit1Read35,
+    /// QE is bit 1 of status register 2, read with 0x35 and written with 0x31.
+    Sr2Bit1Write31,
+    /// Reserved quad enable requirement.
+    Reserved,
+}
+
+/// Method used to poll whether the device is busy during program and erase operations.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SFDPBusyPolling {
+    /// Poll bit 0 of status register 1 (0x05), which is 1 while busy.
+    StatusRegister,
+    /// Poll bit 7 of the flag status register (0x70), which is 0 while busy.
+    FlagStatusRegister,
+}
+
+impl SFDPQuadEnable {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0b000 => SFDPQuadEnable::NoQuadEnable,
+            0b001 => SFDPQuadEnable::Sr2Bit1ClearedBySr1Write,
+            0b010 => SFDPQuadEnable::Sr1Bit6,
+            0b011 => SFDPQuadEnable::Sr2Bit7,
+            0b100 => SFDPQuadEnable::Sr2Bit1,
+            0b101 => SFDPQuadEnable::Sr2Bit1Read35,
+            0b110 => SFDPQuadEnable::Sr2Bit1Write31,
+            _ => SFDPQuadEnable::Reserved,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub enum SFDPStatus1Volatility {
     /// Status register 1 is non-volatile, powers up to its last state, write-enable with 0x06.
@@ -249,6 +384,7 @@ impl SFDPStatus1Volatility {
 /// Struct of timing information from JESD216A-compliant tables.
 ///
 /// Note that erase instruction timing is stored inside the respective erase instructions.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug)]
 pub struct SFDPTiming {
     /// Typical time to erase the entire chip, if known.
@@ -269,15 +405,6 @@ pub struct SFDPTiming {
     pub page_prog_time_max: Duration,
 }
 
-/// Bitfield extraction helper macro.
-///
-/// `bits!(word, length, offset)` extracts `length` number of bits at offset `offset`.
-macro_rules! bits {
-    ($d:expr, $n:expr, $o:expr) => {
-        ($d & (((1 << $n) - 1) << $o)) >> $o
-    };
-}
-
 impl FlashParams {
     pub fn from_bytes(major: u8, minor: u8, data: &[u8]) -> Result<Self> {
         log::debug!(
@@ -286,7 +413,7 @@ impl FlashParams {
         );
 
         // Check we have enough data.
-        if data.len() % 4 != 0 {
+        if !data.len().is_multiple_of(4) {
             log::error!("SFPD data is not a multiple of 4 bytes.");
             return Err(Error::InvalidSFDPParams);
         } else if data.len() < 9 * 4 {
@@ -295,7 +422,7 @@ impl FlashParams {
         } else if major != 1 {
             log::error!("Only SFPD major version 1 is supported.");
             return Err(Error::InvalidSFDPParams);
-        } else if minor > 5 && data.len() < 16 * 4 {
+        } else if minor >= 5 && data.len() < 16 * 4 {
             log::error!("SFPD data is not long enough for version >= 1.5.");
             return Err(Error::InvalidSFDPParams);
         }
@@ -307,7 +434,7 @@ impl FlashParams {
         }
 
         // Parse the first 9 DWORDs, which must always be available if SFDP is supported.
-        let mut params = Self::read_jesd216(major, minor, &dwords);
+        let mut params = Self::read_jesd216(major, minor, &dwords)?;
 
         // 1.5: JESD216A adds DWORDs 10-16.
         if minor >= 5 {
@@ -326,6 +453,16 @@ impl FlashParams {
         (self.density / 8) as usize
     }
 
+    /// Get the busy polling method indicated by the status register polling field,
+    /// preferring the flag status register when both methods are supported.
+    pub fn busy_polling(&self) -> Option<SFDPBusyPolling> {
+        match (self.busy_poll_flag, self.busy_poll_status) {
+            (Some(true), _) => Some(SFDPBusyPolling::FlagStatusRegister),
+            (_, Some(true)) => Some(SFDPBusyPolling::StatusRegister),
+            _ => None,
+        }
+    }
+
     /// Get the smallest erase granularity and its opcode.
     pub fn sector_erase(&self) -> Option<(usize, u8)> {
         let mut size = u32::MAX;
@@ -345,7 +482,7 @@ impl FlashParams {
     }
 
     /// Read the legacy information from JESD216 (DWORDs 1-9) and create a new FlashParams object.
-    fn read_jesd216(major: u8, minor: u8, dwords: &[u32]) -> FlashParams {
+    fn read_jesd216(major: u8, minor: u8, dwords: &[u32]) -> Result<FlashParams> {
         // 1st DWORD
         let address_bytes = SFDPAddressBytes::from_bits(bits!(dwords[0], 2, 17));
         let legacy_4kb_erase_inst = bits!(dwords[0], 8, 8) as u8;
@@ -362,10 +499,29 @@ impl FlashParams {
         let density = if dwords[1] >> 31 == 0 {
             (dwords[1] as u64) + 1
         } else {
-            1u64 << (dwords[1] & 0x7FFF_FFFF)
+            match 1u64.checked_shl(dwords[1] & 0x7FFF_FFFF) {
+                Some(density) => density,
+                None => {
+                    log::error!("Invalid SFDP density 0x{:08X}", dwords[1]);
+                    return Err(Error::InvalidSFDPParams);
+                }
+            }
+        };
+
+        // 3rd and 4th DWORDs: dual and quad fast reads, with support indicated in DWORD 1.
+        let fast_read = |supported: u32, bits: u32| {
+            if supported == 1 {
+                Some(SFDPFastRead::from_bits(bits))
+            } else {
+                None
+            }
         };
+        let fast_read_112 = fast_read(bits!(dwords[0], 1, 16), bits!(dwords[3], 16, 0));
+        let fast_read_122 = fast_read(bits!(dwords[0], 1, 20), bits!(dwords[3], 16, 16));
+        let fast_read_144 = fast_read(bits!(dwords[0], 1, 21), bits!(dwords[2], 16, 0));
+        let fast_read_114 = fast_read(bits!(dwords[0], 1, 22), bits!(dwords[2], 16, 16));
 
-        // DWORDS 3,4, 5, 6, and 7 relate to multiple I/O and are skipped.
+        // DWORDS 5, 6, and 7 relate to 2-2-2 and 4-4-4 modes and are skipped.
 
         // 8th and 9th DWORD
         let mut erase_insts = [None; 4];
@@ -378,7 +534,7 @@ impl FlashParams {
             if opcode != 0 {
                 erase_insts[0] = Some(SFDPEraseInst {
                     opcode,
-                    size: 1 << erase_size_1,
+                    size: Self::erase_size(erase_size_1)?,
                     time_typ: None,
                     time_max: None,
                 });
@@ -389,7 +545,7 @@ impl FlashParams {
             if opcode != 0 {
                 erase_insts[1] = Some(SFDPEraseInst {
                     opcode,
-                    size: 1 << erase_size_2,
+                    size: Self::erase_size(erase_size_2)?,
                     time_typ: None,
                     time_max: None,
                 });
@@ -400,7 +556,7 @@ impl FlashParams {
             if opcode != 0 {
                 erase_insts[2] = Some(SFDPEraseInst {
                     opcode,
-                    size: 1 << erase_size_3,
+                    size: Self::erase_size(erase_size_3)?,
                     time_typ: None,
                     time_max: None,
                 });
@@ -411,7 +567,7 @@ impl FlashParams {
             if opcode != 0 {
                 erase_insts[3] = Some(SFDPEraseInst {
                     opcode,
-                    size: 1 << erase_size_4,
+                    size: Self::erase_size(erase_size_4)?,
                     time_typ: None,
                     time_max: None,
                 });
@@ -420,7 +576,7 @@ impl FlashParams {
 
         // Return a FlashParams with the legacy information set and further information
         // cleared, which can be filled in if additional DWORDs are available.
-        FlashParams {
+        Ok(FlashParams {
             version_major: major,
             version_minor: minor,
             address_bytes,
@@ -430,6 +586,10 @@ impl FlashParams {
             legacy_volatile_write_en_inst,
             legacy_block_protect_volatile,
             legacy_byte_write_granularity,
+            fast_read_112,
+            fast_read_122,
+            fast_read_114,
+            fast_read_144,
             erase_insts,
             timing: None,
             page_size: None,
@@ -437,8 +597,18 @@ impl FlashParams {
             busy_poll_status: None,
             reset_inst_f0: None,
             reset_inst_66_99: None,
+            quad_enable: None,
             status_1_vol: None,
-        }
+            four_byte_instructions: None,
+        })
+    }
+
+    /// Convert SFDP erase size field, a power of two, into the erase size in bytes.
+    fn erase_size(bits: u32) -> Result<u32> {
+        1u32.checked_shl(bits).ok_or_else(|| {
+            log::error!("Invalid SFDP erase size 2^{}", bits);
+            Error::InvalidSFDPParams
+        })
     }
 
     /// Parse JESD216A DWORDs 10 to 16.
@@ -503,7 +673,8 @@ impl FlashParams {
         self.busy_poll_flag = Some((status_reg_poll & 0b00_0010) != 0);
         self.busy_poll_status = Some((status_reg_poll & 0b00_0001) != 0);
 
-        // 15th DWORD skipped: multiple I/O.
+        // 15th DWORD: quad enable requirements; the remaining multiple I/O fields are skipped.
+        self.quad_enable = Some(SFDPQuadEnable::from_bits(bits!(dwords[14], 3, 20)));
 
         // 16th DWORD
         let reset = bits!(dwords[15], 6, 8);
@@ -618,6 +789,19 @@ impl core::fmt::Display for FlashParams {
             "    Writes have byte granularity: {}",
             self.legacy_byte_write_granularity
         )?;
+        writeln!(f, "  Fast read instructions:")?;
+        for (name, inst) in [
+            ("1-1-2", self.fast_read_112),
+            ("1-2-2", self.fast_read_122),
+            ("1-1-4", self.fast_read_114),
+            ("1-4-4", self.fast_read_144),
+        ] {
+            if let Some(inst) = inst {
+                writeln!(f, "    {}: {}", name, inst)?;
+            } else {
+                writeln!(f, "    {}: Not present", name)?;
+            }
+        }
         writeln!(f, "  Erase instructions:")?;
         for i in 0..4 {
             if let Some(inst) = self.erase_insts[i] {
@@ -664,9 +848,297 @@ impl core::fmt::Display for FlashParams {
         if let Some(reset_inst_66_99) = self.reset_inst_66_99 {
             writeln!(f, "  Reset using opcodes 0x66, 0x99: {}", reset_inst_66_99)?;
         }
+        if let Some(quad_enable) = self.quad_enable {
+            writeln!(f, "  Quad enable requirement: {:?}", quad_enable)?;
+        }
         if let Some(status_1_vol) = self.status_1_vol {
             writeln!(f, "  Status register 1 volatility: {:?}", status_1_vol)?;
         }
+        if let Some(four_byte) = self.four_byte_instructions {
+            let opcodes: Vec<u8> = four_byte.opcodes().collect();
+            writeln!(f, "  4-byte address instructions: {:02X?}", opcodes)?;
+        }
         Ok(())
     }
 }
+
+/// SFDP 4-Byte Address Instruction Table.
+///
+/// Lists the instructions which take a 4-byte address regardless of the device's
+/// address mode, so devices larger than 16MiB can be accessed without switching
+/// address mode.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SFDPFourByteInstructions {
+    /// Read Data (0x13).
+    pub read: bool,
+    /// FastRead (0x0C).
+    pub fast_read: bool,
+    /// Dual Output Fast Read, 1-1-2 (0x3C).
+    pub fast_read_112: bool,
+    /// Dual I/O Fast Read, 1-2-2 (0xBC).
+    pub fast_read_122: bool,
+    /// Quad Output Fast Read, 1-1-4 (0x6C).
+    pub fast_read_114: bool,
+    /// Quad I/O Fast Read, 1-4-4 (0xEC).
+    pub fast_read_144: bool,
+    /// PageProgram (0x12).
+    pub page_program: bool,
+    /// Quad Input Page Program, 1-1-4 (0x34).
+    pub page_program_114: bool,
+    /// Quad I/O Page Program, 1-4-4 (0x3E).
+    pub page_program_144: bool,
+    /// DTR FastRead (0x0E).
+    pub fast_read_dtr: bool,
+    /// DTR Dual I/O Fast Read (0xBE).
+    pub fast_read_122_dtr: bool,
+    /// DTR Quad I/O Fast Read (0xEE).
+    pub fast_read_144_dtr: bool,
+    /// 4-byte address opcodes for each of the erase instructions in
+    /// `FlashParams::erase_insts`, if supported.
+    pub erase_opcodes: [Option<u8>; 4],
+}
+
+impl SFDPFourByteInstructions {
+    /// Table parameter ID, as found in the SFDP parameter headers.
+    pub(crate) const PARAMETER_ID: u16 = 0xFF84;
+
+    /// Parse the 4-Byte Address Instruction Table from `data`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        log::debug!("Parsing 4BAIT from data: {:X?}", data);
+        if data.len() < 8 {
+            log::error!("4BAIT too short: got {} bytes", data.len());
+            return Err(Error::InvalidSFDPParams);
+        }
+        let support = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let bit = |n: u32| bits!(support, 1, n) == 1;
+        let mut erase_opcodes = [None; 4];
+        for (i, opcode) in erase_opcodes.iter_mut().enumerate() {
+            if bit(9 + i as u32) && data[4 + i] != 0xFF {
+                *opcode = Some(data[4 + i]);
+            }
+        }
+        Ok(SFDPFourByteInstructions {
+            read: bit(0),
+            fast_read: bit(1),
+            fast_read_112: bit(2),
+            fast_read_122: bit(3),
+            fast_read_114: bit(4),
+            fast_read_144: bit(5),
+            page_program: bit(6),
+            page_program_114: bit(7),
+            page_program_144: bit(8),
+            fast_read_dtr: bit(13),
+            fast_read_122_dtr: bit(14),
+            fast_read_144_dtr: bit(15),
+            erase_opcodes,
+        })
+    }
+
+    /// Get the opcodes of all supported read and program instructions.
+    pub fn opcodes(&self) -> impl Iterator<Item = u8> + '_ {
+        [
+            (self.read, 0x13),
+            (self.fast_read, 0x0C),
+            (self.fast_read_112, 0x3C),
+            (self.fast_read_122, 0xBC),
+            (self.fast_read_114, 0x6C),
+            (self.fast_read_144, 0xEC),
+            (self.page_program, 0x12),
+            (self.page_program_114, 0x34),
+            (self.page_program_144, 0x3E),
+            (self.fast_read_dtr, 0x0E),
+            (self.fast_read_122_dtr, 0xBE),
+            (self.fast_read_144_dtr, 0xEE),
+        ]
+        .into_iter()
+        .filter(|(supported, _)| *supported)
+        .map(|(_, opcode)| opcode)
+        .chain(self.erase_opcodes.into_iter().flatten())
+    }
+
+    /// Check if the 4-byte address instruction `opcode` is supported.
+    pub fn supports(&self, opcode: u8) -> bool {
+        self.opcodes().any(|op| op == opcode)
+    }
+}
+
+/// SFDP Sector Map configuration detection command.
+///
+/// Each command reads one byte, of which the bit selected by `mask` gives one bit
+/// of the configuration ID.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SFDPConfigDetect {
+    /// Opcode of the read instruction.
+    pub opcode: u8,
+    /// Address sent with the instruction, if any.
+    pub address: Option<u32>,
+    /// Number of address bytes, or None if the current address mode is used.
+    pub address_bytes: Option<u8>,
+    /// Dummy cycles between the address and the data.
+    pub dummy_cycles: u8,
+    /// Mask selecting the configuration bit in the byte read.
+    pub mask: u8,
+}
+
+/// SFDP Sector Map region: the size of a region and which erase types may be used in it.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SFDPSectorRegion {
+    /// Size of the region in bytes.
+    pub size: u32,
+    /// Supported erase types, with bit n set if erase type n+1 of `FlashParams::erase_insts`
+    /// may be used in this region.
+    pub erase_types: u8,
+}
+
+/// SFDP Sector Map Parameter Table.
+///
+/// Devices with non-uniform sector layouts, such as those with 4kB parameter sectors
+/// at the top or bottom of memory and 64kB sectors elsewhere, describe each region of
+/// memory with the erase types it supports. Devices with configurable layouts list a
+/// map for each configuration, and the commands which detect the current configuration.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SFDPSectorMap {
+    /// Configuration detection commands, most significant configuration ID bit first.
+    pub detect: Vec<SFDPConfigDetect>,
+    /// Region maps for each configuration ID, in order of increasing address.
+    pub maps: Vec<(u8, Vec<SFDPSectorRegion>)>,
+}
+
+impl SFDPSectorMap {
+    /// Table parameter ID, as found in the SFDP parameter headers.
+    pub(crate) const PARAMETER_ID: u16 = 0xFF81;
+
+    /// Parse the Sector Map Parameter Table from `data`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        log::debug!("Parsing SFDP sector map from data: {:X?}", data);
+        let dwords: Vec<u32> = data
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let mut map = SFDPSectorMap::default();
+        let mut idx = 0;
+        loop {
+            let header = *dwords.get(idx).ok_or(Error::InvalidSFDPParams)?;
+            let last = bits!(header, 1, 0) == 1;
+            if bits!(header, 1, 1) == 0 {
+                // Configuration detection command descriptor.
+                let address = *dwords.get(idx + 1).ok_or(Error::InvalidSFDPParams)?;
+                let address_bytes = match bits!(header, 2, 22) {
+                    0b00 => Some(0),
+                    0b01 => Some(3),
+                    0b10 => Some(4),
+                    _ => None,
+                };
+                map.detect.push(SFDPConfigDetect {
+                    opcode: bits!(header, 8, 8) as u8,
+                    address: (address_bytes != Some(0)).then_some(address),
+                    address_bytes,
+                    dummy_cycles: bits!(header, 4, 16) as u8,
+                    mask: bits!(header, 8, 24) as u8,
+                });
+                idx += 2;
+            } else {
+                // Configuration map descriptor.
+                let id = bits!(header, 8, 8) as u8;
+                let nregions = bits!(header, 8, 16) as usize + 1;
+                let regions = dwords
+                    .get(idx + 1..idx + 1 + nregions)
+                    .ok_or(Error::InvalidSFDPParams)?
+                    .iter()
+                    .map(|r| SFDPSectorRegion {
+                        size: (bits!(r, 24, 8) + 1) * 256,
+                        erase_types: bits!(r, 4, 0) as u8,
+                    })
+                    .collect();
+                map.maps.push((id, regions));
+                idx += 1 + nregions;
+            }
+            if last {
+                break;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Get the regions for configuration `id`, if present.
+    pub fn regions(&self, id: u8) -> Option<&[SFDPSectorRegion]> {
+        self.maps
+            .iter()
+            .find(|(map_id, _)| *map_id == id)
+            .map(|(_, regions)| regions.as_slice())
+    }
+}
+
+#[test]
+fn test_four_byte_instructions() {
+    // Read, FastRead, PageProgram, and erase types 1 and 3 supported.
+    let table = [0b0100_0011, 0b0000_1010, 0, 0, 0x21, 0xFF, 0xDC, 0xFF];
+    let four_byte = SFDPFourByteInstructions::from_bytes(&table).unwrap();
+    assert!(four_byte.read && four_byte.fast_read && four_byte.page_program);
+    assert!(!four_byte.fast_read_144);
+    assert_eq!(
+        four_byte.erase_opcodes,
+        [Some(0x21), None, Some(0xDC), None]
+    );
+    assert!(four_byte.supports(0x12) && four_byte.supports(0xDC));
+    assert!(!four_byte.supports(0xEC));
+    assert!(SFDPFourByteInstructions::from_bytes(&table[..4]).is_err());
+}
+
+#[test]
+fn test_sector_map() {
+    // One detection command, then maps for configurations 0 and 1: with 4kB sectors
+    // in the first 32kB, then uniform 64kB sectors.
+    let dwords: [u32; 8] = [
+        0x0800_6500 | 0b01 << 22 | 8 << 16,
+        0,
+        0b10 | 0x01 << 16,
+        ((32 * 1024 / 256 - 1) << 8) | 0b0001,
+        ((8160 * 1024 / 256 - 1) << 8) | 0b0100,
+        0b11 | 0x01 << 8,
+        ((8192 * 1024 / 256 - 1) << 8) | 0b0100,
+        0,
+    ];
+    let data: Vec<u8> = dwords.iter().flat_map(|d| d.to_le_bytes()).collect();
+    let map = SFDPSectorMap::from_bytes(&data[..28]).unwrap();
+    assert_eq!(map.detect.len(), 1);
+    assert_eq!((map.detect[0].opcode, map.detect[0].mask), (0x65, 0x08));
+    assert_eq!(map.detect[0].dummy_cycles, 8);
+    let regions = map.regions(0).unwrap();
+    assert_eq!(regions.len(), 2);
+    assert_eq!(
+        (regions[0].size, regions[0].erase_types),
+        (32 * 1024, 0b0001)
+    );
+    assert_eq!(map.regions(1).unwrap()[0].size, 8192 * 1024);
+    assert!(SFDPSectorMap::from_bytes(&data[..12]).is_err());
+}
+
+#[test]
+fn test_malformed_sfdp() {
+    // Truncated or corrupt headers are rejected rather than panicking.
+    assert!(SFDPHeader::from_bytes(&[]).is_err());
+    assert!(SFDPHeader::from_bytes(b"SFDP").is_err());
+    assert!(SFDPHeader::from_bytes(b"SFDP\x06\x01\x01\xFF\x00").is_err());
+    let header = SFDPHeader::from_bytes(b"SFDP\x06\x01\x00\xFF\x00\x06\x01\x10\x30\x00\x00\xFF");
+    assert_eq!(header.unwrap().params.len(), 1);
+
+    // Version 1.5 tables must contain 16 DWORDs.
+    assert!(FlashParams::from_bytes(1, 5, &[0; 9 * 4]).is_err());
+
+    // Out-of-range density and erase sizes are rejected.
+    let mut table = [0u8; 9 * 4];
+    table[4..8].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+    assert!(FlashParams::from_bytes(1, 0, &table).is_err());
+    let mut table = [0u8; 9 * 4];
+    table[28] = 40;
+    table[29] = 0x20;
+    assert!(FlashParams::from_bytes(1, 0, &table).is_err());
+    table[28] = 12;
+    let params = FlashParams::from_bytes(1, 0, &table).unwrap();
+    assert_eq!(params.erase_insts[0].unwrap().size, 4096);
+}
@@ -0,0 +1,260 @@
+//! Sharing one storage device between several owners.
+//!
+//! A flash often holds data for several subsystems, such as a filesystem and an OTA
+//! updater, each of which wants to own a `Storage`. Rather than partitioning the
+//! device into exclusively borrowed windows, a `SharedStorage` handle may be copied to
+//! each owner, and locks the device for the duration of each operation it makes.
+//!
+//! The lock is provided by the `Lock` trait, implemented for `RefCell` for owners on a
+//! single thread or interrupt priority. Owners in different interrupt priorities need a
+//! lock which also excludes them from each other, such as a
+//! `critical_section::Mutex<RefCell<_>>`, for which `Lock` is implemented with the
+//! `critical-section` feature. Other locks, such as a blocking RTOS mutex, can be used
+//! by implementing `Lock` for a wrapper around them.
+//!
+//! Async tasks can instead share the storage through an `AsyncSharedStorage`, with the
+//! `embassy-sync` feature, which waits for an `embassy_sync` async `Mutex` rather than
+//! blocking while another task holds it.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::{ReadStorage, Result, Storage};
+
+/// Exclusive access to a value, held for the duration of a closure.
+pub trait Lock {
+    /// Type of the value protected by the lock.
+    type Target;
+
+    /// Run `f` with exclusive access to the value.
+    fn lock<R>(&self, f: impl FnOnce(&mut Self::Target) -> R) -> R;
+}
+
+/// Panics if the value is already borrowed, which can only happen if an operation is
+/// started through a handle while another is in progress on the same thread, such as
+/// from within a `ProgressSink`.
+impl<T> Lock for RefCell<T> {
+    type Target = T;
+
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.borrow_mut())
+    }
+}
+
+/// Takes a critical section for the duration of `f`, so that owners in different
+/// interrupt priorities cannot interrupt each other's operations. Panics if the value is
+/// already borrowed, as for `RefCell`.
+#[cfg(feature = "critical-section")]
+impl<T> Lock for critical_section::Mutex<RefCell<T>> {
+    type Target = T;
+
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.borrow_ref_mut(cs)))
+    }
+}
+
+/// Handle to storage shared through the lock `L`, which may be copied to each owner.
+pub struct SharedStorage<'a, L> {
+    lock: &'a L,
+}
+
+impl<'a, L: Lock> SharedStorage<'a, L> {
+    /// Create a handle to the storage protected by `lock`.
+    pub fn new(lock: &'a L) -> Self {
+        SharedStorage { lock }
+    }
+
+    /// Run `f` with exclusive access to the storage, for operations beyond the
+    /// storage traits, or several operations which must not be interleaved with others.
+    pub fn lock<R>(&self, f: impl FnOnce(&mut L::Target) -> R) -> R {
+        self.lock.lock(f)
+    }
+}
+
+impl<'a, L> Clone for SharedStorage<'a, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, L> Copy for SharedStorage<'a, L> {}
+
+impl<'a, L: Lock> ReadStorage for SharedStorage<'a, L>
+where
+    L::Target: ReadStorage,
+{
+    fn capacity(&self) -> Option<usize> {
+        self.lock.lock(|storage| storage.capacity())
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.lock.lock(|storage| storage.read(address, length))
+    }
+}
+
+impl<'a, L: Lock> Storage for SharedStorage<'a, L>
+where
+    L::Target: Storage,
+{
+    fn erase_size(&self) -> Option<usize> {
+        self.lock.lock(|storage| storage.erase_size())
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.lock
+            .lock(|storage| storage.program_data(address, data))
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.lock
+            .lock(|storage| storage.erase_data(address, length))
+    }
+}
+
+/// Handle to storage shared between async tasks through an `embassy_sync` async
+/// `Mutex`, which may be copied to each task.
+///
+/// The methods of the storage traits are provided as async methods, each waiting for
+/// the mutex before running the operation on the storage. The operation itself still
+/// blocks, as the storage traits are blocking.
+#[cfg(feature = "embassy-sync")]
+pub struct AsyncSharedStorage<'a, M: embassy_sync::blocking_mutex::raw::RawMutex, T> {
+    mutex: &'a embassy_sync::mutex::Mutex<M, T>,
+}
+
+#[cfg(feature = "embassy-sync")]
+impl<'a, M: embassy_sync::blocking_mutex::raw::RawMutex, T> AsyncSharedStorage<'a, M, T> {
+    /// Create a handle to the storage protected by `mutex`.
+    pub fn new(mutex: &'a embassy_sync::mutex::Mutex<M, T>) -> Self {
+        AsyncSharedStorage { mutex }
+    }
+
+    /// Wait for exclusive access to the storage, for operations beyond the storage
+    /// traits, or several operations which must not be interleaved with others.
+    pub async fn lock(&self) -> embassy_sync::mutex::MutexGuard<'a, M, T> {
+        self.mutex.lock().await
+    }
+
+    /// Get the capacity of the storage, in bytes, as `ReadStorage::capacity()`.
+    pub async fn capacity(&self) -> Option<usize>
+    where
+        T: ReadStorage,
+    {
+        self.mutex.lock().await.capacity()
+    }
+
+    /// Read `length` bytes starting at `address`, as `ReadStorage::read()`.
+    pub async fn read(&self, address: u32, length: usize) -> Result<Vec<u8>>
+    where
+        T: ReadStorage,
+    {
+        self.mutex.lock().await.read(address, length)
+    }
+
+    /// Get the size of the smallest erasable region, as `Storage::erase_size()`.
+    pub async fn erase_size(&self) -> Option<usize>
+    where
+        T: Storage,
+    {
+        self.mutex.lock().await.erase_size()
+    }
+
+    /// Program `data` starting at `address`, as `Storage::program_data()`.
+    pub async fn program_data(&self, address: u32, data: &[u8]) -> Result<()>
+    where
+        T: Storage,
+    {
+        self.mutex.lock().await.program_data(address, data)
+    }
+
+    /// Erase `length` bytes starting at `address`, as `Storage::erase_data()`.
+    pub async fn erase_data(&self, address: u32, length: usize) -> Result<()>
+    where
+        T: Storage,
+    {
+        self.mutex.lock().await.erase_data(address, length)
+    }
+}
+
+#[cfg(feature = "embassy-sync")]
+impl<'a, M: embassy_sync::blocking_mutex::raw::RawMutex, T> Clone for AsyncSharedStorage<'a, M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "embassy-sync")]
+impl<'a, M: embassy_sync::blocking_mutex::raw::RawMutex, T> Copy for AsyncSharedStorage<'a, M, T> {}
+
+#[test]
+fn test_shared_storage() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let flash = crate::mock::configured_flash(&mut mock);
+    let flash = RefCell::new(flash);
+
+    let mut fs = SharedStorage::new(&flash);
+    let mut ota = fs;
+    fs.program_data(0x100, &[1, 2, 3]).unwrap();
+    ota.erase_data(0x8000, 4096).unwrap();
+    ota.program_data(0x8000, &[4]).unwrap();
+    assert_eq!(ota.read(0x100, 3).unwrap(), [1, 2, 3]);
+    assert_eq!(fs.read(0x8000, 2).unwrap(), [4, 0xFF]);
+    assert_eq!(fs.capacity(), Some(64 * 1024));
+    assert_eq!(ota.lock(|flash| flash.page_size()), Some(256));
+}
+
+#[cfg(feature = "critical-section")]
+#[test]
+fn test_shared_storage_critical_section() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let flash = crate::mock::configured_flash(&mut mock);
+    let flash = critical_section::Mutex::new(RefCell::new(flash));
+
+    let mut fs = SharedStorage::new(&flash);
+    let mut ota = fs;
+    fs.program_data(0x100, &[1, 2, 3]).unwrap();
+    ota.erase_data(0x8000, 4096).unwrap();
+    assert_eq!(ota.read(0x100, 3).unwrap(), [1, 2, 3]);
+    assert_eq!(fs.read(0x8000, 1).unwrap(), [0xFF]);
+    assert_eq!(ota.lock(|flash| flash.page_size()), Some(256));
+}
+
+#[cfg(feature = "embassy-sync")]
+#[test]
+fn test_async_shared_storage() {
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let flash = crate::mock::configured_flash(&mut mock);
+    let flash = embassy_sync::mutex::Mutex::<NoopRawMutex, _>::new(flash);
+
+    let fs = AsyncSharedStorage::new(&flash);
+    let ota = fs;
+    block_on(fs.program_data(0x100, &[1, 2, 3])).unwrap();
+    block_on(ota.erase_data(0x8000, 4096)).unwrap();
+    block_on(ota.program_data(0x8000, &[4])).unwrap();
+    assert_eq!(block_on(ota.read(0x100, 3)).unwrap(), [1, 2, 3]);
+    assert_eq!(block_on(fs.read(0x8000, 2)).unwrap(), [4, 0xFF]);
+    assert_eq!(block_on(fs.capacity()), Some(64 * 1024));
+    assert_eq!(block_on(fs.erase_size()), Some(4096));
+
+    // A task waits while another holds the storage.
+    let guard = block_on(fs.lock());
+    let mut cx = Context::from_waker(core::task::Waker::noop());
+    let mut read = core::pin::pin!(ota.read(0x100, 1));
+    assert!(read.as_mut().poll(&mut cx).is_pending());
+    drop(guard);
+    assert_eq!(block_on(read).unwrap(), [1]);
+}
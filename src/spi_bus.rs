@@ -0,0 +1,231 @@
+//! `embedded-hal` SPI bus transport with a separately driven chip select.
+//!
+//! Most HALs implement `SpiDevice`, which drives CS itself, but bit-banged buses and buses
+//! shared using unusual CS arrangements may only provide a `SpiBus` and an `OutputPin`.
+//! `SpiBusAccess` implements `FlashAccess` for such a pair, asserting CS around each
+//! transfer. Flashes require CS to stay high for a minimum time between instructions,
+//! tSHSL, which is typically 10 to 50ns; buses slow enough to meet this by themselves may
+//! skip the delay, while others provide a `DelayNs` to wait after each transfer.
+//! The same `DelayNs` also implements `FlashAccess::delay()`, used for power-up and
+//! program/erase waits, so without one those waits return immediately.
+
+use alloc::vec::Vec;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+use crate::board::pin_error;
+use crate::{Command, Error, FlashAccess, Result};
+
+/// `DelayNs` which does not wait, used by `SpiBusAccess` when no deselect delay is set.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// `FlashAccess` over a `SpiBus`, driving the `cs` chip select pin itself.
+pub struct SpiBusAccess<B, CS, D = NoDelay> {
+    bus: B,
+    cs: CS,
+    delay: D,
+    deselect_ns: u32,
+}
+
+impl<B: SpiBus, CS: OutputPin> SpiBusAccess<B, CS> {
+    /// Create a transport using `bus`, with `cs` as the chip select.
+    ///
+    /// CS is driven high, deselecting the flash.
+    pub fn new(bus: B, mut cs: CS) -> Result<Self> {
+        cs.set_high().map_err(pin_error)?;
+        Ok(SpiBusAccess {
+            bus,
+            cs,
+            delay: NoDelay,
+            deselect_ns: 0,
+        })
+    }
+}
+
+impl<B: SpiBus, CS: OutputPin, D: DelayNs> SpiBusAccess<B, CS, D> {
+    /// Wait at least `deselect_ns` nanoseconds using `delay` after each transfer,
+    /// to meet the minimum CS high time.
+    pub fn with_deselect_delay<D2: DelayNs>(
+        self,
+        delay: D2,
+        deselect_ns: u32,
+    ) -> SpiBusAccess<B, CS, D2> {
+        SpiBusAccess {
+            bus: self.bus,
+            cs: self.cs,
+            delay,
+            deselect_ns,
+        }
+    }
+
+    /// Release the bus, chip select pin, and delay.
+    pub fn release(self) -> (B, CS, D) {
+        (self.bus, self.cs, self.delay)
+    }
+
+    /// Run `transfer` with CS asserted, then deassert CS and wait the deselect time.
+    fn transaction(&mut self, transfer: impl FnOnce(&mut B) -> Result<()>) -> Result<()> {
+        self.cs.set_low().map_err(pin_error)?;
+        let result = transfer(&mut self.bus);
+        self.cs.set_high().map_err(pin_error)?;
+        if self.deselect_ns > 0 {
+            self.delay.delay_ns(self.deselect_ns);
+        }
+        result
+    }
+}
+
+fn spi_error<E: embedded_hal::spi::Error>(err: E) -> Error {
+    log::error!("SPI bus error: {:?}", err.kind());
+    #[cfg(feature = "std")]
+    return Error::Access(anyhow::anyhow!("SPI bus error: {}", err.kind()));
+    #[cfg(not(feature = "std"))]
+    return Error::Access;
+}
+
+impl<B: SpiBus, CS: OutputPin, D: DelayNs> FlashAccess for SpiBusAccess<B, CS, D> {
+    type Error = Error;
+
+    fn write(&mut self, command: Command, data: &[u8]) -> Result<()> {
+        self.transaction(|bus| {
+            bus.write(&command.to_array()).map_err(spi_error)?;
+            bus.write(data).map_err(spi_error)?;
+            bus.flush().map_err(spi_error)
+        })
+    }
+
+    fn exchange(&mut self, command: Command, data: &[u8], nbytes: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(command.len() + data.len() + nbytes);
+        buf.extend_from_slice(&command.to_array());
+        buf.extend_from_slice(data);
+        buf.resize(buf.len() + nbytes, 0);
+        self.transaction(|bus| {
+            bus.transfer_in_place(&mut buf).map_err(spi_error)?;
+            bus.flush().map_err(spi_error)
+        })?;
+        Ok(buf)
+    }
+
+    fn delay(&mut self, duration: core::time::Duration) {
+        let mut us = duration.as_micros();
+        while us > 0 {
+            let step = us.min(u32::MAX as u128) as u32;
+            self.delay.delay_us(step);
+            us -= step as u128;
+        }
+    }
+}
+
+#[test]
+fn test_spi_bus_access() {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    /// Bytes clocked out in each transfer, and whether CS is asserted.
+    #[derive(Default)]
+    struct Wire {
+        transfers: Vec<Vec<u8>>,
+        selected: bool,
+        delay_ns: u64,
+    }
+
+    struct TestBus(Rc<RefCell<Wire>>);
+    struct TestCs(Rc<RefCell<Wire>>);
+    struct TestDelay(Rc<RefCell<Wire>>);
+
+    impl embedded_hal::spi::ErrorType for TestBus {
+        type Error = core::convert::Infallible;
+    }
+
+    /// Responds to Read JEDEC ID, and reads zeros otherwise.
+    impl SpiBus for TestBus {
+        fn read(&mut self, words: &mut [u8]) -> core::result::Result<(), Self::Error> {
+            words.fill(0);
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> core::result::Result<(), Self::Error> {
+            let mut wire = self.0.borrow_mut();
+            assert!(wire.selected);
+            wire.transfers.last_mut().unwrap().extend_from_slice(words);
+            Ok(())
+        }
+
+        fn transfer(
+            &mut self,
+            read: &mut [u8],
+            write: &[u8],
+        ) -> core::result::Result<(), Self::Error> {
+            let mut buf = write.to_vec();
+            self.transfer_in_place(&mut buf)?;
+            read.copy_from_slice(&buf[..read.len()]);
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> core::result::Result<(), Self::Error> {
+            self.write(words)?;
+            let id = [0x00, 0xEF, 0x40, 0x18];
+            let jedec = words[0] == 0x9F;
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = if jedec { *id.get(i).unwrap_or(&0) } else { 0 };
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl embedded_hal::digital::ErrorType for TestCs {
+        type Error = core::convert::Infallible;
+    }
+
+    impl OutputPin for TestCs {
+        fn set_low(&mut self) -> core::result::Result<(), Self::Error> {
+            let mut wire = self.0.borrow_mut();
+            assert!(!wire.selected);
+            wire.selected = true;
+            wire.transfers.push(Vec::new());
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> core::result::Result<(), Self::Error> {
+            self.0.borrow_mut().selected = false;
+            Ok(())
+        }
+    }
+
+    impl DelayNs for TestDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            let mut wire = self.0.borrow_mut();
+            assert!(!wire.selected);
+            wire.delay_ns += ns as u64;
+        }
+    }
+
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    let mut access = SpiBusAccess::new(TestBus(wire.clone()), TestCs(wire.clone()))
+        .unwrap()
+        .with_deselect_delay(TestDelay(wire.clone()), 50);
+    let mut flash = crate::Flash::new(&mut access);
+    assert_eq!(flash.read_jedec_id().unwrap(), (0, 0xEF, 0x4018));
+    flash.write_enable().unwrap();
+    assert_eq!(
+        wire.borrow().transfers,
+        [alloc::vec![0x9F, 0, 0, 0], alloc::vec![0x06]]
+    );
+    assert_eq!(wire.borrow().delay_ns, 100);
+    assert!(!wire.borrow().selected);
+
+    // Waits requested by the driver use the same delay.
+    access.delay(core::time::Duration::from_millis(3));
+    assert_eq!(wire.borrow().delay_ns, 100 + 3_000_000);
+}
@@ -1,4 +1,5 @@
 /// Status Register 1
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug)]
 pub struct StatusRegister1(pub u8);
 
@@ -37,6 +38,7 @@ impl StatusRegister1 {
 }
 
 /// Status Register 2
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug)]
 pub struct StatusRegister2(pub u8);
 
@@ -45,13 +47,48 @@ impl StatusRegister2 {
     pub fn get_cmp(&self) -> bool {
         self.0 & 0b0100_0000 != 0
     }
+
+    /// Set CMP (protection complement) bit.
+    pub fn set_cmp(&mut self, cmp: bool) {
+        self.0 &= 0b1011_1111;
+        self.0 |= (cmp as u8) << 6;
+    }
+
+    /// Get LB (security register lock) bit of security register `index`, from 1 to 3.
+    pub fn get_security_lock(&self, index: u8) -> bool {
+        self.0 & (1 << (index + 2)) != 0
+    }
+
+    /// Set LB bit of security register `index`, from 1 to 3.
+    ///
+    /// LB bits are one-time programmable, so once written the register is locked forever.
+    pub fn set_security_lock(&mut self, index: u8) {
+        self.0 |= 1 << (index + 2);
+    }
 }
 
 /// Status Register 3
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug)]
 pub struct StatusRegister3(pub u8);
 
 impl StatusRegister3 {
+    /// Get ADS bit, set while a Winbond device is in 4-byte address mode.
+    pub fn get_ads(&self) -> bool {
+        self.0 & 0b0000_0001 != 0
+    }
+
+    /// Get ADP bit, which makes a Winbond device power up in 4-byte address mode if set.
+    pub fn get_adp(&self) -> bool {
+        self.0 & 0b0000_0010 != 0
+    }
+
+    /// Set ADP bit.
+    pub fn set_adp(&mut self, adp: bool) {
+        self.0 &= 0b1111_1101;
+        self.0 |= (adp as u8) << 1;
+    }
+
     /// Get WPS (write protect selection) bit.
     ///
     /// This bit is non-standard and its functionality
@@ -69,3 +106,198 @@ impl StatusRegister3 {
         self.0 |= (wps as u8) << 2;
     }
 }
+
+/// Configuration Register 1 of Spansion/Infineon S25FL-S and S25FS-S devices.
+///
+/// CR1 is read with 0x35 and written as the second data byte of Write Registers (0x01),
+/// sharing the instructions of status register 2 on other devices, or through RDAR
+/// and WRAR on devices with a register file.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug)]
+pub struct ConfigRegister1(pub u8);
+
+impl ConfigRegister1 {
+    /// Get FREEZE bit, which locks the block protection bits until power-off.
+    pub fn get_freeze(&self) -> bool {
+        self.0 & 0b0000_0001 != 0
+    }
+
+    /// Get QUAD bit, which enables quad I/O.
+    pub fn get_quad(&self) -> bool {
+        self.0 & 0b0000_0010 != 0
+    }
+
+    /// Set QUAD bit.
+    pub fn set_quad(&mut self, quad: bool) {
+        self.0 &= 0b1111_1101;
+        self.0 |= (quad as u8) << 1;
+    }
+
+    /// Get TBPARM bit, which places the 4kB parameter sectors at the top of memory
+    /// if set, or at the bottom if clear.
+    ///
+    /// This bit is one-time programmable.
+    pub fn get_tbparm(&self) -> bool {
+        self.0 & 0b0000_0100 != 0
+    }
+
+    /// Set TBPARM bit.
+    pub fn set_tbparm(&mut self, tbparm: bool) {
+        self.0 &= 0b1111_1011;
+        self.0 |= (tbparm as u8) << 2;
+    }
+
+    /// Get BPNV bit, which makes the block protection bits volatile if set.
+    ///
+    /// This bit is one-time programmable.
+    pub fn get_bpnv(&self) -> bool {
+        self.0 & 0b0000_1000 != 0
+    }
+
+    /// Set BPNV bit.
+    pub fn set_bpnv(&mut self, bpnv: bool) {
+        self.0 &= 0b1111_0111;
+        self.0 |= (bpnv as u8) << 3;
+    }
+
+    /// Get TBPROT bit, which makes block protection start at the bottom of memory
+    /// if set, or at the top if clear.
+    ///
+    /// This bit is one-time programmable.
+    pub fn get_tbprot(&self) -> bool {
+        self.0 & 0b0010_0000 != 0
+    }
+
+    /// Set TBPROT bit.
+    pub fn set_tbprot(&mut self, tbprot: bool) {
+        self.0 &= 0b1101_1111;
+        self.0 |= (tbprot as u8) << 5;
+    }
+
+    /// Get LC (latency code) bits, which select the dummy cycles of the fast read
+    /// instructions for the clock frequency in use.
+    pub fn get_latency_code(&self) -> u8 {
+        self.0 >> 6
+    }
+
+    /// Set LC (latency code) bits.
+    ///
+    /// Panics if `lc` is greater than 3.
+    pub fn set_latency_code(&mut self, lc: u8) {
+        assert!(lc <= 3, "set_latency_code: lc must be at most 3");
+        self.0 &= 0b0011_1111;
+        self.0 |= lc << 6;
+    }
+}
+
+/// Flag Status Register of Micron devices, read with 0x70.
+///
+/// The error bits stay set after a failed operation until cleared with Clear Flag Status
+/// Register (0x50).
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug)]
+pub struct FlagStatusRegister(pub u8);
+
+impl FlagStatusRegister {
+    /// Get the ready bit, which is set when no program or erase is in progress.
+    pub fn get_ready(&self) -> bool {
+        self.0 & 0b1000_0000 != 0
+    }
+
+    /// Get the erase suspend bit.
+    pub fn get_erase_suspended(&self) -> bool {
+        self.0 & 0b0100_0000 != 0
+    }
+
+    /// Get the erase error bit, set when an erase failed or was attempted on protected memory.
+    pub fn get_erase_error(&self) -> bool {
+        self.0 & 0b0010_0000 != 0
+    }
+
+    /// Get the program error bit, set when a program failed or was attempted on protected
+    /// memory.
+    pub fn get_program_error(&self) -> bool {
+        self.0 & 0b0001_0000 != 0
+    }
+
+    /// Get the program suspend bit.
+    pub fn get_program_suspended(&self) -> bool {
+        self.0 & 0b0000_0100 != 0
+    }
+
+    /// Get the protection error bit, set when a program or erase targeted protected memory.
+    pub fn get_protection_error(&self) -> bool {
+        self.0 & 0b0000_0010 != 0
+    }
+
+    /// Get the addressing bit, set when 4-byte addresses are selected.
+    pub fn get_four_byte(&self) -> bool {
+        self.0 & 0b0000_0001 != 0
+    }
+}
+
+/// Bank Address Register of Spansion/Infineon S25FL-S devices.
+///
+/// The bank register is read with BRRD (0x16) and written with BRWR (0x17). It provides
+/// the address bits above A23 for instructions with 3-byte addresses, and its EXTADD bit
+/// switches the device to 4-byte addresses instead.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug)]
+pub struct BankRegister(pub u8);
+
+impl BankRegister {
+    /// Get BA bits, the address bits above A23.
+    pub fn get_bank(&self) -> u8 {
+        self.0 & 0b0111_1111
+    }
+
+    /// Set BA bits.
+    ///
+    /// Panics if `bank` is greater than 127.
+    pub fn set_bank(&mut self, bank: u8) {
+        assert!(bank <= 0x7F, "set_bank: bank must be at most 127");
+        self.0 &= 0b1000_0000;
+        self.0 |= bank;
+    }
+
+    /// Get EXTADD bit, which selects 4-byte addresses for all instructions if set.
+    pub fn get_extadd(&self) -> bool {
+        self.0 & 0b1000_0000 != 0
+    }
+
+    /// Set EXTADD bit.
+    pub fn set_extadd(&mut self, extadd: bool) {
+        self.0 &= 0b0111_1111;
+        self.0 |= (extadd as u8) << 7;
+    }
+}
+
+/// How instructions with 3-byte addresses reach memory above 16MB.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AddressExtension {
+    /// Memory above 16MB is only reachable with 4-byte addresses.
+    #[default]
+    None,
+    /// The Spansion bank register provides the upper address bits.
+    BankRegister,
+    /// The Winbond Extended Address Register provides the upper address bits.
+    ExtendedAddressRegister,
+}
+
+/// Whether a status register write updates the non-volatile register bits.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StatusWriteMode {
+    /// Update the non-volatile bits, which are retained through power cycles but have
+    /// limited write endurance, enabling the write with Write Enable (0x06), or the
+    /// instruction given by SFDP for status register 1.
+    #[default]
+    NonVolatile,
+    /// Update only the volatile copy of the bits, which is lost at power-off, enabling
+    /// the write with Write Enable for Volatile Status Register (0x50).
+    ///
+    /// Registers accessed through a `RegisterFile` are written with WRAR, whose address
+    /// selects volatile or non-volatile registers, so are not affected by this mode.
+    Volatile,
+}
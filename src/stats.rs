@@ -0,0 +1,387 @@
+//! Erase and program statistics for estimating flash endurance.
+//!
+//! Each sector of a flash device survives a limited number of erase cycles, typically
+//! 100,000. `StatsFlash` wraps any `Storage` and counts the erases and programs reaching
+//! each sector, so long-running products can estimate the remaining endurance, and
+//! relocate frequently rewritten data away from sectors nearing their rating before
+//! they fail.
+//!
+//! Counters are kept in RAM and lost on reset unless persisted. `save()` appends them to
+//! a `Journal` on other storage, and `restore()` loads the latest copy after a reset. A
+//! device of many sectors needs more than one record, so the counters are split across
+//! as many records as needed, each holding:
+//!
+//! | Bytes         | Content                                          |
+//! |---------------|--------------------------------------------------|
+//! | 4             | Number of sectors `n`, little-endian             |
+//! | 4             | Index of the first sector in the record          |
+//! | 8 × `k`       | Erase then program count of `k` sectors, 32-bit  |
+//!
+//! Only a complete run of records, with consecutive sectors from the first to the last,
+//! is loaded, so a save interrupted by power loss leaves the previous copy in use. The
+//! journal should be dedicated to the counters, and large enough for two copies plus one
+//! sector. To keep the journal on the counted device itself, encode the counters using
+//! `to_records()` and append them to a journal mounted on the `StatsFlash`, then decode
+//! them using `load_records()`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Error, Journal, ReadStorage, Result, Storage};
+
+/// Size of a record's sector count and first sector index.
+const RECORD_HEADER_LEN: usize = 8;
+/// Size of each sector's counters in a record.
+const SECTOR_RECORD_LEN: usize = 8;
+
+/// Erase and program counts of one sector.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectorStats {
+    /// Number of times the sector was erased.
+    pub erases: u32,
+    /// Number of program operations reaching the sector.
+    pub programs: u32,
+}
+
+/// `Storage` counting erases and programs per sector.
+pub struct StatsFlash<F> {
+    storage: F,
+    sector_size: usize,
+    sectors: Vec<SectorStats>,
+}
+
+impl<F: Storage> StatsFlash<F> {
+    /// Count operations on `storage`, in sectors of its erase size, starting from zero.
+    ///
+    /// The capacity and erase size of `storage` must both be known.
+    pub fn new(storage: F) -> Result<Self> {
+        let (Some(capacity), Some(sector_size)) = (storage.capacity(), storage.erase_size()) else {
+            log::error!("Statistics require a known capacity and erase size");
+            return Err(Error::InvalidConfiguration);
+        };
+        Ok(StatsFlash {
+            storage,
+            sector_size,
+            sectors: vec![SectorStats::default(); capacity.div_ceil(sector_size)],
+        })
+    }
+
+    /// Get a reference to the wrapped storage.
+    pub fn storage(&self) -> &F {
+        &self.storage
+    }
+
+    /// Release the wrapped storage, discarding the counters.
+    pub fn release(self) -> F {
+        self.storage
+    }
+
+    /// Get the size of each counted sector in bytes.
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// Get the counts of the sector containing `address`, if within the capacity.
+    pub fn sector_stats(&self, address: u32) -> Option<SectorStats> {
+        self.sectors
+            .get(address as usize / self.sector_size)
+            .copied()
+    }
+
+    /// Get the address and counts of each sector, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, SectorStats)> + '_ {
+        self.sectors
+            .iter()
+            .enumerate()
+            .map(|(sector, stats)| ((sector * self.sector_size) as u32, *stats))
+    }
+
+    /// Get the total number of sector erases.
+    pub fn total_erases(&self) -> u64 {
+        self.sectors.iter().map(|stats| stats.erases as u64).sum()
+    }
+
+    /// Get the total number of sector program operations.
+    pub fn total_programs(&self) -> u64 {
+        self.sectors.iter().map(|stats| stats.programs as u64).sum()
+    }
+
+    /// Get the address and erase count of the most erased sector, the first if tied.
+    pub fn most_worn(&self) -> Option<(u32, u32)> {
+        self.iter()
+            .map(|(address, stats)| (address, stats.erases))
+            .reduce(|worst, next| if next.1 > worst.1 { next } else { worst })
+    }
+
+    /// Estimate the erase cycles remaining before the most erased sector reaches
+    /// `rated_cycles`, the endurance given in the datasheet.
+    pub fn remaining_endurance(&self, rated_cycles: u32) -> u32 {
+        let worst = self.most_worn().map_or(0, |(_, erases)| erases);
+        rated_cycles.saturating_sub(worst)
+    }
+
+    /// Get the addresses of the sectors erased at least `threshold` times, for example
+    /// to relocate their data before they wear out.
+    pub fn worn_sectors(&self, threshold: u32) -> impl Iterator<Item = u32> + '_ {
+        self.iter()
+            .filter(move |(_, stats)| stats.erases >= threshold)
+            .map(|(address, _)| address)
+    }
+
+    /// Reset every counter to zero.
+    pub fn reset(&mut self) {
+        self.sectors.fill(SectorStats::default());
+    }
+
+    /// Encode the counters as records of at most `max_len` bytes, such as
+    /// `Journal::max_record_len()`, which must hold at least one sector's counters.
+    pub fn to_records(&self, max_len: usize) -> Result<Vec<Vec<u8>>> {
+        let per_record = max_len.saturating_sub(RECORD_HEADER_LEN) / SECTOR_RECORD_LEN;
+        if per_record == 0 {
+            log::error!("Statistics records of {} bytes are too short", max_len);
+            return Err(Error::NoSpace);
+        }
+        let mut records = Vec::new();
+        for (chunk, sectors) in self.sectors.chunks(per_record).enumerate() {
+            let mut record =
+                Vec::with_capacity(RECORD_HEADER_LEN + sectors.len() * SECTOR_RECORD_LEN);
+            record.extend((self.sectors.len() as u32).to_le_bytes());
+            record.extend(((chunk * per_record) as u32).to_le_bytes());
+            for stats in sectors {
+                record.extend(stats.erases.to_le_bytes());
+                record.extend(stats.programs.to_le_bytes());
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Replace the counters with the latest complete copy in `records`, oldest first,
+    /// returning `false` and leaving them unchanged if there is none.
+    ///
+    /// Returns `InvalidConfiguration` if there is no complete copy, but there are records
+    /// for a different number of sectors.
+    pub fn load_records<'r>(
+        &mut self,
+        records: impl IntoIterator<Item = &'r [u8]>,
+    ) -> Result<bool> {
+        let sectors = self.sectors.len();
+        let mut mismatched = false;
+        let mut run: Vec<&[u8]> = Vec::new();
+        let mut next = 0;
+        let mut complete = None;
+        for record in records {
+            let header = |i: usize| {
+                let field = record.get(4 * i..4 * i + 4)?;
+                Some(u32::from_le_bytes(field.try_into().unwrap()) as usize)
+            };
+            let counters = record.len().saturating_sub(RECORD_HEADER_LEN);
+            let (Some(count), Some(first)) = (header(0), header(1)) else {
+                run.clear();
+                continue;
+            };
+            if count != sectors || !counters.is_multiple_of(SECTOR_RECORD_LEN) {
+                mismatched = true;
+                run.clear();
+                continue;
+            }
+            // Each copy starts from the first sector, and continues where the last
+            // record of the same copy ended.
+            if first == 0 {
+                run.clear();
+                next = 0;
+            } else if run.is_empty() || first != next {
+                run.clear();
+                continue;
+            }
+            run.push(record);
+            next += counters / SECTOR_RECORD_LEN;
+            if next == sectors {
+                complete = Some(core::mem::take(&mut run));
+            }
+        }
+        let Some(records) = complete else {
+            if mismatched {
+                log::error!("Statistics records do not match {} sectors", sectors);
+                return Err(Error::InvalidConfiguration);
+            }
+            return Ok(false);
+        };
+        let counters = records
+            .iter()
+            .flat_map(|record| record[RECORD_HEADER_LEN..].chunks_exact(SECTOR_RECORD_LEN));
+        for (stats, counter) in self.sectors.iter_mut().zip(counters) {
+            stats.erases = u32::from_le_bytes(counter[0..4].try_into().unwrap());
+            stats.programs = u32::from_le_bytes(counter[4..8].try_into().unwrap());
+        }
+        Ok(true)
+    }
+
+    /// Append the counters to `journal`, returning the sequence number of the last record.
+    pub fn save<G: Storage>(&self, journal: &mut Journal<'_, G>) -> Result<u32> {
+        let mut seq = 0;
+        for record in self.to_records(journal.max_record_len())? {
+            seq = journal.append(&record)?;
+        }
+        Ok(seq)
+    }
+
+    /// Load the counters from the latest complete copy in `journal`, returning `false`
+    /// and leaving them unchanged if there is none.
+    pub fn restore<G: Storage>(&mut self, journal: &mut Journal<'_, G>) -> Result<bool> {
+        let records = journal.records()?;
+        self.load_records(records.iter().map(|(_, record)| record.as_slice()))
+    }
+
+    /// Get the range of sector indices overlapping `length` bytes from `address`.
+    fn overlapping(&self, address: u32, length: usize) -> core::ops::Range<usize> {
+        if length == 0 {
+            return 0..0;
+        }
+        let start = address as usize / self.sector_size;
+        let end = (address as usize + length).div_ceil(self.sector_size);
+        usize::min(start, self.sectors.len())..usize::min(end, self.sectors.len())
+    }
+}
+
+impl<F: Storage> ReadStorage for StatsFlash<F> {
+    fn capacity(&self) -> Option<usize> {
+        self.storage.capacity()
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        self.storage.read(address, length)
+    }
+}
+
+impl<F: Storage> Storage for StatsFlash<F> {
+    fn erase_size(&self) -> Option<usize> {
+        Some(self.sector_size)
+    }
+
+    /// Counts one program of each sector `data` reaches, if it succeeds.
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.storage.program_data(address, data)?;
+        for sector in self.overlapping(address, data.len()) {
+            let stats = &mut self.sectors[sector];
+            stats.programs = stats.programs.saturating_add(1);
+        }
+        Ok(())
+    }
+
+    /// Counts one erase of each sector in the range, if it succeeds.
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.storage.erase_data(address, length)?;
+        for sector in self.overlapping(address, length) {
+            let stats = &mut self.sectors[sector];
+            stats.erases = stats.erases.saturating_add(1);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stats_flash() {
+    use crate::mock::MockFlash;
+    use crate::Flash;
+
+    let mut mock = MockFlash::new(64 * 1024);
+    assert!(StatsFlash::new(Flash::new(&mut mock)).is_err());
+    let flash = crate::mock::configured_flash(&mut mock);
+    let mut stats = StatsFlash::new(flash).unwrap();
+    assert_eq!(stats.iter().count(), 16);
+    assert_eq!(stats.most_worn(), Some((0, 0)));
+
+    stats.erase_data(0x1000, 0x2000).unwrap();
+    stats.erase_data(0x2000, 0x1000).unwrap();
+    stats.program_data(0x1F00, &[0; 0x200]).unwrap();
+    assert!(stats.erase_data(0x2100, 0x1000).is_err());
+    assert!(stats.program_data(0xFFF0, &[0; 32]).is_err());
+    assert_eq!(
+        stats.sector_stats(0x2ABC),
+        Some(SectorStats {
+            erases: 2,
+            programs: 1
+        })
+    );
+    assert_eq!(stats.sector_stats(0x10000), None);
+    assert_eq!((stats.total_erases(), stats.total_programs()), (3, 2));
+    assert_eq!(stats.most_worn(), Some((0x2000, 2)));
+    assert_eq!(stats.remaining_endurance(100_000), 99_998);
+    assert_eq!(stats.worn_sectors(1).collect::<Vec<_>>(), [0x1000, 0x2000]);
+
+    // Persist the counters in a journal on the counted device, then restore them.
+    let saved = stats.iter().collect::<Vec<_>>();
+    let records = stats.to_records(4086).unwrap();
+    assert_eq!(records.len(), 1);
+    let mut journal = Journal::mount(&mut stats, 0xC000..0x10000).unwrap();
+    for record in &records {
+        journal.append(record).unwrap();
+    }
+    // The journal's own program is counted after the record was taken.
+    assert_eq!(stats.sector_stats(0xC000).unwrap().programs, 1);
+    stats.reset();
+    assert_eq!(stats.total_erases(), 0);
+    let latest = Journal::mount(&mut stats, 0xC000..0x10000)
+        .unwrap()
+        .records()
+        .unwrap();
+    assert!(stats
+        .load_records(latest.iter().map(|(_, record)| record.as_slice()))
+        .unwrap());
+    assert_eq!(stats.iter().collect::<Vec<_>>(), saved);
+    assert!(stats.to_records(15).is_err());
+    // A record for a different device, or an incomplete copy, is not loaded.
+    assert!(stats.load_records([&records[0][..12]]).is_err());
+    let chunks = stats.to_records(8 + 5 * 8).unwrap();
+    assert_eq!(chunks.len(), 4);
+    assert!(!stats
+        .load_records(chunks[..3].iter().map(|r| r.as_slice()))
+        .unwrap());
+    assert!(!stats
+        .load_records(chunks[1..].iter().map(|r| r.as_slice()))
+        .unwrap());
+    let flash = stats.release();
+
+    // Or in a journal on other storage.
+    let mut other = MockFlash::new(64 * 1024);
+    let mut other_flash = crate::mock::configured_flash(&mut other);
+    let mut journal = Journal::mount(&mut other_flash, 0..0x2000).unwrap();
+    let mut stats = StatsFlash::new(flash).unwrap();
+    assert!(!stats.restore(&mut journal).unwrap());
+    stats.erase_data(0, 0x1000).unwrap();
+    stats.save(&mut journal).unwrap();
+    let mut stats = StatsFlash::new(stats.release()).unwrap();
+    assert!(stats.restore(&mut journal).unwrap());
+    assert_eq!(stats.sector_stats(0).unwrap().erases, 1);
+    drop(other_flash);
+
+    // A 16MiB device of 4096 sectors needs several records, and an interrupted save
+    // leaves the previous copy.
+    let mut mock = MockFlash::new(16 * 1024 * 1024);
+    let flash = crate::mock::configured_flash(&mut mock);
+    let mut stats = StatsFlash::new(flash).unwrap();
+    stats.erase_data(0xFF_F000, 0x1000).unwrap();
+    let mut other_flash = crate::mock::configured_flash(&mut other);
+    let mut journal = Journal::mount(&mut other_flash, 0..0x10000).unwrap();
+    stats.save(&mut journal).unwrap();
+    assert!(journal.records().unwrap().len() > 1);
+    drop(other_flash);
+    stats.erase_data(0xFF_F000, 0x1000).unwrap();
+    other.set_power_loss_after(Some(20_000));
+    let mut other_flash = crate::mock::configured_flash(&mut other);
+    let mut journal = Journal::mount(&mut other_flash, 0..0x10000).unwrap();
+    assert!(stats.save(&mut journal).is_err());
+    drop(other_flash);
+    other.restore_power();
+    let mut other_flash = crate::mock::configured_flash(&mut other);
+    let mut journal = Journal::mount(&mut other_flash, 0..0x10000).unwrap();
+    stats.reset();
+    assert!(stats.restore(&mut journal).unwrap());
+    assert_eq!(stats.sector_stats(0xFF_F000).unwrap().erases, 1);
+    stats.erase_data(0xFF_F000, 0x1000).unwrap();
+    stats.save(&mut journal).unwrap();
+    stats.reset();
+    assert!(stats.restore(&mut journal).unwrap());
+    assert_eq!(stats.sector_stats(0xFF_F000).unwrap().erases, 2);
+}
@@ -0,0 +1,230 @@
+//! Storage traits and partitions of flash memory.
+//!
+//! `ReadStorage` and `Storage` abstract over byte-addressed memory which is read,
+//! programmed, and erased in place, and are implemented by `Flash`, `ReadOnlyFlash`
+//! (read only), and `Partition`.
+//!
+//! A `Partition` restricts a storage device to a window of its address space, so that
+//! areas such as the bootloader, configuration, and logs can each be handed to a different
+//! subsystem: addresses are relative to the start of the partition, and any operation
+//! extending outside it is rejected with `InvalidAddress` before reaching the device.
+//! Partitions may themselves be partitioned.
+
+use alloc::vec::Vec;
+
+use crate::{Error, Flash, FlashAccess, ReadOnlyFlash, Result};
+
+/// Memory which may be read.
+pub trait ReadStorage {
+    /// Get the capacity in bytes, if known.
+    fn capacity(&self) -> Option<usize>;
+
+    /// Read `length` bytes starting at `address`.
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>>;
+}
+
+/// Memory which may be read, programmed, and erased.
+pub trait Storage: ReadStorage {
+    /// Get the smallest erase size in bytes, if known.
+    fn erase_size(&self) -> Option<usize>;
+
+    /// Program `data` starting at `address`, which must already be erased.
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()>;
+
+    /// Erase `length` bytes starting at `address`, which must be aligned to the
+    /// available erase sizes.
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()>;
+}
+
+impl<'a, A: FlashAccess> ReadStorage for Flash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn capacity(&self) -> Option<usize> {
+        Flash::capacity(self)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        Flash::read(self, address, length)
+    }
+}
+
+impl<'a, A: FlashAccess> Storage for Flash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn erase_size(&self) -> Option<usize> {
+        Flash::erase_size(self)
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        Flash::program_data(self, address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        self.erase_range(address, length, false)
+    }
+}
+
+impl<'a, A: FlashAccess> ReadStorage for ReadOnlyFlash<'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    fn capacity(&self) -> Option<usize> {
+        ReadOnlyFlash::capacity(self)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        ReadOnlyFlash::read(self, address, length)
+    }
+}
+
+/// A window of `length` bytes starting at `offset` in an underlying storage device.
+pub struct Partition<'a, F> {
+    storage: &'a mut F,
+    offset: u32,
+    length: usize,
+}
+
+impl<'a, F: ReadStorage> Partition<'a, F> {
+    /// Create a partition of the `length` bytes starting at `offset` in `storage`.
+    ///
+    /// The window must lie within the capacity of `storage`, if known. For storage
+    /// which may be erased, use `Partition::new_erasable()` to also check the window
+    /// is aligned to the erase size, so erasing the partition never affects its neighbours.
+    pub fn new(storage: &'a mut F, offset: u32, length: usize) -> Result<Self> {
+        let end = offset as usize + length;
+        if let Some(capacity) = storage.capacity() {
+            if end > capacity {
+                log::error!(
+                    "Partition 0x{:08X}..0x{:08X} exceeds capacity {}",
+                    offset,
+                    end,
+                    capacity
+                );
+                return Err(Error::InvalidAddress {
+                    address: end as u32,
+                });
+            }
+        }
+        Ok(Partition {
+            storage,
+            offset,
+            length,
+        })
+    }
+
+    /// Get the offset of the partition in the underlying storage.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Get the length of the partition in bytes.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Check if the partition is empty.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Check `length` bytes from `address` lie within the partition,
+    /// and return the corresponding address in the underlying storage.
+    fn translate(&self, address: u32, length: usize) -> Result<u32> {
+        let end = address as usize + length;
+        if end > self.length {
+            log::error!(
+                "Access 0x{:08X}..0x{:08X} outside partition of {} bytes",
+                address,
+                end,
+                self.length
+            );
+            return Err(Error::InvalidAddress {
+                address: end as u32,
+            });
+        }
+        Ok(self.offset + address)
+    }
+}
+
+impl<'a, F: Storage> Partition<'a, F> {
+    /// Create an erasable partition of the `length` bytes starting at `offset` in `storage`.
+    ///
+    /// As well as the checks made by `Partition::new()`, the window must be aligned to
+    /// the erase size of `storage`.
+    pub fn new_erasable(storage: &'a mut F, offset: u32, length: usize) -> Result<Self> {
+        let erase_size = match storage.erase_size() {
+            Some(erase_size) => erase_size,
+            None => {
+                log::warn!("Sector erase size not known.");
+                return Err(Error::NoEraseInstruction);
+            }
+        };
+        for address in [offset as usize, offset as usize + length] {
+            if !address.is_multiple_of(erase_size) {
+                log::error!("Partition boundary 0x{:08X} is not sector-aligned", address);
+                return Err(Error::InvalidAddress {
+                    address: address as u32,
+                });
+            }
+        }
+        Self::new(storage, offset, length)
+    }
+
+    /// Erase the whole partition.
+    pub fn erase_all(&mut self) -> Result<()> {
+        self.erase_data(0, self.length)
+    }
+}
+
+impl<'a, F: ReadStorage> ReadStorage for Partition<'a, F> {
+    fn capacity(&self) -> Option<usize> {
+        Some(self.length)
+    }
+
+    fn read(&mut self, address: u32, length: usize) -> Result<Vec<u8>> {
+        let address = self.translate(address, length)?;
+        self.storage.read(address, length)
+    }
+}
+
+impl<'a, F: Storage> Storage for Partition<'a, F> {
+    fn erase_size(&self) -> Option<usize> {
+        self.storage.erase_size()
+    }
+
+    fn program_data(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        let address = self.translate(address, data.len())?;
+        self.storage.program_data(address, data)
+    }
+
+    fn erase_data(&mut self, address: u32, length: usize) -> Result<()> {
+        let address = self.translate(address, length)?;
+        self.storage.erase_data(address, length)
+    }
+}
+
+#[test]
+fn test_partition() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(Partition::new(&mut flash, 0xF000, 0x2000).is_err());
+    assert!(Partition::new_erasable(&mut flash, 0x1800, 0x1000).is_err());
+    {
+        let mut config = Partition::new_erasable(&mut flash, 0x4000, 0x2000).unwrap();
+        config.program_data(0x10, b"config").unwrap();
+        assert_eq!(config.read(0x10, 6).unwrap(), b"config");
+        assert!(config.program_data(0x1FFC, b"overflow").is_err());
+        assert!(config.read(0x2000, 1).is_err());
+
+        // A nested partition is relative to its parent.
+        let mut nested = Partition::new(&mut config, 0x10, 6).unwrap();
+        assert_eq!(nested.read(0, 6).unwrap(), b"config");
+        assert!(nested.read(0, 7).is_err());
+    }
+    assert_eq!(flash.read(0x4010, 6).unwrap(), b"config");
+    let mut config = Partition::new_erasable(&mut flash, 0x4000, 0x2000).unwrap();
+    config.erase_all().unwrap();
+    assert_eq!(flash.read(0x4010, 6).unwrap(), [0xFF; 6]);
+}
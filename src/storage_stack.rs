@@ -0,0 +1,270 @@
+//! Preset storage compositions for common uses.
+//!
+//! Each preset takes a `Flash` and the region of flash to use, and assembles the
+//! recommended combination of the crate's storage layers with sane defaults:
+//!
+//! * `config()`: CRC-protected variables in a two-sector emulated EEPROM, see `Eeprom`.
+//! * `logging()`: a `Journal` of CRC-protected records, see `Log`.
+//! * `ota()`: two firmware image slots, each an erasable `Partition` written sequentially
+//!   through a `BufferedFlash` and verified by CRC, see `Ota`.
+//!
+//! The pieces remain usable individually for layouts the presets do not cover.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::crc::{storage_crc32, Crc32};
+use crate::{
+    BufferedFlash, Eeprom, Error, Flash, FlashAccess, Journal, Partition, Result, Storage,
+};
+
+/// Page size used to buffer OTA writes if the flash page size is not known.
+const DEFAULT_PAGE_SIZE: usize = 256;
+
+/// Create an emulated EEPROM for configuration variables in `region`,
+/// which must be exactly two sectors.
+pub fn config<'f, 'a, A: FlashAccess>(
+    flash: &'f mut Flash<'a, A>,
+    region: Range<u32>,
+) -> Result<Eeprom<'f, 'a, A>>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    Partition::new_erasable(&mut *flash, region.start, region.len())?;
+    let sector_size = flash.erase_size().unwrap();
+    if region.len() != 2 * sector_size {
+        log::error!("Configuration region must be exactly two sectors");
+        return Err(Error::InvalidAddress {
+            address: region.end,
+        });
+    }
+    Eeprom::mount(flash, region.start, region.start + sector_size as u32)
+}
+
+/// Open the log in `region`, which must be sector-aligned and at least two sectors.
+pub fn logging<'f, 'a, A: FlashAccess>(
+    flash: &'f mut Flash<'a, A>,
+    region: Range<u32>,
+) -> Result<Log<'f, 'a, A>>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    Ok(Log {
+        journal: Journal::mount(flash, region)?,
+    })
+}
+
+/// Create an updater for the two firmware image slots `slots`, which must be sector-aligned.
+pub fn ota<'f, 'a, A: FlashAccess>(
+    flash: &'f mut Flash<'a, A>,
+    slots: [Range<u32>; 2],
+) -> Result<Ota<'f, 'a, A>>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    for slot in slots.iter() {
+        if Partition::new_erasable(&mut *flash, slot.start, slot.len())?.is_empty() {
+            log::error!("OTA slot at 0x{:08X} is empty", slot.start);
+            return Err(Error::InvalidAddress {
+                address: slot.start,
+            });
+        }
+    }
+    if slots[0].start < slots[1].end && slots[1].start < slots[0].end {
+        log::error!("OTA slots overlap");
+        return Err(Error::InvalidAddress {
+            address: slots[1].start,
+        });
+    }
+    Ok(Ota { flash, slots })
+}
+
+/// Log of CRC-protected records, stored in a `Journal`.
+///
+/// A record interrupted by power loss is discarded when the log is opened again. When
+/// the region is full, appending erases its oldest sector, discarding the oldest records.
+pub struct Log<'f, 'a, A: FlashAccess>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    journal: Journal<'f, Flash<'a, A>>,
+}
+
+impl<'f, 'a, A: FlashAccess> Log<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Append a record containing `data`.
+    ///
+    /// Returns `NoSpace` if it is longer than `Journal::max_record_len()`.
+    pub fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.journal.append(data)?;
+        Ok(())
+    }
+
+    /// Read all valid records, oldest first.
+    pub fn records(&mut self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .journal
+            .records()?
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect())
+    }
+
+    /// Erase the log.
+    pub fn clear(&mut self) -> Result<()> {
+        self.journal.clear()
+    }
+
+    /// Get the underlying journal, for example to read sequence numbers.
+    pub fn journal(&mut self) -> &mut Journal<'f, Flash<'a, A>> {
+        &mut self.journal
+    }
+}
+
+/// Updater for two firmware image slots.
+///
+/// An update erases the target slot, writes the image sequentially while computing its
+/// CRC-32, then verifies the programmed image against that CRC.
+pub struct Ota<'f, 'a, A: FlashAccess> {
+    flash: &'f mut Flash<'a, A>,
+    slots: [Range<u32>; 2],
+}
+
+impl<'f, 'a, A: FlashAccess> Ota<'f, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Get the address range of `slot`.
+    pub fn slot(&self, slot: usize) -> Range<u32> {
+        self.slots[slot].clone()
+    }
+
+    /// Get a partition covering `slot`.
+    fn partition(&mut self, slot: usize) -> Result<Partition<'_, Flash<'a, A>>> {
+        let region = self.slots[slot].clone();
+        Partition::new_erasable(&mut *self.flash, region.start, region.len())
+    }
+
+    /// Begin writing a new image to `slot`, which is erased.
+    pub fn begin(&mut self, slot: usize) -> Result<OtaUpdate<'_, 'a, A>> {
+        self.partition(slot)?.erase_all()?;
+        let page_size = self.flash.page_size().unwrap_or(DEFAULT_PAGE_SIZE);
+        Ok(OtaUpdate {
+            buffered: BufferedFlash::new(&mut *self.flash, page_size)?,
+            region: self.slots[slot].clone(),
+            written: 0,
+            crc: Crc32::new(),
+        })
+    }
+
+    /// Check the first `length` bytes of `slot` have CRC-32 `crc`.
+    pub fn verify(&mut self, slot: usize, length: usize, crc: u32) -> Result<bool> {
+        let mut partition = self.partition(slot)?;
+        if length > partition.len() {
+            return Ok(false);
+        }
+        Ok(storage_crc32(&mut partition, 0, length)? == crc)
+    }
+}
+
+/// An image being written to an OTA slot, created by `Ota::begin()`.
+///
+/// Writes are coalesced into whole pages, so the image may be written in chunks of any
+/// size. Dropping an update without calling `finish()` programs any buffered data, but
+/// only logs errors.
+pub struct OtaUpdate<'o, 'a, A: FlashAccess>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    buffered: BufferedFlash<'o, Flash<'a, A>>,
+    region: Range<u32>,
+    written: usize,
+    crc: Crc32,
+}
+
+impl<'o, 'a, A: FlashAccess> OtaUpdate<'o, 'a, A>
+where
+    Error: From<<A as FlashAccess>::Error>,
+{
+    /// Write the next chunk of the image.
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.written + data.len() > self.region.len() {
+            log::error!("Image does not fit in slot");
+            return Err(Error::NoSpace);
+        }
+        let address = self.region.start + self.written as u32;
+        self.buffered.program_data(address, data)?;
+        self.crc.update(data);
+        self.written += data.len();
+        Ok(())
+    }
+
+    /// Finish writing the image, verifying it was programmed correctly.
+    ///
+    /// Returns the length and CRC-32 of the image, which may be stored to validate
+    /// it later using `Ota::verify()`.
+    pub fn finish(mut self) -> Result<(usize, u32)> {
+        self.buffered.flush()?;
+        let crc = self.crc.finish();
+        let start = self.region.start;
+        if storage_crc32(&mut self.buffered, start, self.written)? != crc {
+            log::error!("OTA image at 0x{:08X} failed verification", start);
+            return Err(Error::ChecksumMismatch { address: start });
+        }
+        Ok((self.written, crc))
+    }
+}
+
+#[test]
+fn test_logging() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(logging(&mut flash, 0x1000..0x2000).is_err());
+    {
+        let mut log = logging(&mut flash, 0x1000..0x3000).unwrap();
+        log.append(b"first").unwrap();
+        log.append(b"second").unwrap();
+        assert!(log.append(&[0; 4096]).is_err());
+    }
+
+    // Corrupt the second record's data, as if programming was interrupted,
+    // then reopen the log.
+    flash.program_data(0x1000 + 15 + 6, &[0x00]).unwrap();
+    let mut log = logging(&mut flash, 0x1000..0x3000).unwrap();
+    log.append(b"third").unwrap();
+    assert_eq!(
+        log.records().unwrap(),
+        [b"first".to_vec(), b"third".to_vec()]
+    );
+
+    // When full, the oldest records are discarded.
+    for n in 0..20u8 {
+        log.append(&[n; 1000]).unwrap();
+    }
+    let records = log.records().unwrap();
+    assert_eq!(records.last().unwrap(), &[19; 1000]);
+    assert!(records.len() < 20);
+    assert_eq!(log.journal().next_sequence(), 22);
+    log.clear().unwrap();
+    assert!(log.records().unwrap().is_empty());
+}
+
+#[test]
+fn test_ota() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(ota(&mut flash, [0x0000..0x8000, 0x4000..0xC000]).is_err());
+    let mut ota = ota(&mut flash, [0x0000..0x8000, 0x8000..0x10000]).unwrap();
+    let image: Vec<u8> = (0..5000).map(|x| x as u8).collect();
+    let mut update = ota.begin(1).unwrap();
+    for chunk in image.chunks(1000) {
+        update.write(chunk).unwrap();
+    }
+    let (length, crc) = update.finish().unwrap();
+    assert_eq!(length, image.len());
+    assert_eq!(crc, crate::crc::crc32(&image));
+    assert!(ota.verify(1, length, crc).unwrap());
+    assert!(!ota.verify(0, length, crc).unwrap());
+}
@@ -0,0 +1,106 @@
+//! Tracing of the instructions issued to the device.
+//!
+//! When bringing up a new board or device, it helps to see the exact SPI traffic the driver
+//! produces. `Flash::set_trace_hook()` sets a `TraceHook` which is called after every
+//! instruction passed to the `FlashAccess`, with its opcode, address, data lengths, time
+//! taken, and result, so the traffic can be logged without a logic analyser.
+//!
+//! Tracing is only available with the `trace` feature, so costs nothing otherwise.
+
+use core::time::Duration;
+
+use crate::{Command, Error};
+
+/// Description of one instruction issued to the device.
+#[derive(Debug)]
+pub struct TraceEvent<'a> {
+    /// The instruction issued.
+    pub command: Command,
+    /// Opcode of the instruction.
+    pub opcode: u8,
+    /// Address sent with the instruction, if any.
+    ///
+    /// Instructions which send their address as part of the data, such as those of octal
+    /// mode and SPI NAND devices, have no address here.
+    pub address: Option<u32>,
+    /// Number of data bytes written after the instruction and any address.
+    pub written: usize,
+    /// Number of bytes read back.
+    pub read: usize,
+    /// Time taken by the transport, if it can be measured; this requires `std`.
+    pub duration: Option<Duration>,
+    /// Result of the transfer.
+    pub result: core::result::Result<(), &'a Error>,
+}
+
+/// Receiver of a `TraceEvent` for each instruction issued to the device.
+pub trait TraceHook {
+    /// Called after each instruction completes or fails.
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+/// Measures the time taken by a transfer where a clock is available.
+pub(crate) struct Timer {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Timer {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        #[cfg(feature = "std")]
+        return Some(self.start.elapsed());
+        #[cfg(not(feature = "std"))]
+        return None;
+    }
+}
+
+#[test]
+fn test_trace_hook() {
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    type Log = Rc<RefCell<Vec<(u8, Option<u32>, usize, usize, bool)>>>;
+
+    struct Recorder(Log);
+    impl TraceHook for Recorder {
+        fn trace(&mut self, event: &TraceEvent) {
+            self.0.borrow_mut().push((
+                event.opcode,
+                event.address,
+                event.written,
+                event.read,
+                event.result.is_ok(),
+            ));
+        }
+    }
+
+    let log = Log::default();
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::Flash::new(&mut mock);
+    flash.set_trace_hook(Some(Box::new(Recorder(log.clone()))));
+    flash.read_jedec_id().unwrap();
+    flash.write_enable().unwrap();
+    flash.page_program(0x100, &[0xA5; 4]).unwrap();
+    assert_eq!(
+        log.borrow()[..4],
+        [
+            (0x9F, None, 0, 3, true),
+            (0x06, None, 0, 0, true),
+            (0x06, None, 0, 0, true),
+            (0x02, Some(0x100), 4, 0, true),
+        ]
+    );
+    // Status polling after the program is traced too.
+    assert_eq!(log.borrow()[4].0, 0x05);
+
+    flash.set_trace_hook(None);
+    flash.write_enable().unwrap();
+    assert_eq!(log.borrow().len(), 5);
+}
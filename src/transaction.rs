@@ -0,0 +1,548 @@
+//! Transaction descriptors for controllers which execute flash commands themselves.
+//!
+//! QSPI peripherals with DMA, and memory-mapped flash controllers, are typically
+//! programmed with the instruction, address, dummy cycles, and data phase of a command
+//! rather than with a stream of bytes. A `Transaction` describes one such command,
+//! so the command knowledge in this crate can be reused without it owning the bus.
+
+use either::Either;
+
+use crate::commands::octal::OctalMode;
+use crate::commands::spansion::{four_byte_opcode, Command, CommandOpCode};
+use crate::{Error, FlashParams, Result};
+
+/// Direction of the data phase of a transaction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The transaction has no data phase.
+    None,
+    /// Data is read from the flash.
+    Read,
+    /// Data is written to the flash.
+    Write,
+}
+
+/// Number of data lines used in each phase of a transaction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Lines {
+    /// Lines used to send the opcode.
+    pub instruction: u8,
+    /// Lines used to send the address, mode bits, and dummy cycles.
+    pub address: u8,
+    /// Lines used to transfer data.
+    pub data: u8,
+}
+
+impl Lines {
+    /// Standard SPI, using a single line for all phases (1-1-1).
+    pub const SINGLE: Lines = Lines::new(1, 1, 1);
+
+    /// Create a new line configuration.
+    pub const fn new(instruction: u8, address: u8, data: u8) -> Self {
+        Lines {
+            instruction,
+            address,
+            data,
+        }
+    }
+}
+
+/// Bus protocol used for all commands.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// Standard SPI, where opcodes are sent on one line and each instruction
+    /// determines the lines used for its address and data phases.
+    Spi,
+    /// QPI, where the opcode, address, and data of every instruction use four lines.
+    Qpi,
+    /// Octal SPI, where every instruction uses eight lines, a two-byte opcode,
+    /// and a 4-byte address.
+    Octal(OctalMode),
+}
+
+/// Read instructions, by the number of lines used for each phase.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadMode {
+    /// FastRead (0x0B), 1-1-1.
+    Fast,
+    /// Dual Output Fast Read (0x3B), 1-1-2.
+    DualOutput,
+    /// Dual I/O Fast Read (0xBB), 1-2-2.
+    DualIO,
+    /// Quad Output Fast Read (0x6B), 1-1-4.
+    QuadOutput,
+    /// Quad I/O Fast Read (0xEB), 1-4-4.
+    QuadIO,
+    /// DTR Fast Read (0x0D), 1-1-1 with the address and data on both clock edges.
+    FastDtr,
+    /// DTR Dual I/O Fast Read (0xBD), 1-2-2 with the address and data on both clock edges.
+    DualIODtr,
+    /// DTR Quad I/O Fast Read (0xED), 1-4-4 with the address and data on both clock edges.
+    QuadIODtr,
+}
+
+impl ReadMode {
+    /// Get the standard opcode for this read instruction.
+    pub fn opcode(self) -> u8 {
+        match self {
+            ReadMode::Fast => CommandOpCode::FastRead,
+            ReadMode::DualOutput => CommandOpCode::ReadDualOut,
+            ReadMode::DualIO => CommandOpCode::ReadDualIO,
+            ReadMode::QuadOutput => CommandOpCode::ReadQuadOut,
+            ReadMode::QuadIO => CommandOpCode::ReadQuadIO,
+            ReadMode::FastDtr => CommandOpCode::FastReadDtr,
+            ReadMode::DualIODtr => CommandOpCode::ReadDualIODtr,
+            ReadMode::QuadIODtr => CommandOpCode::ReadQuadIODtr,
+        }
+        .into()
+    }
+
+    /// Check if this read instruction uses double transfer rate.
+    pub fn is_dtr(self) -> bool {
+        matches!(
+            self,
+            ReadMode::FastDtr | ReadMode::DualIODtr | ReadMode::QuadIODtr
+        )
+    }
+
+    /// Get typical dummy cycles and mode bits for this read instruction.
+    ///
+    /// These are the common defaults at moderate clock frequencies, matching for example
+    /// Winbond W25Q devices; the DTR instructions require more dummy cycles than their
+    /// single transfer rate equivalents. Check the datasheet for the device and frequency.
+    pub fn typical_dummy_cycles(self) -> (u8, Option<u8>) {
+        match self {
+            ReadMode::Fast | ReadMode::DualOutput | ReadMode::QuadOutput => (8, None),
+            ReadMode::DualIO => (0, Some(0xFF)),
+            ReadMode::QuadIO => (4, Some(0xFF)),
+            ReadMode::FastDtr => (6, None),
+            ReadMode::DualIODtr => (4, Some(0xFF)),
+            ReadMode::QuadIODtr => (7, Some(0xFF)),
+        }
+    }
+
+    /// Get the lines used in each phase of this read instruction.
+    pub fn lines(self) -> Lines {
+        match self {
+            ReadMode::Fast => Lines::SINGLE,
+            ReadMode::DualOutput => Lines::new(1, 1, 2),
+            ReadMode::DualIO => Lines::new(1, 2, 2),
+            ReadMode::QuadOutput => Lines::new(1, 1, 4),
+            ReadMode::QuadIO | ReadMode::QuadIODtr => Lines::new(1, 4, 4),
+            ReadMode::FastDtr => Lines::SINGLE,
+            ReadMode::DualIODtr => Lines::new(1, 2, 2),
+        }
+    }
+}
+
+/// Configuration of the read instruction used for data reads.
+///
+/// The number of dummy cycles depends on the device and often on the clock frequency,
+/// and I/O modes may require mode bits after the address; the correct values may be
+/// taken from SFDP using `from_sfdp()`, or from the device datasheet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReadParameters {
+    /// Read instruction to use.
+    pub mode: ReadMode,
+    /// Opcode to send, normally `mode.opcode()`.
+    pub opcode: u8,
+    /// Number of dummy cycles after the address and any mode bits.
+    pub dummy_cycles: u8,
+    /// Mode bits sent after the address, if required.
+    ///
+    /// Note that many devices enter a continuous read mode for some values, such as
+    /// 0xA0 or 0x20, where subsequent reads do not send an opcode; 0xFF avoids this.
+    pub mode_bits: Option<u8>,
+}
+
+impl ReadParameters {
+    /// Mode bits which enter continuous read mode, with M5-4 = (1, 0).
+    pub const CONTINUOUS_MODE_BITS: u8 = 0x20;
+
+    /// Create read parameters for `mode` using its standard opcode and typical
+    /// dummy cycles and mode bits, see `ReadMode::typical_dummy_cycles()`.
+    pub fn typical(mode: ReadMode) -> Self {
+        let (dummy_cycles, mode_bits) = mode.typical_dummy_cycles();
+        Self::new(mode, dummy_cycles, mode_bits)
+    }
+
+    /// Create read parameters for `mode` using its standard opcode.
+    pub fn new(mode: ReadMode, dummy_cycles: u8, mode_bits: Option<u8>) -> Self {
+        ReadParameters {
+            mode,
+            opcode: mode.opcode(),
+            dummy_cycles,
+            mode_bits,
+        }
+    }
+
+    /// Get read parameters for `mode` from the SFDP parameters `params`.
+    ///
+    /// Returns `None` if the device does not indicate support for `mode`.
+    /// FastRead is not described by SFDP and always uses 8 dummy cycles,
+    /// and the DTR instructions are not described by the basic parameter table.
+    pub fn from_sfdp(params: &FlashParams, mode: ReadMode) -> Option<Self> {
+        let inst = match mode {
+            ReadMode::Fast => return Some(Self::default()),
+            ReadMode::DualOutput => params.fast_read_112,
+            ReadMode::DualIO => params.fast_read_122,
+            ReadMode::QuadOutput => params.fast_read_114,
+            ReadMode::QuadIO => params.fast_read_144,
+            ReadMode::FastDtr | ReadMode::DualIODtr | ReadMode::QuadIODtr => None,
+        }?;
+        let mode_bits = if inst.mode_clocks > 0 {
+            Some(0xFF)
+        } else {
+            None
+        };
+        Some(ReadParameters {
+            mode,
+            opcode: inst.opcode,
+            dummy_cycles: inst.dummy_cycles,
+            mode_bits,
+        })
+    }
+}
+
+impl Default for ReadParameters {
+    /// FastRead with 8 dummy cycles and no mode bits.
+    fn default() -> Self {
+        Self::new(ReadMode::Fast, 8, None)
+    }
+}
+
+/// Description of a single flash command.
+///
+/// On the bus, a transaction consists of the opcode, then the address if present
+/// (most significant byte first), then the mode bits if present, then the dummy cycles,
+/// and finally `length` bytes of data in `direction`, with each phase using `lines`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    /// Instruction opcode.
+    pub opcode: u8,
+    /// Second opcode byte, sent after `opcode` in octal mode.
+    pub opcode_extension: Option<u8>,
+    /// Address, if the instruction takes one.
+    pub address: Option<u32>,
+    /// Number of address bytes sent if an address is present, at most 4.
+    pub address_bytes: u8,
+    /// Mode bits sent after the address, if any.
+    pub mode_bits: Option<u8>,
+    /// Number of dummy clock cycles between the address or mode bits and the data.
+    pub dummy_cycles: u8,
+    /// Direction of the data phase.
+    pub direction: Direction,
+    /// Number of bytes in the data phase.
+    pub length: usize,
+    /// Number of lines used in each phase.
+    pub lines: Lines,
+    /// If set, the address, mode bits, and data are transferred on both clock edges.
+    pub dtr: bool,
+}
+
+impl Transaction {
+    /// Create a transaction consisting of only `opcode`.
+    pub const fn command(opcode: u8) -> Self {
+        Transaction {
+            opcode,
+            opcode_extension: None,
+            address: None,
+            address_bytes: 0,
+            mode_bits: None,
+            dummy_cycles: 0,
+            direction: Direction::None,
+            length: 0,
+            lines: Lines::SINGLE,
+            dtr: false,
+        }
+    }
+
+    /// Create a FastRead (0x0B) transaction reading `length` bytes from `address`.
+    pub fn fast_read(address: u32, address_bytes: u8, length: usize) -> Self {
+        Self::read(&ReadParameters::default(), address, address_bytes, length)
+    }
+
+    /// Create a transaction reading `length` bytes from `address` using the
+    /// read instruction described by `params`.
+    pub fn read(params: &ReadParameters, address: u32, address_bytes: u8, length: usize) -> Self {
+        Transaction {
+            address: Some(address),
+            address_bytes,
+            mode_bits: params.mode_bits,
+            dummy_cycles: params.dummy_cycles,
+            direction: Direction::Read,
+            length,
+            lines: params.mode.lines(),
+            dtr: params.mode.is_dtr(),
+            ..Self::command(params.opcode)
+        }
+    }
+
+    /// Create a PageProgram (0x02) transaction writing `length` bytes to `address`.
+    ///
+    /// Note that WriteEnable must be sent first, and the status register
+    /// polled afterwards until the program completes.
+    pub fn page_program(address: u32, address_bytes: u8, length: usize) -> Self {
+        Transaction {
+            address: Some(address),
+            address_bytes,
+            direction: Direction::Write,
+            length,
+            ..Self::command(CommandOpCode::PageProgram.into())
+        }
+    }
+
+    /// Create an erase transaction using `opcode` at `address`.
+    ///
+    /// Note that WriteEnable must be sent first, and the status register
+    /// polled afterwards until the erase completes.
+    pub fn erase(opcode: u8, address: u32, address_bytes: u8) -> Self {
+        Transaction {
+            address: Some(address),
+            address_bytes,
+            ..Self::command(opcode)
+        }
+    }
+
+    /// Create a transaction sending `opcode` and reading `length` bytes of response,
+    /// such as for reading status registers or IDs.
+    pub fn read_register(opcode: u8, length: usize) -> Self {
+        Transaction {
+            direction: Direction::Read,
+            length,
+            ..Self::command(opcode)
+        }
+    }
+
+    /// Create the transaction `FlashAccess::exchange()` performs for `command`, writing
+    /// `write_len` bytes of data and then reading `read_len` bytes.
+    ///
+    /// This allows transports which frame instructions themselves, such as for octal mode,
+    /// to do so using `with_protocol()`. Instructions whose address is written as part of
+    /// their data, such as `Command::Octal`, have no address. Any mode bits or dummy bytes
+    /// written before a read are not described, and the data phase reads `read_len` bytes.
+    pub fn from_command(command: Command, write_len: usize, read_len: usize) -> Self {
+        let (opcode, address) = command.parts();
+        let (address, address_bytes) = match address {
+            Some(Either::Left(address)) => (Some(*address), 3),
+            Some(Either::Right(address)) => (Some(*address), 4),
+            None => (None, 0),
+        };
+        let (direction, length) = if read_len > 0 {
+            (Direction::Read, read_len)
+        } else if write_len > 0 {
+            (Direction::Write, write_len)
+        } else {
+            (Direction::None, 0)
+        };
+        Transaction {
+            address,
+            address_bytes,
+            direction,
+            length,
+            ..Self::command(opcode)
+        }
+    }
+
+    /// Adjust this transaction for `protocol`.
+    ///
+    /// In QPI mode, all phases use four lines. In octal mode, all phases use eight lines,
+    /// the opcode is followed by its command extension, and addresses are sent as four
+    /// bytes using the 4-byte address instructions. Memory reads use the octal read
+    /// instruction of the device family with its power-up dummy cycles, as does Read SFDP,
+    /// which keeps its own opcode. Other reads, such as of status and configuration
+    /// registers, use the register read framing of the family, see
+    /// `OctalFamily::register_dummy_cycles()`.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        match protocol {
+            Protocol::Spi => (),
+            Protocol::Qpi => self.lines = Lines::new(4, 4, 4),
+            Protocol::Octal(mode) => {
+                let family = mode.family;
+                self.lines = Lines::new(8, 8, 8);
+                self.dtr = mode.dtr;
+                match self.direction {
+                    Direction::Read if self.address.is_some() && is_memory_read(self.opcode) => {
+                        self.opcode = family.read_opcode(mode.dtr);
+                        self.mode_bits = None;
+                        self.dummy_cycles = family.read_dummy_cycles();
+                    }
+                    Direction::Read if self.opcode == CommandOpCode::ReadSFDPRegister as u8 => {
+                        self.mode_bits = None;
+                        self.dummy_cycles = family.read_dummy_cycles();
+                    }
+                    Direction::Read => {
+                        if family.register_read_address() {
+                            self.address.get_or_insert(0);
+                        }
+                        self.mode_bits = None;
+                        self.dummy_cycles = family.register_dummy_cycles();
+                    }
+                    _ if self.address.is_some() => self.opcode = four_byte_opcode(self.opcode),
+                    _ => (),
+                }
+                if self.address.is_some() {
+                    self.address_bytes = 4;
+                }
+                self.opcode_extension = Some(family.extension().encode(self.opcode));
+            }
+        }
+        self
+    }
+
+    /// Get the number of bytes before the data phase.
+    ///
+    /// Dummy cycles are counted as the number of bytes clocked on `lines.address` lines
+    /// during those cycles, rounded up to a whole number of bytes.
+    pub fn header_len(&self) -> usize {
+        let address_len = if self.address.is_some() {
+            self.address_bytes as usize
+        } else {
+            0
+        };
+        let mode_len = self.mode_bits.is_some() as usize;
+        let opcode_len = 1 + self.opcode_extension.is_some() as usize;
+        opcode_len + address_len + mode_len + self.dummy_bytes()
+    }
+
+    /// Get the number of bytes clocked during the dummy cycles, rounded up.
+    ///
+    /// In DTR transactions, two bits per line are counted for each dummy cycle.
+    pub fn dummy_bytes(&self) -> usize {
+        let edges = if self.dtr { 2 } else { 1 };
+        (self.dummy_cycles as usize * self.lines.address as usize * edges).div_ceil(8)
+    }
+
+    /// Write the bytes before the data phase into `buf`.
+    ///
+    /// Dummy cycles are sent as zero bytes, as counted by `header_len()`.
+    /// Returns the number of bytes written, or `InvalidConfiguration` if the transaction
+    /// has an address and `address_bytes` is not between 1 and 4.
+    pub fn write_header(&self, buf: &mut [u8]) -> Result<usize> {
+        if self.address.is_some() && !(1..=4).contains(&self.address_bytes) {
+            log::error!("Invalid address length of {} bytes", self.address_bytes);
+            return Err(Error::InvalidConfiguration);
+        }
+        let len = self.header_len();
+        if buf.len() < len {
+            return Err(Error::BufferTooSmall {
+                length: buf.len(),
+                required: len,
+            });
+        }
+        buf[0] = self.opcode;
+        let mut idx = 1;
+        if let Some(extension) = self.opcode_extension {
+            buf[idx] = extension;
+            idx += 1;
+        }
+        if let Some(address) = self.address {
+            let n = self.address_bytes as usize;
+            buf[idx..idx + n].copy_from_slice(&address.to_be_bytes()[4 - n..]);
+            idx += n;
+        }
+        if let Some(mode_bits) = self.mode_bits {
+            buf[idx] = mode_bits;
+            idx += 1;
+        }
+        buf[idx..len].fill(0);
+        Ok(len)
+    }
+}
+
+/// Check if `opcode` is a memory read instruction, in any of its 3-byte address, 4-byte
+/// address, or octal forms.
+fn is_memory_read(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x03 | 0x0B
+            | 0x3B
+            | 0xBB
+            | 0x6B
+            | 0xEB
+            | 0x0D
+            | 0xBD
+            | 0xED
+            | 0x13
+            | 0x0C
+            | 0x3C
+            | 0xBC
+            | 0x6C
+            | 0xEC
+            | 0x0E
+            | 0xBE
+            | 0xEE
+            | 0xCC
+            | 0xFD
+    )
+}
+
+#[test]
+fn test_write_header() {
+    let mut buf = [0xAA; 8];
+    let tx = Transaction::fast_read(0x12_3456, 3, 16);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], &[0x0B, 0x12, 0x34, 0x56, 0x00]);
+    let tx = Transaction::erase(0x21, 0x1234_5678, 4);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], &[0x21, 0x12, 0x34, 0x56, 0x78]);
+    assert!(tx.write_header(&mut buf[..4]).is_err());
+    let params = ReadParameters::new(ReadMode::QuadIO, 4, Some(0xFF));
+    let tx = Transaction::read(&params, 0x12_3456, 3, 16);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], &[0xEB, 0x12, 0x34, 0x56, 0xFF, 0x00, 0x00]);
+    let params = ReadParameters::new(ReadMode::Fast, 2, None);
+    let tx = Transaction::read(&params, 0x12_3456, 3, 16).with_protocol(Protocol::Qpi);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 5);
+    let tx = Transaction::read(&ReadParameters::typical(ReadMode::QuadIODtr), 0, 3, 16);
+    assert!(tx.dtr);
+    assert_eq!((tx.opcode, tx.header_len()), (0xED, 12));
+    let tx = Transaction::erase(0x21, 0, 5);
+    assert!(matches!(
+        tx.write_header(&mut buf),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert!(Transaction::erase(0x20, 0, 0)
+        .write_header(&mut buf)
+        .is_err());
+}
+
+#[test]
+fn test_octal_protocol() {
+    use crate::commands::octal::OctalFamily;
+    let mut buf = [0xAA; 32];
+    let protocol = Protocol::Octal(OctalMode {
+        family: OctalFamily::Macronix,
+        dtr: true,
+    });
+    let tx = Transaction::erase(0x20, 0x0123_4000, 3).with_protocol(protocol);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 6);
+    assert_eq!(&buf[..6], &[0x21, 0xDE, 0x01, 0x23, 0x40, 0x00]);
+    let tx = Transaction::fast_read(0x1000, 3, 16).with_protocol(protocol);
+    assert_eq!((tx.opcode, tx.opcode_extension), (0xEE, Some(0x11)));
+    assert_eq!(tx.lines, Lines::new(8, 8, 8));
+    // 20 dummy cycles on 8 lines at double data rate.
+    assert_eq!(tx.header_len(), 2 + 4 + 40);
+    // Register reads keep their opcode, with a zero address and 4 dummy cycles.
+    let tx = Transaction::read_register(0x05, 1).with_protocol(protocol);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 2 + 4 + 8);
+    assert_eq!(&buf[..6], &[0x05, 0xFA, 0, 0, 0, 0]);
+    let tx = Transaction::from_command(Command::ReadSFDPRegister(0x10.try_into().unwrap()), 0, 8)
+        .with_protocol(protocol);
+    assert_eq!(
+        (tx.opcode, tx.address, tx.dummy_cycles),
+        (0x5A, Some(0x10), 20)
+    );
+    let protocol = Protocol::Octal(OctalMode {
+        family: OctalFamily::Micron,
+        dtr: false,
+    });
+    let tx = Transaction::command(0x06).with_protocol(protocol);
+    assert_eq!(tx.write_header(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], &[0x06, 0x06]);
+    let tx = Transaction::from_command(Command::ReadStatusRegister1, 0, 1).with_protocol(protocol);
+    assert_eq!(
+        (tx.address, tx.dummy_cycles, tx.header_len()),
+        (None, 8, 2 + 8)
+    );
+}
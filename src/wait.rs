@@ -0,0 +1,134 @@
+//! Event-driven waiting for the device to become ready.
+//!
+//! By default the driver polls the status register in a loop while a program or erase
+//! completes, sleeping first for half the typical time given by SFDP. Platforms which
+//! can be told when the device is ready, by a flash-busy GPIO interrupt, an RTOS event,
+//! or hardware status polling such as the STM32 QUADSPI auto-polling mode, can instead
+//! set a `WaitReady` hook using `Flash::set_wait_ready()` and sleep until then.
+//!
+//! The typical and maximum times of each operation are described by `Timings`, taken from
+//! the SFDP Basic Parameter Table or set using `Flash::set_timings()`. When polling, the
+//! status register is read every eighth of the typical time after the first half of it has
+//! passed, and if enabled using `Flash::set_timeouts()`, an operation still busy after its
+//! maximum time fails with `Error::Timeout`.
+
+use core::time::Duration;
+
+use crate::{Error, Result};
+
+/// Typical and maximum times taken by program and erase operations.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timings {
+    /// Typical time to program a full page.
+    pub page_program_typ: Duration,
+    /// Maximum time to program a full page.
+    pub page_program_max: Duration,
+    /// Typical time of the smallest sector erase.
+    pub sector_erase_typ: Duration,
+    /// Maximum time of the smallest sector erase.
+    pub sector_erase_max: Duration,
+    /// Typical time to erase the entire chip.
+    pub chip_erase_typ: Duration,
+    /// Maximum time to erase the entire chip.
+    pub chip_erase_max: Duration,
+}
+
+impl Timings {
+    /// Get the typical time to program `length` bytes in pages of `page_size` bytes,
+    /// assuming each page takes as long as a full page.
+    pub fn program_estimate(&self, length: usize, page_size: usize) -> Duration {
+        self.page_program_typ * length.div_ceil(page_size) as u32
+    }
+
+    /// Get the typical time to erase `sectors` of the smallest erase size.
+    pub fn erase_estimate(&self, sectors: usize) -> Duration {
+        self.sector_erase_typ * sectors as u32
+    }
+}
+
+/// Hook called in place of the status polling loop while the device is busy.
+pub trait WaitReady {
+    /// Wait until the device is likely to be ready, given the typical duration of the
+    /// operation in progress if known.
+    ///
+    /// The driver reads the status register before each call and after it returns,
+    /// calling the hook again while the device is still busy, so the hook may return
+    /// early, for example after a timeout, without the operation being reported done.
+    fn wait_ready(&mut self, expected: Option<Duration>) -> Result<()>;
+}
+
+/// Call `poll` until it returns a value, as the status polling of `Flash`.
+///
+/// Without a `timeout`, the device is polled continuously. With one, `delay` is called to
+/// wait a sixteenth of it between polls, and `Error::Timeout` returned once the delays add
+/// up to `timeout`, so elapsed time is only counted if the delays are implemented.
+pub(crate) fn poll_until<C: ?Sized, T>(
+    context: &mut C,
+    timeout: Option<Duration>,
+    mut delay: impl FnMut(&mut C, Duration),
+    mut poll: impl FnMut(&mut C) -> Result<Option<T>>,
+) -> Result<T> {
+    let mut waited = Duration::ZERO;
+    loop {
+        if let Some(value) = poll(context)? {
+            return Ok(value);
+        }
+        if let Some(timeout) = timeout {
+            if waited >= timeout {
+                log::error!("Device still busy after {:?}", waited);
+                return Err(Error::Timeout);
+            }
+            let interval = (timeout / 16).max(Duration::from_micros(1));
+            delay(context, interval);
+            waited += interval;
+        }
+    }
+}
+
+#[test]
+fn test_wait_ready() {
+    use alloc::{boxed::Box, rc::Rc};
+    use core::cell::Cell;
+
+    struct Hook(Rc<Cell<usize>>);
+    impl WaitReady for Hook {
+        fn wait_ready(&mut self, _expected: Option<Duration>) -> Result<()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    let calls = Rc::new(Cell::new(0));
+    let mut mock = crate::mock::MockFlash::new(64 * 1024).with_busy_polls(3);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_wait_ready(Some(Box::new(Hook(calls.clone()))));
+    flash.program_data(0x100, &[0x55; 16]).unwrap();
+    assert_eq!(calls.get(), 3);
+
+    // The hook is not called when the device is already ready.
+    flash.wait_while_busy().unwrap();
+    assert_eq!(calls.get(), 3);
+    drop(flash);
+    assert_eq!(mock.busy_violations(), 0);
+
+    // With timeouts enabled, a hook which returns without the device becoming ready
+    // cannot wait forever.
+    mock.set_stuck_busy(true);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    flash.set_wait_ready(Some(Box::new(Hook(calls.clone()))));
+    flash.set_timings(Some(Timings {
+        page_program_typ: Duration::from_micros(16),
+        page_program_max: Duration::from_micros(40),
+        sector_erase_typ: Duration::from_micros(80),
+        sector_erase_max: Duration::from_micros(200),
+        chip_erase_typ: Duration::from_millis(1),
+        chip_erase_max: Duration::from_millis(2),
+    }));
+    flash.set_timeouts(true);
+    assert!(matches!(
+        flash.program_data(0x200, &[0x55; 16]),
+        Err(Error::Timeout)
+    ));
+    assert_eq!(calls.get(), 3 + 17);
+}
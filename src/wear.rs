@@ -0,0 +1,230 @@
+//! Wear leveling for small, frequently updated records.
+//!
+//! `WearLeveler` stores successive versions of a fixed-size record, such as a boot
+//! counter or calibration data, in consecutive slots across several sectors, so each
+//! sector is only erased once every `sectors` times it fills. Each slot holds:
+//!
+//! | Bytes          | Content                                 |
+//! |----------------|-----------------------------------------|
+//! | 4              | Sequence number, little-endian          |
+//! | `record_len`   | Record data                             |
+//! | 4              | CRC-32 of sequence number and data      |
+//!
+//! Mounting finds the valid slot with the latest sequence number, compared using serial
+//! number arithmetic so the order survives the sequence wrapping to 0. A sector is only
+//! erased when writing moves into it, and it never holds the latest record, so the
+//! latest record survives power loss at any point.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::crc::Crc32;
+use crate::{Error, Partition, Result, Storage};
+
+/// Sequence number of an erased slot.
+const SEQ_ERASED: u32 = 0xFFFF_FFFF;
+/// Size of a slot's sequence number and CRC.
+const SLOT_OVERHEAD: usize = 8;
+
+/// Rotating-slot wear leveler for one record of `record_len` bytes.
+pub struct WearLeveler<'a, F: Storage> {
+    storage: &'a mut F,
+    region: Range<u32>,
+    sector_size: usize,
+    record_len: usize,
+    /// Latest record and its sequence number, if any.
+    latest: Option<(u32, Vec<u8>)>,
+    /// Index of the slot where the next record will be written.
+    next: usize,
+}
+
+impl<'a, F: Storage> WearLeveler<'a, F> {
+    /// Mount a wear leveler storing records of `record_len` bytes in `region`.
+    ///
+    /// `region` must be aligned to the erase size and contain at least two sectors,
+    /// each large enough for one slot.
+    pub fn mount(storage: &'a mut F, region: Range<u32>, record_len: usize) -> Result<Self> {
+        Partition::new_erasable(&mut *storage, region.start, region.len())?;
+        let sector_size = storage.erase_size().unwrap();
+        if region.len() < 2 * sector_size || sector_size < record_len + SLOT_OVERHEAD {
+            log::error!("Wear leveling region must be at least two sectors");
+            return Err(Error::NoSpace);
+        }
+        let mut wear = WearLeveler {
+            storage,
+            region,
+            sector_size,
+            record_len,
+            latest: None,
+            next: 0,
+        };
+        wear.scan()?;
+        log::debug!(
+            "Mounted wear leveler, sequence {:?}, next slot {}",
+            wear.sequence(),
+            wear.next
+        );
+        Ok(wear)
+    }
+
+    /// Get the latest record, if any has been written.
+    pub fn read(&self) -> Option<&[u8]> {
+        self.latest.as_ref().map(|(_, data)| data.as_slice())
+    }
+
+    /// Get the sequence number of the latest record, which counts the writes made.
+    pub fn sequence(&self) -> Option<u32> {
+        self.latest.as_ref().map(|(seq, _)| *seq)
+    }
+
+    /// Write a new version of the record, which must be `record_len` bytes.
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != self.record_len {
+            return Err(Error::BufferTooSmall {
+                length: data.len(),
+                required: self.record_len,
+            });
+        }
+        let seq = self
+            .sequence()
+            .map_or(0, |seq| seq.wrapping_add(1) % SEQ_ERASED);
+        if self.next.is_multiple_of(self.slots_per_sector()) {
+            let sector = self.slot_address(self.next);
+            log::trace!("Erasing wear leveling sector at 0x{:08X}", sector);
+            self.storage.erase_data(sector, self.sector_size)?;
+        }
+        let mut slot = Vec::with_capacity(self.slot_len());
+        slot.extend(seq.to_le_bytes());
+        slot.extend(data);
+        let mut crc = Crc32::new();
+        crc.update(&slot);
+        slot.extend(crc.finish().to_le_bytes());
+        self.storage
+            .program_data(self.slot_address(self.next), &slot)?;
+        self.latest = Some((seq, data.to_vec()));
+        self.next = (self.next + 1) % self.slots();
+        Ok(())
+    }
+
+    fn slot_len(&self) -> usize {
+        self.record_len + SLOT_OVERHEAD
+    }
+
+    fn slots_per_sector(&self) -> usize {
+        self.sector_size / self.slot_len()
+    }
+
+    fn slots(&self) -> usize {
+        self.region.len() / self.sector_size * self.slots_per_sector()
+    }
+
+    fn slot_address(&self, slot: usize) -> u32 {
+        let per_sector = self.slots_per_sector();
+        let sector = slot / per_sector;
+        let offset = sector * self.sector_size + (slot % per_sector) * self.slot_len();
+        self.region.start + offset as u32
+    }
+
+    /// Find the latest valid record, and the slot after it.
+    fn scan(&mut self) -> Result<()> {
+        let mut latest_slot = None;
+        for slot in 0..self.slots() {
+            let data = self
+                .storage
+                .read(self.slot_address(slot), self.slot_len())?;
+            let (body, stored) = data.split_at(4 + self.record_len);
+            let seq = u32::from_le_bytes(body[..4].try_into().unwrap());
+            if seq == SEQ_ERASED {
+                continue;
+            }
+            let mut crc = Crc32::new();
+            crc.update(body);
+            if crc.finish() != u32::from_le_bytes(stored.try_into().unwrap()) {
+                log::warn!("Skipping wear leveling slot {} with bad CRC", slot);
+                continue;
+            }
+            if self
+                .sequence()
+                .is_none_or(|latest| seq.wrapping_sub(latest) as i32 > 0)
+            {
+                self.latest = Some((seq, body[4..].to_vec()));
+                latest_slot = Some(slot);
+            }
+        }
+        if let Some(slot) = latest_slot {
+            self.next = (slot + 1) % self.slots();
+            // If the next sector was being erased when power was lost, it may be
+            // partially written, so skip to the start of the following sector.
+            let per_sector = self.slots_per_sector();
+            if !self.next.is_multiple_of(per_sector) {
+                let next = self
+                    .storage
+                    .read(self.slot_address(self.next), self.slot_len())?;
+                if next.iter().any(|x| *x != 0xFF) {
+                    self.next = (self.next / per_sector + 1) * per_sector % self.slots();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_wear_leveler() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(WearLeveler::mount(&mut flash, 0x1000..0x2000, 8).is_err());
+    {
+        let mut wear = WearLeveler::mount(&mut flash, 0x1000..0x4000, 1000).unwrap();
+        assert_eq!(wear.read(), None);
+        // Four slots fit in each sector, so this wraps around the region.
+        for n in 0..20u32 {
+            wear.write(&[n as u8; 1000]).unwrap();
+        }
+        assert!(wear.write(&[0; 8]).is_err());
+    }
+    let wear = WearLeveler::mount(&mut flash, 0x1000..0x4000, 1000).unwrap();
+    assert_eq!(
+        (wear.sequence(), wear.read()),
+        (Some(19), Some(&[19u8; 1000][..]))
+    );
+
+    // Corrupt the latest record, which is in slot 7, the last of the second sector.
+    flash.program_data(0x2000 + 3 * 1008 + 4, &[0x00]).unwrap();
+    let mut wear = WearLeveler::mount(&mut flash, 0x1000..0x4000, 1000).unwrap();
+    assert_eq!(wear.sequence(), Some(18));
+    wear.write(&[42; 1000]).unwrap();
+    let wear = WearLeveler::mount(&mut flash, 0x1000..0x4000, 1000).unwrap();
+    assert_eq!(
+        (wear.sequence(), wear.read()),
+        (Some(19), Some(&[42u8; 1000][..]))
+    );
+}
+
+#[test]
+fn test_wear_leveler_sequence_wrap() {
+    let mut mock = crate::mock::MockFlash::new(64 * 1024);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    // Records just before the sequence wraps, in the first two slots.
+    for (slot, seq) in [0xFFFF_FFFDu32, 0xFFFF_FFFE].into_iter().enumerate() {
+        let mut record = Vec::from(seq.to_le_bytes());
+        record.extend([seq as u8; 8]);
+        let mut crc = Crc32::new();
+        crc.update(&record);
+        record.extend(crc.finish().to_le_bytes());
+        flash
+            .program_data(0x1000 + (slot * 16) as u32, &record)
+            .unwrap();
+    }
+    let mut wear = WearLeveler::mount(&mut flash, 0x1000..0x3000, 8).unwrap();
+    assert_eq!(wear.sequence(), Some(0xFFFF_FFFE));
+    wear.write(&[1; 8]).unwrap();
+    wear.write(&[2; 8]).unwrap();
+    assert_eq!(wear.sequence(), Some(1));
+
+    let wear = WearLeveler::mount(&mut flash, 0x1000..0x3000, 8).unwrap();
+    assert_eq!(
+        (wear.sequence(), wear.read()),
+        (Some(1), Some(&[2u8; 8][..]))
+    );
+}
@@ -0,0 +1,121 @@
+//! Handoff to memory-mapped execute-in-place controllers.
+//!
+//! Controllers such as the RP2040 SSI, STM32 QUADSPI in memory-mapped mode, and the
+//! ESP32 SPI0 cache are programmed once with a read command, which they then issue
+//! themselves for every access to the mapped window. `Flash::enter_xip()` quiesces the
+//! device and returns an `XipConfig` giving the exact command to program into the
+//! controller, and `Flash::exit_xip()` returns the device to normal commands after
+//! the controller is taken out of memory-mapped mode.
+//!
+//! With continuous read, the controller sends the opcode only for the first read,
+//! and the device then expects every later read to begin directly with the address.
+//! Controllers call this mode-bit or instruction-less operation, for example the
+//! RP2040 SSI with `INST_L` set to 0 after the first read, or STM32 QUADSPI with
+//! `SIOO` set.
+
+use crate::transaction::{Lines, Transaction};
+
+/// Read command configuration for a memory-mapped controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct XipConfig {
+    /// Read instruction opcode, sent before the first read, or before every read
+    /// unless `continuous` is set.
+    pub opcode: u8,
+    /// Second opcode byte, sent after `opcode` in octal mode.
+    pub opcode_extension: Option<u8>,
+    /// Number of address bytes.
+    pub address_bytes: u8,
+    /// Mode bits sent after the address, if any. With `continuous` set, these keep
+    /// the device in continuous read mode.
+    pub mode_bits: Option<u8>,
+    /// Number of dummy clock cycles after the mode bits.
+    pub dummy_cycles: u8,
+    /// Number of lines used in each phase.
+    pub lines: Lines,
+    /// If set, the address, mode bits, and data are transferred on both clock edges.
+    pub dtr: bool,
+    /// If set, the device enters continuous read mode, and reads after the first
+    /// must omit the opcode.
+    pub continuous: bool,
+}
+
+impl XipConfig {
+    /// Create the configuration for reads like `transaction`.
+    pub(crate) fn from_transaction(transaction: &Transaction, continuous: bool) -> Self {
+        XipConfig {
+            opcode: transaction.opcode,
+            opcode_extension: transaction.opcode_extension,
+            address_bytes: transaction.address_bytes,
+            mode_bits: transaction.mode_bits,
+            dummy_cycles: transaction.dummy_cycles,
+            lines: transaction.lines,
+            dtr: transaction.dtr,
+            continuous,
+        }
+    }
+
+    /// Get the number of clock cycles taken to send the mode bits, for controllers
+    /// which count them as part of the dummy cycles.
+    pub fn mode_cycles(&self) -> u8 {
+        match self.mode_bits {
+            Some(_) => {
+                let cycles = 8 / self.lines.address;
+                if self.dtr {
+                    cycles / 2
+                } else {
+                    cycles
+                }
+            }
+            None => 0,
+        }
+    }
+}
+
+#[test]
+fn test_xip_handoff() {
+    use crate::{BoardConfig, Error, ReadMode, ReadParameters};
+
+    let mut mock = crate::mock::MockFlash::new(64 * 1024).with_busy_polls(2);
+    let mut flash = crate::mock::configured_flash(&mut mock);
+    assert!(matches!(
+        flash.enter_xip(true),
+        Err(Error::ContinuousReadUnsupported)
+    ));
+    let config = flash.enter_xip(false).unwrap();
+    assert_eq!((config.opcode, config.dummy_cycles), (0x0B, 8));
+    assert_eq!(config.mode_cycles(), 0);
+
+    flash.set_read_parameters(ReadParameters::typical(ReadMode::QuadIO));
+    assert!(matches!(
+        flash.enter_xip(true),
+        Err(Error::BoardUnsupported)
+    ));
+    flash.set_board(BoardConfig {
+        quad_io: true,
+        ..Default::default()
+    });
+
+    // A program in progress is finished before handing over.
+    flash.program_data(0x100, &[0x55]).unwrap();
+    let config = flash.enter_xip(true).unwrap();
+    assert!(!flash.is_busy_pending());
+    assert_eq!(
+        config,
+        XipConfig {
+            opcode: 0xEB,
+            opcode_extension: None,
+            address_bytes: 3,
+            mode_bits: Some(ReadParameters::CONTINUOUS_MODE_BITS),
+            dummy_cycles: 4,
+            lines: Lines::new(1, 4, 4),
+            dtr: false,
+            continuous: true,
+        }
+    );
+    assert_eq!(config.mode_cycles(), 2);
+    assert!(flash.is_continuous_read());
+    flash.exit_xip().unwrap();
+    assert!(!flash.is_continuous_read());
+    flash.set_read_parameters(ReadParameters::default());
+    assert_eq!(flash.read(0x100, 1).unwrap(), [0x55]);
+}